@@ -8,7 +8,7 @@ use tauri::{command, State};
 #[derive(Serialize)]
 pub struct FileEntry {
     name: String,
-    size: usize,
+    size: u64,
 }
 
 #[command]
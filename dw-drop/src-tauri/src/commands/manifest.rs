@@ -149,7 +149,7 @@ pub fn add_permissions(items: Vec<String>, state: State<DwState>) -> Result<(),
     write_state!(state.package => |package| {
         let manifest = package.manifest_mut().ok_or(Error::InvalidApk)?;
         for item in items {
-            Manifest::add_uses_permission(manifest, &item)?;
+            Manifest::add_uses_permission(manifest, &item, None)?;
         }
         Ok(())
     })
@@ -0,0 +1,129 @@
+//! Census of the call sites of sensitive APIs.
+//!
+//! Invocations are matched on the invoked method descriptor, as in the
+//! callgraph: a call through a subclass reference (such as
+//! `Lcom/foo/MyLoader;->loadClass`) does not match the descriptor of the
+//! overridden method.
+
+use crate::callgraph;
+use crate::errors::AnalysisResult;
+use crate::repo::{MethodDescr, MethodUid, Repo};
+use dw_dex::Addr;
+use lazy_static::lazy_static;
+use std::collections::BTreeMap;
+
+lazy_static! {
+    /// Methods related to dynamic code loading, reflection, native code,
+    /// commands execution and cryptography.
+    pub static ref SENSITIVE_APIS: Vec<MethodDescr> = [
+        "Ldalvik/system/DexClassLoader;-><init>(Ljava/lang/String;Ljava/lang/String;Ljava/lang/String;Ljava/lang/ClassLoader;)V",
+        "Ldalvik/system/PathClassLoader;-><init>(Ljava/lang/String;Ljava/lang/ClassLoader;)V",
+        "Ldalvik/system/InMemoryDexClassLoader;-><init>(Ljava/nio/ByteBuffer;Ljava/lang/ClassLoader;)V",
+        "Ljava/lang/ClassLoader;->loadClass(Ljava/lang/String;)Ljava/lang/Class;",
+        "Ljava/lang/Class;->forName(Ljava/lang/String;)Ljava/lang/Class;",
+        "Ljava/lang/Class;->getMethod(Ljava/lang/String;[Ljava/lang/Class;)Ljava/lang/reflect/Method;",
+        "Ljava/lang/Class;->getDeclaredMethod(Ljava/lang/String;[Ljava/lang/Class;)Ljava/lang/reflect/Method;",
+        "Ljava/lang/reflect/Method;->invoke(Ljava/lang/Object;[Ljava/lang/Object;)Ljava/lang/Object;",
+        "Ljava/lang/System;->load(Ljava/lang/String;)V",
+        "Ljava/lang/System;->loadLibrary(Ljava/lang/String;)V",
+        "Ljava/lang/Runtime;->exec(Ljava/lang/String;)Ljava/lang/Process;",
+        "Ljava/lang/Runtime;->exec([Ljava/lang/String;)Ljava/lang/Process;",
+        "Ljava/lang/ProcessBuilder;->start()Ljava/lang/Process;",
+        "Ljavax/crypto/Cipher;->getInstance(Ljava/lang/String;)Ljavax/crypto/Cipher;",
+    ]
+    .into_iter()
+    .map(|sig| MethodDescr::try_from(sig).expect("valid signature"))
+    .collect();
+}
+
+/// Lists, for each of the `patterns` methods invoked by the application,
+/// the methods and addresses of its call sites. Methods of system classes
+/// are not scanned, and patterns that are never invoked are left out.
+///
+/// # Errors
+///
+/// This function may return an error if a method reference cannot be read
+/// from a dex file.
+pub fn usage(
+    repo: &Repo,
+    patterns: &[MethodDescr],
+) -> AnalysisResult<BTreeMap<MethodDescr, Vec<(MethodUid, Addr)>>> {
+    let mut usage: BTreeMap<MethodDescr, Vec<(MethodUid, Addr)>> = BTreeMap::new();
+    for (_, method) in repo
+        .iter_classes_methods()
+        .filter(|(class, _)| class.is_defined() && !class.is_system())
+    {
+        for (called, sites) in callgraph::compute_calls(method)? {
+            if patterns.contains(&called) {
+                usage
+                    .entry(called)
+                    .or_default()
+                    .extend(sites.into_keys().map(|addr| (method.uid(), addr)));
+            }
+        }
+    }
+    Ok(usage)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::testing::{self, DexBuilder, ACC_PUBLIC, ACC_STATIC};
+
+    #[test]
+    fn runtime_exec_call_site() {
+        let mut builder = DexBuilder::new();
+        let class = builder.class("LTest;", "Ljava/lang/Object;");
+        let get_runtime = builder.method(
+            "Ljava/lang/Runtime;",
+            "getRuntime",
+            "Ljava/lang/Runtime;",
+            &[],
+        ) as u16;
+        let exec = builder.method(
+            "Ljava/lang/Runtime;",
+            "exec",
+            "Ljava/lang/Process;",
+            &["Ljava/lang/String;"],
+        ) as u16;
+        let command = builder.string("id") as u16;
+
+        // 0: invoke-static {}, Ljava/lang/Runtime;->getRuntime()Ljava/lang/Runtime;
+        // 3: move-result-object v0
+        // 4: const-string v1, "id"
+        // 6: invoke-virtual {v0, v1}, Ljava/lang/Runtime;->exec(Ljava/lang/String;)Ljava/lang/Process;
+        // 9: return-void
+        let run = builder.method("LTest;", "run", "V", &[]);
+        builder.code(
+            class,
+            run,
+            ACC_PUBLIC | ACC_STATIC,
+            2,
+            0,
+            2,
+            &[
+                0x0071,
+                get_runtime,
+                0x0000,
+                0x000c,
+                0x011a,
+                command,
+                0x206e,
+                exec,
+                0x0010,
+                0x000e,
+            ],
+        );
+        let dex = builder.build_dex();
+        let repo = testing::repo(&dex);
+        let (_, run) = testing::method(&repo, "run");
+
+        let usage = usage(&repo, &SENSITIVE_APIS).unwrap();
+        let exec = MethodDescr::try_from(
+            "Ljava/lang/Runtime;->exec(Ljava/lang/String;)Ljava/lang/Process;",
+        )
+        .unwrap();
+        assert_eq!(usage.len(), 1);
+        assert_eq!(usage[&exec], [(run.uid(), Addr(6))]);
+    }
+}
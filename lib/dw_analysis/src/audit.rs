@@ -0,0 +1,48 @@
+//! Consolidated security review of an application.
+//!
+//! The manifest-based detectors are run by [`Package::security_report`],
+//! this module completes their report with the findings of the detectors
+//! that require code analysis.
+
+use crate::callgraph::WebViewRisk;
+use crate::errors::AnalysisResult;
+use crate::repo::Repo;
+use dw_package::report::{Check, SecurityReport, Severity};
+use dw_package::Package;
+use std::collections::BTreeMap;
+
+/// Runs all the detectors on `package` and returns their findings.
+///
+/// Code-based detectors (`WebView` configurations) are run only when a
+/// repository holding the application code is given, so that manifest-only
+/// inputs can still be reviewed.
+///
+/// # Errors
+///
+/// This function may return an error if the manifest cannot be read or if
+/// the callgraph cannot be built.
+pub fn security_report(package: &Package, repo: Option<&Repo>) -> AnalysisResult<SecurityReport> {
+    let mut report = package.security_report()?;
+
+    if let Some(repo) = repo {
+        let cg = repo.build_callgraph()?;
+        for finding in cg.webview_findings(repo, &BTreeMap::new())? {
+            let method = repo[finding.method].descriptor();
+            let (severity, message) = match finding.risk {
+                WebViewRisk::JavaScriptInterface => (
+                    Severity::High,
+                    format!("JavaScript interface exposed with JavaScript enabled in {method}"),
+                ),
+                WebViewRisk::FileAccess => (
+                    Severity::Medium,
+                    format!("WebView file access allowed in {method}"),
+                ),
+            };
+            report.push(Check::WebView, severity, message);
+        }
+        report.code_analyzed = true;
+        report.sort();
+    }
+
+    Ok(report)
+}
@@ -1,16 +1,19 @@
 //! Graph representations of the possible calls between classes methods
 //! of an application and dependencies.
 
-use crate::errors::AnalysisResult;
+use crate::constants::{Constants, Value};
+use crate::errors::{AnalysisError, AnalysisResult};
 use crate::repo;
 use dw_dex::instrs::Instr;
 use dw_dex::registers::Reg;
 use dw_dex::types::Type;
 use dw_dex::{Addr, DexIndex};
+use petgraph::algo::tarjan_scc;
 use petgraph::dot::{Config, Dot};
 use petgraph::prelude::*;
 use petgraph::visit::{DfsPostOrder, NodeRef, Reversed};
-use std::collections::{BTreeMap, BTreeSet};
+use std::collections::btree_map::Entry;
+use std::collections::{BTreeMap, BTreeSet, VecDeque};
 use std::convert::TryFrom;
 use std::fmt::{self, Write};
 
@@ -52,6 +55,8 @@ pub struct Method<'a> {
     status: MethodStatus,
     zombie_roots: BTreeSet<Addr>,
     zombie_calls: BTreeSet<Addr>,
+    unreachable: bool,
+    removable: bool,
 }
 
 impl<'a> fmt::Display for Method<'a> {
@@ -67,6 +72,8 @@ impl<'a> Method<'a> {
             status,
             zombie_roots: BTreeSet::new(),
             zombie_calls: BTreeSet::new(),
+            unreachable: false,
+            removable: false,
         }
     }
 
@@ -75,7 +82,19 @@ impl<'a> Method<'a> {
     }
 
     pub fn is_zombie(&self) -> bool {
-        !self.zombie_roots.is_empty() || !self.zombie_calls.is_empty()
+        self.unreachable || !self.zombie_roots.is_empty() || !self.zombie_calls.is_empty()
+    }
+
+    /// Returns `true` if the method is not a zombie and its definition has
+    /// been resolved (either in the repository or as an inherited method).
+    pub fn is_reachable(&self) -> bool {
+        !self.is_zombie() && !matches!(self.status, MethodStatus::Unknown)
+    }
+
+    /// Returns `true` if the method code has been found dead by
+    /// [`CallGraph::prune_unreachable`].
+    pub fn is_removable(&self) -> bool {
+        self.removable
     }
 
     pub fn class_name(&self) -> String {
@@ -87,16 +106,51 @@ impl<'a> Method<'a> {
     }
 }
 
+/// The dispatch kind of an invocation instruction.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord)]
+pub enum CallKind {
+    /// `invoke-direct` and `invoke-super`, dispatched without virtual
+    /// method lookup on the receiver.
+    Direct,
+    Virtual,
+    Interface,
+    Static,
+}
+
+impl fmt::Display for CallKind {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            Self::Direct => write!(f, "direct"),
+            Self::Virtual => write!(f, "virtual"),
+            Self::Interface => write!(f, "interface"),
+            Self::Static => write!(f, "static"),
+        }
+    }
+}
+
+/// An invocation instruction of a method.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct CallEdge {
+    pub kind: CallKind,
+    /// The address of the invocation instruction in the caller.
+    pub site: Addr,
+    /// `false` if the invoked method cannot be found, neither in the
+    /// repository nor as an inherited method.
+    pub resolved: bool,
+}
+
 #[derive(Debug, Clone)]
 pub struct Call {
-    call_addrs: BTreeSet<Addr>,
+    /// Invocation instructions ordered by address, empty for calls that do
+    /// not appear in bytecode.
+    sites: Vec<CallEdge>,
 }
 
 impl fmt::Display for Call {
     fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
-        for (i, addr) in self.call_addrs.iter().enumerate() {
+        for (i, addr) in self.call_addrs().enumerate() {
             write!(f, "{addr}")?;
-            if i < self.call_addrs.len() - 1 {
+            if i < self.sites.len() - 1 {
                 write!(f, ", ")?;
             }
         }
@@ -104,6 +158,62 @@ impl fmt::Display for Call {
     }
 }
 
+impl Call {
+    fn call_addrs(&self) -> impl Iterator<Item = &Addr> {
+        self.sites.iter().map(|edge| &edge.site)
+    }
+}
+
+/// A call site registering a broadcast receiver at runtime.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct ReceiverSite {
+    /// The method containing the `registerReceiver` invocation.
+    pub method: repo::MethodUid,
+    /// The address of the `registerReceiver` invocation.
+    pub addr: Addr,
+    /// The intent filter actions that are known to be constant strings.
+    pub actions: BTreeSet<String>,
+}
+
+/// `WebView` configuration APIs involved in insecure usages.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord)]
+pub enum WebViewApi {
+    /// `WebSettings.setJavaScriptEnabled(true)`.
+    SetJavaScriptEnabled,
+    /// `WebView.addJavascriptInterface(Object, String)`.
+    AddJavascriptInterface,
+    /// `WebSettings.setAllowFileAccess(true)`.
+    SetAllowFileAccess,
+}
+
+/// A call site of a `WebView` configuration API.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct WebViewCall {
+    pub method: repo::MethodUid,
+    pub addr: Addr,
+    pub api: WebViewApi,
+}
+
+/// The risky `WebView` configurations.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord)]
+pub enum WebViewRisk {
+    /// A JavaScript interface is exposed while JavaScript is enabled.
+    JavaScriptInterface,
+    /// Access to the file system is allowed.
+    FileAccess,
+}
+
+/// An insecure `WebView` configuration found in a method.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct WebViewFinding {
+    pub risk: WebViewRisk,
+    /// The method in which the risky configuration is completed.
+    pub method: repo::MethodUid,
+    /// The API calls involved, possibly located in other methods of
+    /// the same class.
+    pub calls: Vec<WebViewCall>,
+}
+
 #[derive(Debug)]
 pub struct CallGraph<'a> {
     inner: DiGraph<Method<'a>, Call>,
@@ -142,7 +252,7 @@ impl<'a> CallGraph<'a> {
                 .filter(|meth| meth.code().is_some())
             {
                 let src = *nodes_map.get(method.descriptor()).unwrap(); // cannot panic due to first loop
-                for (called, call_addrs) in compute_calls(method)? {
+                for (called, sites) in compute_calls(method)? {
                     let dst = if let Some(id) = nodes_map.get(&called) {
                         *id
                     } else {
@@ -156,9 +266,7 @@ impl<'a> CallGraph<'a> {
                                 method.descriptor(),
                                 called,
                             );
-                            for call_addr in &call_addrs {
-                                cg[src].zombie_roots.insert(*call_addr);
-                            }
+                            cg[src].zombie_roots.extend(sites.keys());
                             m
                         } else if let Some(mdef) = repo.find_method_by_descriptor(&called) {
                             Method::new(MethodDef::Method(mdef), MethodStatus::System)
@@ -177,16 +285,23 @@ impl<'a> CallGraph<'a> {
                                 method.descriptor(),
                                 called,
                             );
-                            for call_addr in &call_addrs {
-                                cg[src].zombie_roots.insert(*call_addr);
-                            }
+                            cg[src].zombie_roots.extend(sites.keys());
                             m
                         };
                         let id = cg.add_node(m);
                         nodes_map.insert(called, id);
                         id
                     };
-                    cg.add_edge(src, dst, Call { call_addrs });
+                    let resolved = !matches!(cg[dst].status, MethodStatus::Unknown);
+                    let sites = sites
+                        .into_iter()
+                        .map(|(site, kind)| CallEdge {
+                            kind,
+                            site,
+                            resolved,
+                        })
+                        .collect();
+                    cg.add_edge(src, dst, Call { sites });
                 }
             }
         }
@@ -205,7 +320,23 @@ impl<'a> CallGraph<'a> {
             Dot::with_attr_getters(
                 &self.inner,
                 &[Config::GraphContentOnly],
-                &|_, _| String::new(),
+                &|_, edge| {
+                    // calls that do not appear in bytecode have no kind, and
+                    // calls through several kinds of invocation have no
+                    // single one
+                    let mut kinds = edge.weight().sites.iter().map(|site| site.kind);
+                    let kind = kinds
+                        .next()
+                        .filter(|first| kinds.all(|kind| kind == *first));
+                    let color = match kind {
+                        Some(CallKind::Direct) => "black",
+                        Some(CallKind::Virtual) => "blue",
+                        Some(CallKind::Interface) => "darkgreen",
+                        Some(CallKind::Static) => "orange",
+                        None => "gray",
+                    };
+                    format!("color={color}")
+                },
                 &|_, node| {
                     let m = node.weight();
                     let color = if matches!(m.status, MethodStatus::System) {
@@ -226,6 +357,85 @@ impl<'a> CallGraph<'a> {
         res
     }
 
+    /// Renders the callgraph in the GraphML format. Methods defined in the
+    /// repository are identified by their [`repo::MethodUid`], unresolved
+    /// ones by their node index prefixed with `n`. The `kind` of an edge
+    /// lists the comma-separated [`CallKind`]s of its invocations.
+    #[must_use]
+    pub fn to_graphml(&self) -> String {
+        use html_escape::encode_text;
+
+        let node_id = |id: NodeIndex| match self.inner[id].def {
+            MethodDef::Method(m) => format!("{}", m.uid()),
+            MethodDef::Descriptor(_) => format!("n{}", id.index()),
+        };
+
+        let mut res = String::new();
+        res.push_str("<?xml version=\"1.0\" encoding=\"UTF-8\"?>\n");
+        res.push_str("<graphml xmlns=\"http://graphml.graphdrawing.org/xmlns\">\n");
+        for (id, domain, name, typ) in [
+            ("method", "node", "method", "string"),
+            ("class", "node", "class", "string"),
+            ("is_system", "node", "is_system", "boolean"),
+            ("is_zombie", "node", "is_zombie", "boolean"),
+            ("kind", "edge", "kind", "string"),
+        ] {
+            writeln!(
+                res,
+                "  <key id=\"{id}\" for=\"{domain}\" attr.name=\"{name}\" attr.type=\"{typ}\"/>"
+            )
+            .unwrap();
+        }
+        res.push_str("  <graph edgedefault=\"directed\">\n");
+        for id in self.inner.node_indices() {
+            let m = &self.inner[id];
+            writeln!(res, "    <node id=\"{}\">", node_id(id)).unwrap();
+            writeln!(
+                res,
+                "      <data key=\"method\">{}</data>",
+                encode_text(&m.to_string())
+            )
+            .unwrap();
+            writeln!(
+                res,
+                "      <data key=\"class\">{}</data>",
+                encode_text(&m.class_name())
+            )
+            .unwrap();
+            writeln!(
+                res,
+                "      <data key=\"is_system\">{}</data>",
+                matches!(m.status, MethodStatus::System)
+            )
+            .unwrap();
+            writeln!(
+                res,
+                "      <data key=\"is_zombie\">{}</data>",
+                m.is_zombie()
+            )
+            .unwrap();
+            res.push_str("    </node>\n");
+        }
+        for edge in self.inner.edge_references() {
+            write!(
+                res,
+                "    <edge source=\"{}\" target=\"{}\">",
+                node_id(edge.source()),
+                node_id(edge.target())
+            )
+            .unwrap();
+            let kinds: BTreeSet<CallKind> = edge.weight().sites.iter().map(|e| e.kind).collect();
+            let kinds: Vec<String> = kinds.iter().map(ToString::to_string).collect();
+            if !kinds.is_empty() {
+                write!(res, "<data key=\"kind\">{}</data>", kinds.join(",")).unwrap();
+            }
+            res.push_str("</edge>\n");
+        }
+        res.push_str("  </graph>\n");
+        res.push_str("</graphml>\n");
+        res
+    }
+
     pub fn mark_unknown_refs(&mut self, repo: &repo::Repo) -> AnalysisResult<()> {
         for id in self.inner.node_indices() {
             let mut new_zombie_roots = BTreeSet::new();
@@ -328,15 +538,13 @@ impl<'a> CallGraph<'a> {
         while !todo.is_empty() {
             let dst = todo.pop_first().unwrap();
             visited.insert(dst);
-            let callers: Vec<(NodeIndex, BTreeSet<Addr>)> = self
+            let callers: Vec<(NodeIndex, Vec<Addr>)> = self
                 .inner
                 .edges_directed(dst, Direction::Incoming)
-                .map(|edge| (edge.source(), edge.weight().call_addrs.clone()))
+                .map(|edge| (edge.source(), edge.weight().call_addrs().copied().collect()))
                 .collect();
             for (src, addrs) in callers {
-                for addr in addrs {
-                    self.inner[src].zombie_calls.insert(addr);
-                }
+                self.inner[src].zombie_calls.extend(addrs);
                 if !visited.contains(&src) {
                     todo.insert(src);
                 }
@@ -444,7 +652,304 @@ impl<'a> CallGraph<'a> {
         Ok(())
     }
 
-    pub fn traverse_from_callees_to_callers(&self) -> CGRevIterator {
+    fn node_of(&self, uid: repo::MethodUid) -> AnalysisResult<NodeIndex> {
+        self.inner
+            .node_indices()
+            .find(|id| matches!(self.inner[*id].def, MethodDef::Method(m) if m.uid() == uid))
+            .ok_or_else(|| AnalysisError::Internal(format!("method {uid:?} not in callgraph")))
+    }
+
+    /// Returns the invocation instructions of the `caller` method, each
+    /// with the invoked method if it is defined in the repository. Calls
+    /// that do not appear in bytecode, such as the ones added with
+    /// [`CallGraph::add_call`], are left out.
+    ///
+    /// Nothing is returned if the method is not a node of the callgraph.
+    pub fn edges_from(
+        &self,
+        caller: repo::MethodUid,
+    ) -> impl Iterator<Item = (Option<repo::MethodUid>, &CallEdge)> {
+        self.node_of(caller)
+            .into_iter()
+            .flat_map(|id| self.inner.edges_directed(id, Direction::Outgoing))
+            .flat_map(|edge| {
+                let callee = match self.inner[edge.target()].def {
+                    MethodDef::Method(m) => Some(m.uid()),
+                    MethodDef::Descriptor(_) => None,
+                };
+                edge.weight().sites.iter().map(move |site| (callee, site))
+            })
+    }
+
+    /// Adds a call from `caller` to `callee` that does not appear in
+    /// bytecode, such as a call through reflection.
+    ///
+    /// # Errors
+    ///
+    /// This function returns an error if one of the methods is not a node
+    /// of the callgraph.
+    pub fn add_call(
+        &mut self,
+        caller: repo::MethodUid,
+        callee: repo::MethodUid,
+    ) -> AnalysisResult<()> {
+        let src = self.node_of(caller)?;
+        let dst = self.node_of(callee)?;
+        if self.inner.find_edge(src, dst).is_none() {
+            self.inner.add_edge(src, dst, Call { sites: Vec::new() });
+        }
+        Ok(())
+    }
+
+    /// Marks as zombies the application methods that are not reachable
+    /// from `roots`, and unmarks the reachable ones, so that zombies reflect
+    /// calls added after the callgraph has been built. When `roots` is
+    /// empty, application methods without callers are taken as roots.
+    ///
+    /// Methods calling unknown methods stay zombies whatever their
+    /// reachability.
+    ///
+    /// # Errors
+    ///
+    /// This function returns an error if one of the roots is not a node of
+    /// the callgraph.
+    pub fn recompute_reachability(&mut self, roots: &[repo::MethodUid]) -> AnalysisResult<()> {
+        let roots: Vec<NodeIndex> = if roots.is_empty() {
+            self.inner
+                .node_indices()
+                .filter(|id| {
+                    matches!(self.inner[*id].status, MethodStatus::App)
+                        && self
+                            .inner
+                            .neighbors_directed(*id, Direction::Incoming)
+                            .next()
+                            .is_none()
+                })
+                .collect()
+        } else {
+            roots
+                .iter()
+                .map(|uid| self.node_of(*uid))
+                .collect::<AnalysisResult<_>>()?
+        };
+
+        let mut reachable = BTreeSet::new();
+        let mut dfs = Dfs::empty(&self.inner);
+        for root in roots {
+            dfs.move_to(root);
+            while let Some(id) = dfs.next(&self.inner) {
+                reachable.insert(id);
+            }
+        }
+
+        for id in self.inner.node_indices() {
+            let method = &mut self.inner[id];
+            if matches!(method.status, MethodStatus::App) {
+                method.unreachable = !reachable.contains(&id);
+            }
+        }
+        Ok(())
+    }
+
+    /// Marks as removable the application methods with code that are not
+    /// reachable from `roots`, and unmarks the reachable ones. The code of
+    /// removable methods can then be stubbed out with
+    /// [`Repo::stub_methods`](repo::Repo::stub_methods).
+    ///
+    /// Virtual calls are bound to the invoked method descriptor only, so
+    /// that overriding methods look unreachable: the application methods
+    /// overriding (or implementing) a method of a parent class are thus
+    /// roots as well, as the runtime may dispatch calls to them. Only direct
+    /// methods (private or static methods) are marked, except static
+    /// initializers and constructors, which are invoked by the runtime.
+    /// Roots that are not nodes of the callgraph are ignored.
+    pub fn prune_unreachable(&mut self, repo: &repo::Repo, roots: &[repo::MethodUid]) {
+        let overriding = self.inner.node_indices().filter(|id| {
+            matches!(self.inner[*id].status, MethodStatus::App)
+                && matches!(self.inner[*id].def, MethodDef::Method(m) if repo.overrides(m))
+        });
+        let roots: Vec<NodeIndex> = roots
+            .iter()
+            .filter_map(|uid| self.node_of(*uid).ok())
+            .chain(overriding)
+            .collect();
+
+        let mut reachable = BTreeSet::new();
+        let mut dfs = Dfs::empty(&self.inner);
+        for root in roots {
+            dfs.move_to(root);
+            while let Some(id) = dfs.next(&self.inner) {
+                reachable.insert(id);
+            }
+        }
+
+        for id in self.inner.node_indices() {
+            let removable = !reachable.contains(&id)
+                && matches!(self.inner[id].status, MethodStatus::App)
+                && matches!(
+                    self.inner[id].def,
+                    MethodDef::Method(m) if m.code().is_some()
+                        && (m.is_private() || m.is_static())
+                        && !matches!(m.name(), "<init>" | "<clinit>")
+                );
+            self.inner[id].removable = removable;
+        }
+    }
+
+    /// Iterates over the methods marked as removable by
+    /// [`CallGraph::prune_unreachable`].
+    pub fn removable_methods(&self) -> impl Iterator<Item = repo::MethodUid> + '_ {
+        self.inner
+            .node_indices()
+            .filter(|id| self.inner[*id].removable)
+            .filter_map(|id| self.uid_of(id))
+    }
+
+    /// Returns the strongly connected components of the callgraph, i.e. the
+    /// groups of mutually recursive methods, in reverse topological order:
+    /// components of callees come before components of their callers.
+    ///
+    /// Methods that are not defined in the repository are left out.
+    #[must_use]
+    pub fn sccs(&self) -> Vec<Vec<repo::MethodUid>> {
+        self.components()
+            .iter()
+            .map(|component| self.uids(component))
+            .collect()
+    }
+
+    /// Returns the DAG of the strongly connected components of the
+    /// callgraph, with an edge from a component to another whenever a method
+    /// of the former calls a method of the latter. Nodes are indexed in the
+    /// order of [`CallGraph::sccs`].
+    #[must_use]
+    pub fn condensation(&self) -> DiGraph<Vec<repo::MethodUid>, ()> {
+        let components = self.components();
+        let mut dag = DiGraph::with_capacity(components.len(), 0);
+        let mut component_of = BTreeMap::new();
+        for component in &components {
+            let id = dag.add_node(self.uids(component));
+            for node in component {
+                component_of.insert(*node, id);
+            }
+        }
+        for edge in self.inner.edge_references() {
+            if let (Some(src), Some(dst)) = (
+                component_of.get(&edge.source()),
+                component_of.get(&edge.target()),
+            ) {
+                if src != dst {
+                    dag.update_edge(*src, *dst, ());
+                }
+            }
+        }
+        dag
+    }
+
+    // Tarjan's algorithm yields components in reverse topological order.
+    fn components(&self) -> Vec<Vec<NodeIndex>> {
+        tarjan_scc(&self.inner)
+            .into_iter()
+            .filter(|component| component.iter().any(|id| self.inner[*id].is_defined()))
+            .collect()
+    }
+
+    fn uids(&self, component: &[NodeIndex]) -> Vec<repo::MethodUid> {
+        component.iter().filter_map(|id| self.uid_of(*id)).collect()
+    }
+
+    fn uid_of(&self, id: NodeIndex) -> Option<repo::MethodUid> {
+        match self.inner[id].def {
+            MethodDef::Method(m) => Some(m.uid()),
+            MethodDef::Descriptor(_) => None,
+        }
+    }
+
+    fn neighbors(
+        &self,
+        uid: repo::MethodUid,
+        direction: Direction,
+    ) -> impl Iterator<Item = repo::MethodUid> + '_ {
+        self.node_of(uid)
+            .ok()
+            .into_iter()
+            .flat_map(move |id| self.inner.neighbors_directed(id, direction))
+            .filter_map(|id| self.uid_of(id))
+    }
+
+    /// Iterates over the methods calling the given one. Nothing is returned
+    /// if the method is not a node of the callgraph.
+    pub fn callers(&self, uid: repo::MethodUid) -> impl Iterator<Item = repo::MethodUid> + '_ {
+        self.neighbors(uid, Direction::Incoming)
+    }
+
+    /// Iterates over the methods called by the given one, leaving out the
+    /// methods that are not defined in the repository. Nothing is returned
+    /// if the method is not a node of the callgraph.
+    pub fn callees(&self, uid: repo::MethodUid) -> impl Iterator<Item = repo::MethodUid> + '_ {
+        self.neighbors(uid, Direction::Outgoing)
+    }
+
+    /// Returns the methods that may be (transitively) called from `roots`,
+    /// roots included. Roots that are not nodes of the callgraph are
+    /// ignored.
+    #[must_use]
+    pub fn reachable_from(&self, roots: &[repo::MethodUid]) -> BTreeSet<repo::MethodUid> {
+        let mut reached: BTreeSet<NodeIndex> = roots
+            .iter()
+            .filter_map(|uid| self.node_of(*uid).ok())
+            .collect();
+        let mut queue: VecDeque<NodeIndex> = reached.iter().copied().collect();
+        while let Some(id) = queue.pop_front() {
+            for next in self.inner.neighbors_directed(id, Direction::Outgoing) {
+                if reached.insert(next) {
+                    queue.push_back(next);
+                }
+            }
+        }
+        reached
+            .into_iter()
+            .filter_map(|id| self.uid_of(id))
+            .collect()
+    }
+
+    /// Returns one of the shortest chains of calls leading from `from` to
+    /// `to`, both included, or `None` if `to` cannot be reached from `from`.
+    #[must_use]
+    pub fn shortest_path(
+        &self,
+        from: repo::MethodUid,
+        to: repo::MethodUid,
+    ) -> Option<Vec<repo::MethodUid>> {
+        let start = self.node_of(from).ok()?;
+        let target = self.node_of(to).ok()?;
+
+        // breadth-first traversal, remembering from where each method is
+        // discovered first
+        let mut parents = BTreeMap::from([(start, start)]);
+        let mut queue = VecDeque::from([start]);
+        while let Some(id) = queue.pop_front() {
+            if id == target {
+                let mut path = vec![id];
+                let mut current = id;
+                while current != start {
+                    current = parents[&current];
+                    path.push(current);
+                }
+                path.reverse();
+                return path.into_iter().map(|id| self.uid_of(id)).collect();
+            }
+            for next in self.inner.neighbors_directed(id, Direction::Outgoing) {
+                if let Entry::Vacant(entry) = parents.entry(next) {
+                    entry.insert(id);
+                    queue.push_back(next);
+                }
+            }
+        }
+        None
+    }
+
+    pub fn traverse_from_callees_to_callers(&self) -> CGRevIterator<'_> {
         CGRevIterator::new(&self.inner)
     }
 
@@ -481,6 +986,187 @@ impl<'a> CallGraph<'a> {
         }
     }
 
+    /// Returns the subgraph induced by the methods satisfying `predicate`.
+    /// Contrary to [`CallGraph::filter`], callers of kept methods are not
+    /// kept unless they satisfy the predicate too.
+    pub fn retain<P>(&self, predicate: P) -> Self
+    where
+        P: Fn(&Method) -> bool,
+    {
+        let mut stable_graph: StableDiGraph<_, _> = self.inner.clone().into();
+        stable_graph.retain_nodes(|graph, id| predicate(&graph[id]));
+        Self {
+            inner: stable_graph.into(),
+        }
+    }
+
+    /// Lists the call sites of `registerReceiver` methods, which register
+    /// broadcast receivers at runtime.
+    ///
+    /// Actions of the registered intent filter are the constant ones given
+    /// to its constructor and to `IntentFilter.addAction`, as tracked by the
+    /// constants pass up to the call site. Constants states of calling
+    /// methods are taken from `constants` when available, and computed
+    /// otherwise.
+    ///
+    /// # Errors
+    ///
+    /// This function may return an error if a calling method cannot be
+    /// analyzed.
+    pub fn dynamic_receivers(
+        &self,
+        repo: &repo::Repo,
+        constants: &BTreeMap<repo::MethodUid, Constants>,
+    ) -> AnalysisResult<Vec<ReceiverSite>> {
+        let mut res = Vec::new();
+        for edge in self.inner.edge_references() {
+            if !is_register_receiver(self.inner[edge.target()].def.descriptor()) {
+                continue;
+            }
+            let MethodDef::Method(method) = &self.inner[edge.source()].def else {
+                continue;
+            };
+            let computed;
+            let constants = match constants.get(&method.uid()) {
+                Some(constants) => constants,
+                None => {
+                    computed = compute_constants(method, repo)?;
+                    &computed
+                }
+            };
+            let invokes = compute_invokes(method)?;
+            for addr in edge.weight().call_addrs() {
+                let Some((_, args)) = invokes.get(addr) else {
+                    continue;
+                };
+                // the intent filter is the second parameter, after 'this'
+                let Some(filter) = args.get(2) else {
+                    continue;
+                };
+                let actions = match constants.entries.get(addr) {
+                    Some(state) => match state.read_reg(*filter)? {
+                        Some(Value::IntentFilter(actions)) => actions.clone(),
+                        _ => BTreeSet::new(),
+                    },
+                    None => BTreeSet::new(),
+                };
+                res.push(ReceiverSite {
+                    method: method.uid(),
+                    addr: *addr,
+                    actions,
+                });
+            }
+        }
+        Ok(res)
+    }
+
+    /// Looks for insecure `WebView` configurations.
+    ///
+    /// A [`WebViewRisk::JavaScriptInterface`] finding is reported for each
+    /// method adding a JavaScript interface, when JavaScript is enabled in
+    /// the same method or, failing that, elsewhere in the same class. A
+    /// [`WebViewRisk::FileAccess`] finding is reported for each method
+    /// allowing file access. Boolean arguments must be constant (as given
+    /// by `constants` or computed otherwise) to be taken into account.
+    ///
+    /// # Errors
+    ///
+    /// This function may return an error if a calling method cannot be
+    /// analyzed.
+    pub fn webview_findings(
+        &self,
+        repo: &repo::Repo,
+        constants: &BTreeMap<repo::MethodUid, Constants>,
+    ) -> AnalysisResult<Vec<WebViewFinding>> {
+        let mut methods: BTreeMap<repo::MethodUid, &repo::Method> = BTreeMap::new();
+        let mut calls: Vec<WebViewCall> = Vec::new();
+        for edge in self.inner.edge_references() {
+            let Some(api) = webview_api(self.inner[edge.target()].def.descriptor()) else {
+                continue;
+            };
+            let MethodDef::Method(method) = &self.inner[edge.source()].def else {
+                continue;
+            };
+            methods.insert(method.uid(), method);
+            if api == WebViewApi::AddJavascriptInterface {
+                calls.extend(edge.weight().call_addrs().map(|addr| WebViewCall {
+                    method: method.uid(),
+                    addr: *addr,
+                    api,
+                }));
+                continue;
+            }
+
+            let computed;
+            let constants = match constants.get(&method.uid()) {
+                Some(constants) => constants,
+                None => {
+                    computed = compute_constants(method, repo)?;
+                    &computed
+                }
+            };
+            let invokes = compute_invokes(method)?;
+            for addr in edge.weight().call_addrs() {
+                let (Some((_, args)), Some(state)) =
+                    (invokes.get(addr), constants.entries.get(addr))
+                else {
+                    continue;
+                };
+                // the boolean flag is the first parameter, after 'this'
+                let Some(flag) = args.get(1) else {
+                    continue;
+                };
+                if matches!(state.read_reg(*flag)?, Some(Value::Int(v)) if *v != 0) {
+                    calls.push(WebViewCall {
+                        method: method.uid(),
+                        addr: *addr,
+                        api,
+                    });
+                }
+            }
+        }
+        calls.sort_by_key(|call| (call.method, call.addr));
+
+        let class_of = |uid: &repo::MethodUid| methods[uid].definer().class_name();
+        let mut res = Vec::new();
+        for call in &calls {
+            match call.api {
+                WebViewApi::SetJavaScriptEnabled => (),
+                WebViewApi::AddJavascriptInterface => {
+                    let enabling = |same_method: bool| {
+                        calls.iter().filter(move |c| {
+                            c.api == WebViewApi::SetJavaScriptEnabled
+                                && if same_method {
+                                    c.method == call.method
+                                } else {
+                                    class_of(&c.method) == class_of(&call.method)
+                                }
+                        })
+                    };
+                    let mut involved: Vec<WebViewCall> = enabling(true).cloned().collect();
+                    if involved.is_empty() {
+                        involved = enabling(false).cloned().collect();
+                    }
+                    if involved.is_empty() {
+                        continue;
+                    }
+                    involved.push(call.clone());
+                    res.push(WebViewFinding {
+                        risk: WebViewRisk::JavaScriptInterface,
+                        method: call.method,
+                        calls: involved,
+                    });
+                }
+                WebViewApi::SetAllowFileAccess => res.push(WebViewFinding {
+                    risk: WebViewRisk::FileAccess,
+                    method: call.method,
+                    calls: vec![call.clone()],
+                }),
+            }
+        }
+        Ok(res)
+    }
+
     pub fn nb_methods(&self) -> usize {
         self.inner.node_count()
     }
@@ -492,14 +1178,20 @@ impl<'a> CallGraph<'a> {
             .count()
     }
 
+    pub fn nb_removable_methods(&self) -> usize {
+        self.inner.node_weights().filter(|m| m.removable).count()
+    }
+
     pub fn nb_zombie_methods(&self) -> usize {
         self.inner.node_weights().filter(|m| m.is_zombie()).count()
     }
 }
 
-fn compute_calls(
+// Returns the invoked method descriptors of the given method, with the
+// address and kind of each of their invocation instructions.
+pub(crate) fn compute_calls(
     method: &repo::Method,
-) -> AnalysisResult<BTreeMap<repo::MethodDescr, BTreeSet<Addr>>> {
+) -> AnalysisResult<BTreeMap<repo::MethodDescr, BTreeMap<Addr, CallKind>>> {
     let mut map = BTreeMap::new();
     let Some(code) = method.code() else {
         return Ok(map);
@@ -507,36 +1199,91 @@ fn compute_calls(
     let dex = method.dex();
 
     for instr in code.read().unwrap().iter_instructions() {
-        match instr.instr() {
-            Instr::InvokeVirtual(_, m)
-            | Instr::InvokeSuper(_, m)
-            | Instr::InvokeDirect(_, m)
-            | Instr::InvokeStatic(_, m)
-            | Instr::InvokeInterface(_, m)
-            | Instr::InvokeVirtualRange(_, m)
+        let (m, kind) = match instr.instr() {
+            Instr::InvokeVirtual(_, m) | Instr::InvokeVirtualRange(_, m) => (m, CallKind::Virtual),
+            Instr::InvokeSuper(_, m)
             | Instr::InvokeSuperRange(_, m)
-            | Instr::InvokeDirectRange(_, m)
-            | Instr::InvokeStaticRange(_, m)
-            | Instr::InvokeInterfaceRange(_, m) => {
-                let descriptor = m.get(dex)?;
-                let prototype = repo::MethodDescr::try_from((dex, descriptor))?;
-                match map.get_mut(&prototype) {
-                    None => {
-                        let addrs = BTreeSet::from([instr.addr()]);
-                        map.insert(prototype, addrs);
-                    }
-                    Some(addrs) => {
-                        addrs.insert(instr.addr());
-                    }
-                }
+            | Instr::InvokeDirect(_, m)
+            | Instr::InvokeDirectRange(_, m) => (m, CallKind::Direct),
+            Instr::InvokeStatic(_, m) | Instr::InvokeStaticRange(_, m) => (m, CallKind::Static),
+            Instr::InvokeInterface(_, m) | Instr::InvokeInterfaceRange(_, m) => {
+                (m, CallKind::Interface)
             }
-            _ => (),
+            _ => continue,
+        };
+        let descriptor = m.get(dex)?;
+        let prototype = repo::MethodDescr::try_from((dex, descriptor))?;
+        map.entry(prototype)
+            .or_insert_with(BTreeMap::new)
+            .insert(instr.addr(), kind);
+    }
+
+    Ok(map)
+}
+
+// Returns the invoked method descriptor and arguments registers of every
+// invocation instruction of the given method, indexed by address.
+fn compute_invokes(
+    method: &repo::Method,
+) -> AnalysisResult<BTreeMap<Addr, (repo::MethodDescr, Vec<Reg>)>> {
+    let mut map = BTreeMap::new();
+    let Some(code) = method.code() else {
+        return Ok(map);
+    };
+    let dex = method.dex();
+
+    for instr in code.read().unwrap().iter_instructions() {
+        let (args, m): (Vec<Reg>, _) = match instr.instr() {
+            Instr::InvokeVirtual(args, m)
+            | Instr::InvokeSuper(args, m)
+            | Instr::InvokeDirect(args, m)
+            | Instr::InvokeStatic(args, m)
+            | Instr::InvokeInterface(args, m) => (args.iter().collect(), m),
+            Instr::InvokeVirtualRange(args, m)
+            | Instr::InvokeSuperRange(args, m)
+            | Instr::InvokeDirectRange(args, m)
+            | Instr::InvokeStaticRange(args, m)
+            | Instr::InvokeInterfaceRange(args, m) => (args.iter().collect(), m),
+            _ => continue,
         };
+        let descriptor = repo::MethodDescr::try_from((dex, m.get(dex)?))?;
+        map.insert(instr.addr(), (descriptor, args));
     }
 
     Ok(map)
 }
 
+fn compute_constants(method: &repo::Method, repo: &repo::Repo) -> AnalysisResult<Constants> {
+    let class_name = method.definer().class_name();
+    let class = repo
+        .get_class_by_name(&class_name)
+        .ok_or(AnalysisError::ClassNotFound(class_name))?;
+    Constants::compute(method, class)
+}
+
+fn is_register_receiver(descr: &repo::MethodDescr) -> bool {
+    descr.name() == "registerReceiver"
+        && matches!(
+            &descr.parameters_types()[..],
+            [Type::Class(receiver), Type::Class(filter), ..]
+                if receiver == "android/content/BroadcastReceiver"
+                    && filter == "android/content/IntentFilter"
+        )
+}
+
+fn webview_api(descr: &repo::MethodDescr) -> Option<WebViewApi> {
+    match (descr.name(), &descr.parameters_types()[..]) {
+        ("setJavaScriptEnabled", [Type::Boolean]) => Some(WebViewApi::SetJavaScriptEnabled),
+        ("setAllowFileAccess", [Type::Boolean]) => Some(WebViewApi::SetAllowFileAccess),
+        ("addJavascriptInterface", [Type::Class(object), Type::Class(name)])
+            if object == "java/lang/Object" && name == "java/lang/String" =>
+        {
+            Some(WebViewApi::AddJavascriptInterface)
+        }
+        _ => None,
+    }
+}
+
 pub struct CGRevIterator<'a> {
     graph: &'a DiGraph<Method<'a>, Call>,
     dfspo: DfsPostOrder<NodeIndex, fixedbitset::FixedBitSet>,
@@ -577,3 +1324,665 @@ impl<'a> Iterator for CGRevIterator<'a> {
         }
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::testing::{self, DexBuilder, ACC_PRIVATE, ACC_PUBLIC, ACC_STATIC};
+
+    #[test]
+    fn prune_unreachable_private_method() {
+        let mut builder = DexBuilder::new();
+        let class = builder.class("LMain;", "Ljava/lang/Object;");
+        // return-void
+        let used = builder.method("LMain;", "used", "V", &[]);
+        builder.code(class, used, ACC_PRIVATE, 1, 1, 0, &[0x000e]);
+        let unused = builder.method("LMain;", "unused", "V", &[]);
+        builder.code(class, unused, ACC_PRIVATE, 1, 1, 0, &[0x000e]);
+        // invoke-direct {p0}, LMain;->used()V
+        // return-void
+        let on_create = builder.method("LMain;", "onCreate", "V", &[]);
+        builder.code(
+            class,
+            on_create,
+            ACC_PUBLIC,
+            1,
+            1,
+            1,
+            &[0x1070, used as u16, 0x0000, 0x000e],
+        );
+        let dex = builder.build_dex();
+        let mut repo = testing::repo(&dex);
+        let [on_create, unused] = ["onCreate", "unused"].map(|name| {
+            let (_, method) = testing::method(&repo, name);
+            method.uid()
+        });
+
+        let mut cg = repo.build_callgraph().unwrap();
+        cg.prune_unreachable(&repo, &[on_create]);
+        let removable: Vec<_> = cg.removable_methods().collect();
+        assert_eq!(removable, [unused]);
+        assert_eq!(cg.nb_removable_methods(), 1);
+        assert_eq!(repo.stub_methods(&removable), 1);
+
+        let output = dw_dex::write(&dex, &dw_dex::WriteOptions::default()).unwrap();
+        let saved = dw_dex::parse(&output).unwrap();
+        let repo = testing::repo(&saved);
+        let instrs = |name: &str| -> Vec<Instr> {
+            let (_, method) = testing::method(&repo, name);
+            let code = method.code().unwrap().read().unwrap();
+            code.iter_instructions()
+                .map(|i| i.instr().clone())
+                .collect()
+        };
+        assert!(matches!(
+            instrs("unused")[..],
+            [Instr::Const4(_, 0), Instr::Throw(_)]
+        ));
+        assert!(matches!(instrs("used")[..], [Instr::ReturnVoid]));
+        assert!(matches!(
+            instrs("onCreate")[..],
+            [Instr::InvokeDirect(..), Instr::ReturnVoid]
+        ));
+    }
+
+    #[test]
+    fn prune_unreachable_keeps_overriding_methods() {
+        let mut builder = DexBuilder::new();
+        let class = builder.class("LTask;", "Ljava/lang/Object;");
+        builder.implements(class, "Ljava/lang/Runnable;");
+        // return-void
+        let helper = builder.method("LTask;", "helper", "V", &[]);
+        builder.code(class, helper, ACC_PRIVATE, 1, 1, 0, &[0x000e]);
+        let unused = builder.method("LTask;", "unused", "V", &[]);
+        builder.code(class, unused, ACC_PRIVATE, 1, 1, 0, &[0x000e]);
+        // invoke-direct {p0}, LTask;->helper()V
+        // return-void
+        let run = builder.method("LTask;", "run", "V", &[]);
+        builder.code(
+            class,
+            run,
+            ACC_PUBLIC,
+            1,
+            1,
+            1,
+            &[0x1070, helper as u16, 0x0000, 0x000e],
+        );
+        let dex = builder.build_dex();
+        let repo = testing::repo(&dex);
+        let (_, unused) = testing::method(&repo, "unused");
+
+        // run() is only called by the runtime, through Runnable
+        let mut cg = repo.build_callgraph().unwrap();
+        cg.prune_unreachable(&repo, &[]);
+        let removable: Vec<_> = cg.removable_methods().collect();
+        assert_eq!(removable, [unused.uid()]);
+    }
+
+    #[test]
+    fn graphml_export() {
+        let mut builder = DexBuilder::new();
+        let class = builder.class("LTest;", "Ljava/lang/Object;");
+        let helper = builder.method("LTest;", "helper", "V", &[]);
+        builder.code(class, helper, ACC_STATIC, 0, 0, 0, &[0x000e]);
+        let virt = builder.method("LTest;", "virt", "V", &[]);
+        builder.code(class, virt, ACC_PUBLIC, 1, 1, 0, &[0x000e]);
+        let init = builder.method("LOther;", "<init>", "V", &[]) as u16;
+        let run = builder.method("LTest;", "run", "V", &[]);
+        let (helper, virt) = (helper as u16, virt as u16);
+        builder.code(
+            class,
+            run,
+            ACC_PUBLIC,
+            1,
+            1,
+            1,
+            &[
+                0x0071, helper, 0x0000, // 0: invoke-static {}, helper
+                0x106e, virt, 0x0000, // 3: invoke-virtual {v0}, virt
+                0x1070, init, 0x0000, // 6: invoke-direct {v0}, LOther;-><init>
+                0x0071, helper, 0x0000, // 9: invoke-static {}, helper
+                0x000e, // 12: return-void
+            ],
+        );
+        let dex = builder.build_dex();
+        let repo = testing::repo(&dex);
+
+        let cg = repo.build_callgraph().unwrap();
+        let graphml = cg.to_graphml();
+
+        // every element is closed in order, and no raw '<' leaks in text
+        let mut stack = Vec::new();
+        for tag in graphml.split('<').skip(1) {
+            let (tag, _) = tag.split_once('>').unwrap();
+            if tag.starts_with('?') || tag.ends_with('/') {
+                continue;
+            }
+            if let Some(name) = tag.strip_prefix('/') {
+                assert_eq!(stack.pop(), Some(name));
+            } else {
+                stack.push(tag.split(' ').next().unwrap());
+            }
+        }
+        assert!(stack.is_empty());
+
+        assert_eq!(cg.nb_methods(), 4);
+        assert_eq!(graphml.matches("<node ").count(), cg.nb_methods());
+        assert_eq!(graphml.matches("<edge ").count(), 3);
+        assert!(graphml.contains("&lt;init&gt;"));
+        assert!(graphml.contains("<data key=\"kind\">static</data>"));
+        assert!(graphml.contains("<data key=\"kind\">virtual</data>"));
+        assert!(graphml.contains("<data key=\"kind\">direct</data>"));
+        assert_eq!(
+            graphml
+                .matches("<data key=\"is_zombie\">true</data>")
+                .count(),
+            cg.nb_zombie_methods()
+        );
+    }
+
+    #[test]
+    fn dynamic_receiver_constant_action() {
+        let mut builder = DexBuilder::new();
+        let class = builder.class("LTest;", "Ljava/lang/Object;");
+        let filter = builder.type_("Landroid/content/IntentFilter;") as u16;
+        let init = builder.method("Landroid/content/IntentFilter;", "<init>", "V", &[]) as u16;
+        let add_action = builder.method(
+            "Landroid/content/IntentFilter;",
+            "addAction",
+            "V",
+            &["Ljava/lang/String;"],
+        ) as u16;
+        let register = builder.method(
+            "Landroid/content/Context;",
+            "registerReceiver",
+            "Landroid/content/Intent;",
+            &[
+                "Landroid/content/BroadcastReceiver;",
+                "Landroid/content/IntentFilter;",
+            ],
+        ) as u16;
+        let action = builder.string("android.intent.action.BOOT_COMPLETED") as u16;
+        let method = builder.method("LTest;", "run", "V", &["Landroid/content/Context;"]);
+        builder.code(
+            class,
+            method,
+            ACC_PUBLIC | ACC_STATIC,
+            4,
+            1,
+            3,
+            &[
+                0x0022, filter, // 0: new-instance v0, IntentFilter
+                0x1070, init, 0x0000, // 2: invoke-direct {v0}, IntentFilter.<init>()
+                0x011a, action, // 5: const-string v1, "android.intent.action.BOOT_COMPLETED"
+                0x206e, add_action, 0x0010, // 7: invoke-virtual {v0, v1}, addAction
+                0x0212, // 10: const/4 v2, 0
+                0x306e, register, 0x0023, // 11: invoke-virtual {v3, v2, v0}, registerReceiver
+                0x000e, // 14: return-void
+            ],
+        );
+        let dex = builder.build_dex();
+        let repo = testing::repo(&dex);
+
+        let cg = repo.build_callgraph().unwrap();
+        let sites = cg.dynamic_receivers(&repo, &BTreeMap::new()).unwrap();
+        assert_eq!(sites.len(), 1);
+        assert_eq!(repo[sites[0].method].name(), "run");
+        assert_eq!(sites[0].addr, Addr(11));
+        assert_eq!(
+            sites[0].actions,
+            BTreeSet::from(["android.intent.action.BOOT_COMPLETED".to_string()])
+        );
+    }
+
+    #[test]
+    fn dynamic_receiver_reused_register() {
+        let mut builder = DexBuilder::new();
+        let class = builder.class("LTest;", "Ljava/lang/Object;");
+        let filter = builder.type_("Landroid/content/IntentFilter;") as u16;
+        let init = builder.method("Landroid/content/IntentFilter;", "<init>", "V", &[]) as u16;
+        let init_with = builder.method(
+            "Landroid/content/IntentFilter;",
+            "<init>",
+            "V",
+            &["Ljava/lang/String;"],
+        ) as u16;
+        let add_action = builder.method(
+            "Landroid/content/IntentFilter;",
+            "addAction",
+            "V",
+            &["Ljava/lang/String;"],
+        ) as u16;
+        let register = builder.method(
+            "Landroid/content/Context;",
+            "registerReceiver",
+            "Landroid/content/Intent;",
+            &[
+                "Landroid/content/BroadcastReceiver;",
+                "Landroid/content/IntentFilter;",
+            ],
+        ) as u16;
+        let first = builder.string("com.example.FIRST") as u16;
+        let second = builder.string("com.example.SECOND") as u16;
+        let method = builder.method("LTest;", "run", "V", &["Landroid/content/Context;"]);
+        builder.code(
+            class,
+            method,
+            ACC_PUBLIC | ACC_STATIC,
+            4,
+            1,
+            3,
+            &[
+                0x0022, filter, // 0: new-instance v0, IntentFilter
+                0x011a, first, // 2: const-string v1, "com.example.FIRST"
+                0x2070, init_with, 0x0010, // 4: invoke-direct {v0, v1}, <init>(String)
+                0x0022, filter, // 7: new-instance v0, IntentFilter
+                0x1070, init, 0x0000, // 9: invoke-direct {v0}, IntentFilter.<init>()
+                0x011a, second, // 12: const-string v1, "com.example.SECOND"
+                0x206e, add_action, 0x0010, // 14: invoke-virtual {v0, v1}, addAction
+                0x0212, // 17: const/4 v2, 0
+                0x306e, register, 0x0023, // 18: invoke-virtual {v3, v2, v0}, registerReceiver
+                0x000e, // 21: return-void
+            ],
+        );
+        let dex = builder.build_dex();
+        let repo = testing::repo(&dex);
+
+        // the first filter is unrelated to the registered one
+        let cg = repo.build_callgraph().unwrap();
+        let sites = cg.dynamic_receivers(&repo, &BTreeMap::new()).unwrap();
+        assert_eq!(sites.len(), 1);
+        assert_eq!(sites[0].addr, Addr(18));
+        assert_eq!(
+            sites[0].actions,
+            BTreeSet::from(["com.example.SECOND".to_string()])
+        );
+    }
+
+    #[test]
+    fn webview_javascript_interface() {
+        let mut builder = DexBuilder::new();
+        let class = builder.class("LTest;", "Ljava/lang/Object;");
+        let get_settings = builder.method(
+            "Landroid/webkit/WebView;",
+            "getSettings",
+            "Landroid/webkit/WebSettings;",
+            &[],
+        ) as u16;
+        let enable_js = builder.method(
+            "Landroid/webkit/WebSettings;",
+            "setJavaScriptEnabled",
+            "V",
+            &["Z"],
+        ) as u16;
+        let add_interface = builder.method(
+            "Landroid/webkit/WebView;",
+            "addJavascriptInterface",
+            "V",
+            &["Ljava/lang/Object;", "Ljava/lang/String;"],
+        ) as u16;
+        let name = builder.string("bridge") as u16;
+        let method = builder.method("LTest;", "run", "V", &["Landroid/webkit/WebView;"]);
+        builder.code(
+            class,
+            method,
+            ACC_PUBLIC | ACC_STATIC,
+            4,
+            1,
+            3,
+            &[
+                0x106e,
+                get_settings,
+                0x0003, // 0: invoke-virtual {v3}, getSettings
+                0x000c, // 3: move-result-object v0
+                0x1112, // 4: const/4 v1, 1
+                0x206e,
+                enable_js,
+                0x0010, // 5: invoke-virtual {v0, v1}, setJavaScriptEnabled
+                0x011a,
+                name, // 8: const-string v1, "bridge"
+                0x306e,
+                add_interface,
+                0x0113, // 10: invoke-virtual {v3, v3, v1}, addJavascriptInterface
+                0x000e, // 13: return-void
+            ],
+        );
+        let dex = builder.build_dex();
+        let repo = testing::repo(&dex);
+
+        let cg = repo.build_callgraph().unwrap();
+        let findings = cg.webview_findings(&repo, &BTreeMap::new()).unwrap();
+        assert_eq!(findings.len(), 1);
+        assert_eq!(findings[0].risk, WebViewRisk::JavaScriptInterface);
+        assert_eq!(repo[findings[0].method].name(), "run");
+        let calls: Vec<(Addr, WebViewApi)> =
+            findings[0].calls.iter().map(|c| (c.addr, c.api)).collect();
+        assert_eq!(
+            calls,
+            vec![
+                (Addr(5), WebViewApi::SetJavaScriptEnabled),
+                (Addr(10), WebViewApi::AddJavascriptInterface),
+            ]
+        );
+    }
+
+    #[test]
+    fn retain_zombies() {
+        let mut builder = DexBuilder::new();
+        let class = builder.class("LTest;", "Ljava/lang/Object;");
+        let missing = builder.method("LMissing;", "run", "V", &[]) as u16;
+        let dead = builder.method("LTest;", "dead", "V", &[]);
+        let alive = builder.method("LTest;", "alive", "V", &[]);
+        builder.code(
+            class,
+            dead,
+            ACC_PUBLIC | ACC_STATIC,
+            0,
+            0,
+            0,
+            &[
+                0x0071, missing, 0x0000, // 0: invoke-static {}, LMissing;->run()V
+                0x000e, // 3: return-void
+            ],
+        );
+        builder.code(
+            class,
+            alive,
+            ACC_PUBLIC | ACC_STATIC,
+            0,
+            0,
+            0,
+            &[0x000e], // 0: return-void
+        );
+        let dex = builder.build_dex();
+        let repo = testing::repo(&dex);
+
+        let cg = repo.build_callgraph().unwrap();
+        let names = |cg: &CallGraph| {
+            let mut names: Vec<_> = cg
+                .inner
+                .node_weights()
+                .map(|m| m.name().to_string())
+                .collect();
+            names.sort();
+            names
+        };
+        assert_eq!(names(&cg.retain(|m| m.is_zombie())), ["dead"]);
+        assert_eq!(names(&cg.retain(|m| m.is_reachable())), ["alive"]);
+    }
+
+    #[test]
+    fn reflective_call_revives_zombie() {
+        let mut builder = DexBuilder::new();
+        let class = builder.class("LTest;", "Ljava/lang/Object;");
+        let main = builder.method("LTest;", "main", "V", &[]);
+        let hidden = builder.method("LTest;", "hidden", "V", &[]);
+        for method in [main, hidden] {
+            builder.code(
+                class,
+                method,
+                ACC_PUBLIC | ACC_STATIC,
+                0,
+                0,
+                0,
+                &[0x000e], // 0: return-void
+            );
+        }
+        let dex = builder.build_dex();
+        let repo = testing::repo(&dex);
+        let uid = |name: &str| testing::method(&repo, name).1.uid();
+
+        let mut cg = repo.build_callgraph().unwrap();
+        assert_eq!(cg.nb_zombie_methods(), 0);
+        cg.recompute_reachability(&[uid("main")]).unwrap();
+        assert_eq!(cg.nb_zombie_methods(), 1);
+        cg.recompute_reachability(&[uid("main")]).unwrap();
+        assert_eq!(cg.nb_zombie_methods(), 1);
+
+        // main invokes hidden through reflection
+        cg.add_call(uid("main"), uid("hidden")).unwrap();
+        cg.recompute_reachability(&[uid("main")]).unwrap();
+        assert_eq!(cg.nb_zombie_methods(), 0);
+        cg.recompute_reachability(&[]).unwrap();
+        assert_eq!(cg.nb_zombie_methods(), 0);
+    }
+
+    #[test]
+    fn static_call_edge() {
+        let mut builder = DexBuilder::new();
+        let class = builder.class("LTest;", "Ljava/lang/Object;");
+        let main = builder.method("LTest;", "main", "V", &[]);
+        let callee = builder.method("LTest;", "callee", "V", &[]);
+        let callee_ref = callee as u16;
+        let missing = builder.method("LMissing;", "run", "V", &[]) as u16;
+        builder.code(
+            class,
+            main,
+            ACC_PUBLIC | ACC_STATIC,
+            1,
+            0,
+            1,
+            &[
+                0x0012, // 0: const/4 v0, 0
+                0x0071, callee_ref, 0x0000, // 1: invoke-static {}, Test.callee()
+                0x106e, missing, 0x0000, // 4: invoke-virtual {v0}, Missing.run()
+                0x000e, // 7: return-void
+            ],
+        );
+        builder.code(
+            class,
+            callee,
+            ACC_PUBLIC | ACC_STATIC,
+            0,
+            0,
+            0,
+            &[0x000e], // 0: return-void
+        );
+        let dex = builder.build_dex();
+        let repo = testing::repo(&dex);
+        let uid = |name: &str| testing::method(&repo, name).1.uid();
+
+        let cg = repo.build_callgraph().unwrap();
+        let mut edges: Vec<_> = cg.edges_from(uid("main")).collect();
+        edges.sort_by_key(|(_, edge)| edge.site);
+        assert_eq!(
+            edges,
+            [
+                (
+                    Some(uid("callee")),
+                    &CallEdge {
+                        kind: CallKind::Static,
+                        site: Addr(1),
+                        resolved: true,
+                    }
+                ),
+                (
+                    None,
+                    &CallEdge {
+                        kind: CallKind::Virtual,
+                        site: Addr(4),
+                        resolved: false,
+                    }
+                ),
+            ]
+        );
+        assert_eq!(cg.edges_from(uid("callee")).count(), 0);
+        assert!(cg.to_dot().contains("color=orange"));
+    }
+
+    #[test]
+    fn mixed_call_kinds_edge() {
+        let mut builder = DexBuilder::new();
+        let class = builder.class("LTest;", "Ljava/lang/Object;");
+        let main = builder.method("LTest;", "main", "V", &["LTest;"]);
+        let callee = builder.method("LTest;", "callee", "V", &[]);
+        let callee_ref = callee as u16;
+        builder.code(
+            class,
+            main,
+            ACC_PUBLIC | ACC_STATIC,
+            1,
+            1,
+            1,
+            &[
+                0x1070, callee_ref, 0x0000, // 0: invoke-direct {v0}, Test.callee()
+                0x106e, callee_ref, 0x0000, // 3: invoke-virtual {v0}, Test.callee()
+                0x000e, // 6: return-void
+            ],
+        );
+        builder.code(class, callee, ACC_PUBLIC, 1, 1, 0, &[0x000e]);
+        let dex = builder.build_dex();
+        let repo = testing::repo(&dex);
+
+        let cg = repo.build_callgraph().unwrap();
+        let dot = cg.to_dot();
+        assert!(dot.contains("color=gray"));
+        assert!(!dot.contains("color=blue"));
+    }
+
+    #[test]
+    fn recursive_sccs() {
+        let mut builder = DexBuilder::new();
+        let class = builder.class("LTest;", "Ljava/lang/Object;");
+        let even = builder.method("LTest;", "even", "V", &[]);
+        let odd = builder.method("LTest;", "odd", "V", &[]);
+        let leaf = builder.method("LTest;", "leaf", "V", &[]);
+        let (even_ref, odd_ref, leaf_ref) = (even as u16, odd as u16, leaf as u16);
+        builder.code(
+            class,
+            even,
+            ACC_PUBLIC | ACC_STATIC,
+            0,
+            0,
+            0,
+            &[
+                0x0071, odd_ref, 0x0000, // 0: invoke-static {}, Test.odd()
+                0x000e, // 3: return-void
+            ],
+        );
+        builder.code(
+            class,
+            odd,
+            ACC_PUBLIC | ACC_STATIC,
+            0,
+            0,
+            0,
+            &[
+                0x0071, even_ref, 0x0000, // 0: invoke-static {}, Test.even()
+                0x0071, leaf_ref, 0x0000, // 3: invoke-static {}, Test.leaf()
+                0x000e, // 6: return-void
+            ],
+        );
+        builder.code(
+            class,
+            leaf,
+            ACC_PUBLIC | ACC_STATIC,
+            0,
+            0,
+            0,
+            &[0x000e], // 0: return-void
+        );
+        let dex = builder.build_dex();
+        let repo = testing::repo(&dex);
+        let uid = |name: &str| testing::method(&repo, name).1.uid();
+
+        let cg = repo.build_callgraph().unwrap();
+        let mut sccs = cg.sccs();
+        for scc in &mut sccs {
+            scc.sort();
+        }
+        let mut cycle = vec![uid("even"), uid("odd")];
+        cycle.sort();
+        assert_eq!(sccs, vec![vec![uid("leaf")], cycle.clone()]);
+
+        let dag = cg.condensation();
+        assert_eq!(dag.node_count(), 2);
+        assert_eq!(dag.edge_count(), 1);
+        let edge = dag.edge_references().next().unwrap();
+        let mut caller = dag[edge.source()].clone();
+        caller.sort();
+        assert_eq!(caller, cycle);
+        assert_eq!(dag[edge.target()], vec![uid("leaf")]);
+    }
+
+    #[test]
+    fn callers_callees_and_paths() {
+        let mut builder = DexBuilder::new();
+        let class = builder.class("LTest;", "Ljava/lang/Object;");
+        let main = builder.method("LTest;", "main", "V", &[]);
+        let a = builder.method("LTest;", "a", "V", &[]);
+        let b = builder.method("LTest;", "b", "V", &[]);
+        let sink = builder.method("LTest;", "sink", "V", &[]);
+        let unused = builder.method("LTest;", "unused", "V", &[]);
+        let (a_ref, b_ref, sink_ref) = (a as u16, b as u16, sink as u16);
+        builder.code(
+            class,
+            main,
+            ACC_PUBLIC | ACC_STATIC,
+            0,
+            0,
+            0,
+            &[
+                0x0071, a_ref, 0x0000, // 0: invoke-static {}, Test.a()
+                0x0071, b_ref, 0x0000, // 3: invoke-static {}, Test.b()
+                0x000e, // 6: return-void
+            ],
+        );
+        builder.code(
+            class,
+            a,
+            ACC_PUBLIC | ACC_STATIC,
+            0,
+            0,
+            0,
+            &[
+                0x0071, b_ref, 0x0000, // 0: invoke-static {}, Test.b()
+                0x000e, // 3: return-void
+            ],
+        );
+        builder.code(
+            class,
+            b,
+            ACC_PUBLIC | ACC_STATIC,
+            0,
+            0,
+            0,
+            &[
+                0x0071, sink_ref, 0x0000, // 0: invoke-static {}, Test.sink()
+                0x000e, // 3: return-void
+            ],
+        );
+        for method in [sink, unused] {
+            builder.code(
+                class,
+                method,
+                ACC_PUBLIC | ACC_STATIC,
+                0,
+                0,
+                0,
+                &[0x000e], // 0: return-void
+            );
+        }
+        let dex = builder.build_dex();
+        let repo = testing::repo(&dex);
+        let uid = |name: &str| testing::method(&repo, name).1.uid();
+
+        let cg = repo.build_callgraph().unwrap();
+        let callers: BTreeSet<_> = cg.callers(uid("b")).collect();
+        assert_eq!(callers, BTreeSet::from([uid("main"), uid("a")]));
+        let callees: BTreeSet<_> = cg.callees(uid("main")).collect();
+        assert_eq!(callees, BTreeSet::from([uid("a"), uid("b")]));
+        assert_eq!(cg.callers(uid("main")).count(), 0);
+
+        assert_eq!(
+            cg.reachable_from(&[uid("a")]),
+            BTreeSet::from([uid("a"), uid("b"), uid("sink")])
+        );
+        assert_eq!(
+            cg.shortest_path(uid("main"), uid("sink")),
+            Some(vec![uid("main"), uid("b"), uid("sink")])
+        );
+        assert_eq!(cg.shortest_path(uid("sink"), uid("main")), None);
+        assert_eq!(cg.shortest_path(uid("main"), uid("unused")), None);
+    }
+}
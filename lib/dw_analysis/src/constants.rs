@@ -0,0 +1,680 @@
+//! Constant values tracking pass.
+//!
+//! This forward dataflow pass keeps, for each register, the constant
+//! value it holds (if any), as loaded by `const*` instructions and
+//! propagated through `move*` instructions. The content of string
+//! builders is followed along `append` chains, so that strings built by
+//! concatenation of constants are known as well, and `Class.forName`
+//! calls with a constant name give back a class literal. Similarly, the
+//! constant actions added to intent filters are collected. Any other
+//! definition of a register makes it non-constant.
+
+use crate::controlflow::Branch;
+use crate::dataflow;
+use crate::dataflow::{AbstractForwardState, Dataflow};
+use crate::errors::{AnalysisError, AnalysisResult};
+use crate::repo::{Class, Method, MethodDefiner, MethodDescr};
+use dw_dex::instrs::Instr;
+use dw_dex::methods::MethodIdItem;
+use dw_dex::registers::Reg;
+use dw_dex::types::Type;
+use dw_dex::{Addr, Dex, DexIndex, Index};
+use std::collections::{BTreeMap, BTreeSet};
+use std::fmt;
+
+/// Result of the constants pass.
+///
+/// Contains constant values of registers at entries and exits of every
+/// instruction of the analyzed method.
+pub type Constants = Dataflow<State>;
+
+impl Constants {
+    /// Runs the constants tracking pass onto given method and returns
+    /// results of the dataflow analysis.
+    ///
+    /// # Errors
+    ///
+    /// This function may generate errors if the method has no code or
+    /// if a constant operand cannot be resolved in its dex file.
+    pub fn compute(method: &Method, class: &Class) -> AnalysisResult<Self> {
+        dataflow::forward(method, class, &())
+    }
+
+    /// Returns the constant values of the argument registers (including
+    /// the receiver of non-static calls) of every reachable `invoke*`
+    /// instruction of the method, keyed by address.
+    ///
+    /// # Errors
+    ///
+    /// This function may return an error if the method has no code or is
+    /// not the one this pass has been run onto.
+    pub fn invoke_arguments(
+        &self,
+        method: &Method,
+    ) -> AnalysisResult<BTreeMap<Addr, Vec<Option<Value>>>> {
+        let code = method.code().ok_or(AnalysisError::NoCode)?;
+        let mut arguments = BTreeMap::new();
+        for instr in code.read().unwrap().iter_instructions() {
+            let Some((args, _)) = invoke_operands(instr.instr()) else {
+                continue;
+            };
+            // unreachable call sites have no computed state
+            let Some(state) = self.in_state(instr.addr()) else {
+                continue;
+            };
+            let values = args
+                .into_iter()
+                .map(|r| state.read_reg(r).map(Option::<&Value>::cloned))
+                .collect::<AnalysisResult<_>>()?;
+            arguments.insert(instr.addr(), values);
+        }
+        Ok(arguments)
+    }
+}
+
+/// A constant value held by a register.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum Value {
+    /// A 32 bits literal (either an integer or the bits of a float).
+    Int(i32),
+    /// A 64 bits literal (either a long or the bits of a double).
+    Wide(i64),
+    /// A string literal.
+    String(String),
+    /// A class literal.
+    Type(Type),
+    /// A `StringBuilder` (or `StringBuffer`) with a known content.
+    Builder(String),
+    /// An `IntentFilter` with the constant actions added to it. Actions
+    /// that are not constant are left out.
+    IntentFilter(BTreeSet<String>),
+}
+
+impl fmt::Display for Value {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            Self::Int(i) => write!(f, "{i}"),
+            Self::Wide(w) => write!(f, "{w}L"),
+            Self::String(s) => write!(f, "{s:?}"),
+            Self::Type(t) => write!(f, "{t}.class"),
+            Self::Builder(s) => write!(f, "builder({s:?})"),
+            Self::IntentFilter(actions) => write!(f, "filter({actions:?})"),
+        }
+    }
+}
+
+/// The abstract state for the constants pass.
+///
+/// A register set to `None` holds a value that is not known to be constant.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct State {
+    registers: Vec<Option<Value>>,
+    // value returned by the previous instruction, for `move-result*`
+    result: Option<Value>,
+}
+
+impl fmt::Display for State {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        for (i, value) in self.registers.iter().enumerate() {
+            match value {
+                Some(v) => write!(f, "    v{i}: {v}")?,
+                None => write!(f, "    v{i}: ?")?,
+            }
+        }
+        Ok(())
+    }
+}
+
+impl State {
+    /// Returns the constant value held by the rth register, if any.
+    ///
+    /// # Errors
+    ///
+    /// This function may return an out of bounds error.
+    pub fn read_reg(&self, r: Reg) -> AnalysisResult<Option<&Value>> {
+        self.registers
+            .get(r.value() as usize)
+            .map(Option::as_ref)
+            .ok_or_else(|| out_of_bounds(r))
+    }
+
+    fn write_reg(&mut self, r: Reg, value: Option<Value>) -> AnalysisResult<()> {
+        let idx = r.value() as usize;
+        if idx >= self.registers.len() {
+            return Err(out_of_bounds(r));
+        }
+        // overwriting the upper half of a wide value invalidates it
+        if idx > 0 && matches!(self.registers[idx - 1], Some(Value::Wide(_))) {
+            self.registers[idx - 1] = None;
+        }
+        self.registers[idx] = value;
+        Ok(())
+    }
+
+    fn write_pair(&mut self, r: Reg, value: Option<Value>) -> AnalysisResult<()> {
+        self.write_reg(r, value)?;
+        self.write_reg(r.next(), None)
+    }
+
+    fn copy_reg(&mut self, dst: Reg, src: Reg) -> AnalysisResult<()> {
+        let value = self.read_reg(src)?.cloned();
+        self.write_reg(dst, value)
+    }
+
+    fn copy_pair(&mut self, dst: Reg, src: Reg) -> AnalysisResult<()> {
+        let value = self.read_reg(src)?.cloned();
+        self.write_pair(dst, value)
+    }
+
+    fn builder(&self, r: Reg) -> AnalysisResult<Option<String>> {
+        match self.read_reg(r)? {
+            Some(Value::Builder(s)) => Ok(Some(s.clone())),
+            _ => Ok(None),
+        }
+    }
+
+    /// Sets the content of the builder referenced by `r`. Other registers
+    /// that may reference the same builder are made non-constant.
+    fn write_builder(&mut self, r: Reg, content: Option<String>) -> AnalysisResult<()> {
+        self.write_object(r, content.map(Value::Builder))
+    }
+
+    /// Sets the value of the mutable object (builder or intent filter)
+    /// referenced by `r`. Other registers that may reference the same
+    /// object are made non-constant.
+    fn write_object(&mut self, r: Reg, value: Option<Value>) -> AnalysisResult<()> {
+        if let Some(old @ (Value::Builder(_) | Value::IntentFilter(_))) = self.read_reg(r)?.cloned()
+        {
+            for value in &mut self.registers {
+                if value.as_ref() == Some(&old) {
+                    *value = None;
+                }
+            }
+        }
+        self.write_reg(r, value)
+    }
+
+    /// Computes the effect of a call to `descr` onto the string builders
+    /// and returns the constant value returned by the call, if any.
+    fn transfer_invoke(
+        &mut self,
+        args: &[Reg],
+        descr: &MethodDescr,
+    ) -> AnalysisResult<Option<Value>> {
+        let MethodDefiner::Class(class) = descr.definer() else {
+            return Ok(None);
+        };
+        let arg = |i: usize| {
+            args.get(i).copied().ok_or_else(|| {
+                AnalysisError::Internal(format!("missing argument register for {descr}"))
+            })
+        };
+
+        match (class.as_str(), descr.name()) {
+            ("java/lang/StringBuilder" | "java/lang/StringBuffer", "<init>") => {
+                let content = match descr.parameters_types().as_slice() {
+                    [] | [Type::Int] => Some(String::new()),
+                    [Type::Class(c)] if c == "java/lang/String" => match self.read_reg(arg(1)?)? {
+                        Some(Value::String(s)) => Some(s.clone()),
+                        _ => None,
+                    },
+                    _ => None,
+                };
+                self.write_builder(arg(0)?, content)?;
+                Ok(None)
+            }
+            ("java/lang/StringBuilder" | "java/lang/StringBuffer", "append") => {
+                let appended = match descr.parameters_types().as_slice() {
+                    [typ] => appended_string(typ, self.read_reg(arg(1)?)?),
+                    _ => None,
+                };
+                let content = match (self.builder(arg(0)?)?, appended) {
+                    (Some(content), Some(appended)) => Some(content + &appended),
+                    _ => None,
+                };
+                self.write_builder(arg(0)?, content.clone())?;
+                Ok(content.map(Value::Builder))
+            }
+            ("java/lang/StringBuilder" | "java/lang/StringBuffer", "toString") => {
+                Ok(self.builder(arg(0)?)?.map(Value::String))
+            }
+            ("android/content/IntentFilter", "<init>") => {
+                let mut actions = BTreeSet::new();
+                let known = match descr.parameters_types().as_slice() {
+                    [] => true,
+                    [Type::Class(c), ..] if c == "java/lang/String" => {
+                        if let Some(Value::String(action)) = self.read_reg(arg(1)?)? {
+                            actions.insert(action.clone());
+                        }
+                        true
+                    }
+                    _ => false,
+                };
+                self.write_object(arg(0)?, known.then_some(Value::IntentFilter(actions)))?;
+                Ok(None)
+            }
+            ("android/content/IntentFilter", "addAction") => {
+                if let Some(Value::IntentFilter(actions)) = self.read_reg(arg(0)?)? {
+                    let mut actions = actions.clone();
+                    if let Some(Value::String(action)) = self.read_reg(arg(1)?)? {
+                        actions.insert(action.clone());
+                    }
+                    self.write_object(arg(0)?, Some(Value::IntentFilter(actions)))?;
+                }
+                Ok(None)
+            }
+            ("java/lang/Class", "forName") => match self.read_reg(arg(0)?)? {
+                Some(Value::String(name)) => {
+                    Ok(Some(Value::Type(Type::Class(name.replace('.', "/")))))
+                }
+                _ => Ok(None),
+            },
+            _ => {
+                // builders given to unknown methods may be modified
+                for r in args {
+                    if self.builder(*r)?.is_some() {
+                        self.write_builder(*r, None)?;
+                    }
+                }
+                Ok(None)
+            }
+        }
+    }
+}
+
+/// Returns the string appended to a builder by an `append` overload taking
+/// a parameter of type `typ`, given the value of that parameter.
+fn appended_string(typ: &Type, value: Option<&Value>) -> Option<String> {
+    match (typ, value?) {
+        (Type::Class(_), Value::String(s)) => Some(s.clone()),
+        (Type::Int | Type::Short | Type::Byte, Value::Int(i)) => Some(i.to_string()),
+        (Type::Char, Value::Int(c)) => char::from_u32(*c as u32).map(String::from),
+        (Type::Boolean, Value::Int(b)) => Some((*b != 0).to_string()),
+        (Type::Long, Value::Wide(w)) => Some(w.to_string()),
+        _ => None,
+    }
+}
+
+/// Returns the argument registers and the called method of an `invoke*`
+/// instruction.
+fn invoke_operands(instr: &Instr) -> Option<(Vec<Reg>, &Index<MethodIdItem>)> {
+    match instr {
+        Instr::InvokeVirtual(args, m)
+        | Instr::InvokeSuper(args, m)
+        | Instr::InvokeDirect(args, m)
+        | Instr::InvokeStatic(args, m)
+        | Instr::InvokeInterface(args, m) => Some((args.iter().collect(), m)),
+        Instr::InvokeVirtualRange(args, m)
+        | Instr::InvokeSuperRange(args, m)
+        | Instr::InvokeDirectRange(args, m)
+        | Instr::InvokeStaticRange(args, m)
+        | Instr::InvokeInterfaceRange(args, m) => Some((args.iter().collect(), m)),
+        _ => None,
+    }
+}
+
+fn out_of_bounds(r: Reg) -> AnalysisError {
+    AnalysisError::Internal(format!("register {r} out of bounds"))
+}
+
+impl<'a> AbstractForwardState<'a> for State {
+    type Context<'c> = ();
+    type Error = AnalysisError;
+
+    fn init(method: &Method, _class: &Class) -> AnalysisResult<Self> {
+        let code = method.code().ok_or(AnalysisError::NoCode)?;
+        let registers_size = code.read().unwrap().registers_size();
+        Ok(Self {
+            registers: vec![None; registers_size],
+            result: None,
+        })
+    }
+
+    fn join(&mut self, other: &Self, _ctx: &()) -> AnalysisResult<()> {
+        if self.registers.len() != other.registers.len() {
+            return Err(AnalysisError::Internal(
+                "cannot join states of different sizes".to_string(),
+            ));
+        }
+        for (r1, r2) in self.registers.iter_mut().zip(other.registers.iter()) {
+            if r1 != r2 {
+                *r1 = None;
+            }
+        }
+        if self.result != other.result {
+            self.result = None;
+        }
+        Ok(())
+    }
+
+    fn transfer_branch(&mut self, _branch: &Branch, _ctx: &()) -> AnalysisResult<()> {
+        Ok(())
+    }
+
+    fn transfer_instr(&mut self, instr: &Instr, dex: &Dex, _ctx: &()) -> AnalysisResult<()> {
+        let result = self.result.take();
+        if let Some((args, m)) = invoke_operands(instr) {
+            // methods of invalid definers are not tracked
+            if let Ok(descr) = MethodDescr::try_from((dex, m.get(dex)?)) {
+                self.result = self.transfer_invoke(&args, &descr)?;
+            }
+            return Ok(());
+        }
+
+        match instr {
+            Instr::MoveResultObject(a) => self.write_reg(*a, result),
+
+            Instr::Move(a, b)
+            | Instr::MoveFrom16(a, b)
+            | Instr::Move16(a, b)
+            | Instr::MoveObject(a, b)
+            | Instr::MoveObjectFrom16(a, b)
+            | Instr::MoveObject16(a, b) => self.copy_reg(*a, *b),
+
+            Instr::MoveWide(a, b) | Instr::MoveWideFrom16(a, b) | Instr::MoveWide16(a, b) => {
+                self.copy_pair(*a, *b)
+            }
+
+            Instr::Const4(a, v) => self.write_reg(*a, Some(Value::Int(i32::from(*v)))),
+            Instr::Const16(a, v) => self.write_reg(*a, Some(Value::Int(i32::from(*v)))),
+            Instr::Const(a, v) => self.write_reg(*a, Some(Value::Int(*v))),
+            Instr::ConstHigh16(a, v) => self.write_reg(*a, Some(Value::Int(i32::from(*v) << 16))),
+            Instr::ConstWide16(a, v) => self.write_pair(*a, Some(Value::Wide(i64::from(*v)))),
+            Instr::ConstWide32(a, v) => self.write_pair(*a, Some(Value::Wide(i64::from(*v)))),
+            Instr::ConstWide(a, v) => self.write_pair(*a, Some(Value::Wide(*v))),
+            Instr::ConstWideHigh16(a, v) => {
+                self.write_pair(*a, Some(Value::Wide(i64::from(*v) << 48)))
+            }
+            Instr::ConstString(a, s) | Instr::ConstStringJumbo(a, s) => {
+                let s = s.get(dex)?.to_string(dex)?;
+                self.write_reg(*a, Some(Value::String(s)))
+            }
+            Instr::ConstClass(a, t) => {
+                let t = t.get(dex)?.to_type(dex)?;
+                self.write_reg(*a, Some(Value::Type(t)))
+            }
+
+            Instr::MoveResult(a)
+            | Instr::MoveException(a)
+            | Instr::ConstMethodHandle(a, _)
+            | Instr::ConstMethodType(a, _)
+            | Instr::InstanceOf(a, _, _)
+            | Instr::ArrayLength(a, _)
+            | Instr::NewInstance(a, _)
+            | Instr::NewArray(a, _, _)
+            | Instr::CmplFloat(a, _, _)
+            | Instr::CmpgFloat(a, _, _)
+            | Instr::CmplDouble(a, _, _)
+            | Instr::CmpgDouble(a, _, _)
+            | Instr::CmpLong(a, _, _)
+            | Instr::Aget(a, _, _)
+            | Instr::AgetObject(a, _, _)
+            | Instr::AgetBoolean(a, _, _)
+            | Instr::AgetByte(a, _, _)
+            | Instr::AgetChar(a, _, _)
+            | Instr::AgetShort(a, _, _)
+            | Instr::Iget(a, _, _)
+            | Instr::IgetObject(a, _, _)
+            | Instr::IgetBoolean(a, _, _)
+            | Instr::IgetByte(a, _, _)
+            | Instr::IgetChar(a, _, _)
+            | Instr::IgetShort(a, _, _)
+            | Instr::Sget(a, _)
+            | Instr::SgetObject(a, _)
+            | Instr::SgetBoolean(a, _)
+            | Instr::SgetByte(a, _)
+            | Instr::SgetChar(a, _)
+            | Instr::SgetShort(a, _)
+            | Instr::NegInt(a, _)
+            | Instr::NotInt(a, _)
+            | Instr::NegFloat(a, _)
+            | Instr::IntToFloat(a, _)
+            | Instr::LongToInt(a, _)
+            | Instr::LongToFloat(a, _)
+            | Instr::FloatToInt(a, _)
+            | Instr::DoubleToInt(a, _)
+            | Instr::DoubleToFloat(a, _)
+            | Instr::IntToByte(a, _)
+            | Instr::IntToChar(a, _)
+            | Instr::IntToShort(a, _)
+            | Instr::AddInt(a, _, _)
+            | Instr::SubInt(a, _, _)
+            | Instr::MulInt(a, _, _)
+            | Instr::DivInt(a, _, _)
+            | Instr::RemInt(a, _, _)
+            | Instr::AndInt(a, _, _)
+            | Instr::OrInt(a, _, _)
+            | Instr::XorInt(a, _, _)
+            | Instr::ShlInt(a, _, _)
+            | Instr::ShrInt(a, _, _)
+            | Instr::UshrInt(a, _, _)
+            | Instr::AddFloat(a, _, _)
+            | Instr::SubFloat(a, _, _)
+            | Instr::MulFloat(a, _, _)
+            | Instr::DivFloat(a, _, _)
+            | Instr::RemFloat(a, _, _)
+            | Instr::AddInt2addr(a, _)
+            | Instr::SubInt2addr(a, _)
+            | Instr::MulInt2addr(a, _)
+            | Instr::DivInt2addr(a, _)
+            | Instr::RemInt2addr(a, _)
+            | Instr::AndInt2addr(a, _)
+            | Instr::OrInt2addr(a, _)
+            | Instr::XorInt2addr(a, _)
+            | Instr::ShlInt2addr(a, _)
+            | Instr::ShrInt2addr(a, _)
+            | Instr::UshrInt2addr(a, _)
+            | Instr::AddFloat2addr(a, _)
+            | Instr::SubFloat2addr(a, _)
+            | Instr::MulFloat2addr(a, _)
+            | Instr::DivFloat2addr(a, _)
+            | Instr::RemFloat2addr(a, _)
+            | Instr::AddIntLit16(a, _, _)
+            | Instr::RsubInt(a, _, _)
+            | Instr::MulIntLit16(a, _, _)
+            | Instr::DivIntLit16(a, _, _)
+            | Instr::RemIntLit16(a, _, _)
+            | Instr::AndIntLit16(a, _, _)
+            | Instr::OrIntLit16(a, _, _)
+            | Instr::XorIntLit16(a, _, _)
+            | Instr::AddIntLit8(a, _, _)
+            | Instr::RsubIntLit8(a, _, _)
+            | Instr::MulIntLit8(a, _, _)
+            | Instr::DivIntLit8(a, _, _)
+            | Instr::RemIntLit8(a, _, _)
+            | Instr::AndIntLit8(a, _, _)
+            | Instr::OrIntLit8(a, _, _)
+            | Instr::XorIntLit8(a, _, _)
+            | Instr::ShlIntLit8(a, _, _)
+            | Instr::ShrIntLit8(a, _, _)
+            | Instr::UshrIntLit8(a, _, _) => self.write_reg(*a, None),
+
+            Instr::MoveResultWide(a)
+            | Instr::AgetWide(a, _, _)
+            | Instr::IgetWide(a, _, _)
+            | Instr::SgetWide(a, _)
+            | Instr::NegLong(a, _)
+            | Instr::NotLong(a, _)
+            | Instr::NegDouble(a, _)
+            | Instr::IntToLong(a, _)
+            | Instr::IntToDouble(a, _)
+            | Instr::LongToDouble(a, _)
+            | Instr::FloatToLong(a, _)
+            | Instr::FloatToDouble(a, _)
+            | Instr::DoubleToLong(a, _)
+            | Instr::AddLong(a, _, _)
+            | Instr::SubLong(a, _, _)
+            | Instr::MulLong(a, _, _)
+            | Instr::DivLong(a, _, _)
+            | Instr::RemLong(a, _, _)
+            | Instr::AndLong(a, _, _)
+            | Instr::OrLong(a, _, _)
+            | Instr::XorLong(a, _, _)
+            | Instr::ShlLong(a, _, _)
+            | Instr::ShrLong(a, _, _)
+            | Instr::UshrLong(a, _, _)
+            | Instr::AddDouble(a, _, _)
+            | Instr::SubDouble(a, _, _)
+            | Instr::MulDouble(a, _, _)
+            | Instr::DivDouble(a, _, _)
+            | Instr::RemDouble(a, _, _)
+            | Instr::AddLong2addr(a, _)
+            | Instr::SubLong2addr(a, _)
+            | Instr::MulLong2addr(a, _)
+            | Instr::DivLong2addr(a, _)
+            | Instr::RemLong2addr(a, _)
+            | Instr::AndLong2addr(a, _)
+            | Instr::OrLong2addr(a, _)
+            | Instr::XorLong2addr(a, _)
+            | Instr::ShlLong2addr(a, _)
+            | Instr::ShrLong2addr(a, _)
+            | Instr::UshrLong2addr(a, _)
+            | Instr::AddDouble2addr(a, _)
+            | Instr::SubDouble2addr(a, _)
+            | Instr::MulDouble2addr(a, _)
+            | Instr::DivDouble2addr(a, _)
+            | Instr::RemDouble2addr(a, _) => self.write_pair(*a, None),
+
+            // other instructions do not define any register
+            _ => Ok(()),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::testing::{self, DexBuilder, ACC_PUBLIC, ACC_STATIC};
+
+    /// Builds a `Test.run()` static method with the given code, and returns
+    /// the constant values of the invoke arguments along with the constants
+    /// pass result.
+    fn invoke_arguments(
+        mut builder: DexBuilder,
+        registers: u16,
+        insns: &[u16],
+    ) -> (BTreeMap<Addr, Vec<Option<Value>>>, Constants) {
+        let class = builder.class("LTest;", "Ljava/lang/Object;");
+        let run = builder.method("LTest;", "run", "V", &[]);
+        builder.code(class, run, ACC_PUBLIC | ACC_STATIC, registers, 0, 2, insns);
+
+        let dex = builder.build_dex();
+        let repo = testing::repo(&dex);
+        let (class, method) = testing::method(&repo, "run");
+        let constants = crate::constants(method, class).unwrap();
+        (constants.invoke_arguments(method).unwrap(), constants)
+    }
+
+    fn string(s: &str) -> Option<Value> {
+        Some(Value::String(s.to_string()))
+    }
+
+    #[test]
+    fn class_for_name_constant() {
+        let mut builder = DexBuilder::new();
+        let name = builder.string("com.x.Y") as u16;
+        let for_name = builder.method(
+            "Ljava/lang/Class;",
+            "forName",
+            "Ljava/lang/Class;",
+            &["Ljava/lang/String;"],
+        ) as u16;
+
+        // 0: const-string v0, "com.x.Y"
+        // 2: invoke-static {v0}, Class.forName(String)
+        // 5: move-result-object v1
+        // 6: return-void
+        let (arguments, constants) = invoke_arguments(
+            builder,
+            2,
+            &[0x001a, name, 0x1071, for_name, 0x0000, 0x010c, 0x000e],
+        );
+        assert_eq!(arguments[&Addr(2)], vec![string("com.x.Y")]);
+        assert_eq!(
+            constants
+                .in_state(Addr(6))
+                .unwrap()
+                .read_reg(Reg::from(1u8))
+                .unwrap(),
+            Some(&Value::Type(Type::Class("com/x/Y".to_string())))
+        );
+    }
+
+    #[test]
+    fn string_builder_chain() {
+        let mut builder = DexBuilder::new();
+        let string_builder = builder.type_("Ljava/lang/StringBuilder;") as u16;
+        let init = builder.method("Ljava/lang/StringBuilder;", "<init>", "V", &[]) as u16;
+        let append = builder.method(
+            "Ljava/lang/StringBuilder;",
+            "append",
+            "Ljava/lang/StringBuilder;",
+            &["Ljava/lang/String;"],
+        ) as u16;
+        let append_int = builder.method(
+            "Ljava/lang/StringBuilder;",
+            "append",
+            "Ljava/lang/StringBuilder;",
+            &["I"],
+        ) as u16;
+        let to_string = builder.method(
+            "Ljava/lang/StringBuilder;",
+            "toString",
+            "Ljava/lang/String;",
+            &[],
+        ) as u16;
+        let for_name = builder.method(
+            "Ljava/lang/Class;",
+            "forName",
+            "Ljava/lang/Class;",
+            &["Ljava/lang/String;"],
+        ) as u16;
+        let prefix = builder.string("com.x.Y") as u16;
+
+        // 0: new-instance v0, StringBuilder
+        // 2: invoke-direct {v0}, StringBuilder.<init>()
+        // 5: const-string v1, "com.x.Y"
+        // 7: invoke-virtual {v0, v1}, StringBuilder.append(String)
+        // 10: move-result-object v0
+        // 11: const/4 v1, 2
+        // 12: invoke-virtual {v0, v1}, StringBuilder.append(int)
+        // 15: invoke-virtual {v0}, StringBuilder.toString()
+        // 18: move-result-object v1
+        // 19: invoke-static {v1}, Class.forName(String)
+        // 22: return-void
+        let (arguments, _) = invoke_arguments(
+            builder,
+            3,
+            &[
+                0x0022,
+                string_builder,
+                0x1070,
+                init,
+                0x0000,
+                0x011a,
+                prefix,
+                0x206e,
+                append,
+                0x0010,
+                0x000c,
+                0x2112,
+                0x206e,
+                append_int,
+                0x0010,
+                0x106e,
+                to_string,
+                0x0000,
+                0x010c,
+                0x1071,
+                for_name,
+                0x0001,
+                0x000e,
+            ],
+        );
+        assert_eq!(arguments[&Addr(19)], vec![string("com.x.Y2")]);
+        assert_eq!(
+            arguments[&Addr(15)],
+            vec![Some(Value::Builder("com.x.Y2".to_string()))]
+        );
+    }
+}
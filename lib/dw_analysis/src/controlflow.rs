@@ -7,9 +7,12 @@ use dw_dex::instrs::{Instr, Instruction, LabeledInstr};
 use dw_dex::registers::Reg;
 use dw_dex::types::Type;
 use dw_dex::{Addr, Dex, DexIndex, PrettyPrint};
+use petgraph::algo::dominators::{self, Dominators};
+use petgraph::algo::tarjan_scc;
 use petgraph::dot::{Config, Dot};
 use petgraph::graph::{DiGraph, NodeIndex};
-use petgraph::visit::NodeRef;
+use petgraph::visit::{depth_first_search, DfsEvent, EdgeRef, NodeRef};
+use petgraph::Direction;
 use std::collections::{BTreeMap, BTreeSet};
 use std::fmt;
 use std::fmt::Write;
@@ -38,7 +41,7 @@ impl<'a> fmt::Display for Block<'a> {
 
 impl<'a> Block<'a> {
     fn new(instrs: Vec<LabeledInstr>, dex: &'a Dex) -> Self {
-        let can_throw = instruction_can_throw(&instrs[0]);
+        let can_throw = instruction_can_throw(instrs[0].instr());
         Self {
             dex,
             instrs,
@@ -83,7 +86,7 @@ pub enum Operand {
     Zero,
 }
 
-#[derive(Debug)]
+#[derive(Debug, Clone)]
 pub enum Branch {
     IfTrue(Reg, Comp, Operand),
     IfFalse(Reg, Comp, Operand),
@@ -118,10 +121,126 @@ impl fmt::Display for Branch {
     }
 }
 
+/// Options to select which exceptional edges (i.e. edges from throwing
+/// instructions to their catch handlers) are added when building a [`Cfg`].
+#[derive(Debug, Clone, Copy)]
+pub struct CfgOptions {
+    exceptional_edges: bool,
+    div_exceptional_edges: bool,
+    array_exceptional_edges: bool,
+    cast_exceptional_edges: bool,
+}
+
+/// Default values enable all exceptional edges.
+impl Default for CfgOptions {
+    fn default() -> Self {
+        Self {
+            exceptional_edges: true,
+            div_exceptional_edges: true,
+            array_exceptional_edges: true,
+            cast_exceptional_edges: true,
+        }
+    }
+}
+
+impl CfgOptions {
+    /// Do not add any exceptional edge.
+    #[must_use]
+    pub const fn no_exceptional_edges(self) -> Self {
+        Self {
+            exceptional_edges: false,
+            ..self
+        }
+    }
+
+    /// Do not add exceptional edges for division by zero.
+    #[must_use]
+    pub const fn no_div_exceptional_edges(self) -> Self {
+        Self {
+            div_exceptional_edges: false,
+            ..self
+        }
+    }
+
+    /// Do not add exceptional edges for array accesses.
+    #[must_use]
+    pub const fn no_array_exceptional_edges(self) -> Self {
+        Self {
+            array_exceptional_edges: false,
+            ..self
+        }
+    }
+
+    /// Do not add exceptional edges for failed casts.
+    #[must_use]
+    pub const fn no_cast_exceptional_edges(self) -> Self {
+        Self {
+            cast_exceptional_edges: false,
+            ..self
+        }
+    }
+
+    // Instructions classes are recognized from their success branch.
+    fn keeps_exceptional_edges(&self, branching: &[(Branch, Addr)]) -> bool {
+        self.exceptional_edges
+            && branching.iter().all(|(branch, _)| match branch {
+                Branch::DivSuccess => self.div_exceptional_edges,
+                Branch::ArrayAccessSuccess => self.array_exceptional_edges,
+                Branch::CastSuccess(_, _) => self.cast_exceptional_edges,
+                _ => true,
+            })
+    }
+}
+
+/// A loop of a control flow graph, blocks being identified by their start
+/// address.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Loop {
+    /// The block through which the loop is entered.
+    pub header: Addr,
+    /// The blocks of the loop, including its header.
+    pub body: BTreeSet<Addr>,
+    /// The blocks branching back to the header.
+    pub back_edges: BTreeSet<Addr>,
+    /// Whether the loop can be entered through several blocks, in which
+    /// case the header does not dominate the loop and the body is the whole
+    /// strongly connected region containing it.
+    pub irreducible: bool,
+}
+
+/// A try block of a method, as described by a dex `try_item` and its
+/// associated `encoded_catch_handler`.
+///
+/// Dex try items do not overlap: nested source-level try blocks are split
+/// into several regions, the inner ones listing the handlers of the outer
+/// blocks after their own.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct TryRegion {
+    /// The address of the first covered instruction.
+    pub start: Addr,
+    /// The address following the last covered instruction.
+    pub end: Addr,
+    /// The handlers in matching order, as (exception type, handler address)
+    /// pairs. The catch-all handler, if any, has no type and comes last.
+    pub handlers: Vec<(Option<Type>, Addr)>,
+}
+
+impl TryRegion {
+    /// Checks whether the instruction at `addr` is covered by the region.
+    #[must_use]
+    pub fn contains(&self, addr: Addr) -> bool {
+        self.start <= addr && addr < self.end
+    }
+}
+
+/// Successor blocks start addresses, with the branch leading to each of them.
+pub type Successors = Vec<(Addr, Branch)>;
+
 #[derive(Debug)]
 pub struct Cfg<'a> {
     pub(crate) inner: DiGraph<Block<'a>, Branch>,
     node_ids: BTreeMap<Addr, NodeIndex>,
+    tries: Vec<TryRegion>,
 }
 
 impl<'a> Cfg<'a> {
@@ -133,6 +252,147 @@ impl<'a> Cfg<'a> {
         self.node_ids.values().map(move |id| &self.inner[*id])
     }
 
+    /// Returns the predecessors of the block starting at `addr`, along
+    /// with the branch leading from each of them to that block.
+    ///
+    /// Nothing is returned if no block starts at `addr`.
+    pub fn predecessors(&self, addr: Addr) -> impl Iterator<Item = (&Block<'_>, &Branch)> {
+        self.node_ids
+            .get(&addr)
+            .into_iter()
+            .flat_map(move |id| self.inner.edges_directed(*id, Direction::Incoming))
+            .map(move |edge| (&self.inner[edge.source()], edge.weight()))
+    }
+
+    /// Returns the successors of the block starting at `addr`, split into
+    /// `(normal, exceptional)` ones and ordered by address. Exceptional
+    /// successors are the handlers reached through [`Branch::Catch`] and
+    /// [`Branch::CatchAll`] edges.
+    ///
+    /// Nothing is returned if no block starts at `addr`.
+    #[must_use]
+    pub fn successors(&self, addr: Addr) -> (Successors, Successors) {
+        let (mut exceptional, mut normal): (Vec<_>, Vec<_>) = self
+            .node_ids
+            .get(&addr)
+            .into_iter()
+            .flat_map(|id| self.inner.edges_directed(*id, Direction::Outgoing))
+            .map(|edge| {
+                (
+                    self.inner[edge.target()].start_addr(),
+                    edge.weight().clone(),
+                )
+            })
+            .partition(|(_, branch)| matches!(branch, Branch::Catch(_) | Branch::CatchAll));
+        normal.sort_by_key(|(addr, _)| *addr);
+        exceptional.sort_by_key(|(addr, _)| *addr);
+        (normal, exceptional)
+    }
+
+    /// Computes the dominators of the blocks reachable from the entry block.
+    #[must_use]
+    pub fn dominators(&self) -> Dominators<NodeIndex> {
+        dominators::simple_fast(&self.inner, self.start_index())
+    }
+
+    /// Returns the immediate dominator of every block reachable from the
+    /// entry block, except the entry block itself.
+    #[must_use]
+    pub fn immediate_dominators(&self) -> BTreeMap<Addr, Addr> {
+        let dominators = self.dominators();
+        self.node_ids
+            .iter()
+            .filter_map(|(addr, id)| {
+                dominators
+                    .immediate_dominator(*id)
+                    .map(|idom| (*addr, self.inner[idom].start_addr()))
+            })
+            .collect()
+    }
+
+    /// Returns the loops of the control flow graph, ordered by header
+    /// address.
+    ///
+    /// Loops are found from the edges going back to a block being visited
+    /// by a depth-first traversal. When the target of such an edge dominates
+    /// its source, it is the header of a natural loop whose body is made of
+    /// the blocks reaching the source without going through the header.
+    /// Otherwise the loop has several entries: it is flagged as irreducible
+    /// instead of being merged with another loop.
+    #[must_use]
+    pub fn natural_loops(&self) -> Vec<Loop> {
+        let dominators = self.dominators();
+        let dominates = |a: NodeIndex, b: NodeIndex| {
+            dominators
+                .dominators(b)
+                .is_some_and(|mut doms| doms.any(|d| d == a))
+        };
+
+        // back edge sources of each header, and whether the header dominates them all
+        let mut headers: BTreeMap<NodeIndex, (BTreeSet<NodeIndex>, bool)> = BTreeMap::new();
+        depth_first_search(&self.inner, Some(self.start_index()), |event| {
+            if let DfsEvent::BackEdge(src, header) = event {
+                let (sources, reducible) = headers
+                    .entry(header)
+                    .or_insert_with(|| (BTreeSet::new(), true));
+                sources.insert(src);
+                *reducible &= dominates(header, src);
+            }
+        });
+
+        let regions = if headers.values().all(|(_, reducible)| *reducible) {
+            Vec::new()
+        } else {
+            tarjan_scc(&self.inner)
+        };
+
+        let mut loops: Vec<Loop> = headers
+            .into_iter()
+            .map(|(header, (sources, reducible))| {
+                let body = if reducible {
+                    let mut body = BTreeSet::from([header]);
+                    let mut worklist: Vec<NodeIndex> = sources.iter().copied().collect();
+                    while let Some(id) = worklist.pop() {
+                        if body.insert(id) {
+                            worklist.extend(
+                                self.inner
+                                    .neighbors_directed(id, Direction::Incoming)
+                                    .filter(|pred| dominates(header, *pred)),
+                            );
+                        }
+                    }
+                    body
+                } else {
+                    regions
+                        .iter()
+                        .find(|region| region.contains(&header))
+                        .map(|region| region.iter().copied().collect())
+                        .unwrap_or_default()
+                };
+                Loop {
+                    header: self.inner[header].start_addr(),
+                    body: body
+                        .into_iter()
+                        .map(|id| self.inner[id].start_addr())
+                        .collect(),
+                    back_edges: sources
+                        .into_iter()
+                        .map(|id| self.inner[id].start_addr())
+                        .collect(),
+                    irreducible: !reducible,
+                }
+            })
+            .collect();
+        loops.sort_by_key(|l| l.header);
+        loops
+    }
+
+    /// Returns the try regions of the method, ordered by address.
+    #[must_use]
+    pub fn try_regions(&self) -> Vec<TryRegion> {
+        self.tries.clone()
+    }
+
     #[must_use]
     pub fn to_dot(&self) -> String {
         let mut res = String::new();
@@ -172,14 +432,108 @@ impl<'a> Cfg<'a> {
         res
     }
 
+    /// Returns an equivalent control flow graph in which linear chains of
+    /// blocks are merged into single blocks.
+    ///
+    /// Two blocks are merged when the first one has a single fallthrough
+    /// successor which has no other predecessor, and when both blocks
+    /// belong to the same try regions. This is intended to produce more
+    /// readable outputs, the result should not be used for dataflow
+    /// analyses as it breaks the leading position of throwing instructions.
+    #[must_use]
+    pub fn coalesce(&self) -> Self {
+        let mut cfgraph = DiGraph::new();
+        let mut blocks_map = BTreeMap::new();
+        let mut new_ids = BTreeMap::new();
+
+        // node_ids is ordered by address, so chain heads come first
+        for (addr, id) in &self.node_ids {
+            if new_ids.contains_key(id) {
+                continue;
+            }
+            let head = &self.inner[*id];
+            let mut chain = vec![*id];
+            let mut instrs = head.instrs.clone();
+            let mut current = *id;
+            while let Some(next) = self.fallthrough_successor(current) {
+                instrs.extend(self.inner[next].instrs.iter().cloned());
+                chain.push(next);
+                current = next;
+            }
+            let new_id = cfgraph.add_node(Block {
+                dex: head.dex,
+                instrs,
+                can_throw: head.can_throw,
+            });
+            for old_id in chain {
+                new_ids.insert(old_id, new_id);
+            }
+            blocks_map.insert(*addr, new_id);
+        }
+
+        for edge in self.inner.edge_references() {
+            if self.fallthrough_successor(edge.source()) == Some(edge.target()) {
+                continue;
+            }
+            cfgraph.add_edge(
+                new_ids[&edge.source()],
+                new_ids[&edge.target()],
+                edge.weight().clone(),
+            );
+        }
+
+        Self {
+            inner: cfgraph,
+            node_ids: blocks_map,
+            tries: self.tries.clone(),
+        }
+    }
+
+    // Returns the successor of the given block if they can be merged together.
+    fn fallthrough_successor(&self, id: NodeIndex) -> Option<NodeIndex> {
+        let mut edges = self.inner.edges_directed(id, Direction::Outgoing);
+        let edge = edges.next()?;
+        if edges.next().is_some() {
+            return None;
+        }
+        if !matches!(
+            edge.weight(),
+            Branch::Sequence
+                | Branch::ArrayAccessSuccess
+                | Branch::InvokeSuccess
+                | Branch::DivSuccess
+        ) {
+            return None;
+        }
+        let next = edge.target();
+        if next == id
+            || next == self.start_index()
+            || self.inner.edges_directed(next, Direction::Incoming).count() != 1
+        {
+            return None;
+        }
+        let (addr, next_addr) = (self.inner[id].start_addr(), self.inner[next].start_addr());
+        let same_tries = self
+            .tries
+            .iter()
+            .all(|try_| try_.contains(addr) == try_.contains(next_addr));
+        same_tries.then_some(next)
+    }
+
     pub fn build(method: &'a Method) -> AnalysisResult<Self> {
+        Self::build_with(method, CfgOptions::default())
+    }
+
+    /// Builds the control flow graph of a method, adding only the exceptional
+    /// edges selected by `options`.
+    pub fn build_with(method: &'a Method, options: CfgOptions) -> AnalysisResult<Self> {
         let dex = method.dex();
         let code = method.code().ok_or(AnalysisError::NoCode)?;
 
         let mut cfgraph = DiGraph::new();
         let mut blocks_map = BTreeMap::new();
 
-        let leaders = compute_block_leaders(&code.read().unwrap(), dex)?;
+        let leaders = compute_block_leaders(&code.read().unwrap())?;
         for block in split_into_blocks(&code.read().unwrap(), dex, leaders) {
             blocks_map.insert(block.start_addr(), cfgraph.add_node(block));
         }
@@ -201,10 +555,14 @@ impl<'a> Cfg<'a> {
         for (leader_addr, linstr) in breakers {
             let src_id = blocks_map[&leader_addr];
             let branching = instruction_branching(&code.read().unwrap(), dex, &linstr)?;
-            let block_tries = block_tries(&code.read().unwrap(), dex, &linstr)?;
+            let block_tries = if options.keeps_exceptional_edges(&branching) {
+                block_tries(&code.read().unwrap(), dex, &linstr)?
+            } else {
+                vec![]
+            };
             if branching.is_empty()
-                && !instruction_can_throw(&linstr)
-                && !instruction_does_return(&linstr)
+                && !instruction_can_throw(linstr.instr())
+                && !instruction_does_return(linstr.instr())
             {
                 if let Some(dst_id) = blocks_map.get(&linstr.next_addr()) {
                     cfgraph.add_edge(src_id, *dst_id, Branch::Sequence);
@@ -219,9 +577,12 @@ impl<'a> Cfg<'a> {
                 });
         }
 
+        let tries = try_regions(&code.read().unwrap(), dex)?;
+
         Ok(Self {
             inner: cfgraph,
             node_ids: blocks_map,
+            tries,
         })
     }
 }
@@ -233,21 +594,19 @@ impl<'a> Cfg<'a> {
 //   - throwable instruction is a leader (so that we can easily retrieve state before the
 //     instruction when running a dataflow analysis)
 //   - start of a catch block is a leader
-fn compute_block_leaders(code: &CodeItem, dex: &Dex) -> AnalysisResult<BTreeSet<Addr>> {
+fn compute_block_leaders(code: &CodeItem) -> AnalysisResult<BTreeSet<Addr>> {
     let mut leaders = BTreeSet::new();
 
-    // collect leaders caused by instruction branching
-    for linstr in code.iter_instructions() {
-        let branching = instruction_branching(code, dex, linstr)?;
-        if !branching.is_empty() || instruction_can_throw(linstr) || instruction_does_return(linstr)
-        {
-            leaders.insert(linstr.next_addr());
-        }
-        for (_, dst) in branching {
-            leaders.insert(dst);
+    // collect leaders caused by instruction branching, whose targets are
+    // resolved by the code item (edges are labelled by `instruction_branching`)
+    for (addr, instr, targets) in code.iter_instructions_with_targets() {
+        let can_throw = instruction_can_throw(instr);
+        if !targets.is_empty() || can_throw || instruction_does_return(instr) {
+            leaders.insert(addr.offset(instr.size() as i32));
         }
-        if instruction_can_throw(linstr) {
-            leaders.insert(linstr.addr());
+        leaders.extend(targets);
+        if can_throw {
+            leaders.insert(addr);
         }
     }
 
@@ -453,16 +812,16 @@ fn sparse_switch_payload_branching(
     }
 }
 
-fn instruction_does_return(linstr: &LabeledInstr) -> bool {
+fn instruction_does_return(instr: &Instr) -> bool {
     matches!(
-        linstr.instr(),
+        instr,
         Instr::ReturnVoid | Instr::Return(_) | Instr::ReturnWide(_) | Instr::ReturnObject(_)
     )
 }
 
-fn instruction_can_throw(linstr: &LabeledInstr) -> bool {
+fn instruction_can_throw(instr: &Instr) -> bool {
     matches!(
-        linstr.instr(),
+        instr,
         Instr::Throw(_)
             | Instr::ArrayLength(_, _)
             | Instr::FilledNewArray(_, _)
@@ -539,3 +898,305 @@ fn block_tries(
     }
     Ok(vec![])
 }
+
+fn try_regions(code: &CodeItem, dex: &Dex) -> AnalysisResult<Vec<TryRegion>> {
+    code.iter_tries()
+        .map(|try_| {
+            let catches = try_.handlers(code)?;
+            let mut handlers: Vec<(Option<Type>, Addr)> = catches
+                .iter_handlers()
+                .map(|h| Ok((Some(h.catch_type(dex)?), Addr(h.catch_addr()))))
+                .collect::<AnalysisResult<_>>()?;
+            if let Some(a) = catches.catch_all_addr() {
+                handlers.push((None, Addr(a)));
+            }
+            Ok(TryRegion {
+                start: try_.start_addr(),
+                end: try_.end_addr(),
+                handlers,
+            })
+        })
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::testing::{self, DexBuilder, ACC_PUBLIC, ACC_STATIC};
+
+    #[test]
+    fn coalesce_linear_chain() {
+        let mut builder = DexBuilder::new();
+        let class = builder.class("LTest;", "Ljava/lang/Object;");
+        let callee = builder.method("LTest;", "callee", "V", &[]) as u16;
+        let method = builder.method("LTest;", "run", "V", &[]);
+        builder.code(
+            class,
+            method,
+            ACC_PUBLIC | ACC_STATIC,
+            1,
+            0,
+            0,
+            &[
+                0x0012, // 0: const/4 v0, 0
+                0x0071, callee, 0x0000, // 1: invoke-static {}, Test.callee()
+                0x0038, 0x0003, // 4: if-eqz v0, +3
+                0x000e, // 6: return-void
+                0x000e, // 7: return-void
+            ],
+        );
+        let dex = builder.build_dex();
+        let repo = testing::repo(&dex);
+        let (_, method) = testing::method(&repo, "run");
+
+        let cfg = Cfg::build(method).unwrap();
+        assert_eq!(cfg.iter_ordered_blocks().count(), 5);
+
+        let coalesced = cfg.coalesce();
+        let blocks: Vec<Vec<usize>> = coalesced
+            .iter_ordered_blocks()
+            .map(|b| b.instructions().map(|i| i.addr().0).collect())
+            .collect();
+        assert_eq!(blocks, vec![vec![0, 1, 4], vec![6], vec![7]]);
+
+        let start = coalesced.start_index();
+        let mut targets: Vec<(String, usize)> = coalesced
+            .inner
+            .edges_directed(start, Direction::Outgoing)
+            .map(|e| {
+                (
+                    e.weight().to_string(),
+                    coalesced.inner[e.target()].start_addr().0,
+                )
+            })
+            .collect();
+        targets.sort();
+        assert_eq!(
+            targets,
+            vec![("<false>".to_string(), 6), ("<true>".to_string(), 7)]
+        );
+    }
+
+    #[test]
+    fn build_without_exceptional_edges() {
+        let mut builder = DexBuilder::new();
+        let class = builder.class("LTest;", "Ljava/lang/Object;");
+        let callee = builder.method("LTest;", "callee", "V", &[]) as u16;
+        let method = builder.method("LTest;", "run", "V", &[]);
+        builder.code(
+            class,
+            method,
+            ACC_PUBLIC | ACC_STATIC,
+            1,
+            0,
+            0,
+            &[
+                0x1012, // 0: const/4 v0, 1
+                0x0071, callee, 0x0000, // 1: invoke-static {}, Test.callee()
+                0x00db, 0x0200, // 4: div-int/lit8 v0, v0, 2
+                0x000e, // 6: return-void
+                0x000e, // 7: return-void
+            ],
+        );
+        builder.catch_all(class, method, 1, 5, 7);
+        let dex = builder.build_dex();
+        let repo = testing::repo(&dex);
+        let (_, method) = testing::method(&repo, "run");
+
+        let edges = |options| Cfg::build_with(method, options).unwrap().inner.edge_count();
+        assert_eq!(Cfg::build(method).unwrap().inner.edge_count(), 5);
+        assert_eq!(edges(CfgOptions::default()), 5);
+        assert_eq!(edges(CfgOptions::default().no_div_exceptional_edges()), 4);
+        assert_eq!(edges(CfgOptions::default().no_exceptional_edges()), 3);
+    }
+
+    #[test]
+    fn exceptional_successors() {
+        let mut builder = DexBuilder::new();
+        let class = builder.class("LTest;", "Ljava/lang/Object;");
+        let method = builder.method("LTest;", "run", "V", &[]);
+        builder.code(
+            class,
+            method,
+            ACC_PUBLIC | ACC_STATIC,
+            1,
+            0,
+            0,
+            &[
+                0x0012, // 0: const/4 v0, 0
+                0x0027, // 1: throw v0
+                0x000e, // 2: return-void
+            ],
+        );
+        builder.catch_all(class, method, 1, 1, 2);
+        let dex = builder.build_dex();
+        let repo = testing::repo(&dex);
+        let (_, method) = testing::method(&repo, "run");
+
+        let cfg = Cfg::build(method).unwrap();
+        let (normal, exceptional) = cfg.successors(Addr(0));
+        assert!(matches!(normal[..], [(Addr(1), Branch::Sequence)]));
+        assert!(exceptional.is_empty());
+        let (normal, exceptional) = cfg.successors(Addr(1));
+        assert!(normal.is_empty());
+        assert!(matches!(exceptional[..], [(Addr(2), Branch::CatchAll)]));
+        assert_eq!(cfg.successors(Addr(3)).0.len(), 0);
+    }
+
+    #[test]
+    fn nested_try_regions() {
+        let mut builder = DexBuilder::new();
+        let class = builder.class("LTest;", "Ljava/lang/Object;");
+        let callee = builder.method("LTest;", "callee", "V", &[]) as u16;
+        let method = builder.method("LTest;", "run", "V", &[]);
+        builder.code(
+            class,
+            method,
+            ACC_PUBLIC | ACC_STATIC,
+            0,
+            0,
+            0,
+            &[
+                0x0071, callee, 0x0000, // 0: invoke-static {}, Test.callee()
+                0x0071, callee, 0x0000, // 3: invoke-static {}, Test.callee()
+                0x0071, callee, 0x0000, // 6: invoke-static {}, Test.callee()
+                0x000e, // 9: return-void
+                0x000e, // 10: return-void
+                0x000e, // 11: return-void
+                0x000e, // 12: return-void
+            ],
+        );
+        // try { callee(); try { callee(); } catch (IOException) { } finally { } callee(); }
+        // catch (Exception) { }
+        let exception = ("Ljava/lang/Exception;", 11);
+        builder.catch(class, method, 0, 3, &[exception], None);
+        builder.catch(
+            class,
+            method,
+            3,
+            3,
+            &[("Ljava/io/IOException;", 10), exception],
+            Some(12),
+        );
+        builder.catch(class, method, 6, 3, &[exception], None);
+        let dex = builder.build_dex();
+        let repo = testing::repo(&dex);
+        let (_, method) = testing::method(&repo, "run");
+
+        let regions = Cfg::build(method).unwrap().try_regions();
+        let typ = |name: &str| Some(Type::Class(name.to_string()));
+        let exception = (typ("java/lang/Exception"), Addr(11));
+        assert_eq!(
+            regions,
+            vec![
+                TryRegion {
+                    start: Addr(0),
+                    end: Addr(3),
+                    handlers: vec![exception.clone()],
+                },
+                TryRegion {
+                    start: Addr(3),
+                    end: Addr(6),
+                    handlers: vec![
+                        (typ("java/io/IOException"), Addr(10)),
+                        exception.clone(),
+                        (None, Addr(12)),
+                    ],
+                },
+                TryRegion {
+                    start: Addr(6),
+                    end: Addr(9),
+                    handlers: vec![exception],
+                },
+            ]
+        );
+    }
+
+    fn build_loops(insns: &[u16], registers: u16) -> Vec<Loop> {
+        let mut builder = DexBuilder::new();
+        let class = builder.class("LTest;", "Ljava/lang/Object;");
+        let method = builder.method("LTest;", "run", "V", &[]);
+        builder.code(
+            class,
+            method,
+            ACC_PUBLIC | ACC_STATIC,
+            registers,
+            0,
+            0,
+            insns,
+        );
+        let dex = builder.build_dex();
+        let repo = testing::repo(&dex);
+        let (_, method) = testing::method(&repo, "run");
+        Cfg::build(method).unwrap().natural_loops()
+    }
+
+    fn addrs(addrs: &[usize]) -> BTreeSet<Addr> {
+        addrs.iter().map(|a| Addr(*a)).collect()
+    }
+
+    #[test]
+    fn while_loop() {
+        let loops = build_loops(
+            &[
+                0x1012, // 0: const/4 v0, 1
+                0x0038, 0x0005, // 1: if-eqz v0, +5
+                0x00d8, 0xff00, // 3: add-int/lit8 v0, v0, -1
+                0xfc28, // 5: goto -4
+                0x000e, // 6: return-void
+            ],
+            1,
+        );
+        assert_eq!(
+            loops,
+            vec![Loop {
+                header: Addr(1),
+                body: addrs(&[1, 3]),
+                back_edges: addrs(&[3]),
+                irreducible: false,
+            }]
+        );
+    }
+
+    #[test]
+    fn nested_loops() {
+        let loops = build_loops(
+            &[
+                0x1012, // 0: const/4 v0, 1
+                0x0038, 0x0009, // 1: if-eqz v0, +9
+                0x1112, // 3: const/4 v1, 1
+                0x0138, 0x0005, // 4: if-eqz v1, +5
+                0x01d8, 0xff01, // 6: add-int/lit8 v1, v1, -1
+                0xfc28, // 8: goto -4
+                0xf828, // 9: goto -8
+                0x000e, // 10: return-void
+            ],
+            2,
+        );
+        assert_eq!(loops.len(), 2);
+        assert_eq!(loops[0].header, Addr(1));
+        assert_eq!(loops[0].body, addrs(&[1, 3, 4, 6, 9]));
+        assert_eq!(loops[0].back_edges, addrs(&[9]));
+        assert_eq!(loops[1].header, Addr(4));
+        assert_eq!(loops[1].body, addrs(&[4, 6]));
+        assert_eq!(loops[1].back_edges, addrs(&[6]));
+        assert!(loops.iter().all(|l| !l.irreducible));
+    }
+
+    #[test]
+    fn irreducible_loop() {
+        let loops = build_loops(
+            &[
+                0x0012, // 0: const/4 v0, 0
+                0x0038, 0x0004, // 1: if-eqz v0, +4
+                0x0039, 0x0003, // 3: if-nez v0, +3
+                0xfe28, // 5: goto -2
+                0x000e, // 6: return-void
+            ],
+            1,
+        );
+        assert_eq!(loops.len(), 1);
+        assert!(loops[0].irreducible);
+        assert_eq!(loops[0].body, addrs(&[3, 5]));
+    }
+}
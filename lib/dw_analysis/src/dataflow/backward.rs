@@ -1,5 +1,5 @@
 use crate::controlflow::{Branch, Cfg};
-use crate::dataflow::Dataflow;
+use crate::dataflow::{Dataflow, DataflowOptions, Worklist};
 use crate::errors::{AnalysisError, AnalysisResult};
 use crate::repo::{Class, Method};
 use dw_dex::instrs::Instr;
@@ -7,7 +7,7 @@ use dw_dex::{Addr, Dex, PrettyPrinter};
 use petgraph::graph::NodeIndex;
 use petgraph::visit::{DfsPostOrder, EdgeRef};
 use petgraph::Direction;
-use std::collections::{BTreeMap, VecDeque};
+use std::collections::{BTreeMap, BTreeSet};
 use std::fmt;
 
 /// The abstract state that is carried along the control flow graph
@@ -70,6 +70,28 @@ pub trait AbstractBackwardState<'a>: Eq + Sized {
         method: &Method,
         ctx: &Self::Context<'a>,
     ) -> Result<(), Self::Error>;
+
+    /// The widening operation function, applied at the sources of loops
+    /// back edges between the previous exit state and the newly met one
+    /// (`self`), once the block has been visited more than the widening
+    /// threshold (see [`DataflowOptions`]). `iteration` is the number of
+    /// visits of the block so far, including the current one.
+    ///
+    /// Domains of finite height do not need widening, hence the default
+    /// implementation leaves the met state untouched.
+    ///
+    /// # Errors
+    ///
+    /// This method should return a `Self::Error` if given states cannot be
+    /// widened properly with respect to the context.
+    fn widen(
+        &mut self,
+        _previous: &Self,
+        _iteration: usize,
+        _ctx: &Self::Context<'a>,
+    ) -> Result<(), Self::Error> {
+        Ok(())
+    }
 }
 
 /// Performs a backward dataflow analysis.
@@ -88,6 +110,25 @@ pub fn backward<'a, S>(
     class: &Class,
     context: &S::Context<'a>,
 ) -> AnalysisResult<Dataflow<S>>
+where
+    S: AbstractBackwardState<'a> + Clone + fmt::Display,
+    S::Error: Into<AnalysisError>,
+{
+    backward_with(method, class, context, DataflowOptions::default())
+}
+
+/// Performs a backward dataflow analysis, with the worklist algorithm
+/// parameterized by `options`.
+///
+/// # Errors
+///
+/// See [`backward`].
+pub fn backward_with<'a, S>(
+    method: &Method,
+    class: &Class,
+    context: &S::Context<'a>,
+    options: DataflowOptions,
+) -> AnalysisResult<Dataflow<S>>
 where
     S: AbstractBackwardState<'a> + Clone + fmt::Display,
     S::Error: Into<AnalysisError>,
@@ -101,16 +142,47 @@ where
     let mut exits: BTreeMap<Addr, S> = BTreeMap::new();
 
     // For backward dataflow, optimal order is postorder.
-    let mut worklist: VecDeque<NodeIndex> = VecDeque::new();
-    let mut postorder = DfsPostOrder::new(cfgraph, cfg.start_index());
-    while let Some(id) = postorder.next(cfgraph) {
-        worklist.push_front(id);
+    let mut postorder = Vec::new();
+    let mut dfs = DfsPostOrder::new(cfgraph, cfg.start_index());
+    while let Some(id) = dfs.next(cfgraph) {
+        postorder.push(id);
     }
 
-    while let Some(id) = worklist.pop_back() {
+    // Every loop goes through the source of a retreating edge, i.e. an edge
+    // that does not go forward in reverse postorder.
+    let positions: BTreeMap<NodeIndex, usize> = postorder
+        .iter()
+        .enumerate()
+        .map(|(i, id)| (*id, i))
+        .collect();
+    let loop_sources: BTreeSet<NodeIndex> = cfgraph
+        .edge_references()
+        .filter(|edge| {
+            matches!(
+                (positions.get(&edge.source()), positions.get(&edge.target())),
+                (Some(src), Some(dst)) if src <= dst
+            )
+        })
+        .map(|edge| edge.source())
+        .collect();
+    let mut worklist = Worklist::new(options, &postorder, &cfg, true);
+    let mut visits: BTreeMap<NodeIndex, usize> = BTreeMap::new();
+
+    while let Some(id) = worklist.pop() {
+        // blocks visited in reverse address order may not be reached yet
+        let mut successors = cfgraph.edges_directed(id, Direction::Outgoing).peekable();
+        if worklist.is_naive()
+            && successors.peek().is_some()
+            && !successors.any(|edge| block_entries.contains_key(&edge.target()))
+        {
+            continue;
+        }
         let block = &cfgraph[id];
         log::debug!("    ---- block@{}", block.start_addr());
 
+        let iteration = visits.entry(id).or_default();
+        *iteration += 1;
+
         // retrieve list of already computed successors
         let succs: Vec<_> = cfgraph
             .edges_directed(id, Direction::Outgoing)
@@ -149,6 +221,15 @@ where
         };
 
         if let Some(new_state) = &mut new_state {
+            if loop_sources.contains(&id) && *iteration > options.widening_threshold {
+                let exit_addr = block.rev_instructions().next().unwrap().addr();
+                if let Some(previous) = exits.get(&exit_addr) {
+                    new_state
+                        .widen(previous, *iteration, context)
+                        .map_err(S::Error::into)?;
+                }
+            }
+
             if has_exception_handlers {
                 log::debug!("    -- EXIT STATE (when exceptions are not thrown):");
             } else {
@@ -222,18 +303,13 @@ where
         };
 
         // checking if need to treat again predecessors:
-        // - if previous state was None, predecessors are already in worklist;
+        // - if previous state was None, predecessors may already be pending,
+        //   except for the sources of back edges that must be treated again;
         // - if previous state was a different Some(thing), add in worklist.
-        if let Some(old_state) = block_entries.get(&id) {
-            if &new_state != old_state {
-                cfgraph
-                    .edges_directed(id, Direction::Incoming)
-                    .for_each(|edge| {
-                        if !worklist.contains(&edge.source()) {
-                            worklist.push_front(edge.source());
-                        }
-                    });
-            }
+        if block_entries.get(&id) != Some(&new_state) {
+            cfgraph
+                .edges_directed(id, Direction::Incoming)
+                .for_each(|edge| worklist.push(edge.source()));
         }
 
         block_entries.insert(id, new_state);
@@ -245,5 +321,9 @@ where
     S::entry_reached(entrypoint_state, class, method, context).map_err(S::Error::into)?;
     log::debug!("");
 
-    Ok(Dataflow { entries, exits })
+    Ok(Dataflow {
+        entries,
+        exits,
+        visits: visits.values().sum(),
+    })
 }
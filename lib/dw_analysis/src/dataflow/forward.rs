@@ -1,5 +1,5 @@
 use crate::controlflow::{Branch, Cfg};
-use crate::dataflow::Dataflow;
+use crate::dataflow::{Dataflow, DataflowOptions, Worklist};
 use crate::errors::{AnalysisError, AnalysisResult};
 use crate::repo::{Class, Method};
 use dw_dex::instrs::Instr;
@@ -7,7 +7,7 @@ use dw_dex::{Addr, Dex, PrettyPrinter};
 use petgraph::graph::NodeIndex;
 use petgraph::visit::{DfsPostOrder, EdgeRef};
 use petgraph::Direction;
-use std::collections::{BTreeMap, VecDeque};
+use std::collections::{BTreeMap, BTreeSet};
 use std::fmt;
 
 /// The abstract state that is carried along the control flow graph
@@ -58,8 +58,35 @@ pub trait AbstractForwardState<'a>: Eq + Sized {
         dex: &Dex,
         ctx: &Self::Context<'a>,
     ) -> Result<(), Self::Error>;
+
+    /// The widening operation function, applied at loop headers between the
+    /// previous entry state and the newly joined one (`self`), once the
+    /// header has been visited more than the widening threshold (see
+    /// [`DataflowOptions`]). `iteration` is the number of visits of the
+    /// header so far, including the current one.
+    ///
+    /// Domains of finite height do not need widening, hence the default
+    /// implementation leaves the joined state untouched. When widening
+    /// changes a state, a few descending (narrowing) iterations are run once
+    /// the fixpoint is reached to recover some precision.
+    ///
+    /// # Errors
+    ///
+    /// This method should return a `Self::Error` if given states cannot be
+    /// widened properly with respect to the context.
+    fn widen(
+        &mut self,
+        _previous: &Self,
+        _iteration: usize,
+        _ctx: &Self::Context<'a>,
+    ) -> Result<(), Self::Error> {
+        Ok(())
+    }
 }
 
+/// Number of descending iterations performed after a widened fixpoint.
+const NARROWING_PASSES: usize = 2;
+
 /// Performs a forward dataflow analysis.
 ///
 /// The analysis parameters are given by the `AbstractForwardState` trait
@@ -76,6 +103,25 @@ pub fn forward<'a, S>(
     class: &Class,
     context: &S::Context<'a>,
 ) -> AnalysisResult<Dataflow<S>>
+where
+    S: AbstractForwardState<'a> + Clone + fmt::Display,
+    S::Error: Into<AnalysisError>,
+{
+    forward_with(method, class, context, DataflowOptions::default())
+}
+
+/// Performs a forward dataflow analysis, with the worklist algorithm
+/// parameterized by `options`.
+///
+/// # Errors
+///
+/// See [`forward`].
+pub fn forward_with<'a, S>(
+    method: &Method,
+    class: &Class,
+    context: &S::Context<'a>,
+    options: DataflowOptions,
+) -> AnalysisResult<Dataflow<S>>
 where
     S: AbstractForwardState<'a> + Clone + fmt::Display,
     S::Error: Into<AnalysisError>,
@@ -89,66 +135,60 @@ where
     let mut exits: BTreeMap<Addr, S> = BTreeMap::new();
 
     // For forward dataflow, optimal order is reverse postorder.
-    // The postorder here is reversed when we pop_back from the deque.
-    let mut worklist: VecDeque<NodeIndex> = VecDeque::new();
-    let mut postorder = DfsPostOrder::new(cfgraph, cfg.start_index());
-    while let Some(id) = postorder.next(cfgraph) {
-        worklist.push_back(id);
+    let mut postorder = Vec::new();
+    let mut dfs = DfsPostOrder::new(cfgraph, cfg.start_index());
+    while let Some(id) = dfs.next(cfgraph) {
+        postorder.push(id);
     }
 
-    while let Some(id) = worklist.pop_back() {
+    // Loop headers are targets of retreating edges, i.e. edges that do not
+    // go forward in reverse postorder.
+    let positions: BTreeMap<NodeIndex, usize> = postorder
+        .iter()
+        .enumerate()
+        .map(|(i, id)| (*id, i))
+        .collect();
+    let loop_headers: BTreeSet<NodeIndex> = cfgraph
+        .edge_references()
+        .filter(|edge| {
+            matches!(
+                (positions.get(&edge.source()), positions.get(&edge.target())),
+                (Some(src), Some(dst)) if src <= dst
+            )
+        })
+        .map(|edge| edge.target())
+        .collect();
+    let rev_postorder: Vec<NodeIndex> = postorder.iter().rev().copied().collect();
+    let mut worklist = Worklist::new(options, &rev_postorder, &cfg, false);
+    let mut widened = false;
+    let mut visits: BTreeMap<NodeIndex, usize> = BTreeMap::new();
+
+    while let Some(id) = worklist.pop() {
+        // blocks visited in address order may not be reached yet
+        if worklist.is_naive()
+            && id != cfg.start_index()
+            && !cfgraph
+                .edges_directed(id, Direction::Incoming)
+                .any(|edge| block_exits.contains_key(&edge.source()))
+        {
+            continue;
+        }
         let block = &cfgraph[id];
         log::debug!("    ---- block@{}", block.start_addr());
 
-        // retrieve list of already computed predecessors
-        let preds: Vec<_> = cfgraph
-            .edges_directed(id, Direction::Incoming)
-            .filter(|edge| block_exits.contains_key(&edge.source()))
-            .collect();
-
-        // recompose new_state from exit states of predecessor blocks,
-        let mut new_state = if preds.is_empty() {
-            // when no predecessors:
-            // entry = initial state
-            S::init(method, class).map_err(S::Error::into)?
-        } else {
-            // otherwise:
-            // entry = join of predecessors exits
-            let mut entry: S = match preds[0].weight() {
-                Branch::Catch(_) | Branch::CatchAll => {
-                    let entry_addr = cfgraph[preds[0].source()].start_addr();
-                    entries.get(&entry_addr).unwrap().clone()
-                }
-                _ => block_exits.get(&preds[0].source()).unwrap().clone(),
-            };
-            entry
-                .transfer_branch(preds[0].weight(), context)
-                .map_err(S::Error::into)?;
-            for edge in preds.iter().skip(1) {
-                match edge.weight() {
-                    Branch::Catch(_) | Branch::CatchAll => {
-                        let entry_addr = cfgraph[edge.source()].start_addr();
-                        if let Some(ent) = entries.get(&entry_addr) {
-                            let mut previous = ent.clone();
-                            previous
-                                .transfer_branch(edge.weight(), context)
-                                .map_err(S::Error::into)?;
-                            entry.join(&previous, context).map_err(S::Error::into)?;
-                        }
-                    }
-                    _ => {
-                        if let Some(ent) = block_exits.get(&edge.source()) {
-                            let mut previous = ent.clone();
-                            previous
-                                .transfer_branch(edge.weight(), context)
-                                .map_err(S::Error::into)?;
-                            entry.join(&previous, context).map_err(S::Error::into)?;
-                        }
-                    }
-                }
+        let iteration = visits.entry(id).or_default();
+        *iteration += 1;
+
+        let mut new_state = block_entry(&cfg, id, method, class, context, &block_exits, &entries)?;
+        if loop_headers.contains(&id) && *iteration > options.widening_threshold {
+            if let Some(previous) = entries.get(&block.start_addr()) {
+                let joined = new_state.clone();
+                new_state
+                    .widen(previous, *iteration, context)
+                    .map_err(S::Error::into)?;
+                widened |= new_state != joined;
             }
-            entry
-        };
+        }
 
         log::debug!("    -- ENTRY STATE:");
         for line in format!("{new_state}").split('\n') {
@@ -174,25 +214,117 @@ where
         log::debug!("");
 
         // checking if need to treat again successors:
-        // - if previous state was None, successors are already in worklist;
+        // - if previous state was None, successors may already be pending,
+        //   except for the targets of back edges that must be treated again;
         // - if previous state was a different Some(thing), add in worklist.
-        if let Some(old_state) = block_exits.get(&id) {
-            if &new_state != old_state {
-                cfgraph
-                    .edges_directed(id, Direction::Outgoing)
-                    .for_each(|edge| {
-                        if !(matches!(edge.weight(), Branch::Catch(_))
-                            || matches!(edge.weight(), Branch::CatchAll)
-                            || worklist.contains(&edge.target()))
-                        {
-                            worklist.push_front(edge.target());
-                        }
-                    });
-            }
+        if block_exits.get(&id) != Some(&new_state) {
+            cfgraph
+                .edges_directed(id, Direction::Outgoing)
+                .for_each(|edge| {
+                    if !matches!(edge.weight(), Branch::Catch(_) | Branch::CatchAll) {
+                        worklist.push(edge.target());
+                    }
+                });
         }
 
         block_exits.insert(id, new_state);
     }
 
-    Ok(Dataflow { entries, exits })
+    // Descending iterations starting from the (post-)fixpoint: each state is
+    // recomputed from its predecessors without widening, which keeps states
+    // sound while possibly refining what widening over-approximated.
+    for _ in 0..if widened { NARROWING_PASSES } else { 0 } {
+        let mut changed = false;
+        for id in &rev_postorder {
+            let mut new_state =
+                block_entry(&cfg, *id, method, class, context, &block_exits, &entries)?;
+            for linstr in cfgraph[*id].instructions() {
+                entries.insert(linstr.addr(), new_state.clone());
+                new_state
+                    .transfer_instr(linstr.instr(), dex, context)
+                    .map_err(S::Error::into)?;
+                exits.insert(linstr.addr(), new_state.clone());
+            }
+            changed |= block_exits.get(id) != Some(&new_state);
+            block_exits.insert(*id, new_state);
+        }
+        if !changed {
+            break;
+        }
+    }
+
+    Ok(Dataflow {
+        entries,
+        exits,
+        visits: visits.values().sum(),
+    })
+}
+
+/// Computes the entry state of a block from the exit states of its already
+/// computed predecessor blocks (or from the entry state of the predecessor
+/// for exception edges), or initial state when there is no such predecessor.
+fn block_entry<'a, S>(
+    cfg: &Cfg,
+    id: NodeIndex,
+    method: &Method,
+    class: &Class,
+    context: &S::Context<'a>,
+    block_exits: &BTreeMap<NodeIndex, S>,
+    entries: &BTreeMap<Addr, S>,
+) -> AnalysisResult<S>
+where
+    S: AbstractForwardState<'a> + Clone,
+    S::Error: Into<AnalysisError>,
+{
+    let cfgraph = &cfg.inner;
+
+    // retrieve list of already computed predecessors
+    let preds: Vec<_> = cfgraph
+        .edges_directed(id, Direction::Incoming)
+        .filter(|edge| block_exits.contains_key(&edge.source()))
+        .collect();
+
+    // recompose new_state from exit states of predecessor blocks,
+    if preds.is_empty() {
+        // when no predecessors:
+        // entry = initial state
+        return S::init(method, class).map_err(S::Error::into);
+    }
+
+    // otherwise:
+    // entry = join of predecessors exits
+    let mut entry: S = match preds[0].weight() {
+        Branch::Catch(_) | Branch::CatchAll => {
+            let entry_addr = cfgraph[preds[0].source()].start_addr();
+            entries.get(&entry_addr).unwrap().clone()
+        }
+        _ => block_exits.get(&preds[0].source()).unwrap().clone(),
+    };
+    entry
+        .transfer_branch(preds[0].weight(), context)
+        .map_err(S::Error::into)?;
+    for edge in preds.iter().skip(1) {
+        match edge.weight() {
+            Branch::Catch(_) | Branch::CatchAll => {
+                let entry_addr = cfgraph[edge.source()].start_addr();
+                if let Some(ent) = entries.get(&entry_addr) {
+                    let mut previous = ent.clone();
+                    previous
+                        .transfer_branch(edge.weight(), context)
+                        .map_err(S::Error::into)?;
+                    entry.join(&previous, context).map_err(S::Error::into)?;
+                }
+            }
+            _ => {
+                if let Some(ent) = block_exits.get(&edge.source()) {
+                    let mut previous = ent.clone();
+                    previous
+                        .transfer_branch(edge.weight(), context)
+                        .map_err(S::Error::into)?;
+                    entry.join(&previous, context).map_err(S::Error::into)?;
+                }
+            }
+        }
+    }
+    Ok(entry)
 }
@@ -1,20 +1,566 @@
 //! Dataflow analysis framework.
 
+use crate::controlflow::{Branch, Cfg};
 use dw_dex::Addr;
-use std::collections::BTreeMap;
+use petgraph::dot::{Config, Dot};
+use petgraph::graph::NodeIndex;
+use std::collections::{BTreeMap, BTreeSet, VecDeque};
+use std::fmt;
+use std::fmt::Write;
 
 mod backward;
 mod forward;
 
-pub use backward::{backward, AbstractBackwardState};
-pub use forward::{forward, AbstractForwardState};
+pub use backward::{backward, backward_with, AbstractBackwardState};
+pub use forward::{forward, forward_with, AbstractForwardState};
+
+/// The order in which the worklist algorithms visit the blocks of the
+/// control flow graph. Orders are given for forward analyses, backward
+/// analyses visiting blocks in the reverse order.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub enum IterationOrder {
+    /// The pending block that comes first in reverse postorder is visited
+    /// first, so that blocks are visited after their predecessors whenever
+    /// possible.
+    #[default]
+    ReversePostorder,
+    /// Blocks are visited in reverse postorder once, then in the order
+    /// they are scheduled again.
+    Worklist,
+    /// All the blocks are visited in address order, pass after pass, until
+    /// a whole pass leaves the states unchanged.
+    Naive,
+}
+
+/// Options of the dataflow analyses worklist algorithms.
+#[derive(Debug, Clone, Copy)]
+pub struct DataflowOptions {
+    widening_threshold: usize,
+    order: IterationOrder,
+}
+
+/// Default values widen loop states from their second visit on, and visit
+/// blocks in reverse postorder.
+impl Default for DataflowOptions {
+    fn default() -> Self {
+        Self {
+            widening_threshold: 1,
+            order: IterationOrder::default(),
+        }
+    }
+}
+
+impl DataflowOptions {
+    /// Sets the number of visits of a loop block after which its state is
+    /// widened at each new visit. Higher thresholds delay widening, hence
+    /// give more precise results for loops that stabilize by themselves.
+    #[must_use]
+    pub const fn widening_threshold(self, widening_threshold: usize) -> Self {
+        Self {
+            widening_threshold,
+            ..self
+        }
+    }
+
+    /// Sets the order in which blocks are visited. Analyses whose states
+    /// are widened may reach different fixpoints depending on the order.
+    #[must_use]
+    pub const fn order(self, order: IterationOrder) -> Self {
+        Self { order, ..self }
+    }
+}
+
+/// The blocks remaining to be visited by a worklist algorithm.
+pub(crate) struct Worklist {
+    order: IterationOrder,
+    // positions of the blocks in the traversal order
+    positions: BTreeMap<NodeIndex, usize>,
+    pending: BTreeSet<(usize, NodeIndex)>,
+    queue: VecDeque<NodeIndex>,
+    // all the blocks for the naive order, with the next one to visit and
+    // whether a state changed during the current pass
+    blocks: Vec<NodeIndex>,
+    next: usize,
+    changed: bool,
+}
+
+impl Worklist {
+    /// Schedules the blocks of `traversal`, which is the reverse postorder
+    /// of the graph for forward analyses and its postorder for backward
+    /// ones. The naive order visits all the blocks of `cfg` in address
+    /// order instead, `rev` reversing it.
+    pub(crate) fn new(
+        options: DataflowOptions,
+        traversal: &[NodeIndex],
+        cfg: &Cfg,
+        rev: bool,
+    ) -> Self {
+        let positions: BTreeMap<NodeIndex, usize> = traversal
+            .iter()
+            .enumerate()
+            .map(|(i, id)| (*id, i))
+            .collect();
+        let mut blocks = Vec::new();
+        if options.order == IterationOrder::Naive {
+            blocks = cfg.inner.node_indices().collect();
+            blocks.sort_by_key(|id| cfg.inner[*id].start_addr());
+            if rev {
+                blocks.reverse();
+            }
+        }
+        Self {
+            order: options.order,
+            pending: positions.iter().map(|(id, i)| (*i, *id)).collect(),
+            queue: traversal.iter().copied().collect(),
+            positions,
+            blocks,
+            next: 0,
+            changed: false,
+        }
+    }
+
+    /// Returns whether blocks are visited pass after pass, in which case
+    /// blocks whose state cannot be computed yet must be skipped.
+    pub(crate) fn is_naive(&self) -> bool {
+        self.order == IterationOrder::Naive
+    }
+
+    pub(crate) fn pop(&mut self) -> Option<NodeIndex> {
+        match self.order {
+            IterationOrder::ReversePostorder => self.pending.pop_first().map(|(_, id)| id),
+            IterationOrder::Worklist => self.queue.pop_front(),
+            IterationOrder::Naive => {
+                if self.next == self.blocks.len() {
+                    if !self.changed {
+                        return None;
+                    }
+                    self.next = 0;
+                    self.changed = false;
+                }
+                self.next += 1;
+                self.blocks.get(self.next - 1).copied()
+            }
+        }
+    }
+
+    /// Schedules a block to be visited again, as the state flowing into
+    /// it has changed.
+    pub(crate) fn push(&mut self, id: NodeIndex) {
+        match self.order {
+            IterationOrder::ReversePostorder => {
+                let position = self.positions.get(&id).copied().unwrap_or(usize::MAX);
+                let _ = self.pending.insert((position, id));
+            }
+            IterationOrder::Worklist => {
+                if !self.queue.contains(&id) {
+                    self.queue.push_back(id);
+                }
+            }
+            IterationOrder::Naive => self.changed = true,
+        }
+    }
+}
 
 /// Dataflow analysis result object.
 ///
-/// Contains entries and exits abstract states for every basic block
-/// of the analyzed method, after reaching fixpoint.
+/// Contains entries and exits abstract states for every instruction
+/// of the analyzed method, after reaching fixpoint. Both maps are keyed
+/// by instruction address, and states are given in program order
+/// whatever the direction of the analysis: `entries` holds states right
+/// before instructions, `exits` holds states right after them.
 #[derive(Debug, Clone)]
 pub struct Dataflow<S> {
     pub entries: BTreeMap<Addr, S>,
     pub exits: BTreeMap<Addr, S>,
+    /// The number of block visits needed to reach the fixpoint, descending
+    /// iterations excluded.
+    pub visits: usize,
+}
+
+impl<S> Dataflow<S> {
+    /// Returns the state right before the instruction at `addr`.
+    ///
+    /// For a forward analysis, when `addr` is the start of a basic block,
+    /// this is the join of the states flowing from all its predecessors
+    /// (after branch transfers), or the initial state at method entry.
+    #[must_use]
+    pub fn in_state(&self, addr: Addr) -> Option<&S> {
+        self.entries.get(&addr)
+    }
+
+    /// Returns the state right after the instruction at `addr`.
+    ///
+    /// For a backward analysis, when `addr` is the end of a basic block,
+    /// this is the meet of the states flowing from all its successors.
+    #[must_use]
+    pub fn out_state(&self, addr: Addr) -> Option<&S> {
+        self.exits.get(&addr)
+    }
+
+    /// Returns the states flowing into the block starting at `addr` from
+    /// each of its predecessors in `cfg`, keyed by predecessor start address.
+    ///
+    /// The state of a predecessor is the exit state of its last instruction,
+    /// except for exception edges which carry the entry state of the
+    /// predecessor (its throwing instruction has not been executed). States
+    /// are given before branch transfers, so that joining them does not
+    /// necessarily give back [`Self::in_state`]. Predecessors that were not
+    /// reached by the analysis are omitted.
+    #[must_use]
+    pub fn predecessor_states(&self, addr: Addr, cfg: &Cfg) -> BTreeMap<Addr, &S> {
+        cfg.predecessors(addr)
+            .filter_map(|(block, branch)| {
+                let state = match branch {
+                    Branch::Catch(_) | Branch::CatchAll => self.in_state(block.start_addr()),
+                    _ => self.out_state(block.rev_instructions().next()?.addr()),
+                };
+                Some((block.start_addr(), state?))
+            })
+            .collect()
+    }
+}
+
+impl<S: fmt::Display> Dataflow<S> {
+    /// Renders the control flow graph of the analyzed method in the dot
+    /// format, each block being labeled with its instructions surrounded by
+    /// the states at its entry and at its exit. Edges are labeled by their
+    /// branch. Blocks that were not reached by the analysis have no state.
+    #[must_use]
+    pub fn to_dot(&self, cfg: &Cfg) -> String {
+        let label = |block: &crate::controlflow::Block| {
+            let state = |state: Option<&S>| match state {
+                Some(state) => state.to_string(),
+                None => "<unreachable>".to_string(),
+            };
+            let exit = block
+                .rev_instructions()
+                .next()
+                .and_then(|instr| self.out_state(instr.addr()));
+            let label = format!(
+                "in:\n{}\n{}out:\n{}\n",
+                state(self.in_state(block.start_addr())),
+                block,
+                state(exit),
+            );
+            // left-justified lines
+            label
+                .replace('\\', "\\\\")
+                .replace('"', "\\\"")
+                .replace('\n', "\\l")
+        };
+
+        let mut res = String::new();
+        res.push_str("digraph {\n");
+        write!(
+            res,
+            "{}",
+            Dot::with_attr_getters(
+                &cfg.inner,
+                &[
+                    Config::GraphContentOnly,
+                    Config::NodeNoLabel,
+                    Config::EdgeNoLabel
+                ],
+                &|_, edge| format!("label=\"{}\"", edge.weight()),
+                &|_, (_, block)| format!("shape=box,fontname=monospace,label=\"{}\"", label(block)),
+            )
+        )
+        .unwrap();
+        res.push('}');
+        res
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::constants::{Constants, Value};
+    use crate::errors::AnalysisResult;
+    use crate::repo::{Class, Method};
+    use crate::testing::{self, DexBuilder, ACC_PUBLIC, ACC_STATIC};
+    use dw_dex::instrs::Instr;
+    use dw_dex::registers::Reg;
+    use dw_dex::Dex;
+    use std::cell::Cell;
+    use std::fmt;
+
+    #[test]
+    fn loop_header_joins_back_edge() {
+        let mut builder = DexBuilder::new();
+        let class = builder.class("LTest;", "Ljava/lang/Object;");
+        let method = builder.method("LTest;", "run", "V", &["I"]);
+        builder.code(
+            class,
+            method,
+            ACC_PUBLIC | ACC_STATIC,
+            2,
+            1,
+            0,
+            &[
+                0x0012, // 0: const/4 v0, 0
+                0x0138, 0x0004, // 1: if-eqz v1, +4
+                0x1012, // 3: const/4 v0, 1
+                0xfd28, // 4: goto -3
+                0x000e, // 5: return-void
+            ],
+        );
+        let dex = builder.build_dex();
+        let repo = testing::repo(&dex);
+        let (class, method) = testing::method(&repo, "run");
+
+        // the loop header is reached with v0 = 0 and, through the back
+        // edge, with v0 = 1
+        let constants = Constants::compute(method, class).unwrap();
+        let v0 = constants
+            .in_state(Addr(1))
+            .unwrap()
+            .read_reg(Reg::from(0u8))
+            .unwrap()
+            .cloned();
+        assert_eq!(v0, None);
+    }
+
+    #[test]
+    fn states_at_join_point() {
+        let mut builder = DexBuilder::new();
+        let class = builder.class("LTest;", "Ljava/lang/Object;");
+        let method = builder.method("LTest;", "run", "V", &["I"]);
+        builder.code(
+            class,
+            method,
+            ACC_PUBLIC | ACC_STATIC,
+            2,
+            1,
+            0,
+            &[
+                0x0012, // 0: const/4 v0, 0
+                0x0138, 0x0004, // 1: if-eqz v1, +4
+                0x1012, // 3: const/4 v0, 1
+                0x0228, // 4: goto +2
+                0x2012, // 5: const/4 v0, 2
+                0x000e, // 6: return-void
+            ],
+        );
+        let dex = builder.build_dex();
+        let repo = testing::repo(&dex);
+        let (class, method) = testing::method(&repo, "run");
+
+        let cfg = Cfg::build(method).unwrap();
+        let constants = Constants::compute(method, class).unwrap();
+        let v0 = |state: &crate::constants::State| state.read_reg(Reg::from(0u8)).unwrap().cloned();
+
+        assert_eq!(
+            v0(constants.out_state(Addr(5)).unwrap()),
+            Some(Value::Int(2))
+        );
+        assert_eq!(v0(constants.in_state(Addr(6)).unwrap()), None);
+
+        let preds: Vec<(usize, Option<Value>)> = constants
+            .predecessor_states(Addr(6), &cfg)
+            .into_iter()
+            .map(|(addr, state)| (addr.0, v0(state)))
+            .collect();
+        assert_eq!(
+            preds,
+            vec![(3, Some(Value::Int(1))), (5, Some(Value::Int(2)))]
+        );
+    }
+
+    #[test]
+    fn states_to_dot() {
+        let mut builder = DexBuilder::new();
+        let class = builder.class("LTest;", "Ljava/lang/Object;");
+        let method = builder.method("LTest;", "run", "V", &["I"]);
+        let quoted = builder.string("a \"quoted\" string") as u16;
+        builder.code(
+            class,
+            method,
+            ACC_PUBLIC | ACC_STATIC,
+            2,
+            1,
+            0,
+            &[
+                0x001a, quoted, // 0: const-string v0, "a \"quoted\" string"
+                0x0138, 0x0003, // 2: if-eqz v1, +3
+                0x1012, // 4: const/4 v0, 1
+                0x000e, // 5: return-void
+            ],
+        );
+        let dex = builder.build_dex();
+        let repo = testing::repo(&dex);
+        let (class, method) = testing::method(&repo, "run");
+
+        let cfg = Cfg::build(method).unwrap();
+        let dot = Constants::compute(method, class).unwrap().to_dot(&cfg);
+
+        let node = regex::Regex::new(r#"^\d+ \[ (.*)\]$"#).unwrap();
+        let edge = regex::Regex::new(r#"^\d+ -> \d+ \[ label="<[a-z_ ]+>" ?\]$"#).unwrap();
+        // attributes are made of unescaped characters or escape sequences
+        // inside quoted strings
+        let attributes = regex::Regex::new(r#"^([a-z]+=([a-z]+|"([^"\\]|\\.)*"),?)+$"#).unwrap();
+        let lines: Vec<&str> = dot.lines().map(str::trim).collect();
+        assert_eq!(lines.first(), Some(&"digraph {"));
+        assert_eq!(lines.last(), Some(&"}"));
+        let mut nodes = 0;
+        for line in &lines[1..lines.len() - 1] {
+            if let Some(captures) = node.captures(line) {
+                assert!(attributes.is_match(&captures[1]), "{line}");
+                nodes += 1;
+            } else {
+                assert!(edge.is_match(line), "{line}");
+            }
+        }
+        assert_eq!(nodes, cfg.iter_ordered_blocks().count());
+        assert!(dot.contains(r#"v0: \"a \\\"quoted\\\" string\""#));
+    }
+
+    // Counts executed instructions, joining states by summing them: there is
+    // no fixpoint on loops unless widening jumps to the saturated count.
+    #[derive(Debug, Clone, PartialEq, Eq)]
+    struct Counter(u32);
+
+    impl fmt::Display for Counter {
+        fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+            write!(f, "{}", self.0)
+        }
+    }
+
+    impl<'a> AbstractForwardState<'a> for Counter {
+        // first iteration at which widening occurred
+        type Context<'c> = Cell<usize>;
+        type Error = crate::errors::AnalysisError;
+
+        fn init(_method: &Method, _class: &Class) -> AnalysisResult<Self> {
+            Ok(Self(0))
+        }
+
+        fn join(&mut self, other: &Self, _ctx: &Cell<usize>) -> AnalysisResult<()> {
+            self.0 = self.0.saturating_add(other.0);
+            Ok(())
+        }
+
+        fn transfer_branch(&mut self, _branch: &Branch, _ctx: &Cell<usize>) -> AnalysisResult<()> {
+            Ok(())
+        }
+
+        fn transfer_instr(
+            &mut self,
+            _instr: &Instr,
+            _dex: &Dex,
+            _ctx: &Cell<usize>,
+        ) -> AnalysisResult<()> {
+            self.0 = self.0.saturating_add(1);
+            Ok(())
+        }
+
+        fn widen(
+            &mut self,
+            previous: &Self,
+            iteration: usize,
+            ctx: &Cell<usize>,
+        ) -> AnalysisResult<()> {
+            if self != previous {
+                self.0 = u32::MAX;
+                if ctx.get() == 0 {
+                    ctx.set(iteration);
+                }
+            }
+            Ok(())
+        }
+    }
+
+    #[test]
+    fn widening_threshold() {
+        let mut builder = DexBuilder::new();
+        let class = builder.class("LTest;", "Ljava/lang/Object;");
+        let method = builder.method("LTest;", "run", "V", &[]);
+        builder.code(
+            class,
+            method,
+            ACC_PUBLIC | ACC_STATIC,
+            1,
+            0,
+            0,
+            &[
+                0x1012, // 0: const/4 v0, 1
+                0x0038, 0x0005, // 1: if-eqz v0, +5
+                0x00d8, 0xff00, // 3: add-int/lit8 v0, v0, -1
+                0xfc28, // 5: goto -4
+                0x000e, // 6: return-void
+            ],
+        );
+        let dex = builder.build_dex();
+        let repo = testing::repo(&dex);
+        let (class, method) = testing::method(&repo, "run");
+
+        for threshold in [1, 5] {
+            let widened_at = Cell::new(0);
+            let options = DataflowOptions::default().widening_threshold(threshold);
+            let counters: Dataflow<Counter> =
+                forward_with(method, class, &widened_at, options).unwrap();
+            assert_eq!(widened_at.get(), threshold + 1);
+            assert_eq!(counters.in_state(Addr(1)), Some(&Counter(u32::MAX)));
+            assert_eq!(counters.in_state(Addr(0)), Some(&Counter(0)));
+        }
+    }
+
+    #[test]
+    fn iteration_orders() {
+        let mut builder = DexBuilder::new();
+        let class = builder.class("LTest;", "Ljava/lang/Object;");
+        let method = builder.method("LTest;", "run", "V", &["I"]);
+        builder.code(
+            class,
+            method,
+            ACC_PUBLIC | ACC_STATIC,
+            3,
+            1,
+            0,
+            &[
+                0x0012, // 0: const/4 v0, 0
+                0x0112, // 1: const/4 v1, 0
+                0x0238, 0x0008, // 2: if-eqz v2, +8
+                0x0238, 0x0004, // 4: if-eqz v2, +4
+                0x1112, // 6: const/4 v1, 1
+                0xfd28, // 7: goto -3
+                0x1012, // 8: const/4 v0, 1
+                0xf928, // 9: goto -7
+                0x000e, // 10: return-void
+            ],
+        );
+        let dex = builder.build_dex();
+        let repo = testing::repo(&dex);
+        let (class, method) = testing::method(&repo, "run");
+
+        let orders = [
+            IterationOrder::ReversePostorder,
+            IterationOrder::Worklist,
+            IterationOrder::Naive,
+        ];
+        let constants: Vec<Dataflow<crate::constants::State>> = orders
+            .iter()
+            .map(|order| {
+                let options = DataflowOptions::default().order(*order);
+                forward_with(method, class, &(), options).unwrap()
+            })
+            .collect();
+        let live: Vec<Dataflow<crate::liveness::LiveRegisters>> = orders
+            .iter()
+            .map(|order| {
+                let options = DataflowOptions::default().order(*order);
+                backward_with(method, class, &(), options).unwrap()
+            })
+            .collect();
+        // same fixpoints, reached with fewer visits in reverse postorder
+        for other in &constants[1..] {
+            assert_eq!(other.entries, constants[0].entries);
+            assert_eq!(other.exits, constants[0].exits);
+        }
+        for other in &live[1..] {
+            assert_eq!(other.entries, live[0].entries);
+            assert_eq!(other.exits, live[0].exits);
+        }
+        assert!(constants[0].visits < constants[2].visits);
+        assert!(live[0].visits < live[2].visits);
+    }
 }
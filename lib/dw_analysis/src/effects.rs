@@ -0,0 +1,157 @@
+//! Fields read and written by methods.
+//!
+//! This is a syntactic scan of the field access instructions of a method
+//! code, without any dataflow analysis: the summary is the same whether
+//! the instructions are reachable or not.
+
+use crate::errors::AnalysisResult;
+use crate::repo::{FieldDescr, FieldUid, Method, Repo};
+use dw_dex::instrs::Instr;
+use dw_dex::DexIndex;
+use std::collections::BTreeSet;
+
+/// The fields that a method reads or writes.
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct FieldEffects {
+    /// Fields read by `iget` and `sget` instructions.
+    pub reads: BTreeSet<FieldUid>,
+    /// Fields written by `iput` and `sput` instructions.
+    pub writes: BTreeSet<FieldUid>,
+    /// Referenced fields that cannot be resolved in the repository, either
+    /// read or written.
+    pub unresolved: BTreeSet<FieldDescr>,
+}
+
+impl FieldEffects {
+    /// Scans the field access instructions of the given method, resolving
+    /// referenced fields with [`Repo::lookup_instance_field`] and
+    /// [`Repo::lookup_static_field`]. Methods without code have no effect.
+    ///
+    /// # Errors
+    ///
+    /// This function may return an error if a field reference cannot be
+    /// read from the method dex file.
+    pub fn compute(method: &Method, repo: &Repo) -> AnalysisResult<Self> {
+        let mut effects = Self::default();
+        let Some(code) = method.code() else {
+            return Ok(effects);
+        };
+        let dex = method.dex();
+        for instr in code.read().unwrap().iter_instructions() {
+            let (field, is_static, is_write) = match instr.instr() {
+                Instr::Iget(_, _, field)
+                | Instr::IgetWide(_, _, field)
+                | Instr::IgetObject(_, _, field)
+                | Instr::IgetBoolean(_, _, field)
+                | Instr::IgetByte(_, _, field)
+                | Instr::IgetChar(_, _, field)
+                | Instr::IgetShort(_, _, field) => (field, false, false),
+                Instr::Iput(_, _, field)
+                | Instr::IputWide(_, _, field)
+                | Instr::IputObject(_, _, field)
+                | Instr::IputBoolean(_, _, field)
+                | Instr::IputByte(_, _, field)
+                | Instr::IputChar(_, _, field)
+                | Instr::IputShort(_, _, field) => (field, false, true),
+                Instr::Sget(_, field)
+                | Instr::SgetWide(_, field)
+                | Instr::SgetObject(_, field)
+                | Instr::SgetBoolean(_, field)
+                | Instr::SgetByte(_, field)
+                | Instr::SgetChar(_, field)
+                | Instr::SgetShort(_, field) => (field, true, false),
+                Instr::Sput(_, field)
+                | Instr::SputWide(_, field)
+                | Instr::SputObject(_, field)
+                | Instr::SputBoolean(_, field)
+                | Instr::SputByte(_, field)
+                | Instr::SputChar(_, field)
+                | Instr::SputShort(_, field) => (field, true, true),
+                _ => continue,
+            };
+            let descriptor = FieldDescr::try_from((dex, field.get(dex)?))?;
+            let resolved = if is_static {
+                repo.lookup_static_field(&descriptor)
+            } else {
+                repo.lookup_instance_field(&descriptor)
+            };
+            match resolved {
+                Some(field) if is_write => effects.writes.insert(field.uid()),
+                Some(field) => effects.reads.insert(field.uid()),
+                None => effects.unresolved.insert(descriptor),
+            };
+        }
+        Ok(effects)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::testing::{self, DexBuilder, ACC_PUBLIC, ACC_STATIC};
+
+    #[test]
+    fn getter_and_setter() {
+        let mut builder = DexBuilder::new();
+        let base = builder.class("LBase;", "Ljava/lang/Object;");
+        let value = builder.field("LBase;", "value", "I");
+        builder.instance_field(base, value);
+        let sub = builder.class("LSub;", "LBase;");
+        // inherited field, referenced through the subclass
+        let sub_value = builder.field("LSub;", "value", "I") as u16;
+        let unknown = builder.field("LOther;", "count", "I") as u16;
+
+        // iget v0, p0, LSub;->value:I
+        // sget v1, LOther;->count:I
+        // return v0
+        let get = builder.method("LSub;", "get", "I", &[]);
+        builder.code(
+            sub,
+            get,
+            ACC_PUBLIC,
+            3,
+            1,
+            0,
+            &[0x2052, sub_value, 0x0160, unknown, 0x000f],
+        );
+        // iput p1, p0, LSub;->value:I
+        // return-void
+        let set = builder.method("LSub;", "set", "V", &["I"]);
+        builder.code(sub, set, ACC_PUBLIC, 2, 2, 0, &[0x0159, sub_value, 0x000e]);
+        // return-void
+        let clinit = builder.method("LSub;", "<clinit>", "V", &[]);
+        builder.code(sub, clinit, ACC_STATIC, 0, 0, 0, &[0x000e]);
+        let dex = builder.build_dex();
+
+        let repo = testing::repo(&dex);
+        let effects = |name: &str| {
+            let (_, method) = testing::method(&repo, name);
+            FieldEffects::compute(method, &repo).unwrap()
+        };
+        let value = repo
+            .get_class_by_name("Base")
+            .unwrap()
+            .iter_fields(&repo)
+            .next()
+            .unwrap()
+            .uid();
+
+        let get = effects("get");
+        assert_eq!(get.reads, BTreeSet::from([value]));
+        assert!(get.writes.is_empty());
+        assert_eq!(
+            get.unresolved
+                .iter()
+                .map(ToString::to_string)
+                .collect::<Vec<_>>(),
+            ["I Other->count"]
+        );
+
+        let set = effects("set");
+        assert!(set.reads.is_empty());
+        assert_eq!(set.writes, BTreeSet::from([value]));
+        assert!(set.unresolved.is_empty());
+
+        assert_eq!(effects("<clinit>"), FieldEffects::default());
+    }
+}
@@ -3,6 +3,7 @@
 use crate::typing::errors::TypeError;
 use dw_dex::errors::DexError;
 use dw_package::errors::PackageError;
+use dw_resources::errors::ResourcesError;
 use regex::Error as RegexError;
 use std::path::PathBuf;
 use thiserror::Error;
@@ -20,6 +21,9 @@ pub enum AnalysisError {
     #[error("package error: {0}")]
     Package(#[from] PackageError),
 
+    #[error("resources error: {0}")]
+    Resources(#[from] ResourcesError),
+
     #[error("dex error: {0}")]
     Dex(#[from] DexError),
 
@@ -29,12 +33,18 @@ pub enum AnalysisError {
     #[error("class not found: {0}")]
     ClassNotFound(String),
 
+    #[error("bad method signature: {0}")]
+    BadSignature(String),
+
     #[error("instruction not found: {0}")]
     InstructionNotFound(String),
 
     #[error("the method has no implementation")]
     NoCode,
 
+    #[error("invalid register layout: {0}")]
+    RegisterLayout(String),
+
     #[error("typing error: {0}")]
     Type(#[from] TypeError),
 }
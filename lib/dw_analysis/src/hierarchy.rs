@@ -4,11 +4,11 @@ use crate::errors::{AnalysisError, AnalysisResult};
 use crate::repo::{Class, RepoCounters};
 use petgraph::dot::{Config, Dot};
 use petgraph::graph::{DiGraph, NodeIndex};
-use petgraph::visit::Dfs;
+use petgraph::visit::{Dfs, EdgeRef, Reversed};
 use petgraph::Direction;
 use std::collections::{BTreeMap, BTreeSet};
 use std::fmt;
-use Direction::Outgoing;
+use Direction::{Incoming, Outgoing};
 
 #[derive(Debug, PartialEq, Eq)]
 pub enum Inheritance {
@@ -132,6 +132,67 @@ impl<'a> Hierarchy<'a> {
         parents
     }
 
+    /// Returns the direct superclass of a class, if it is known.
+    #[must_use]
+    pub fn superclass<'b>(&'b self, class: &Class) -> Option<&'b Class<'a>> {
+        self.direct_parents(class, &Inheritance::Extends).next()
+    }
+
+    /// Returns the interfaces directly implemented by a class, in
+    /// declaration order.
+    #[must_use]
+    pub fn interfaces<'b>(&'b self, class: &Class) -> Vec<&'b Class<'a>> {
+        let mut interfaces: Vec<&Class> = self
+            .direct_parents(class, &Inheritance::Implements)
+            .collect();
+        // edges are iterated from the last inserted one
+        interfaces.reverse();
+        interfaces
+    }
+
+    /// Returns the classes and interfaces that extend or implement a class,
+    /// directly or not. The class itself is not included.
+    #[must_use]
+    pub fn all_children<'b>(&'b self, class: &Class) -> Vec<&'b Class<'a>> {
+        let Some(id) = self.node_ids.get(class.name()) else {
+            return Vec::new();
+        };
+        let reversed = Reversed(&self.inner);
+        let mut children = Vec::new();
+        let mut dfs = Dfs::new(reversed, *id);
+        while let Some(child) = dfs.next(reversed) {
+            if child != *id {
+                children.push(&self.inner[child]);
+            }
+        }
+        children
+    }
+
+    /// Returns the classes whose direct superclass is the given class.
+    #[must_use]
+    pub fn subclasses<'b>(&'b self, class: &Class) -> Vec<&'b Class<'a>> {
+        self.node_ids
+            .get(class.name())
+            .into_iter()
+            .flat_map(|id| self.inner.edges_directed(*id, Incoming))
+            .filter(|edge| *edge.weight() == Inheritance::Extends)
+            .map(|edge| &self.inner[edge.source()])
+            .collect()
+    }
+
+    fn direct_parents<'b>(
+        &'b self,
+        class: &Class,
+        link: &'b Inheritance,
+    ) -> impl Iterator<Item = &'b Class<'a>> {
+        self.node_ids
+            .get(class.name())
+            .into_iter()
+            .flat_map(move |id| self.inner.edges(*id))
+            .filter(move |edge| edge.weight() == link)
+            .map(|edge| &self.inner[edge.target()])
+    }
+
     #[must_use]
     pub fn to_dot(&self) -> String {
         format!(
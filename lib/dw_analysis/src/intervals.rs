@@ -0,0 +1,613 @@
+//! Integer intervals pass.
+//!
+//! This forward dataflow pass keeps, for each register, an interval of
+//! the 32 bits integer values it may hold. Intervals are computed from
+//! literal operands and propagated through `move*` instructions, simple
+//! arithmetic (additions, subtractions and multiplications) and
+//! conditional branches. Since the interval domain has infinite height,
+//! states are widened at loop headers (then narrowed, see
+//! [`AbstractForwardState::widen`]).
+//!
+//! Registers holding other kinds of values (references, wide values,
+//! floats) are given the full range of 32 bits integers.
+
+use crate::controlflow::{Branch, Comp, Operand};
+use crate::dataflow;
+use crate::dataflow::{AbstractForwardState, Dataflow};
+use crate::errors::{AnalysisError, AnalysisResult};
+use crate::repo::{Class, Method};
+use dw_dex::instrs::Instr;
+use dw_dex::registers::Reg;
+use dw_dex::{Addr, Dex};
+use std::fmt;
+
+/// Result of the intervals pass.
+///
+/// Contains integer intervals of registers at entries and exits of every
+/// instruction of the analyzed method.
+pub type Intervals = Dataflow<State>;
+
+impl Intervals {
+    /// Runs the intervals pass onto given method and returns results of
+    /// the dataflow analysis.
+    ///
+    /// # Errors
+    ///
+    /// This function may generate errors if the method has no code.
+    pub fn compute(method: &Method, class: &Class) -> AnalysisResult<Self> {
+        dataflow::forward(method, class, &())
+    }
+
+    /// Returns the bounds (both included) of the values held by `reg` right
+    /// before the instruction at `pc`, or `None` if the instruction has not
+    /// been reached or if nothing is known about the register.
+    #[must_use]
+    pub fn range_at(&self, pc: Addr, reg: Reg) -> Option<(i64, i64)> {
+        let interval = self.in_state(pc)?.read_reg(reg).ok()?;
+        (interval != Interval::TOP).then_some((interval.lo, interval.hi))
+    }
+}
+
+/// A non-empty interval of 32 bits integers, bounds included.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Interval {
+    pub lo: i64,
+    pub hi: i64,
+}
+
+impl fmt::Display for Interval {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        if *self == Self::TOP {
+            write!(f, "?")
+        } else {
+            write!(f, "[{}, {}]", self.lo, self.hi)
+        }
+    }
+}
+
+impl Interval {
+    /// The interval of all 32 bits integers.
+    pub const TOP: Self = Self {
+        lo: i32::MIN as i64,
+        hi: i32::MAX as i64,
+    };
+
+    #[must_use]
+    pub const fn singleton(v: i64) -> Self {
+        Self { lo: v, hi: v }
+    }
+
+    // Results that may overflow are not tracked.
+    fn checked(lo: i64, hi: i64) -> Self {
+        if lo < Self::TOP.lo || hi > Self::TOP.hi {
+            Self::TOP
+        } else {
+            Self { lo, hi }
+        }
+    }
+
+    fn hull(self, other: Self) -> Self {
+        Self {
+            lo: self.lo.min(other.lo),
+            hi: self.hi.max(other.hi),
+        }
+    }
+
+    fn widen(self, next: Self) -> Self {
+        Self {
+            lo: if next.lo < self.lo {
+                Self::TOP.lo
+            } else {
+                self.lo
+            },
+            hi: if next.hi > self.hi {
+                Self::TOP.hi
+            } else {
+                self.hi
+            },
+        }
+    }
+
+    fn add(self, other: Self) -> Self {
+        Self::checked(self.lo + other.lo, self.hi + other.hi)
+    }
+
+    fn sub(self, other: Self) -> Self {
+        Self::checked(self.lo - other.hi, self.hi - other.lo)
+    }
+
+    fn mul(self, other: Self) -> Self {
+        let products = [
+            self.lo * other.lo,
+            self.lo * other.hi,
+            self.hi * other.lo,
+            self.hi * other.hi,
+        ];
+        Self::checked(
+            *products.iter().min().unwrap(),
+            *products.iter().max().unwrap(),
+        )
+    }
+
+    /// Restricts `self` to the values `x` such that `x comp y` holds for
+    /// some `y` in `other`. Returns `None` if there is no such value.
+    fn refine(self, comp: Comp, other: Self) -> Option<Self> {
+        let (lo, hi) = match comp {
+            Comp::Eq => (self.lo.max(other.lo), self.hi.min(other.hi)),
+            Comp::Ne if other.lo == other.hi && self.lo == other.lo => (self.lo + 1, self.hi),
+            Comp::Ne if other.lo == other.hi && self.hi == other.lo => (self.lo, self.hi - 1),
+            Comp::Ne => (self.lo, self.hi),
+            Comp::Lt => (self.lo, self.hi.min(other.hi - 1)),
+            Comp::Le => (self.lo, self.hi.min(other.hi)),
+            Comp::Gt => (self.lo.max(other.lo + 1), self.hi),
+            Comp::Ge => (self.lo.max(other.lo), self.hi),
+        };
+        (lo <= hi).then_some(Self { lo, hi })
+    }
+}
+
+const fn negate(comp: Comp) -> Comp {
+    match comp {
+        Comp::Eq => Comp::Ne,
+        Comp::Ne => Comp::Eq,
+        Comp::Lt => Comp::Ge,
+        Comp::Ge => Comp::Lt,
+        Comp::Gt => Comp::Le,
+        Comp::Le => Comp::Gt,
+    }
+}
+
+// Gives the comparison of swapped operands.
+const fn swap(comp: Comp) -> Comp {
+    match comp {
+        Comp::Eq => Comp::Eq,
+        Comp::Ne => Comp::Ne,
+        Comp::Lt => Comp::Gt,
+        Comp::Ge => Comp::Le,
+        Comp::Gt => Comp::Lt,
+        Comp::Le => Comp::Ge,
+    }
+}
+
+/// The abstract state for the intervals pass.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct State {
+    registers: Vec<Interval>,
+}
+
+impl fmt::Display for State {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        for (i, interval) in self.registers.iter().enumerate() {
+            write!(f, "    v{i}: {interval}")?;
+        }
+        Ok(())
+    }
+}
+
+impl State {
+    /// Returns the interval of the values held by the rth register.
+    ///
+    /// # Errors
+    ///
+    /// This function may return an out of bounds error.
+    pub fn read_reg(&self, r: Reg) -> AnalysisResult<Interval> {
+        self.registers
+            .get(r.value() as usize)
+            .copied()
+            .ok_or_else(|| out_of_bounds(r))
+    }
+
+    fn write_reg(&mut self, r: Reg, interval: Interval) -> AnalysisResult<()> {
+        let reg = self
+            .registers
+            .get_mut(r.value() as usize)
+            .ok_or_else(|| out_of_bounds(r))?;
+        *reg = interval;
+        Ok(())
+    }
+
+    fn write_pair(&mut self, r: Reg) -> AnalysisResult<()> {
+        self.write_reg(r, Interval::TOP)?;
+        self.write_reg(r.next(), Interval::TOP)
+    }
+
+    fn binop(
+        &mut self,
+        dst: Reg,
+        a: Reg,
+        b: Interval,
+        op: fn(Interval, Interval) -> Interval,
+    ) -> AnalysisResult<()> {
+        let a = self.read_reg(a)?;
+        self.write_reg(dst, op(a, b))
+    }
+}
+
+fn out_of_bounds(r: Reg) -> AnalysisError {
+    AnalysisError::Internal(format!("register {r} out of bounds"))
+}
+
+impl<'a> AbstractForwardState<'a> for State {
+    type Context<'c> = ();
+    type Error = AnalysisError;
+
+    fn init(method: &Method, _class: &Class) -> AnalysisResult<Self> {
+        let code = method.code().ok_or(AnalysisError::NoCode)?;
+        let registers_size = code.read().unwrap().registers_size();
+        Ok(Self {
+            registers: vec![Interval::TOP; registers_size],
+        })
+    }
+
+    fn join(&mut self, other: &Self, _ctx: &()) -> AnalysisResult<()> {
+        if self.registers.len() != other.registers.len() {
+            return Err(AnalysisError::Internal(
+                "cannot join states of different sizes".to_string(),
+            ));
+        }
+        for (r1, r2) in self.registers.iter_mut().zip(other.registers.iter()) {
+            *r1 = r1.hull(*r2);
+        }
+        Ok(())
+    }
+
+    fn widen(&mut self, previous: &Self, _iteration: usize, _ctx: &()) -> AnalysisResult<()> {
+        for (r1, r2) in self.registers.iter_mut().zip(previous.registers.iter()) {
+            *r1 = r2.widen(*r1);
+        }
+        Ok(())
+    }
+
+    fn transfer_branch(&mut self, branch: &Branch, _ctx: &()) -> AnalysisResult<()> {
+        let (a, comp, b) = match branch {
+            Branch::IfTrue(a, comp, b) => (*a, *comp, *b),
+            Branch::IfFalse(a, comp, b) => (*a, negate(*comp), *b),
+            Branch::Switch(a, key) => {
+                let a_interval = self.read_reg(*a)?;
+                let key = Interval::singleton(i64::from(*key));
+                if let Some(refined) = a_interval.refine(Comp::Eq, key) {
+                    self.write_reg(*a, refined)?;
+                }
+                return Ok(());
+            }
+            _ => return Ok(()),
+        };
+        let a_interval = self.read_reg(a)?;
+        let b_interval = match b {
+            Operand::Register(b) => self.read_reg(b)?,
+            Operand::Zero => Interval::singleton(0),
+        };
+        // infeasible branches are kept unrefined
+        if let Some(refined) = a_interval.refine(comp, b_interval) {
+            self.write_reg(a, refined)?;
+        }
+        if let Operand::Register(b) = b {
+            if let Some(refined) = b_interval.refine(swap(comp), a_interval) {
+                self.write_reg(b, refined)?;
+            }
+        }
+        Ok(())
+    }
+
+    fn transfer_instr(&mut self, instr: &Instr, _dex: &Dex, _ctx: &()) -> AnalysisResult<()> {
+        match instr {
+            Instr::Move(a, b) | Instr::MoveFrom16(a, b) | Instr::Move16(a, b) => {
+                let interval = self.read_reg(*b)?;
+                self.write_reg(*a, interval)
+            }
+
+            Instr::Const4(a, v) => self.write_reg(*a, Interval::singleton(i64::from(*v))),
+            Instr::Const16(a, v) => self.write_reg(*a, Interval::singleton(i64::from(*v))),
+            Instr::Const(a, v) => self.write_reg(*a, Interval::singleton(i64::from(*v))),
+            Instr::ConstHigh16(a, v) => {
+                self.write_reg(*a, Interval::singleton(i64::from(i32::from(*v) << 16)))
+            }
+
+            Instr::AddInt(a, b, c) => {
+                let c = self.read_reg(*c)?;
+                self.binop(*a, *b, c, Interval::add)
+            }
+            Instr::SubInt(a, b, c) => {
+                let c = self.read_reg(*c)?;
+                self.binop(*a, *b, c, Interval::sub)
+            }
+            Instr::MulInt(a, b, c) => {
+                let c = self.read_reg(*c)?;
+                self.binop(*a, *b, c, Interval::mul)
+            }
+            Instr::AddInt2addr(a, b) => {
+                let b = self.read_reg(*b)?;
+                self.binop(*a, *a, b, Interval::add)
+            }
+            Instr::SubInt2addr(a, b) => {
+                let b = self.read_reg(*b)?;
+                self.binop(*a, *a, b, Interval::sub)
+            }
+            Instr::MulInt2addr(a, b) => {
+                let b = self.read_reg(*b)?;
+                self.binop(*a, *a, b, Interval::mul)
+            }
+            Instr::AddIntLit16(a, b, v) => {
+                self.binop(*a, *b, Interval::singleton(i64::from(*v)), Interval::add)
+            }
+            Instr::MulIntLit16(a, b, v) => {
+                self.binop(*a, *b, Interval::singleton(i64::from(*v)), Interval::mul)
+            }
+            Instr::AddIntLit8(a, b, v) => {
+                self.binop(*a, *b, Interval::singleton(i64::from(*v)), Interval::add)
+            }
+            Instr::MulIntLit8(a, b, v) => {
+                self.binop(*a, *b, Interval::singleton(i64::from(*v)), Interval::mul)
+            }
+            Instr::RsubInt(a, b, v) => {
+                let b = self.read_reg(*b)?;
+                self.write_reg(*a, Interval::singleton(i64::from(*v)).sub(b))
+            }
+            Instr::RsubIntLit8(a, b, v) => {
+                let b = self.read_reg(*b)?;
+                self.write_reg(*a, Interval::singleton(i64::from(*v)).sub(b))
+            }
+            Instr::IntToByte(a, _) => self.write_reg(
+                *a,
+                Interval {
+                    lo: i64::from(i8::MIN),
+                    hi: i64::from(i8::MAX),
+                },
+            ),
+            Instr::IntToShort(a, _) => self.write_reg(
+                *a,
+                Interval {
+                    lo: i64::from(i16::MIN),
+                    hi: i64::from(i16::MAX),
+                },
+            ),
+            Instr::IntToChar(a, _) => self.write_reg(
+                *a,
+                Interval {
+                    lo: 0,
+                    hi: i64::from(u16::MAX),
+                },
+            ),
+            Instr::ArrayLength(a, _) => self.write_reg(
+                *a,
+                Interval {
+                    lo: 0,
+                    hi: Interval::TOP.hi,
+                },
+            ),
+
+            Instr::MoveObject(a, _)
+            | Instr::MoveObjectFrom16(a, _)
+            | Instr::MoveObject16(a, _)
+            | Instr::MoveResult(a)
+            | Instr::MoveResultObject(a)
+            | Instr::MoveException(a)
+            | Instr::ConstString(a, _)
+            | Instr::ConstStringJumbo(a, _)
+            | Instr::ConstClass(a, _)
+            | Instr::ConstMethodHandle(a, _)
+            | Instr::ConstMethodType(a, _)
+            | Instr::InstanceOf(a, _, _)
+            | Instr::NewInstance(a, _)
+            | Instr::NewArray(a, _, _)
+            | Instr::CmplFloat(a, _, _)
+            | Instr::CmpgFloat(a, _, _)
+            | Instr::CmplDouble(a, _, _)
+            | Instr::CmpgDouble(a, _, _)
+            | Instr::CmpLong(a, _, _)
+            | Instr::Aget(a, _, _)
+            | Instr::AgetObject(a, _, _)
+            | Instr::AgetBoolean(a, _, _)
+            | Instr::AgetByte(a, _, _)
+            | Instr::AgetChar(a, _, _)
+            | Instr::AgetShort(a, _, _)
+            | Instr::Iget(a, _, _)
+            | Instr::IgetObject(a, _, _)
+            | Instr::IgetBoolean(a, _, _)
+            | Instr::IgetByte(a, _, _)
+            | Instr::IgetChar(a, _, _)
+            | Instr::IgetShort(a, _, _)
+            | Instr::Sget(a, _)
+            | Instr::SgetObject(a, _)
+            | Instr::SgetBoolean(a, _)
+            | Instr::SgetByte(a, _)
+            | Instr::SgetChar(a, _)
+            | Instr::SgetShort(a, _)
+            | Instr::NegInt(a, _)
+            | Instr::NotInt(a, _)
+            | Instr::NegFloat(a, _)
+            | Instr::IntToFloat(a, _)
+            | Instr::LongToInt(a, _)
+            | Instr::LongToFloat(a, _)
+            | Instr::FloatToInt(a, _)
+            | Instr::DoubleToInt(a, _)
+            | Instr::DoubleToFloat(a, _)
+            | Instr::DivInt(a, _, _)
+            | Instr::RemInt(a, _, _)
+            | Instr::AndInt(a, _, _)
+            | Instr::OrInt(a, _, _)
+            | Instr::XorInt(a, _, _)
+            | Instr::ShlInt(a, _, _)
+            | Instr::ShrInt(a, _, _)
+            | Instr::UshrInt(a, _, _)
+            | Instr::AddFloat(a, _, _)
+            | Instr::SubFloat(a, _, _)
+            | Instr::MulFloat(a, _, _)
+            | Instr::DivFloat(a, _, _)
+            | Instr::RemFloat(a, _, _)
+            | Instr::DivInt2addr(a, _)
+            | Instr::RemInt2addr(a, _)
+            | Instr::AndInt2addr(a, _)
+            | Instr::OrInt2addr(a, _)
+            | Instr::XorInt2addr(a, _)
+            | Instr::ShlInt2addr(a, _)
+            | Instr::ShrInt2addr(a, _)
+            | Instr::UshrInt2addr(a, _)
+            | Instr::AddFloat2addr(a, _)
+            | Instr::SubFloat2addr(a, _)
+            | Instr::MulFloat2addr(a, _)
+            | Instr::DivFloat2addr(a, _)
+            | Instr::RemFloat2addr(a, _)
+            | Instr::DivIntLit16(a, _, _)
+            | Instr::RemIntLit16(a, _, _)
+            | Instr::AndIntLit16(a, _, _)
+            | Instr::OrIntLit16(a, _, _)
+            | Instr::XorIntLit16(a, _, _)
+            | Instr::DivIntLit8(a, _, _)
+            | Instr::RemIntLit8(a, _, _)
+            | Instr::AndIntLit8(a, _, _)
+            | Instr::OrIntLit8(a, _, _)
+            | Instr::XorIntLit8(a, _, _)
+            | Instr::ShlIntLit8(a, _, _)
+            | Instr::ShrIntLit8(a, _, _)
+            | Instr::UshrIntLit8(a, _, _) => self.write_reg(*a, Interval::TOP),
+
+            Instr::MoveWide(a, _)
+            | Instr::MoveWideFrom16(a, _)
+            | Instr::MoveWide16(a, _)
+            | Instr::ConstWide16(a, _)
+            | Instr::ConstWide32(a, _)
+            | Instr::ConstWide(a, _)
+            | Instr::ConstWideHigh16(a, _)
+            | Instr::MoveResultWide(a)
+            | Instr::AgetWide(a, _, _)
+            | Instr::IgetWide(a, _, _)
+            | Instr::SgetWide(a, _)
+            | Instr::NegLong(a, _)
+            | Instr::NotLong(a, _)
+            | Instr::NegDouble(a, _)
+            | Instr::IntToLong(a, _)
+            | Instr::IntToDouble(a, _)
+            | Instr::LongToDouble(a, _)
+            | Instr::FloatToLong(a, _)
+            | Instr::FloatToDouble(a, _)
+            | Instr::DoubleToLong(a, _)
+            | Instr::AddLong(a, _, _)
+            | Instr::SubLong(a, _, _)
+            | Instr::MulLong(a, _, _)
+            | Instr::DivLong(a, _, _)
+            | Instr::RemLong(a, _, _)
+            | Instr::AndLong(a, _, _)
+            | Instr::OrLong(a, _, _)
+            | Instr::XorLong(a, _, _)
+            | Instr::ShlLong(a, _, _)
+            | Instr::ShrLong(a, _, _)
+            | Instr::UshrLong(a, _, _)
+            | Instr::AddDouble(a, _, _)
+            | Instr::SubDouble(a, _, _)
+            | Instr::MulDouble(a, _, _)
+            | Instr::DivDouble(a, _, _)
+            | Instr::RemDouble(a, _, _)
+            | Instr::AddLong2addr(a, _)
+            | Instr::SubLong2addr(a, _)
+            | Instr::MulLong2addr(a, _)
+            | Instr::DivLong2addr(a, _)
+            | Instr::RemLong2addr(a, _)
+            | Instr::AndLong2addr(a, _)
+            | Instr::OrLong2addr(a, _)
+            | Instr::XorLong2addr(a, _)
+            | Instr::ShlLong2addr(a, _)
+            | Instr::ShrLong2addr(a, _)
+            | Instr::UshrLong2addr(a, _)
+            | Instr::AddDouble2addr(a, _)
+            | Instr::SubDouble2addr(a, _)
+            | Instr::MulDouble2addr(a, _)
+            | Instr::DivDouble2addr(a, _)
+            | Instr::RemDouble2addr(a, _) => self.write_pair(*a),
+
+            // other instructions do not define any register
+            _ => Ok(()),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::testing::{self, DexBuilder, ACC_PUBLIC, ACC_STATIC};
+
+    fn compute(insns: &[u16], registers_size: u16, ins: u16) -> Vec<Option<(i64, i64)>> {
+        let mut builder = DexBuilder::new();
+        let class = builder.class("LTest;", "Ljava/lang/Object;");
+        let method = builder.method("LTest;", "run", "V", &["I"]);
+        builder.code(
+            class,
+            method,
+            ACC_PUBLIC | ACC_STATIC,
+            registers_size,
+            ins,
+            0,
+            insns,
+        );
+        let dex = builder.build_dex();
+        let repo = testing::repo(&dex);
+        let (class, method) = testing::method(&repo, "run");
+
+        let intervals = Intervals::compute(method, class).unwrap();
+        // v0 at every instruction, then other registers at the last one
+        let mut ranges: Vec<_> = intervals
+            .entries
+            .keys()
+            .map(|addr| intervals.range_at(*addr, Reg::from(0u8)))
+            .collect();
+        let last = *intervals.entries.keys().last().unwrap();
+        for r in 1..registers_size {
+            ranges.push(intervals.range_at(last, Reg::from(r as u8)));
+        }
+        ranges
+    }
+
+    #[test]
+    fn straight_line() {
+        let ranges = compute(
+            &[
+                0x3012, // 0: const/4 v0, 3
+                0x01da, 0x0400, // 1: mul-int/lit8 v1, v0, 4
+                0x01b0, // 3: add-int/2addr v1, v0
+                0x000e, // 4: return-void
+            ],
+            3,
+            1,
+        );
+        assert_eq!(
+            ranges,
+            vec![
+                None,
+                Some((3, 3)),
+                Some((3, 3)),
+                Some((3, 3)),
+                Some((15, 15)),
+                None,
+            ]
+        );
+    }
+
+    #[test]
+    fn counted_loop() {
+        let ranges = compute(
+            &[
+                0x0012, // 0: const/4 v0, 0
+                0x0113, 0x000a, // 1: const/16 v1, 10
+                0x1035, 0x0005, // 3: if-ge v0, v1, +5
+                0x00d8, 0x0100, // 5: add-int/lit8 v0, v0, 1
+                0xfc28, // 7: goto -4
+                0x000e, // 8: return-void
+            ],
+            3,
+            1,
+        );
+        assert_eq!(
+            ranges,
+            vec![
+                None,
+                Some((0, 0)),
+                Some((0, 10)),
+                Some((0, 9)),
+                Some((1, 10)),
+                Some((10, 10)),
+                Some((10, 10)),
+                None,
+            ]
+        );
+    }
+}
@@ -1,16 +1,28 @@
 //! This crate provides Android application bytecode analysis algorithm for
 //! the `DroidWorks` project.
 
+pub mod apis;
+pub mod audit;
 pub mod callgraph;
+pub mod constants;
 pub mod controlflow;
 pub mod dataflow;
+pub mod effects;
 pub mod errors;
 pub mod hierarchy;
+pub mod intervals;
+pub mod liveness;
 pub mod repo;
 pub mod stats;
 pub mod typing;
 
+#[cfg(test)]
+mod testing;
+
 use crate::errors::AnalysisResult;
+use std::collections::BTreeMap;
+
+pub use apis::SENSITIVE_APIS;
 
 pub fn forward_typecheck(
     method: &repo::Method,
@@ -20,6 +32,38 @@ pub fn forward_typecheck(
     typing::Types::forward_compute(method, class, repo)
 }
 
+pub fn constants(
+    method: &repo::Method,
+    class: &repo::Class,
+) -> AnalysisResult<constants::Constants> {
+    constants::Constants::compute(method, class)
+}
+
+pub fn field_effects(
+    method: &repo::Method,
+    repo: &repo::Repo,
+) -> AnalysisResult<effects::FieldEffects> {
+    effects::FieldEffects::compute(method, repo)
+}
+
+pub fn api_usage(
+    repo: &repo::Repo,
+    patterns: &[repo::MethodDescr],
+) -> AnalysisResult<BTreeMap<repo::MethodDescr, Vec<(repo::MethodUid, dw_dex::Addr)>>> {
+    apis::usage(repo, patterns)
+}
+
+pub fn intervals(
+    method: &repo::Method,
+    class: &repo::Class,
+) -> AnalysisResult<intervals::Intervals> {
+    intervals::Intervals::compute(method, class)
+}
+
+pub fn liveness(method: &repo::Method, class: &repo::Class) -> AnalysisResult<liveness::Liveness> {
+    liveness::Liveness::compute(method, class)
+}
+
 pub fn backward_typecheck(
     method: &repo::Method,
     class: &repo::Class,
@@ -0,0 +1,203 @@
+//! Registers liveness pass.
+//!
+//! This backward dataflow pass computes, for each instruction, the set of
+//! registers whose current value may be read later on. A register is live
+//! before an instruction if it is read by the instruction, or if it is
+//! live after the instruction and not overwritten by it. Sets coming from
+//! several successors are merged by union.
+//!
+//! Wide values (longs and doubles) are held by pairs of registers, both
+//! registers of a pair are read or written together.
+
+use crate::controlflow::Branch;
+use crate::dataflow;
+use crate::dataflow::{AbstractBackwardState, Dataflow};
+use crate::errors::{AnalysisError, AnalysisResult};
+use crate::repo::{Class, Method};
+use dw_dex::instrs::{Instr, Operands};
+use dw_dex::registers::Reg;
+use dw_dex::{Addr, Dex};
+use fixedbitset::FixedBitSet;
+use std::fmt;
+
+/// Result of the liveness pass.
+///
+/// Contains live registers at entries and exits of every instruction of
+/// the analyzed method.
+pub type Liveness = Dataflow<LiveRegisters>;
+
+impl Liveness {
+    /// Runs the liveness pass onto given method and returns results of
+    /// the dataflow analysis.
+    ///
+    /// # Errors
+    ///
+    /// This function may generate errors if the method has no code, or if
+    /// an instruction references a register out of the method frame.
+    pub fn compute(method: &Method, class: &Class) -> AnalysisResult<Self> {
+        dataflow::backward(method, class, &())
+    }
+
+    /// Returns the registers that are live right before the instruction at
+    /// `pc`, or `None` if the instruction has not been reached.
+    #[must_use]
+    pub fn live_at(&self, pc: Addr) -> Option<&LiveRegisters> {
+        self.in_state(pc)
+    }
+
+    /// Returns the maximum number of registers that are simultaneously live
+    /// at some point of the method.
+    #[must_use]
+    pub fn max_simultaneous(&self) -> usize {
+        self.entries
+            .values()
+            .chain(self.exits.values())
+            .map(LiveRegisters::len)
+            .max()
+            .unwrap_or(0)
+    }
+}
+
+/// The abstract state for the liveness pass: a set of registers.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct LiveRegisters(FixedBitSet);
+
+impl fmt::Display for LiveRegisters {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "    live: {{")?;
+        for (i, r) in self.iter().enumerate() {
+            if i > 0 {
+                write!(f, ", ")?;
+            }
+            write!(f, "{r}")?;
+        }
+        write!(f, "}}")
+    }
+}
+
+impl LiveRegisters {
+    /// Checks whether the rth register is live.
+    #[must_use]
+    pub fn contains(&self, r: Reg) -> bool {
+        self.0.contains(r.value() as usize)
+    }
+
+    /// Returns the number of live registers.
+    #[must_use]
+    pub fn len(&self) -> usize {
+        self.0.count_ones(..)
+    }
+
+    /// Checks whether no register is live.
+    #[must_use]
+    pub fn is_empty(&self) -> bool {
+        self.len() == 0
+    }
+
+    /// Returns an iterator over the live registers, in increasing order.
+    pub fn iter(&self) -> impl Iterator<Item = Reg> + '_ {
+        self.0.ones().map(|i| Reg::from(i as u16))
+    }
+
+    fn set(&mut self, r: Reg, live: bool) -> AnalysisResult<()> {
+        let i = r.value() as usize;
+        if i >= self.0.len() {
+            return Err(AnalysisError::Internal(format!(
+                "register {r} out of bounds"
+            )));
+        }
+        self.0.set(i, live);
+        Ok(())
+    }
+}
+
+impl<'a> AbstractBackwardState<'a> for LiveRegisters {
+    type Context<'c> = ();
+    type Error = AnalysisError;
+
+    fn init(method: &Method, _class: &Class) -> AnalysisResult<Self> {
+        let code = method.code().ok_or(AnalysisError::NoCode)?;
+        let registers_size = code.read().unwrap().registers_size();
+        Ok(Self(FixedBitSet::with_capacity(registers_size)))
+    }
+
+    fn meet(&mut self, other: &Self, _ctx: &()) -> AnalysisResult<()> {
+        if self.0.len() != other.0.len() {
+            return Err(AnalysisError::Internal(
+                "cannot meet states of different sizes".to_string(),
+            ));
+        }
+        self.0.union_with(&other.0);
+        Ok(())
+    }
+
+    fn transfer_branch(&mut self, _branch: &Branch, _ctx: &()) -> AnalysisResult<()> {
+        Ok(())
+    }
+
+    fn transfer_instr(&mut self, instr: &Instr, _dex: &Dex, _ctx: &()) -> AnalysisResult<()> {
+        for r in instr.dst_registers() {
+            self.set(r, false)?;
+        }
+        for r in instr.src_registers() {
+            self.set(r, true)?;
+        }
+        Ok(())
+    }
+
+    fn entry_reached(&self, _class: &Class, _method: &Method, _ctx: &()) -> AnalysisResult<()> {
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::testing::{self, DexBuilder, ACC_PUBLIC, ACC_STATIC};
+
+    #[test]
+    fn unused_register_is_dead() {
+        let mut builder = DexBuilder::new();
+        let class = builder.class("LTest;", "Ljava/lang/Object;");
+        let method = builder.method("LTest;", "run", "I", &["I"]);
+        builder.code(
+            class,
+            method,
+            ACC_PUBLIC | ACC_STATIC,
+            3,
+            1,
+            0,
+            &[
+                0x1012, // 0: const/4 v0, 1
+                0x2112, // 1: const/4 v1, 2
+                0x0238, 0x0003, // 2: if-eqz v2, +3
+                0x3112, // 4: const/4 v1, 3
+                0x010f, // 5: return v1
+            ],
+        );
+        let dex = builder.build_dex();
+        let repo = testing::repo(&dex);
+        let (class, method) = testing::method(&repo, "run");
+
+        let liveness = Liveness::compute(method, class).unwrap();
+        let live = |addr| -> Vec<u16> {
+            liveness
+                .live_at(Addr(addr))
+                .unwrap()
+                .iter()
+                .map(|r| r.value())
+                .collect()
+        };
+        assert_eq!(live(0), [2]);
+        assert_eq!(live(1), [2]);
+        assert_eq!(live(2), [1, 2]);
+        assert_eq!(live(4), [] as [u16; 0]);
+        assert_eq!(live(5), [1]);
+        assert!(liveness
+            .entries
+            .values()
+            .chain(liveness.exits.values())
+            .all(|state| !state.contains(Reg::from(0u8))));
+        assert_eq!(liveness.max_simultaneous(), 2);
+    }
+}
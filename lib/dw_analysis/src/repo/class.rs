@@ -1,6 +1,6 @@
 use crate::errors::AnalysisResult;
 use crate::repo::*;
-use crate::stats::is_stub;
+use crate::stats::{self, StubReason};
 use dw_dex::classes::{ClassDefItem, ClassFlags};
 use dw_dex::types::Type;
 use dw_dex::{Dex, DexCollection, DexIndex, Index};
@@ -170,15 +170,39 @@ impl<'a> Class<'a> {
         self.system
     }
 
-    /// Estimate if the class contains stub. Heuristics rely on the presence
-    /// of the 'Stub!' string and on the instruction counts (small methods).
+    /// Returns the name of the source file the class has been compiled
+    /// from, if recorded in its definition.
+    ///
+    /// # Errors
+    ///
+    /// This function may return an error if the file name cannot be read
+    /// from the class dex file.
+    pub fn source_file(&self) -> AnalysisResult<Option<String>> {
+        match self.dex_content {
+            Some((content, dex)) => Ok(content.get(dex)?.source_file(dex)?),
+            None => Ok(None),
+        }
+    }
+
+    /// Estimate if the class contains stub, that is if one of its methods
+    /// only throws an exception with the `"Stub!"` message, as the methods
+    /// of the Android SDK jars do (see [`Method::is_stub`]).
     pub fn has_stub_code(&self, repo: &'a Repo) -> AnalysisResult<bool> {
-        let Some((_, dex)) = self.dex_content else {
-            return Ok(false)
-        };
-        for method in self.iter_methods(repo).filter(|m| m.code().is_some()) {
-            let code = method.code().unwrap();
-            if is_stub(&code.read().unwrap(), dex)? {
+        for method in self.iter_methods(repo) {
+            if method.is_stub(repo)? == Some(StubReason::ThrowOnly)
+                && stats::loads_sdk_stub_message(method)?
+            {
+                return Ok(true);
+            }
+        }
+        Ok(false)
+    }
+
+    /// Returns whether one of the class methods only throws an exception,
+    /// whatever its message (see [`StubReason::ThrowOnly`]).
+    pub fn has_throw_only_code(&self, repo: &'a Repo) -> AnalysisResult<bool> {
+        for method in self.iter_methods(repo) {
+            if method.is_stub(repo)? == Some(StubReason::ThrowOnly) {
                 return Ok(true);
             }
         }
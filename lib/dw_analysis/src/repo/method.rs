@@ -1,10 +1,13 @@
 use crate::errors::{AnalysisError, AnalysisResult};
-use crate::repo::MethodUid;
+use crate::repo::{MethodUid, Repo};
+use crate::stats::{self, StubReason};
 use dw_dex::code::CodeItem;
 use dw_dex::methods::{EncodedMethod, MethodFlags, MethodIdItem};
-use dw_dex::types::Type;
+use dw_dex::registers::Reg;
+use dw_dex::types::{ProtoDescr, Type};
 use dw_dex::Dex;
 use std::fmt;
+use std::str::FromStr;
 use std::sync::RwLock;
 
 /// The enriched method definition.
@@ -75,6 +78,63 @@ impl<'a> Method<'a> {
         self.content.code(self.dex).ok().flatten()
     }
 
+    /// Returns the lowest and highest source line numbers of the method
+    /// instructions, as found in its debug information. Methods without
+    /// code or line numbers have no range.
+    ///
+    /// # Errors
+    ///
+    /// This function may return an error if the debug information cannot
+    /// be read from the method dex file.
+    pub fn line_range(&self) -> AnalysisResult<Option<(u32, u32)>> {
+        let Some(code) = self.code() else {
+            return Ok(None);
+        };
+        let code = code.read().unwrap();
+        let Some(debug_info) = code.debug_info(self.dex)? else {
+            return Ok(None);
+        };
+        Ok(debug_info
+            .iter_line_entries()
+            .fold(None, |range, (_, line)| match range {
+                Some((min, max)) => Some((line.min(min), line.max(max))),
+                None => Some((line, line)),
+            }))
+    }
+
+    /// Hashes the method body in a form that does not depend on register
+    /// allocation nor on the dex file layout: registers are renumbered in
+    /// order of first use, and string, type, field and method indices are
+    /// replaced by the values they refer to. Methods without code have the
+    /// fingerprint of an empty body.
+    ///
+    /// Fingerprints are 64-bit FNV-1a hashes, hence are stable across
+    /// builds and platforms.
+    ///
+    /// # Errors
+    ///
+    /// This function may return an error if an index or a handler
+    /// referenced by the code cannot be resolved in the method dex file.
+    pub fn normalized_fingerprint(&self) -> AnalysisResult<u64> {
+        const FNV_OFFSET_BASIS: u64 = 0xcbf2_9ce4_8422_2325;
+        const FNV_PRIME: u64 = 0x0100_0000_01b3;
+
+        let body = self.normalized_body()?.join("\n");
+        Ok(body.bytes().fold(FNV_OFFSET_BASIS, |hash, byte| {
+            (hash ^ u64::from(byte)).wrapping_mul(FNV_PRIME)
+        }))
+    }
+
+    /// Returns the body of the method in the normalized form of
+    /// [`CodeItem::normalized_body`], one line per instruction then one
+    /// line per try item.
+    pub(crate) fn normalized_body(&self) -> AnalysisResult<Vec<String>> {
+        let Some(code) = self.code() else {
+            return Ok(Vec::new());
+        };
+        Ok(code.read().unwrap().normalized_body(self.dex)?)
+    }
+
     #[inline]
     #[must_use]
     pub const fn is_public(&self) -> bool {
@@ -160,10 +220,114 @@ impl<'a> Method<'a> {
             .flags()
             .contains(MethodFlags::ACC_DECLARED_SYNCHRONIZED)
     }
+
+    /// Returns why the method looks like a stub, or [`None`] if its body
+    /// does real work. Calls to a superclass constructor are ignored, so
+    /// that constructors can be stubs too. Methods without code are not
+    /// stubs.
+    ///
+    /// # Errors
+    ///
+    /// This function may return an error if a method reference cannot be
+    /// read from the method dex file.
+    pub fn is_stub(&self, repo: &Repo) -> AnalysisResult<Option<StubReason>> {
+        stats::stub_reason(self, repo)
+    }
+
+    /// Checks that the method registers can hold its parameters (and the
+    /// `this` reference for non-static methods), as assumed by the analyses
+    /// that map parameters to the last registers of the frame.
+    /// Methods without code are always considered valid.
+    pub fn validate_register_layout(&self) -> AnalysisResult<()> {
+        let Some(code) = self.code() else {
+            return Ok(());
+        };
+        let code = code.read().unwrap();
+        let registers_size = code.registers_size();
+        let ins_size = code.ins_size();
+        if registers_size < ins_size {
+            return Err(AnalysisError::RegisterLayout(format!(
+                "{}: registers_size ({registers_size}) < ins_size ({ins_size})",
+                self.descriptor
+            )));
+        }
+        let nb_param_registers = self.nb_param_registers();
+        if registers_size < nb_param_registers {
+            return Err(AnalysisError::RegisterLayout(format!(
+                "{}: registers_size ({registers_size}) < parameters registers ({nb_param_registers})",
+                self.descriptor
+            )));
+        }
+        Ok(())
+    }
+
+    /// Returns the registers holding the `this` reference and the
+    /// parameters of the method when it is invoked, which are the last
+    /// registers of its frame.
+    ///
+    /// # Errors
+    ///
+    /// This function returns an error if the method has no code, or if
+    /// its [register layout is invalid](Self::validate_register_layout).
+    pub fn register_layout(&self) -> AnalysisResult<RegisterLayout> {
+        let code = self.code().ok_or_else(|| {
+            AnalysisError::RegisterLayout(format!("{}: no code", self.descriptor))
+        })?;
+        self.validate_register_layout()?;
+        let registers_size = code.read().unwrap().registers_size();
+
+        // registers_size fits in 16 bits in dex files
+        let mut next = (registers_size - self.nb_param_registers()) as u16;
+        let this = if self.is_static() {
+            None
+        } else {
+            next += 1;
+            Some(Reg::from(next - 1))
+        };
+        let mut parameters = Vec::with_capacity(self.parameters_types().len());
+        for typ in self.parameters_types() {
+            let is_wide = matches!(typ, Type::Long | Type::Double);
+            parameters.push((Reg::from(next), typ.clone(), is_wide));
+            next += if is_wide { 2 } else { 1 };
+        }
+        Ok(RegisterLayout {
+            registers_size,
+            this,
+            parameters,
+        })
+    }
+
+    // Number of registers holding the parameters, 'this' included.
+    fn nb_param_registers(&self) -> usize {
+        self.parameters_types()
+            .iter()
+            .map(|t| match t {
+                Type::Long | Type::Double => 2,
+                _ => 1,
+            })
+            .sum::<usize>()
+            + usize::from(!self.is_static())
+    }
+}
+
+/// The layout of the registers of a method frame on method entry.
+#[derive(Debug, Clone)]
+pub struct RegisterLayout {
+    /// The number of registers of the frame.
+    pub registers_size: usize,
+    /// The register holding the `this` reference, for non-static methods.
+    pub this: Option<Reg>,
+    /// The first register of each parameter, with the parameter type and
+    /// whether it is wide, i.e. held in a pair of registers.
+    pub parameters: Vec<(Reg, Type, bool)>,
 }
 
 /// A wrapper to cache prototype information of a method and to allow
 /// deriving of eq and ord traits.
+///
+/// Descriptors are parsed from and displayed in the smali syntax, that is
+/// the definer type and the method name followed by its JVM descriptor, as
+/// in `Lcom/foo/Bar;->baz(I[Ljava/lang/String;)V`.
 #[derive(Debug, Clone, PartialEq, Eq, PartialOrd, Ord)]
 pub struct MethodDescr {
     definer: MethodDefiner,
@@ -185,22 +349,64 @@ impl TryFrom<(&Dex, &MethodIdItem)> for MethodDescr {
     }
 }
 
+/// Parses a method descriptor in the smali syntax, such as
+/// `Lcom/foo/Bar;->baz(ILjava/lang/String;)V`.
+impl TryFrom<&str> for MethodDescr {
+    type Error = AnalysisError;
+
+    fn try_from(sig: &str) -> Result<Self, Self::Error> {
+        let bad_signature = || AnalysisError::BadSignature(sig.to_string());
+        let (definer, method) = sig.split_once("->").ok_or_else(bad_signature)?;
+        let name = method.split('(').next().unwrap_or_default();
+        if name.is_empty() {
+            return Err(bad_signature());
+        }
+        let definer = Type::try_from(definer).map_err(|_| bad_signature())?;
+        let proto = ProtoDescr::try_from(&method[name.len()..]).map_err(|_| bad_signature())?;
+
+        Ok(Self {
+            definer: MethodDefiner::try_from(&definer).map_err(|_| bad_signature())?,
+            name: name.to_string(),
+            return_type: proto.return_type,
+            parameters_types: proto.parameters,
+        })
+    }
+}
+
+impl FromStr for MethodDescr {
+    type Err = AnalysisError;
+
+    fn from_str(sig: &str) -> Result<Self, Self::Err> {
+        Self::try_from(sig)
+    }
+}
+
 impl fmt::Display for MethodDescr {
     fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
-        let parameters = self
-            .parameters_types
-            .iter()
-            .map(|t| format!("{t}"))
-            .collect::<String>();
-        write!(
-            f,
-            "{}->{}({}){}",
-            self.definer, self.name, parameters, self.return_type
-        )
+        match &self.definer {
+            MethodDefiner::Class(cl) => write!(f, "L{cl};")?,
+            MethodDefiner::Array(n, t) => write!(f, "{}{t}", "[".repeat(*n))?,
+        }
+        write!(f, "->{}{}", self.name, self.proto())
     }
 }
 
 impl MethodDescr {
+    #[must_use]
+    pub const fn new(
+        definer: MethodDefiner,
+        name: String,
+        return_type: Type,
+        parameters_types: Vec<Type>,
+    ) -> Self {
+        Self {
+            definer,
+            name,
+            return_type,
+            parameters_types,
+        }
+    }
+
     #[inline]
     pub fn definer(&self) -> &MethodDefiner {
         &self.definer
@@ -220,6 +426,15 @@ impl MethodDescr {
     pub fn parameters_types(&self) -> &Vec<Type> {
         &self.parameters_types
     }
+
+    /// Returns the prototype of the method.
+    #[must_use]
+    pub fn proto(&self) -> ProtoDescr {
+        ProtoDescr {
+            return_type: self.return_type.clone(),
+            parameters: self.parameters_types.clone(),
+        }
+    }
 }
 
 #[derive(Debug, Clone, PartialEq, Eq, PartialOrd, Ord)]
@@ -264,3 +479,254 @@ impl MethodDefiner {
         }
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use crate::errors::AnalysisError;
+    use crate::repo::MethodDescr;
+    use crate::stats::StubReason;
+    use crate::testing::{self, DexBuilder, ACC_PUBLIC, ACC_STATIC};
+    use dw_dex::registers::Reg;
+    use dw_dex::types::Type;
+
+    #[test]
+    fn stub_reasons() {
+        let mut builder = DexBuilder::new();
+        let class = builder.class("LTest;", "Ljava/lang/Object;");
+        let exception = builder.type_("Ljava/lang/RuntimeException;") as u16;
+        let exception_init = builder.method(
+            "Ljava/lang/RuntimeException;",
+            "<init>",
+            "V",
+            &["Ljava/lang/String;"],
+        ) as u16;
+        let object_init = builder.method("Ljava/lang/Object;", "<init>", "V", &[]) as u16;
+        let message = builder.string("stub") as u16;
+        let sdk_message = builder.string("Stub!") as u16;
+
+        // new-instance v0, Ljava/lang/RuntimeException;
+        // const-string v1, "stub"
+        // invoke-direct {v0, v1}, Ljava/lang/RuntimeException;-><init>(Ljava/lang/String;)V
+        // throw v0
+        let throwing = builder.method("LTest;", "throwing", "V", &[]);
+        builder.code(
+            class,
+            throwing,
+            ACC_PUBLIC | ACC_STATIC,
+            2,
+            0,
+            2,
+            &[
+                0x0022,
+                exception,
+                0x011a,
+                message,
+                0x2070,
+                exception_init,
+                0x0010,
+                0x0027,
+            ],
+        );
+        // const/4 v0, 0
+        // return v0
+        let zero = builder.method("LTest;", "zero", "I", &[]);
+        builder.code(
+            class,
+            zero,
+            ACC_PUBLIC | ACC_STATIC,
+            1,
+            0,
+            0,
+            &[0x0012, 0x000f],
+        );
+        // add-int/lit8 v0, v1, 1
+        // return v0
+        let next = builder.method("LTest;", "next", "I", &["I"]);
+        builder.code(
+            class,
+            next,
+            ACC_PUBLIC | ACC_STATIC,
+            2,
+            1,
+            0,
+            &[0x00d8, 0x0101, 0x000f],
+        );
+        // invoke-direct {v0}, Ljava/lang/Object;-><init>()V
+        // return-void
+        let init = builder.method("LTest;", "<init>", "V", &[]);
+        builder.code(
+            class,
+            init,
+            ACC_PUBLIC,
+            1,
+            1,
+            1,
+            &[0x1070, object_init, 0x0000, 0x000e],
+        );
+        // same as `throwing`, with the message of the Android SDK stubs
+        let sdk = builder.class("LSdk;", "Ljava/lang/Object;");
+        let stubbed = builder.method("LSdk;", "stubbed", "V", &[]);
+        builder.code(
+            sdk,
+            stubbed,
+            ACC_PUBLIC | ACC_STATIC,
+            2,
+            0,
+            2,
+            &[
+                0x0022,
+                exception,
+                0x011a,
+                sdk_message,
+                0x2070,
+                exception_init,
+                0x0010,
+                0x0027,
+            ],
+        );
+        let dex = builder.build_dex();
+        let repo = testing::repo(&dex);
+
+        let class = repo.iter_classes().find(|c| c.name() == "Test").unwrap();
+        let reason = |name: &str| testing::method(&repo, name).1.is_stub(&repo).unwrap();
+        assert_eq!(reason("throwing"), Some(StubReason::ThrowOnly));
+        assert_eq!(reason("zero"), Some(StubReason::ReturnDefault));
+        assert_eq!(reason("next"), None);
+        assert_eq!(reason("<init>"), Some(StubReason::Empty));
+        assert!(class.has_throw_only_code(&repo).unwrap());
+        assert!(!class.has_stub_code(&repo).unwrap());
+
+        let sdk = repo.iter_classes().find(|c| c.name() == "Sdk").unwrap();
+        assert!(sdk.has_stub_code(&repo).unwrap());
+    }
+
+    #[test]
+    fn register_layout_of_parameters() {
+        let mut builder = DexBuilder::new();
+        let class = builder.class("LTest;", "Ljava/lang/Object;");
+        let sum = builder.method("LTest;", "sum", "V", &["J", "I"]);
+        builder.code(class, sum, ACC_PUBLIC | ACC_STATIC, 5, 3, 0, &[0x000e]);
+        let get = builder.method("LTest;", "get", "V", &["Ljava/lang/String;"]);
+        builder.code(class, get, ACC_PUBLIC, 3, 2, 0, &[0x000e]);
+
+        let dex = builder.build_dex();
+        let repo = testing::repo(&dex);
+
+        let layout = |name: &str| {
+            let layout = testing::method(&repo, name).1.register_layout().unwrap();
+            let parameters: Vec<_> = layout
+                .parameters
+                .iter()
+                .map(|(reg, typ, is_wide)| (reg.value(), typ.clone(), *is_wide))
+                .collect();
+            (
+                layout.registers_size,
+                layout.this.map(Reg::value),
+                parameters,
+            )
+        };
+
+        // v0-v1: locals, v2-v3: long, v4: int
+        assert_eq!(
+            layout("sum"),
+            (5, None, vec![(2, Type::Long, true), (4, Type::Int, false)])
+        );
+        // v0: local, v1: this, v2: string
+        assert_eq!(
+            layout("get"),
+            (
+                3,
+                Some(1),
+                vec![(2, Type::Class("java/lang/String".to_string()), false)]
+            )
+        );
+    }
+
+    #[test]
+    fn register_layout_too_small() {
+        let mut builder = DexBuilder::new();
+        let class = builder.class("LTest;", "Ljava/lang/Object;");
+        let valid = builder.method("LTest;", "valid", "V", &["J"]);
+        builder.code(class, valid, ACC_PUBLIC, 3, 3, 0, &[0x000e]);
+        let corrupt = builder.method("LTest;", "corrupt", "V", &["I", "I"]);
+        builder.code(class, corrupt, ACC_PUBLIC | ACC_STATIC, 1, 2, 0, &[0x000e]);
+
+        let dex = builder.build_dex();
+        let repo = testing::repo(&dex);
+
+        let class = repo.iter_classes().find(|c| c.name() == "Test").unwrap();
+        let valid = testing::method(&repo, "valid").1;
+        assert!(valid.validate_register_layout().is_ok());
+        let corrupt = testing::method(&repo, "corrupt").1;
+        assert!(matches!(
+            corrupt.validate_register_layout(),
+            Err(AnalysisError::RegisterLayout(_))
+        ));
+        assert!(matches!(
+            crate::forward_typecheck(corrupt, class, &repo),
+            Err(AnalysisError::RegisterLayout(_))
+        ));
+    }
+
+    #[test]
+    fn method_descriptors() {
+        let sig = "Lcom/foo/Bar;->baz(I[Ljava/lang/String;)V";
+        let descr: MethodDescr = sig.parse().unwrap();
+        assert_eq!(descr.definer().class_name(), "com/foo/Bar");
+        assert_eq!(descr.name(), "baz");
+        assert_eq!(descr.return_type(), &Type::Void);
+        assert_eq!(
+            descr.parameters_types(),
+            &[
+                Type::Int,
+                Type::Array(1, Box::new(Type::Class("java/lang/String".to_string())))
+            ]
+        );
+        assert_eq!(descr.proto().to_string(), "(I[Ljava/lang/String;)V");
+        assert_eq!(descr.to_string(), sig);
+
+        let array_clone: MethodDescr = "[I->clone()Ljava/lang/Object;".parse().unwrap();
+        assert_eq!(array_clone.to_string(), "[I->clone()Ljava/lang/Object;");
+        for invalid in ["La/B;->(I)V", "La/B;baz(I)V", "I->baz()V", "La/B;->baz(V)V"] {
+            assert!(matches!(
+                invalid.parse::<MethodDescr>(),
+                Err(AnalysisError::BadSignature(_))
+            ));
+        }
+    }
+
+    #[test]
+    fn source_lines() {
+        let mut builder = DexBuilder::new();
+        let class = builder.class("LTest;", "Ljava/lang/Object;");
+        builder.source_file(class, "Test.java");
+        // const/4 v0, 0
+        // add-int/lit8 v0, v0, 1
+        // return v0
+        let run = builder.method("LTest;", "run", "I", &[]);
+        builder.code(
+            class,
+            run,
+            ACC_PUBLIC | ACC_STATIC,
+            1,
+            0,
+            0,
+            &[0x0012, 0x00d8, 0x0100, 0x000f],
+        );
+        builder.lines(class, run, &[(0, 12), (1, 14), (3, 13)]);
+        // return-void
+        let bare = builder.method("LTest;", "bare", "V", &[]);
+        builder.code(class, bare, ACC_PUBLIC | ACC_STATIC, 0, 0, 0, &[0x000e]);
+        let dex = builder.build_dex();
+        let repo = testing::repo(&dex);
+
+        let class = repo.get_class_by_name("Test").unwrap();
+        assert_eq!(class.source_file().unwrap().as_deref(), Some("Test.java"));
+        let line_range = |name: &str| testing::method(&repo, name).1.line_range().unwrap();
+        assert_eq!(line_range("run"), Some((12, 14)));
+        assert_eq!(line_range("bare"), None);
+
+        let object = repo.get_class_by_name("java/lang/Object").unwrap();
+        assert_eq!(object.source_file().unwrap(), None);
+    }
+}
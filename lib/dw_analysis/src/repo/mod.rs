@@ -8,9 +8,7 @@ mod repository;
 mod uids;
 
 pub use class::Class;
-pub use field::Field;
-pub use method::Method;
-pub use repository::Repo;
+pub use field::{Field, FieldDescr};
+pub use method::{Method, MethodDefiner, MethodDescr, RegisterLayout};
+pub use repository::{Repo, StringXrefs, MULTIDEX_METHOD_REFS_THRESHOLD};
 pub use uids::{ClassUid, FieldUid, MethodUid, RepoCounters};
-
-pub(crate) use method::MethodDescr;
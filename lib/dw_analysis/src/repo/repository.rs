@@ -1,13 +1,27 @@
 //! A repository to centralize application and dependencies classes.
 
 use crate::callgraph::CallGraph;
+use crate::constants::{Constants, Value};
 use crate::errors::{AnalysisError, AnalysisResult};
 use crate::hierarchy::Hierarchy;
 use crate::repo::*;
+use crate::typing::Types;
 use dw_dex::classes::ClassDefItem;
-use dw_dex::Dex;
+use dw_dex::instrs::Instr;
+use dw_dex::registers::Reg;
+use dw_dex::types::Type;
+use dw_dex::{Addr, Dex, DexIndex, ReferenceCounts};
+use dw_resources::manifest::{ComponentKind, Manifest};
+use petgraph::algo::tarjan_scc;
+use petgraph::graph::{DiGraph, NodeIndex};
+use rayon::prelude::*;
 use regex::Regex;
+use std::collections::{BTreeMap, BTreeSet};
 use std::ops;
+use std::sync::OnceLock;
+
+/// Referencing methods and instruction addresses of each string constant.
+pub type StringXrefs = BTreeMap<String, Vec<(MethodUid, Addr)>>;
 
 pub struct Repo<'a> {
     dexs: Vec<&'a Dex>,
@@ -15,8 +29,50 @@ pub struct Repo<'a> {
     counters: RepoCounters,
     methods: Vec<Method<'a>>,
     fields: Vec<Field<'a>>,
+    // built on first use, reset when a dex is registered
+    string_xrefs: OnceLock<StringXrefs>,
 }
 
+/// Number of method references above which a dex is considered close to
+/// the 65536 limit by [`Repo::multidex_report`].
+pub const MULTIDEX_METHOD_REFS_THRESHOLD: usize = 60_000;
+
+const ACTIVITY_CALLBACKS: &[&str] = &[
+    "onCreate",
+    "onStart",
+    "onRestart",
+    "onResume",
+    "onPause",
+    "onStop",
+    "onDestroy",
+    "onNewIntent",
+    "onActivityResult",
+    "onSaveInstanceState",
+    "onRestoreInstanceState",
+];
+const SERVICE_CALLBACKS: &[&str] = &[
+    "onCreate",
+    "onStart",
+    "onStartCommand",
+    "onBind",
+    "onUnbind",
+    "onRebind",
+    "onHandleIntent",
+    "onDestroy",
+];
+const RECEIVER_CALLBACKS: &[&str] = &["onReceive"];
+const PROVIDER_CALLBACKS: &[&str] = &[
+    "onCreate", "query", "insert", "update", "delete", "getType", "call", "openFile",
+];
+const APPLICATION_CALLBACKS: &[&str] = &[
+    "onCreate",
+    "attachBaseContext",
+    "onConfigurationChanged",
+    "onLowMemory",
+    "onTrimMemory",
+    "onTerminate",
+];
+
 impl<'a> Default for Repo<'a> {
     fn default() -> Self {
         Self {
@@ -25,6 +81,7 @@ impl<'a> Default for Repo<'a> {
             counters: RepoCounters::new(),
             methods: Vec::new(),
             fields: Vec::new(),
+            string_xrefs: OnceLock::new(),
         }
     }
 }
@@ -53,6 +110,7 @@ impl<'a> Repo<'a> {
 
     pub fn register_dex(&mut self, dex: &'a Dex, is_system: bool) -> AnalysisResult<()> {
         self.dexs.push(dex);
+        self.string_xrefs.take();
 
         for class_def in dex.iter_class_defs() {
             self.register_class(class_def, dex, is_system)?;
@@ -174,6 +232,77 @@ impl<'a> Repo<'a> {
             .flat_map(move |class| class.iter_methods(self).map(move |method| (class, method)))
     }
 
+    /// Returns, for each string loaded by a `const-string` (or
+    /// `const-string/jumbo`) instruction, the methods and addresses of the
+    /// instructions loading it.
+    ///
+    /// As every method code is scanned, the index is built on first call
+    /// and cached until another dex is registered.
+    ///
+    /// # Errors
+    ///
+    /// This function may return an error if a string constant cannot be
+    /// resolved.
+    pub fn string_xrefs(&self) -> AnalysisResult<&StringXrefs> {
+        if let Some(xrefs) = self.string_xrefs.get() {
+            return Ok(xrefs);
+        }
+        let mut xrefs = StringXrefs::new();
+        for (_, method) in self.iter_classes_methods() {
+            let Some(code) = method.code() else { continue };
+            let dex = method.dex();
+            for instr in code.read().unwrap().iter_instructions() {
+                if let Instr::ConstString(_, s) | Instr::ConstStringJumbo(_, s) = instr.instr() {
+                    xrefs
+                        .entry(s.get(dex)?.to_string(dex)?)
+                        .or_default()
+                        .push((method.uid(), instr.addr()));
+                }
+            }
+        }
+        Ok(self.string_xrefs.get_or_init(|| xrefs))
+    }
+
+    /// Lists the methods and addresses of the instructions loading a string
+    /// constant that contains `needle`, ordered by string.
+    ///
+    /// # Errors
+    ///
+    /// See [`Repo::string_xrefs`].
+    pub fn find_string_users(&self, needle: &str) -> AnalysisResult<Vec<(MethodUid, Addr)>> {
+        Ok(self
+            .string_xrefs()?
+            .iter()
+            .filter(|(s, _)| s.contains(needle))
+            .flat_map(|(_, users)| users.iter().copied())
+            .collect())
+    }
+
+    /// Runs the forward (or backward) typechecking pass on every method
+    /// with code of the non-system classes, in parallel.
+    ///
+    /// Analyses only read the repository and take read locks on code
+    /// items, so they do not interfere with each other. Results are
+    /// returned in the order of [`Repo::iter_classes_methods`].
+    #[must_use]
+    pub fn par_typecheck(&self, backward: bool) -> Vec<(MethodUid, AnalysisResult<Types>)> {
+        let methods: Vec<(&Class, &Method)> = self
+            .iter_classes_methods()
+            .filter(|(class, method)| !class.is_system() && method.code().is_some())
+            .collect();
+        methods
+            .into_par_iter()
+            .map(|(class, method)| {
+                let res = if backward {
+                    Types::backward_compute(method, class, self)
+                } else {
+                    Types::forward_compute(method, class, self)
+                };
+                (method.uid(), res)
+            })
+            .collect()
+    }
+
     pub(crate) fn find_method_by_descriptor(&self, descriptor: &MethodDescr) -> Option<&Method> {
         let class = self.get_class_by_name(&descriptor.definer().class_name())?;
         class.get_method(
@@ -184,6 +313,61 @@ impl<'a> Repo<'a> {
         )
     }
 
+    /// Finds a method from its descriptor in the smali syntax, such as
+    /// `Lcom/foo/Bar;->baz(ILjava/lang/String;)V`. Malformed descriptors
+    /// are ignored, see [`Repo::try_find_method`] to report them.
+    pub fn find_method(&self, sig: &str) -> Option<&Method<'_>> {
+        self.try_find_method(sig).ok().flatten()
+    }
+
+    /// Same as [`Repo::find_method`], but fails with
+    /// [`AnalysisError::BadSignature`] if the descriptor is malformed.
+    pub fn try_find_method(&self, sig: &str) -> AnalysisResult<Option<&Method<'_>>> {
+        let descriptor = MethodDescr::try_from(sig)?;
+        Ok(self.find_method_by_descriptor(&descriptor))
+    }
+
+    /// Resolves an instance field reference as the runtime does for
+    /// `iget` and `iput` instructions: the field is looked up in the
+    /// referenced class, then in its superclasses.
+    ///
+    /// Returns `None` if the field cannot be found, or if the found field
+    /// is static.
+    pub fn lookup_instance_field(&self, descriptor: &FieldDescr) -> Option<&Field<'_>> {
+        self.lookup_field(descriptor.class_name(), descriptor, false)
+    }
+
+    /// Resolves a static field reference as the runtime does for `sget`
+    /// and `sput` instructions: the field is looked up in the referenced
+    /// class, then in its interfaces, then in its superclasses.
+    ///
+    /// Returns `None` if the field cannot be found, or if the found field
+    /// is not static.
+    pub fn lookup_static_field(&self, descriptor: &FieldDescr) -> Option<&Field<'_>> {
+        self.lookup_field(descriptor.class_name(), descriptor, true)
+    }
+
+    fn lookup_field(
+        &self,
+        class_name: &str,
+        descriptor: &FieldDescr,
+        is_static: bool,
+    ) -> Option<&Field<'_>> {
+        let class = self.get_class_by_name(class_name)?;
+        if let Some(field) = class.get_field(descriptor.name(), descriptor.type_(), self) {
+            return (field.is_static() == is_static).then_some(field);
+        }
+        if is_static {
+            for interface in self.hierarchy.interfaces(class) {
+                if let Some(field) = self.lookup_field(interface.name(), descriptor, is_static) {
+                    return Some(field);
+                }
+            }
+        }
+        let superclass = self.hierarchy.superclass(class)?;
+        self.lookup_field(superclass.name(), descriptor, is_static)
+    }
+
     pub(crate) fn is_inherited(&self, descriptor: &MethodDescr) -> bool {
         let Some(class) = self.get_class_by_name(&descriptor.definer().class_name()) else { return false };
         for parent in self.hierarchy.all_parents(class) {
@@ -202,10 +386,221 @@ impl<'a> Repo<'a> {
         false
     }
 
+    /// Returns whether a virtual method overrides (or implements) a method
+    /// of one of the parents of its class, and may thus be called by the
+    /// runtime through virtual dispatch. Since the methods of a parent that
+    /// is not defined in the repository are unknown, it is assumed to
+    /// declare the method.
+    pub(crate) fn overrides(&self, method: &Method) -> bool {
+        if method.is_private() || method.is_static() || method.name() == "<init>" {
+            return false;
+        }
+        let descriptor = method.descriptor();
+        let Some(class) = self.get_class_by_name(&descriptor.definer().class_name()) else {
+            return true;
+        };
+        self.hierarchy
+            .all_parents(class)
+            .iter()
+            .filter(|parent| parent.name() != class.name())
+            .any(|parent| {
+                !parent.is_defined()
+                    || parent
+                        .get_method(
+                            descriptor.name(),
+                            descriptor.return_type(),
+                            descriptor.parameters_types(),
+                            self,
+                        )
+                        .is_some()
+            })
+    }
+
     pub fn build_callgraph(&self) -> AnalysisResult<CallGraph> {
         CallGraph::build(self, false)
     }
 
+    /// Returns the methods the Android framework may call on the
+    /// components declared in `manifest` (activities, services, receivers
+    /// and providers), and on its `Application` subclass: constructors and
+    /// lifecycle methods such as `onCreate` or `onReceive`, possibly
+    /// inherited from an application superclass.
+    ///
+    /// Component names written in the relative form (`.Main` or `Main`)
+    /// are expanded with the manifest package name. Components whose class
+    /// is not defined in the repository are skipped.
+    ///
+    /// # Errors
+    ///
+    /// This function returns an error if the manifest cannot be queried.
+    pub fn entry_points(&self, manifest: &Manifest) -> AnalysisResult<Vec<MethodUid>> {
+        let package = manifest.package()?.unwrap_or_default();
+        let mut classes: Vec<(String, &[&str])> = manifest
+            .components_with_filters(None)?
+            .into_iter()
+            .filter_map(|component| {
+                let name = component.tag.name()?;
+                let callbacks = match component.kind {
+                    ComponentKind::Activity => ACTIVITY_CALLBACKS,
+                    ComponentKind::Service => SERVICE_CALLBACKS,
+                    ComponentKind::Receiver => RECEIVER_CALLBACKS,
+                    ComponentKind::Provider => PROVIDER_CALLBACKS,
+                };
+                Some((name, callbacks))
+            })
+            .collect();
+        if let Some(name) = manifest.application_name(None)? {
+            classes.push((name, APPLICATION_CALLBACKS));
+        }
+
+        let mut entry_points = BTreeSet::new();
+        for (name, callbacks) in classes {
+            let name = Manifest::qualify(&package, &name);
+            let class_name = name.replace('.', "/");
+            let Some(class) = self
+                .get_class_by_name(&class_name)
+                .filter(|cl| cl.is_defined())
+            else {
+                log::warn!("component class {name} not found in repository");
+                continue;
+            };
+            for parent in self.hierarchy.all_parents(class) {
+                entry_points.extend(
+                    parent
+                        .iter_methods(self)
+                        .filter(|method| {
+                            method.code().is_some()
+                                && (method.name() == "<init>" || callbacks.contains(&method.name()))
+                        })
+                        .map(Method::uid),
+                );
+            }
+        }
+        Ok(entry_points.into_iter().collect())
+    }
+
+    /// Returns the application methods with code that cannot be reached
+    /// from the [entry points](Self::entry_points) declared in `manifest`.
+    ///
+    /// # Errors
+    ///
+    /// This function returns an error if the manifest cannot be queried or
+    /// if the callgraph cannot be built.
+    pub fn unreachable_methods(&self, manifest: &Manifest) -> AnalysisResult<Vec<MethodUid>> {
+        let entry_points = self.entry_points(manifest)?;
+        let reachable = self.build_callgraph()?.reachable_from(&entry_points);
+        Ok(self
+            .iter_classes_methods()
+            .filter(|(class, method)| {
+                class.is_defined()
+                    && !class.is_system()
+                    && method.code().is_some()
+                    && !reachable.contains(&method.uid())
+            })
+            .map(|(_, method)| method.uid())
+            .collect())
+    }
+
+    /// Replaces the code of the given methods by a stub throwing a
+    /// `NullPointerException`, keeping the dex files verifiable, and
+    /// returns the number of stubbed methods. Methods without code are left
+    /// untouched. The modified dex files have to be written back for the
+    /// change to be saved.
+    pub fn stub_methods(&mut self, uids: &[MethodUid]) -> usize {
+        self.string_xrefs = OnceLock::new();
+        let mut stubbed = 0;
+        for uid in uids {
+            if let Some(code) = self[*uid].code() {
+                code.write().unwrap().replace_with_throw_stub();
+                stubbed += 1;
+            }
+        }
+        stubbed
+    }
+
+    /// Lists every call site of the `sink` method, together with the
+    /// constant value passed as its `arg_index`th argument (or `None`
+    /// if the argument is not known to be constant at this call site).
+    ///
+    /// The argument index does not count the implicit `this` argument
+    /// of non-static invocations.
+    ///
+    /// # Errors
+    ///
+    /// This function may return an error if `arg_index` is out of the
+    /// sink parameters bounds, or if a calling method cannot be analyzed.
+    pub fn sink_arguments(
+        &self,
+        sink: &MethodDescr,
+        arg_index: usize,
+    ) -> AnalysisResult<Vec<(MethodUid, Addr, Option<Value>)>> {
+        if arg_index >= sink.parameters_types().len() {
+            return Err(AnalysisError::Internal(format!(
+                "argument index {arg_index} out of bounds for {sink}"
+            )));
+        }
+        // registers offset of the argument, without 'this' register
+        let arg_offset = sink.parameters_types()[..arg_index]
+            .iter()
+            .map(|t| match t {
+                Type::Long | Type::Double => 2,
+                _ => 1,
+            })
+            .sum::<usize>();
+
+        let mut res = Vec::new();
+        for (class, method) in self.iter_classes_methods() {
+            let Some(code) = method.code() else { continue };
+            let dex = method.dex();
+
+            let mut call_sites = Vec::new();
+            for instr in code.read().unwrap().iter_instructions() {
+                let (args, m, is_static): (Vec<Reg>, _, _) = match instr.instr() {
+                    Instr::InvokeStatic(args, m) => (args.iter().collect(), m, true),
+                    Instr::InvokeVirtual(args, m)
+                    | Instr::InvokeSuper(args, m)
+                    | Instr::InvokeDirect(args, m)
+                    | Instr::InvokeInterface(args, m) => (args.iter().collect(), m, false),
+                    Instr::InvokeStaticRange(args, m) => (args.iter().collect(), m, true),
+                    Instr::InvokeVirtualRange(args, m)
+                    | Instr::InvokeSuperRange(args, m)
+                    | Instr::InvokeDirectRange(args, m)
+                    | Instr::InvokeInterfaceRange(args, m) => (args.iter().collect(), m, false),
+                    _ => continue,
+                };
+                if &MethodDescr::try_from((dex, m.get(dex)?))? != sink {
+                    continue;
+                }
+                let position = if is_static {
+                    arg_offset
+                } else {
+                    arg_offset + 1
+                };
+                let reg = args.get(position).copied().ok_or_else(|| {
+                    AnalysisError::Internal(format!(
+                        "missing argument register at {} for {sink}",
+                        instr.addr()
+                    ))
+                })?;
+                call_sites.push((instr.addr(), reg));
+            }
+            if call_sites.is_empty() {
+                continue;
+            }
+
+            let constants = Constants::compute(method, class)?;
+            for (addr, reg) in call_sites {
+                // unreachable call sites have no computed state
+                let value = match constants.entries.get(&addr) {
+                    Some(state) => state.read_reg(reg)?.cloned(),
+                    None => None,
+                };
+                res.push((method.uid(), addr, value));
+            }
+        }
+        Ok(res)
+    }
+
     /// Checks whether an object can be replaced by another with a typechecking point of view.
     /// The only accepted types are class names here.
     ///
@@ -312,6 +707,244 @@ impl<'a> Repo<'a> {
         }
     }
 
+    /// Returns the classes that implement the given interface, either
+    /// directly, through one of their superclasses or through a
+    /// sub-interface. Interfaces and abstract classes are left out, as well
+    /// as system classes unless `include_system` is set.
+    ///
+    /// Classes are sorted by name, and none is returned if the interface is
+    /// not in the repository.
+    #[must_use]
+    pub fn implementors(&self, interface: &str, include_system: bool) -> Vec<ClassUid> {
+        let Some(interface) = self.hierarchy.get_class(interface) else {
+            return Vec::new();
+        };
+        let implementors = self
+            .hierarchy
+            .all_children(interface)
+            .into_iter()
+            .filter(|class| class.is_defined() && !class.is_interface() && !class.is_abstract())
+            .filter(|class| include_system || !class.is_system())
+            .collect();
+        sorted_uids(implementors)
+    }
+
+    /// Returns the subclasses of the given class, either only the direct
+    /// ones or, if `transitive` is set, all of them. System classes are
+    /// left out unless `include_system` is set.
+    ///
+    /// Classes are sorted by name, and none is returned if the class is not
+    /// in the repository.
+    #[must_use]
+    pub fn subclasses(&self, class: &str, transitive: bool, include_system: bool) -> Vec<ClassUid> {
+        let Some(class) = self.hierarchy.get_class(class) else {
+            return Vec::new();
+        };
+        let mut subclasses = self.hierarchy.subclasses(class);
+        if transitive {
+            let mut i = 0;
+            while i < subclasses.len() {
+                subclasses.extend(self.hierarchy.subclasses(subclasses[i]));
+                i += 1;
+            }
+        }
+        subclasses.retain(|class| include_system || !class.is_system());
+        sorted_uids(subclasses)
+    }
+
+    /// Returns the static initialization dependencies of the application
+    /// classes that have a `<clinit>` method. An edge from a class to
+    /// another means that the static initializer of the former triggers
+    /// the initialization of the latter, by accessing one of its static
+    /// fields, invoking one of its static methods or instantiating it.
+    ///
+    /// Nodes are added in class name order.
+    ///
+    /// # Errors
+    ///
+    /// This function may return an error if a field, method or type
+    /// reference cannot be read from a dex file.
+    pub fn clinit_dependencies(&self) -> AnalysisResult<DiGraph<ClassUid, ()>> {
+        let mut initializers: Vec<(&Class, &Method)> = self
+            .iter_classes()
+            .filter(|class| class.is_defined() && !class.is_system())
+            .filter_map(|class| {
+                let clinit = class.iter_methods(self).find(|m| m.name() == "<clinit>")?;
+                Some((class, clinit))
+            })
+            .collect();
+        initializers.sort_by(|(c1, _), (c2, _)| c1.name().cmp(c2.name()));
+
+        let mut graph = DiGraph::with_capacity(initializers.len(), 0);
+        let nodes: BTreeMap<&str, NodeIndex> = initializers
+            .iter()
+            .map(|(class, _)| (class.name(), graph.add_node(class.uid())))
+            .collect();
+        for (class, clinit) in &initializers {
+            let Some(code) = clinit.code() else {
+                continue;
+            };
+            let dex = clinit.dex();
+            for instr in code.read().unwrap().iter_instructions() {
+                let target = match instr.instr() {
+                    Instr::Sget(_, field)
+                    | Instr::SgetWide(_, field)
+                    | Instr::SgetObject(_, field)
+                    | Instr::SgetBoolean(_, field)
+                    | Instr::SgetByte(_, field)
+                    | Instr::SgetChar(_, field)
+                    | Instr::SgetShort(_, field)
+                    | Instr::Sput(_, field)
+                    | Instr::SputWide(_, field)
+                    | Instr::SputObject(_, field)
+                    | Instr::SputBoolean(_, field)
+                    | Instr::SputByte(_, field)
+                    | Instr::SputChar(_, field)
+                    | Instr::SputShort(_, field) => {
+                        let descriptor = FieldDescr::try_from((dex, field.get(dex)?))?;
+                        match self.lookup_static_field(&descriptor) {
+                            Some(field) => field.descriptor().class_name().to_string(),
+                            None => continue,
+                        }
+                    }
+                    Instr::InvokeStatic(_, method) | Instr::InvokeStaticRange(_, method) => {
+                        MethodDescr::try_from((dex, method.get(dex)?))?
+                            .definer()
+                            .class_name()
+                    }
+                    Instr::NewInstance(_, typ) => match typ.get(dex)?.to_type(dex)? {
+                        Type::Class(name) => name,
+                        _ => continue,
+                    },
+                    _ => continue,
+                };
+                if let Some(target) = nodes.get(target.as_str()) {
+                    let source = nodes[class.name()];
+                    if source != *target {
+                        graph.update_edge(source, *target, ());
+                    }
+                }
+            }
+        }
+        Ok(graph)
+    }
+
+    /// Returns the classes of [`Repo::clinit_dependencies`] in a best-effort
+    /// initialization order, where a class comes after the classes that its
+    /// static initializer depends on. Classes that belong to a dependency
+    /// cycle are flagged, as their actual order depends on which one is
+    /// initialized first at runtime.
+    ///
+    /// # Errors
+    ///
+    /// This function may return an error if the dependencies cannot be
+    /// computed.
+    pub fn clinit_topo_order(&self) -> AnalysisResult<Vec<(ClassUid, bool)>> {
+        let graph = self.clinit_dependencies()?;
+        let mut order = Vec::with_capacity(graph.node_count());
+        // Tarjan's algorithm yields components in reverse topological order,
+        // that is dependencies first.
+        for mut component in tarjan_scc(&graph) {
+            component.sort();
+            let cyclic = component.len() > 1;
+            order.extend(component.into_iter().map(|id| (graph[id], cyclic)));
+        }
+        Ok(order)
+    }
+
+    /// Groups the methods of application classes whose bodies are similar,
+    /// as candidates for code cloned from one application to another.
+    ///
+    /// Methods with the same normalized body, hence the same
+    /// [`Method::normalized_fingerprint`], belong to the same group. With a
+    /// `threshold` below 1, a group joins the first group (in the order of
+    /// their normalized bodies) whose normalized instructions multiset has
+    /// a Jaccard similarity with its own of at least `threshold`. Groups are
+    /// not merged transitively: every method is similar to the first one of
+    /// its group. Methods without code are left out, as well as groups of a
+    /// single method. Trivial bodies (such as constructors only calling
+    /// their super constructor) are grouped as any other.
+    ///
+    /// # Errors
+    ///
+    /// This function may return an error if a method body cannot be
+    /// normalized.
+    pub fn find_clones(&self, threshold: f32) -> AnalysisResult<Vec<Vec<MethodUid>>> {
+        let mut bodies: BTreeMap<Vec<String>, Vec<MethodUid>> = BTreeMap::new();
+        for (_, method) in self
+            .iter_classes_methods()
+            .filter(|(class, _)| class.is_defined() && !class.is_system())
+        {
+            if method.code().is_none() {
+                continue;
+            }
+            bodies
+                .entry(method.normalized_body()?)
+                .or_default()
+                .push(method.uid());
+        }
+
+        // groups are indexed by the length of their first body, since
+        // multisets of lengths n <= m have a similarity of at most n / m
+        let mut groups: Vec<(BodyCounts, Vec<MethodUid>)> = Vec::new();
+        let mut lengths: BTreeMap<usize, Vec<usize>> = BTreeMap::new();
+        for (body, methods) in bodies {
+            let counts = BodyCounts::new(body);
+            let similar = if threshold < 1.0 {
+                let len = counts.len as f32;
+                let min = (len * threshold).floor() as usize;
+                let max = if threshold > 0.0 {
+                    (len / threshold).ceil() as usize
+                } else {
+                    usize::MAX
+                };
+                let mut candidates: Vec<usize> = lengths
+                    .range(min..=max)
+                    .flat_map(|(_, groups)| groups.iter().copied())
+                    .collect();
+                candidates.sort_unstable();
+                candidates
+                    .into_iter()
+                    .find(|i| groups[*i].0.is_similar(&counts, threshold))
+            } else {
+                None
+            };
+            match similar {
+                Some(i) => groups[i].1.extend(methods),
+                None => {
+                    lengths.entry(counts.len).or_default().push(groups.len());
+                    groups.push((counts, methods));
+                }
+            }
+        }
+
+        let mut clones: Vec<Vec<MethodUid>> = groups
+            .into_iter()
+            .map(|(_, methods)| methods)
+            .filter(|methods| methods.len() > 1)
+            .map(|mut methods| {
+                methods.sort();
+                methods
+            })
+            .collect();
+        clones.sort();
+        Ok(clones)
+    }
+
+    /// Returns the reference counts of the registered dex files, in
+    /// registration order, each flagged if its method references exceed
+    /// [`MULTIDEX_METHOD_REFS_THRESHOLD`].
+    #[must_use]
+    pub fn multidex_report(&self) -> Vec<(ReferenceCounts, bool)> {
+        self.dexs
+            .iter()
+            .map(|dex| {
+                let counts = dex.reference_counts();
+                (counts, counts.methods > MULTIDEX_METHOD_REFS_THRESHOLD)
+            })
+            .collect()
+    }
+
     pub fn nb_classes(&self) -> usize {
         self.counters.nb_classes()
     }
@@ -324,3 +957,610 @@ impl<'a> Repo<'a> {
         self.counters.nb_fields()
     }
 }
+
+fn sorted_uids(mut classes: Vec<&Class>) -> Vec<ClassUid> {
+    classes.sort_by(|c1, c2| c1.name().cmp(c2.name()));
+    classes.iter().map(|class| class.uid()).collect()
+}
+
+/// The lines of a normalized method body, and their opcodes, given as
+/// element counts.
+struct BodyCounts {
+    len: usize,
+    lines: BTreeMap<String, usize>,
+    opcodes: BTreeMap<String, usize>,
+}
+
+impl BodyCounts {
+    fn new(body: Vec<String>) -> Self {
+        let len = body.len();
+        let mut lines = BTreeMap::new();
+        let mut opcodes = BTreeMap::new();
+        for line in body {
+            let opcode = line.split(' ').next().unwrap_or_default().to_string();
+            *opcodes.entry(opcode).or_insert(0) += 1;
+            *lines.entry(line).or_insert(0) += 1;
+        }
+        Self {
+            len,
+            lines,
+            opcodes,
+        }
+    }
+
+    /// Tells whether the Jaccard similarity of the lines multisets reaches
+    /// `threshold`. Identical lines have identical opcodes, so that the
+    /// similarity of the opcodes multisets, checked first, is an upper
+    /// bound.
+    fn is_similar(&self, other: &Self, threshold: f32) -> bool {
+        jaccard(&self.opcodes, &other.opcodes) >= threshold
+            && jaccard(&self.lines, &other.lines) >= threshold
+    }
+}
+
+/// Returns the Jaccard similarity of two multisets, given as element
+/// counts.
+fn jaccard(a: &BTreeMap<String, usize>, b: &BTreeMap<String, usize>) -> f32 {
+    let mut inter = 0;
+    let mut union = 0;
+    for (line, &count) in a {
+        let other = b.get(line).copied().unwrap_or(0);
+        inter += count.min(other);
+        union += count.max(other);
+    }
+    union += b
+        .iter()
+        .filter(|(line, _)| !a.contains_key(*line))
+        .map(|(_, count)| count)
+        .sum::<usize>();
+    if union == 0 {
+        1.0
+    } else {
+        inter as f32 / union as f32
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::testing::{self, DexBuilder, ACC_ABSTRACT, ACC_INTERFACE, ACC_PUBLIC, ACC_STATIC};
+
+    #[test]
+    fn sink_arguments_constant_string() {
+        let mut builder = DexBuilder::new();
+        let class = builder.class("LTest;", "Ljava/lang/Object;");
+        let get_instance = builder.method(
+            "Ljavax/crypto/Cipher;",
+            "getInstance",
+            "Ljavax/crypto/Cipher;",
+            &["Ljava/lang/String;"],
+        ) as u16;
+        let algorithm = builder.string("AES/ECB/NoPadding") as u16;
+
+        // const-string v0, "AES/ECB/NoPadding"
+        // invoke-static {v0}, Cipher.getInstance(String)
+        // return-void
+        let constant = builder.method("LTest;", "constant", "V", &[]);
+        builder.code(
+            class,
+            constant,
+            ACC_PUBLIC | ACC_STATIC,
+            1,
+            0,
+            1,
+            &[0x001a, algorithm, 0x1071, get_instance, 0x0000, 0x000e],
+        );
+
+        // invoke-static {v0}, Cipher.getInstance(String)
+        // return-void
+        let parameter = builder.method("LTest;", "parameter", "V", &["Ljava/lang/String;"]);
+        builder.code(
+            class,
+            parameter,
+            ACC_PUBLIC | ACC_STATIC,
+            1,
+            1,
+            1,
+            &[0x1071, get_instance, 0x0000, 0x000e],
+        );
+
+        let dex = builder.build_dex();
+        let repo = testing::repo(&dex);
+
+        let sink = MethodDescr::new(
+            MethodDefiner::Class("javax/crypto/Cipher".to_string()),
+            "getInstance".to_string(),
+            Type::Class("javax/crypto/Cipher".to_string()),
+            vec![Type::Class("java/lang/String".to_string())],
+        );
+        let mut args: Vec<_> = repo
+            .sink_arguments(&sink, 0)
+            .unwrap()
+            .into_iter()
+            .map(|(muid, addr, value)| (repo[muid].name().to_string(), addr, value))
+            .collect();
+        args.sort_by(|a1, a2| a1.0.cmp(&a2.0));
+
+        assert_eq!(
+            args,
+            vec![
+                (
+                    "constant".to_string(),
+                    Addr(2),
+                    Some(Value::String("AES/ECB/NoPadding".to_string()))
+                ),
+                ("parameter".to_string(), Addr(0), None),
+            ]
+        );
+        assert!(repo.sink_arguments(&sink, 1).is_err());
+    }
+
+    #[test]
+    fn string_users() {
+        let mut builder = DexBuilder::new();
+        let class = builder.class("LTest;", "Ljava/lang/Object;");
+        let url = builder.string("http://evil") as u16;
+        let other = builder.string("benign") as u16;
+
+        // const/4 v0, 0
+        // const-string v0, "http://evil"
+        // return-void
+        let download = builder.method("LTest;", "download", "V", &[]);
+        builder.code(
+            class,
+            download,
+            ACC_PUBLIC | ACC_STATIC,
+            1,
+            0,
+            0,
+            &[0x0012, 0x001a, url, 0x000e],
+        );
+        // const-string v0, "benign"
+        // return-void
+        let log = builder.method("LTest;", "log", "V", &[]);
+        builder.code(
+            class,
+            log,
+            ACC_PUBLIC | ACC_STATIC,
+            1,
+            0,
+            0,
+            &[0x001a, other, 0x000e],
+        );
+
+        let dex = builder.build_dex();
+        let repo = testing::repo(&dex);
+
+        let users = |uses: &[(MethodUid, Addr)]| -> Vec<(String, Addr)> {
+            uses.iter()
+                .map(|(uid, addr)| (repo[*uid].name().to_string(), *addr))
+                .collect()
+        };
+        let expected = vec![("download".to_string(), Addr(1))];
+        let xrefs = repo.string_xrefs().unwrap();
+        assert_eq!(xrefs.len(), 2);
+        assert_eq!(users(&xrefs["http://evil"]), expected);
+        assert_eq!(users(&repo.find_string_users("evil").unwrap()), expected);
+        assert!(repo.find_string_users("https").unwrap().is_empty());
+        assert_eq!(repo.find_string_users("").unwrap().len(), 2);
+    }
+
+    #[test]
+    fn par_typecheck_matches_sequential() {
+        let mut builder = DexBuilder::new();
+        let class = builder.class("LTest;", "Ljava/lang/Object;");
+        let callee = builder.method("LTest;", "callee", "V", &[]) as u16;
+        builder.code(
+            class,
+            callee.into(),
+            ACC_PUBLIC | ACC_STATIC,
+            0,
+            0,
+            0,
+            &[0x000e],
+        );
+        // const/4 v0, 1
+        // invoke-static {}, Test.callee()
+        // return-void
+        let valid = builder.method("LTest;", "valid", "V", &[]);
+        builder.code(
+            class,
+            valid,
+            ACC_PUBLIC | ACC_STATIC,
+            1,
+            0,
+            0,
+            &[0x1012, 0x0071, callee, 0x0000, 0x000e],
+        );
+        // return-object v0 (uninitialized)
+        let invalid = builder.method("LTest;", "invalid", "Ljava/lang/Object;", &[]);
+        builder.code(class, invalid, ACC_PUBLIC | ACC_STATIC, 1, 0, 0, &[0x0011]);
+
+        let dex = builder.build_dex();
+        let repo = testing::repo(&dex);
+
+        let summary = |res: &AnalysisResult<Types>| {
+            res.as_ref()
+                .map(|types| (types.entries.clone(), types.exits.clone()))
+                .map_err(ToString::to_string)
+        };
+        for backward in [false, true] {
+            let sequential: Vec<_> = repo
+                .iter_classes_methods()
+                .filter(|(_, method)| method.code().is_some())
+                .map(|(class, method)| {
+                    let res = if backward {
+                        Types::backward_compute(method, class, &repo)
+                    } else {
+                        Types::forward_compute(method, class, &repo)
+                    };
+                    (method.uid(), summary(&res))
+                })
+                .collect();
+            let parallel: Vec<_> = repo
+                .par_typecheck(backward)
+                .iter()
+                .map(|(uid, res)| (*uid, summary(res)))
+                .collect();
+            assert_eq!(parallel.len(), 3);
+            assert_eq!(parallel, sequential);
+        }
+        let invalid = repo
+            .par_typecheck(false)
+            .into_iter()
+            .find(|(uid, _)| repo[*uid].name() == "invalid")
+            .unwrap();
+        assert!(invalid.1.is_err());
+    }
+
+    #[test]
+    fn manifest_entry_points() {
+        let mut builder = DexBuilder::new();
+        let activity = builder.class("Lcom/example/Main;", "Landroid/app/Activity;");
+        let helper = builder.method("Lcom/example/Main;", "helper", "V", &[]) as u16;
+        let unused = builder.method("Lcom/example/Main;", "unused", "V", &[]);
+
+        // invoke-direct {v0}, Main.helper()
+        // return-void
+        let on_create = builder.method("Lcom/example/Main;", "onCreate", "V", &[]);
+        builder.code(
+            activity,
+            on_create,
+            ACC_PUBLIC,
+            1,
+            1,
+            1,
+            &[0x1070, helper, 0x0000, 0x000e],
+        );
+        builder.code(activity, helper.into(), ACC_PUBLIC, 1, 1, 0, &[0x000e]);
+        builder.code(activity, unused, ACC_PUBLIC, 1, 1, 0, &[0x000e]);
+
+        let dex = builder.build_dex();
+        let repo = testing::repo(&dex);
+
+        let manifest = testing::manifest("com.example", None, &[("activity", ".Main")]);
+        let names = |uids: Vec<MethodUid>| -> Vec<String> {
+            uids.into_iter()
+                .map(|uid| repo[uid].name().to_string())
+                .collect()
+        };
+        assert_eq!(
+            names(repo.entry_points(&manifest).unwrap()),
+            vec!["onCreate"]
+        );
+        assert_eq!(
+            names(repo.unreachable_methods(&manifest).unwrap()),
+            vec!["unused"]
+        );
+
+        let manifest = testing::manifest("com.example", None, &[("receiver", "com.example.Main")]);
+        assert!(repo.entry_points(&manifest).unwrap().is_empty());
+    }
+
+    #[test]
+    fn find_method_by_signature() {
+        let mut builder = DexBuilder::new();
+        let class = builder.class("Lcom/foo/Bar;", "Ljava/lang/Object;");
+        let baz = builder.method("Lcom/foo/Bar;", "baz", "V", &["I", "Ljava/lang/String;"]);
+        builder.code(class, baz, ACC_PUBLIC | ACC_STATIC, 2, 2, 0, &[0x000e]);
+
+        let dex = builder.build_dex();
+        let repo = testing::repo(&dex);
+
+        let sig = "Lcom/foo/Bar;->baz(ILjava/lang/String;)V";
+        let method = repo.find_method(sig).unwrap();
+        assert_eq!(method.name(), "baz");
+        assert_eq!(
+            method.descriptor().parameters_types(),
+            &[Type::Int, Type::Class("java/lang/String".to_string())]
+        );
+        assert!(repo.find_method("Lcom/foo/Bar;->baz(I)V").is_none());
+
+        for garbage in [
+            "garbage",
+            "Lcom/foo/Bar;->baz(ILjava/lang/String)V",
+            "LBar;->(I)V",
+        ] {
+            assert!(repo.find_method(garbage).is_none());
+            assert!(matches!(
+                repo.try_find_method(garbage),
+                Err(AnalysisError::BadSignature(_))
+            ));
+        }
+    }
+
+    #[test]
+    fn implementors_and_subclasses() {
+        let mut builder = DexBuilder::new();
+        let task = builder.class("LTask;", "Ljava/lang/Object;");
+        builder.class_flags(task, ACC_PUBLIC | ACC_INTERFACE | ACC_ABSTRACT);
+        let a = builder.class("LA;", "Ljava/lang/Object;");
+        builder.implements(a, "LTask;");
+        let base = builder.class("LBase;", "Ljava/lang/Object;");
+        builder.class_flags(base, ACC_PUBLIC | ACC_ABSTRACT);
+        builder.implements(base, "LTask;");
+        builder.class("LB;", "LBase;");
+        builder.class("LC;", "LA;");
+        builder.class("LD;", "LC;");
+        let dex = builder.build_dex();
+
+        let mut builder = DexBuilder::new();
+        let lib = builder.class("LLibTask;", "LA;");
+        builder.implements(lib, "LTask;");
+        let system = builder.build_dex();
+
+        let mut repo = Repo::new();
+        repo.register_dex(&system, true).unwrap();
+        repo.register_dex(&dex, false).unwrap();
+        repo.close_hierarchy();
+        let names = |uids: Vec<ClassUid>| -> Vec<String> {
+            uids.into_iter()
+                .map(|uid| {
+                    repo.iter_classes()
+                        .find(|class| class.uid() == uid)
+                        .unwrap()
+                        .name()
+                        .to_string()
+                })
+                .collect()
+        };
+
+        assert_eq!(
+            names(repo.implementors("Task", false)),
+            ["A", "B", "C", "D"]
+        );
+        assert_eq!(
+            names(repo.implementors("Task", true)),
+            ["A", "B", "C", "D", "LibTask"]
+        );
+        assert!(repo.implementors("Unknown", true).is_empty());
+
+        assert_eq!(names(repo.subclasses("A", false, false)), ["C"]);
+        assert_eq!(names(repo.subclasses("A", true, false)), ["C", "D"]);
+        assert_eq!(
+            names(repo.subclasses("A", true, true)),
+            ["C", "D", "LibTask"]
+        );
+        assert_eq!(names(repo.subclasses("Base", true, false)), ["B"]);
+    }
+
+    #[test]
+    fn multidex_report() {
+        let mut builder = DexBuilder::new();
+        let class = builder.class("LTest;", "Ljava/lang/Object;");
+        let value = builder.field("LTest;", "value", "I");
+        builder.instance_field(class, value);
+        let to_string =
+            builder.method("Ljava/lang/Object;", "toString", "Ljava/lang/String;", &[]) as u16;
+        // invoke-virtual {p0}, Ljava/lang/Object;->toString()Ljava/lang/String;
+        // return-void
+        let run = builder.method("LTest;", "run", "V", &[]);
+        builder.code(
+            class,
+            run,
+            ACC_PUBLIC,
+            1,
+            1,
+            1,
+            &[0x106e, to_string, 0x0000, 0x000e],
+        );
+        let dex = builder.build_dex();
+        let mut repo = Repo::new();
+        repo.register_dex(&dex, false).unwrap();
+
+        let counts = dex.reference_counts();
+        assert_eq!(counts.strings, dex.iter_string_ids().count());
+        assert_eq!(counts.types, dex.iter_type_ids().count());
+        assert_eq!(counts.fields, dex.iter_field_ids().count());
+        assert_eq!(counts.methods, dex.iter_method_ids().count());
+        assert_eq!((counts.fields, counts.methods), (1, 2));
+        assert_eq!(repo.multidex_report(), [(counts, false)]);
+    }
+
+    #[test]
+    fn clinit_cycle() {
+        let mut builder = DexBuilder::new();
+        let a = builder.class("LA;", "Ljava/lang/Object;");
+        let b = builder.class("LB;", "Ljava/lang/Object;");
+        let c = builder.class("LC;", "Ljava/lang/Object;");
+        let a_type = builder.type_("LA;") as u16;
+        let a_get = builder.method("LA;", "get", "V", &[]);
+        let b_get = builder.method("LB;", "get", "V", &[]);
+        builder.code(a, a_get, ACC_PUBLIC | ACC_STATIC, 0, 0, 0, &[0x000e]);
+        builder.code(b, b_get, ACC_PUBLIC | ACC_STATIC, 0, 0, 0, &[0x000e]);
+
+        // invoke-static {}, LB;->get()V
+        // return-void
+        let a_clinit = builder.method("LA;", "<clinit>", "V", &[]);
+        let insns = [0x0071, b_get as u16, 0x0000, 0x000e];
+        builder.code(a, a_clinit, ACC_STATIC, 0, 0, 0, &insns);
+        // invoke-static {}, LA;->get()V
+        // return-void
+        let b_clinit = builder.method("LB;", "<clinit>", "V", &[]);
+        let insns = [0x0071, a_get as u16, 0x0000, 0x000e];
+        builder.code(b, b_clinit, ACC_STATIC, 0, 0, 0, &insns);
+        // new-instance v0, LA;
+        // return-void
+        let c_clinit = builder.method("LC;", "<clinit>", "V", &[]);
+        builder.code(c, c_clinit, ACC_STATIC, 1, 0, 0, &[0x0022, a_type, 0x000e]);
+        let dex = builder.build_dex();
+        let repo = testing::repo(&dex);
+
+        let uid = |name: &str| repo.get_class_by_name(name).unwrap().uid();
+        let graph = repo.clinit_dependencies().unwrap();
+        let edges: Vec<_> = graph
+            .raw_edges()
+            .iter()
+            .map(|edge| (graph[edge.source()], graph[edge.target()]))
+            .collect();
+        assert_eq!(
+            edges,
+            [
+                (uid("A"), uid("B")),
+                (uid("B"), uid("A")),
+                (uid("C"), uid("A"))
+            ]
+        );
+        assert_eq!(
+            repo.clinit_topo_order().unwrap(),
+            [(uid("A"), true), (uid("B"), true), (uid("C"), false)]
+        );
+    }
+
+    #[test]
+    fn register_allocation_clones() {
+        let mut builder = DexBuilder::new();
+        let class = builder.class("LTest;", "Ljava/lang/Object;");
+        // const/4 v0, 1
+        // add-int v1, v0, v2
+        // return v1
+        let first = builder.method("LTest;", "first", "I", &["I"]);
+        builder.code(
+            class,
+            first,
+            ACC_PUBLIC | ACC_STATIC,
+            3,
+            1,
+            0,
+            &[0x1012, 0x0190, 0x0200, 0x010f],
+        );
+        // const/4 v2, 1
+        // add-int v0, v2, v3
+        // return v0
+        let second = builder.method("LTest;", "second", "I", &["I"]);
+        builder.code(
+            class,
+            second,
+            ACC_PUBLIC | ACC_STATIC,
+            4,
+            1,
+            0,
+            &[0x1212, 0x0090, 0x0302, 0x000f],
+        );
+        // const/4 v0, 2
+        // add-int v1, v0, v2
+        // return v1
+        let third = builder.method("LTest;", "third", "I", &["I"]);
+        builder.code(
+            class,
+            third,
+            ACC_PUBLIC | ACC_STATIC,
+            3,
+            1,
+            0,
+            &[0x2012, 0x0190, 0x0200, 0x010f],
+        );
+        let dex = builder.build_dex();
+        let repo = testing::repo(&dex);
+        let method = |name: &str| testing::method(&repo, name).1;
+        let (first, second, third) = (method("first"), method("second"), method("third"));
+
+        assert_eq!(
+            first.normalized_fingerprint().unwrap(),
+            second.normalized_fingerprint().unwrap()
+        );
+        assert_ne!(
+            first.normalized_fingerprint().unwrap(),
+            third.normalized_fingerprint().unwrap()
+        );
+        assert_eq!(
+            repo.find_clones(1.0).unwrap(),
+            [vec![first.uid(), second.uid()]]
+        );
+        // two of the four distinct instructions are shared
+        assert_eq!(
+            repo.find_clones(0.5).unwrap(),
+            [vec![first.uid(), second.uid(), third.uid()]]
+        );
+    }
+
+    #[test]
+    fn try_items_clones() {
+        let mut builder = DexBuilder::new();
+        let class = builder.class("LTest;", "Ljava/lang/Object;");
+        // nop
+        // return-void
+        let plain = builder.method("LTest;", "plain", "V", &[]);
+        builder.code(
+            class,
+            plain,
+            ACC_PUBLIC | ACC_STATIC,
+            0,
+            0,
+            0,
+            &[0x0000, 0x000e],
+        );
+        let guarded = builder.method("LTest;", "guarded", "V", &[]);
+        builder.code(
+            class,
+            guarded,
+            ACC_PUBLIC | ACC_STATIC,
+            0,
+            0,
+            0,
+            &[0x0000, 0x000e],
+        );
+        builder.catch_all(class, guarded, 0, 1, 1);
+        let dex = builder.build_dex();
+        let repo = testing::repo(&dex);
+        let method = |name: &str| testing::method(&repo, name).1;
+
+        assert_ne!(
+            method("plain").normalized_fingerprint().unwrap(),
+            method("guarded").normalized_fingerprint().unwrap()
+        );
+        assert!(repo.find_clones(1.0).unwrap().is_empty());
+    }
+
+    #[test]
+    fn non_transitive_clones() {
+        let mut builder = DexBuilder::new();
+        let class = builder.class("LTest;", "Ljava/lang/Object;");
+        // const/4 v0, n
+        // const/4 v0, n + 1
+        // const/4 v0, n + 2
+        // return-void
+        for n in 1..=3 {
+            let name = format!("m{n}");
+            let idx = builder.method("LTest;", &name, "V", &[]);
+            let insns = [
+                n << 12 | 0x12,
+                (n + 1) << 12 | 0x12,
+                (n + 2) << 12 | 0x12,
+                0x000e,
+            ];
+            builder.code(class, idx, ACC_PUBLIC | ACC_STATIC, 1, 0, 0, &insns);
+        }
+        let dex = builder.build_dex();
+        let repo = testing::repo(&dex);
+        let uid = |name: &str| testing::method(&repo, name).1.uid();
+
+        // m1 and m3 share two lines out of six, while m2 shares three lines
+        // out of five with each of them
+        assert_eq!(repo.find_clones(0.5).unwrap(), [vec![uid("m1"), uid("m2")]]);
+        assert_eq!(
+            repo.find_clones(0.3).unwrap(),
+            [vec![uid("m1"), uid("m2"), uid("m3")]]
+        );
+    }
+}
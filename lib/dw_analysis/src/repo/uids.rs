@@ -1,3 +1,4 @@
+use std::fmt;
 use std::num::NonZeroUsize;
 
 /// Unique id to identify a class in the repo
@@ -14,6 +15,12 @@ impl MethodUid {
     }
 }
 
+impl fmt::Display for MethodUid {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        self.0.fmt(f)
+    }
+}
+
 /// Unique id to identify a field in the repo
 #[derive(Debug, Clone, Copy, PartialOrd, Ord, PartialEq, Eq)]
 pub struct FieldUid(NonZeroUsize);
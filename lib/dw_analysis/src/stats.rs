@@ -1,40 +1,372 @@
 //! Utils functions to make simple heuristics on functions code.
 
-use crate::errors::AnalysisResult;
-use dw_dex::code::CodeItem;
-use dw_dex::instrs::Instr;
+use crate::controlflow::Cfg;
+use crate::errors::{AnalysisError, AnalysisResult};
+use crate::repo::{Class, Method, MethodDescr, Repo};
+use dw_dex::instrs::{Instr, Instruction};
+use dw_dex::registers::Reg;
 use dw_dex::{Dex, DexIndex};
-use std::collections::BTreeSet;
+use std::collections::BTreeMap;
 
-// Stubbed method looks like this:
+/// The shape of a stub method body, see [`Method::is_stub`].
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum StubReason {
+    /// The body only constructs and throws an exception.
+    ThrowOnly,
+    /// The body only returns a constant, such as `0` or `null`.
+    ReturnDefault,
+    /// The body only returns, without value.
+    Empty,
+}
+
+/// The message of the exceptions thrown by the stubbed methods of the
+/// Android SDK.
+const SDK_STUB_MESSAGE: &str = "Stub!";
+
+// Stubbed methods look like this one from the Android SDK:
 //    0000: invoke-direct {v2}, java/lang/Object-><init>()V
 //    0003: new-instance v0, Ljava/lang/RuntimeException;
 //    0005: const-string v1, "Stub!"
 //    0007: invoke-direct {v0, v1}, java/lang/RuntimeException-><init>(Ljava/lang/String;)V
-//    0010: throw v0
+//    000a: throw v0
 //
-// Determination of stubbed method relies on instructions number and
-// the presence of the string "Stub!".
-pub(crate) fn is_stub(code: &CodeItem, dex: &Dex) -> AnalysisResult<bool> {
-    let icount = code.instructions_count();
-    let is_stubbed = if icount < 10 {
-        let strings = used_strings(code, dex)?;
-        strings.contains("Stub!")
-    } else {
-        false
+// The leading call to a superclass constructor is ignored, then the
+// remaining instructions must make one of the three stub shapes.
+pub(crate) fn stub_reason(method: &Method, repo: &Repo) -> AnalysisResult<Option<StubReason>> {
+    let Some(code) = method.code() else {
+        return Ok(None);
     };
-    Ok(is_stubbed)
+    let code = code.read().unwrap();
+    let dex = method.dex();
+    let instrs: Vec<&Instr> = code
+        .iter_instructions()
+        .map(|instr| instr.instr())
+        .filter(|instr| !matches!(instr, Instr::Nop))
+        .collect();
+
+    let mut body = instrs.as_slice();
+    if let [Instr::InvokeDirect(_, init), rest @ ..] = body {
+        let init = MethodDescr::try_from((dex, init.get(dex)?))?;
+        let superclass = init.definer().class_name();
+        if init.name() == "<init>"
+            && repo
+                .is_typeable_as(&method.definer().class_name(), &superclass)
+                .unwrap_or(false)
+        {
+            body = rest;
+        }
+    }
+
+    Ok(match body {
+        [Instr::ReturnVoid] => Some(StubReason::Empty),
+        [constant, Instr::Return(r) | Instr::ReturnWide(r) | Instr::ReturnObject(r)]
+            if constant_register(constant).map(Reg::value) == Some(r.value()) =>
+        {
+            Some(StubReason::ReturnDefault)
+        }
+        [Instr::NewInstance(exception, _), init @ .., Instr::Throw(thrown)]
+            if exception.value() == thrown.value()
+                && is_constructor_call(init, *exception, dex)? =>
+        {
+            Some(StubReason::ThrowOnly)
+        }
+        _ => None,
+    })
 }
 
-pub(crate) fn used_strings(code: &CodeItem, dex: &Dex) -> AnalysisResult<BTreeSet<String>> {
-    let mut strings = BTreeSet::new();
-    for instr in code.iter_instructions() {
-        match instr.instr() {
-            Instr::ConstString(_, s) | Instr::ConstStringJumbo(_, s) => {
-                strings.insert(s.get(dex)?.to_string(dex)?);
+// Checks that a method loads the message of the Android SDK stubs.
+pub(crate) fn loads_sdk_stub_message(method: &Method) -> AnalysisResult<bool> {
+    let Some(code) = method.code() else {
+        return Ok(false);
+    };
+    let dex = method.dex();
+    for instr in code.read().unwrap().iter_instructions() {
+        if let Instr::ConstString(_, s) | Instr::ConstStringJumbo(_, s) = instr.instr() {
+            if s.get(dex)?.to_string(dex)? == SDK_STUB_MESSAGE {
+                return Ok(true);
             }
-            _ => (),
         }
     }
-    Ok(strings)
+    Ok(false)
+}
+
+// Returns the register written by a literal constant instruction.
+fn constant_register(instr: &Instr) -> Option<Reg> {
+    match instr {
+        Instr::Const4(r, _)
+        | Instr::Const16(r, _)
+        | Instr::Const(r, _)
+        | Instr::ConstHigh16(r, _)
+        | Instr::ConstWide16(r, _)
+        | Instr::ConstWide32(r, _)
+        | Instr::ConstWide(r, _)
+        | Instr::ConstWideHigh16(r, _) => Some(*r),
+        _ => None,
+    }
+}
+
+// Checks that the instructions only load constant arguments then call a
+// constructor on the `object` register.
+fn is_constructor_call(instrs: &[&Instr], object: Reg, dex: &Dex) -> AnalysisResult<bool> {
+    let [args @ .., Instr::InvokeDirect(regs, init)] = instrs else {
+        return Ok(false);
+    };
+    let constant_args = args.iter().all(|instr| {
+        constant_register(instr).is_some()
+            || matches!(instr, Instr::ConstString(..) | Instr::ConstStringJumbo(..))
+    });
+    let on_object = regs.iter().next().map(Reg::value) == Some(object.value());
+    Ok(constant_args && on_object && init.get(dex)?.name(dex)? == "<init>")
+}
+
+/// Simple metrics about a class definition.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct ClassMetrics {
+    pub methods: usize,
+    pub fields: usize,
+    /// Size in bytes of the code of the class methods, only methods with
+    /// bodies being considered.
+    pub code_size: usize,
+    pub synthetic: bool,
+}
+
+/// Computes the [`ClassMetrics`] of a class. Undefined classes have no
+/// code and are not considered synthetic.
+#[must_use]
+pub fn class_metrics(class: &Class, repo: &Repo) -> ClassMetrics {
+    let code_size = class
+        .iter_methods(repo)
+        .filter_map(|method| method.code())
+        .map(|code| {
+            let code = code.read().unwrap();
+            code.iter_instructions()
+                .map(Instruction::size)
+                .sum::<usize>()
+                * 2
+        })
+        .sum();
+    ClassMetrics {
+        methods: class.iter_methods(repo).count(),
+        fields: class.iter_fields(repo).count(),
+        code_size,
+        synthetic: class.is_defined() && class.is_synthetic(),
+    }
+}
+
+/// Control flow complexity metrics of a method.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Complexity {
+    /// McCabe's cyclomatic complexity, computed as `edges - nodes + 2` over
+    /// the control flow graph (exceptional edges included).
+    pub cyclomatic: usize,
+    pub basic_blocks: usize,
+    pub instructions: usize,
+    /// The maximum number of nested loops, 0 for methods without loops.
+    pub loop_depth: usize,
+}
+
+/// Computes the [`Complexity`] of a method.
+///
+/// # Errors
+///
+/// This function returns an error if the method has no code or if its
+/// control flow graph cannot be built.
+pub fn method_complexity(method: &Method) -> AnalysisResult<Complexity> {
+    let cfg = Cfg::build(method)?;
+    let instructions = method
+        .code()
+        .ok_or(AnalysisError::NoCode)?
+        .read()
+        .unwrap()
+        .instructions_count();
+
+    let nodes = cfg.inner.node_count();
+    let edges = cfg.inner.edge_count();
+
+    // the depth of a loop is the number of loops containing its header
+    let loops = cfg.natural_loops();
+    let loop_depth = loops
+        .iter()
+        .map(|l| loops.iter().filter(|o| o.body.contains(&l.header)).count())
+        .max()
+        .unwrap_or(0);
+
+    Ok(Complexity {
+        cyclomatic: (edges + 2).saturating_sub(nodes),
+        basic_blocks: nodes,
+        instructions,
+        loop_depth,
+    })
+}
+
+/// Lower bounds of the cyclomatic complexity buckets of
+/// [`ComplexityReport::histogram`].
+pub const COMPLEXITY_BUCKETS: [usize; 5] = [1, 6, 11, 21, 51];
+
+/// Complexity metrics of the application methods of a repository.
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct ComplexityReport {
+    /// Metrics of each method with code, keyed by method descriptor.
+    pub methods: BTreeMap<String, Complexity>,
+    /// Number of methods per cyclomatic complexity bucket (see
+    /// [`COMPLEXITY_BUCKETS`]).
+    pub histogram: [usize; COMPLEXITY_BUCKETS.len()],
+}
+
+/// Computes the [`ComplexityReport`] of the methods of non-system classes.
+/// Methods whose control flow graph cannot be built are skipped.
+#[must_use]
+pub fn complexity_report(repo: &Repo) -> ComplexityReport {
+    let mut report = ComplexityReport::default();
+    for class in repo.iter_classes().filter(|c| !c.is_system()) {
+        for method in class.iter_methods(repo).filter(|m| m.code().is_some()) {
+            let complexity = match method_complexity(method) {
+                Ok(complexity) => complexity,
+                Err(err) => {
+                    log::warn!("skipping method {}: {err}", method.descriptor());
+                    continue;
+                }
+            };
+            let bucket = COMPLEXITY_BUCKETS
+                .iter()
+                .rposition(|bound| complexity.cyclomatic >= *bound)
+                .unwrap_or(0);
+            report.histogram[bucket] += 1;
+            report
+                .methods
+                .insert(method.descriptor().to_string(), complexity);
+        }
+    }
+    report
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::testing::{self, DexBuilder, ACC_PUBLIC, ACC_STATIC};
+
+    #[test]
+    fn small_class_metrics() {
+        const ACC_ABSTRACT: u32 = 0x400;
+
+        let mut builder = DexBuilder::new();
+        let class = builder.class("LTest;", "Ljava/lang/Object;");
+        for name in ["a", "b"] {
+            let field = builder.field("LTest;", name, "I");
+            builder.instance_field(class, field);
+        }
+        let run = builder.method("LTest;", "run", "V", &[]);
+        builder.code(
+            class,
+            run,
+            ACC_PUBLIC | ACC_STATIC,
+            1,
+            0,
+            0,
+            &[
+                0x0012, // const/4 v0, 0
+                0x000e, // return-void
+            ],
+        );
+        let get = builder.method("LTest;", "get", "I", &[]);
+        builder.code(
+            class,
+            get,
+            ACC_PUBLIC,
+            2,
+            1,
+            0,
+            &[
+                0x0013, 0x002a, // const/16 v0, 42
+                0x000f, // return v0
+            ],
+        );
+        let todo = builder.method("LTest;", "todo", "V", &[]);
+        builder.declare(class, todo, ACC_PUBLIC | ACC_ABSTRACT);
+
+        let dex = builder.build_dex();
+        let repo = testing::repo(&dex);
+
+        let class = repo.iter_classes().find(|c| c.name() == "Test").unwrap();
+        assert_eq!(
+            class_metrics(class, &repo),
+            ClassMetrics {
+                methods: 3,
+                fields: 2,
+                code_size: 10,
+                synthetic: false,
+            }
+        );
+    }
+
+    #[test]
+    fn method_complexities() {
+        let mut builder = DexBuilder::new();
+        let class = builder.class("LTest;", "Ljava/lang/Object;");
+        let nested_ifs = builder.method("LTest;", "nestedIfs", "V", &["I"]);
+        builder.code(
+            class,
+            nested_ifs,
+            ACC_PUBLIC | ACC_STATIC,
+            2,
+            1,
+            0,
+            &[
+                0x0138, 0x0005, // 0: if-eqz v1, +5
+                0x0139, 0x0003, // 2: if-nez v1, +3
+                0x1012, // 4: const/4 v0, 1
+                0x000e, // 5: return-void
+            ],
+        );
+        let nested_loops = builder.method("LTest;", "nestedLoops", "V", &[]);
+        builder.code(
+            class,
+            nested_loops,
+            ACC_PUBLIC | ACC_STATIC,
+            2,
+            0,
+            0,
+            &[
+                0x1012, // 0: const/4 v0, 1
+                0x0038, 0x0009, // 1: if-eqz v0, +9
+                0x1112, // 3: const/4 v1, 1
+                0x0138, 0x0005, // 4: if-eqz v1, +5
+                0x01d8, 0xff01, // 6: add-int/lit8 v1, v1, -1
+                0xfc28, // 8: goto -4
+                0xf828, // 9: goto -8
+                0x000e, // 10: return-void
+            ],
+        );
+        let dex = builder.build_dex();
+        let repo = testing::repo(&dex);
+        let complexity = |name: &str| {
+            let (_, method) = testing::method(&repo, name);
+            method_complexity(method).unwrap()
+        };
+
+        // 4 blocks, 5 edges: 2 decisions
+        assert_eq!(
+            complexity("nestedIfs"),
+            Complexity {
+                cyclomatic: 3,
+                basic_blocks: 4,
+                instructions: 4,
+                loop_depth: 0,
+            }
+        );
+        // 7 blocks, 8 edges: 2 decisions
+        assert_eq!(
+            complexity("nestedLoops"),
+            Complexity {
+                cyclomatic: 3,
+                basic_blocks: 7,
+                instructions: 8,
+                loop_depth: 2,
+            }
+        );
+
+        let report = complexity_report(&repo);
+        assert_eq!(report.methods.len(), 2);
+        assert_eq!(report.histogram, [2, 0, 0, 0, 0]);
+    }
 }
@@ -0,0 +1,71 @@
+//! Helpers to assemble minimal dex files and manifests for unit tests.
+
+use crate::repo::{Class, Method, Repo};
+pub(crate) use dw_dex::testing::{
+    DexBuilder, ACC_ABSTRACT, ACC_INTERFACE, ACC_PRIVATE, ACC_PUBLIC, ACC_STATIC,
+};
+use dw_dex::Dex;
+use dw_resources::manifest::{self, Manifest};
+use dw_resources::testing::{xml, Node};
+
+/// Builds a repository holding the classes of `dex`, with a closed
+/// hierarchy.
+pub(crate) fn repo(dex: &Dex) -> Repo<'_> {
+    let mut repo = Repo::new();
+    repo.register_dex(dex, false).unwrap();
+    repo.close_hierarchy();
+    repo
+}
+
+/// Returns the first method of a repository named `name`, along with its
+/// class.
+pub(crate) fn method<'r>(repo: &'r Repo, name: &str) -> (&'r Class<'r>, &'r Method<'r>) {
+    repo.iter_classes_methods()
+        .find(|(_, m)| m.name() == name)
+        .unwrap()
+}
+
+/// Assembles a binary manifest for `package`, with an optional
+/// application class name and a list of `(tag, name)` components
+/// declared under the `<application>` node.
+pub(crate) fn manifest(
+    package: &str,
+    application: Option<&str>,
+    components: &[(&str, &str)],
+) -> Manifest {
+    let mut strings: Vec<&str> = Vec::new();
+    let mut intern = |s| match strings.iter().position(|x| *x == s) {
+        Some(i) => i as u32,
+        None => {
+            strings.push(s);
+            strings.len() as u32 - 1
+        }
+    };
+
+    // string typed attributes
+    let string = |name, value| (name, 0x03, value);
+    let name = intern("name");
+    let manifest_tag = intern("manifest");
+    let package = [string(intern("package"), intern(package))];
+    let application_tag = intern("application");
+    let application: Vec<_> = application
+        .map(|app| string(name, intern(app)))
+        .into_iter()
+        .collect();
+    let components: Vec<_> = components
+        .iter()
+        .map(|&(tag, component)| (intern(tag), [string(name, intern(component))]))
+        .collect();
+
+    let mut nodes = vec![
+        Node::Start(manifest_tag, &package),
+        Node::Start(application_tag, &application),
+    ];
+    for (tag, attrs) in &components {
+        nodes.push(Node::Start(*tag, attrs));
+        nodes.push(Node::End(*tag));
+    }
+    nodes.push(Node::End(application_tag));
+    nodes.push(Node::End(manifest_tag));
+    manifest::parse(&xml(&strings, &nodes)).expect("valid test manifest")
+}
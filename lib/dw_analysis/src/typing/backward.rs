@@ -4,12 +4,15 @@ use crate::errors::{AnalysisError, AnalysisResult};
 use crate::repo::{Class, Method, Repo};
 use crate::typing::errors::TypeError;
 use crate::typing::types::{
-    AbstractType, JAVA_LANG_CLASS, JAVA_LANG_OBJECT, JAVA_LANG_STRING, JAVA_LANG_THROWABLE,
+    AbstractType, JAVA_LANG_CLASS, JAVA_LANG_INVOKE_METHOD_HANDLE, JAVA_LANG_INVOKE_METHOD_TYPE,
+    JAVA_LANG_OBJECT, JAVA_LANG_STRING, JAVA_LANG_THROWABLE,
 };
 use crate::typing::{tc, State};
 use dw_dex::instrs::Instr;
-use dw_dex::types::Type;
-use dw_dex::{Dex, DexIndex, WithDex};
+use dw_dex::methods::MethodIdItem;
+use dw_dex::registers::Reg;
+use dw_dex::types::{ProtoIdItem, Type};
+use dw_dex::{Dex, DexIndex, Index, WithDex};
 use std::convert::TryFrom;
 
 impl<'a> AbstractBackwardState<'a> for State {
@@ -86,6 +89,14 @@ impl<'a> AbstractBackwardState<'a> for State {
     fn transfer_branch(&mut self, branch: &Branch, repo: &Repo) -> AnalysisResult<()> {
         use AbstractType::Null;
 
+        // results do not flow across basic blocks boundaries, except from
+        // the instruction that produced them to its fallthrough successor
+        if self.last_result.is_some()
+            && !matches!(branch, Branch::InvokeSuccess | Branch::ArrayAccessSuccess)
+        {
+            return Err(TypeError::MissingResult.into());
+        }
+
         match branch {
             Branch::IfTrue(_, _, _)
             | Branch::IfFalse(_, _, _)
@@ -1175,7 +1186,8 @@ impl<'a> AbstractBackwardState<'a> for State {
                     meth_descr => match last_result {
                         None => Ok(()), // result will not been used
                         Some(status_typ) => {
-                            let meth_ret_typ = AbstractType::try_from(&meth_descr)?;
+                            let meth_ret_typ =
+                                AbstractType::from_invoke_result(&meth_descr, method, dex)?;
                             let m_typ = status_typ.meet(meth_ret_typ.clone(), repo)?;
                             tc!(meth_ret_typ <: &m_typ ; repo)
                         }
@@ -1292,7 +1304,8 @@ impl<'a> AbstractBackwardState<'a> for State {
                     meth_descr => match last_result {
                         None => Ok(()), // result will not been used
                         Some(status_typ) => {
-                            let meth_ret_typ = AbstractType::try_from(&meth_descr)?;
+                            let meth_ret_typ =
+                                AbstractType::from_invoke_result(&meth_descr, method, dex)?;
                             let m_typ = status_typ.meet(meth_ret_typ.clone(), repo)?;
                             tc!(meth_ret_typ <: &m_typ ; repo)
                         }
@@ -1835,13 +1848,31 @@ impl<'a> AbstractBackwardState<'a> for State {
                 self.write_pair(*src2, m_typ2)
             }
 
-            Instr::InvokeCustom(_, _)
-            | Instr::InvokeCustomRange(_, _)
-            | Instr::InvokePolymorphic(_, _, _)
-            | Instr::InvokePolymorphicRange(_, _, _)
-            | Instr::ConstMethodHandle(_, _)
-            | Instr::ConstMethodType(_, _) => {
-                unimplemented!("support for {:?}", instr)
+            Instr::InvokePolymorphic(args, meth, proto) => {
+                self.backward_invoke_polymorphic(args.iter(), *meth, *proto, last_result, dex, repo)
+            }
+            Instr::InvokePolymorphicRange(args, meth, proto) => {
+                self.backward_invoke_polymorphic(args.iter(), *meth, *proto, last_result, dex, repo)
+            }
+            Instr::InvokeCustom(args, call_site) => {
+                let proto = call_site.get(dex)?.method_type(dex)?;
+                self.backward_invoke_proto(args.iter(), proto, last_result, dex, repo)
+            }
+            Instr::InvokeCustomRange(args, call_site) => {
+                let proto = call_site.get(dex)?.method_type(dex)?;
+                self.backward_invoke_proto(args.iter(), proto, last_result, dex, repo)
+            }
+            Instr::ConstMethodHandle(dst, _) => {
+                let dst_typ = self.read_reg(*dst)?.clone();
+                let m_typ = dst_typ.meet(JAVA_LANG_OBJECT.clone(), repo)?;
+                tc!(JAVA_LANG_INVOKE_METHOD_HANDLE <: &m_typ ; repo)?;
+                self.write_reg(*dst, Top)
+            }
+            Instr::ConstMethodType(dst, _) => {
+                let dst_typ = self.read_reg(*dst)?.clone();
+                let m_typ = dst_typ.meet(JAVA_LANG_OBJECT.clone(), repo)?;
+                tc!(JAVA_LANG_INVOKE_METHOD_TYPE <: &m_typ ; repo)?;
+                self.write_reg(*dst, Top)
             }
 
             Instr::PackedSwitchPayload(_, _)
@@ -1851,50 +1882,21 @@ impl<'a> AbstractBackwardState<'a> for State {
     }
 
     fn entry_reached(&self, class: &Class, method: &Method, repo: &Repo) -> AnalysisResult<()> {
-        let nb_registers = method
-            .code()
-            .as_ref()
-            .unwrap()
-            .read()
-            .unwrap()
-            .registers_size();
+        let layout = method.register_layout()?;
+        let nb_registers = layout.registers_size;
+        let mut registers = vec![AbstractType::Bottom; nb_registers];
 
-        let mut registers = Vec::new();
-        for _ in 0..nb_registers {
-            registers.push(AbstractType::Bottom);
-        }
-
-        let nb_param_registers: usize = method
-            .parameters_types()
-            .iter()
-            .map(|t| match t {
-                Type::Long | Type::Double => 2,
-                _ => 1,
-            })
-            .sum();
-        let first_param_reg = nb_registers - nb_param_registers;
-
-        if !method.is_static() {
+        if let Some(this) = layout.this {
             let this_name = class.name().to_string();
-            registers[first_param_reg - 1] = AbstractType::object_singleton(this_name);
+            registers[this.value() as usize] = AbstractType::object_singleton(this_name);
         }
 
-        let mut param_reg = first_param_reg;
-
-        for type_descr in method.parameters_types() {
+        for (reg, type_descr, is_wide) in &layout.parameters {
             let typ = AbstractType::try_from(type_descr)?;
-            match typ {
-                AbstractType::Long | AbstractType::Double => {
-                    registers[param_reg] = typ.clone();
-                    param_reg += 1;
-                    registers[param_reg] = typ;
-                    param_reg += 1;
-                }
-                _ => {
-                    registers[param_reg] = typ;
-                    param_reg += 1;
-                }
+            if *is_wide {
+                registers[reg.next().value() as usize] = typ.clone();
             }
+            registers[reg.value() as usize] = typ;
         }
 
         if self.registers.len() != nb_registers {
@@ -1910,3 +1912,90 @@ impl<'a> AbstractBackwardState<'a> for State {
         Ok(())
     }
 }
+
+impl State {
+    /// Typechecks the receiver of a signature polymorphic invocation against
+    /// the method definer (`MethodHandle` or `VarHandle`), then the other
+    /// arguments against the prototype of the call site.
+    fn backward_invoke_polymorphic(
+        &mut self,
+        mut args_it: impl Iterator<Item = Reg>,
+        meth: Index<MethodIdItem>,
+        proto: Index<ProtoIdItem>,
+        last_result: Option<AbstractType>,
+        dex: &Dex,
+        repo: &Repo,
+    ) -> AnalysisResult<()> {
+        use AbstractType::Null;
+
+        let this_reg = args_it.next().ok_or(TypeError::MissingThisArgument)?;
+        let this_reg_typ = self.read_reg(this_reg)?.clone();
+
+        let method = meth.get(dex)?;
+        let definer_typ = AbstractType::try_from(&method.definer(dex)?)?;
+        tc!(definer_typ <: &*JAVA_LANG_OBJECT ; repo)?;
+        let m_typ = definer_typ.meet(this_reg_typ, repo)?;
+        tc!(Null <: &m_typ; repo)?;
+        self.write_reg(this_reg, m_typ)?;
+
+        self.backward_invoke_proto(args_it, proto.get(dex)?, last_result, dex, repo)
+    }
+
+    /// Typechecks invocation arguments against the parameters of `proto`,
+    /// and the expected result (if any) against its return type.
+    fn backward_invoke_proto(
+        &mut self,
+        mut args_it: impl Iterator<Item = Reg>,
+        proto: &ProtoIdItem,
+        last_result: Option<AbstractType>,
+        dex: &Dex,
+        repo: &Repo,
+    ) -> AnalysisResult<()> {
+        use AbstractType::{Array, Double, Long, Null, Object};
+
+        for p_descr in &proto.parameters_types(dex)? {
+            let p_typ = AbstractType::try_from(p_descr)?;
+            let arg_reg = args_it.next().ok_or(TypeError::BadArity)?;
+            match p_typ {
+                Double | Long => {
+                    args_it.next(); // consume the following register as it's a pair
+                    let arg_reg_typ = self.read_pair(arg_reg)?.clone();
+                    let m_typ = arg_reg_typ.meet(p_typ.clone(), repo)?;
+                    tc!(p_typ <: &m_typ ; repo)?;
+                    self.write_pair(arg_reg, m_typ)?;
+                }
+                Object(_) | Array(_, _) => {
+                    let arg_reg_typ = self.read_reg(arg_reg)?.clone();
+                    let m_typ = p_typ.meet(arg_reg_typ, repo)?;
+                    tc!(Null <: &m_typ; repo)?;
+                    self.write_reg(arg_reg, m_typ)?;
+                }
+                _ => {
+                    let arg_reg_typ = self.read_reg(arg_reg)?.clone();
+                    let m_typ = arg_reg_typ.meet(p_typ.clone(), repo)?;
+                    tc!(p_typ <: &m_typ ; repo)?;
+                    self.write_reg(arg_reg, m_typ)?;
+                }
+            }
+        }
+        // all args should have been consumed
+        if args_it.next().is_some() {
+            return Err(TypeError::BadArity.into());
+        }
+
+        match proto.return_type(dex)? {
+            Type::Void => match last_result {
+                None => Ok(()),
+                Some(_) => Err(TypeError::MissingResult.into()),
+            },
+            ret_descr => match last_result {
+                None => Ok(()), // result will not been used
+                Some(status_typ) => {
+                    let ret_typ = AbstractType::try_from(&ret_descr)?;
+                    let m_typ = status_typ.meet(ret_typ.clone(), repo)?;
+                    tc!(ret_typ <: &m_typ ; repo)
+                }
+            },
+        }
+    }
+}
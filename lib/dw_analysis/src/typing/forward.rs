@@ -4,70 +4,37 @@ use crate::errors::{AnalysisError, AnalysisResult};
 use crate::repo::{Class, Method, Repo};
 use crate::typing::errors::TypeError;
 use crate::typing::types::{
-    AbstractType, JAVA_LANG_CLASS, JAVA_LANG_OBJECT, JAVA_LANG_STRING, JAVA_LANG_THROWABLE,
+    AbstractType, JAVA_LANG_CLASS, JAVA_LANG_INVOKE_METHOD_HANDLE, JAVA_LANG_INVOKE_METHOD_TYPE,
+    JAVA_LANG_OBJECT, JAVA_LANG_STRING, JAVA_LANG_THROWABLE,
 };
-use crate::typing::{tc, State};
+use crate::typing::{tc, State, TypingContext};
 use dw_dex::instrs::Instr;
-use dw_dex::types::Type;
-use dw_dex::{Dex, DexIndex, WithDex};
+use dw_dex::methods::MethodIdItem;
+use dw_dex::registers::Reg;
+use dw_dex::types::{ProtoIdItem, Type};
+use dw_dex::{Dex, DexIndex, Index, WithDex};
 use std::convert::TryFrom;
 
 impl<'a> AbstractForwardState<'a> for State {
-    type Context<'c> = Repo<'c>;
+    type Context<'c> = TypingContext<'c>;
     type Error = AnalysisError;
 
     fn init(method: &Method, class: &Class) -> AnalysisResult<Self> {
-        // Note1: method registers layout:
-        // [...local registers ...]
-        // ['this' register (if method is not static)]
-        // [...parameters...]
-
-        // Note2: nb_registers = local_regs + this_reg + param_regs
-
-        let nb_registers = method
-            .code()
-            .as_ref()
-            .unwrap()
-            .read()
-            .unwrap()
-            .registers_size();
-
-        let mut registers = Vec::new();
-        for _ in 0..nb_registers {
-            registers.push(AbstractType::Top);
-        }
+        let layout = method.register_layout()?;
+        let nb_registers = layout.registers_size;
+        let mut registers = vec![AbstractType::Top; nb_registers];
 
-        let nb_param_registers: usize = method
-            .parameters_types()
-            .iter()
-            .map(|t| match t {
-                Type::Long | Type::Double => 2,
-                _ => 1,
-            })
-            .sum();
-        let first_param_reg = nb_registers - nb_param_registers;
-
-        if !method.is_static() {
+        if let Some(this) = layout.this {
             let this_name = class.name().to_string();
-            registers[first_param_reg - 1] = AbstractType::object_singleton(this_name);
+            registers[this.value() as usize] = AbstractType::object_singleton(this_name);
         }
 
-        let mut param_reg = first_param_reg;
-
-        for type_descr in method.parameters_types() {
+        for (reg, type_descr, is_wide) in &layout.parameters {
             let typ = AbstractType::try_from(type_descr)?;
-            match typ {
-                AbstractType::Long | AbstractType::Double => {
-                    registers[param_reg] = typ.clone();
-                    param_reg += 1;
-                    registers[param_reg] = typ;
-                    param_reg += 1;
-                }
-                _ => {
-                    registers[param_reg] = typ;
-                    param_reg += 1;
-                }
+            if *is_wide {
+                registers[reg.next().value() as usize] = typ.clone();
             }
+            registers[reg.value() as usize] = typ;
         }
 
         let expected = match method.return_type() {
@@ -86,21 +53,22 @@ impl<'a> AbstractForwardState<'a> for State {
         })
     }
 
-    fn join(&mut self, other: &Self, repo: &Repo) -> AnalysisResult<()> {
+    fn join(&mut self, other: &Self, ctx: &TypingContext) -> AnalysisResult<()> {
+        let max = ctx.options.max_object_types;
         if self.registers.len() != other.registers.len() {
             return Err(TypeError::IncompatibleStates.into());
         }
         for i in 0..self.registers.len() {
             let reg1 = self.registers[i].clone();
             let reg2 = other.registers[i].clone();
-            self.registers[i] = reg1.join(reg2, repo)?;
+            self.registers[i] = reg1.join(reg2, ctx.repo, max)?;
         }
 
         self.last_exception = match (&self.last_exception, &other.last_exception) {
             (Some(st1), Some(st2)) => {
                 let t1 = st1.clone();
                 let t2 = st2.clone();
-                Some(t1.join(t2, repo)?)
+                Some(t1.join(t2, ctx.repo, max)?)
             }
             _ => None,
         };
@@ -109,7 +77,7 @@ impl<'a> AbstractForwardState<'a> for State {
             (Some(st1), Some(st2)) => {
                 let t1 = st1.clone();
                 let t2 = st2.clone();
-                Some(t1.join(t2, repo)?)
+                Some(t1.join(t2, ctx.repo, max)?)
             }
             _ => None,
         };
@@ -119,7 +87,15 @@ impl<'a> AbstractForwardState<'a> for State {
         Ok(())
     }
 
-    fn transfer_branch(&mut self, branch: &Branch, repo: &Repo) -> AnalysisResult<()> {
+    fn transfer_branch(&mut self, branch: &Branch, ctx: &TypingContext) -> AnalysisResult<()> {
+        let repo = ctx.repo;
+
+        // results do not flow across basic blocks boundaries, except from
+        // the instruction that produced them to its fallthrough successor
+        if !matches!(branch, Branch::InvokeSuccess | Branch::ArrayAccessSuccess) {
+            self.last_result = None;
+        }
+
         match branch {
             Branch::IfTrue(_, _, _)
             | Branch::IfFalse(_, _, _)
@@ -150,9 +126,16 @@ impl<'a> AbstractForwardState<'a> for State {
     #[allow(clippy::cognitive_complexity)]
     #[allow(clippy::too_many_lines)]
     #[allow(clippy::enum_glob_use)]
-    fn transfer_instr(&mut self, instr: &Instr, dex: &Dex, repo: &Repo) -> AnalysisResult<()> {
+    fn transfer_instr(
+        &mut self,
+        instr: &Instr,
+        dex: &Dex,
+        ctx: &TypingContext,
+    ) -> AnalysisResult<()> {
         use AbstractType::*;
 
+        let repo = ctx.repo;
+
         // save last status registers, and reset them to the 'default' value (None)
         let last_exception = std::mem::replace(&mut self.last_exception, None);
         let last_result = std::mem::replace(&mut self.last_result, None);
@@ -397,7 +380,7 @@ impl<'a> AbstractForwardState<'a> for State {
             Instr::IfEq(src1, src2, _) | Instr::IfNe(src1, src2, _) => {
                 let src_typ1 = self.read_reg(*src1)?.clone();
                 let src_typ2 = self.read_reg(*src2)?.clone();
-                let src_typ = src_typ1.join(src_typ2, repo)?;
+                let src_typ = src_typ1.join(src_typ2, repo, ctx.options.max_object_types)?;
                 tc!(src_typ <: &JoinZero; repo)
             }
             Instr::IfLt(src1, src2, _)
@@ -406,7 +389,7 @@ impl<'a> AbstractForwardState<'a> for State {
             | Instr::IfLe(src1, src2, _) => {
                 let src_typ1 = self.read_reg(*src1)?.clone();
                 let src_typ2 = self.read_reg(*src2)?.clone();
-                let src_typ = src_typ1.join(src_typ2, repo)?;
+                let src_typ = src_typ1.join(src_typ2, repo, ctx.options.max_object_types)?;
                 tc!(src_typ <: &Integer; repo)
             }
 
@@ -426,12 +409,12 @@ impl<'a> AbstractForwardState<'a> for State {
                 let idx_typ = self.read_reg(*idx)?;
                 tc!(idx_typ <: &Integer ; repo)?;
                 let arr_typ = self.read_reg(*arr)?;
-                match arr_typ {
-                    Array(1, elt_typ) if elt_typ.as_ref() == &Integer => {
-                        self.write_reg(*dst, Integer)
+                match arr_typ.clone() {
+                    Array(1, elt_typ) => {
+                        tc!(elt_typ <: &Join32 ; repo)?;
+                        self.write_reg(*dst, *elt_typ)
                     }
-                    Array(1, elt_typ) if elt_typ.as_ref() == &Float => self.write_reg(*dst, Float),
-                    Null => self.write_reg(*dst, Integer),
+                    _ if arr_typ.subseteq(&Null, repo)? => self.write_reg(*dst, Meet32),
                     _ => Err(TypeError::ExpectedArray.into()),
                 }
             }
@@ -443,10 +426,11 @@ impl<'a> AbstractForwardState<'a> for State {
                 tc!(idx_typ <: &Integer ; repo)?;
                 let arr_typ = self.read_reg(*arr)?;
                 match arr_typ {
-                    Array(1, elt_typ) if elt_typ.as_ref() == &Integer => {
+                    Array(1, elt_typ) => {
+                        tc!(elt_typ <: &Integer ; repo)?;
                         self.write_reg(*dst, Integer)
                     }
-                    Null => self.write_reg(*dst, Integer),
+                    _ if arr_typ.subseteq(&Null, repo)? => self.write_reg(*dst, Integer),
                     _ => Err(TypeError::ExpectedArray.into()),
                 }
             }
@@ -454,7 +438,6 @@ impl<'a> AbstractForwardState<'a> for State {
                 let idx_typ = self.read_reg(*idx)?;
                 tc!(idx_typ <: &Integer ; repo)?;
                 let arr_typ = self.read_reg(*arr)?;
-                tc!(idx_typ <: &Integer ; repo)?;
                 match arr_typ {
                     Array(1, elt_typ) => {
                         tc!(elt_typ <: &Join64 ; repo)?;
@@ -487,13 +470,11 @@ impl<'a> AbstractForwardState<'a> for State {
                 let arr_typ = self.read_reg(*arr)?;
                 let src_typ = self.read_reg(*src)?;
                 match &arr_typ {
-                    Null => {
-                        tc!(src_typ <: &Join32 ; repo)
-                    }
                     Array(1, elt_typ) => {
                         tc!(elt_typ <: &Join32 ; repo)?;
                         tc!(src_typ <: elt_typ ; repo)
                     }
+                    _ if arr_typ.subseteq(&Null, repo)? => tc!(src_typ <: &Join32 ; repo),
                     _ => Err(TypeError::ExpectedArray.into()),
                 }
             }
@@ -506,13 +487,11 @@ impl<'a> AbstractForwardState<'a> for State {
                 let arr_typ = self.read_reg(*arr)?;
                 let src_typ = self.read_reg(*src)?;
                 match &arr_typ {
-                    Null => {
-                        tc!(src_typ <: &Integer ; repo)
-                    }
                     Array(1, elt_typ) => {
                         tc!(elt_typ <: &Integer ; repo)?;
                         tc!(src_typ <: elt_typ ; repo)
                     }
+                    _ if arr_typ.subseteq(&Null, repo)? => tc!(src_typ <: &Integer ; repo),
                     _ => Err(TypeError::ExpectedArray.into()),
                 }
             }
@@ -522,13 +501,11 @@ impl<'a> AbstractForwardState<'a> for State {
                 let arr_typ = self.read_reg(*arr)?;
                 let src_typ = self.read_pair(*src)?;
                 match &arr_typ {
-                    Null => {
-                        tc!(src_typ <: &Join64 ; repo)
-                    }
                     Array(1, elt_typ) => {
                         tc!(elt_typ <: &Join64 ; repo)?;
                         tc!(src_typ <: elt_typ ; repo)
                     }
+                    _ if arr_typ.subseteq(&Null, repo)? => tc!(src_typ <: &Join64 ; repo),
                     _ => Err(TypeError::ExpectedArray.into()),
                 }
             }
@@ -538,9 +515,6 @@ impl<'a> AbstractForwardState<'a> for State {
                 let src_typ = self.read_reg(*src)?;
                 tc!(idx_typ <: &Integer ; repo)?;
                 match &arr_typ {
-                    Null => {
-                        tc!(src_typ <: &*JAVA_LANG_OBJECT ; repo)
-                    }
                     Array(1, elt_typ) => {
                         tc!(elt_typ <: &*JAVA_LANG_OBJECT ; repo)?;
                         tc!(src_typ <: elt_typ ; repo)
@@ -549,6 +523,9 @@ impl<'a> AbstractForwardState<'a> for State {
                         assert!(n > &1);
                         tc!(src_typ <: &Array(n - 1, elt_typ.clone()) ; repo)
                     }
+                    _ if arr_typ.subseteq(&Null, repo)? => {
+                        tc!(src_typ <: &*JAVA_LANG_OBJECT ; repo)
+                    }
                     _ => Err(TypeError::ExpectedArray.into()),
                 }
             }
@@ -880,7 +857,7 @@ impl<'a> AbstractForwardState<'a> for State {
                 // saving return type if non void
                 let meth_descr = method.return_type(dex)?;
                 if meth_descr != Type::Void {
-                    let meth_ret_typ = AbstractType::try_from(&meth_descr)?;
+                    let meth_ret_typ = AbstractType::from_invoke_result(&meth_descr, method, dex)?;
                     self.last_result = Some(meth_ret_typ);
                 }
 
@@ -957,7 +934,7 @@ impl<'a> AbstractForwardState<'a> for State {
                 // saving return type if non void
                 let meth_descr = method.return_type(dex)?;
                 if meth_descr != Type::Void {
-                    let meth_ret_typ = AbstractType::try_from(&meth_descr)?;
+                    let meth_ret_typ = AbstractType::from_invoke_result(&meth_descr, method, dex)?;
                     self.last_result = Some(meth_ret_typ);
                 }
 
@@ -1270,13 +1247,25 @@ impl<'a> AbstractForwardState<'a> for State {
                 self.write_pair(*bid, Double)
             }
 
-            Instr::InvokeCustom(_, _)
-            | Instr::InvokeCustomRange(_, _)
-            | Instr::InvokePolymorphic(_, _, _)
-            | Instr::InvokePolymorphicRange(_, _, _)
-            | Instr::ConstMethodHandle(_, _)
-            | Instr::ConstMethodType(_, _) => {
-                unimplemented!("support for {:?}", instr)
+            Instr::InvokePolymorphic(args, meth, proto) => {
+                self.forward_invoke_polymorphic(args.iter(), *meth, *proto, dex, repo)
+            }
+            Instr::InvokePolymorphicRange(args, meth, proto) => {
+                self.forward_invoke_polymorphic(args.iter(), *meth, *proto, dex, repo)
+            }
+            Instr::InvokeCustom(args, call_site) => {
+                let proto = call_site.get(dex)?.method_type(dex)?;
+                self.forward_invoke_proto(args.iter(), proto, dex, repo)
+            }
+            Instr::InvokeCustomRange(args, call_site) => {
+                let proto = call_site.get(dex)?.method_type(dex)?;
+                self.forward_invoke_proto(args.iter(), proto, dex, repo)
+            }
+            Instr::ConstMethodHandle(dst, _) => {
+                self.write_reg(*dst, JAVA_LANG_INVOKE_METHOD_HANDLE.clone())
+            }
+            Instr::ConstMethodType(dst, _) => {
+                self.write_reg(*dst, JAVA_LANG_INVOKE_METHOD_TYPE.clone())
             }
 
             Instr::PackedSwitchPayload(_, _)
@@ -1285,3 +1274,62 @@ impl<'a> AbstractForwardState<'a> for State {
         }
     }
 }
+
+impl State {
+    /// Typechecks the receiver of a signature polymorphic invocation against
+    /// the method definer (`MethodHandle` or `VarHandle`), then the other
+    /// arguments against the prototype of the call site.
+    fn forward_invoke_polymorphic(
+        &mut self,
+        mut args_it: impl Iterator<Item = Reg>,
+        meth: Index<MethodIdItem>,
+        proto: Index<ProtoIdItem>,
+        dex: &Dex,
+        repo: &Repo,
+    ) -> AnalysisResult<()> {
+        let this_reg = args_it.next().ok_or(TypeError::MissingThisArgument)?;
+        let this_reg_typ = self.read_reg(this_reg)?;
+
+        let method = meth.get(dex)?;
+        let definer_typ = AbstractType::try_from(&method.definer(dex)?)?;
+        tc!(definer_typ <: &*JAVA_LANG_OBJECT ; repo)?;
+        tc!(this_reg_typ <: &definer_typ ; repo)?;
+
+        self.forward_invoke_proto(args_it, proto.get(dex)?, dex, repo)
+    }
+
+    /// Typechecks invocation arguments against the parameters of `proto`,
+    /// and saves its return type if non void.
+    fn forward_invoke_proto(
+        &mut self,
+        mut args_it: impl Iterator<Item = Reg>,
+        proto: &ProtoIdItem,
+        dex: &Dex,
+        repo: &Repo,
+    ) -> AnalysisResult<()> {
+        use AbstractType::{Double, Long};
+
+        for p_descr in &proto.parameters_types(dex)? {
+            let p_typ = AbstractType::try_from(p_descr)?;
+            let arg_reg = args_it.next().ok_or(TypeError::BadArity)?;
+            let arg_typ = match p_typ {
+                Double | Long => {
+                    args_it.next(); // consume the following register as it's a pair
+                    self.read_pair(arg_reg)?
+                }
+                _ => self.read_reg(arg_reg)?,
+            };
+            tc!(arg_typ <: &p_typ ; repo)?;
+        }
+        // all args should have been consumed
+        if args_it.next().is_some() {
+            return Err(TypeError::BadArity.into());
+        }
+
+        let ret_descr = proto.return_type(dex)?;
+        if ret_descr != Type::Void {
+            self.last_result = Some(AbstractType::try_from(&ret_descr)?);
+        }
+        Ok(())
+    }
+}
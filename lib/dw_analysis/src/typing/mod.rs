@@ -31,7 +31,26 @@ impl Types {
     /// This function may generate errors, mainly due to typecheck error (breaking subtyping
     /// type usage, etc.), but also due to internal error (register out of bounds, etc.).
     pub fn forward_compute(method: &Method, class: &Class, context: &Repo) -> AnalysisResult<Self> {
-        dataflow::forward(method, class, context)
+        Self::forward_compute_with(method, class, context, TypingOptions::default())
+    }
+
+    /// Runs a forward typechecking pass, with the abstract types
+    /// parameterized by `options`.
+    ///
+    /// # Errors
+    ///
+    /// See [`Types::forward_compute`].
+    pub fn forward_compute_with(
+        method: &Method,
+        class: &Class,
+        context: &Repo,
+        options: TypingOptions,
+    ) -> AnalysisResult<Self> {
+        let context = TypingContext {
+            repo: context,
+            options,
+        };
+        dataflow::forward(method, class, &context)
     }
 
     /// Runs a backward typechecking pass onto given method and
@@ -52,6 +71,39 @@ impl Types {
     }
 }
 
+/// Options of the typing passes.
+#[derive(Debug, Clone, Copy)]
+pub struct TypingOptions {
+    max_object_types: usize,
+}
+
+/// Default values let joined object types sets hold up to 16 types.
+impl Default for TypingOptions {
+    fn default() -> Self {
+        Self {
+            max_object_types: 16,
+        }
+    }
+}
+
+impl TypingOptions {
+    /// Sets the maximum number of types of an object types set resulting
+    /// of a join. Larger sets are widened to the least common superclass
+    /// of the joined types, which bounds the cost of joins on methods
+    /// merging values of many unrelated classes, at the price of the
+    /// implemented interfaces.
+    #[must_use]
+    pub const fn max_object_types(self, max_object_types: usize) -> Self {
+        Self { max_object_types }
+    }
+}
+
+/// The context of the forward typing pass.
+pub struct TypingContext<'c> {
+    repo: &'c Repo<'c>,
+    options: TypingOptions,
+}
+
 macro_rules! tc {
     ( $t1:ident <: $t2:expr ; $repo:expr ) => {
         $t1.is_subseteq($t2, $repo)
@@ -125,3 +177,347 @@ impl State {
         self.write_reg(r.next(), t)
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::errors::AnalysisError;
+    use crate::testing::{self, DexBuilder, ACC_PUBLIC, ACC_STATIC};
+    use crate::typing::types::{
+        ARRAY_INTERFACES, JAVA_LANG_INVOKE_METHOD_TYPE, JAVA_LANG_OBJECT, JAVA_LANG_STRING,
+    };
+
+    #[test]
+    fn move_result_after_branch_merge() {
+        let mut builder = DexBuilder::new();
+        let class = builder.class("LTest;", "Ljava/lang/Object;");
+        let callee = builder.method("LTest;", "callee", "I", &[]) as u16;
+        let method = builder.method("LTest;", "run", "V", &[]);
+        builder.code(
+            class,
+            method,
+            ACC_PUBLIC | ACC_STATIC,
+            1,
+            0,
+            0,
+            &[
+                0x0012, // 0: const/4 v0, 0
+                0x0038, 0x0005, // 1: if-eqz v0, +5
+                0x0071, callee, 0x0000, // 3: invoke-static {}, Test.callee()
+                0x000a, // 6: move-result v0
+                0x000e, // 7: return-void
+            ],
+        );
+        let direct = builder.method("LTest;", "direct", "V", &[]);
+        builder.code(
+            class,
+            direct,
+            ACC_PUBLIC | ACC_STATIC,
+            1,
+            0,
+            0,
+            &[
+                0x0071, callee, 0x0000, // 0: invoke-static {}, Test.callee()
+                0x000a, // 3: move-result v0
+                0x000e, // 4: return-void
+            ],
+        );
+        let dex = builder.build_dex();
+        let repo = testing::repo(&dex);
+
+        let (class, method) = testing::method(&repo, "direct");
+        assert!(Types::forward_compute(method, class, &repo).is_ok());
+        assert!(Types::backward_compute(method, class, &repo).is_ok());
+
+        let (class, method) = testing::method(&repo, "run");
+        assert!(matches!(
+            Types::forward_compute(method, class, &repo),
+            Err(AnalysisError::Type(TypeError::MissingResult))
+        ));
+        assert!(matches!(
+            Types::backward_compute(method, class, &repo),
+            Err(AnalysisError::Type(TypeError::MissingResult))
+        ));
+    }
+
+    #[test]
+    fn array_clone() {
+        let mut builder = DexBuilder::new();
+        let class = builder.class("LTest;", "Ljava/lang/Object;");
+        let clone = builder.method("[I", "clone", "Ljava/lang/Object;", &[]) as u16;
+        let method = builder.method("LTest;", "run", "V", &["[I"]);
+        builder.code(
+            class,
+            method,
+            ACC_PUBLIC | ACC_STATIC,
+            2,
+            1,
+            1,
+            &[
+                0x106e, clone, 0x0001, // 0: invoke-virtual {v1}, int[].clone()
+                0x000c, // 3: move-result-object v0
+                0x000e, // 4: return-void
+            ],
+        );
+        let dex = builder.build_dex();
+        let repo = testing::repo(&dex);
+
+        let (class, method) = testing::method(&repo, "run");
+        let int_array = AbstractType::Array(1, Box::new(AbstractType::Integer));
+        let types = Types::forward_compute(method, class, &repo).unwrap();
+        let state = types.out_state(dw_dex::Addr(3)).unwrap();
+        assert_eq!(state.read_reg(Reg::from(0u8)).unwrap(), &int_array);
+        assert!(Types::backward_compute(method, class, &repo).is_ok());
+    }
+
+    #[test]
+    fn array_accesses() {
+        let mut builder = DexBuilder::new();
+        let class = builder.class("LTest;", "Ljava/lang/Object;");
+        let method = builder.method("LTest;", "run", "V", &["[I", "[[Ljava/lang/Object;"]);
+        builder.code(
+            class,
+            method,
+            ACC_PUBLIC | ACC_STATIC,
+            4,
+            2,
+            0,
+            &[
+                0x0012, // 0: const/4 v0, 0
+                0x0144, 0x0002, // 1: aget v1, v2, v0
+                0x014b, 0x0002, // 3: aput v1, v2, v0
+                0x0146, 0x0003, // 5: aget-object v1, v3, v0
+                0x014d, 0x0003, // 7: aput-object v1, v3, v0
+                0x0146, 0x0001, // 9: aget-object v1, v1, v0
+                0x000e, // 11: return-void
+            ],
+        );
+        let wrong = builder.method("LTest;", "wrong", "V", &["[I", "F"]);
+        builder.code(
+            class,
+            wrong,
+            ACC_PUBLIC | ACC_STATIC,
+            3,
+            2,
+            0,
+            &[
+                0x0012, // 0: const/4 v0, 0
+                0x024b, 0x0001, // 1: aput v2, v1, v0
+                0x000e, // 3: return-void
+            ],
+        );
+        let dex = builder.build_dex();
+        let repo = testing::repo(&dex);
+
+        let (class, method) = testing::method(&repo, "run");
+        let types = Types::forward_compute(method, class, &repo).unwrap();
+        let state = types.out_state(dw_dex::Addr(1)).unwrap();
+        assert_eq!(
+            state.read_reg(Reg::from(1u8)).unwrap(),
+            &AbstractType::Integer
+        );
+        let state = types.out_state(dw_dex::Addr(5)).unwrap();
+        assert_eq!(
+            state.read_reg(Reg::from(1u8)).unwrap(),
+            &AbstractType::Array(1, Box::new(JAVA_LANG_OBJECT.clone()))
+        );
+        let state = types.out_state(dw_dex::Addr(9)).unwrap();
+        assert_eq!(state.read_reg(Reg::from(1u8)).unwrap(), &*JAVA_LANG_OBJECT);
+        assert!(Types::backward_compute(method, class, &repo).is_ok());
+
+        let (class, method) = testing::method(&repo, "wrong");
+        assert!(matches!(
+            Types::forward_compute(method, class, &repo),
+            Err(AnalysisError::Type(TypeError::NotASubtype(
+                AbstractType::Float,
+                AbstractType::Integer
+            )))
+        ));
+    }
+
+    #[test]
+    fn array_branches_merge() {
+        let mut builder = DexBuilder::new();
+        let class = builder.class("LTest;", "Ljava/lang/Object;");
+        builder.class("Ljava/lang/String;", "Ljava/lang/Object;");
+        builder.class("Ljava/lang/Integer;", "Ljava/lang/Object;");
+        let method = builder.method(
+            "LTest;",
+            "run",
+            "V",
+            &["[Ljava/lang/String;", "[Ljava/lang/Integer;"],
+        );
+        builder.code(
+            class,
+            method,
+            ACC_PUBLIC | ACC_STATIC,
+            3,
+            2,
+            0,
+            &[
+                0x1007, // 0: move-object v0, v1
+                0x0138, 0x0003, // 1: if-eqz v1, +3
+                0x2007, // 3: move-object v0, v2
+                0x000e, // 4: return-void
+            ],
+        );
+        let dex = builder.build_dex();
+        let repo = testing::repo(&dex);
+
+        let (class, method) = testing::method(&repo, "run");
+        let types = Types::forward_compute(method, class, &repo).unwrap();
+        let state = types.in_state(dw_dex::Addr(4)).unwrap();
+        let object_array = AbstractType::Array(1, Box::new(JAVA_LANG_OBJECT.clone()));
+        assert_eq!(state.read_reg(Reg::from(0u8)).unwrap(), &object_array);
+
+        let array = |n, t: &AbstractType| AbstractType::Array(n, Box::new(t.clone()));
+        let join = |t1: AbstractType, t2: AbstractType| t1.join(t2, &repo, usize::MAX).unwrap();
+        let (int, float) = (AbstractType::Integer, AbstractType::Float);
+        assert_eq!(
+            join(array(2, &JAVA_LANG_STRING), array(1, &JAVA_LANG_STRING)),
+            object_array
+        );
+        assert_eq!(
+            join(array(1, &int), array(1, &JAVA_LANG_STRING)),
+            *ARRAY_INTERFACES
+        );
+        assert_eq!(
+            join(array(2, &int), array(2, &float)),
+            array(1, &ARRAY_INTERFACES)
+        );
+    }
+
+    #[test]
+    fn method_handles() {
+        let mut builder = DexBuilder::new();
+        let class = builder.class("LTest;", "Ljava/lang/Object;");
+        let invoke = builder.method(
+            "Ljava/lang/invoke/MethodHandle;",
+            "invoke",
+            "Ljava/lang/Object;",
+            &["[Ljava/lang/Object;"],
+        ) as u16;
+        let proto = builder.proto("Ljava/lang/String;", &["I"]) as u16;
+        let method = builder.method(
+            "LTest;",
+            "run",
+            "Ljava/lang/String;",
+            &["Ljava/lang/invoke/MethodHandle;", "I"],
+        );
+        builder.code(
+            class,
+            method,
+            ACC_PUBLIC | ACC_STATIC,
+            3,
+            2,
+            2,
+            &[
+                0x00ff, proto, // 0: const-method-type v0, (I)String
+                0x20fa, invoke, 0x0021, proto,  // 2: invoke-polymorphic {v1, v2}
+                0x000c, // 6: move-result-object v0
+                0x0011, // 7: return-object v0
+            ],
+        );
+        let wrong = builder.method(
+            "LTest;",
+            "wrong",
+            "V",
+            &["Ljava/lang/invoke/MethodHandle;", "F"],
+        );
+        builder.code(
+            class,
+            wrong,
+            ACC_PUBLIC | ACC_STATIC,
+            2,
+            2,
+            2,
+            &[
+                0x20fa, invoke, 0x0010, proto,  // 0: invoke-polymorphic {v0, v1}
+                0x000e, // 4: return-void
+            ],
+        );
+        let dex = builder.build_dex();
+        let repo = testing::repo(&dex);
+
+        let (class, method) = testing::method(&repo, "run");
+        let types = Types::forward_compute(method, class, &repo).unwrap();
+        let state = types.out_state(dw_dex::Addr(0)).unwrap();
+        assert_eq!(
+            state.read_reg(Reg::from(0u8)).unwrap(),
+            &*JAVA_LANG_INVOKE_METHOD_TYPE
+        );
+        let state = types.out_state(dw_dex::Addr(6)).unwrap();
+        assert_eq!(state.read_reg(Reg::from(0u8)).unwrap(), &*JAVA_LANG_STRING);
+        assert!(Types::backward_compute(method, class, &repo).is_ok());
+
+        let (class, method) = testing::method(&repo, "wrong");
+        assert!(matches!(
+            Types::forward_compute(method, class, &repo),
+            Err(AnalysisError::Type(TypeError::NotASubtype(
+                AbstractType::Float,
+                AbstractType::Integer
+            )))
+        ));
+    }
+
+    #[test]
+    fn widened_object_types() {
+        let mut builder = DexBuilder::new();
+        let class = builder.class("LTest;", "Ljava/lang/Object;");
+        builder.class("LBase;", "Ljava/lang/Object;");
+        let mut subclasses = Vec::new();
+        for (name, interfaces) in [
+            ("LS1;", ["LI1;", "LI2;"]),
+            ("LS2;", ["LI1;", "LI3;"]),
+            ("LS3;", ["LI2;", "LI3;"]),
+        ] {
+            let sub = builder.class(name, "LBase;");
+            for interface in interfaces {
+                builder.implements(sub, interface);
+            }
+            subclasses.push(builder.type_(name) as u16);
+        }
+        // 0: new-instance v0, S1
+        // 2: if-eqz v1, +8
+        // 4: new-instance v0, S2
+        // 6: if-gez v1, +4
+        // 8: new-instance v0, S3
+        // 10: return-void
+        let method = builder.method("LTest;", "run", "V", &["I"]);
+        builder.code(
+            class,
+            method,
+            ACC_PUBLIC | ACC_STATIC,
+            2,
+            1,
+            0,
+            &[
+                0x0022,
+                subclasses[0],
+                0x0138,
+                0x0008,
+                0x0022,
+                subclasses[1],
+                0x013b,
+                0x0004,
+                0x0022,
+                subclasses[2],
+                0x000e,
+            ],
+        );
+        let dex = builder.build_dex();
+        let repo = testing::repo(&dex);
+
+        let (class, method) = testing::method(&repo, "run");
+        let base = AbstractType::object_singleton("Base".to_string());
+
+        let types = Types::forward_compute(method, class, &repo).unwrap();
+        let state = types.in_state(dw_dex::Addr(10)).unwrap();
+        assert_ne!(state.read_reg(Reg::from(0u8)).unwrap(), &base);
+
+        let options = TypingOptions::default().max_object_types(2);
+        let types = Types::forward_compute_with(method, class, &repo, options).unwrap();
+        let state = types.in_state(dw_dex::Addr(10)).unwrap();
+        assert_eq!(state.read_reg(Reg::from(0u8)).unwrap(), &base);
+    }
+}
@@ -2,13 +2,26 @@ use crate::errors::{AnalysisError, AnalysisResult};
 use crate::repo::Repo;
 use crate::typing::errors::{TypeError, TypeResult};
 use dw_dex::fields::FieldIdItem;
+use dw_dex::methods::MethodIdItem;
 use dw_dex::types::{Type, TypeIdItem};
-use dw_dex::{DexIndex, Index, WithDex};
+use dw_dex::{Dex, DexIndex, Index, WithDex};
 use lazy_static::lazy_static;
 use std::collections::BTreeSet;
 use std::convert::TryFrom;
 use std::fmt;
 
+/// An element of the typing lattice.
+///
+/// The textual representation distinguishes concrete types from
+/// lattice-only points:
+///  - concrete types are `integer`, `float`, `long`, `double`, `null`,
+///    `OBJ[c1, c2, ...]` for objects (classes being separated by `", "`)
+///    and `ARR[n * t]` for arrays of dimension `n` of elements `t`;
+///  - lattice-only points are wrapped between angle brackets: `⟨top⟩`,
+///    `⟨join64⟩`, `⟨meet64⟩`, `⟨joinzero⟩`, `⟨join32⟩`, `⟨meet32⟩`,
+///    `⟨meetzero⟩` and `⟨bottom⟩`.
+///
+/// Concrete types can be parsed back with [`AbstractType::parse`].
 #[derive(Debug, Clone, PartialEq, Eq)]
 pub enum AbstractType {
     Top,
@@ -31,16 +44,16 @@ pub enum AbstractType {
 impl fmt::Display for AbstractType {
     fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
         match self {
-            Self::Top => write!(f, "⊤"),
-            Self::Join64 => write!(f, "Join64"),
+            Self::Top => write!(f, "⟨top⟩"),
+            Self::Join64 => write!(f, "⟨join64⟩"),
             Self::Long => write!(f, "long"),
             Self::Double => write!(f, "double"),
-            Self::Meet64 => write!(f, "Meet64"),
-            Self::JoinZero => write!(f, " JoinZero"),
-            Self::Join32 => write!(f, "Join32"),
+            Self::Meet64 => write!(f, "⟨meet64⟩"),
+            Self::JoinZero => write!(f, "⟨joinzero⟩"),
+            Self::Join32 => write!(f, "⟨join32⟩"),
             Self::Integer => write!(f, "integer"),
             Self::Float => write!(f, "float"),
-            Self::Meet32 => write!(f, "Meet32"),
+            Self::Meet32 => write!(f, "⟨meet32⟩"),
             Self::Object(s) => {
                 write!(f, "OBJ[")?;
                 for (i, c) in s.iter().enumerate() {
@@ -54,8 +67,8 @@ impl fmt::Display for AbstractType {
             }
             Self::Array(n, t) => write!(f, "ARR[{n} * {t}]"),
             Self::Null => write!(f, "null"),
-            Self::MeetZero => write!(f, "MeetZero"),
-            Self::Bottom => write!(f, "⊥"),
+            Self::MeetZero => write!(f, "⟨meetzero⟩"),
+            Self::Bottom => write!(f, "⟨bottom⟩"),
         }
     }
 }
@@ -103,11 +116,75 @@ lazy_static! {
         AbstractType::object_singleton("java/lang/String".to_string());
     pub static ref JAVA_LANG_CLASS: AbstractType =
         AbstractType::object_singleton("java/lang/Class".to_string());
+    pub static ref JAVA_LANG_INVOKE_METHOD_HANDLE: AbstractType =
+        AbstractType::object_singleton("java/lang/invoke/MethodHandle".to_string());
+    pub static ref JAVA_LANG_INVOKE_METHOD_TYPE: AbstractType =
+        AbstractType::object_singleton("java/lang/invoke/MethodType".to_string());
     pub static ref JAVA_IO_SERIALIZABLE: AbstractType =
         AbstractType::object_singleton("java/io/Serializable".to_string());
+    /// The least upper bound of arrays whose elements types are unrelated,
+    /// as every array type implements these two interfaces.
+    pub static ref ARRAY_INTERFACES: AbstractType = AbstractType::Object(BTreeSet::from([
+        "java/lang/Cloneable".to_string(),
+        "java/io/Serializable".to_string(),
+    ]));
 }
 
 impl AbstractType {
+    /// Returns the abstract type of the value returned by a non-static
+    /// invocation of `method`, whose return type is `ret`.
+    ///
+    /// This is the abstract type of `ret`, except for `clone()` invoked on
+    /// an array type: it is declared as returning `java.lang.Object` but
+    /// returns an array of the same type.
+    pub(crate) fn from_invoke_result(
+        ret: &Type,
+        method: &MethodIdItem,
+        dex: &Dex,
+    ) -> AnalysisResult<Self> {
+        let definer = method.definer(dex)?;
+        if matches!(definer, Type::Array(_, _))
+            && method.name(dex)? == "clone"
+            && method.parameters_types(dex)?.is_empty()
+        {
+            return Ok(Self::try_from(&definer)?);
+        }
+        Ok(Self::try_from(ret)?)
+    }
+
+    /// Parses the textual representation of a concrete type, as produced
+    /// by its `Display` implementation.
+    ///
+    /// Returns `None` if the input is not a concrete type, which includes
+    /// lattice-only points and arrays of such points.
+    #[must_use]
+    pub fn parse(s: &str) -> Option<Self> {
+        match s {
+            "integer" => Some(Self::Integer),
+            "float" => Some(Self::Float),
+            "long" => Some(Self::Long),
+            "double" => Some(Self::Double),
+            "null" => Some(Self::Null),
+            _ => {
+                if let Some(classes) = s.strip_prefix("OBJ[") {
+                    let classes = classes.strip_suffix(']')?;
+                    if classes.is_empty() {
+                        return None;
+                    }
+                    Some(Self::Object(
+                        classes.split(", ").map(ToString::to_string).collect(),
+                    ))
+                } else {
+                    let (n, t) = s
+                        .strip_prefix("ARR[")?
+                        .strip_suffix(']')?
+                        .split_once(" * ")?;
+                    Some(Self::Array(n.parse().ok()?, Box::new(Self::parse(t)?)))
+                }
+            }
+        }
+    }
+
     pub(crate) fn object_singleton(class: String) -> Self {
         let mut set = BTreeSet::new();
         set.insert(class);
@@ -177,10 +254,7 @@ impl AbstractType {
                 }
                 Ok(true)
             }
-            (Self::Array(_, _), t @ Self::Object(_)) => Ok(t == &*JAVA_LANG_OBJECT
-                || t == &Self::JoinZero
-                || t == &Self::Top
-                || t == &*JAVA_IO_SERIALIZABLE),
+            (Self::Array(_, _), Self::Object(os)) => Ok(Self::is_array_supertype(os)),
             (Self::Null, Self::Object(_)) | (Self::MeetZero, Self::Object(_)) => Ok(true),
 
             (Self::Array(m, s), Self::Array(n, t)) => {
@@ -188,9 +262,10 @@ impl AbstractType {
                     s.subseteq(t, repo)
                 } else {
                     Ok((n < m)
-                        && (t.as_ref() == &*JAVA_LANG_OBJECT
-                            || t.as_ref() == &Self::JoinZero
-                            || t.as_ref() == &Self::Top))
+                        && match t.as_ref() {
+                            Self::Object(os) => Self::is_array_supertype(os),
+                            t => t == &Self::JoinZero || t == &Self::Top,
+                        })
                 }
             }
             (Self::Null, Self::Array(_, _)) | (Self::MeetZero, Self::Array(_, _)) => Ok(true),
@@ -201,7 +276,68 @@ impl AbstractType {
         }
     }
 
-    pub(crate) fn join(self, other: Self, repo: &Repo) -> AnalysisResult<Self> {
+    /// Returns `true` if every class of an object type is a supertype of
+    /// all array types.
+    fn is_array_supertype(os: &BTreeSet<String>) -> bool {
+        os.iter().all(|o| {
+            matches!(
+                o.as_str(),
+                "java/lang/Object" | "java/lang/Cloneable" | "java/io/Serializable"
+            )
+        })
+    }
+
+    /// Returns the type of the values found at depth `n` of an array of
+    /// elements `t` and of dimension `n + extra`.
+    fn array_element(extra: usize, t: Self) -> Self {
+        if extra == 0 {
+            t
+        } else {
+            Self::Array(extra, Box::new(t))
+        }
+    }
+
+    const fn is_reference(&self) -> bool {
+        matches!(self, Self::Object(_) | Self::Array(_, _) | Self::Null)
+    }
+
+    /// Returns the most specific class of an object types set, that is the
+    /// least common superclass of the joined types, or `java/lang/Object`
+    /// when the set holds no resolved class.
+    ///
+    /// As values of an object types set are typeable as every type of the
+    /// set, any of its types is a sound approximation of the whole set.
+    fn widen_objects(os: &BTreeSet<String>, repo: &Repo) -> AnalysisResult<Self> {
+        let classes: Vec<&String> = os
+            .iter()
+            .filter(|o| {
+                repo.get_class_by_name(o)
+                    .is_some_and(|class| class.is_defined() && !class.is_interface())
+            })
+            .collect();
+        for class in &classes {
+            let mut most_specific = true;
+            for other in &classes {
+                if !repo.is_typeable_as(class, other)? {
+                    most_specific = false;
+                    break;
+                }
+            }
+            if most_specific {
+                return Ok(Self::object_singleton((*class).clone()));
+            }
+        }
+        Ok(JAVA_LANG_OBJECT.clone())
+    }
+
+    /// Joins two abstract types. Object types sets holding more than
+    /// `max_object_types` types are widened to their most specific class.
+    pub(crate) fn join(
+        self,
+        other: Self,
+        repo: &Repo,
+        max_object_types: usize,
+    ) -> AnalysisResult<Self> {
         if self.subseteq(&other, repo)? {
             return Ok(other);
         }
@@ -238,18 +374,33 @@ impl AbstractType {
                     }
                 }
                 assert!(!res.is_empty());
-                Ok(Self::Object(res))
+                if res.len() > max_object_types {
+                    Self::widen_objects(&res, repo)
+                } else {
+                    Ok(Self::Object(res))
+                }
             }
 
             (Self::Array(_, _), Self::Object(_)) | (Self::Object(_), Self::Array(_, _)) => {
                 Ok(JAVA_LANG_OBJECT.clone())
             }
 
+            // arrays are covariant: lub(T[], U[]) = lub(T, U)[] when T and U
+            // are reference types, and arrays of unrelated primitive types
+            // only share the interfaces implemented by every array
             (Self::Array(n1, t1), Self::Array(n2, t2)) => {
-                if n1 == n2 {
-                    Ok(Self::Array(n1, Box::new(t1.join(*t2, repo)?)))
+                let n = n1.min(n2);
+                let t1 = Self::array_element(n1 - n, *t1);
+                let t2 = Self::array_element(n2 - n, *t2);
+                if t1.is_reference() && t2.is_reference() {
+                    Ok(Self::Array(
+                        n,
+                        Box::new(t1.join(t2, repo, max_object_types)?),
+                    ))
+                } else if n == 1 {
+                    Ok(ARRAY_INTERFACES.clone())
                 } else {
-                    Ok(JAVA_LANG_OBJECT.clone())
+                    Ok(Self::Array(n - 1, Box::new(ARRAY_INTERFACES.clone())))
                 }
             }
 
@@ -341,3 +492,55 @@ impl AbstractType {
         }
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn parse_concrete_types() {
+        let types = [
+            AbstractType::Integer,
+            AbstractType::Float,
+            AbstractType::Long,
+            AbstractType::Double,
+            AbstractType::Null,
+            JAVA_LANG_STRING.clone(),
+            AbstractType::Object(BTreeSet::from([
+                "java/io/Serializable".to_string(),
+                "java/lang/Comparable".to_string(),
+            ])),
+            AbstractType::Array(2, Box::new(AbstractType::Integer)),
+            AbstractType::Array(1, Box::new(JAVA_LANG_OBJECT.clone())),
+        ];
+        for typ in types {
+            assert_eq!(AbstractType::parse(&typ.to_string()), Some(typ));
+        }
+    }
+
+    #[test]
+    fn display_lattice_points() {
+        let points = [
+            AbstractType::Top,
+            AbstractType::Join64,
+            AbstractType::Meet64,
+            AbstractType::JoinZero,
+            AbstractType::Join32,
+            AbstractType::Meet32,
+            AbstractType::MeetZero,
+            AbstractType::Bottom,
+        ];
+        let rendered: BTreeSet<String> = points.iter().map(ToString::to_string).collect();
+        assert_eq!(rendered.len(), points.len());
+        for s in &rendered {
+            assert!(s.starts_with('⟨') && s.ends_with('⟩'));
+            assert_eq!(AbstractType::parse(s), None);
+        }
+        assert_eq!(
+            AbstractType::parse(
+                &AbstractType::Array(1, Box::new(AbstractType::Join32)).to_string()
+            ),
+            None
+        );
+    }
+}
@@ -4,8 +4,8 @@ use crate::errors::{DexError, DexResult};
 use crate::fields::FieldIdItem;
 use crate::methods::MethodIdItem;
 use crate::strings::StringIdItem;
-use crate::types::TypeIdItem;
-use crate::values::EncodedValue;
+use crate::types::{Type, TypeIdItem};
+use crate::values::{EncodedValue, Value};
 use crate::{Dex, DexCollection, DexIndex, Index, PrettyPrint};
 use dw_utils::leb::Uleb128;
 use std::fmt;
@@ -38,6 +38,17 @@ impl DexCollection for AnnotationsDirectoryItem {
 }
 
 impl AnnotationsDirectoryItem {
+    /// Returns the annotations directory of the class definition of the
+    /// given type, if it is defined in the dex and has one.
+    pub(crate) fn of_class(class_idx: Index<TypeIdItem>, dex: &Dex) -> DexResult<Option<&Self>> {
+        dex.class_def_items
+            .iter()
+            .find(|class| class.class_idx.as_usize() == class_idx.as_usize())
+            .and_then(|class| class.annotations_off)
+            .map(|off| off.get(dex))
+            .transpose()
+    }
+
     pub(crate) fn size(&self) -> usize {
         16 + self.field_annotations.len() * 8
             + self.method_annotations.len() * 8
@@ -126,6 +137,52 @@ impl AnnotationSetItem {
     pub(crate) fn size(&self) -> usize {
         4 + 4 * self.entries.len()
     }
+
+    /// Decodes all the annotations of the set.
+    pub(crate) fn annotations<'a>(&self, dex: &'a Dex) -> DexResult<Vec<Annotation<'a>>> {
+        self.entries
+            .iter()
+            .map(|entry| {
+                let item = entry.annotation_off.get(dex)?;
+                item.annotation.decode(dex, Some(item.visibility))
+            })
+            .collect()
+    }
+
+    /// Returns the generic signature held by the `dalvik.annotation.Signature`
+    /// annotation of the set, if any. The signature is stored as an array of
+    /// strings that have to be concatenated.
+    pub(crate) fn generic_signature(&self, dex: &Dex) -> DexResult<Option<String>> {
+        for entry in &self.entries {
+            let annotation = &entry.annotation_off.get(dex)?.annotation;
+            if annotation.type_idx.get(dex)?.to_type(dex)?
+                != Type::Class("dalvik/annotation/Signature".to_string())
+            {
+                continue;
+            }
+            for element in &annotation.elements {
+                if element.name_idx.get(dex)?.to_string(dex)? != "value" {
+                    continue;
+                }
+                let EncodedValue::Array(array) = &element.value else {
+                    return Err(DexError::Structure(
+                        "Signature annotation value must be an array".to_string(),
+                    ));
+                };
+                let mut signature = String::new();
+                for value in &array.values {
+                    let EncodedValue::String(_, idx) = value else {
+                        return Err(DexError::Structure(
+                            "Signature annotation value must contain strings".to_string(),
+                        ));
+                    };
+                    signature.push_str(&idx.get(dex)?.to_string(dex)?);
+                }
+                return Ok(Some(signature));
+            }
+        }
+        Ok(None)
+    }
 }
 
 #[derive(Debug)]
@@ -164,13 +221,52 @@ impl AnnotationItem {
     }
 }
 
-#[derive(Debug)]
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
 pub enum Visibility {
     Build,
     Runtime,
     System,
 }
 
+/// A decoded annotation, with its elements values decoded recursively.
+#[derive(Debug, Clone)]
+pub struct Annotation<'a> {
+    pub typ: Type,
+    /// Visibility of the annotation, [`None`] for annotations nested in
+    /// encoded values.
+    pub visibility: Option<Visibility>,
+    pub elements: Vec<(String, Value<'a>)>,
+}
+
+impl<'a> Annotation<'a> {
+    /// Returns the value of the element with the given name, if any.
+    #[must_use]
+    pub fn element(&self, name: &str) -> Option<&Value<'a>> {
+        self.elements
+            .iter()
+            .find(|(element, _)| element == name)
+            .map(|(_, value)| value)
+    }
+}
+
+impl PrettyPrint for Annotation<'_> {
+    fn pp(&self, f: &mut fmt::Formatter, dex: &Dex) -> DexResult<()> {
+        write!(f, "@{}", self.typ)?;
+        if !self.elements.is_empty() {
+            write!(f, "(")?;
+            for (i, (name, value)) in self.elements.iter().enumerate() {
+                write!(f, "{name}=")?;
+                value.pp(f, dex)?;
+                if i != self.elements.len() - 1 {
+                    write!(f, ", ")?;
+                }
+            }
+            write!(f, ")")?;
+        }
+        Ok(())
+    }
+}
+
 #[derive(Debug)]
 pub(crate) struct EncodedAnnotation {
     pub(crate) type_idx: Index<TypeIdItem>,
@@ -196,6 +292,28 @@ impl PrettyPrint for EncodedAnnotation {
 }
 
 impl EncodedAnnotation {
+    pub(crate) fn decode<'a>(
+        &self,
+        dex: &'a Dex,
+        visibility: Option<Visibility>,
+    ) -> DexResult<Annotation<'a>> {
+        let elements = self
+            .elements
+            .iter()
+            .map(|element| {
+                Ok((
+                    element.name_idx.get(dex)?.to_string(dex)?,
+                    element.value.decode(dex)?,
+                ))
+            })
+            .collect::<DexResult<_>>()?;
+        Ok(Annotation {
+            typ: self.type_idx.get(dex)?.to_type(dex)?,
+            visibility,
+            elements,
+        })
+    }
+
     pub(crate) fn size(&self) -> usize {
         let elements_size: usize = self.elements.iter().map(AnnotationElement::size).sum();
         self.type_idx.as_uleb().size() + self.size.size() + elements_size
@@ -220,3 +338,354 @@ impl AnnotationElement {
         self.name_idx.as_uleb().size() + self.value.size()
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::classes::{ClassDefItem, ClassFlags};
+    use crate::fields::{EncodedField, FieldFlags};
+    use crate::merge::{push_string, push_type};
+    use crate::methods::{EncodedMethod, MethodFlags, MethodIdItem};
+    use crate::values::EncodedArray;
+
+    fn push_signature_set(dex: &mut Dex, off: usize, parts: &[&str]) -> Index<AnnotationSetItem> {
+        let descriptor_idx = push_string(dex, "Ldalvik/annotation/Signature;");
+        let type_idx = Index::new(dex.type_id_items.len());
+        dex.type_id_items.push(TypeIdItem {
+            index: type_idx,
+            descriptor_idx,
+        });
+        let name_idx = push_string(dex, "value");
+        let values: Vec<_> = parts
+            .iter()
+            .map(|part| EncodedValue::String(0, push_string(dex, part)))
+            .collect();
+        dex.annotation_items.insert(
+            off,
+            AnnotationItem {
+                index: Index::new(off),
+                visibility: Visibility::System,
+                annotation: EncodedAnnotation {
+                    type_idx,
+                    size: Uleb128::new(1, None),
+                    elements: vec![AnnotationElement {
+                        name_idx,
+                        value: EncodedValue::Array(EncodedArray {
+                            size: Uleb128::new(values.len() as u32, None),
+                            values,
+                        }),
+                    }],
+                },
+            },
+        );
+        dex.annotation_set_items.insert(
+            off + 0x10,
+            AnnotationSetItem {
+                index: Index::new(off + 0x10),
+                entries: vec![AnnotationOffItem {
+                    annotation_off: Index::new(off),
+                }],
+            },
+        );
+        Index::new(off + 0x10)
+    }
+
+    #[test]
+    fn generic_signatures() {
+        let mut dex = Dex::new(35);
+
+        // class Test<T> { List<String> get(); void set(); }
+        let class_set = push_signature_set(
+            &mut dex,
+            0x100,
+            &["<T:", "Ljava/lang/Object;", ">", "Ljava/lang/Object;"],
+        );
+        let method_set = push_signature_set(
+            &mut dex,
+            0x200,
+            &["()", "Ljava/util/List<", "Ljava/lang/String;", ">;"],
+        );
+        dex.annotations_directory_items.insert(
+            0x300,
+            AnnotationsDirectoryItem {
+                index: Index::new(0x300),
+                class_annotations_off: class_set,
+                field_annotations: Vec::new(),
+                method_annotations: vec![MethodAnnotation {
+                    method_idx: Index::new(0),
+                    annotations_off: method_set,
+                }],
+                parameter_annotations: Vec::new(),
+            },
+        );
+        // another class whose directory wrongly lists the second method
+        // and a field of the first class
+        dex.annotations_directory_items.insert(
+            0x400,
+            AnnotationsDirectoryItem {
+                index: Index::new(0x400),
+                class_annotations_off: Index::new(0),
+                field_annotations: vec![FieldAnnotation {
+                    field_idx: Index::new(0),
+                    annotations_off: method_set,
+                }],
+                method_annotations: vec![MethodAnnotation {
+                    method_idx: Index::new(1),
+                    annotations_off: method_set,
+                }],
+                parameter_annotations: Vec::new(),
+            },
+        );
+        for (idx, annotations_off) in [(0, 0x300), (1, 0x400)] {
+            dex.class_def_items.push(ClassDefItem {
+                index: Index::new(idx),
+                class_idx: Index::new(idx),
+                access_flags: ClassFlags::ACC_PUBLIC,
+                superclass_idx: None,
+                interfaces_off: None,
+                source_file_idx: None,
+                annotations_off: Some(Index::new(annotations_off)),
+                class_data_off: None,
+                static_values_off: None,
+            });
+        }
+        for idx in 0..2 {
+            dex.method_id_items.push(MethodIdItem {
+                index: Index::new(idx),
+                class_idx: Index::new(0),
+                proto_idx: Index::new(0),
+                name_idx: Index::new(0),
+            });
+        }
+        dex.field_id_items.push(FieldIdItem {
+            index: Index::new(0),
+            class_idx: Index::new(0),
+            type_idx: Index::new(0),
+            name_idx: Index::new(0),
+        });
+        let class = &dex.class_def_items[0];
+        let field = EncodedField {
+            field_idx_diff: Uleb128::new(0, None),
+            field_idx: Index::new(0),
+            access_flags_repr: Uleb128::new(1, None),
+            access_flags: FieldFlags::ACC_PUBLIC,
+        };
+        let method = |idx: usize| EncodedMethod {
+            method_idx_diff: Uleb128::new(idx as u32, None),
+            method_idx: Index::new(idx),
+            access_flags_repr: Uleb128::new(1, None),
+            access_flags: MethodFlags::ACC_PUBLIC,
+            code_off: None,
+        };
+
+        assert_eq!(
+            class.generic_signature(&dex).unwrap().as_deref(),
+            Some("<T:Ljava/lang/Object;>Ljava/lang/Object;")
+        );
+        assert_eq!(
+            method(0).generic_signature(&dex).unwrap().as_deref(),
+            Some("()Ljava/util/List<Ljava/lang/String;>;")
+        );
+        assert_eq!(method(1).generic_signature(&dex).unwrap(), None);
+        assert_eq!(method(0).annotations(&dex).unwrap().len(), 1);
+        assert!(method(1).annotations(&dex).unwrap().is_empty());
+        assert!(field.annotations(&dex).unwrap().is_empty());
+    }
+
+    #[test]
+    fn annotation_defaults() {
+        let mut dex = Dex::new(35);
+
+        // @interface Names { String[] value() default {"a", "b"}; }
+        let names_type = push_type(&mut dex, "LNames;");
+        let default_type = push_type(&mut dex, "Ldalvik/annotation/AnnotationDefault;");
+        let value_name = push_string(&mut dex, "value");
+        let values: Vec<_> = ["a", "b"]
+            .iter()
+            .map(|s| EncodedValue::String(0, push_string(&mut dex, s)))
+            .collect();
+        let defaults = EncodedAnnotation {
+            type_idx: names_type,
+            size: Uleb128::new(1, None),
+            elements: vec![AnnotationElement {
+                name_idx: value_name,
+                value: EncodedValue::Array(EncodedArray {
+                    size: Uleb128::new(values.len() as u32, None),
+                    values,
+                }),
+            }],
+        };
+        dex.annotation_items.insert(
+            0x100,
+            AnnotationItem {
+                index: Index::new(0x100),
+                visibility: Visibility::System,
+                annotation: EncodedAnnotation {
+                    type_idx: default_type,
+                    size: Uleb128::new(1, None),
+                    elements: vec![AnnotationElement {
+                        name_idx: value_name,
+                        value: EncodedValue::Annotation(defaults),
+                    }],
+                },
+            },
+        );
+        dex.annotation_set_items.insert(
+            0x200,
+            AnnotationSetItem {
+                index: Index::new(0x200),
+                entries: vec![AnnotationOffItem {
+                    annotation_off: Index::new(0x100),
+                }],
+            },
+        );
+        dex.annotations_directory_items.insert(
+            0x300,
+            AnnotationsDirectoryItem {
+                index: Index::new(0x300),
+                class_annotations_off: Index::new(0x200),
+                field_annotations: Vec::new(),
+                method_annotations: Vec::new(),
+                parameter_annotations: Vec::new(),
+            },
+        );
+        let class = ClassDefItem {
+            index: Index::new(0),
+            class_idx: names_type,
+            access_flags: ClassFlags::ACC_PUBLIC | ClassFlags::ACC_ANNOTATION,
+            superclass_idx: None,
+            interfaces_off: None,
+            source_file_idx: None,
+            annotations_off: Some(Index::new(0x300)),
+            class_data_off: None,
+            static_values_off: None,
+        };
+
+        let annotations = class.annotations(&dex).unwrap();
+        assert_eq!(annotations.len(), 1);
+        assert_eq!(annotations[0].visibility, Some(Visibility::System));
+
+        let defaults = class.annotation_defaults(&dex).unwrap();
+        assert_eq!(defaults.len(), 1);
+        assert_eq!(defaults[0].0, "value");
+        let Value::Array(values) = &defaults[0].1 else {
+            panic!("array default expected");
+        };
+        let strings: Vec<_> = values
+            .iter()
+            .map(|value| match value {
+                Value::String(s) => s.as_str(),
+                _ => panic!("string expected"),
+            })
+            .collect();
+        assert_eq!(strings, ["a", "b"]);
+    }
+
+    #[test]
+    fn kotlin_metadata() {
+        let mut dex = Dex::new(35);
+
+        // @kotlin.Metadata(k = 1, mv = {1, 9, 0}, d1 = {"\u0006\u0002"}, d2 = {"LKt;", ""})
+        let class_type = push_type(&mut dex, "LKt;");
+        let metadata_type = push_type(&mut dex, "Lkotlin/Metadata;");
+        let element = |dex: &mut Dex, name: &str, value| AnnotationElement {
+            name_idx: push_string(dex, name),
+            value,
+        };
+        let strings = |dex: &mut Dex, values: &[&str]| {
+            let values: Vec<_> = values
+                .iter()
+                .map(|s| EncodedValue::String(0, push_string(dex, s)))
+                .collect();
+            EncodedValue::Array(EncodedArray {
+                size: Uleb128::new(values.len() as u32, None),
+                values,
+            })
+        };
+        let d1 = strings(&mut dex, &["\u{6}\u{2}"]);
+        let d2 = strings(&mut dex, &["LKt;", ""]);
+        let elements = vec![
+            element(&mut dex, "d1", d1),
+            element(&mut dex, "d2", d2),
+            element(&mut dex, "k", EncodedValue::Int(0, 1)),
+            element(
+                &mut dex,
+                "mv",
+                EncodedValue::Array(EncodedArray {
+                    size: Uleb128::new(3, None),
+                    values: vec![
+                        EncodedValue::Int(0, 1),
+                        EncodedValue::Int(0, 9),
+                        EncodedValue::Int(0, 0),
+                    ],
+                }),
+            ),
+        ];
+        dex.annotation_items.insert(
+            0x100,
+            AnnotationItem {
+                index: Index::new(0x100),
+                visibility: Visibility::Runtime,
+                annotation: EncodedAnnotation {
+                    type_idx: metadata_type,
+                    size: Uleb128::new(elements.len() as u32, None),
+                    elements,
+                },
+            },
+        );
+        dex.annotation_set_items.insert(
+            0x200,
+            AnnotationSetItem {
+                index: Index::new(0x200),
+                entries: vec![AnnotationOffItem {
+                    annotation_off: Index::new(0x100),
+                }],
+            },
+        );
+        dex.annotations_directory_items.insert(
+            0x300,
+            AnnotationsDirectoryItem {
+                index: Index::new(0x300),
+                class_annotations_off: Index::new(0x200),
+                field_annotations: Vec::new(),
+                method_annotations: Vec::new(),
+                parameter_annotations: Vec::new(),
+            },
+        );
+        let class = ClassDefItem {
+            index: Index::new(0),
+            class_idx: class_type,
+            access_flags: ClassFlags::ACC_PUBLIC | ClassFlags::ACC_FINAL,
+            superclass_idx: None,
+            interfaces_off: None,
+            source_file_idx: None,
+            annotations_off: Some(Index::new(0x300)),
+            class_data_off: None,
+            static_values_off: None,
+        };
+
+        let annotations = class.annotations(&dex).unwrap();
+        let metadata = annotations
+            .iter()
+            .find(|annotation| annotation.typ == Type::Class("kotlin/Metadata".to_string()))
+            .unwrap();
+        assert_eq!(metadata.visibility, Some(Visibility::Runtime));
+        let strings = |name| match metadata.element(name) {
+            Some(Value::Array(values)) => values
+                .iter()
+                .map(|value| match value {
+                    Value::String(s) => s.clone(),
+                    _ => panic!("string expected"),
+                })
+                .collect::<Vec<_>>(),
+            _ => panic!("array expected"),
+        };
+        assert_eq!(strings("d1"), ["\u{6}\u{2}"]);
+        assert_eq!(strings("d2"), ["LKt;", ""]);
+        assert!(matches!(metadata.element("k"), Some(Value::Int(1))));
+        assert!(matches!(
+            metadata.element("mv"),
+            Some(Value::Array(mv)) if mv.len() == 3
+        ));
+    }
+}
@@ -1,12 +1,12 @@
 //! Dalvik classes data structures.
 
-use crate::annotations::AnnotationsDirectoryItem;
+use crate::annotations::{Annotation, AnnotationsDirectoryItem};
 use crate::errors::{DexError, DexResult};
 use crate::fields::EncodedField;
 use crate::methods::EncodedMethod;
 use crate::strings::StringIdItem;
-use crate::types::{TypeIdItem, TypeList};
-use crate::values::EncodedArrayItem;
+use crate::types::{Type, TypeIdItem, TypeList};
+use crate::values::{EncodedArrayItem, Value};
 use crate::{Dex, DexCollection, DexIndex, Index, Map};
 use bitflags::bitflags;
 use dw_utils::leb::Uleb128;
@@ -89,6 +89,62 @@ impl ClassDefItem {
             .transpose()
     }
 
+    /// Returns the generic signature of the class, as found in its
+    /// `dalvik.annotation.Signature` annotation, if any.
+    pub fn generic_signature(&self, dex: &Dex) -> DexResult<Option<String>> {
+        let Some(off) = self.annotations_off else {
+            return Ok(None);
+        };
+        let directory = off.get(dex)?;
+        if directory.class_annotations_off.as_usize() == 0 {
+            return Ok(None);
+        }
+        directory
+            .class_annotations_off
+            .get(dex)?
+            .generic_signature(dex)
+    }
+
+    /// Returns the decoded annotations of the class.
+    pub fn annotations<'a>(&self, dex: &'a Dex) -> DexResult<Vec<Annotation<'a>>> {
+        let Some(off) = self.annotations_off else {
+            return Ok(Vec::new());
+        };
+        let directory = off.get(dex)?;
+        if directory.class_annotations_off.as_usize() == 0 {
+            return Ok(Vec::new());
+        }
+        directory.class_annotations_off.get(dex)?.annotations(dex)
+    }
+
+    /// Returns the default values of the elements of an annotation class, as
+    /// found in its `dalvik.annotation.AnnotationDefault` annotation.
+    pub fn annotation_defaults<'a>(&self, dex: &'a Dex) -> DexResult<Vec<(String, Value<'a>)>> {
+        let default_type = Type::Class("dalvik/annotation/AnnotationDefault".to_string());
+        for annotation in self.annotations(dex)? {
+            if annotation.typ != default_type {
+                continue;
+            }
+            return match annotation.element("value") {
+                Some(Value::Annotation(defaults)) => Ok(defaults.elements.clone()),
+                _ => Err(DexError::Structure(
+                    "AnnotationDefault annotation value must be an annotation".to_string(),
+                )),
+            };
+        }
+        Ok(Vec::new())
+    }
+
+    /// Returns the decoded initial values of the static fields of the class,
+    /// in the order of [`ClassDataItem::iter_static_fields`]. Trailing fields
+    /// initialized to their default value (`0` or `null`) may be omitted.
+    pub fn static_values<'a>(&self, dex: &'a Dex) -> DexResult<Vec<Value<'a>>> {
+        let Some(off) = self.static_values_off else {
+            return Ok(Vec::new());
+        };
+        off.get(dex)?.value.decode(dex)
+    }
+
     /// The class can be a simple declaration, in which case this methods
     /// returns [`None`]. If it contains data (methods, fields, etc.),
     /// returns it.
@@ -96,6 +152,45 @@ impl ClassDefItem {
         self.class_data_off.map(|off| off.get(dex)).transpose()
     }
 
+    /// Returns the hiddenapi restriction flags of the class members, or
+    /// [`None`] if the dex file has no hiddenapi section (which is the
+    /// case before Android P).
+    pub fn hiddenapi_flags(&self, dex: &Dex) -> DexResult<Option<HiddenapiFlags>> {
+        let Some(hiddenapi) = dex.hiddenapi_class_data_items.values().next() else {
+            return Ok(None);
+        };
+        let offset = hiddenapi
+            .offsets
+            .get(self.index.as_usize())
+            .ok_or_else(|| DexError::ResNotFound("HiddenapiClassFlag".to_string()))?;
+        // a zero offset stands for a class without data
+        let Some(data) = self.data(dex)?.filter(|_| offset.as_usize() != 0) else {
+            return Ok(Some(HiddenapiFlags::default()));
+        };
+
+        // flags of a class members are stored contiguously, in the class data order
+        let mut flags = hiddenapi
+            .flags
+            .range(offset.as_usize()..)
+            .map(|(_, flag)| flag.flag);
+        let mut take = |n: usize| -> DexResult<Vec<HiddenapiFlag>> {
+            let members: Vec<_> = flags.by_ref().take(n).collect();
+            if members.len() == n {
+                Ok(members)
+            } else {
+                Err(DexError::Structure(
+                    "truncated hiddenapi class flags".to_string(),
+                ))
+            }
+        };
+        Ok(Some(HiddenapiFlags {
+            static_fields: take(data.static_fields.len())?,
+            instance_fields: take(data.instance_fields.len())?,
+            direct_methods: take(data.direct_methods.len())?,
+            virtual_methods: take(data.virtual_methods.len())?,
+        }))
+    }
+
     pub(crate) fn size(&self) -> usize {
         32
     }
@@ -290,7 +385,7 @@ impl HiddenapiClassFlag {
     }
 }
 
-#[derive(Debug, Clone, Copy)]
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
 pub enum HiddenapiFlag {
     Whitelist,
     Greylist,
@@ -301,6 +396,16 @@ pub enum HiddenapiFlag {
     GreylistMaxR,
 }
 
+/// The hiddenapi restriction flags of the members of a class, ordered as
+/// the members of its [`ClassDataItem`].
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct HiddenapiFlags {
+    pub static_fields: Vec<HiddenapiFlag>,
+    pub instance_fields: Vec<HiddenapiFlag>,
+    pub direct_methods: Vec<HiddenapiFlag>,
+    pub virtual_methods: Vec<HiddenapiFlag>,
+}
+
 impl TryFrom<u32> for HiddenapiFlag {
     type Error = DexError;
 
@@ -319,3 +424,105 @@ impl TryFrom<u32> for HiddenapiFlag {
         }
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::fields::FieldFlags;
+    use crate::methods::MethodFlags;
+
+    #[test]
+    fn hiddenapi_flags() {
+        let mut dex = Dex::new(39);
+        let class = |idx: usize, class_data_off: Option<usize>| ClassDefItem {
+            index: Index::new(idx),
+            class_idx: Index::new(idx),
+            access_flags: ClassFlags::ACC_PUBLIC,
+            superclass_idx: None,
+            interfaces_off: None,
+            source_file_idx: None,
+            annotations_off: None,
+            class_data_off: class_data_off.map(Index::new),
+            static_values_off: None,
+        };
+        let field = |idx: usize| EncodedField {
+            field_idx_diff: Uleb128::new(idx as u32, None),
+            field_idx: Index::new(idx),
+            access_flags_repr: Uleb128::new(0x9, None),
+            access_flags: FieldFlags::ACC_PUBLIC | FieldFlags::ACC_STATIC,
+        };
+        let method = |idx: usize| EncodedMethod {
+            method_idx_diff: Uleb128::new(idx as u32, None),
+            method_idx: Index::new(idx),
+            access_flags_repr: Uleb128::new(1, None),
+            access_flags: MethodFlags::ACC_PUBLIC,
+            code_off: None,
+        };
+        let uleb = |v: u32| Uleb128::new(v, None);
+
+        // class 0 has data (one static field, one direct and two virtual
+        // methods), class 1 is a simple declaration
+        dex.class_def_items.push(class(0, Some(0x100)));
+        dex.class_def_items.push(class(1, None));
+        dex.class_data_items.insert(
+            0x100,
+            ClassDataItem {
+                index: Index::new(0x100),
+                static_fields_size: uleb(1),
+                instance_fields_size: uleb(0),
+                direct_methods_size: uleb(1),
+                virtual_methods_size: uleb(2),
+                static_fields: vec![field(0)],
+                instance_fields: Vec::new(),
+                direct_methods: vec![method(0)],
+                virtual_methods: vec![method(1), method(2)],
+            },
+        );
+
+        assert_eq!(dex.class_def_items[0].hiddenapi_flags(&dex).unwrap(), None);
+
+        // section header (size and two offsets) takes 12 bytes
+        let flags = [
+            HiddenapiFlag::Whitelist,
+            HiddenapiFlag::Greylist,
+            HiddenapiFlag::Blacklist,
+            HiddenapiFlag::GreylistMaxO,
+        ];
+        dex.hiddenapi_class_data_items.insert(
+            0x200,
+            HiddenapiClassDataItem {
+                offsets: vec![Index::new(12), Index::new(0)],
+                flags: flags
+                    .iter()
+                    .enumerate()
+                    .map(|(i, flag)| {
+                        let repr = uleb(*flag as u32);
+                        (
+                            12 + i,
+                            HiddenapiClassFlag {
+                                uleb_repr: repr,
+                                flag: *flag,
+                            },
+                        )
+                    })
+                    .collect(),
+            },
+        );
+
+        let class_flags = dex.class_def_items[0]
+            .hiddenapi_flags(&dex)
+            .unwrap()
+            .unwrap();
+        assert_eq!(class_flags.static_fields, vec![HiddenapiFlag::Whitelist]);
+        assert!(class_flags.instance_fields.is_empty());
+        assert_eq!(class_flags.direct_methods, vec![HiddenapiFlag::Greylist]);
+        assert_eq!(
+            class_flags.virtual_methods,
+            vec![HiddenapiFlag::Blacklist, HiddenapiFlag::GreylistMaxO]
+        );
+        assert_eq!(
+            dex.class_def_items[1].hiddenapi_flags(&dex).unwrap(),
+            Some(HiddenapiFlags::default())
+        );
+    }
+}
@@ -2,15 +2,17 @@
 
 use crate::errors::{DexError, DexResult};
 use crate::fields::FieldIdItem;
-use crate::instrs::{Instr, Instruction, LabeledInstr};
+use crate::instrs::{normalize, Instr, Instruction, LabeledInstr};
 use crate::methods::MethodIdItem;
+use crate::registers::Reg;
 use crate::strings::StringIdItem;
-use crate::types::{Type, TypeIdItem};
-use crate::values::{EncodedArray, EncodedArrayItem};
+use crate::types::{ProtoIdItem, Type, TypeIdItem};
+use crate::values::{EncodedArray, EncodedArrayItem, EncodedValue};
+use crate::writers::write_instr;
 use crate::{Addr, Dex, DexCollection, DexIndex, Index, PrettyPrint};
 use dw_utils::leb::{Sleb128, Uleb128};
 use std::collections::BTreeMap;
-use std::fmt;
+use std::fmt::{self, Write};
 use std::sync::RwLock;
 
 #[derive(Debug)]
@@ -50,6 +52,17 @@ impl CallSiteIdItem {
         Ok(&self.call_site_off.get(dex)?.value)
     }
 
+    /// Returns the prototype of the method linked by the call site, that is
+    /// the method type found as third call site argument.
+    pub fn method_type<'a>(&self, dex: &'a Dex) -> DexResult<&'a ProtoIdItem> {
+        match self.arguments(dex)?.values.get(2) {
+            Some(EncodedValue::MethodType(_, proto)) => proto.get(dex),
+            _ => Err(DexError::Structure(
+                "call site without method type".to_string(),
+            )),
+        }
+    }
+
     pub(crate) fn size(&self) -> usize {
         4
     }
@@ -203,6 +216,65 @@ impl CodeItem {
         self.insns.iter()
     }
 
+    /// Iterates over instructions along with the addresses they may branch
+    /// to: the target of gotos, the target and the fall-through of
+    /// conditional branches, and the cases targets and the fall-through of
+    /// switches. Other instructions come with no successors.
+    ///
+    /// A switch whose payload cannot be found only yields its fall-through.
+    pub fn iter_instructions_with_targets(
+        &self,
+    ) -> impl Iterator<Item = (Addr, &Instr, Vec<Addr>)> {
+        self.insns
+            .iter()
+            .map(|linstr| (linstr.addr(), linstr.instr(), self.branch_targets(linstr)))
+    }
+
+    fn branch_targets(&self, linstr: &LabeledInstr) -> Vec<Addr> {
+        let addr = linstr.addr();
+        let payload = |offset: i32| {
+            self.instruction_at(addr.offset(offset))
+                .ok()
+                .map(LabeledInstr::instr)
+        };
+        let offsets = match linstr.instr() {
+            Instr::Goto(offset) => return vec![addr.offset(i32::from(*offset))],
+            Instr::Goto16(offset) => return vec![addr.offset(i32::from(*offset))],
+            Instr::Goto32(offset) => return vec![addr.offset(*offset)],
+            Instr::IfEq(_, _, offset)
+            | Instr::IfNe(_, _, offset)
+            | Instr::IfLt(_, _, offset)
+            | Instr::IfGe(_, _, offset)
+            | Instr::IfGt(_, _, offset)
+            | Instr::IfLe(_, _, offset)
+            | Instr::IfEqz(_, offset)
+            | Instr::IfNez(_, offset)
+            | Instr::IfLtz(_, offset)
+            | Instr::IfGez(_, offset)
+            | Instr::IfGtz(_, offset)
+            | Instr::IfLez(_, offset) => vec![i32::from(*offset)],
+            Instr::PackedSwitch(_, offset) => match payload(*offset) {
+                Some(Instr::PackedSwitchPayload(_, targets)) => targets.clone(),
+                _ => Vec::new(),
+            },
+            Instr::SparseSwitch(_, offset) => match payload(*offset) {
+                Some(Instr::SparseSwitchPayload(_, targets)) => targets.clone(),
+                _ => Vec::new(),
+            },
+            _ => return Vec::new(),
+        };
+
+        // targets are relative to the branching instruction
+        let mut targets = Vec::new();
+        let successors = offsets.into_iter().map(|offset| addr.offset(offset));
+        for target in successors.chain([linstr.next_addr()]) {
+            if !targets.contains(&target) {
+                targets.push(target);
+            }
+        }
+        targets
+    }
+
     #[inline]
     pub fn instruction_at(&self, addr: Addr) -> DexResult<&LabeledInstr> {
         let index = self
@@ -212,6 +284,36 @@ impl CodeItem {
         Ok(&self.insns[index])
     }
 
+    /// Returns the code units encoding the instruction at the given address.
+    /// Instructions are stored decoded, so they are encoded again here.
+    pub fn instruction_bytes(&self, addr: Addr) -> DexResult<Vec<u16>> {
+        let linstr = self.instruction_at(addr)?;
+        let mut bytes = Vec::with_capacity(2 * linstr.size());
+        let _ = write_instr(&mut bytes, linstr.instr())?;
+        Ok(bytes
+            .chunks_exact(2)
+            .map(|unit| u16::from_le_bytes([unit[0], unit[1]]))
+            .collect())
+    }
+
+    /// Replaces the instruction at the given address by an instruction of
+    /// the same size, so that no other instruction is moved.
+    pub fn patch_instruction(&mut self, addr: Addr, new: &Instr) -> DexResult<()> {
+        let index = self
+            .insns
+            .binary_search_by(|probe| probe.addr().cmp(&addr))
+            .map_err(|_| DexError::InstructionNotFound(addr))?;
+        let new_instr = LabeledInstr {
+            addr,
+            instr: new.clone(),
+        };
+        if new_instr.size() != self.insns[index].size() {
+            return Err(DexError::BadInstructionSize);
+        }
+        self.insns[index] = new_instr;
+        Ok(())
+    }
+
     pub fn patch_instruction_at(&mut self, addr: Addr, new_instrs: Vec<Instr>) -> DexResult<()> {
         let index = self
             .insns
@@ -247,10 +349,424 @@ impl CodeItem {
         Ok(())
     }
 
+    /// Replaces the whole method body by `const/4 v0, 0; throw v0`, so that
+    /// invoking the method raises a `NullPointerException`. Try blocks and
+    /// debug information, which refer to the former instructions, are
+    /// dropped.
+    pub fn replace_with_throw_stub(&mut self) {
+        let reg = Reg::from(0u8);
+        let const_instr = LabeledInstr {
+            addr: Addr::entry(),
+            instr: Instr::Const4(reg, 0),
+        };
+        let throw_instr = LabeledInstr {
+            addr: Addr(const_instr.size()),
+            instr: Instr::Throw(reg),
+        };
+        self.insns = vec![const_instr, throw_instr];
+        self.registers_size = self.registers_size.max(1);
+        self.outs_size = 0;
+        self.debug_info_off = None;
+        self.tries.clear();
+        self.handlers = None;
+    }
+
     #[inline]
     pub fn iter_tries(&self) -> impl Iterator<Item = &TryItem> {
         self.tries.iter()
     }
+
+    /// Returns the body in a form that does not depend on register
+    /// allocation nor on the dex file layout, one line per instruction then
+    /// one line per try item. Registers are renumbered in order of first
+    /// use, string, type, field and method indices are replaced by the
+    /// values they refer to, and addresses are relative to the start of
+    /// the code.
+    ///
+    /// # Errors
+    ///
+    /// An error is returned if an index or a handler cannot be resolved.
+    pub fn normalized_body(&self, dex: &Dex) -> DexResult<Vec<String>> {
+        let mut registers: BTreeMap<u16, usize> = BTreeMap::new();
+        let mut body = Vec::new();
+        for instr in self.iter_instructions() {
+            body.push(normalize(instr.instr(), dex, |reg| {
+                let next = registers.len();
+                *registers.entry(reg.value()).or_insert(next)
+            })?);
+        }
+        for try_item in self.iter_tries() {
+            let mut line = format!("try {} {}", try_item.start_addr(), try_item.insn_count());
+            let handlers = try_item.handlers(self)?;
+            for handler in handlers.iter_handlers() {
+                let (catch_type, addr) = (handler.catch_type(dex)?, handler.catch_addr());
+                write!(line, " {catch_type}:{addr}")?;
+            }
+            if let Some(addr) = handlers.catch_all_addr() {
+                write!(line, " *:{addr}")?;
+            }
+            body.push(line);
+        }
+        Ok(body)
+    }
+
+    /// Adds a catch-all handler at `handler` to the instructions from
+    /// `start` (included) to `end` (excluded). See [`CodeItem::add_catch`].
+    ///
+    /// # Errors
+    ///
+    /// See [`CodeItem::add_catch`].
+    pub fn add_catch_all(&mut self, start: Addr, end: Addr, handler: Addr) -> DexResult<()> {
+        self.add_clause(start, end, None, handler)
+    }
+
+    /// Adds a handler at `handler` catching the exceptions of type
+    /// `exc_type` to the instructions from `start` (included) to `end`
+    /// (excluded).
+    ///
+    /// The new handler wraps the existing ones: where the range overlaps
+    /// existing try blocks, it is only reached by the exceptions that they
+    /// do not catch. Try blocks are split as needed to stay sorted and non
+    /// overlapping, and the handlers list is rebuilt.
+    ///
+    /// # Errors
+    ///
+    /// An error is returned if the range is empty or longer than 65535 code
+    /// units, or if `start`, `end` and `handler` are not instruction
+    /// addresses (`end` may also be the end of the code).
+    pub fn add_catch(
+        &mut self,
+        start: Addr,
+        end: Addr,
+        exc_type: Index<TypeIdItem>,
+        handler: Addr,
+    ) -> DexResult<()> {
+        self.add_clause(start, end, Some(exc_type), handler)
+    }
+
+    fn add_clause(
+        &mut self,
+        start: Addr,
+        end: Addr,
+        exc_type: Option<Index<TypeIdItem>>,
+        handler: Addr,
+    ) -> DexResult<()> {
+        let code_end = self
+            .insns
+            .last()
+            .map_or(Addr::entry(), LabeledInstr::next_addr);
+        self.instruction_at(start)?;
+        self.instruction_at(handler)?;
+        if end != code_end {
+            self.instruction_at(end)?;
+        }
+        if start.0 >= end.0 || end.0 - start.0 > usize::from(u16::MAX) {
+            return Err(DexError::Structure(format!(
+                "invalid try range from {start} to {end}"
+            )));
+        }
+
+        // existing try blocks, with their handlers as (type, address)
+        // clauses followed by the catch-all address
+        let mut blocks = Vec::with_capacity(self.tries.len());
+        for try_item in &self.tries {
+            let handlers = try_item.handlers(self)?;
+            let clauses: Clauses = (
+                handlers
+                    .handlers
+                    .iter()
+                    .map(|pair| (pair.type_idx.as_usize(), pair.catch_addr()))
+                    .collect(),
+                handlers.catch_all_addr(),
+            );
+            blocks.push((try_item.start_addr, try_item.end_addr().0, clauses));
+        }
+
+        // splits blocks at the boundaries of the new range, then adds the
+        // new clause to the pieces it covers
+        let mut bounds: Vec<usize> = blocks
+            .iter()
+            .flat_map(|(start, end, _)| [*start, *end])
+            .chain([start.0, end.0])
+            .collect();
+        bounds.sort_unstable();
+        bounds.dedup();
+        let mut pieces: Vec<(usize, usize, Clauses)> = Vec::new();
+        for bound in bounds.windows(2) {
+            let (lo, hi) = (bound[0], bound[1]);
+            let mut clauses = blocks
+                .iter()
+                .find(|(start, end, _)| *start <= lo && hi <= *end)
+                .map(|(_, _, clauses)| clauses.clone());
+            if start.0 <= lo && hi <= end.0 {
+                let (typed, catch_all) = clauses.get_or_insert_with(|| (Vec::new(), None));
+                // exceptions already caught do not reach the new handler
+                if catch_all.is_none() {
+                    match exc_type {
+                        None => *catch_all = Some(handler.0),
+                        Some(typ) if !typed.iter().any(|(t, _)| *t == typ.as_usize()) => {
+                            typed.push((typ.as_usize(), handler.0));
+                        }
+                        Some(_) => (),
+                    }
+                }
+            }
+            let Some(clauses) = clauses else {
+                continue;
+            };
+            match pieces.last_mut() {
+                // insn_count is a 16-bit value
+                Some((last_start, last_end, last))
+                    if *last_end == lo
+                        && *last == clauses
+                        && hi - *last_start <= usize::from(u16::MAX) =>
+                {
+                    *last_end = hi;
+                }
+                _ => pieces.push((lo, hi, clauses)),
+            }
+        }
+
+        // handlers are shared by the blocks with the same clauses
+        let mut distinct: Vec<&Clauses> = Vec::new();
+        for (_, _, clauses) in &pieces {
+            if !distinct.contains(&clauses) {
+                distinct.push(clauses);
+            }
+        }
+        let size = Uleb128::new(distinct.len() as u32, None);
+        let mut off = size.size();
+        let mut list = BTreeMap::new();
+        let mut offsets: Vec<(&Clauses, usize)> = Vec::with_capacity(distinct.len());
+        for clauses in distinct {
+            let (typed, catch_all) = clauses;
+            // a negative size tells that a catch-all address follows
+            let nb_typed = typed.len() as i32;
+            let nb_typed = if catch_all.is_some() {
+                -nb_typed
+            } else {
+                nb_typed
+            };
+            let handler = EncodedCatchHandler {
+                size: Sleb128::new(nb_typed, None),
+                handlers: typed
+                    .iter()
+                    .map(|(typ, addr)| EncodedTypeAddrPair {
+                        type_idx: Index::new_uleb(Uleb128::new(*typ as u32, None)),
+                        addr: Uleb128::new(*addr as u32, None),
+                    })
+                    .collect(),
+                catch_all_addr: catch_all.map(|addr| Uleb128::new(addr as u32, None)),
+            };
+            let handler_size = handler.size.size()
+                + handler
+                    .handlers
+                    .iter()
+                    .map(|pair| pair.type_idx.as_uleb().size() + pair.addr.size())
+                    .sum::<usize>()
+                + handler.catch_all_addr.map_or(0, |addr| addr.size());
+            offsets.push((clauses, off));
+            let _ = list.insert(off, handler);
+            off += handler_size;
+        }
+        let tries = pieces
+            .iter()
+            .map(|(lo, hi, clauses)| TryItem {
+                start_addr: *lo,
+                insn_count: hi - lo,
+                handler_off: offsets
+                    .iter()
+                    .find(|(c, _)| *c == clauses)
+                    .map_or(0, |(_, off)| *off),
+            })
+            .collect();
+        self.tries = tries;
+        self.handlers = Some(EncodedCatchHandlerList { size, list });
+        Ok(())
+    }
+}
+
+/// The clauses of a catch handler: the caught type indices along with the
+/// handler addresses, then the catch-all handler address.
+type Clauses = (Vec<(usize, usize)>, Option<usize>);
+
+/// Where the relative offsets of a branching instruction point to,
+/// given as instruction indexes in the relocated stream.
+enum BranchTarget {
+    Branch(usize),
+    Payload(usize),
+    Switch { payload: usize, cases: Vec<usize> },
+}
+
+/// Rewrites the relative targets of branching instructions (conditional
+/// branches, gotos, switches and fill-array-data) after instructions have
+/// been inserted or removed.
+///
+/// `instrs` is the new instruction stream, starting at address 0, and
+/// `old_to_new` maps the address of each original instruction that has
+/// been kept to its address in that stream. Instructions that are not the
+/// image of an original instruction are left untouched.
+///
+/// Gotos whose new offset does not fit anymore are widened (`goto` to
+/// `goto/16` to `goto/32`); the resulting shift of the following
+/// instructions is taken into account for all the rewritten offsets.
+/// Payload alignment is not restored, this is left to the caller.
+///
+/// # Errors
+///
+/// An error is returned if an original target has no new address, or if a
+/// conditional branch offset is out of range once relocated.
+pub fn relocate_branches(instrs: &mut [Instr], old_to_new: &BTreeMap<Addr, Addr>) -> DexResult<()> {
+    let mut base_addrs = Vec::with_capacity(instrs.len());
+    let mut addr = 0;
+    for instr in instrs.iter() {
+        base_addrs.push(addr);
+        addr += instr.size();
+    }
+    let index_of: BTreeMap<usize, usize> = base_addrs
+        .iter()
+        .enumerate()
+        .map(|(index, addr)| (*addr, index))
+        .collect();
+    let new_to_old: BTreeMap<Addr, Addr> = old_to_new.iter().map(|(o, n)| (*n, *o)).collect();
+    let relocated = |old: Addr, offset: i32| -> DexResult<usize> {
+        let target = Addr::from_offset(old, offset);
+        let new_target = old_to_new
+            .get(&target)
+            .ok_or(DexError::InstructionNotFound(target))?;
+        index_of
+            .get(&new_target.0)
+            .copied()
+            .ok_or(DexError::InstructionNotFound(*new_target))
+    };
+
+    let mut targets = Vec::new();
+    for (index, instr) in instrs.iter().enumerate() {
+        let Some(old) = new_to_old.get(&Addr(base_addrs[index])).copied() else {
+            continue;
+        };
+        let target = match instr {
+            Instr::Goto(offset) => BranchTarget::Branch(relocated(old, i32::from(*offset))?),
+            Instr::Goto16(offset)
+            | Instr::IfEq(_, _, offset)
+            | Instr::IfNe(_, _, offset)
+            | Instr::IfLt(_, _, offset)
+            | Instr::IfGe(_, _, offset)
+            | Instr::IfGt(_, _, offset)
+            | Instr::IfLe(_, _, offset)
+            | Instr::IfEqz(_, offset)
+            | Instr::IfNez(_, offset)
+            | Instr::IfLtz(_, offset)
+            | Instr::IfGez(_, offset)
+            | Instr::IfGtz(_, offset)
+            | Instr::IfLez(_, offset) => BranchTarget::Branch(relocated(old, i32::from(*offset))?),
+            Instr::Goto32(offset) => BranchTarget::Branch(relocated(old, *offset)?),
+            Instr::FillArrayData(_, offset) => BranchTarget::Payload(relocated(old, *offset)?),
+            Instr::PackedSwitch(_, offset) | Instr::SparseSwitch(_, offset) => {
+                let payload = relocated(old, *offset)?;
+                let cases = match &instrs[payload] {
+                    Instr::PackedSwitchPayload(_, cases) | Instr::SparseSwitchPayload(_, cases) => {
+                        cases
+                            .iter()
+                            .map(|case| relocated(old, *case))
+                            .collect::<DexResult<Vec<usize>>>()?
+                    }
+                    _ => {
+                        return Err(DexError::Structure(format!(
+                            "switch at {old} does not point to a switch payload"
+                        )))
+                    }
+                };
+                BranchTarget::Switch { payload, cases }
+            }
+            _ => continue,
+        };
+        targets.push((index, target));
+    }
+
+    loop {
+        let mut addrs = Vec::with_capacity(instrs.len());
+        let mut addr = 0;
+        for instr in instrs.iter() {
+            addrs.push(addr as i64);
+            addr += instr.size();
+        }
+
+        let mut widened = false;
+        for (index, target) in &targets {
+            let offset_to = |target: usize| addrs[target] - addrs[*index];
+            match target {
+                BranchTarget::Branch(target) => {
+                    let offset = offset_to(*target);
+                    let instr = &mut instrs[*index];
+                    match instr {
+                        Instr::Goto(_) | Instr::Goto16(_) | Instr::Goto32(_) => {
+                            let new_instr = match (i8::try_from(offset), i16::try_from(offset)) {
+                                (Ok(o), _) if o != 0 && matches!(instr, Instr::Goto(_)) => {
+                                    Instr::Goto(o)
+                                }
+                                (_, Ok(o)) if o != 0 && !matches!(instr, Instr::Goto32(_)) => {
+                                    Instr::Goto16(o)
+                                }
+                                _ => Instr::Goto32(offset_i32(offset)?),
+                            };
+                            widened |= new_instr.size() != instr.size();
+                            *instr = new_instr;
+                        }
+                        Instr::IfEq(_, _, o)
+                        | Instr::IfNe(_, _, o)
+                        | Instr::IfLt(_, _, o)
+                        | Instr::IfGe(_, _, o)
+                        | Instr::IfGt(_, _, o)
+                        | Instr::IfLe(_, _, o)
+                        | Instr::IfEqz(_, o)
+                        | Instr::IfNez(_, o)
+                        | Instr::IfLtz(_, o)
+                        | Instr::IfGez(_, o)
+                        | Instr::IfGtz(_, o)
+                        | Instr::IfLez(_, o) => {
+                            *o = i16::try_from(offset).map_err(|_| {
+                                DexError::Structure(format!(
+                                    "branch offset {offset} is out of range"
+                                ))
+                            })?;
+                        }
+                        _ => unreachable!(),
+                    }
+                }
+                BranchTarget::Payload(payload) => {
+                    if let Instr::FillArrayData(_, o) = &mut instrs[*index] {
+                        *o = offset_i32(offset_to(*payload))?;
+                    }
+                }
+                BranchTarget::Switch { payload, cases } => {
+                    if let Instr::PackedSwitch(_, o) | Instr::SparseSwitch(_, o) =
+                        &mut instrs[*index]
+                    {
+                        *o = offset_i32(offset_to(*payload))?;
+                    }
+                    let new_cases = cases
+                        .iter()
+                        .map(|case| offset_i32(offset_to(*case)))
+                        .collect::<DexResult<Vec<i32>>>()?;
+                    if let Instr::PackedSwitchPayload(_, c) | Instr::SparseSwitchPayload(_, c) =
+                        &mut instrs[*payload]
+                    {
+                        *c = new_cases;
+                    }
+                }
+            }
+        }
+
+        if !widened {
+            return Ok(());
+        }
+    }
+}
+
+fn offset_i32(offset: i64) -> DexResult<i32> {
+    i32::try_from(offset)
+        .map_err(|_| DexError::Structure(format!("branch offset {offset} is out of range")))
 }
 
 #[derive(Debug)]
@@ -367,6 +883,33 @@ impl DexCollection for DebugInfoItem {
 }
 
 impl DebugInfoItem {
+    /// Returns the entries of the line number table, as pairs of the address
+    /// of the first instruction of a source line and its line number.
+    pub fn iter_line_entries(&self) -> impl Iterator<Item = (Addr, u32)> + '_ {
+        let mut addr = 0;
+        let mut line = i64::from(self.line_start.value());
+        self.bytecode
+            .iter()
+            .take_while(|instr| !matches!(instr, DbgInstr::EndSequence))
+            .filter_map(move |instr| match instr {
+                DbgInstr::AdvancePc { addr_diff } => {
+                    addr += addr_diff.value() as usize;
+                    None
+                }
+                DbgInstr::AdvanceLine { line_diff } => {
+                    line += i64::from(line_diff.value());
+                    None
+                }
+                DbgInstr::Special(opcode) => {
+                    let adjusted = opcode - DBG_FIRST_SPECIAL;
+                    line += DBG_LINE_BASE + i64::from(adjusted % DBG_LINE_RANGE);
+                    addr += usize::from(adjusted / DBG_LINE_RANGE);
+                    Some((Addr(addr), line as u32))
+                }
+                _ => None,
+            })
+    }
+
     pub(crate) fn size(&self) -> usize {
         let parameter_names_size: usize = self
             .parameter_names
@@ -378,6 +921,11 @@ impl DebugInfoItem {
     }
 }
 
+// Special opcodes both advance the address and the line number.
+const DBG_FIRST_SPECIAL: u8 = 0x0a;
+const DBG_LINE_BASE: i64 = -4;
+const DBG_LINE_RANGE: u8 = 15;
+
 #[derive(Debug)]
 pub(crate) enum DbgInstr {
     EndSequence,
@@ -448,3 +996,265 @@ impl DbgInstr {
         }
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn line_entries() {
+        // .line 3 at 0, .line 5 at 2, .line 15 at 5
+        let info = DebugInfoItem {
+            index: Index::new(0),
+            line_start: Uleb128::new(3, None),
+            parameters_size: Uleb128::new(0, None),
+            parameter_names: Vec::new(),
+            bytecode: vec![
+                DbgInstr::SetPrologueEnd,
+                DbgInstr::Special(0x0e),
+                DbgInstr::Special(0x2e),
+                DbgInstr::AdvanceLine {
+                    line_diff: Sleb128::new(10, None),
+                },
+                DbgInstr::AdvancePc {
+                    addr_diff: Uleb128::new(3, None),
+                },
+                DbgInstr::Special(0x0e),
+                DbgInstr::EndSequence,
+                DbgInstr::Special(0x0e),
+            ],
+        };
+        assert_eq!(
+            info.iter_line_entries().collect::<Vec<_>>(),
+            [(Addr(0), 3), (Addr(2), 5), (Addr(5), 15)]
+        );
+    }
+
+    #[test]
+    fn relocate_widens_backward_goto() {
+        // 0: nop (target), 1..=127: nop, 128: goto -128, 129: return-void
+        let mut instrs = vec![Instr::Nop; 128];
+        instrs.push(Instr::Goto(-128));
+        instrs.push(Instr::ReturnVoid);
+
+        // insert a nop at address 1, between the target and the goto
+        instrs.insert(1, Instr::Nop);
+        let mut old_to_new = BTreeMap::new();
+        old_to_new.insert(Addr(0), Addr(0));
+        for old in 1..=129 {
+            old_to_new.insert(Addr(old), Addr(old + 1));
+        }
+
+        relocate_branches(&mut instrs, &old_to_new).unwrap();
+        assert!(matches!(instrs[129], Instr::Goto16(-129)));
+        assert!(matches!(instrs[130], Instr::ReturnVoid));
+    }
+
+    #[test]
+    fn relocate_forward_branch_over_widened_goto() {
+        // 0: if-eqz v0, +131, 2..=129: nop, 130: goto -128 (to 2), 131: return-void
+        let mut instrs = vec![Instr::IfEqz(Reg::from(0u8), 131)];
+        instrs.extend(vec![Instr::Nop; 128]);
+        instrs.push(Instr::Goto(-128));
+        instrs.push(Instr::ReturnVoid);
+
+        // insert a nop at address 3, the goto then needs to be widened
+        instrs.insert(2, Instr::Nop);
+        let mut old_to_new = BTreeMap::new();
+        old_to_new.insert(Addr(0), Addr(0));
+        old_to_new.insert(Addr(2), Addr(2));
+        for old in 3..=131 {
+            old_to_new.insert(Addr(old), Addr(old + 1));
+        }
+
+        relocate_branches(&mut instrs, &old_to_new).unwrap();
+        assert!(matches!(instrs[130], Instr::Goto16(-129)));
+        // the goto grew by one code unit
+        assert!(matches!(instrs[0], Instr::IfEqz(_, 133)));
+    }
+
+    fn code_item(instrs: Vec<Instr>) -> CodeItem {
+        let mut addr = 0;
+        let insns = instrs
+            .into_iter()
+            .map(|instr| {
+                let linstr = LabeledInstr {
+                    addr: Addr(addr),
+                    instr,
+                };
+                addr += linstr.size();
+                linstr
+            })
+            .collect();
+        CodeItem {
+            index: Index::new(0),
+            registers_size: 1,
+            ins_size: 1,
+            outs_size: 0,
+            debug_info_off: None,
+            insns,
+            tries: Vec::new(),
+            handlers: None,
+        }
+    }
+
+    fn targets(code: &CodeItem) -> Vec<(usize, Vec<usize>)> {
+        code.iter_instructions_with_targets()
+            .map(|(addr, _, targets)| (addr.0, targets.into_iter().map(|t| t.0).collect()))
+            .collect()
+    }
+
+    #[test]
+    fn packed_switch_targets() {
+        // 0: packed-switch v0, +6, 3..=5: return-void, 6: payload (cases 4 and 5)
+        let code = code_item(vec![
+            Instr::PackedSwitch(Reg::from(0u8), 6),
+            Instr::ReturnVoid,
+            Instr::ReturnVoid,
+            Instr::ReturnVoid,
+            Instr::PackedSwitchPayload(0, vec![4, 5]),
+        ]);
+        assert_eq!(
+            targets(&code),
+            [
+                (0, vec![4, 5, 3]),
+                (3, vec![]),
+                (4, vec![]),
+                (5, vec![]),
+                (6, vec![])
+            ]
+        );
+    }
+
+    #[test]
+    fn sparse_switch_targets() {
+        // 0: sparse-switch v0, +6, 3..=5: return-void, 6: payload (cases 5 and 3)
+        let code = code_item(vec![
+            Instr::SparseSwitch(Reg::from(0u8), 6),
+            Instr::ReturnVoid,
+            Instr::ReturnVoid,
+            Instr::ReturnVoid,
+            Instr::SparseSwitchPayload(vec![-1, 10], vec![5, 3]),
+        ]);
+        assert_eq!(targets(&code)[0], (0, vec![5, 3]));
+
+        // missing payload, only the fall-through remains
+        let code = code_item(vec![
+            Instr::SparseSwitch(Reg::from(0u8), 4),
+            Instr::ReturnVoid,
+        ]);
+        assert_eq!(targets(&code)[0], (0, vec![3]));
+    }
+
+    #[test]
+    fn goto32_targets() {
+        // 0: return-void, 1: if-eqz v0, +5, 3: goto/32 -3, 6: return-void
+        let code = code_item(vec![
+            Instr::ReturnVoid,
+            Instr::IfEqz(Reg::from(0u8), 5),
+            Instr::Goto32(-3),
+            Instr::ReturnVoid,
+        ]);
+        assert_eq!(
+            targets(&code),
+            [(0, vec![]), (1, vec![6, 3]), (3, vec![0]), (6, vec![])]
+        );
+    }
+
+    #[test]
+    fn add_overlapping_catches() {
+        // 0..=2: nop, 3: return-void
+        let mut addr = Addr::entry();
+        let mut insns = Vec::new();
+        for instr in [Instr::Nop, Instr::Nop, Instr::Nop, Instr::ReturnVoid] {
+            let linstr = LabeledInstr { addr, instr };
+            addr = linstr.next_addr();
+            insns.push(linstr);
+        }
+        let mut code = CodeItem {
+            index: Index::new(0),
+            registers_size: 0,
+            ins_size: 0,
+            outs_size: 0,
+            debug_info_off: None,
+            insns,
+            tries: Vec::new(),
+            handlers: None,
+        };
+        let clauses = |code: &CodeItem| {
+            code.iter_tries()
+                .map(|try_item| {
+                    let handlers = try_item.handlers(code).unwrap();
+                    (
+                        try_item.start_addr().0,
+                        try_item.end_addr().0,
+                        handlers
+                            .iter_handlers()
+                            .map(|pair| (pair.type_idx.as_usize(), pair.catch_addr()))
+                            .collect::<Vec<_>>(),
+                        handlers.catch_all_addr(),
+                    )
+                })
+                .collect::<Vec<_>>()
+        };
+
+        code.add_catch_all(Addr(1), Addr(3), Addr(3)).unwrap();
+        code.add_catch(Addr(0), Addr(2), Index::new(7), Addr(3))
+            .unwrap();
+        // the catch-all block already catches everything from 1 to 3
+        assert_eq!(
+            clauses(&code),
+            [(0, 1, vec![(7, 3)], None), (1, 3, vec![], Some(3))]
+        );
+        code.add_catch(Addr(0), Addr(4), Index::new(7), Addr(3))
+            .unwrap();
+        assert_eq!(
+            clauses(&code),
+            [
+                (0, 1, vec![(7, 3)], None),
+                (1, 3, vec![], Some(3)),
+                (3, 4, vec![(7, 3)], None)
+            ]
+        );
+        // both typed blocks share the same handler
+        assert_eq!(code.tries[0].handler_off, code.tries[2].handler_off);
+        assert!(code.add_catch_all(Addr(2), Addr(2), Addr(3)).is_err());
+        assert!(code.add_catch_all(Addr(0), Addr(5), Addr(3)).is_err());
+    }
+
+    #[test]
+    fn normalized_body_ignores_registers() {
+        use crate::testing::{DexBuilder, ACC_STATIC};
+
+        let mut builder = DexBuilder::new();
+        let class = builder.class("LFoo;", "Ljava/lang/Object;");
+        let x = builder.string("x") as u16;
+        let log = builder.method("LFoo;", "log", "V", &["Ljava/lang/String;"]) as u16;
+        // const-string vN, "x"; invoke-static {vN}, LFoo;->log(...)V; return-void
+        let body = |reg: u16| [reg << 8 | 0x1a, x, 0x1071, log, reg, 0x000e];
+        let methods: Vec<u32> = ["a", "b", "c"]
+            .into_iter()
+            .map(|name| builder.method("LFoo;", name, "V", &[]))
+            .collect();
+        builder.code(class, methods[0], ACC_STATIC, 2, 0, 1, &body(1));
+        builder.code(class, methods[1], ACC_STATIC, 1, 0, 1, &body(0));
+        builder.code(class, methods[2], ACC_STATIC, 1, 0, 1, &body(0));
+        builder.catch_all(class, methods[2], 0, 2, 5);
+        let dex = builder.build_dex();
+
+        let data = dex.iter_class_defs().next().unwrap().data(&dex);
+        let bodies: Vec<Vec<String>> = data
+            .unwrap()
+            .unwrap()
+            .iter_methods()
+            .map(|method| {
+                let code = method.code(&dex).unwrap().unwrap().read().unwrap();
+                code.normalized_body(&dex).unwrap()
+            })
+            .collect();
+        assert_eq!(bodies[0], bodies[1]);
+        assert_eq!(bodies[0][0], "const-string r0 String(\"x\")");
+        assert_eq!(bodies[2][..3], bodies[0]);
+        assert_eq!(bodies[2][3], "try 0 2 *:5");
+    }
+}
@@ -39,6 +39,9 @@ pub enum DexError {
     #[error("dex {0} has invalid offset")]
     InvalidOffset(String),
 
+    #[error("dex offset {off:#x} is out of bounds")]
+    MalformedOffset { off: usize },
+
     #[error("unexpected padding value in dex")]
     NonZeroPadding,
 
@@ -59,6 +62,15 @@ pub enum DexError {
 
     #[error("Bad instruction(s) size")]
     BadInstructionSize,
+
+    #[error("Instruction size mismatch (address: {addr}, opcode: {opcode:#06x})")]
+    InstructionSizeMismatch { addr: Addr, opcode: u16 },
+
+    #[error("cannot use dex version {version:03}: {reason}")]
+    Version { version: u32, reason: String },
+
+    #[error("compact dex cannot be written")]
+    UnsupportedCompactDexWrite,
 }
 
 impl nom::error::ParseError<&[u8]> for DexError {
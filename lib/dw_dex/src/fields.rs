@@ -1,5 +1,6 @@
 //! Dalvik class fields data structures.
 
+use crate::annotations::{Annotation, AnnotationsDirectoryItem};
 use crate::errors::{DexError, DexResult};
 use crate::strings::StringIdItem;
 use crate::types::{Type, TypeIdItem};
@@ -87,6 +88,22 @@ impl EncodedField {
     pub const fn flags(&self) -> FieldFlags {
         self.access_flags
     }
+
+    /// Returns the decoded annotations of the field.
+    pub fn annotations<'a>(&self, dex: &'a Dex) -> DexResult<Vec<Annotation<'a>>> {
+        let class_idx = self.field_idx.get(dex)?.class_idx;
+        let Some(directory) = AnnotationsDirectoryItem::of_class(class_idx, dex)? else {
+            return Ok(Vec::new());
+        };
+        match directory
+            .field_annotations
+            .iter()
+            .find(|annotation| annotation.field_idx.as_usize() == self.field_idx.as_usize())
+        {
+            Some(annotation) => annotation.annotations_off.get(dex)?.annotations(dex),
+            None => Ok(Vec::new()),
+        }
+    }
 }
 
 bitflags! {
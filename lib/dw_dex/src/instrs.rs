@@ -12,7 +12,7 @@ use crate::{Addr, Dex, DexIndex, Index, PrettyPrint, WithDex};
 use instruction_derive::Instruction;
 use serde::ser::{self, SerializeStruct, Serializer};
 use serde::Serialize;
-use std::fmt;
+use std::fmt::{self, Write};
 
 pub trait Instruction {
     fn mnemonic(&self) -> &str;
@@ -68,7 +68,7 @@ impl<'a> Serialize for WithDex<'a, LabeledInstr> {
     where
         S: Serializer,
     {
-        let operands = collect_operands::<S>(self.data.instr(), self.dex)?;
+        let operands = collect_operands(self.data.instr(), self.dex).map_err(ser::Error::custom)?;
 
         let mut state = serializer.serialize_struct("Instr", 3)?;
         state.serialize_field("address", &self.data.addr().0)?;
@@ -1481,12 +1481,411 @@ impl PrettyPrint for Instr {
     }
 }
 
+/// An index into one of the dex tables, as referenced by an instruction.
+#[derive(Debug, Clone, Copy)]
+pub enum AnyIndex {
+    String(Index<StringIdItem>),
+    Type(Index<TypeIdItem>),
+    Field(Index<FieldIdItem>),
+    Method(Index<MethodIdItem>),
+    Proto(Index<ProtoIdItem>),
+    CallSite(Index<CallSiteIdItem>),
+    MethodHandle(Index<MethodHandleItem>),
+}
+
+/// Registers and index operands of an instruction.
+///
+/// Wide values (longs and doubles) are held by pairs of registers, both
+/// registers of a pair are reported.
+pub trait Operands {
+    /// Returns the registers written by the instruction.
+    fn dst_registers(&self) -> Vec<Reg>;
+
+    /// Returns the registers read by the instruction.
+    fn src_registers(&self) -> Vec<Reg>;
+
+    /// Returns the dex table entry referenced by the instruction, if any.
+    /// For `invoke-polymorphic` instructions, this is the invoked method
+    /// (not its prototype).
+    fn referenced_index(&self) -> Option<AnyIndex>;
+}
+
+impl Operands for Instr {
+    fn dst_registers(&self) -> Vec<Reg> {
+        written_read_registers(self).0
+    }
+
+    fn src_registers(&self) -> Vec<Reg> {
+        written_read_registers(self).1
+    }
+
+    fn referenced_index(&self) -> Option<AnyIndex> {
+        match self {
+            Self::ConstString(_, s) | Self::ConstStringJumbo(_, s) => Some(AnyIndex::String(*s)),
+
+            Self::ConstClass(_, t)
+            | Self::CheckCast(_, t)
+            | Self::InstanceOf(_, _, t)
+            | Self::NewInstance(_, t)
+            | Self::NewArray(_, _, t)
+            | Self::FilledNewArray(_, t)
+            | Self::FilledNewArrayRange(_, t) => Some(AnyIndex::Type(*t)),
+
+            Self::Iget(_, _, f)
+            | Self::IgetWide(_, _, f)
+            | Self::IgetObject(_, _, f)
+            | Self::IgetBoolean(_, _, f)
+            | Self::IgetByte(_, _, f)
+            | Self::IgetChar(_, _, f)
+            | Self::IgetShort(_, _, f)
+            | Self::Iput(_, _, f)
+            | Self::IputWide(_, _, f)
+            | Self::IputObject(_, _, f)
+            | Self::IputBoolean(_, _, f)
+            | Self::IputByte(_, _, f)
+            | Self::IputChar(_, _, f)
+            | Self::IputShort(_, _, f)
+            | Self::Sget(_, f)
+            | Self::SgetWide(_, f)
+            | Self::SgetObject(_, f)
+            | Self::SgetBoolean(_, f)
+            | Self::SgetByte(_, f)
+            | Self::SgetChar(_, f)
+            | Self::SgetShort(_, f)
+            | Self::Sput(_, f)
+            | Self::SputWide(_, f)
+            | Self::SputObject(_, f)
+            | Self::SputBoolean(_, f)
+            | Self::SputByte(_, f)
+            | Self::SputChar(_, f)
+            | Self::SputShort(_, f) => Some(AnyIndex::Field(*f)),
+
+            Self::InvokeVirtual(_, m)
+            | Self::InvokeSuper(_, m)
+            | Self::InvokeDirect(_, m)
+            | Self::InvokeStatic(_, m)
+            | Self::InvokeInterface(_, m)
+            | Self::InvokePolymorphic(_, m, _)
+            | Self::InvokeVirtualRange(_, m)
+            | Self::InvokeSuperRange(_, m)
+            | Self::InvokeDirectRange(_, m)
+            | Self::InvokeStaticRange(_, m)
+            | Self::InvokeInterfaceRange(_, m)
+            | Self::InvokePolymorphicRange(_, m, _) => Some(AnyIndex::Method(*m)),
+
+            Self::ConstMethodType(_, p) => Some(AnyIndex::Proto(*p)),
+            Self::InvokeCustom(_, cs) | Self::InvokeCustomRange(_, cs) => {
+                Some(AnyIndex::CallSite(*cs))
+            }
+            Self::ConstMethodHandle(_, h) => Some(AnyIndex::MethodHandle(*h)),
+
+            _ => None,
+        }
+    }
+}
+
+/// Formats an instruction in a form that does not depend on the dex file
+/// layout: registers (read ones, then written ones) are renamed by
+/// `rename`, and indices are replaced by the values they refer to.
+pub(crate) fn normalize(
+    instr: &Instr,
+    dex: &Dex,
+    mut rename: impl FnMut(Reg) -> usize,
+) -> DexResult<String> {
+    let mut line = instr.mnemonic().to_string();
+    for reg in instr
+        .src_registers()
+        .into_iter()
+        .chain(instr.dst_registers())
+    {
+        write!(line, " r{}", rename(reg))?;
+    }
+    for operand in collect_operands(instr, dex)? {
+        if !matches!(
+            operand,
+            Operand::Reg(_) | Operand::RegList(_) | Operand::RegRange(_)
+        ) {
+            write!(line, " {operand:?}")?;
+        }
+    }
+    // payloads have no operands, their data is kept as is
+    if matches!(
+        instr,
+        Instr::PackedSwitchPayload(_, _)
+            | Instr::SparseSwitchPayload(_, _)
+            | Instr::FillArrayDataPayload(_)
+    ) {
+        write!(line, " {instr:?}")?;
+    }
+    Ok(line)
+}
+
+// Both registers of the pair holding a wide value.
+fn pair(r: Reg) -> [Reg; 2] {
+    [r, r.next()]
+}
+
+// Returns the registers respectively written and read by an instruction.
+fn written_read_registers(instr: &Instr) -> (Vec<Reg>, Vec<Reg>) {
+    match instr {
+        Instr::Move(a, b)
+        | Instr::MoveFrom16(a, b)
+        | Instr::Move16(a, b)
+        | Instr::MoveObject(a, b)
+        | Instr::MoveObjectFrom16(a, b)
+        | Instr::MoveObject16(a, b)
+        | Instr::InstanceOf(a, b, _)
+        | Instr::ArrayLength(a, b)
+        | Instr::NewArray(a, b, _)
+        | Instr::IgetObject(a, b, _)
+        | Instr::Iget(a, b, _)
+        | Instr::IgetBoolean(a, b, _)
+        | Instr::IgetByte(a, b, _)
+        | Instr::IgetChar(a, b, _)
+        | Instr::IgetShort(a, b, _)
+        | Instr::NegInt(a, b)
+        | Instr::NotInt(a, b)
+        | Instr::NegFloat(a, b)
+        | Instr::IntToFloat(a, b)
+        | Instr::FloatToInt(a, b)
+        | Instr::IntToByte(a, b)
+        | Instr::IntToChar(a, b)
+        | Instr::IntToShort(a, b)
+        | Instr::AddIntLit16(a, b, _)
+        | Instr::RsubInt(a, b, _)
+        | Instr::MulIntLit16(a, b, _)
+        | Instr::DivIntLit16(a, b, _)
+        | Instr::RemIntLit16(a, b, _)
+        | Instr::AndIntLit16(a, b, _)
+        | Instr::OrIntLit16(a, b, _)
+        | Instr::XorIntLit16(a, b, _)
+        | Instr::AddIntLit8(a, b, _)
+        | Instr::RsubIntLit8(a, b, _)
+        | Instr::MulIntLit8(a, b, _)
+        | Instr::DivIntLit8(a, b, _)
+        | Instr::RemIntLit8(a, b, _)
+        | Instr::AndIntLit8(a, b, _)
+        | Instr::OrIntLit8(a, b, _)
+        | Instr::XorIntLit8(a, b, _)
+        | Instr::ShlIntLit8(a, b, _)
+        | Instr::ShrIntLit8(a, b, _)
+        | Instr::UshrIntLit8(a, b, _) => (vec![*a], vec![*b]),
+
+        Instr::MoveWide(a, b)
+        | Instr::MoveWideFrom16(a, b)
+        | Instr::MoveWide16(a, b)
+        | Instr::NegLong(a, b)
+        | Instr::NotLong(a, b)
+        | Instr::NegDouble(a, b)
+        | Instr::LongToDouble(a, b)
+        | Instr::DoubleToLong(a, b) => (pair(*a).to_vec(), pair(*b).to_vec()),
+
+        Instr::IntToLong(a, b)
+        | Instr::IntToDouble(a, b)
+        | Instr::FloatToLong(a, b)
+        | Instr::FloatToDouble(a, b)
+        | Instr::IgetWide(a, b, _) => (pair(*a).to_vec(), vec![*b]),
+
+        Instr::LongToInt(a, b)
+        | Instr::LongToFloat(a, b)
+        | Instr::DoubleToInt(a, b)
+        | Instr::DoubleToFloat(a, b) => (vec![*a], pair(*b).to_vec()),
+
+        Instr::MoveResult(a)
+        | Instr::MoveResultObject(a)
+        | Instr::MoveException(a)
+        | Instr::Const4(a, _)
+        | Instr::Const16(a, _)
+        | Instr::Const(a, _)
+        | Instr::ConstHigh16(a, _)
+        | Instr::ConstString(a, _)
+        | Instr::ConstStringJumbo(a, _)
+        | Instr::ConstClass(a, _)
+        | Instr::NewInstance(a, _)
+        | Instr::Sget(a, _)
+        | Instr::SgetObject(a, _)
+        | Instr::SgetBoolean(a, _)
+        | Instr::SgetByte(a, _)
+        | Instr::SgetChar(a, _)
+        | Instr::SgetShort(a, _)
+        | Instr::ConstMethodHandle(a, _)
+        | Instr::ConstMethodType(a, _) => (vec![*a], vec![]),
+
+        Instr::MoveResultWide(a)
+        | Instr::ConstWide16(a, _)
+        | Instr::ConstWide32(a, _)
+        | Instr::ConstWide(a, _)
+        | Instr::ConstWideHigh16(a, _)
+        | Instr::SgetWide(a, _) => (pair(*a).to_vec(), vec![]),
+
+        Instr::Return(a)
+        | Instr::ReturnObject(a)
+        | Instr::MonitorEnter(a)
+        | Instr::MonitorExit(a)
+        | Instr::CheckCast(a, _)
+        | Instr::FillArrayData(a, _)
+        | Instr::Throw(a)
+        | Instr::PackedSwitch(a, _)
+        | Instr::SparseSwitch(a, _)
+        | Instr::IfEqz(a, _)
+        | Instr::IfNez(a, _)
+        | Instr::IfLtz(a, _)
+        | Instr::IfGez(a, _)
+        | Instr::IfGtz(a, _)
+        | Instr::IfLez(a, _)
+        | Instr::Sput(a, _)
+        | Instr::SputObject(a, _)
+        | Instr::SputBoolean(a, _)
+        | Instr::SputByte(a, _)
+        | Instr::SputChar(a, _)
+        | Instr::SputShort(a, _) => (vec![], vec![*a]),
+
+        Instr::ReturnWide(a) | Instr::SputWide(a, _) => (vec![], pair(*a).to_vec()),
+
+        Instr::IfEq(a, b, _)
+        | Instr::IfNe(a, b, _)
+        | Instr::IfLt(a, b, _)
+        | Instr::IfGe(a, b, _)
+        | Instr::IfGt(a, b, _)
+        | Instr::IfLe(a, b, _)
+        | Instr::Iput(a, b, _)
+        | Instr::IputObject(a, b, _)
+        | Instr::IputBoolean(a, b, _)
+        | Instr::IputByte(a, b, _)
+        | Instr::IputChar(a, b, _)
+        | Instr::IputShort(a, b, _) => (vec![], vec![*a, *b]),
+
+        Instr::IputWide(a, b, _) => (vec![], vec![*a, a.next(), *b]),
+
+        Instr::CmplFloat(a, b, c)
+        | Instr::CmpgFloat(a, b, c)
+        | Instr::Aget(a, b, c)
+        | Instr::AgetObject(a, b, c)
+        | Instr::AgetBoolean(a, b, c)
+        | Instr::AgetByte(a, b, c)
+        | Instr::AgetChar(a, b, c)
+        | Instr::AgetShort(a, b, c)
+        | Instr::AddInt(a, b, c)
+        | Instr::SubInt(a, b, c)
+        | Instr::MulInt(a, b, c)
+        | Instr::DivInt(a, b, c)
+        | Instr::RemInt(a, b, c)
+        | Instr::AndInt(a, b, c)
+        | Instr::OrInt(a, b, c)
+        | Instr::XorInt(a, b, c)
+        | Instr::ShlInt(a, b, c)
+        | Instr::ShrInt(a, b, c)
+        | Instr::UshrInt(a, b, c)
+        | Instr::AddFloat(a, b, c)
+        | Instr::SubFloat(a, b, c)
+        | Instr::MulFloat(a, b, c)
+        | Instr::DivFloat(a, b, c)
+        | Instr::RemFloat(a, b, c) => (vec![*a], vec![*b, *c]),
+
+        Instr::CmplDouble(a, b, c) | Instr::CmpgDouble(a, b, c) | Instr::CmpLong(a, b, c) => {
+            (vec![*a], vec![*b, b.next(), *c, c.next()])
+        }
+
+        Instr::AgetWide(a, b, c) => (pair(*a).to_vec(), vec![*b, *c]),
+
+        Instr::Aput(a, b, c)
+        | Instr::AputObject(a, b, c)
+        | Instr::AputBoolean(a, b, c)
+        | Instr::AputByte(a, b, c)
+        | Instr::AputChar(a, b, c)
+        | Instr::AputShort(a, b, c) => (vec![], vec![*a, *b, *c]),
+
+        Instr::AputWide(a, b, c) => (vec![], vec![*a, a.next(), *b, *c]),
+
+        Instr::AddLong(a, b, c)
+        | Instr::SubLong(a, b, c)
+        | Instr::MulLong(a, b, c)
+        | Instr::DivLong(a, b, c)
+        | Instr::RemLong(a, b, c)
+        | Instr::AndLong(a, b, c)
+        | Instr::OrLong(a, b, c)
+        | Instr::XorLong(a, b, c)
+        | Instr::AddDouble(a, b, c)
+        | Instr::SubDouble(a, b, c)
+        | Instr::MulDouble(a, b, c)
+        | Instr::DivDouble(a, b, c)
+        | Instr::RemDouble(a, b, c) => (pair(*a).to_vec(), vec![*b, b.next(), *c, c.next()]),
+
+        Instr::ShlLong(a, b, c) | Instr::ShrLong(a, b, c) | Instr::UshrLong(a, b, c) => {
+            (pair(*a).to_vec(), vec![*b, b.next(), *c])
+        }
+
+        Instr::AddInt2addr(a, b)
+        | Instr::SubInt2addr(a, b)
+        | Instr::MulInt2addr(a, b)
+        | Instr::DivInt2addr(a, b)
+        | Instr::RemInt2addr(a, b)
+        | Instr::AndInt2addr(a, b)
+        | Instr::OrInt2addr(a, b)
+        | Instr::XorInt2addr(a, b)
+        | Instr::ShlInt2addr(a, b)
+        | Instr::ShrInt2addr(a, b)
+        | Instr::UshrInt2addr(a, b)
+        | Instr::AddFloat2addr(a, b)
+        | Instr::SubFloat2addr(a, b)
+        | Instr::MulFloat2addr(a, b)
+        | Instr::DivFloat2addr(a, b)
+        | Instr::RemFloat2addr(a, b) => (vec![*a], vec![*a, *b]),
+
+        Instr::AddLong2addr(a, b)
+        | Instr::SubLong2addr(a, b)
+        | Instr::MulLong2addr(a, b)
+        | Instr::DivLong2addr(a, b)
+        | Instr::RemLong2addr(a, b)
+        | Instr::AndLong2addr(a, b)
+        | Instr::OrLong2addr(a, b)
+        | Instr::XorLong2addr(a, b)
+        | Instr::AddDouble2addr(a, b)
+        | Instr::SubDouble2addr(a, b)
+        | Instr::MulDouble2addr(a, b)
+        | Instr::DivDouble2addr(a, b)
+        | Instr::RemDouble2addr(a, b) => (pair(*a).to_vec(), vec![*a, a.next(), *b, b.next()]),
+
+        Instr::ShlLong2addr(a, b) | Instr::ShrLong2addr(a, b) | Instr::UshrLong2addr(a, b) => {
+            (pair(*a).to_vec(), vec![*a, a.next(), *b])
+        }
+
+        // wide arguments are already given as pairs of registers
+        Instr::FilledNewArray(args, _)
+        | Instr::InvokeVirtual(args, _)
+        | Instr::InvokeSuper(args, _)
+        | Instr::InvokeDirect(args, _)
+        | Instr::InvokeStatic(args, _)
+        | Instr::InvokeInterface(args, _)
+        | Instr::InvokePolymorphic(args, _, _)
+        | Instr::InvokeCustom(args, _) => (vec![], args.iter().collect()),
+
+        Instr::FilledNewArrayRange(args, _)
+        | Instr::InvokeVirtualRange(args, _)
+        | Instr::InvokeSuperRange(args, _)
+        | Instr::InvokeDirectRange(args, _)
+        | Instr::InvokeStaticRange(args, _)
+        | Instr::InvokeInterfaceRange(args, _)
+        | Instr::InvokePolymorphicRange(args, _, _)
+        | Instr::InvokeCustomRange(args, _) => (vec![], args.iter().collect()),
+
+        Instr::Nop
+        | Instr::ReturnVoid
+        | Instr::Goto(_)
+        | Instr::Goto16(_)
+        | Instr::Goto32(_)
+        | Instr::PackedSwitchPayload(_, _)
+        | Instr::SparseSwitchPayload(_, _)
+        | Instr::FillArrayDataPayload(_) => (vec![], vec![]),
+    }
+}
+
 impl<'a> Serialize for WithDex<'a, Instr> {
     fn serialize<S>(&self, serializer: S) -> Result<S::Ok, S::Error>
     where
         S: Serializer,
     {
-        let operands = collect_operands::<S>(&self.data, self.dex)?;
+        let operands = collect_operands(&self.data, self.dex).map_err(ser::Error::custom)?;
 
         let mut state = serializer.serialize_struct("Instr", 2)?;
         state.serialize_field("mnemonic", &self.data.mnemonic())?;
@@ -1585,7 +1984,7 @@ enum OpMethodHandle {
     InvokeInterface,
 }
 
-fn collect_operands<S: Serializer>(instr: &Instr, dex: &Dex) -> Result<Vec<Operand>, S::Error> {
+fn collect_operands(instr: &Instr, dex: &Dex) -> DexResult<Vec<Operand>> {
     match instr {
         Instr::Nop
         | Instr::PackedSwitchPayload(_, _)
@@ -1681,27 +2080,26 @@ fn collect_operands<S: Serializer>(instr: &Instr, dex: &Dex) -> Result<Vec<Opera
             Ok(vec![Operand::Reg(*a), Operand::Const(i64::from(*b) << 48)])
         }
         Instr::ConstString(a, s) | Instr::ConstStringJumbo(a, s) => {
-            Ok(vec![Operand::Reg(*a), operand_string::<S>(*s, dex)?])
+            Ok(vec![Operand::Reg(*a), operand_string(*s, dex)?])
         }
-        Instr::ConstClass(a, t) => Ok(vec![Operand::Reg(*a), operand_type::<S>(*t, dex)?]),
-        Instr::CheckCast(a, t) => Ok(vec![Operand::Reg(*a), operand_type::<S>(*t, dex)?]),
+        Instr::ConstClass(a, t) => Ok(vec![Operand::Reg(*a), operand_type(*t, dex)?]),
+        Instr::CheckCast(a, t) => Ok(vec![Operand::Reg(*a), operand_type(*t, dex)?]),
         Instr::InstanceOf(a, b, t) => Ok(vec![
             Operand::Reg(*a),
             Operand::Reg(*b),
-            operand_type::<S>(*t, dex)?,
+            operand_type(*t, dex)?,
         ]),
-        Instr::NewInstance(a, t) => Ok(vec![Operand::Reg(*a), operand_type::<S>(*t, dex)?]),
+        Instr::NewInstance(a, t) => Ok(vec![Operand::Reg(*a), operand_type(*t, dex)?]),
         Instr::NewArray(a, b, t) => Ok(vec![
             Operand::Reg(*a),
             Operand::Reg(*b),
-            operand_type::<S>(*t, dex)?,
-        ]),
-        Instr::FilledNewArray(args, t) => Ok(vec![
-            Operand::RegList(args.clone()),
-            operand_type::<S>(*t, dex)?,
+            operand_type(*t, dex)?,
         ]),
+        Instr::FilledNewArray(args, t) => {
+            Ok(vec![Operand::RegList(args.clone()), operand_type(*t, dex)?])
+        }
         Instr::FilledNewArrayRange(rr, t) => {
-            Ok(vec![Operand::RegRange(*rr), operand_type::<S>(*t, dex)?])
+            Ok(vec![Operand::RegRange(*rr), operand_type(*t, dex)?])
         }
         Instr::FillArrayData(a, b) => Ok(vec![Operand::Reg(*a), Operand::Addr(*b)]),
         Instr::Goto(a) => Ok(vec![Operand::Addr(i32::from(*a))]),
@@ -1798,7 +2196,7 @@ fn collect_operands<S: Serializer>(instr: &Instr, dex: &Dex) -> Result<Vec<Opera
         | Instr::IputShort(a, b, c) => Ok(vec![
             Operand::Reg(*a),
             Operand::Reg(*b),
-            operand_field::<S>(*c, dex)?,
+            operand_field(*c, dex)?,
         ]),
         Instr::Sget(a, b)
         | Instr::SgetWide(a, b)
@@ -1813,21 +2211,21 @@ fn collect_operands<S: Serializer>(instr: &Instr, dex: &Dex) -> Result<Vec<Opera
         | Instr::SputBoolean(a, b)
         | Instr::SputByte(a, b)
         | Instr::SputChar(a, b)
-        | Instr::SputShort(a, b) => Ok(vec![Operand::Reg(*a), operand_field::<S>(*b, dex)?]),
+        | Instr::SputShort(a, b) => Ok(vec![Operand::Reg(*a), operand_field(*b, dex)?]),
         Instr::InvokeVirtual(args, b)
         | Instr::InvokeSuper(args, b)
         | Instr::InvokeDirect(args, b)
         | Instr::InvokeStatic(args, b)
         | Instr::InvokeInterface(args, b) => Ok(vec![
             Operand::RegList(args.clone()),
-            operand_method::<S>(*b, dex)?,
+            operand_method(*b, dex)?,
         ]),
         Instr::InvokeVirtualRange(rr, c)
         | Instr::InvokeSuperRange(rr, c)
         | Instr::InvokeDirectRange(rr, c)
         | Instr::InvokeStaticRange(rr, c)
         | Instr::InvokeInterfaceRange(rr, c) => {
-            Ok(vec![Operand::RegRange(*rr), operand_method::<S>(*c, dex)?])
+            Ok(vec![Operand::RegRange(*rr), operand_method(*c, dex)?])
         }
 
         Instr::AddIntLit16(a, b, c)
@@ -1861,81 +2259,55 @@ fn collect_operands<S: Serializer>(instr: &Instr, dex: &Dex) -> Result<Vec<Opera
 
         Instr::InvokePolymorphic(args, meth, proto) => Ok(vec![
             Operand::RegList(args.clone()),
-            operand_method::<S>(*meth, dex)?,
-            operand_proto::<S>(*proto, dex)?,
+            operand_method(*meth, dex)?,
+            operand_proto(*proto, dex)?,
         ]),
         Instr::InvokePolymorphicRange(rr, meth, proto) => Ok(vec![
             Operand::RegRange(*rr),
-            operand_method::<S>(*meth, dex)?,
-            operand_proto::<S>(*proto, dex)?,
+            operand_method(*meth, dex)?,
+            operand_proto(*proto, dex)?,
         ]),
 
         Instr::InvokeCustom(args, cs) => Ok(vec![
             Operand::RegList(args.clone()),
-            operand_call_site::<S>(*cs, dex)?,
-        ]),
-        Instr::InvokeCustomRange(rr, cs) => Ok(vec![
-            Operand::RegRange(*rr),
-            operand_call_site::<S>(*cs, dex)?,
+            operand_call_site(*cs, dex)?,
         ]),
+        Instr::InvokeCustomRange(rr, cs) => {
+            Ok(vec![Operand::RegRange(*rr), operand_call_site(*cs, dex)?])
+        }
 
         Instr::ConstMethodHandle(a, h) => {
-            Ok(vec![Operand::Reg(*a), operand_method_handle::<S>(*h, dex)?])
-        }
-        Instr::ConstMethodType(a, proto) => {
-            Ok(vec![Operand::Reg(*a), operand_proto::<S>(*proto, dex)?])
+            Ok(vec![Operand::Reg(*a), operand_method_handle(*h, dex)?])
         }
+        Instr::ConstMethodType(a, proto) => Ok(vec![Operand::Reg(*a), operand_proto(*proto, dex)?]),
     }
 }
 
-fn operand_string<S: Serializer>(s: Index<StringIdItem>, dex: &Dex) -> Result<Operand, S::Error> {
-    let s = s
-        .get(dex)
-        .map_err(|err| ser::Error::custom(format!("{err}")))?
-        .to_string(dex)
-        .map_err(|err| ser::Error::custom(format!("{err}")))?
-        .replace('\n', "\\n");
+fn operand_string(s: Index<StringIdItem>, dex: &Dex) -> DexResult<Operand> {
+    let s = s.get(dex)?.to_string(dex)?.replace('\n', "\\n");
     Ok(Operand::String(s))
 }
 
-fn operand_type<S: Serializer>(t: Index<TypeIdItem>, dex: &Dex) -> Result<Operand, S::Error> {
-    let typ = t
-        .get(dex)
-        .map_err(|err| ser::Error::custom(format!("{err}")))?
-        .to_type(dex)
-        .map_err(|err| ser::Error::custom(format!("{err}")))?;
+fn operand_type(t: Index<TypeIdItem>, dex: &Dex) -> DexResult<Operand> {
+    let typ = t.get(dex)?.to_type(dex)?;
     Ok(Operand::Type(typ))
 }
 
-fn operand_proto<S: Serializer>(p: Index<ProtoIdItem>, dex: &Dex) -> Result<Operand, S::Error> {
-    let proto = p
-        .get(dex)
-        .map_err(|err| ser::Error::custom(format!("{err}")))?;
-    let proto_return_ = proto
-        .return_type(dex)
-        .map_err(|err| ser::Error::custom(format!("{err}")))?;
-    let proto_params = proto
-        .parameters_types(dex)
-        .map_err(|err| ser::Error::custom(format!("{err}")))?;
+fn operand_proto(p: Index<ProtoIdItem>, dex: &Dex) -> DexResult<Operand> {
+    let proto = p.get(dex)?;
+    let proto_return_ = proto.return_type(dex)?;
+    let proto_params = proto.parameters_types(dex)?;
     Ok(Operand::Proto {
         return_: proto_return_,
         params: proto_params,
     })
 }
 
-fn operand_field<S: Serializer>(f: Index<FieldIdItem>, dex: &Dex) -> Result<Operand, S::Error> {
-    let field = f
-        .get(dex)
-        .map_err(|err| ser::Error::custom(format!("{err}")))?;
-    let classname = field
-        .class_name(dex)
-        .map_err(|err| ser::Error::custom(format!("{err}")))?;
-    let name = field
-        .name(dex)
-        .map_err(|err| ser::Error::custom(format!("{err}")))?;
-    let type_ = field
-        .type_(dex)
-        .map_err(|err| ser::Error::custom(format!("{err}")))?;
+fn operand_field(f: Index<FieldIdItem>, dex: &Dex) -> DexResult<Operand> {
+    let field = f.get(dex)?;
+    let classname = field.class_name(dex)?;
+    let name = field.name(dex)?;
+    let type_ = field.type_(dex)?;
     Ok(Operand::Field {
         classname,
         name,
@@ -1943,22 +2315,12 @@ fn operand_field<S: Serializer>(f: Index<FieldIdItem>, dex: &Dex) -> Result<Oper
     })
 }
 
-fn operand_method<S: Serializer>(m: Index<MethodIdItem>, dex: &Dex) -> Result<Operand, S::Error> {
-    let method = m
-        .get(dex)
-        .map_err(|err| ser::Error::custom(format!("{err}")))?;
-    let definer = method
-        .definer(dex)
-        .map_err(|err| ser::Error::custom(format!("{err}")))?;
-    let name = method
-        .name(dex)
-        .map_err(|err| ser::Error::custom(format!("{err}")))?;
-    let return_ = method
-        .return_type(dex)
-        .map_err(|err| ser::Error::custom(format!("{err}")))?;
-    let params = method
-        .parameters_types(dex)
-        .map_err(|err| ser::Error::custom(format!("{err}")))?;
+fn operand_method(m: Index<MethodIdItem>, dex: &Dex) -> DexResult<Operand> {
+    let method = m.get(dex)?;
+    let definer = method.definer(dex)?;
+    let name = method.name(dex)?;
+    let return_ = method.return_type(dex)?;
+    let params = method.parameters_types(dex)?;
     Ok(Operand::Method {
         definer,
         name,
@@ -1967,29 +2329,19 @@ fn operand_method<S: Serializer>(m: Index<MethodIdItem>, dex: &Dex) -> Result<Op
     })
 }
 
-fn operand_call_site<S: Serializer>(
-    cs: Index<CallSiteIdItem>,
-    dex: &Dex,
-) -> Result<Operand, S::Error> {
+fn operand_call_site(cs: Index<CallSiteIdItem>, dex: &Dex) -> DexResult<Operand> {
     let cs_args = cs
-        .get(dex)
-        .map_err(|err| ser::Error::custom(format!("{err}")))?
-        .arguments(dex)
-        .map_err(|err| ser::Error::custom(format!("{err}")))?
+        .get(dex)?
+        .arguments(dex)?
         .values
         .iter()
-        .map(|val| op_value::<S>(val, dex))
-        .collect::<Result<Vec<OpValue>, S::Error>>()?;
+        .map(|val| op_value(val, dex))
+        .collect::<DexResult<Vec<OpValue>>>()?;
     Ok(Operand::CallSite(cs_args))
 }
 
-fn operand_method_handle<S: Serializer>(
-    h: Index<MethodHandleItem>,
-    dex: &Dex,
-) -> Result<Operand, S::Error> {
-    let handle = h
-        .get(dex)
-        .map_err(|err| ser::Error::custom(format!("{err}")))?;
+fn operand_method_handle(h: Index<MethodHandleItem>, dex: &Dex) -> DexResult<Operand> {
+    let handle = h.get(dex)?;
     let handle_kind = match handle.method_handle {
         MethodHandle::StaticPut(_) => OpMethodHandle::StaticPut,
         MethodHandle::StaticGet(_) => OpMethodHandle::StaticGet,
@@ -2006,18 +2358,10 @@ fn operand_method_handle<S: Serializer>(
         | MethodHandle::StaticGet(field)
         | MethodHandle::InstancePut(field)
         | MethodHandle::InstanceGet(field) => {
-            let field = field
-                .get(dex)
-                .map_err(|err| ser::Error::custom(format!("{err}")))?;
-            let classname = field
-                .class_name(dex)
-                .map_err(|err| ser::Error::custom(format!("{err}")))?;
-            let name = field
-                .name(dex)
-                .map_err(|err| ser::Error::custom(format!("{err}")))?;
-            let type_ = field
-                .type_(dex)
-                .map_err(|err| ser::Error::custom(format!("{err}")))?;
+            let field = field.get(dex)?;
+            let classname = field.class_name(dex)?;
+            let name = field.name(dex)?;
+            let type_ = field.type_(dex)?;
             Operand::Field {
                 classname,
                 name,
@@ -2029,21 +2373,11 @@ fn operand_method_handle<S: Serializer>(
         | MethodHandle::InvokeConstructor(meth)
         | MethodHandle::InvokeDirect(meth)
         | MethodHandle::InvokeInterface(meth) => {
-            let meth = meth
-                .get(dex)
-                .map_err(|err| ser::Error::custom(format!("{err}")))?;
-            let definer = meth
-                .definer(dex)
-                .map_err(|err| ser::Error::custom(format!("{err}")))?;
-            let name = meth
-                .name(dex)
-                .map_err(|err| ser::Error::custom(format!("{err}")))?;
-            let return_ = meth
-                .return_type(dex)
-                .map_err(|err| ser::Error::custom(format!("{err}")))?;
-            let params = meth
-                .parameters_types(dex)
-                .map_err(|err| ser::Error::custom(format!("{err}")))?;
+            let meth = meth.get(dex)?;
+            let definer = meth.definer(dex)?;
+            let name = meth.name(dex)?;
+            let return_ = meth.return_type(dex)?;
+            let params = meth.parameters_types(dex)?;
             Operand::Method {
                 definer,
                 name,
@@ -2058,7 +2392,7 @@ fn operand_method_handle<S: Serializer>(
     })
 }
 
-fn op_value<S: Serializer>(value: &EncodedValue, dex: &Dex) -> Result<OpValue, S::Error> {
+fn op_value(value: &EncodedValue, dex: &Dex) -> DexResult<OpValue> {
     match value {
         EncodedValue::Byte(v) => Ok(OpValue::Byte(*v)),
         EncodedValue::Short(_, v) => Ok(OpValue::Short(*v)),
@@ -2068,33 +2402,45 @@ fn op_value<S: Serializer>(value: &EncodedValue, dex: &Dex) -> Result<OpValue, S
         EncodedValue::Float(_, v) => Ok(OpValue::Float(*v)),
         EncodedValue::Double(_, v) => Ok(OpValue::Double(*v)),
         EncodedValue::MethodType(_, proto) => {
-            let Operand::Proto { return_, params } = operand_proto::<S>(*proto, dex)? else { unreachable!() };
+            let Operand::Proto { return_, params } = operand_proto(*proto, dex)? else {
+                unreachable!()
+            };
             Ok(OpValue::MethodType { return_, params })
         }
         EncodedValue::MethodHandle(_, handle) => {
             let Operand::MethodHandle {
-                    handle_kind,
-                    handle_arg,
-                } = operand_method_handle::<S>(*handle, dex)? else { unreachable!() };
+                handle_kind,
+                handle_arg,
+            } = operand_method_handle(*handle, dex)?
+            else {
+                unreachable!()
+            };
             Ok(OpValue::MethodHandle {
                 handle_kind,
                 handle_arg,
             })
         }
         EncodedValue::String(_, s) => {
-            let Operand::String(string) = operand_string::<S>(*s, dex)? else { unreachable!() };
+            let Operand::String(string) = operand_string(*s, dex)? else {
+                unreachable!()
+            };
             Ok(OpValue::String(string))
         }
         EncodedValue::Type(_, typ) => {
-            let Operand::Type(t) = operand_type::<S>(*typ, dex)? else { unreachable!() };
+            let Operand::Type(t) = operand_type(*typ, dex)? else {
+                unreachable!()
+            };
             Ok(OpValue::Type(t))
         }
         EncodedValue::Field(_, field) => {
             let Operand::Field {
-                    classname,
-                    name,
-                    type_,
-                } = operand_field::<S>(*field, dex)? else { unreachable!() };
+                classname,
+                name,
+                type_,
+            } = operand_field(*field, dex)?
+            else {
+                unreachable!()
+            };
             Ok(OpValue::Field {
                 classname,
                 name,
@@ -2103,11 +2449,14 @@ fn op_value<S: Serializer>(value: &EncodedValue, dex: &Dex) -> Result<OpValue, S
         }
         EncodedValue::Method(_, method) => {
             let Operand::Method {
-                    definer,
-                    name,
-                    return_,
-                    params,
-                } = operand_method::<S>(*method, dex)? else { unreachable!() };
+                definer,
+                name,
+                return_,
+                params,
+            } = operand_method(*method, dex)?
+            else {
+                unreachable!()
+            };
             Ok(OpValue::Method {
                 definer,
                 name,
@@ -2117,10 +2466,13 @@ fn op_value<S: Serializer>(value: &EncodedValue, dex: &Dex) -> Result<OpValue, S
         }
         EncodedValue::Enum(_, field) => {
             let Operand::Field {
-                    classname,
-                    name,
-                    type_,
-                } = operand_field::<S>(*field, dex)? else { unreachable!() };
+                classname,
+                name,
+                type_,
+            } = operand_field(*field, dex)?
+            else {
+                unreachable!()
+            };
             Ok(OpValue::Enum {
                 classname,
                 name,
@@ -2131,20 +2483,78 @@ fn op_value<S: Serializer>(value: &EncodedValue, dex: &Dex) -> Result<OpValue, S
             let vals = arr
                 .values
                 .iter()
-                .map(|val| op_value::<S>(val, dex))
-                .collect::<Result<Vec<OpValue>, S::Error>>()?;
+                .map(|val| op_value(val, dex))
+                .collect::<DexResult<Vec<OpValue>>>()?;
             Ok(OpValue::Array(vals))
         }
         EncodedValue::Annotation(ann) => {
-            let Operand::Type(type_) = operand_type::<S>(ann.type_idx, dex)? else { unreachable!() };
-            let elements = ann.elements.iter().map(|elt| {
-                let Operand::String(s) = operand_string::<S>(elt.name_idx, dex)? else { unreachable!() };
-                let v = op_value::<S>(&elt.value, dex)?;
-                Ok((s, v))
-            }).collect::<Result<Vec<(String, OpValue)>, S::Error>>()?;
+            let Operand::Type(type_) = operand_type(ann.type_idx, dex)? else {
+                unreachable!()
+            };
+            let elements = ann
+                .elements
+                .iter()
+                .map(|elt| {
+                    let Operand::String(s) = operand_string(elt.name_idx, dex)? else {
+                        unreachable!()
+                    };
+                    let v = op_value(&elt.value, dex)?;
+                    Ok((s, v))
+                })
+                .collect::<DexResult<Vec<(String, OpValue)>>>()?;
             Ok(OpValue::Annotation { type_, elements })
         }
         EncodedValue::Null => Ok(OpValue::Null),
         EncodedValue::Boolean(b) => Ok(OpValue::Boolean(*b)),
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn values(regs: Vec<Reg>) -> Vec<u16> {
+        regs.into_iter().map(Reg::value).collect()
+    }
+
+    #[test]
+    fn add_int_registers() {
+        let instr = Instr::AddInt(Reg::from(0u8), Reg::from(1u8), Reg::from(2u8));
+        assert_eq!(values(instr.dst_registers()), [0]);
+        assert_eq!(values(instr.src_registers()), [1, 2]);
+        assert!(instr.referenced_index().is_none());
+    }
+
+    #[test]
+    fn iget_object_registers() {
+        let instr = Instr::IgetObject(Reg::from(3u8), Reg::from(4u8), Index::new(7));
+        assert_eq!(values(instr.dst_registers()), [3]);
+        assert_eq!(values(instr.src_registers()), [4]);
+        assert!(matches!(
+            instr.referenced_index(),
+            Some(AnyIndex::Field(f)) if f.as_usize() == 7
+        ));
+    }
+
+    #[test]
+    fn invoke_virtual_registers() {
+        let instr = Instr::InvokeVirtual(RegList::from(vec![5u8, 1, 2]), Index::new(2));
+        assert!(instr.dst_registers().is_empty());
+        assert_eq!(values(instr.src_registers()), [5, 1, 2]);
+        assert!(matches!(
+            instr.referenced_index(),
+            Some(AnyIndex::Method(m)) if m.as_usize() == 2
+        ));
+    }
+
+    #[test]
+    fn wide_registers_pairs() {
+        let instr = Instr::AddLong(Reg::from(0u8), Reg::from(2u8), Reg::from(4u8));
+        assert_eq!(values(instr.dst_registers()), [0, 1]);
+        assert_eq!(values(instr.src_registers()), [2, 3, 4, 5]);
+
+        let instr = Instr::IputWide(Reg::from(6u8), Reg::from(0u8), Index::new(1));
+        assert!(instr.dst_registers().is_empty());
+        assert_eq!(values(instr.src_registers()), [6, 7, 0]);
+    }
+}
@@ -1,33 +1,39 @@
 //! Android Dex data structures definitions.
 
 mod addr;
-mod annotations;
 mod hexlify;
 mod map;
-mod mutf8;
+mod merge;
 mod parsers;
 mod strings;
-mod values;
 mod writers;
 
+pub mod annotations;
 pub mod classes;
 pub mod code;
 pub mod errors;
 pub mod fields;
 pub mod instrs;
 pub mod methods;
+pub mod mutf8;
 pub mod registers;
+pub mod smali;
+#[cfg(any(test, feature = "testing"))]
+pub mod testing;
 pub mod types;
+pub mod values;
 
 pub use crate::addr::Addr;
+pub use crate::merge::merge_dex as merge;
 pub use crate::parsers::parse_dex as parse;
-pub use crate::writers::write_dex as write;
+pub use crate::writers::{write_dex as write, WriteOptions};
 
 use crate::annotations::*;
 use crate::classes::*;
 use crate::code::*;
-use crate::errors::DexResult;
+use crate::errors::{DexError, DexResult};
 use crate::fields::*;
+use crate::instrs::Instr;
 use crate::map::MapList;
 use crate::methods::*;
 use crate::strings::*;
@@ -68,6 +74,7 @@ pub(crate) struct HeaderItem {
     pub(crate) class_defs_off: usize,
     pub(crate) data_size: usize,
     pub(crate) data_off: usize,
+    pub(crate) compact: bool,
 }
 
 impl HeaderItem {
@@ -95,6 +102,7 @@ impl HeaderItem {
             class_defs_off: 0,
             data_size: 0,
             data_off: 0,
+            compact: false,
         }
     }
 
@@ -213,6 +221,24 @@ pub struct Dex {
     pub(crate) hiddenapi_class_data_items: Map<HiddenapiClassDataItem>,
 }
 
+/// Number of ids of each kind held by a dex. Method, field and type ids
+/// are referenced by 16-bit indexes in instructions, which limits a dex to
+/// 65536 of each.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub struct ReferenceCounts {
+    pub strings: usize,
+    pub types: usize,
+    pub fields: usize,
+    pub methods: usize,
+}
+
+/// Indexing is meant for trusted indices, such as the ones of items that
+/// have been read from the dex itself.
+///
+/// # Panics
+///
+/// Panics if the index does not reference an item of the dex. Use
+/// [`Dex::try_get`] for indices that may be out of bounds.
 impl<Idx: DexIndex> ops::Index<Idx> for Dex {
     type Output = Idx::T;
 
@@ -248,12 +274,122 @@ impl Dex {
         }
     }
 
+    /// Returns the item referenced by an index, the fallible counterpart
+    /// of indexing the dex.
+    ///
+    /// # Errors
+    ///
+    /// Returns an error if the index does not reference an item of the dex.
+    #[inline]
+    pub fn try_get<Idx: DexIndex>(&self, idx: Idx) -> DexResult<&Idx::T> {
+        idx.get(self)
+    }
+
     #[inline]
     #[must_use]
     pub const fn version(&self) -> u32 {
         self.header_item.version
     }
 
+    /// Tells whether this dex has been parsed from a compact dex (`cdex`)
+    /// file, in which case it cannot be written back.
+    #[inline]
+    #[must_use]
+    pub const fn is_compact(&self) -> bool {
+        self.header_item.compact
+    }
+
+    /// Sets the version that will be written in the dex magic.
+    ///
+    /// # Errors
+    ///
+    /// Returns [`DexError::Version`] if the version is not one of 035, 037,
+    /// 038 or 039, or if the dex uses features that are not available in
+    /// this version.
+    pub fn set_version(&mut self, version: u32) -> DexResult<()> {
+        if !matches!(version, 35 | 37 | 38 | 39) {
+            return Err(DexError::Version {
+                version,
+                reason: "unknown version".to_string(),
+            });
+        }
+        if let Some((required, feature)) = self.required_version()? {
+            if version < required {
+                return Err(DexError::Version {
+                    version,
+                    reason: format!("{feature} requires version {required:03}"),
+                });
+            }
+        }
+        self.header_item.version = version;
+        Ok(())
+    }
+
+    /// Updates the file size, checksum and signature of the header to the
+    /// ones of the dex as it is written by [`write`], e.g. after it has
+    /// been built or modified programmatically.
+    ///
+    /// # Errors
+    ///
+    /// Returns an error if the dex cannot be written.
+    pub fn recompute_header(&mut self) -> DexResult<()> {
+        let buffer = write(self, &WriteOptions::default())?;
+        self.header_item.file_size = buffer.len();
+        self.header_item.checksum =
+            u32::from_le_bytes([buffer[8], buffer[9], buffer[10], buffer[11]]);
+        self.header_item.signature = buffer[12..32].to_vec();
+        Ok(())
+    }
+
+    /// Returns the lowest version able to hold the features used in this dex,
+    /// along with the feature that requires it (if any is newer than 035).
+    fn required_version(&self) -> DexResult<Option<(u32, &'static str)>> {
+        if !self.hiddenapi_class_data_items.is_empty() {
+            return Ok(Some((39, "hidden API flags")));
+        }
+        let mut required = None;
+        for code in self.code_items.values() {
+            let code = code.read().unwrap();
+            for instr in code.iter_instructions() {
+                match instr.instr() {
+                    Instr::ConstMethodHandle(..) | Instr::ConstMethodType(..) => {
+                        return Ok(Some((39, "const-method-handle/type instructions")))
+                    }
+                    Instr::InvokePolymorphic(..)
+                    | Instr::InvokePolymorphicRange(..)
+                    | Instr::InvokeCustom(..)
+                    | Instr::InvokeCustomRange(..) => {
+                        required = Some((38, "invoke-polymorphic/custom instructions"));
+                    }
+                    _ => (),
+                }
+            }
+        }
+        if required.is_some() {
+            return Ok(required);
+        }
+        if !self.method_handle_items.is_empty() {
+            return Ok(Some((38, "method handles")));
+        }
+        if !self.call_site_id_items.is_empty() {
+            return Ok(Some((38, "call sites")));
+        }
+        for class in &self.class_def_items {
+            if !class.access_flags.contains(ClassFlags::ACC_INTERFACE) {
+                continue;
+            }
+            if let Some(data) = class.data(self)? {
+                if data
+                    .iter_virtual_methods()
+                    .any(|m| !m.flags().contains(MethodFlags::ACC_ABSTRACT))
+                {
+                    return Ok(Some((37, "default interface methods")));
+                }
+            }
+        }
+        Ok(None)
+    }
+
     #[inline]
     pub fn iter_string_ids(&self) -> impl Iterator<Item = &StringIdItem> {
         self.string_id_items.iter()
@@ -293,6 +429,16 @@ impl Dex {
     pub fn iter_method_handles(&self) -> impl Iterator<Item = &MethodHandleItem> {
         self.method_handle_items.iter()
     }
+
+    #[must_use]
+    pub fn reference_counts(&self) -> ReferenceCounts {
+        ReferenceCounts {
+            strings: self.string_id_items.len(),
+            types: self.type_id_items.len(),
+            fields: self.field_id_items.len(),
+            methods: self.method_id_items.len(),
+        }
+    }
 }
 
 /// Open and parses the given dex file path.
@@ -313,3 +459,35 @@ impl<'a, T> WithDex<'a, T> {
         WithDex { dex, data }
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn set_version_downgrade() {
+        let mut dex = Dex::new(39);
+        dex.set_version(35).unwrap();
+        assert_eq!(dex.version(), 35);
+    }
+
+    #[test]
+    fn set_version_invalid() {
+        let mut dex = Dex::new(38);
+        assert!(matches!(
+            dex.set_version(36),
+            Err(DexError::Version { version: 36, .. })
+        ));
+
+        dex.method_handle_items.push(MethodHandleItem {
+            index: Index::new(0),
+            method_handle: MethodHandle::InvokeStatic(Index::new(0)),
+        });
+        assert!(matches!(
+            dex.set_version(35),
+            Err(DexError::Version { version: 35, .. })
+        ));
+        assert_eq!(dex.version(), 38);
+        dex.set_version(39).unwrap();
+    }
+}
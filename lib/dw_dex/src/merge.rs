@@ -0,0 +1,1621 @@
+use crate::annotations::*;
+use crate::classes::*;
+use crate::code::*;
+use crate::errors::{DexError, DexResult};
+use crate::fields::*;
+use crate::instrs::{Instr, LabeledInstr};
+use crate::map::{MapItem, MapItemType, MapList};
+use crate::methods::*;
+use crate::mutf8;
+use crate::strings::*;
+use crate::types::*;
+use crate::values::*;
+use crate::writers::{write_items, Relocations, WriteOptions};
+use crate::{Dex, DexIndex, Index, Map};
+use dw_utils::leb::Uleb128;
+use std::cmp::Ordering;
+use std::collections::{BTreeMap, BTreeSet};
+use std::convert::TryFrom;
+use std::mem;
+use std::sync::RwLock;
+
+/// Maximum number of layout passes, see [`layout`].
+const MAX_LAYOUT_PASSES: usize = 8;
+
+/// Translation of the indexes and offsets of one of the merged dex files
+/// into the merged one.
+#[derive(Debug, Default)]
+struct Remap {
+    // added to data items offsets, so that items of different dex files
+    // do not collide until they are given their final offsets.
+    base: usize,
+    strings: Vec<usize>,
+    types: Vec<usize>,
+    protos: Vec<usize>,
+    fields: Vec<usize>,
+    methods: Vec<usize>,
+    call_sites: usize,
+    method_handles: usize,
+}
+
+fn lookup<T>(table: &[usize], idx: Index<T>, name: &str) -> DexResult<Index<T>> {
+    table
+        .get(idx.as_usize())
+        .map(|new| Index::new(*new))
+        .ok_or_else(|| DexError::ResNotFound(name.to_string()))
+}
+
+impl Remap {
+    fn string(&self, idx: Index<StringIdItem>) -> DexResult<Index<StringIdItem>> {
+        lookup(&self.strings, idx, "StringIdItem")
+    }
+
+    fn type_(&self, idx: Index<TypeIdItem>) -> DexResult<Index<TypeIdItem>> {
+        lookup(&self.types, idx, "TypeIdItem")
+    }
+
+    fn proto(&self, idx: Index<ProtoIdItem>) -> DexResult<Index<ProtoIdItem>> {
+        lookup(&self.protos, idx, "ProtoIdItem")
+    }
+
+    fn field(&self, idx: Index<FieldIdItem>) -> DexResult<Index<FieldIdItem>> {
+        lookup(&self.fields, idx, "FieldIdItem")
+    }
+
+    fn method(&self, idx: Index<MethodIdItem>) -> DexResult<Index<MethodIdItem>> {
+        lookup(&self.methods, idx, "MethodIdItem")
+    }
+
+    fn call_site(&self, idx: Index<CallSiteIdItem>) -> Index<CallSiteIdItem> {
+        Index::new(self.call_sites + idx.as_usize())
+    }
+
+    fn method_handle(&self, idx: Index<MethodHandleItem>) -> Index<MethodHandleItem> {
+        Index::new(self.method_handles + idx.as_usize())
+    }
+
+    fn offset<T>(&self, off: Index<T>) -> Index<T> {
+        // 0 stands for a missing item in some places (no data item can
+        // be stored in place of the header).
+        match off.as_usize() {
+            0 => off,
+            off => Index::new(self.base + off),
+        }
+    }
+}
+
+/// Dex merging function. Borrows several [`Dex`] structures and returns a
+/// single one holding all their classes.
+///
+/// Strings, types, prototypes, fields and methods referenced by several
+/// dex files are deduplicated and sorted as required by the dex format,
+/// and every index referencing them (in instructions, debug information,
+/// annotations and encoded values) is rewritten. Data items are given
+/// fresh offsets, along with the map list and the header. Hidden API flags
+/// are only kept when a single dex file defines classes.
+///
+/// # Errors
+///
+/// Returns an error if a class is defined in several dex files, or if the
+/// merged dex exceeds the limits of the format (more than 65536 types,
+/// prototypes, fields or methods, or a `const-string` instruction
+/// referencing a string index that does not fit on 16 bits).
+pub fn merge_dex(dexs: &[Dex]) -> DexResult<Dex> {
+    let (merged, _) = merge_remaps(dexs)?;
+    Ok(merged)
+}
+
+/// Merges `dexs`, see [`merge_dex`], and returns the translation of the
+/// indexes of each of them in the merged dex.
+fn merge_remaps(dexs: &[Dex]) -> DexResult<(Dex, Vec<Remap>)> {
+    log::trace!("merging {} dex files...", dexs.len());
+
+    let version = dexs.iter().map(Dex::version).max().unwrap_or(35);
+    let mut merged = Dex::new(version);
+
+    let mut remaps: Vec<Remap> = Vec::with_capacity(dexs.len());
+    let mut base = 0;
+    let mut call_sites = 0;
+    let mut method_handles = 0;
+    for dex in dexs {
+        remaps.push(Remap {
+            base,
+            call_sites,
+            method_handles,
+            ..Remap::default()
+        });
+        base += extent(dex);
+        call_sites += dex.call_site_id_items.len();
+        method_handles += dex.method_handle_items.len();
+    }
+
+    // strings are sorted by UTF-16 code units
+    let (strings, tables) = intern(
+        dexs,
+        |dex| &dex.string_id_items,
+        |i, id| mutf8::decode_utf16(&id.string_data_off.get(&dexs[i])?.data),
+    )?;
+    for (new, (i, id)) in strings.values().enumerate() {
+        let data = id.string_data_off.get(&dexs[*i])?;
+        let data_off = remaps[*i].offset(id.string_data_off);
+        let _ = merged.string_data_items.insert(
+            data_off.as_usize(),
+            StringDataItem {
+                index: data_off,
+                utf16_size: data.utf16_size,
+                data: data.data.clone(),
+            },
+        );
+        merged.string_id_items.push(StringIdItem {
+            index: Index::new(new),
+            string_data_off: data_off,
+        });
+    }
+    for (remap, table) in remaps.iter_mut().zip(tables) {
+        remap.strings = table;
+    }
+
+    // types are sorted by descriptor index
+    let (types, tables) = intern(
+        dexs,
+        |dex| &dex.type_id_items,
+        |i, id| Ok(remaps[i].string(id.descriptor_idx)?.as_usize()),
+    )?;
+    check_limit(types.len(), "types")?;
+    for (new, descriptor) in types.keys().enumerate() {
+        merged.type_id_items.push(TypeIdItem {
+            index: Index::new(new),
+            descriptor_idx: Index::new(*descriptor),
+        });
+    }
+    for (remap, table) in remaps.iter_mut().zip(tables) {
+        remap.types = table;
+    }
+
+    // prototypes are sorted by return type, then by parameters types
+    let (protos, tables) = intern(
+        dexs,
+        |dex| &dex.proto_id_items,
+        |i, id| {
+            let parameters = match id.parameters_off {
+                Some(off) => off
+                    .get(&dexs[i])?
+                    .list
+                    .iter()
+                    .map(|item| Ok(remaps[i].type_(item.type_idx)?.as_usize()))
+                    .collect::<DexResult<Vec<_>>>()?,
+                None => Vec::new(),
+            };
+            Ok((remaps[i].type_(id.return_type_idx)?.as_usize(), parameters))
+        },
+    )?;
+    check_limit(protos.len(), "prototypes")?;
+    for (new, ((return_type, _), (i, id))) in protos.iter().enumerate() {
+        let remap = &remaps[*i];
+        if let Some(off) = id.parameters_off {
+            let list = remap_type_list(remap, off.get(&dexs[*i])?)?;
+            let _ = merged.type_lists.insert(list.index.as_usize(), list);
+        }
+        merged.proto_id_items.push(ProtoIdItem {
+            index: Index::new(new),
+            shorty_idx: remap.string(id.shorty_idx)?,
+            return_type_idx: Index::new(*return_type),
+            parameters_off: id.parameters_off.map(|off| remap.offset(off)),
+        });
+    }
+    for (remap, table) in remaps.iter_mut().zip(tables) {
+        remap.protos = table;
+    }
+
+    // fields are sorted by definer, then by name, then by type
+    let (fields, tables) = intern(
+        dexs,
+        |dex| &dex.field_id_items,
+        |i, id| {
+            let remap = &remaps[i];
+            Ok((
+                remap.type_(id.class_idx)?.as_usize(),
+                remap.string(id.name_idx)?.as_usize(),
+                remap.type_(id.type_idx)?.as_usize(),
+            ))
+        },
+    )?;
+    check_limit(fields.len(), "fields")?;
+    for (new, (class, name, typ)) in fields.keys().enumerate() {
+        merged.field_id_items.push(FieldIdItem {
+            index: Index::new(new),
+            class_idx: Index::new(*class),
+            type_idx: Index::new(*typ),
+            name_idx: Index::new(*name),
+        });
+    }
+    for (remap, table) in remaps.iter_mut().zip(tables) {
+        remap.fields = table;
+    }
+
+    // methods are sorted by definer, then by name, then by prototype
+    let (methods, tables) = intern(
+        dexs,
+        |dex| &dex.method_id_items,
+        |i, id| {
+            let remap = &remaps[i];
+            Ok((
+                remap.type_(id.class_idx)?.as_usize(),
+                remap.string(id.name_idx)?.as_usize(),
+                remap.proto(id.proto_idx)?.as_usize(),
+            ))
+        },
+    )?;
+    check_limit(methods.len(), "methods")?;
+    for (new, (class, name, proto)) in methods.keys().enumerate() {
+        merged.method_id_items.push(MethodIdItem {
+            index: Index::new(new),
+            class_idx: Index::new(*class),
+            proto_idx: Index::new(*proto),
+            name_idx: Index::new(*name),
+        });
+    }
+    for (remap, table) in remaps.iter_mut().zip(tables) {
+        remap.methods = table;
+    }
+
+    for (dex, remap) in dexs.iter().zip(&remaps) {
+        for id in &dex.call_site_id_items {
+            merged.call_site_id_items.push(CallSiteIdItem {
+                index: Index::new(merged.call_site_id_items.len()),
+                call_site_off: remap.offset(id.call_site_off),
+            });
+        }
+        for item in &dex.method_handle_items {
+            merged.method_handle_items.push(MethodHandleItem {
+                index: Index::new(merged.method_handle_items.len()),
+                method_handle: remap_method_handle(remap, &item.method_handle)?,
+            });
+        }
+    }
+
+    merge_class_defs(dexs, &remaps, &mut merged)?;
+
+    // hidden API flags are stored by class definition and member order,
+    // which are kept as is for a single dex file.
+    let keep_hiddenapi = dexs
+        .iter()
+        .filter(|dex| !dex.class_def_items.is_empty())
+        .count()
+        <= 1;
+    for (dex, remap) in dexs.iter().zip(&remaps) {
+        merge_data_items(dex, remap, keep_hiddenapi, &mut merged)?;
+    }
+
+    // the map list is written after all the data items
+    layout(&mut merged, base)?;
+
+    Ok((merged, remaps))
+}
+
+impl Dex {
+    /// Returns the index of the given string, which is added to the dex if
+    /// needed.
+    ///
+    /// Adding an item to a table shifts the following ones, so that the
+    /// whole dex is rebuilt as by [`merge_dex`]: indexes and offsets
+    /// obtained beforehand are not valid anymore.
+    ///
+    /// # Errors
+    ///
+    /// Returns an error if the dex would exceed the limits of the format.
+    pub fn intern_string(&mut self, s: &str) -> DexResult<Index<StringIdItem>> {
+        if let Some(string) = self.find_string(s)? {
+            return Ok(string);
+        }
+        let mut patch = Self::new(self.version());
+        let string = push_string(&mut patch, s);
+        self.merge_patch(patch)?.string(string)
+    }
+
+    /// Returns the index of the type of the given descriptor (such as
+    /// `Ljava/lang/String;`), which is added to the dex if needed. See
+    /// [`Dex::intern_string`] for the consequences of adding an item.
+    ///
+    /// # Errors
+    ///
+    /// Returns an error if the descriptor is invalid, or if the dex would
+    /// exceed the limits of the format.
+    pub fn intern_type(&mut self, descriptor: &str) -> DexResult<Index<TypeIdItem>> {
+        let _ = Type::try_from(descriptor)?;
+        if let Some(typ) = self.find_type(descriptor)? {
+            return Ok(typ);
+        }
+        let mut patch = Self::new(self.version());
+        let typ = push_type(&mut patch, descriptor);
+        self.merge_patch(patch)?.type_(typ)
+    }
+
+    /// Returns the index of the method `name` of prototype `proto` defined
+    /// by the type of descriptor `class`, which is added to the dex (along
+    /// with the strings, types and prototype it references) if needed. See
+    /// [`Dex::intern_string`] for the consequences of adding an item.
+    ///
+    /// # Errors
+    ///
+    /// Returns an error if the class descriptor is invalid, or if the dex
+    /// would exceed the limits of the format.
+    pub fn intern_method(
+        &mut self,
+        class: &str,
+        name: &str,
+        proto: &ProtoDescr,
+    ) -> DexResult<Index<MethodIdItem>> {
+        let _ = Type::try_from(class)?;
+        if let Some(method) = self.find_method(class, name, proto)? {
+            return Ok(method);
+        }
+        let mut patch = Self::new(self.version());
+        let class_idx = push_type(&mut patch, class);
+        let name_idx = push_string(&mut patch, name);
+        let shorty_idx = push_string(&mut patch, &proto.shorty());
+        let return_type_idx = push_type(&mut patch, &proto.return_type.to_string());
+        let parameters_off = if proto.parameters.is_empty() {
+            None
+        } else {
+            let list = proto
+                .parameters
+                .iter()
+                .map(|typ| TypeItem {
+                    type_idx: push_type(&mut patch, &typ.to_string()),
+                })
+                .collect();
+            let index = Index::new(extent(&patch));
+            let _ = patch
+                .type_lists
+                .insert(index.as_usize(), TypeList { index, list });
+            Some(index)
+        };
+        patch.proto_id_items.push(ProtoIdItem {
+            index: Index::new(0),
+            shorty_idx,
+            return_type_idx,
+            parameters_off,
+        });
+        patch.method_id_items.push(MethodIdItem {
+            index: Index::new(0),
+            class_idx,
+            proto_idx: Index::new(0),
+            name_idx,
+        });
+        self.merge_patch(patch)?.method(Index::new(0))
+    }
+
+    // Lookups of existing items, relying on the tables being sorted as
+    // the dex format requires. An item missed in an unsorted table is
+    // deduplicated when merged anyway.
+
+    fn find_string(&self, s: &str) -> DexResult<Option<Index<StringIdItem>>> {
+        let utf16: Vec<u16> = s.encode_utf16().collect();
+        let found = search(&self.string_id_items, &utf16, |id| {
+            mutf8::decode_utf16(&id.string_data_off.get(self)?.data)
+        })?;
+        Ok(found.map(Index::new))
+    }
+
+    fn find_type(&self, descriptor: &str) -> DexResult<Option<Index<TypeIdItem>>> {
+        let Some(descriptor) = self.find_string(descriptor)? else {
+            return Ok(None);
+        };
+        let found = search(&self.type_id_items, &descriptor.as_usize(), |id| {
+            Ok(id.descriptor_idx.as_usize())
+        })?;
+        Ok(found.map(Index::new))
+    }
+
+    fn find_proto(&self, proto: &ProtoDescr) -> DexResult<Option<Index<ProtoIdItem>>> {
+        let Some(return_type) = self.find_type(&proto.return_type.to_string())? else {
+            return Ok(None);
+        };
+        let mut parameters = Vec::with_capacity(proto.parameters.len());
+        for typ in &proto.parameters {
+            let Some(typ) = self.find_type(&typ.to_string())? else {
+                return Ok(None);
+            };
+            parameters.push(typ.as_usize());
+        }
+        let found = search(
+            &self.proto_id_items,
+            &(return_type.as_usize(), parameters),
+            |id| {
+                let parameters = match id.parameters_off {
+                    Some(off) => off
+                        .get(self)?
+                        .list
+                        .iter()
+                        .map(|item| item.type_idx.as_usize())
+                        .collect(),
+                    None => Vec::new(),
+                };
+                Ok((id.return_type_idx.as_usize(), parameters))
+            },
+        )?;
+        Ok(found.map(Index::new))
+    }
+
+    fn find_method(
+        &self,
+        class: &str,
+        name: &str,
+        proto: &ProtoDescr,
+    ) -> DexResult<Option<Index<MethodIdItem>>> {
+        let (Some(class), Some(name), Some(proto)) = (
+            self.find_type(class)?,
+            self.find_string(name)?,
+            self.find_proto(proto)?,
+        ) else {
+            return Ok(None);
+        };
+        let key = (class.as_usize(), name.as_usize(), proto.as_usize());
+        let found = search(&self.method_id_items, &key, |id| {
+            Ok((
+                id.class_idx.as_usize(),
+                id.name_idx.as_usize(),
+                id.proto_idx.as_usize(),
+            ))
+        })?;
+        Ok(found.map(Index::new))
+    }
+
+    /// Merges the items of `patch` into the dex, and returns the
+    /// translation of their indexes. The dex is left unchanged on error.
+    fn merge_patch(&mut self, patch: Self) -> DexResult<Remap> {
+        let dexs = [mem::replace(self, Self::new(self.version())), patch];
+        match merge_remaps(&dexs) {
+            Ok((merged, mut remaps)) => {
+                *self = merged;
+                Ok(remaps.remove(1))
+            }
+            Err(err) => {
+                let [dex, _] = dexs;
+                *self = dex;
+                Err(err)
+            }
+        }
+    }
+}
+
+/// Binary-searches `table`, sorted by the keys computed by `key_of`, for the
+/// position of `key`.
+fn search<T, K: Ord>(
+    table: &[T],
+    key: &K,
+    key_of: impl Fn(&T) -> DexResult<K>,
+) -> DexResult<Option<usize>> {
+    let (mut low, mut high) = (0, table.len());
+    while low < high {
+        let mid = low + (high - low) / 2;
+        match key_of(&table[mid])?.cmp(key) {
+            Ordering::Less => low = mid + 1,
+            Ordering::Greater => high = mid,
+            Ordering::Equal => return Ok(Some(mid)),
+        }
+    }
+    Ok(None)
+}
+
+pub(crate) fn push_string(dex: &mut Dex, s: &str) -> Index<StringIdItem> {
+    let off = extent(dex).max(dex.header_item.size());
+    let _ = dex.string_data_items.insert(
+        off,
+        StringDataItem {
+            index: Index::new(off),
+            utf16_size: Uleb128::new(s.encode_utf16().count() as u32, None),
+            data: mutf8::encode(s),
+        },
+    );
+    let index = Index::new(dex.string_id_items.len());
+    dex.string_id_items.push(StringIdItem {
+        index,
+        string_data_off: Index::new(off),
+    });
+    index
+}
+
+pub(crate) fn push_type(dex: &mut Dex, descriptor: &str) -> Index<TypeIdItem> {
+    let descriptor_idx = push_string(dex, descriptor);
+    let index = Index::new(dex.type_id_items.len());
+    dex.type_id_items.push(TypeIdItem {
+        index,
+        descriptor_idx,
+    });
+    index
+}
+
+/// Returns an offset greater than the ones of all the data items of `dex`.
+fn extent(dex: &Dex) -> usize {
+    fn last<T>(map: &Map<T>) -> usize {
+        map.keys().next_back().map_or(0, |off| off + 1)
+    }
+
+    [
+        last(&dex.type_lists),
+        last(&dex.annotation_set_ref_lists),
+        last(&dex.annotation_set_items),
+        last(&dex.class_data_items),
+        last(&dex.code_items),
+        last(&dex.string_data_items),
+        last(&dex.debug_info_items),
+        last(&dex.annotation_items),
+        last(&dex.encoded_array_items),
+        last(&dex.annotations_directory_items),
+        last(&dex.hiddenapi_class_data_items),
+    ]
+    .into_iter()
+    .max()
+    .unwrap_or(0)
+}
+
+fn check_limit(count: usize, name: &str) -> DexResult<()> {
+    if count > 0x1_0000 {
+        return Err(DexError::Structure(format!(
+            "too many {name} in merged dex ({count})"
+        )));
+    }
+    Ok(())
+}
+
+type Interned<'a, K, T> = (BTreeMap<K, (usize, &'a T)>, Vec<Vec<usize>>);
+
+/// Deduplicates the items of all the dex files by their `key`. Returns the
+/// first occurrence of each item sorted by key (along with the index of its
+/// dex file), and the new index of each item of each dex file.
+fn intern<'a, K: Ord + Clone, T>(
+    dexs: &'a [Dex],
+    items: impl Fn(&'a Dex) -> &'a [T],
+    key: impl Fn(usize, &'a T) -> DexResult<K>,
+) -> DexResult<Interned<'a, K, T>> {
+    let mut interned = BTreeMap::new();
+    let mut keys = Vec::with_capacity(dexs.len());
+    for (i, dex) in dexs.iter().enumerate() {
+        let mut dex_keys = Vec::new();
+        for item in items(dex) {
+            let k = key(i, item)?;
+            let _ = interned.entry(k.clone()).or_insert((i, item));
+            dex_keys.push(k);
+        }
+        keys.push(dex_keys);
+    }
+
+    let positions: BTreeMap<&K, usize> = interned
+        .keys()
+        .enumerate()
+        .map(|(new, k)| (k, new))
+        .collect();
+    let tables = keys
+        .iter()
+        .map(|dex_keys| dex_keys.iter().map(|k| positions[k]).collect())
+        .collect();
+    Ok((interned, tables))
+}
+
+/// Concatenates the class definitions of all the dex files, in an order
+/// where superclasses and interfaces are defined before the classes that
+/// extend or implement them.
+fn merge_class_defs(dexs: &[Dex], remaps: &[Remap], merged: &mut Dex) -> DexResult<()> {
+    let mut classes = BTreeMap::new();
+    let mut supertypes = BTreeMap::new();
+    let mut order = Vec::new();
+    for (i, (dex, remap)) in dexs.iter().zip(remaps).enumerate() {
+        for class in &dex.class_def_items {
+            let typ = remap.type_(class.class_idx)?.as_usize();
+            if classes.insert(typ, (i, class)).is_some() {
+                return Err(DexError::Structure(format!(
+                    "class {} is defined in several dex files",
+                    class.class_name(dex)?
+                )));
+            }
+            let mut supers = Vec::new();
+            if let Some(superclass) = class.superclass_idx {
+                supers.push(remap.type_(superclass)?.as_usize());
+            }
+            if let Some(off) = class.interfaces_off {
+                for item in &off.get(dex)?.list {
+                    supers.push(remap.type_(item.type_idx)?.as_usize());
+                }
+            }
+            let _ = supertypes.insert(typ, supers);
+            order.push(typ);
+        }
+    }
+
+    let mut sorted = Vec::with_capacity(order.len());
+    let mut visited = BTreeSet::new();
+    for typ in order {
+        sort_class(typ, &supertypes, &mut visited, &mut sorted);
+    }
+
+    for typ in sorted {
+        let (i, class) = classes[&typ];
+        let remap = &remaps[i];
+        if let Some(off) = class.interfaces_off {
+            let list = remap_type_list(remap, off.get(&dexs[i])?)?;
+            let _ = merged.type_lists.insert(list.index.as_usize(), list);
+        }
+        merged.class_def_items.push(ClassDefItem {
+            index: Index::new(merged.class_def_items.len()),
+            class_idx: Index::new(typ),
+            access_flags: class.access_flags,
+            superclass_idx: class.superclass_idx.map(|t| remap.type_(t)).transpose()?,
+            interfaces_off: class.interfaces_off.map(|off| remap.offset(off)),
+            source_file_idx: class.source_file_idx.map(|s| remap.string(s)).transpose()?,
+            annotations_off: class.annotations_off.map(|off| remap.offset(off)),
+            class_data_off: class.class_data_off.map(|off| remap.offset(off)),
+            static_values_off: class.static_values_off.map(|off| remap.offset(off)),
+        });
+    }
+    Ok(())
+}
+
+fn sort_class(
+    typ: usize,
+    supertypes: &BTreeMap<usize, Vec<usize>>,
+    visited: &mut BTreeSet<usize>,
+    sorted: &mut Vec<usize>,
+) {
+    if !visited.insert(typ) {
+        return;
+    }
+    for supertype in &supertypes[&typ] {
+        if supertypes.contains_key(supertype) {
+            sort_class(*supertype, supertypes, visited, sorted);
+        }
+    }
+    sorted.push(typ);
+}
+
+/// Copies the data items of `dex` that are not shared with other dex files
+/// into `merged`. Type lists and strings data are handled along with the
+/// items that reference them.
+fn merge_data_items(
+    dex: &Dex,
+    remap: &Remap,
+    keep_hiddenapi: bool,
+    merged: &mut Dex,
+) -> DexResult<()> {
+    for list in dex.annotation_set_ref_lists.values() {
+        let index = remap.offset(list.index);
+        let list = list
+            .list
+            .iter()
+            .map(|item| AnnotationSetRefItem {
+                annotations_off: remap.offset(item.annotations_off),
+            })
+            .collect();
+        let _ = merged
+            .annotation_set_ref_lists
+            .insert(index.as_usize(), AnnotationSetRefList { index, list });
+    }
+
+    for set in dex.annotation_set_items.values() {
+        let index = remap.offset(set.index);
+        // entries are sorted by annotation type
+        let mut entries = set
+            .entries
+            .iter()
+            .map(|entry| {
+                let annotation = entry.annotation_off.get(dex)?;
+                Ok((
+                    remap.type_(annotation.annotation.type_idx)?.as_usize(),
+                    AnnotationOffItem {
+                        annotation_off: remap.offset(entry.annotation_off),
+                    },
+                ))
+            })
+            .collect::<DexResult<Vec<_>>>()?;
+        entries.sort_by_key(|(typ, _)| *typ);
+        let entries = entries.into_iter().map(|(_, entry)| entry).collect();
+        let _ = merged
+            .annotation_set_items
+            .insert(index.as_usize(), AnnotationSetItem { index, entries });
+    }
+
+    for data in dex.class_data_items.values() {
+        let index = remap.offset(data.index);
+        let data = ClassDataItem {
+            index,
+            static_fields_size: data.static_fields_size,
+            instance_fields_size: data.instance_fields_size,
+            direct_methods_size: data.direct_methods_size,
+            virtual_methods_size: data.virtual_methods_size,
+            static_fields: remap_encoded_fields(remap, &data.static_fields)?,
+            instance_fields: remap_encoded_fields(remap, &data.instance_fields)?,
+            direct_methods: remap_encoded_methods(remap, &data.direct_methods)?,
+            virtual_methods: remap_encoded_methods(remap, &data.virtual_methods)?,
+        };
+        let _ = merged.class_data_items.insert(index.as_usize(), data);
+    }
+
+    for code in dex.code_items.values() {
+        let code = remap_code(remap, &code.read().unwrap())?;
+        let _ = merged
+            .code_items
+            .insert(code.index.as_usize(), RwLock::new(code));
+    }
+
+    for info in dex.debug_info_items.values() {
+        let index = remap.offset(info.index);
+        let info = DebugInfoItem {
+            index,
+            line_start: info.line_start,
+            parameters_size: info.parameters_size,
+            parameter_names: info
+                .parameter_names
+                .iter()
+                .map(|name| name.map(|s| remap.string(s)).transpose())
+                .collect::<DexResult<_>>()?,
+            bytecode: info
+                .bytecode
+                .iter()
+                .map(|instr| remap_dbg_instr(remap, instr))
+                .collect::<DexResult<_>>()?,
+        };
+        let _ = merged.debug_info_items.insert(index.as_usize(), info);
+    }
+
+    for item in dex.annotation_items.values() {
+        let index = remap.offset(item.index);
+        let item = AnnotationItem {
+            index,
+            visibility: item.visibility,
+            annotation: remap_annotation(remap, &item.annotation)?,
+        };
+        let _ = merged.annotation_items.insert(index.as_usize(), item);
+    }
+
+    for item in dex.encoded_array_items.values() {
+        let index = remap.offset(item.index);
+        let item = EncodedArrayItem {
+            index,
+            value: remap_array(remap, &item.value)?,
+        };
+        let _ = merged.encoded_array_items.insert(index.as_usize(), item);
+    }
+
+    for directory in dex.annotations_directory_items.values() {
+        let index = remap.offset(directory.index);
+        // annotations are sorted by field or method index
+        let mut field_annotations = directory
+            .field_annotations
+            .iter()
+            .map(|annotation| {
+                Ok(FieldAnnotation {
+                    field_idx: remap.field(annotation.field_idx)?,
+                    annotations_off: remap.offset(annotation.annotations_off),
+                })
+            })
+            .collect::<DexResult<Vec<_>>>()?;
+        field_annotations.sort_by_key(|annotation| annotation.field_idx.as_usize());
+        let mut method_annotations = directory
+            .method_annotations
+            .iter()
+            .map(|annotation| {
+                Ok(MethodAnnotation {
+                    method_idx: remap.method(annotation.method_idx)?,
+                    annotations_off: remap.offset(annotation.annotations_off),
+                })
+            })
+            .collect::<DexResult<Vec<_>>>()?;
+        method_annotations.sort_by_key(|annotation| annotation.method_idx.as_usize());
+        let mut parameter_annotations = directory
+            .parameter_annotations
+            .iter()
+            .map(|annotation| {
+                Ok(ParameterAnnotation {
+                    method_idx: remap.method(annotation.method_idx)?,
+                    annotations_off: remap.offset(annotation.annotations_off),
+                })
+            })
+            .collect::<DexResult<Vec<_>>>()?;
+        parameter_annotations.sort_by_key(|annotation| annotation.method_idx.as_usize());
+        let directory = AnnotationsDirectoryItem {
+            index,
+            class_annotations_off: remap.offset(directory.class_annotations_off),
+            field_annotations,
+            method_annotations,
+            parameter_annotations,
+        };
+        let _ = merged
+            .annotations_directory_items
+            .insert(index.as_usize(), directory);
+    }
+
+    if keep_hiddenapi {
+        for (off, item) in &dex.hiddenapi_class_data_items {
+            let item = HiddenapiClassDataItem {
+                offsets: item.offsets.clone(),
+                flags: item
+                    .flags
+                    .iter()
+                    .map(|(off, flag)| {
+                        (
+                            *off,
+                            HiddenapiClassFlag {
+                                uleb_repr: flag.uleb_repr,
+                                flag: flag.flag,
+                            },
+                        )
+                    })
+                    .collect(),
+            };
+            let _ = merged
+                .hiddenapi_class_data_items
+                .insert(remap.base + off, item);
+        }
+    }
+
+    Ok(())
+}
+
+fn remap_type_list(remap: &Remap, list: &TypeList) -> DexResult<TypeList> {
+    Ok(TypeList {
+        index: remap.offset(list.index),
+        list: list
+            .list
+            .iter()
+            .map(|item| {
+                Ok(TypeItem {
+                    type_idx: remap.type_(item.type_idx)?,
+                })
+            })
+            .collect::<DexResult<_>>()?,
+    })
+}
+
+fn remap_method_handle(remap: &Remap, handle: &MethodHandle) -> DexResult<MethodHandle> {
+    Ok(match handle {
+        MethodHandle::StaticPut(field) => MethodHandle::StaticPut(remap.field(*field)?),
+        MethodHandle::StaticGet(field) => MethodHandle::StaticGet(remap.field(*field)?),
+        MethodHandle::InstancePut(field) => MethodHandle::InstancePut(remap.field(*field)?),
+        MethodHandle::InstanceGet(field) => MethodHandle::InstanceGet(remap.field(*field)?),
+        MethodHandle::InvokeStatic(method) => MethodHandle::InvokeStatic(remap.method(*method)?),
+        MethodHandle::InvokeInstance(method) => {
+            MethodHandle::InvokeInstance(remap.method(*method)?)
+        }
+        MethodHandle::InvokeConstructor(method) => {
+            MethodHandle::InvokeConstructor(remap.method(*method)?)
+        }
+        MethodHandle::InvokeDirect(method) => MethodHandle::InvokeDirect(remap.method(*method)?),
+        MethodHandle::InvokeInterface(method) => {
+            MethodHandle::InvokeInterface(remap.method(*method)?)
+        }
+    })
+}
+
+/// Remaps encoded fields, which must stay sorted by field index as they
+/// are stored as index differences.
+fn remap_encoded_fields(remap: &Remap, fields: &[EncodedField]) -> DexResult<Vec<EncodedField>> {
+    let mut remapped = fields
+        .iter()
+        .map(|field| {
+            Ok(EncodedField {
+                field_idx_diff: field.field_idx_diff,
+                field_idx: remap.field(field.field_idx)?,
+                access_flags_repr: field.access_flags_repr,
+                access_flags: field.access_flags,
+            })
+        })
+        .collect::<DexResult<Vec<_>>>()?;
+    remapped.sort_by_key(|field| field.field_idx.as_usize());
+    let mut previous = 0;
+    for field in &mut remapped {
+        let idx = field.field_idx.as_usize();
+        field.field_idx_diff = Uleb128::new((idx - previous) as u32, None);
+        previous = idx;
+    }
+    Ok(remapped)
+}
+
+/// Remaps encoded methods, which must stay sorted by method index as they
+/// are stored as index differences.
+fn remap_encoded_methods(
+    remap: &Remap,
+    methods: &[EncodedMethod],
+) -> DexResult<Vec<EncodedMethod>> {
+    let mut remapped = methods
+        .iter()
+        .map(|method| {
+            Ok(EncodedMethod {
+                method_idx_diff: method.method_idx_diff,
+                method_idx: remap.method(method.method_idx)?,
+                access_flags_repr: method.access_flags_repr,
+                access_flags: method.access_flags,
+                code_off: method.code_off.map(|off| remap.offset(off)),
+            })
+        })
+        .collect::<DexResult<Vec<_>>>()?;
+    remapped.sort_by_key(|method| method.method_idx.as_usize());
+    let mut previous = 0;
+    for method in &mut remapped {
+        let idx = method.method_idx.as_usize();
+        method.method_idx_diff = Uleb128::new((idx - previous) as u32, None);
+        previous = idx;
+    }
+    Ok(remapped)
+}
+
+fn remap_code(remap: &Remap, code: &CodeItem) -> DexResult<CodeItem> {
+    let insns = code
+        .insns
+        .iter()
+        .map(|linstr| {
+            Ok(LabeledInstr {
+                addr: linstr.addr,
+                instr: remap_instr(remap, &linstr.instr)?,
+            })
+        })
+        .collect::<DexResult<_>>()?;
+
+    // catch types are uleb128-encoded, handlers may move inside the list.
+    let mut handler_offs = BTreeMap::new();
+    let handlers = match &code.handlers {
+        Some(handlers) => {
+            let mut off = handlers.size.size();
+            let mut list = BTreeMap::new();
+            for (old_off, handler) in &handlers.list {
+                let handler = EncodedCatchHandler {
+                    size: handler.size,
+                    handlers: handler
+                        .handlers
+                        .iter()
+                        .map(|pair| {
+                            Ok(EncodedTypeAddrPair {
+                                type_idx: remap.type_(pair.type_idx)?,
+                                addr: pair.addr,
+                            })
+                        })
+                        .collect::<DexResult<_>>()?,
+                    catch_all_addr: handler.catch_all_addr,
+                };
+                let _ = handler_offs.insert(*old_off, off);
+                off += handler.size.size()
+                    + handler
+                        .handlers
+                        .iter()
+                        .map(|pair| pair.type_idx.as_uleb().size() + pair.addr.size())
+                        .sum::<usize>()
+                    + handler.catch_all_addr.map_or(0, |addr| addr.size());
+                let _ = list.insert(handler_offs[old_off], handler);
+            }
+            Some(EncodedCatchHandlerList {
+                size: handlers.size,
+                list,
+            })
+        }
+        None => None,
+    };
+    let tries = code
+        .tries
+        .iter()
+        .map(|try_| TryItem {
+            start_addr: try_.start_addr,
+            insn_count: try_.insn_count,
+            handler_off: handler_offs
+                .get(&try_.handler_off)
+                .copied()
+                .unwrap_or(try_.handler_off),
+        })
+        .collect();
+
+    Ok(CodeItem {
+        index: remap.offset(code.index),
+        registers_size: code.registers_size,
+        ins_size: code.ins_size,
+        outs_size: code.outs_size,
+        debug_info_off: code.debug_info_off.map(|off| remap.offset(off)),
+        insns,
+        tries,
+        handlers,
+    })
+}
+
+fn remap_instr(remap: &Remap, instr: &Instr) -> DexResult<Instr> {
+    use Instr::*;
+
+    let mut instr = instr.clone();
+    match &mut instr {
+        ConstString(_, s) => {
+            *s = remap.string(*s)?;
+            if s.as_usize() > 0xffff {
+                return Err(DexError::Structure(format!(
+                    "string index {} does not fit in const-string",
+                    s.as_usize()
+                )));
+            }
+        }
+        ConstStringJumbo(_, s) => *s = remap.string(*s)?,
+
+        ConstClass(_, t)
+        | CheckCast(_, t)
+        | NewInstance(_, t)
+        | InstanceOf(_, _, t)
+        | NewArray(_, _, t)
+        | FilledNewArray(_, t)
+        | FilledNewArrayRange(_, t) => *t = remap.type_(*t)?,
+
+        Iget(_, _, f)
+        | IgetWide(_, _, f)
+        | IgetObject(_, _, f)
+        | IgetBoolean(_, _, f)
+        | IgetByte(_, _, f)
+        | IgetChar(_, _, f)
+        | IgetShort(_, _, f)
+        | Iput(_, _, f)
+        | IputWide(_, _, f)
+        | IputObject(_, _, f)
+        | IputBoolean(_, _, f)
+        | IputByte(_, _, f)
+        | IputChar(_, _, f)
+        | IputShort(_, _, f)
+        | Sget(_, f)
+        | SgetWide(_, f)
+        | SgetObject(_, f)
+        | SgetBoolean(_, f)
+        | SgetByte(_, f)
+        | SgetChar(_, f)
+        | SgetShort(_, f)
+        | Sput(_, f)
+        | SputWide(_, f)
+        | SputObject(_, f)
+        | SputBoolean(_, f)
+        | SputByte(_, f)
+        | SputChar(_, f)
+        | SputShort(_, f) => *f = remap.field(*f)?,
+
+        InvokeVirtual(_, m)
+        | InvokeSuper(_, m)
+        | InvokeDirect(_, m)
+        | InvokeStatic(_, m)
+        | InvokeInterface(_, m)
+        | InvokeVirtualRange(_, m)
+        | InvokeSuperRange(_, m)
+        | InvokeDirectRange(_, m)
+        | InvokeStaticRange(_, m)
+        | InvokeInterfaceRange(_, m) => *m = remap.method(*m)?,
+
+        InvokePolymorphic(_, m, p) | InvokePolymorphicRange(_, m, p) => {
+            *m = remap.method(*m)?;
+            *p = remap.proto(*p)?;
+        }
+        InvokeCustom(_, c) | InvokeCustomRange(_, c) => *c = remap.call_site(*c),
+        ConstMethodHandle(_, h) => *h = remap.method_handle(*h),
+        ConstMethodType(_, p) => *p = remap.proto(*p)?,
+
+        _ => (),
+    }
+    Ok(instr)
+}
+
+fn remap_dbg_instr(remap: &Remap, instr: &DbgInstr) -> DexResult<DbgInstr> {
+    let string = |s: &Option<Index<StringIdItem>>| s.map(|s| remap.string(s)).transpose();
+    let type_ = |t: &Option<Index<TypeIdItem>>| t.map(|t| remap.type_(t)).transpose();
+
+    Ok(match instr {
+        DbgInstr::EndSequence => DbgInstr::EndSequence,
+        DbgInstr::AdvancePc { addr_diff } => DbgInstr::AdvancePc {
+            addr_diff: *addr_diff,
+        },
+        DbgInstr::AdvanceLine { line_diff } => DbgInstr::AdvanceLine {
+            line_diff: *line_diff,
+        },
+        DbgInstr::StartLocal {
+            register_num,
+            name_idx,
+            type_idx,
+        } => DbgInstr::StartLocal {
+            register_num: *register_num,
+            name_idx: string(name_idx)?,
+            type_idx: type_(type_idx)?,
+        },
+        DbgInstr::StartLocalExtended {
+            register_num,
+            name_idx,
+            type_idx,
+            sig_idx,
+        } => DbgInstr::StartLocalExtended {
+            register_num: *register_num,
+            name_idx: string(name_idx)?,
+            type_idx: type_(type_idx)?,
+            sig_idx: string(sig_idx)?,
+        },
+        DbgInstr::EndLocal { register_num } => DbgInstr::EndLocal {
+            register_num: *register_num,
+        },
+        DbgInstr::RestartLocal { register_num } => DbgInstr::RestartLocal {
+            register_num: *register_num,
+        },
+        DbgInstr::SetPrologueEnd => DbgInstr::SetPrologueEnd,
+        DbgInstr::SetEpilogueBegin => DbgInstr::SetEpilogueBegin,
+        DbgInstr::SetFile { name_idx } => DbgInstr::SetFile {
+            name_idx: string(name_idx)?,
+        },
+        DbgInstr::Special(opcode) => DbgInstr::Special(*opcode),
+    })
+}
+
+fn remap_annotation(remap: &Remap, annotation: &EncodedAnnotation) -> DexResult<EncodedAnnotation> {
+    // elements are sorted by name
+    let mut elements = annotation
+        .elements
+        .iter()
+        .map(|element| {
+            Ok(AnnotationElement {
+                name_idx: remap.string(element.name_idx)?,
+                value: remap_value(remap, &element.value)?,
+            })
+        })
+        .collect::<DexResult<Vec<_>>>()?;
+    elements.sort_by_key(|element| element.name_idx.as_usize());
+    Ok(EncodedAnnotation {
+        type_idx: remap.type_(annotation.type_idx)?,
+        size: annotation.size,
+        elements,
+    })
+}
+
+fn remap_array(remap: &Remap, array: &EncodedArray) -> DexResult<EncodedArray> {
+    Ok(EncodedArray {
+        size: array.size,
+        values: array
+            .values
+            .iter()
+            .map(|value| remap_value(remap, value))
+            .collect::<DexResult<_>>()?,
+    })
+}
+
+/// Returns the number of bytes needed to encode an index in an encoded
+/// value.
+const fn index_size(idx: usize) -> usize {
+    match idx {
+        0..=0xff => 1,
+        0x100..=0xffff => 2,
+        0x1_0000..=0xff_ffff => 3,
+        _ => 4,
+    }
+}
+
+fn remap_value(remap: &Remap, value: &EncodedValue) -> DexResult<EncodedValue> {
+    Ok(match value {
+        EncodedValue::Byte(v) => EncodedValue::Byte(*v),
+        EncodedValue::Short(siz, v) => EncodedValue::Short(*siz, *v),
+        EncodedValue::Char(siz, v) => EncodedValue::Char(*siz, *v),
+        EncodedValue::Int(siz, v) => EncodedValue::Int(*siz, *v),
+        EncodedValue::Long(siz, v) => EncodedValue::Long(*siz, *v),
+        EncodedValue::Float(siz, v) => EncodedValue::Float(*siz, *v),
+        EncodedValue::Double(siz, v) => EncodedValue::Double(*siz, *v),
+        EncodedValue::MethodType(_, proto) => {
+            let proto = remap.proto(*proto)?;
+            EncodedValue::MethodType(index_size(proto.as_usize()), proto)
+        }
+        EncodedValue::MethodHandle(_, handle) => {
+            let handle = remap.method_handle(*handle);
+            EncodedValue::MethodHandle(index_size(handle.as_usize()), handle)
+        }
+        EncodedValue::String(_, s) => {
+            let s = remap.string(*s)?;
+            EncodedValue::String(index_size(s.as_usize()), s)
+        }
+        EncodedValue::Type(_, typ) => {
+            let typ = remap.type_(*typ)?;
+            EncodedValue::Type(index_size(typ.as_usize()), typ)
+        }
+        EncodedValue::Field(_, field) => {
+            let field = remap.field(*field)?;
+            EncodedValue::Field(index_size(field.as_usize()), field)
+        }
+        EncodedValue::Method(_, method) => {
+            let method = remap.method(*method)?;
+            EncodedValue::Method(index_size(method.as_usize()), method)
+        }
+        EncodedValue::Enum(_, field) => {
+            let field = remap.field(*field)?;
+            EncodedValue::Enum(index_size(field.as_usize()), field)
+        }
+        EncodedValue::Array(array) => EncodedValue::Array(remap_array(remap, array)?),
+        EncodedValue::Annotation(annotation) => {
+            EncodedValue::Annotation(remap_annotation(remap, annotation)?)
+        }
+        EncodedValue::Null => EncodedValue::Null,
+        EncodedValue::Boolean(b) => EncodedValue::Boolean(*b),
+    })
+}
+
+/// The order in which data items are laid out. Code items come before class
+/// data items, whose sizes depend on the uleb128-encoded offsets of the
+/// code items.
+const DATA_LAYOUT: [MapItemType; 11] = [
+    MapItemType::TypeList,
+    MapItemType::AnnotationSetRefList,
+    MapItemType::AnnotationSetItem,
+    MapItemType::CodeItem,
+    MapItemType::ClassDataItem,
+    MapItemType::StringDataItem,
+    MapItemType::DebugInfoItem,
+    MapItemType::AnnotationItem,
+    MapItemType::EncodedArrayItem,
+    MapItemType::AnnotationsDirectoryItem,
+    MapItemType::HiddenapiClassDataItem,
+];
+
+/// Gives their final offsets to the data items of `dex`, whose offsets
+/// only have to be unique, and to the map list (stored at `map_off` until
+/// then). The map list and the header are updated accordingly.
+fn layout(dex: &mut Dex, map_off: usize) -> DexResult<()> {
+    dex.header_item.map_off = map_off;
+
+    // moving items changes the size of the class data items, which may in
+    // turn move the following items: the layout is computed until offsets
+    // do not change anymore.
+    for _ in 0..MAX_LAYOUT_PASSES {
+        update_map_list(dex);
+        let (buffer, positions) =
+            write_items(dex, &WriteOptions::default(), &Relocations::default())?;
+        if positions.0.iter().all(|(off, position)| off == position) {
+            dex.header_item.file_size = buffer.len();
+            dex.header_item.data_size = buffer.len() - dex.header_item.data_off;
+            return Ok(());
+        }
+        relocate(dex, &positions);
+    }
+    Err(DexError::Internal(
+        "merged dex layout does not converge".to_string(),
+    ))
+}
+
+/// Rebuilds the map list of `dex`, and the header fields describing its
+/// sections, from its current contents.
+fn update_map_list(dex: &mut Dex) {
+    fn first<T>(map: &Map<T>) -> (usize, usize) {
+        (map.len(), map.keys().next().copied().unwrap_or(0))
+    }
+
+    let mut list = vec![MapItem {
+        typ: MapItemType::HeaderItem,
+        size: 1,
+        offset: 0,
+    }];
+    let mut offset = dex.header_item.size();
+    let ids = [
+        (MapItemType::StringIdItem, dex.string_id_items.len(), 4),
+        (MapItemType::TypeIdItem, dex.type_id_items.len(), 4),
+        (MapItemType::ProtoIdItem, dex.proto_id_items.len(), 12),
+        (MapItemType::FieldIdItem, dex.field_id_items.len(), 8),
+        (MapItemType::MethodIdItem, dex.method_id_items.len(), 8),
+        (MapItemType::ClassDefItem, dex.class_def_items.len(), 32),
+        (MapItemType::CallSiteIdItem, dex.call_site_id_items.len(), 4),
+        (
+            MapItemType::MethodHandleItem,
+            dex.method_handle_items.len(),
+            8,
+        ),
+    ];
+    let mut sections = BTreeMap::new();
+    for (typ, count, size) in ids {
+        if count > 0 {
+            list.push(MapItem {
+                typ,
+                size: count,
+                offset,
+            });
+            let _ = sections.insert(typ, (count, offset));
+            offset += count * size;
+        }
+    }
+
+    let header = &mut dex.header_item;
+    let section = |typ| sections.get(&typ).copied().unwrap_or((0, 0));
+    (header.string_ids_size, header.string_ids_off) = section(MapItemType::StringIdItem);
+    (header.type_ids_size, header.type_ids_off) = section(MapItemType::TypeIdItem);
+    (header.proto_ids_size, header.proto_ids_off) = section(MapItemType::ProtoIdItem);
+    (header.field_ids_size, header.field_ids_off) = section(MapItemType::FieldIdItem);
+    (header.method_ids_size, header.method_ids_off) = section(MapItemType::MethodIdItem);
+    (header.class_defs_size, header.class_defs_off) = section(MapItemType::ClassDefItem);
+    header.data_off = offset;
+
+    for typ in DATA_LAYOUT {
+        let (count, offset) = match typ {
+            MapItemType::TypeList => first(&dex.type_lists),
+            MapItemType::AnnotationSetRefList => first(&dex.annotation_set_ref_lists),
+            MapItemType::AnnotationSetItem => first(&dex.annotation_set_items),
+            MapItemType::CodeItem => first(&dex.code_items),
+            MapItemType::ClassDataItem => first(&dex.class_data_items),
+            MapItemType::StringDataItem => first(&dex.string_data_items),
+            MapItemType::DebugInfoItem => first(&dex.debug_info_items),
+            MapItemType::AnnotationItem => first(&dex.annotation_items),
+            MapItemType::EncodedArrayItem => first(&dex.encoded_array_items),
+            MapItemType::AnnotationsDirectoryItem => first(&dex.annotations_directory_items),
+            MapItemType::HiddenapiClassDataItem => first(&dex.hiddenapi_class_data_items),
+            _ => unreachable!("{:?} items are not data items", typ),
+        };
+        if count > 0 {
+            list.push(MapItem {
+                typ,
+                size: count,
+                offset,
+            });
+        }
+    }
+    list.push(MapItem {
+        typ: MapItemType::MapList,
+        size: 1,
+        offset: dex.header_item.map_off,
+    });
+
+    dex.map_list = MapList { list };
+}
+
+fn moved<T>(relocations: &Relocations, off: Index<T>) -> Index<T> {
+    Index::new(relocations.get(off.as_usize()) as usize)
+}
+
+fn rekey<T>(map: &mut Map<T>, relocations: &Relocations, mut update: impl FnMut(usize, &mut T)) {
+    *map = mem::take(map)
+        .into_iter()
+        .map(|(off, mut item)| {
+            let off = relocations.get(off) as usize;
+            update(off, &mut item);
+            (off, item)
+        })
+        .collect();
+}
+
+/// Moves the data items of `dex` to the offsets given by `relocations`,
+/// and updates every offset referencing them.
+fn relocate(dex: &mut Dex, relocations: &Relocations) {
+    rekey(&mut dex.type_lists, relocations, |off, list| {
+        list.index = Index::new(off);
+    });
+    rekey(
+        &mut dex.annotation_set_ref_lists,
+        relocations,
+        |off, list| {
+            list.index = Index::new(off);
+            for item in &mut list.list {
+                item.annotations_off = moved(relocations, item.annotations_off);
+            }
+        },
+    );
+    rekey(&mut dex.annotation_set_items, relocations, |off, set| {
+        set.index = Index::new(off);
+        for entry in &mut set.entries {
+            entry.annotation_off = moved(relocations, entry.annotation_off);
+        }
+    });
+    rekey(&mut dex.class_data_items, relocations, |off, data| {
+        data.index = Index::new(off);
+        for method in data
+            .direct_methods
+            .iter_mut()
+            .chain(data.virtual_methods.iter_mut())
+        {
+            method.code_off = method.code_off.map(|off| moved(relocations, off));
+        }
+    });
+    rekey(&mut dex.code_items, relocations, |off, code| {
+        let code = code.get_mut().unwrap();
+        code.index = Index::new(off);
+        code.debug_info_off = code.debug_info_off.map(|off| moved(relocations, off));
+    });
+    rekey(&mut dex.string_data_items, relocations, |off, data| {
+        data.index = Index::new(off);
+    });
+    rekey(&mut dex.debug_info_items, relocations, |off, info| {
+        info.index = Index::new(off);
+    });
+    rekey(&mut dex.annotation_items, relocations, |off, item| {
+        item.index = Index::new(off);
+    });
+    rekey(&mut dex.encoded_array_items, relocations, |off, item| {
+        item.index = Index::new(off);
+    });
+    rekey(
+        &mut dex.annotations_directory_items,
+        relocations,
+        |off, directory| {
+            directory.index = Index::new(off);
+            directory.class_annotations_off = moved(relocations, directory.class_annotations_off);
+            for annotation in &mut directory.field_annotations {
+                annotation.annotations_off = moved(relocations, annotation.annotations_off);
+            }
+            for annotation in &mut directory.method_annotations {
+                annotation.annotations_off = moved(relocations, annotation.annotations_off);
+            }
+            for annotation in &mut directory.parameter_annotations {
+                annotation.annotations_off = moved(relocations, annotation.annotations_off);
+            }
+        },
+    );
+    rekey(&mut dex.hiddenapi_class_data_items, relocations, |_, _| ());
+
+    for id in &mut dex.string_id_items {
+        id.string_data_off = moved(relocations, id.string_data_off);
+    }
+    for id in &mut dex.proto_id_items {
+        id.parameters_off = id.parameters_off.map(|off| moved(relocations, off));
+    }
+    for class in &mut dex.class_def_items {
+        class.interfaces_off = class.interfaces_off.map(|off| moved(relocations, off));
+        class.annotations_off = class.annotations_off.map(|off| moved(relocations, off));
+        class.class_data_off = class.class_data_off.map(|off| moved(relocations, off));
+        class.static_values_off = class.static_values_off.map(|off| moved(relocations, off));
+    }
+    for id in &mut dex.call_site_id_items {
+        id.call_site_off = moved(relocations, id.call_site_off);
+    }
+    dex.header_item.map_off = relocations.get(dex.header_item.map_off) as usize;
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::instrs::Instruction;
+    use crate::registers::RegList;
+    use crate::{parse, write, PrettyPrinter};
+
+    /// Builds a dex declaring `class`, whose constructor calls the one of
+    /// `java.lang.Object`.
+    fn dex(class: &str) -> Dex {
+        let mut dex = Dex::new(35);
+        let init = push_string(&mut dex, "<init>");
+        let void = push_type(&mut dex, "V");
+        let object = push_type(&mut dex, "Ljava/lang/Object;");
+        let class = push_type(&mut dex, class);
+        dex.proto_id_items.push(ProtoIdItem {
+            index: Index::new(0),
+            shorty_idx: dex[void].descriptor_idx,
+            return_type_idx: void,
+            parameters_off: None,
+        });
+        for (index, class_idx) in [object, class].into_iter().enumerate() {
+            dex.method_id_items.push(MethodIdItem {
+                index: Index::new(index),
+                class_idx,
+                proto_idx: Index::new(0),
+                name_idx: init,
+            });
+        }
+
+        let insns = vec![
+            Instr::InvokeDirect(RegList::from(vec![0u8]), Index::new(0)),
+            Instr::ReturnVoid,
+        ];
+        let mut addr = 0;
+        let insns = insns
+            .into_iter()
+            .map(|instr| {
+                let linstr = LabeledInstr {
+                    addr: crate::Addr(addr),
+                    instr,
+                };
+                addr += linstr.instr.size();
+                linstr
+            })
+            .collect();
+        let _ = dex.code_items.insert(
+            0x2000,
+            RwLock::new(CodeItem {
+                index: Index::new(0x2000),
+                registers_size: 1,
+                ins_size: 1,
+                outs_size: 1,
+                debug_info_off: None,
+                insns,
+                tries: Vec::new(),
+                handlers: None,
+            }),
+        );
+        let _ = dex.class_data_items.insert(
+            0x3000,
+            ClassDataItem {
+                index: Index::new(0x3000),
+                static_fields_size: Uleb128::new(0, None),
+                instance_fields_size: Uleb128::new(0, None),
+                direct_methods_size: Uleb128::new(1, None),
+                virtual_methods_size: Uleb128::new(0, None),
+                static_fields: Vec::new(),
+                instance_fields: Vec::new(),
+                direct_methods: vec![EncodedMethod {
+                    method_idx_diff: Uleb128::new(1, None),
+                    method_idx: Index::new(1),
+                    access_flags_repr: Uleb128::new(0x10001, None),
+                    access_flags: MethodFlags::ACC_PUBLIC | MethodFlags::ACC_CONSTRUCTOR,
+                    code_off: Some(Index::new(0x2000)),
+                }],
+                virtual_methods: Vec::new(),
+            },
+        );
+        dex.class_def_items.push(ClassDefItem {
+            index: Index::new(0),
+            class_idx: class,
+            access_flags: ClassFlags::ACC_PUBLIC,
+            superclass_idx: Some(object),
+            interfaces_off: None,
+            source_file_idx: None,
+            annotations_off: None,
+            class_data_off: Some(Index::new(0x3000)),
+            static_values_off: None,
+        });
+        dex
+    }
+
+    #[test]
+    fn merge_shared_types() {
+        let merged = merge_dex(&[dex("LA;"), dex("LB;")]).unwrap();
+
+        let types = merged
+            .iter_type_ids()
+            .map(|id| id.to_type(&merged).unwrap())
+            .collect::<Vec<_>>();
+        let object = Type::Class("java/lang/Object".to_string());
+        assert_eq!(types.iter().filter(|typ| **typ == object).count(), 1);
+        assert_eq!(types.len(), 4);
+        assert_eq!(merged.iter_method_ids().count(), 3);
+
+        let written = write(&merged, &WriteOptions::default()).unwrap();
+        let reparsed = parse(&written).unwrap();
+        assert_eq!(write(&reparsed, &WriteOptions::default()).unwrap(), written);
+        let mut classes = Vec::new();
+        for class in reparsed.iter_class_defs() {
+            classes.push(class.class_name(&reparsed).unwrap());
+            let data = class.data(&reparsed).unwrap().unwrap();
+            let method = data.iter_direct_methods().next().unwrap();
+            let code = method.code(&reparsed).unwrap().unwrap().read().unwrap();
+            let Instr::InvokeDirect(_, callee) = code.iter_instructions().next().unwrap().instr()
+            else {
+                panic!("unexpected instruction");
+            };
+            assert_eq!(
+                PrettyPrinter(&reparsed[*callee], &reparsed).to_string(),
+                "Ljava/lang/Object;-><init>()V"
+            );
+        }
+        assert_eq!(classes, ["A", "B"]);
+    }
+
+    #[test]
+    fn intern_method() {
+        let mut dex = dex("LA;");
+        let strings = dex.iter_string_ids().count();
+        let init = dex.intern_string("<init>").unwrap();
+        assert_eq!(dex.iter_string_ids().count(), strings);
+        assert_eq!(dex[init].to_string(&dex).unwrap(), "<init>");
+
+        let string = Type::Class("java/lang/String".to_string());
+        let proto = ProtoDescr {
+            return_type: string.clone(),
+            parameters: vec![Type::Int, string],
+        };
+        let method = dex
+            .intern_method("Ljava/lang/String;", "valueOf", &proto)
+            .unwrap();
+        // existing items are found without rebuilding the dex
+        let table = dex.method_id_items.as_ptr();
+        let again = dex
+            .intern_method("Ljava/lang/String;", "valueOf", &proto)
+            .unwrap();
+        assert_eq!(again.as_usize(), method.as_usize());
+        assert_eq!(dex.method_id_items.as_ptr(), table);
+        assert_eq!(
+            dex.intern_string("<init>").unwrap().as_usize(),
+            init.as_usize()
+        );
+        assert_eq!(dex.method_id_items.as_ptr(), table);
+
+        let reparsed = parse(&write(&dex, &WriteOptions::default()).unwrap()).unwrap();
+        assert_eq!(
+            PrettyPrinter(&reparsed[method], &reparsed).to_string(),
+            "Ljava/lang/String;->valueOf(ILjava/lang/String;)Ljava/lang/String;"
+        );
+        let proto = &reparsed[reparsed[method].proto_idx];
+        assert_eq!(
+            reparsed[proto.shorty_idx].to_string(&reparsed).unwrap(),
+            "LIL"
+        );
+    }
+}
@@ -1,5 +1,6 @@
 //! Dalvik class methods data structures.
 
+use crate::annotations::{Annotation, AnnotationSetItem, AnnotationsDirectoryItem};
 use crate::code::CodeItem;
 use crate::errors::{DexError, DexResult};
 use crate::strings::StringIdItem;
@@ -95,6 +96,38 @@ impl EncodedMethod {
     pub fn code<'a>(&self, dex: &'a Dex) -> DexResult<Option<&'a RwLock<CodeItem>>> {
         self.code_off.map(|off| off.get(dex)).transpose()
     }
+
+    /// Returns the decoded annotations of the method.
+    pub fn annotations<'a>(&self, dex: &'a Dex) -> DexResult<Vec<Annotation<'a>>> {
+        match self.annotation_set(dex)? {
+            Some(set) => set.annotations(dex),
+            None => Ok(Vec::new()),
+        }
+    }
+
+    /// Returns the generic signature of the method, as found in its
+    /// `dalvik.annotation.Signature` annotation, if any.
+    pub fn generic_signature(&self, dex: &Dex) -> DexResult<Option<String>> {
+        match self.annotation_set(dex)? {
+            Some(set) => set.generic_signature(dex),
+            None => Ok(None),
+        }
+    }
+
+    /// Returns the annotation set of the method, as found in the
+    /// annotations directory of its class.
+    fn annotation_set<'a>(&self, dex: &'a Dex) -> DexResult<Option<&'a AnnotationSetItem>> {
+        let class_idx = self.method_idx.get(dex)?.class_idx;
+        let Some(directory) = AnnotationsDirectoryItem::of_class(class_idx, dex)? else {
+            return Ok(None);
+        };
+        directory
+            .method_annotations
+            .iter()
+            .find(|annotation| annotation.method_idx.as_usize() == self.method_idx.as_usize())
+            .map(|annotation| annotation.annotations_off.get(dex))
+            .transpose()
+    }
 }
 
 bitflags! {
@@ -1,12 +1,28 @@
+//! Modified UTF-8 (MUTF-8) encoding of dex strings.
+//!
+//! MUTF-8 differs from UTF-8 in that U+0000 is encoded on two bytes, so
+//! that encoded strings never contain a null byte, and that supplementary
+//! characters are encoded as a surrogate pair of three bytes each.
+
 use crate::errors::{DexError, DexResult};
 
-// Decodes a non-null-terminated MUTF-8 buffer and returns its String form.
+/// Decodes a non-null-terminated MUTF-8 buffer.
+///
+/// # Errors
+///
+/// Returns [`DexError::InvalidMutf8`] if the buffer is not valid MUTF-8,
+/// or if it contains an isolated surrogate code unit.
+pub fn decode(bytes: &[u8]) -> DexResult<String> {
+    String::from_utf16(&decode_utf16(bytes)?).map_err(|err| DexError::InvalidMutf8(err.to_string()))
+}
+
+// Decodes a non-null-terminated MUTF-8 buffer into UTF-16 code units.
 // Reimplementation of https://android.googlesource.com/platform/libcore/+/7047230/dex/src/main/java/com/android/dex/Mutf8.java
 // Since surrogate pairs characters are not valid Rust chars,
 // it builds a vector of u16 (i.e. an UTF16 encoded buffer).
 #[allow(clippy::cast_possible_truncation)]
 #[allow(clippy::cast_lossless)]
-pub(crate) fn decode(inp: &[u8]) -> DexResult<Vec<u16>> {
+pub(crate) fn decode_utf16(inp: &[u8]) -> DexResult<Vec<u16>> {
     let mut i = 0;
     let mut buf: Vec<u16> = Vec::new();
 
@@ -14,7 +30,7 @@ pub(crate) fn decode(inp: &[u8]) -> DexResult<Vec<u16>> {
         let a = inp[i];
         i += 1;
 
-        if a == 0 && i != inp.len() - 1 {
+        if a == 0 {
             return Err(DexError::InvalidMutf8(
                 "null-byte in a non-null terminated string".to_string(),
             ));
@@ -62,12 +78,14 @@ pub(crate) fn decode(inp: &[u8]) -> DexResult<Vec<u16>> {
     Ok(buf)
 }
 
+/// Encodes a string to MUTF-8, without null terminator.
 #[allow(clippy::cast_possible_truncation)]
-pub(crate) fn _encode(s: &str) -> Vec<u8> {
+#[must_use]
+pub fn encode(s: &str) -> Vec<u8> {
     let mut buf = Vec::new();
 
-    for ch in s.chars() {
-        let c = ch as u16;
+    // supplementary characters are encoded as surrogate pairs
+    for c in s.encode_utf16() {
         if c != 0 && c <= 127 {
             // U+0000 uses two bytes
             buf.push(c as u8);
@@ -83,3 +101,25 @@ pub(crate) fn _encode(s: &str) -> Vec<u8> {
 
     buf
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn astral_character() {
+        let encoded = encode("a\u{1f600}");
+        assert_eq!(encoded, [0x61, 0xed, 0xa0, 0xbd, 0xed, 0xb8, 0x80]);
+        assert_eq!(decode(&encoded).unwrap(), "a\u{1f600}");
+        // an isolated surrogate is not a valid string
+        assert!(decode(&encoded[..4]).is_err());
+    }
+
+    #[test]
+    fn embedded_null() {
+        let encoded = encode("a\0b");
+        assert_eq!(encoded, [0x61, 0xc0, 0x80, 0x62]);
+        assert_eq!(decode(&encoded).unwrap(), "a\0b");
+        assert!(decode(&[0x61, 0x00, 0x62]).is_err());
+    }
+}
@@ -16,7 +16,7 @@ use dw_utils::leb::{Sleb128, Uleb128};
 use nom::bits::complete::take as take_bits;
 use nom::bits::{bits, bytes};
 use nom::branch::alt;
-use nom::bytes::complete::{tag, take_till, take_until};
+use nom::bytes::complete::{tag, take_until, take_while_m_n};
 use nom::character::complete::digit1;
 use nom::combinator::{cond, map, value, verify};
 use nom::error::{ErrorKind, ParseError};
@@ -28,6 +28,7 @@ use nom::Err::Error;
 use nom::Finish;
 use nom::{IResult, Offset};
 use sha1::{Digest, Sha1};
+use std::collections::btree_map::Entry;
 use std::collections::BTreeSet;
 use std::convert::TryFrom;
 use std::sync::RwLock;
@@ -43,62 +44,30 @@ const NO_INDEX: u32 = 0xFFFF_FFFF;
 pub fn parse_dex(input: &[u8]) -> DexResult<Dex> {
     log::trace!("parsing dex...");
 
+    if input.starts_with(b"cdex") {
+        return parse_compact_dex(input);
+    }
+
     // parsing header
     let (rest, header) = header_item_parser(input).finish()?;
     let mut cursor = input.offset(rest);
     if cursor != 0x70 {
         return Err(DexError::BadSize("header".to_string()));
     }
-    log::debug!("Dex version:  {}", header.version);
-    log::debug!("Checksum:     {:x}", header.checksum);
-    let checksum = adler32::adler32(&input[12..])?;
-    if checksum != header.checksum {
-        log::warn!("invalid checksum");
-        log::warn!("    expected: {:x}", header.checksum);
-        log::warn!("    computed: {:x}", checksum);
-    }
-    log::debug!("Signature:    {}", hexlify(&header.signature));
-    let mut hasher = Sha1::new();
-    hasher.update(&input[32..]);
-    let signature = hasher.finalize();
-    if hexlify(&signature) != hexlify(&header.signature) {
-        log::warn!("invalid signature");
-        log::warn!("    expected: {}", hexlify(&header.signature));
-        log::warn!("    computed: {}", hexlify(&signature));
-    }
-    log::debug!("File size:    {} bytes", header.file_size);
-    log::debug!("Endianness:   {:?} endian", header.endianness);
-    log::debug!("Map offset:   {}", header.map_off);
+    check_header(input, &header)?;
 
     // parsing map_list
     if input.len() < header.map_off {
         return Err(DexError::InvalidOffset("map_list".to_string()));
     }
     let (_, map_list) = map_list_parser(header.map_off, &input[header.map_off..]).finish()?;
-    for item in &map_list.list {
-        log::debug!(
-            "found {}, offset={:#x}, size={:#x}",
-            item.typ,
-            item.offset,
-            item.size
-        );
-    }
+    check_map_list(&map_list)?;
 
     let mut dex = Dex::new(0);
     dex.header_item = header;
-    dex.map_list = map_list;
-
-    // checking for duplicates
-    let mut map: BTreeSet<MapItemType> = BTreeSet::new();
-    for item in &dex.map_list.list {
-        if map.contains(&item.typ) {
-            return Err(DexError::Structure("duplicate map entry".to_string()));
-        }
-        map.insert(item.typ);
-    }
 
     // reverse map_list to pop and check items
-    let mut sections: Vec<_> = dex.map_list.list.iter().rev().collect();
+    let mut sections: Vec<_> = map_list.list.iter().rev().collect();
 
     // header already parsed, just checking metadata
     if let Some(section_descr) = sections.pop() {
@@ -265,116 +234,359 @@ pub fn parse_dex(input: &[u8]) -> DexResult<Dex> {
 
     // parsing unordered 'data' sections
     while let Some(section_descr) = sections.pop() {
+        if section_descr.typ == MapItemType::CodeItem {
+            dex.code_items =
+                parse_data_section(input, &mut cursor, section_descr, true, code_item_parser)?;
+        } else {
+            parse_data_item_section(&mut dex, input, &mut cursor, section_descr)?;
+        }
+    }
+
+    // final padding
+    while cursor < input.len() {
+        if input[cursor] != 0x0 {
+            return Err(DexError::NonZeroPadding);
+        }
+        cursor += 1;
+    }
+
+    dex.map_list = map_list;
+    Ok(dex)
+}
+
+/// Compact dex parsing function.
+///
+/// Compact dex files are produced by ART inside vdex containers: their data
+/// offsets are relative to a data section that may be shared by several
+/// dex files, code items are packed behind a variable-length preheader and
+/// debug info offsets are stored in a table indexed by method. They are
+/// lowered into the regular [`Dex`] structure, which cannot be written back.
+fn parse_compact_dex(input: &[u8]) -> DexResult<Dex> {
+    // parsing header
+    let (rest, (header, compact)) = compact_header_item_parser(input).finish()?;
+    let mut cursor = input.offset(rest);
+    if cursor != 0x88 {
+        return Err(DexError::BadSize("header".to_string()));
+    }
+    check_header(input, &header)?;
+
+    // data items offsets are relative to the data section
+    let data = header
+        .data_off
+        .checked_add(header.data_size)
+        .and_then(|end| input.get(header.data_off..end))
+        .ok_or_else(|| DexError::InvalidOffset("data section".to_string()))?;
+    if data.len() < header.map_off {
+        return Err(DexError::InvalidOffset("map_list".to_string()));
+    }
+    let (_, map_list) = map_list_parser(header.map_off, &data[header.map_off..]).finish()?;
+    check_map_list(&map_list)?;
+
+    let mut dex = Dex::new(0);
+    dex.header_item = header;
+
+    // parsing 'core' sections, whose offsets are relative to the file
+    let header = &dex.header_item;
+    let descr = |typ, size, offset| MapItem { typ, size, offset };
+    dex.string_id_items = parse_id_section(
+        input,
+        &mut cursor,
+        &descr(
+            MapItemType::StringIdItem,
+            header.string_ids_size,
+            header.string_ids_off,
+        ),
+        0x4,
+        string_id_item_parser,
+    )?;
+    dex.type_id_items = parse_id_section(
+        input,
+        &mut cursor,
+        &descr(
+            MapItemType::TypeIdItem,
+            header.type_ids_size,
+            header.type_ids_off,
+        ),
+        0x4,
+        type_id_item_parser,
+    )?;
+    dex.proto_id_items = parse_id_section(
+        input,
+        &mut cursor,
+        &descr(
+            MapItemType::ProtoIdItem,
+            header.proto_ids_size,
+            header.proto_ids_off,
+        ),
+        0xc,
+        proto_id_item_parser,
+    )?;
+    dex.field_id_items = parse_id_section(
+        input,
+        &mut cursor,
+        &descr(
+            MapItemType::FieldIdItem,
+            header.field_ids_size,
+            header.field_ids_off,
+        ),
+        0x8,
+        field_id_item_parser,
+    )?;
+    dex.method_id_items = parse_id_section(
+        input,
+        &mut cursor,
+        &descr(
+            MapItemType::MethodIdItem,
+            header.method_ids_size,
+            header.method_ids_off,
+        ),
+        0x8,
+        method_id_item_parser,
+    )?;
+    dex.class_def_items = parse_id_section(
+        input,
+        &mut cursor,
+        &descr(
+            MapItemType::ClassDefItem,
+            header.class_defs_size,
+            header.class_defs_off,
+        ),
+        0x20,
+        class_def_item_parser,
+    )?;
+    for section_descr in &map_list.list {
         match section_descr.typ {
-            MapItemType::MapList => {
-                // drop the result, map_list already parsed
-                let _already_parsed =
-                    parse_data_section(input, &mut cursor, section_descr, false, map_list_parser)?;
-            }
-            MapItemType::TypeList => {
-                dex.type_lists =
-                    parse_data_section(input, &mut cursor, section_descr, true, type_list_parser)?;
-            }
-            MapItemType::AnnotationSetRefList => {
-                dex.annotation_set_ref_lists = parse_data_section(
-                    input,
-                    &mut cursor,
-                    section_descr,
-                    true,
-                    annotation_set_ref_list_parser,
-                )?;
-            }
-            MapItemType::AnnotationSetItem => {
-                dex.annotation_set_items = parse_data_section(
-                    input,
-                    &mut cursor,
-                    section_descr,
-                    true,
-                    annotation_set_item_parser,
-                )?;
-            }
-            MapItemType::ClassDataItem => {
-                dex.class_data_items = parse_data_section(
-                    input,
-                    &mut cursor,
-                    section_descr,
-                    false,
-                    class_data_item_parser,
-                )?;
-            }
-            MapItemType::CodeItem => {
-                dex.code_items =
-                    parse_data_section(input, &mut cursor, section_descr, true, code_item_parser)?;
-            }
-            MapItemType::StringDataItem => {
-                dex.string_data_items = parse_data_section(
-                    input,
-                    &mut cursor,
-                    section_descr,
-                    false,
-                    string_data_item_parser,
-                )?;
-            }
-            MapItemType::DebugInfoItem => {
-                dex.debug_info_items = parse_data_section(
+            MapItemType::CallSiteIdItem => {
+                dex.call_site_id_items = parse_id_section(
                     input,
                     &mut cursor,
                     section_descr,
-                    false,
-                    debug_info_item_parser,
+                    0x4,
+                    call_site_id_item_parser,
                 )?;
             }
-            MapItemType::AnnotationItem => {
-                dex.annotation_items = parse_data_section(
+            MapItemType::MethodHandleItem => {
+                dex.method_handle_items = parse_id_section(
                     input,
                     &mut cursor,
                     section_descr,
-                    false,
-                    annotation_item_parser,
+                    0x8,
+                    method_handle_item_parser,
                 )?;
             }
-            MapItemType::EncodedArrayItem => {
-                dex.encoded_array_items = parse_data_section(
-                    input,
-                    &mut cursor,
-                    section_descr,
-                    false,
-                    encoded_array_item_parser,
-                )?;
-            }
-            MapItemType::AnnotationsDirectoryItem => {
-                dex.annotations_directory_items = parse_data_section(
-                    input,
-                    &mut cursor,
-                    section_descr,
-                    true,
-                    annotations_directory_item_parser,
-                )?;
-            }
-            MapItemType::HiddenapiClassDataItem => {
-                dex.hiddenapi_class_data_items = parse_data_section(
-                    input,
-                    &mut cursor,
-                    section_descr,
-                    false,
-                    hiddenapi_class_data_item_parser,
-                )?;
+            _ => (),
+        }
+    }
+
+    // parsing 'data' sections, sections may be shared with other dex files
+    // so that each one is parsed on its own
+    for section_descr in &map_list.list {
+        match section_descr.typ {
+            MapItemType::HeaderItem
+            | MapItemType::StringIdItem
+            | MapItemType::TypeIdItem
+            | MapItemType::ProtoIdItem
+            | MapItemType::FieldIdItem
+            | MapItemType::MethodIdItem
+            | MapItemType::ClassDefItem
+            | MapItemType::CallSiteIdItem
+            | MapItemType::MethodHandleItem
+            | MapItemType::MapList
+            | MapItemType::CodeItem => (),
+            _ => {
+                let mut cursor = section_descr.offset;
+                parse_data_item_section(&mut dex, data, &mut cursor, section_descr)?;
             }
-            _ => return Err(DexError::InvalidType),
         }
     }
 
-    // final padding
-    while cursor < input.len() {
-        if input[cursor] != 0x0 {
-            return Err(DexError::NonZeroPadding);
+    // code items preheaders cannot be parsed forward, code items are
+    // reached from the methods instead
+    let mut code_items = Map::new();
+    for class_data in dex.class_data_items.values() {
+        for method in class_data
+            .direct_methods
+            .iter()
+            .chain(&class_data.virtual_methods)
+        {
+            let Some(code_off) = method.code_off else {
+                continue;
+            };
+            let code_item = match code_items.entry(code_off.as_usize()) {
+                Entry::Occupied(entry) => entry.into_mut(),
+                Entry::Vacant(entry) => {
+                    let (_, code_item) =
+                        compact_code_item_parser(code_off.as_usize(), data).finish()?;
+                    entry.insert(code_item)
+                }
+            };
+            // code items may be shared by several methods with their own
+            // debug info, the first one is kept
+            if code_item.debug_info_off.is_none() {
+                code_item.debug_info_off =
+                    compact_debug_info_offset(data, &compact, method.method_idx.as_usize())?
+                        .map(Index::new);
+            }
         }
-        cursor += 1;
     }
+    dex.code_items = code_items
+        .into_iter()
+        .map(|(offset, code_item)| (offset, RwLock::new(code_item)))
+        .collect();
 
+    dex.map_list = map_list;
     Ok(dex)
 }
 
+/// Checks the header checksum and signature, a mismatch being only reported.
+fn check_header(input: &[u8], header: &HeaderItem) -> DexResult<()> {
+    log::debug!("Dex version:  {}", header.version);
+    log::debug!("Checksum:     {:x}", header.checksum);
+    let checksum = adler32::adler32(&input[12..])?;
+    if checksum != header.checksum {
+        log::warn!("invalid checksum");
+        log::warn!("    expected: {:x}", header.checksum);
+        log::warn!("    computed: {:x}", checksum);
+    }
+    log::debug!("Signature:    {}", hexlify(&header.signature));
+    let mut hasher = Sha1::new();
+    hasher.update(&input[32..]);
+    let signature = hasher.finalize();
+    if hexlify(&signature) != hexlify(&header.signature) {
+        log::warn!("invalid signature");
+        log::warn!("    expected: {}", hexlify(&header.signature));
+        log::warn!("    computed: {}", hexlify(&signature));
+    }
+    log::debug!("File size:    {} bytes", header.file_size);
+    log::debug!("Endianness:   {:?} endian", header.endianness);
+    log::debug!("Map offset:   {}", header.map_off);
+    Ok(())
+}
+
+fn check_map_list(map_list: &MapList) -> DexResult<()> {
+    for item in &map_list.list {
+        log::debug!(
+            "found {}, offset={:#x}, size={:#x}",
+            item.typ,
+            item.offset,
+            item.size
+        );
+    }
+
+    // checking for duplicates
+    let mut map: BTreeSet<MapItemType> = BTreeSet::new();
+    for item in &map_list.list {
+        if map.contains(&item.typ) {
+            return Err(DexError::Structure("duplicate map entry".to_string()));
+        }
+        map.insert(item.typ);
+    }
+    Ok(())
+}
+
+/// Parses the data items of a section other than the code items, whose
+/// layout depends on the dex flavor.
+fn parse_data_item_section(
+    dex: &mut Dex,
+    input: &[u8],
+    cursor: &mut usize,
+    section_descr: &MapItem,
+) -> DexResult<()> {
+    match section_descr.typ {
+        MapItemType::MapList => {
+            // drop the result, map_list already parsed
+            let _already_parsed =
+                parse_data_section(input, cursor, section_descr, false, map_list_parser)?;
+        }
+        MapItemType::TypeList => {
+            dex.type_lists =
+                parse_data_section(input, cursor, section_descr, true, type_list_parser)?;
+        }
+        MapItemType::AnnotationSetRefList => {
+            dex.annotation_set_ref_lists = parse_data_section(
+                input,
+                cursor,
+                section_descr,
+                true,
+                annotation_set_ref_list_parser,
+            )?;
+        }
+        MapItemType::AnnotationSetItem => {
+            dex.annotation_set_items = parse_data_section(
+                input,
+                cursor,
+                section_descr,
+                true,
+                annotation_set_item_parser,
+            )?;
+        }
+        MapItemType::ClassDataItem => {
+            dex.class_data_items =
+                parse_data_section(input, cursor, section_descr, false, class_data_item_parser)?;
+        }
+        MapItemType::StringDataItem => {
+            dex.string_data_items =
+                parse_data_section(input, cursor, section_descr, false, string_data_item_parser)?;
+        }
+        MapItemType::DebugInfoItem => {
+            dex.debug_info_items =
+                parse_data_section(input, cursor, section_descr, false, debug_info_item_parser)?;
+        }
+        MapItemType::AnnotationItem => {
+            dex.annotation_items =
+                parse_data_section(input, cursor, section_descr, false, annotation_item_parser)?;
+        }
+        MapItemType::EncodedArrayItem => {
+            dex.encoded_array_items = parse_data_section(
+                input,
+                cursor,
+                section_descr,
+                false,
+                encoded_array_item_parser,
+            )?;
+        }
+        MapItemType::AnnotationsDirectoryItem => {
+            dex.annotations_directory_items = parse_data_section(
+                input,
+                cursor,
+                section_descr,
+                true,
+                annotations_directory_item_parser,
+            )?;
+        }
+        MapItemType::HiddenapiClassDataItem => {
+            dex.hiddenapi_class_data_items = parse_data_section(
+                input,
+                cursor,
+                section_descr,
+                false,
+                hiddenapi_class_data_item_parser,
+            )?;
+        }
+        _ => return Err(DexError::InvalidType),
+    }
+    Ok(())
+}
+
+fn parse_id_section<'a, T>(
+    input: &'a [u8],
+    cursor: &mut usize,
+    section_descr: &MapItem,
+    elt_size: usize,
+    parser: impl Fn(usize, &'a [u8]) -> IResult<&[u8], T, DexError>,
+) -> DexResult<Vec<T>> {
+    if section_descr.size == 0 {
+        return Ok(Vec::new());
+    }
+    parse_core_section(
+        input,
+        cursor,
+        section_descr,
+        section_descr,
+        elt_size,
+        parser,
+    )
+}
 fn parse_core_section<'a, T>(
     input: &'a [u8],
     cursor: &mut usize,
@@ -444,6 +656,11 @@ fn parse_data_section<'a, T>(
             section_descr.typ,
         )));
     }
+    if section_descr.offset > input.len() {
+        return Err(DexError::MalformedOffset {
+            off: section_descr.offset,
+        });
+    }
     while *cursor != section_descr.offset {
         if input[*cursor] != 0x0 {
             return Err(DexError::NonZeroPadding);
@@ -454,17 +671,15 @@ fn parse_data_section<'a, T>(
         if align {
             let alignment = (4 - (*cursor % 4)) % 4;
             for _ in 0..alignment {
-                if input[*cursor] != 0x0 {
-                    return Err(DexError::NonZeroPadding);
+                match input.get(*cursor) {
+                    Some(0x0) => *cursor += 1,
+                    Some(_) => return Err(DexError::NonZeroPadding),
+                    None => return Err(DexError::MalformedOffset { off: *cursor }),
                 }
-                *cursor += 1;
             }
         }
         if *cursor >= input.len() {
-            return Err(DexError::InvalidOffset(format!(
-                "{} section",
-                section_descr.typ,
-            )));
+            return Err(DexError::MalformedOffset { off: *cursor });
         }
         let (rest, item) = parser(*cursor, &input[*cursor..]).finish()?;
         items.insert(*cursor, item);
@@ -473,13 +688,17 @@ fn parse_data_section<'a, T>(
     Ok(items)
 }
 
-fn magic_parser(input: &[u8]) -> IResult<&[u8], u32, DexError> {
-    let (input, _) = tag("dex\n")(input)?;
-    let (input, v) = map(verify(digit1, |ds: &[u8]| ds.len() == 3), |vs: &[u8]| {
-        u32::from(vs[0] - 0x30) * 100 + u32::from(vs[1] - 0x30) * 10 + u32::from(vs[2] - 0x30)
-    })(input)?;
-    let (input, _) = tag("\x00")(input)?;
-    Ok((input, v))
+fn magic_parser<'a>(
+    prefix: &'static str,
+) -> impl FnMut(&'a [u8]) -> IResult<&'a [u8], u32, DexError> {
+    move |input| {
+        let (input, _) = tag(prefix)(input)?;
+        let (input, v) = map(verify(digit1, |ds: &[u8]| ds.len() == 3), |vs: &[u8]| {
+            u32::from(vs[0] - 0x30) * 100 + u32::from(vs[1] - 0x30) * 10 + u32::from(vs[2] - 0x30)
+        })(input)?;
+        let (input, _) = tag("\x00")(input)?;
+        Ok((input, v))
+    }
 }
 
 fn endian_tag_parser(input: &[u8]) -> IResult<&[u8], Endianness, DexError> {
@@ -490,11 +709,54 @@ fn endian_tag_parser(input: &[u8]) -> IResult<&[u8], Endianness, DexError> {
 }
 
 fn header_item_parser(input: &[u8]) -> IResult<&[u8], HeaderItem, DexError> {
-    let (input, version) = magic_parser(input)?;
+    let (input, version) = magic_parser("dex\n")(input)?;
+    header_item_fields_parser(input, version, 0x70)
+}
+
+/// Compact dex header extension, locating the debug info offsets table.
+struct CompactHeader {
+    debug_info_offsets_pos: usize,
+    debug_info_offsets_table_offset: usize,
+    debug_info_base: usize,
+}
+
+fn compact_header_item_parser(
+    input: &[u8],
+) -> IResult<&[u8], (HeaderItem, CompactHeader), DexError> {
+    let (input, version) = magic_parser("cdex")(input)?;
+    let (input, mut header) = header_item_fields_parser(input, version, 0x88)?;
+    let (input, feature_flags) = le_u32(input)?;
+    let (input, debug_info_offsets_pos) = le_u32(input)?;
+    let (input, debug_info_offsets_table_offset) = le_u32(input)?;
+    let (input, debug_info_base) = le_u32(input)?;
+    let (input, _owned_data_begin) = le_u32(input)?;
+    let (input, _owned_data_end) = le_u32(input)?;
+
+    log::debug!("Features:     {:#x}", feature_flags);
+    header.compact = true;
+
+    Ok((
+        input,
+        (
+            header,
+            CompactHeader {
+                debug_info_offsets_pos: debug_info_offsets_pos as usize,
+                debug_info_offsets_table_offset: debug_info_offsets_table_offset as usize,
+                debug_info_base: debug_info_base as usize,
+            },
+        ),
+    ))
+}
+
+fn header_item_fields_parser(
+    input: &[u8],
+    version: u32,
+    header_size: u32,
+) -> IResult<&[u8], HeaderItem, DexError> {
     let (input, checksum) = le_u32(input)?;
     let (input, signature) = count(le_u8, 20)(input)?;
     let (input, file_size) = le_u32(input)?;
-    let (input, _header_size) = verify(le_u32, |siz| *siz == 0x70)(input)?;
+    let (input, _header_size) = verify(le_u32, |siz| *siz == header_size)(input)?;
     let (input, endianness) = endian_tag_parser(input)?;
     let (input, link_size) = le_u32(input)?;
     let (input, link_off) = le_u32(input)?;
@@ -543,6 +805,7 @@ fn header_item_parser(input: &[u8]) -> IResult<&[u8], HeaderItem, DexError> {
             class_defs_off: class_defs_off as usize,
             data_size: data_size as usize,
             data_off: data_off as usize,
+            compact: false,
         },
     ))
 }
@@ -920,31 +1183,13 @@ fn code_item_parser(offset: usize, input: &[u8]) -> IResult<&[u8], RwLock<CodeIt
     let (input, debug_info_off) = le_u32(input)?;
     let (input, insns_size) = le_u32(input)?;
 
-    if input.len() < insns_size as usize * 2 {
-        return Err(Error(DexError::from_error_kind(input, ErrorKind::Complete)));
-    }
-
-    let mut insns = Vec::new();
-    let mut addr = 0;
-    let mut insns_buffer = &input[..insns_size as usize * 2];
-    while !insns_buffer.is_empty() {
-        let (rest, instr) = parse_instr(insns_buffer)?;
-        let size = instr.size();
-        insns.push(LabeledInstr {
-            addr: Addr(addr),
-            instr,
-        });
-        addr += size;
-        insns_buffer = rest;
-    }
-
-    let (input, _) = cond(tries_size != 0 && insns_size % 2 == 1, tag("\x00\x00"))(
-        &input[insns_size as usize * 2..],
+    let (input, (insns, tries, handlers)) = code_body_parser(
+        input,
+        insns_size as usize,
+        tries_size as usize,
+        insns_size % 2 == 1,
     )?;
 
-    let (input, tries) = count(try_item_parser, tries_size as usize)(input)?;
-    let (input, handlers) = cond(tries_size != 0, encoded_catch_handler_list_parser)(input)?;
-
     let debug_info_off = if debug_info_off == 0 {
         None
     } else {
@@ -966,6 +1211,153 @@ fn code_item_parser(offset: usize, input: &[u8]) -> IResult<&[u8], RwLock<CodeIt
     ))
 }
 
+const CDEX_PREHEADER_REGISTERS_SIZE: u16 = 0x1;
+const CDEX_PREHEADER_INS_SIZE: u16 = 0x2;
+const CDEX_PREHEADER_OUTS_SIZE: u16 = 0x4;
+const CDEX_PREHEADER_TRIES_SIZE: u16 = 0x8;
+const CDEX_PREHEADER_INSNS_SIZE: u16 = 0x10;
+
+/// Parses a compact dex code item. Sizes are packed in 4 bits each (11 bits
+/// for the instructions count), larger values being extended by deltas
+/// stored backward in the preheader that precedes the code item.
+fn compact_code_item_parser(offset: usize, data: &[u8]) -> IResult<&[u8], CodeItem, DexError> {
+    let offset = offset.min(data.len());
+    let (input, fields) = le_u16(&data[offset..])?;
+    let (input, insns_count_and_flags) = le_u16(input)?;
+
+    let mut preheader = data[..offset]
+        .rchunks_exact(2)
+        .map(|unit| usize::from(u16::from_le_bytes([unit[0], unit[1]])));
+    let mut extension = |flag: u16| {
+        if insns_count_and_flags & flag == 0 {
+            Ok(0)
+        } else {
+            preheader
+                .next()
+                .ok_or_else(|| Error(DexError::InvalidOffset("code item preheader".to_string())))
+        }
+    };
+    let mut insns_size = usize::from(insns_count_and_flags >> 5);
+    insns_size += extension(CDEX_PREHEADER_INSNS_SIZE)?;
+    insns_size += extension(CDEX_PREHEADER_INSNS_SIZE)? << 16;
+    // the registers_size field does not count the ins
+    let registers_size = usize::from(fields >> 12) + extension(CDEX_PREHEADER_REGISTERS_SIZE)?;
+    let ins_size = usize::from((fields >> 8) & 0xf) + extension(CDEX_PREHEADER_INS_SIZE)?;
+    let outs_size = usize::from((fields >> 4) & 0xf) + extension(CDEX_PREHEADER_OUTS_SIZE)?;
+    let tries_size = usize::from(fields & 0xf) + extension(CDEX_PREHEADER_TRIES_SIZE)?;
+
+    // code items are only 2-byte aligned, tries still are 4-byte aligned
+    let (input, (insns, tries, handlers)) = code_body_parser(
+        input,
+        insns_size,
+        tries_size,
+        !(offset + 4 + insns_size * 2).is_multiple_of(4),
+    )?;
+
+    Ok((
+        input,
+        CodeItem {
+            index: Index::new(offset),
+            registers_size: registers_size + ins_size,
+            ins_size,
+            outs_size,
+            debug_info_off: None,
+            insns,
+            tries,
+            handlers,
+        },
+    ))
+}
+
+/// Looks up the debug info offset of a method in the compact dex offsets
+/// table. The table holds an offset per block of 16 methods, each block
+/// starting with a big-endian bitmask of the methods having debug info,
+/// followed by the uleb128 deltas of their offsets.
+fn compact_debug_info_offset(
+    data: &[u8],
+    header: &CompactHeader,
+    method_idx: usize,
+) -> DexResult<Option<usize>> {
+    let invalid = || DexError::InvalidOffset("debug info offsets table".to_string());
+    let table = data
+        .get(header.debug_info_offsets_pos..)
+        .ok_or_else(invalid)?;
+    let entry = header.debug_info_offsets_table_offset + (method_idx / 16) * 4;
+    let (_, block_off) = le_u32::<_, DexError>(table.get(entry..).ok_or_else(invalid)?).finish()?;
+    let block = table.get(block_off as usize..).ok_or_else(invalid)?;
+    let (mut block, bitmask) = be_u16::<_, DexError>(block).finish()?;
+
+    let bit = method_idx % 16;
+    if bitmask & (1 << bit) == 0 {
+        return Ok(None);
+    }
+    let mut offset = header.debug_info_base;
+    for _ in 0..(u32::from(bitmask) & ((2 << bit) - 1)).count_ones() {
+        let (rest, delta) = uleb128(block).finish()?;
+        offset += delta.value() as usize;
+        block = rest;
+    }
+    Ok(Some(offset))
+}
+
+type CodeBody = (
+    Vec<LabeledInstr>,
+    Vec<TryItem>,
+    Option<EncodedCatchHandlerList>,
+);
+
+/// Parses the instructions, try items and handlers of a code item, `padded`
+/// telling whether tries are preceded by a padding code unit.
+fn code_body_parser(
+    input: &[u8],
+    insns_size: usize,
+    tries_size: usize,
+    padded: bool,
+) -> IResult<&[u8], CodeBody, DexError> {
+    if input.len() < insns_size * 2 {
+        return Err(Error(DexError::from_error_kind(input, ErrorKind::Complete)));
+    }
+
+    let mut insns = Vec::new();
+    let mut addr = 0;
+    let mut insns_buffer = &input[..insns_size * 2];
+    while !insns_buffer.is_empty() {
+        // payload pseudo-instructions are identified by a whole code unit
+        let opcode = match insns_buffer {
+            [0x00, ident, ..] => u16::from(*ident) << 8,
+            [op, ..] => u16::from(*op),
+            [] => unreachable!(),
+        };
+        let size_mismatch = || {
+            Error(DexError::InstructionSizeMismatch {
+                addr: Addr(addr),
+                opcode,
+            })
+        };
+        let (rest, instr) = parse_instr(insns_buffer).map_err(|err| match err {
+            Error(DexError::Parsing(_, ErrorKind::Eof)) => size_mismatch(),
+            err => err,
+        })?;
+        let size = instr.size();
+        if insns_buffer.offset(rest) != size * 2 {
+            return Err(size_mismatch());
+        }
+        insns.push(LabeledInstr {
+            addr: Addr(addr),
+            instr,
+        });
+        addr += size;
+        insns_buffer = rest;
+    }
+
+    let (input, _) = cond(tries_size != 0 && padded, tag("\x00\x00"))(&input[insns_size * 2..])?;
+
+    let (input, tries) = count(try_item_parser, tries_size)(input)?;
+    let (input, handlers) = cond(tries_size != 0, encoded_catch_handler_list_parser)(input)?;
+
+    Ok((input, (insns, tries, handlers)))
+}
+
 fn try_item_parser(input: &[u8]) -> IResult<&[u8], TryItem, DexError> {
     let (input, start_addr) = le_u32(input)?;
     let (input, insn_count) = le_u16(input)?;
@@ -1072,8 +1464,8 @@ fn debug_bytecode_parser(input: &[u8]) -> IResult<&[u8], Vec<DbgInstr>, DexError
     let mut inp = input;
     let mut bc = Vec::new();
     loop {
-        let opcode = inp[0];
-        inp = &inp[1..];
+        let (i, opcode) = le_u8(inp)?;
+        inp = i;
         match opcode {
             0x00 => {
                 bc.push(DbgInstr::EndSequence);
@@ -1427,10 +1819,14 @@ fn hiddenapi_class_data_item_parser(
     let mut min_flag_offset = std::u32::MAX;
     let mut input = input;
     loop {
-        let off = input.offset(start);
-        if off == min_flag_offset as usize {
+        let off = start.offset(input);
+        let end = min_flag_offset.min(size) as usize;
+        if off == end {
             break;
         }
+        if off > end {
+            return Err(Error(DexError::MalformedOffset { off }));
+        }
         let (inp, offset) = le_u32(input)?;
         offsets.push(Index::new(offset as usize));
         input = inp;
@@ -1441,10 +1837,13 @@ fn hiddenapi_class_data_item_parser(
 
     let mut flags = Map::new();
     loop {
-        let off = input.offset(start);
+        let off = start.offset(input);
         if off == size as usize {
             break;
         }
+        if off > size as usize {
+            return Err(Error(DexError::MalformedOffset { off }));
+        }
         let (inp, uleb_repr) = uleb128(input)?;
         let flag = HiddenapiFlag::try_from(uleb_repr.value()).map_err(Error)?;
         flags.insert(off, HiddenapiClassFlag { uleb_repr, flag });
@@ -2085,7 +2484,12 @@ fn parse_pseudo_instr(input: &[u8]) -> IResult<&[u8], Instr, DexError> {
             let (i, data) = count(count(le_u8, element_width as usize), size as usize)(i)?;
             let expect_size = ((size as usize * element_width as usize + 1) / 2 + 3) * 2;
             let parsed_size = input.offset(i);
-            let padd_offset = expect_size - parsed_size;
+            let padd_offset = expect_size
+                .checked_sub(parsed_size)
+                .ok_or_else(|| Error(DexError::from_error_kind(i, ErrorKind::Verify)))?;
+            if i.len() < padd_offset {
+                return Err(Error(DexError::from_error_kind(i, ErrorKind::Eof)));
+            }
             Ok((&i[padd_offset..], Instr::FillArrayDataPayload(data)))
         }
         _ => Err(Error(DexError::from_error_kind(input, ErrorKind::Switch))),
@@ -2290,8 +2694,8 @@ fn parse_51l(input: &[u8]) -> IResult<&[u8], (u8, i64), DexError> {
 
 fn uleb128(input: &[u8]) -> IResult<&[u8], Uleb128, DexError> {
     let start = input;
-    let (input, bs) = verify(take_till(|b| b & 0x80 == 0), |bs: &[u8]| bs.len() < 5)(input)?;
-    let (input, b) = map(le_u8, u32::from)(input)?;
+    let (input, bs) = leb128_continuation(input)?;
+    let (input, b) = map(verify(le_u8, |b| b & 0x80 == 0), u32::from)(input)?;
 
     let res = bs
         .iter()
@@ -2300,6 +2704,12 @@ fn uleb128(input: &[u8]) -> IResult<&[u8], Uleb128, DexError> {
     Ok((input, Uleb128::new(res, Some(start.offset(input)))))
 }
 
+/// Takes the continuation bytes of a LEB128 value, never reading more than
+/// the 4 bytes that a 32-bit value may need before its last byte.
+fn leb128_continuation(input: &[u8]) -> IResult<&[u8], &[u8], DexError> {
+    take_while_m_n(0, 4, |b| b & 0x80 != 0)(input)
+}
+
 fn uleb128p1(input: &[u8]) -> IResult<&[u8], Option<u32>, DexError> {
     map(uleb128, |x| {
         if x.value() == 0 {
@@ -2313,8 +2723,8 @@ fn uleb128p1(input: &[u8]) -> IResult<&[u8], Option<u32>, DexError> {
 #[allow(clippy::cast_possible_wrap)]
 fn sleb128(input: &[u8]) -> IResult<&[u8], Sleb128, DexError> {
     let start = input;
-    let (input, bs) = verify(take_till(|b| b & 0x80 == 0), |bs: &[u8]| bs.len() < 5)(input)?;
-    let (input, b) = map(le_u8, u32::from)(input)?;
+    let (input, bs) = leb128_continuation(input)?;
+    let (input, b) = map(verify(le_u8, |b| b & 0x80 == 0), u32::from)(input)?;
 
     let is_neg = b & 0x40 != 0;
     let mut res = bs
@@ -2349,9 +2759,15 @@ fn le_u16_on(siz: usize) -> impl Fn(&[u8]) -> IResult<&[u8], u16, DexError> {
     }
 }
 
+/// Signed values are sign-extended from their most significant byte.
 #[allow(clippy::cast_possible_wrap)]
 fn le_i16_on(siz: usize) -> impl Fn(&[u8]) -> IResult<&[u8], i16, DexError> {
-    move |input: &[u8]| map(le_u16_on(siz), |value| value as i16)(input)
+    move |input: &[u8]| {
+        let shift = 16 - 8 * siz;
+        map(le_u16_on(siz), move |value| {
+            (value << shift) as i16 >> shift
+        })(input)
+    }
 }
 
 fn le_u32_on(siz: usize) -> impl Fn(&[u8]) -> IResult<&[u8], u32, DexError> {
@@ -2370,7 +2786,12 @@ fn le_u32_on(siz: usize) -> impl Fn(&[u8]) -> IResult<&[u8], u32, DexError> {
 
 #[allow(clippy::cast_possible_wrap)]
 fn le_i32_on(siz: usize) -> impl Fn(&[u8]) -> IResult<&[u8], i32, DexError> {
-    move |input: &[u8]| map(le_u32_on(siz), |value| value as i32)(input)
+    move |input: &[u8]| {
+        let shift = 32 - 8 * siz;
+        map(le_u32_on(siz), move |value| {
+            (value << shift) as i32 >> shift
+        })(input)
+    }
 }
 
 fn le_u64_on(siz: usize) -> impl Fn(&[u8]) -> IResult<&[u8], u64, DexError> {
@@ -2389,22 +2810,35 @@ fn le_u64_on(siz: usize) -> impl Fn(&[u8]) -> IResult<&[u8], u64, DexError> {
 
 #[allow(clippy::cast_possible_wrap)]
 fn le_i64_on(siz: usize) -> impl Fn(&[u8]) -> IResult<&[u8], i64, DexError> {
-    move |input: &[u8]| map(le_u64_on(siz), |value| value as i64)(input)
+    move |input: &[u8]| {
+        let shift = 64 - 8 * siz;
+        map(le_u64_on(siz), move |value| {
+            (value << shift) as i64 >> shift
+        })(input)
+    }
 }
 
-#[allow(clippy::cast_precision_loss)]
+/// Floating point values are right zero-extended: the stored bytes are the
+/// most significant bytes of the IEEE 754 representation.
 fn le_f32_on(siz: usize) -> impl Fn(&[u8]) -> IResult<&[u8], f32, DexError> {
-    move |input: &[u8]| map(le_u32_on(siz), |value| value as f32)(input)
+    move |input: &[u8]| {
+        let shift = 32 - 8 * siz;
+        map(le_u32_on(siz), move |value| f32::from_bits(value << shift))(input)
+    }
 }
 
-#[allow(clippy::cast_precision_loss)]
 fn le_f64_on(siz: usize) -> impl Fn(&[u8]) -> IResult<&[u8], f64, DexError> {
-    move |input: &[u8]| map(le_u64_on(siz), |value| value as f64)(input)
+    move |input: &[u8]| {
+        let shift = 64 - 8 * siz;
+        map(le_u64_on(siz), move |value| f64::from_bits(value << shift))(input)
+    }
 }
 
 #[cfg(test)]
 mod tests {
     use super::*;
+    use crate::testing::{push_u16, push_u32, DexBuilder, ACC_PUBLIC};
+    use crate::PrettyPrinter;
 
     #[test]
     fn uleb128_parser() {
@@ -2412,6 +2846,8 @@ mod tests {
         assert_eq!(1, uleb128(&[0x01]).unwrap().1.value());
         assert_eq!(127, uleb128(&[0x7f]).unwrap().1.value());
         assert_eq!(16256, uleb128(&[0x80, 0x7f]).unwrap().1.value());
+        assert!(uleb128(&[0x80, 0x80, 0x80, 0x80, 0x80, 0x00]).is_err());
+        assert!(uleb128(&[0x80, 0x80]).is_err());
     }
 
     #[test]
@@ -2438,4 +2874,270 @@ mod tests {
         let r2 = le_u32_on(3)(&input[..3]).unwrap().1;
         assert_eq!(r1, r2);
     }
+
+    #[test]
+    fn static_values_decoding() {
+        let mut dex = Dex::new(35);
+        dex.string_data_items.insert(
+            0x100,
+            StringDataItem {
+                index: Index::new(0x100),
+                utf16_size: Uleb128::new(7, None),
+                data: b"http://".to_vec(),
+            },
+        );
+        dex.string_id_items.push(StringIdItem {
+            index: Index::new(0),
+            string_data_off: Index::new(0x100),
+        });
+        let values = [
+            0x05, // size
+            0x17, 0x00, // VALUE_STRING "http://"
+            0x04, 0x2a, // VALUE_INT 42
+            0x24, 0xfe, 0xff, // VALUE_INT -2, sign-extended
+            0x30, 0xc0, 0x3f, // VALUE_FLOAT 1.5f, right zero-extended
+            0x1e, // VALUE_NULL
+        ];
+        let (_, item) = encoded_array_item_parser(0x200, &values).unwrap();
+        dex.encoded_array_items.insert(0x200, item);
+        let class = ClassDefItem {
+            index: Index::new(0),
+            class_idx: Index::new(0),
+            access_flags: ClassFlags::ACC_PUBLIC,
+            superclass_idx: None,
+            interfaces_off: None,
+            source_file_idx: None,
+            annotations_off: None,
+            class_data_off: None,
+            static_values_off: Some(Index::new(0x200)),
+        };
+
+        let values = class.static_values(&dex).unwrap();
+        assert!(matches!(&values[0], Value::String(s) if s == "http://"));
+        assert!(matches!(values[1], Value::Int(42)));
+        assert!(matches!(values[2], Value::Int(-2)));
+        assert!(matches!(values[3], Value::Float(v) if v == 1.5));
+        assert!(matches!(values[4], Value::Null));
+        assert_eq!(
+            PrettyPrinter(&Value::Array(values), &dex).to_string(),
+            "[\"http://\", 42, -2, 1.5, null]"
+        );
+    }
+
+    fn code_item_bytes(insns: &[u16]) -> Vec<u8> {
+        let mut input = vec![1, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0];
+        input.extend_from_slice(&(insns.len() as u32).to_le_bytes());
+        for unit in insns {
+            input.extend_from_slice(&unit.to_le_bytes());
+        }
+        input
+    }
+
+    #[test]
+    fn code_item_parser_sizes() {
+        // nop; packed-switch-payload (1 target); return-void
+        let input = code_item_bytes(&[0x0000, 0x0100, 1, 0, 0, 3, 0, 0x000e]);
+        let code = code_item_parser(0, &input).unwrap().1.into_inner().unwrap();
+        let addrs: Vec<_> = code.iter_instructions().map(|i| i.addr().0).collect();
+        assert_eq!(addrs, vec![0, 1, 7]);
+    }
+
+    #[test]
+    fn code_item_parser_truncated_payload() {
+        // nop; packed-switch-payload declaring 4 targets but only holding 1
+        let input = code_item_bytes(&[0x0000, 0x0100, 4, 0, 0, 3, 0]);
+        assert!(matches!(
+            code_item_parser(0, &input),
+            Err(Error(DexError::InstructionSizeMismatch {
+                addr: Addr(1),
+                opcode: 0x0100,
+            }))
+        ));
+
+        // return-void; const-string/jumbo missing its last code unit
+        let input = code_item_bytes(&[0x000e, 0x001b, 0]);
+        assert!(matches!(
+            code_item_parser(0, &input),
+            Err(Error(DexError::InstructionSizeMismatch {
+                addr: Addr(1),
+                opcode: 0x1b,
+            }))
+        ));
+    }
+
+    /// Assembles a compact dex declaring `A.<init>()`, whose code item
+    /// registers size is extended by its preheader and whose debug info is
+    /// found through the offsets table.
+    fn compact_dex() -> Vec<u8> {
+        // string ids, type ids, proto ids, method ids and class defs
+        let ids_size = 4 * 4 + 3 * 4 + 12 + 8 + 32;
+        let data_off = 0x88 + ids_size;
+
+        // data offsets are relative to the data section
+        let mut data = Vec::new();
+        let mut string_offs = Vec::new();
+        for string in ["<init>", "LA;", "Ljava/lang/Object;", "V"] {
+            string_offs.push(data.len() as u32);
+            data.push(string.len() as u8);
+            data.extend_from_slice(string.as_bytes());
+            data.push(0);
+        }
+        let debug_off = data.len() as u32;
+        data.extend_from_slice(&[3, 0, 0x0e, 0]); // line 3, one position entry
+        data.resize(data.len().next_multiple_of(2), 0);
+        let code_section_off = data.len() as u32;
+        push_u16(&mut data, 16); // preheader: registers_size delta
+        let code_off = data.len() as u32;
+        push_u16(&mut data, 0x3100); // registers_size (without ins), ins_size
+        push_u16(&mut data, 1 << 5 | CDEX_PREHEADER_REGISTERS_SIZE);
+        push_u16(&mut data, 0x000e); // return-void
+        let class_data_off = data.len() as u32;
+        data.extend_from_slice(&[0, 0, 1, 0, 0, 0x81, 0x80, 0x04, code_off as u8]);
+        data.resize(data.len().next_multiple_of(4), 0);
+        let debug_info_offsets_pos = data.len() as u32;
+        data.extend_from_slice(&[0x00, 0x01, debug_off as u8, 0]); // method 0 only
+        push_u32(&mut data, 0); // single block
+        let map_off = data.len() as u32;
+        let map = [
+            (0x0000, 1, 0),
+            (0x0001, 4, 0x88),
+            (0x0002, 3, 0x98),
+            (0x0003, 1, 0xa4),
+            (0x0005, 1, 0xb0),
+            (0x0006, 1, 0xb8),
+            (0x2002, 4, string_offs[0]),
+            (0x2003, 1, debug_off),
+            (0x2001, 1, code_section_off),
+            (0x2000, 1, class_data_off),
+            (0x1000, 1, map_off),
+        ];
+        push_u32(&mut data, map.len() as u32);
+        for (typ, size, off) in map {
+            push_u16(&mut data, typ);
+            push_u16(&mut data, 0);
+            push_u32(&mut data, size);
+            push_u32(&mut data, off);
+        }
+
+        let mut dex = b"cdex001\0".to_vec();
+        push_u32(&mut dex, 0); // checksum
+        dex.extend_from_slice(&[0; 20]); // signature
+        push_u32(&mut dex, data_off + data.len() as u32);
+        push_u32(&mut dex, 0x88);
+        push_u32(&mut dex, 0x1234_5678);
+        push_u32(&mut dex, 0); // link_size
+        push_u32(&mut dex, 0); // link_off
+        push_u32(&mut dex, map_off);
+        for (size, off) in [
+            (4, 0x88),
+            (3, 0x98),
+            (1, 0xa4),
+            (0, 0),
+            (1, 0xb0),
+            (1, 0xb8),
+        ] {
+            push_u32(&mut dex, size);
+            push_u32(&mut dex, off);
+        }
+        push_u32(&mut dex, data.len() as u32);
+        push_u32(&mut dex, data_off);
+        push_u32(&mut dex, 0); // feature_flags
+        push_u32(&mut dex, debug_info_offsets_pos);
+        push_u32(&mut dex, 4); // debug_info_offsets_table_offset
+        push_u32(&mut dex, 0); // debug_info_base
+        push_u32(&mut dex, 0); // owned_data_begin
+        push_u32(&mut dex, data.len() as u32); // owned_data_end
+
+        for off in string_offs {
+            push_u32(&mut dex, off);
+        }
+        push_u32(&mut dex, 1); // LA;
+        push_u32(&mut dex, 2); // Ljava/lang/Object;
+        push_u32(&mut dex, 3); // V
+        push_u32(&mut dex, 3); // shorty "V"
+        push_u32(&mut dex, 2); // returns V
+        push_u32(&mut dex, 0); // no parameters
+        push_u16(&mut dex, 0); // A
+        push_u16(&mut dex, 0); // ()V
+        push_u32(&mut dex, 0); // <init>
+        for v in [0, 1, 1, 0, NO_INDEX, 0, class_data_off, 0] {
+            push_u32(&mut dex, v);
+        }
+        dex.extend(data);
+        dex
+    }
+
+    #[test]
+    fn parse_compact_dex() {
+        let dex = parse_dex(&compact_dex()).unwrap();
+        assert!(dex.is_compact());
+
+        let classes: Vec<_> = dex
+            .iter_class_defs()
+            .map(|class| class.class_name(&dex).unwrap())
+            .collect();
+        assert_eq!(classes, vec!["A"]);
+        let class = dex.iter_class_defs().next().unwrap();
+        assert_eq!(
+            class.superclass(&dex).unwrap().as_deref(),
+            Some("java/lang/Object")
+        );
+
+        let data = class.data(&dex).unwrap().unwrap();
+        let method = data.iter_methods().next().unwrap();
+        assert_eq!(
+            method.descriptor(&dex).unwrap().name(&dex).unwrap(),
+            "<init>"
+        );
+        let code = method.code(&dex).unwrap().unwrap().read().unwrap();
+        assert_eq!(code.registers_size, 20);
+        assert_eq!(code.ins_size, 1);
+        assert_eq!(code.instructions_count(), 1);
+        let lines: Vec<_> = code
+            .debug_info(&dex)
+            .unwrap()
+            .unwrap()
+            .iter_line_entries()
+            .collect();
+        assert_eq!(lines, vec![(Addr(0), 3)]);
+
+        assert!(matches!(
+            crate::write(&dex, &crate::WriteOptions::default()),
+            Err(DexError::UnsupportedCompactDexWrite)
+        ));
+    }
+
+    #[test]
+    fn truncated_and_corrupted_inputs() {
+        let mut builder = DexBuilder::new();
+        let class = builder.class("LTest;", "Ljava/lang/Object;");
+        builder.source_file(class, "Test.java");
+        let field = builder.field("LTest;", "count", "I");
+        builder.instance_field(class, field);
+        let run = builder.method("LTest;", "run", "V", &[]);
+        builder.code(class, run, ACC_PUBLIC, 1, 1, 0, &[0x0000, 0x000e]);
+        builder.catch(class, run, 0, 1, &[("Ljava/lang/Exception;", 1)], Some(1));
+        builder.lines(class, run, &[(0, 1), (1, 2)]);
+        let input = builder.build();
+
+        for len in 0..input.len() {
+            assert!(crate::parse(&input[..len]).is_err(), "truncated at {len}");
+        }
+        // corrupted inputs may still parse, but must never panic; the
+        // magic, checksum and signature are left untouched
+        for i in 0x20..input.len() {
+            for value in [0x00, 0x7f, 0x80, 0xff] {
+                let mut corrupted = input.clone();
+                corrupted[i] = value;
+                let _ = crate::parse(&corrupted);
+            }
+        }
+        for i in 0x20..input.len() - 4 {
+            for value in [0xffff_fff0u32, input.len() as u32] {
+                let mut corrupted = input.clone();
+                corrupted[i..i + 4].copy_from_slice(&value.to_le_bytes());
+                let _ = crate::parse(&corrupted);
+            }
+        }
+    }
 }
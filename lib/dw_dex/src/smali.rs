@@ -0,0 +1,822 @@
+//! Smali output of Dalvik classes.
+//!
+//! Classes are written in the syntax of `baksmali`, so that they can be
+//! reassembled with `smali`. Labels are named after their kind and the
+//! address they point to (such as `:cond_1a`), and registers are written
+//! as `vN` registers along with the `.registers` directive. Annotations
+//! and local variables debug informations are not written.
+
+use crate::classes::ClassDefItem;
+use crate::code::{CallSiteIdItem, CodeItem, MethodHandle, MethodHandleItem};
+use crate::errors::{DexError, DexResult};
+use crate::fields::{EncodedField, FieldIdItem};
+use crate::instrs::{Instr, Instruction};
+use crate::methods::{EncodedMethod, MethodFlags};
+use crate::registers::RegList;
+use crate::types::Type;
+use crate::values::Value;
+use crate::{Addr, Dex, DexIndex, PrettyPrinter};
+use std::collections::{BTreeMap, BTreeSet};
+use std::fmt::{self, Write};
+
+/// Returns the smali source of the given class.
+pub fn class_to_smali(class: &ClassDefItem, dex: &Dex) -> DexResult<String> {
+    let mut out = String::new();
+    writeln!(
+        out,
+        ".class {}{}",
+        flags(&class.flags()),
+        class.class_idx.get(dex)?.to_type(dex)?
+    )?;
+    if let Some(superclass) = class.superclass_idx {
+        writeln!(out, ".super {}", superclass.get(dex)?.to_type(dex)?)?;
+    }
+    if let Some(source) = class.source_file(dex)? {
+        writeln!(out, ".source {}", string(&source))?;
+    }
+    let interfaces = class.interfaces(dex)?;
+    if !interfaces.is_empty() {
+        write!(out, "\n# interfaces\n")?;
+        for interface in interfaces {
+            writeln!(out, ".implements L{interface};")?;
+        }
+    }
+
+    let Some(data) = class.data(dex)? else {
+        return Ok(out);
+    };
+    let static_values = match class.static_values_off {
+        Some(off) => off.get(dex)?.value.decode(dex)?,
+        None => Vec::new(),
+    };
+    if !data.static_fields.is_empty() {
+        write!(out, "\n\n# static fields\n")?;
+        for (i, field) in data.iter_static_fields().enumerate() {
+            write_field(&mut out, field, static_values.get(i), dex)?;
+        }
+    }
+    if !data.instance_fields.is_empty() {
+        write!(out, "\n\n# instance fields\n")?;
+        for field in data.iter_instance_fields() {
+            write_field(&mut out, field, None, dex)?;
+        }
+    }
+    for (title, methods) in [
+        ("direct methods", &data.direct_methods),
+        ("virtual methods", &data.virtual_methods),
+    ] {
+        if methods.is_empty() {
+            continue;
+        }
+        write!(out, "\n\n# {title}\n")?;
+        for (i, method) in methods.iter().enumerate() {
+            if i > 0 {
+                writeln!(out)?;
+            }
+            out.push_str(&method_to_smali(method, dex)?);
+        }
+    }
+    Ok(out)
+}
+
+/// Returns the smali source of the given method, from its `.method`
+/// directive to its `.end method` directive.
+pub fn method_to_smali(method: &EncodedMethod, dex: &Dex) -> DexResult<String> {
+    let descriptor = method.descriptor(dex)?;
+    let mut out = String::new();
+    writeln!(
+        out,
+        ".method {}{}{}",
+        flags(&method.flags()),
+        descriptor.name(dex)?,
+        PrettyPrinter(descriptor.proto_idx.get(dex)?, dex)
+    )?;
+
+    if let Some(code) = method.code(dex)? {
+        let code = code.read().unwrap();
+        writeln!(out, "    .registers {}", code.registers_size())?;
+
+        // parameters names, params registers start with `this` for
+        // instance methods and wide types take two registers
+        if let Some(info) = code.debug_info(dex)? {
+            let mut register = usize::from(!method.flags().contains(MethodFlags::ACC_STATIC));
+            let parameters = descriptor.parameters_types(dex)?;
+            for (typ, name) in parameters.iter().zip(&info.parameter_names) {
+                if let Some(name) = name {
+                    let name = name.get(dex)?.to_string(dex)?;
+                    writeln!(out, "    .param p{register}, {}", string(&name))?;
+                }
+                register += if matches!(typ, Type::Long | Type::Double) {
+                    2
+                } else {
+                    1
+                };
+            }
+        }
+        writeln!(out)?;
+
+        write_code(&mut out, &code, dex)?;
+    }
+
+    writeln!(out, ".end method")?;
+    Ok(out)
+}
+
+fn write_field(
+    out: &mut String,
+    field: &EncodedField,
+    value: Option<&Value>,
+    dex: &Dex,
+) -> DexResult<()> {
+    let descriptor = field.descriptor(dex)?;
+    write!(
+        out,
+        ".field {}{}:{}",
+        flags(&field.flags()),
+        descriptor.name(dex)?,
+        descriptor.type_(dex)?
+    )?;
+    if let Some(value) = value {
+        write!(out, " = {}", self::value(value, dex)?)?;
+    }
+    writeln!(out)?;
+    Ok(())
+}
+
+/// Labels, line numbers and catch directives of a method body, keyed by
+/// the address they are written at.
+#[derive(Default)]
+struct Directives {
+    labels: BTreeMap<Addr, BTreeSet<String>>,
+    lines: BTreeMap<Addr, u32>,
+    catches: BTreeMap<Addr, Vec<String>>,
+    /// Address of the switch instruction of each switch payload, as cases
+    /// targets are relative to it.
+    switches: BTreeMap<Addr, Addr>,
+}
+
+impl Directives {
+    fn new(code: &CodeItem, dex: &Dex) -> DexResult<Self> {
+        let mut directives = Self::default();
+
+        for (addr, instr, targets) in code.iter_instructions_with_targets() {
+            match instr {
+                Instr::Goto(_) | Instr::Goto16(_) | Instr::Goto32(_) => {
+                    directives.label(targets[0], "goto");
+                }
+                Instr::IfEq(..)
+                | Instr::IfNe(..)
+                | Instr::IfLt(..)
+                | Instr::IfGe(..)
+                | Instr::IfGt(..)
+                | Instr::IfLe(..)
+                | Instr::IfEqz(..)
+                | Instr::IfNez(..)
+                | Instr::IfLtz(..)
+                | Instr::IfGez(..)
+                | Instr::IfGtz(..)
+                | Instr::IfLez(..) => {
+                    directives.label(targets[0], "cond");
+                }
+                Instr::PackedSwitch(_, offset) | Instr::SparseSwitch(_, offset) => {
+                    let payload = addr.offset(*offset);
+                    let (kind, cases) = match code.instruction_at(payload)?.instr() {
+                        Instr::PackedSwitchPayload(_, cases) => ("pswitch", cases),
+                        Instr::SparseSwitchPayload(_, cases) => ("sswitch", cases),
+                        _ => {
+                            return Err(DexError::Structure(format!(
+                                "no switch payload at {payload}"
+                            )))
+                        }
+                    };
+                    directives.label(payload, &format!("{kind}_data"));
+                    for case in cases {
+                        directives.label(addr.offset(*case), kind);
+                    }
+                    directives.switches.insert(payload, addr);
+                }
+                Instr::FillArrayData(_, offset) => {
+                    directives.label(addr.offset(*offset), "array");
+                }
+                _ => (),
+            }
+        }
+
+        for try_ in code.iter_tries() {
+            let start = directives.label(try_.start_addr(), "try_start");
+            let end = directives.label(try_.end_addr(), "try_end");
+            let handlers = try_.handlers(code)?;
+            let mut catches = Vec::new();
+            for handler in handlers.iter_handlers() {
+                let catch = directives.label(Addr(handler.catch_addr()), "catch");
+                catches.push(format!(
+                    ".catch {} {{:{start} .. :{end}}} :{catch}",
+                    handler.catch_type(dex)?
+                ));
+            }
+            if let Some(addr) = handlers.catch_all_addr() {
+                let catch = directives.label(Addr(addr), "catchall");
+                catches.push(format!(".catchall {{:{start} .. :{end}}} :{catch}"));
+            }
+            directives
+                .catches
+                .entry(try_.end_addr())
+                .or_default()
+                .extend(catches);
+        }
+
+        if let Some(info) = code.debug_info(dex)? {
+            directives.lines.extend(info.iter_line_entries());
+        }
+        Ok(directives)
+    }
+
+    fn label(&mut self, addr: Addr, kind: &str) -> String {
+        let label = format!("{kind}_{:x}", addr.0);
+        self.labels.entry(addr).or_default().insert(label.clone());
+        label
+    }
+
+    /// Writes the directives found at the given address, preceded by an
+    /// empty line unless `first` is set.
+    fn write(&self, out: &mut String, addr: Addr, first: bool) -> DexResult<()> {
+        let labels = self.labels.get(&addr);
+        let line = self.lines.get(&addr);
+        if !first && (labels.is_some() || line.is_some()) {
+            writeln!(out)?;
+        }
+        for label in labels.into_iter().flatten() {
+            writeln!(out, "    :{label}")?;
+        }
+        for catch in self.catches.get(&addr).into_iter().flatten() {
+            writeln!(out, "    {catch}")?;
+        }
+        if let Some(line) = line {
+            writeln!(out, "    .line {line}")?;
+        }
+        Ok(())
+    }
+}
+
+fn write_code(out: &mut String, code: &CodeItem, dex: &Dex) -> DexResult<()> {
+    let directives = Directives::new(code, dex)?;
+    let mut end = Addr::entry();
+    for (i, linstr) in code.iter_instructions().enumerate() {
+        let addr = linstr.addr();
+        directives.write(out, addr, i == 0)?;
+        write_instruction(out, addr, linstr.instr(), &directives, dex)?;
+        end = linstr.next_addr();
+    }
+
+    // directives after the last instruction, such as ends of try blocks
+    let addrs: BTreeSet<Addr> = directives
+        .labels
+        .keys()
+        .chain(directives.catches.keys())
+        .filter(|addr| **addr >= end)
+        .copied()
+        .collect();
+    for addr in addrs {
+        directives.write(out, addr, false)?;
+    }
+    Ok(())
+}
+
+#[allow(clippy::too_many_lines)]
+fn write_instruction(
+    out: &mut String,
+    addr: Addr,
+    instr: &Instr,
+    directives: &Directives,
+    dex: &Dex,
+) -> DexResult<()> {
+    let mnemonic = instr.mnemonic();
+    let target = |offset: i32, kind: &str| format!(":{kind}_{:x}", addr.offset(offset).0);
+    match instr {
+        Instr::Const4(a, v) => writeln!(out, "    {mnemonic} {a}, {}", literal(i64::from(*v)))?,
+        Instr::Const16(a, v) => writeln!(out, "    {mnemonic} {a}, {}", literal(i64::from(*v)))?,
+        Instr::Const(a, v) => writeln!(out, "    {mnemonic} {a}, {}", literal(i64::from(*v)))?,
+        Instr::ConstHigh16(a, v) => {
+            writeln!(out, "    {mnemonic} {a}, {}", literal(i64::from(*v) << 16))?;
+        }
+        Instr::ConstWide16(a, v) => {
+            writeln!(out, "    {mnemonic} {a}, {}L", literal(i64::from(*v)))?;
+        }
+        Instr::ConstWide32(a, v) => {
+            writeln!(out, "    {mnemonic} {a}, {}L", literal(i64::from(*v)))?;
+        }
+        Instr::ConstWide(a, v) => writeln!(out, "    {mnemonic} {a}, {}L", literal(*v))?,
+        Instr::ConstWideHigh16(a, v) => {
+            writeln!(out, "    {mnemonic} {a}, {}L", literal(i64::from(*v) << 48))?;
+        }
+        Instr::ConstString(a, s) | Instr::ConstStringJumbo(a, s) => {
+            let s = s.get(dex)?.to_string(dex)?;
+            writeln!(out, "    {mnemonic} {a}, {}", string(&s))?;
+        }
+
+        Instr::FilledNewArray(args, t) => {
+            let typ = t.get(dex)?.to_type(dex)?;
+            writeln!(out, "    {mnemonic} {}, {typ}", registers(args))?;
+        }
+        Instr::FillArrayData(a, o) => writeln!(out, "    {mnemonic} {a}, {}", target(*o, "array"))?,
+        Instr::PackedSwitch(a, o) => {
+            writeln!(out, "    {mnemonic} {a}, {}", target(*o, "pswitch_data"))?;
+        }
+        Instr::SparseSwitch(a, o) => {
+            writeln!(out, "    {mnemonic} {a}, {}", target(*o, "sswitch_data"))?;
+        }
+        Instr::Goto(o) => writeln!(out, "    {mnemonic} {}", target(i32::from(*o), "goto"))?,
+        Instr::Goto16(o) => writeln!(out, "    {mnemonic} {}", target(i32::from(*o), "goto"))?,
+        Instr::Goto32(o) => writeln!(out, "    {mnemonic} {}", target(*o, "goto"))?,
+
+        Instr::IfEq(a, b, o)
+        | Instr::IfNe(a, b, o)
+        | Instr::IfLt(a, b, o)
+        | Instr::IfGe(a, b, o)
+        | Instr::IfGt(a, b, o)
+        | Instr::IfLe(a, b, o) => {
+            writeln!(
+                out,
+                "    {mnemonic} {a}, {b}, {}",
+                target(i32::from(*o), "cond")
+            )?;
+        }
+        Instr::IfEqz(a, o)
+        | Instr::IfNez(a, o)
+        | Instr::IfLtz(a, o)
+        | Instr::IfGez(a, o)
+        | Instr::IfGtz(a, o)
+        | Instr::IfLez(a, o) => {
+            writeln!(out, "    {mnemonic} {a}, {}", target(i32::from(*o), "cond"))?;
+        }
+
+        Instr::Iget(a, b, f)
+        | Instr::IgetWide(a, b, f)
+        | Instr::IgetObject(a, b, f)
+        | Instr::IgetBoolean(a, b, f)
+        | Instr::IgetByte(a, b, f)
+        | Instr::IgetChar(a, b, f)
+        | Instr::IgetShort(a, b, f)
+        | Instr::Iput(a, b, f)
+        | Instr::IputWide(a, b, f)
+        | Instr::IputObject(a, b, f)
+        | Instr::IputBoolean(a, b, f)
+        | Instr::IputByte(a, b, f)
+        | Instr::IputChar(a, b, f)
+        | Instr::IputShort(a, b, f) => {
+            writeln!(out, "    {mnemonic} {a}, {b}, {}", field(f.get(dex)?, dex)?)?;
+        }
+        Instr::Sget(a, f)
+        | Instr::SgetWide(a, f)
+        | Instr::SgetObject(a, f)
+        | Instr::SgetBoolean(a, f)
+        | Instr::SgetByte(a, f)
+        | Instr::SgetChar(a, f)
+        | Instr::SgetShort(a, f)
+        | Instr::Sput(a, f)
+        | Instr::SputWide(a, f)
+        | Instr::SputObject(a, f)
+        | Instr::SputBoolean(a, f)
+        | Instr::SputByte(a, f)
+        | Instr::SputChar(a, f)
+        | Instr::SputShort(a, f) => {
+            writeln!(out, "    {mnemonic} {a}, {}", field(f.get(dex)?, dex)?)?;
+        }
+
+        Instr::InvokeVirtual(args, m)
+        | Instr::InvokeSuper(args, m)
+        | Instr::InvokeDirect(args, m)
+        | Instr::InvokeStatic(args, m)
+        | Instr::InvokeInterface(args, m) => {
+            let method = PrettyPrinter(m.get(dex)?, dex);
+            writeln!(out, "    {mnemonic} {}, {method}", registers(args))?;
+        }
+        Instr::InvokePolymorphic(args, m, p) => {
+            let method = PrettyPrinter(m.get(dex)?, dex);
+            let proto = PrettyPrinter(p.get(dex)?, dex);
+            writeln!(out, "    {mnemonic} {}, {method}, {proto}", registers(args))?;
+        }
+        Instr::InvokeCustom(args, cs) => {
+            let call_site = call_site(cs.get(dex)?, dex)?;
+            writeln!(out, "    {mnemonic} {}, {call_site}", registers(args))?;
+        }
+        Instr::InvokeCustomRange(rr, cs) => {
+            let call_site = call_site(cs.get(dex)?, dex)?;
+            writeln!(out, "    {mnemonic} {rr}, {call_site}")?;
+        }
+
+        Instr::AddIntLit16(a, b, c)
+        | Instr::RsubInt(a, b, c)
+        | Instr::MulIntLit16(a, b, c)
+        | Instr::DivIntLit16(a, b, c)
+        | Instr::RemIntLit16(a, b, c)
+        | Instr::AndIntLit16(a, b, c)
+        | Instr::OrIntLit16(a, b, c)
+        | Instr::XorIntLit16(a, b, c) => {
+            writeln!(out, "    {mnemonic} {a}, {b}, {}", literal(i64::from(*c)))?;
+        }
+        Instr::AddIntLit8(a, b, c)
+        | Instr::RsubIntLit8(a, b, c)
+        | Instr::MulIntLit8(a, b, c)
+        | Instr::DivIntLit8(a, b, c)
+        | Instr::RemIntLit8(a, b, c)
+        | Instr::AndIntLit8(a, b, c)
+        | Instr::OrIntLit8(a, b, c)
+        | Instr::XorIntLit8(a, b, c)
+        | Instr::ShlIntLit8(a, b, c)
+        | Instr::ShrIntLit8(a, b, c)
+        | Instr::UshrIntLit8(a, b, c) => {
+            writeln!(out, "    {mnemonic} {a}, {b}, {}", literal(i64::from(*c)))?;
+        }
+
+        Instr::ConstMethodHandle(a, h) => {
+            writeln!(
+                out,
+                "    {mnemonic} {a}, {}",
+                method_handle(h.get(dex)?, dex)?
+            )?;
+        }
+
+        Instr::PackedSwitchPayload(first_key, cases) => {
+            let switch = payload_owner(directives, addr)?;
+            writeln!(out, "    .packed-switch {}", literal(i64::from(*first_key)))?;
+            for case in cases {
+                writeln!(out, "        :pswitch_{:x}", switch.offset(*case).0)?;
+            }
+            writeln!(out, "    .end packed-switch")?;
+        }
+        Instr::SparseSwitchPayload(keys, cases) => {
+            let switch = payload_owner(directives, addr)?;
+            writeln!(out, "    .sparse-switch")?;
+            for (key, case) in keys.iter().zip(cases) {
+                let key = literal(i64::from(*key));
+                writeln!(
+                    out,
+                    "        {key} -> :sswitch_{:x}",
+                    switch.offset(*case).0
+                )?;
+            }
+            writeln!(out, "    .end sparse-switch")?;
+        }
+        Instr::FillArrayDataPayload(elements) => {
+            let width = elements.first().map_or(1, Vec::len);
+            let suffix = match width {
+                1 => "t",
+                2 => "s",
+                8 => "L",
+                _ => "",
+            };
+            writeln!(out, "    .array-data {width}")?;
+            for element in elements {
+                // elements are little-endian signed values
+                let mut bytes = [0u8; 8];
+                bytes[..element.len()].copy_from_slice(element);
+                let shift = 64 - 8 * element.len() as u32;
+                let value = (i64::from_le_bytes(bytes) << shift) >> shift;
+                writeln!(out, "        {}{suffix}", literal(value))?;
+            }
+            writeln!(out, "    .end array-data")?;
+        }
+
+        // other instructions have the same syntax in smali
+        _ => writeln!(out, "    {}", PrettyPrinter(instr, dex))?,
+    }
+    Ok(())
+}
+
+fn payload_owner(directives: &Directives, payload: Addr) -> DexResult<Addr> {
+    directives
+        .switches
+        .get(&payload)
+        .copied()
+        .ok_or_else(|| DexError::Structure(format!("no switch for payload at {payload}")))
+}
+
+/// Access flags, with a trailing space if not empty.
+fn flags(access: &impl fmt::Display) -> String {
+    access.to_string().replace('_', "-")
+}
+
+fn registers(args: &RegList) -> String {
+    let args: Vec<String> = args.iter().map(|reg| reg.to_string()).collect();
+    format!("{{{}}}", args.join(", "))
+}
+
+fn literal(value: i64) -> String {
+    if value < 0 {
+        format!("-{:#x}", value.unsigned_abs())
+    } else {
+        format!("{value:#x}")
+    }
+}
+
+fn float<T: Into<f64> + fmt::Debug + Copy>(value: T) -> String {
+    let f: f64 = value.into();
+    if f.is_nan() {
+        "NaN".to_string()
+    } else if f.is_infinite() && f > 0.0 {
+        "Infinity".to_string()
+    } else if f.is_infinite() {
+        "-Infinity".to_string()
+    } else {
+        format!("{value:?}")
+    }
+}
+
+/// Quotes and escapes a string, non-ASCII characters being written as
+/// UTF-16 code units.
+fn string(s: &str) -> String {
+    format!("\"{}\"", escape(s.encode_utf16()))
+}
+
+fn escape(units: impl Iterator<Item = u16>) -> String {
+    let mut escaped = String::new();
+    for unit in units {
+        match char::from_u32(u32::from(unit)) {
+            Some('\n') => escaped.push_str("\\n"),
+            Some('\r') => escaped.push_str("\\r"),
+            Some('\t') => escaped.push_str("\\t"),
+            Some(c @ ('"' | '\'' | '\\')) => {
+                escaped.push('\\');
+                escaped.push(c);
+            }
+            Some(c) if (' '..='~').contains(&c) => escaped.push(c),
+            _ => {
+                let _ = write!(escaped, "\\u{unit:04x}");
+            }
+        }
+    }
+    escaped
+}
+
+fn field(field: &FieldIdItem, dex: &Dex) -> DexResult<String> {
+    Ok(format!(
+        "{}->{}:{}",
+        field.class(dex)?,
+        field.name(dex)?,
+        field.type_(dex)?
+    ))
+}
+
+fn method_handle(handle: &MethodHandleItem, dex: &Dex) -> DexResult<String> {
+    let kind = match handle.method_handle {
+        MethodHandle::StaticPut(_) => "static-put",
+        MethodHandle::StaticGet(_) => "static-get",
+        MethodHandle::InstancePut(_) => "instance-put",
+        MethodHandle::InstanceGet(_) => "instance-get",
+        MethodHandle::InvokeStatic(_) => "invoke-static",
+        MethodHandle::InvokeInstance(_) => "invoke-instance",
+        MethodHandle::InvokeConstructor(_) => "invoke-constructor",
+        MethodHandle::InvokeDirect(_) => "invoke-direct",
+        MethodHandle::InvokeInterface(_) => "invoke-interface",
+    };
+    let member = match handle.method_handle {
+        MethodHandle::StaticPut(f)
+        | MethodHandle::StaticGet(f)
+        | MethodHandle::InstancePut(f)
+        | MethodHandle::InstanceGet(f) => field(f.get(dex)?, dex)?,
+        MethodHandle::InvokeStatic(m)
+        | MethodHandle::InvokeInstance(m)
+        | MethodHandle::InvokeConstructor(m)
+        | MethodHandle::InvokeDirect(m)
+        | MethodHandle::InvokeInterface(m) => PrettyPrinter(m.get(dex)?, dex).to_string(),
+    };
+    Ok(format!("{kind}@{member}"))
+}
+
+/// A call site is written as its name and type followed by the extra
+/// arguments of its bootstrap method, then the bootstrap method itself.
+fn call_site(call_site: &CallSiteIdItem, dex: &Dex) -> DexResult<String> {
+    let arguments = call_site.arguments(dex)?.decode(dex)?;
+    let (
+        Some(Value::MethodHandle(bootstrap)),
+        Some(Value::String(name)),
+        Some(Value::MethodType(proto)),
+    ) = (arguments.first(), arguments.get(1), arguments.get(2))
+    else {
+        return Err(DexError::Structure(
+            "invalid call site arguments".to_string(),
+        ));
+    };
+    let MethodHandle::InvokeStatic(bootstrap) = bootstrap.method_handle else {
+        return Err(DexError::Structure(
+            "call site bootstrap method must be static".to_string(),
+        ));
+    };
+
+    let mut s = format!(
+        "call_site_{}({}, {}",
+        call_site.index.as_usize(),
+        string(name),
+        PrettyPrinter(*proto, dex)
+    );
+    for argument in &arguments[3..] {
+        write!(s, ", {}", value(argument, dex)?)?;
+    }
+    write!(s, ")@{}", PrettyPrinter(bootstrap.get(dex)?, dex))?;
+    Ok(s)
+}
+
+fn value(value: &Value, dex: &Dex) -> DexResult<String> {
+    Ok(match value {
+        Value::Byte(v) => format!("{}t", literal(i64::from(*v))),
+        Value::Short(v) => format!("{}s", literal(i64::from(*v))),
+        Value::Char(v) => format!("'{}'", escape(std::iter::once(*v))),
+        Value::Int(v) => literal(i64::from(*v)),
+        Value::Long(v) => format!("{}L", literal(*v)),
+        Value::Float(v) => format!("{}f", float(*v)),
+        Value::Double(v) => float(*v),
+        Value::MethodType(proto) => PrettyPrinter(*proto, dex).to_string(),
+        Value::MethodHandle(handle) => method_handle(handle, dex)?,
+        Value::String(s) => string(s),
+        Value::Type(typ) => typ.to_string(),
+        Value::Field(f) => field(f, dex)?,
+        Value::Method(m) => PrettyPrinter(*m, dex).to_string(),
+        Value::Enum(f) => format!(".enum {}", field(f, dex)?),
+        Value::Array(values) => {
+            let values = values
+                .iter()
+                .map(|v| self::value(v, dex))
+                .collect::<DexResult<Vec<String>>>()?;
+            format!("{{ {} }}", values.join(", "))
+        }
+        Value::Annotation(_) => {
+            return Err(DexError::Structure(
+                "annotation values cannot be written in smali".to_string(),
+            ))
+        }
+        Value::Null => "null".to_string(),
+        Value::Boolean(b) => b.to_string(),
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::classes::{ClassDataItem, ClassFlags};
+    use crate::code::{
+        DbgInstr, DebugInfoItem, EncodedCatchHandler, EncodedCatchHandlerList, EncodedTypeAddrPair,
+        TryItem,
+    };
+    use crate::instrs::LabeledInstr;
+    use crate::registers::Reg;
+    use crate::types::ProtoDescr;
+    use crate::Index;
+    use dw_utils::leb::{Sleb128, Uleb128};
+    use std::sync::RwLock;
+
+    #[test]
+    fn method_with_branch_and_catch() {
+        let mut dex = Dex::new(35);
+        let proto = ProtoDescr {
+            return_type: Type::Int,
+            parameters: vec![Type::Int],
+        };
+        dex.intern_method("LFoo;", "abs", &proto).unwrap();
+        dex.intern_type("Ljava/lang/Object;").unwrap();
+        dex.intern_type("Ljava/lang/Exception;").unwrap();
+        dex.intern_string("x").unwrap();
+        dex.intern_string("Foo.java").unwrap();
+        // items are all interned, indexes do not move anymore
+        let method = dex.intern_method("LFoo;", "abs", &proto).unwrap();
+        let class = dex.intern_type("LFoo;").unwrap();
+        let object = dex.intern_type("Ljava/lang/Object;").unwrap();
+        let exception = dex.intern_type("Ljava/lang/Exception;").unwrap();
+        let x = dex.intern_string("x").unwrap();
+        let source = dex.intern_string("Foo.java").unwrap();
+
+        let v0 = Reg::from(0u8);
+        let v1 = Reg::from(1u8);
+        let instrs = vec![
+            Instr::IfGez(v1, 3),
+            Instr::NegInt(v1, v1),
+            Instr::Return(v1),
+            Instr::MoveException(v0),
+            Instr::Const4(v1, -1),
+            Instr::Return(v1),
+        ];
+        let mut addr = 0;
+        let insns = instrs
+            .into_iter()
+            .map(|instr| {
+                let linstr = LabeledInstr {
+                    addr: Addr(addr),
+                    instr,
+                };
+                addr += linstr.size();
+                linstr
+            })
+            .collect();
+
+        // .line 3 at 0, .line 4 at 2
+        let _ = dex.debug_info_items.insert(
+            0x5000,
+            DebugInfoItem {
+                index: Index::new(0x5000),
+                line_start: Uleb128::new(3, None),
+                parameters_size: Uleb128::new(1, None),
+                parameter_names: vec![Some(x)],
+                bytecode: vec![
+                    DbgInstr::Special(0x0e),
+                    DbgInstr::Special(0x2d),
+                    DbgInstr::EndSequence,
+                ],
+            },
+        );
+        let mut handlers = BTreeMap::new();
+        handlers.insert(
+            1,
+            EncodedCatchHandler {
+                size: Sleb128::new(1, None),
+                handlers: vec![EncodedTypeAddrPair {
+                    type_idx: exception,
+                    addr: Uleb128::new(4, None),
+                }],
+                catch_all_addr: None,
+            },
+        );
+        let _ = dex.code_items.insert(
+            0x6000,
+            RwLock::new(CodeItem {
+                index: Index::new(0x6000),
+                registers_size: 2,
+                ins_size: 1,
+                outs_size: 0,
+                debug_info_off: Some(Index::new(0x5000)),
+                insns,
+                tries: vec![TryItem {
+                    start_addr: 0,
+                    insn_count: 3,
+                    handler_off: 1,
+                }],
+                handlers: Some(EncodedCatchHandlerList {
+                    size: Uleb128::new(1, None),
+                    list: handlers,
+                }),
+            }),
+        );
+        let _ = dex.class_data_items.insert(
+            0x7000,
+            ClassDataItem {
+                index: Index::new(0x7000),
+                static_fields_size: Uleb128::new(0, None),
+                instance_fields_size: Uleb128::new(0, None),
+                direct_methods_size: Uleb128::new(1, None),
+                virtual_methods_size: Uleb128::new(0, None),
+                static_fields: Vec::new(),
+                instance_fields: Vec::new(),
+                direct_methods: vec![EncodedMethod {
+                    method_idx_diff: Uleb128::new(method.as_usize() as u32, None),
+                    method_idx: method,
+                    access_flags_repr: Uleb128::new(0x9, None),
+                    access_flags: MethodFlags::ACC_PUBLIC | MethodFlags::ACC_STATIC,
+                    code_off: Some(Index::new(0x6000)),
+                }],
+                virtual_methods: Vec::new(),
+            },
+        );
+        dex.class_def_items.push(ClassDefItem {
+            index: Index::new(0),
+            class_idx: class,
+            access_flags: ClassFlags::ACC_PUBLIC,
+            superclass_idx: Some(object),
+            interfaces_off: None,
+            source_file_idx: Some(source),
+            annotations_off: None,
+            class_data_off: Some(Index::new(0x7000)),
+            static_values_off: None,
+        });
+
+        let expected = r#".class public LFoo;
+.super Ljava/lang/Object;
+.source "Foo.java"
+
+
+# direct methods
+.method public static abs(I)I
+    .registers 2
+    .param p0, "x"
+
+    :try_start_0
+    .line 3
+    if-gez v1, :cond_3
+
+    .line 4
+    neg-int v1, v1
+
+    :cond_3
+    :try_end_3
+    .catch Ljava/lang/Exception; {:try_start_0 .. :try_end_3} :catch_4
+    return v1
+
+    :catch_4
+    move-exception v0
+    const/4 v1, -0x1
+    return v1
+.end method
+"#;
+        let smali = class_to_smali(&dex.class_def_items[0], &dex).unwrap();
+        assert_eq!(smali, expected);
+        assert_eq!(string("a\"\u{e9}\n"), r#""a\"\u00e9\n""#);
+    }
+}
@@ -74,7 +74,7 @@ impl DexCollection for StringDataItem {
 
 impl StringDataItem {
     fn to_string(&self) -> DexResult<String> {
-        let v = mutf8::decode(&self.data)?;
+        let v = mutf8::decode_utf16(&self.data)?;
         if v.len() == self.utf16_size.value() as usize {
             let s = match String::from_utf16(&v) {
                 Ok(s) => s,
@@ -0,0 +1,602 @@
+//! Helpers to assemble minimal dex files for unit tests, available to
+//! other crates with the `testing` feature.
+
+use crate::Dex;
+
+pub const ACC_PUBLIC: u32 = 0x1;
+pub const ACC_PRIVATE: u32 = 0x2;
+pub const ACC_STATIC: u32 = 0x8;
+pub const ACC_INTERFACE: u32 = 0x200;
+pub const ACC_ABSTRACT: u32 = 0x400;
+
+struct ProtoId {
+    shorty: u32,
+    return_type: u32,
+    parameters: Vec<u32>,
+}
+
+struct EncodedMethod {
+    method_idx: u32,
+    access_flags: u32,
+    code: Option<CodeItem>,
+}
+
+struct CodeItem {
+    registers_size: u16,
+    ins_size: u16,
+    outs_size: u16,
+    insns: Vec<u16>,
+    tries: Vec<Try>,
+    // line number table as (address, line)
+    lines: Vec<(u32, u32)>,
+}
+
+struct Try {
+    start_addr: u32,
+    insn_count: u16,
+    // typed handlers as (type index, handler address)
+    handlers: Vec<(u32, u32)>,
+    catch_all_addr: Option<u32>,
+}
+
+struct ClassDef {
+    class_idx: u32,
+    access_flags: u32,
+    superclass_idx: u32,
+    interfaces: Vec<u32>,
+    source_file: Option<u32>,
+    instance_fields: Vec<u32>,
+    direct_methods: Vec<EncodedMethod>,
+    virtual_methods: Vec<EncodedMethod>,
+}
+
+/// Assembles a dex file from strings, types, prototypes, methods and
+/// classes definitions. Indices are allocated on first use, so that
+/// they can be used to encode instructions before building the file.
+#[derive(Default)]
+pub struct DexBuilder {
+    strings: Vec<String>,
+    types: Vec<u32>,
+    protos: Vec<ProtoId>,
+    fields: Vec<(u32, u32, u32)>,
+    methods: Vec<(u32, u32, u32)>,
+    classes: Vec<ClassDef>,
+    checksum: u32,
+}
+
+impl DexBuilder {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    pub fn string(&mut self, s: &str) -> u32 {
+        if let Some(i) = self.strings.iter().position(|x| x == s) {
+            return i as u32;
+        }
+        self.strings.push(s.to_string());
+        (self.strings.len() - 1) as u32
+    }
+
+    pub fn type_(&mut self, descriptor: &str) -> u32 {
+        let s = self.string(descriptor);
+        if let Some(i) = self.types.iter().position(|x| *x == s) {
+            return i as u32;
+        }
+        self.types.push(s);
+        (self.types.len() - 1) as u32
+    }
+
+    pub fn proto(&mut self, return_type: &str, parameters: &[&str]) -> u32 {
+        let shorty: String = std::iter::once(return_type)
+            .chain(parameters.iter().copied())
+            .map(|t| match &t[..1] {
+                "[" => 'L',
+                c => c.chars().next().unwrap(),
+            })
+            .collect();
+        let shorty = self.string(&shorty);
+        let return_type = self.type_(return_type);
+        let parameters: Vec<u32> = parameters.iter().map(|p| self.type_(p)).collect();
+        if let Some(i) = self.protos.iter().position(|p| {
+            p.shorty == shorty && p.return_type == return_type && p.parameters == parameters
+        }) {
+            return i as u32;
+        }
+        self.protos.push(ProtoId {
+            shorty,
+            return_type,
+            parameters,
+        });
+        (self.protos.len() - 1) as u32
+    }
+
+    pub fn method(
+        &mut self,
+        class: &str,
+        name: &str,
+        return_type: &str,
+        parameters: &[&str],
+    ) -> u32 {
+        let class = self.type_(class);
+        let proto = self.proto(return_type, parameters);
+        let name = self.string(name);
+        let method = (class, proto, name);
+        if let Some(i) = self.methods.iter().position(|m| *m == method) {
+            return i as u32;
+        }
+        self.methods.push(method);
+        (self.methods.len() - 1) as u32
+    }
+
+    pub fn field(&mut self, class: &str, name: &str, type_: &str) -> u32 {
+        let class = self.type_(class);
+        let type_ = self.type_(type_);
+        let name = self.string(name);
+        let field = (class, type_, name);
+        if let Some(i) = self.fields.iter().position(|f| *f == field) {
+            return i as u32;
+        }
+        self.fields.push(field);
+        (self.fields.len() - 1) as u32
+    }
+
+    /// Sets the header checksum, which parsing does not check, so that
+    /// otherwise identical files can mimic different builds.
+    pub fn checksum(&mut self, checksum: u32) {
+        self.checksum = checksum;
+    }
+
+    /// Defines a new class and returns its class definition index.
+    pub fn class(&mut self, name: &str, superclass: &str) -> usize {
+        let class_idx = self.type_(name);
+        let superclass_idx = self.type_(superclass);
+        self.classes.push(ClassDef {
+            class_idx,
+            access_flags: ACC_PUBLIC,
+            superclass_idx,
+            interfaces: Vec::new(),
+            source_file: None,
+            instance_fields: Vec::new(),
+            direct_methods: Vec::new(),
+            virtual_methods: Vec::new(),
+        });
+        self.classes.len() - 1
+    }
+
+    /// Sets the access flags of the given class definition, which is
+    /// public by default.
+    pub fn class_flags(&mut self, class: usize, access_flags: u32) {
+        self.classes[class].access_flags = access_flags;
+    }
+
+    /// Adds an implemented interface to the given class definition.
+    pub fn implements(&mut self, class: usize, interface: &str) {
+        let interface = self.type_(interface);
+        self.classes[class].interfaces.push(interface);
+    }
+
+    /// Adds an implemented method to the given class definition.
+    ///
+    /// Static methods are registered as direct methods, other ones as
+    /// virtual methods.
+    #[allow(clippy::too_many_arguments)]
+    pub fn code(
+        &mut self,
+        class: usize,
+        method_idx: u32,
+        access_flags: u32,
+        registers_size: u16,
+        ins_size: u16,
+        outs_size: u16,
+        insns: &[u16],
+    ) {
+        let method = EncodedMethod {
+            method_idx,
+            access_flags,
+            code: Some(CodeItem {
+                registers_size,
+                ins_size,
+                outs_size,
+                insns: insns.to_vec(),
+                tries: Vec::new(),
+                lines: Vec::new(),
+            }),
+        };
+        if access_flags & ACC_STATIC != 0 {
+            self.classes[class].direct_methods.push(method);
+        } else {
+            self.classes[class].virtual_methods.push(method);
+        }
+    }
+
+    /// Adds a try region with a single catch-all handler to an implemented
+    /// method of the given class definition.
+    pub fn catch_all(
+        &mut self,
+        class: usize,
+        method_idx: u32,
+        start_addr: u32,
+        insn_count: u16,
+        handler_addr: u32,
+    ) {
+        self.catch(
+            class,
+            method_idx,
+            start_addr,
+            insn_count,
+            &[],
+            Some(handler_addr),
+        );
+    }
+
+    /// Adds a try region to an implemented method of the given class
+    /// definition, with handlers given as (exception type descriptor,
+    /// handler address) and an optional catch-all handler address.
+    ///
+    /// Try regions must be added in increasing address order.
+    pub fn catch(
+        &mut self,
+        class: usize,
+        method_idx: u32,
+        start_addr: u32,
+        insn_count: u16,
+        handlers: &[(&str, u32)],
+        catch_all_addr: Option<u32>,
+    ) {
+        let handlers = handlers
+            .iter()
+            .map(|(descriptor, addr)| (self.type_(descriptor), *addr))
+            .collect();
+        let class = &mut self.classes[class];
+        let code = class
+            .direct_methods
+            .iter_mut()
+            .chain(&mut class.virtual_methods)
+            .find(|m| m.method_idx == method_idx)
+            .and_then(|m| m.code.as_mut())
+            .expect("implemented method");
+        code.tries.push(Try {
+            start_addr,
+            insn_count,
+            handlers,
+            catch_all_addr,
+        });
+    }
+
+    /// Sets the source file name of the given class definition.
+    pub fn source_file(&mut self, class: usize, name: &str) {
+        let name = self.string(name);
+        self.classes[class].source_file = Some(name);
+    }
+
+    /// Adds debug information to an implemented method of the given class
+    /// definition, made of a line number table given as (address, line) in
+    /// increasing address order.
+    pub fn lines(&mut self, class: usize, method_idx: u32, lines: &[(u32, u32)]) {
+        let class = &mut self.classes[class];
+        let code = class
+            .direct_methods
+            .iter_mut()
+            .chain(&mut class.virtual_methods)
+            .find(|m| m.method_idx == method_idx)
+            .and_then(|m| m.code.as_mut())
+            .expect("implemented method");
+        code.lines = lines.to_vec();
+    }
+
+    /// Adds a (public) instance field to the given class definition.
+    pub fn instance_field(&mut self, class: usize, field_idx: u32) {
+        self.classes[class].instance_fields.push(field_idx);
+    }
+
+    /// Adds a method without code (abstract or native) to the given class
+    /// definition.
+    pub fn declare(&mut self, class: usize, method_idx: u32, access_flags: u32) {
+        let method = EncodedMethod {
+            method_idx,
+            access_flags,
+            code: None,
+        };
+        if access_flags & ACC_STATIC != 0 {
+            self.classes[class].direct_methods.push(method);
+        } else {
+            self.classes[class].virtual_methods.push(method);
+        }
+    }
+
+    pub fn build(mut self) -> Vec<u8> {
+        for class in &mut self.classes {
+            class.instance_fields.sort_unstable();
+            class.direct_methods.sort_by_key(|m| m.method_idx);
+            class.virtual_methods.sort_by_key(|m| m.method_idx);
+        }
+
+        let string_ids_off = 0x70;
+        let type_ids_off = string_ids_off + 4 * self.strings.len();
+        let proto_ids_off = type_ids_off + 4 * self.types.len();
+        let field_ids_off = proto_ids_off + 12 * self.protos.len();
+        let method_ids_off = field_ids_off + 8 * self.fields.len();
+        let class_defs_off = method_ids_off + 8 * self.methods.len();
+        let data_off = class_defs_off + 32 * self.classes.len();
+
+        // data section, sub-sections are emitted in map order
+        let mut data = Vec::new();
+
+        let type_lists_off = data_off + data.len();
+        let mut proto_parameters_offs = Vec::new();
+        let mut nb_type_lists = 0;
+        for proto in &self.protos {
+            if proto.parameters.is_empty() {
+                proto_parameters_offs.push(0);
+                continue;
+            }
+            align(&mut data, 4);
+            proto_parameters_offs.push(data_off + data.len());
+            nb_type_lists += 1;
+            push_u32(&mut data, proto.parameters.len() as u32);
+            for p in &proto.parameters {
+                push_u16(&mut data, *p as u16);
+            }
+        }
+        let mut interfaces_offs = Vec::new();
+        for class in &self.classes {
+            if class.interfaces.is_empty() {
+                interfaces_offs.push(0);
+                continue;
+            }
+            align(&mut data, 4);
+            interfaces_offs.push(data_off + data.len());
+            nb_type_lists += 1;
+            push_u32(&mut data, class.interfaces.len() as u32);
+            for i in &class.interfaces {
+                push_u16(&mut data, *i as u16);
+            }
+        }
+
+        let debug_info_items_off = data_off + data.len();
+        let mut debug_info_offs = Vec::new();
+        for class in &self.classes {
+            for method in class.direct_methods.iter().chain(&class.virtual_methods) {
+                match &method.code {
+                    Some(code) if !code.lines.is_empty() => {
+                        debug_info_offs.push(data_off + data.len());
+                        push_uleb128(&mut data, 0); // line_start
+                        push_uleb128(&mut data, 0); // parameters_size
+                        let (mut addr, mut line) = (0, 0);
+                        for (entry_addr, entry_line) in &code.lines {
+                            data.push(0x01); // DBG_ADVANCE_PC
+                            push_uleb128(&mut data, entry_addr - addr);
+                            data.push(0x02); // DBG_ADVANCE_LINE
+                            push_sleb128(&mut data, *entry_line as i32 - line as i32);
+                            data.push(0x0e); // special opcode without advance
+                            (addr, line) = (*entry_addr, *entry_line);
+                        }
+                        data.push(0x00); // DBG_END_SEQUENCE
+                    }
+                    _ => debug_info_offs.push(0),
+                }
+            }
+        }
+        let nb_debug_info_items = debug_info_offs.iter().filter(|off| **off != 0).count();
+
+        align(&mut data, 4);
+        let code_items_off = data_off + data.len();
+        let mut debug_info_offs = debug_info_offs.into_iter();
+        let mut code_offs = Vec::new();
+        for class in &self.classes {
+            for method in class.direct_methods.iter().chain(&class.virtual_methods) {
+                let debug_info_off = debug_info_offs.next().unwrap();
+                let Some(code) = &method.code else {
+                    code_offs.push(0);
+                    continue;
+                };
+                align(&mut data, 4);
+                code_offs.push(data_off + data.len());
+                push_u16(&mut data, code.registers_size);
+                push_u16(&mut data, code.ins_size);
+                push_u16(&mut data, code.outs_size);
+                push_u16(&mut data, code.tries.len() as u16);
+                push_u32(&mut data, debug_info_off as u32);
+                push_u32(&mut data, code.insns.len() as u32);
+                for unit in &code.insns {
+                    push_u16(&mut data, *unit);
+                }
+                if code.tries.is_empty() {
+                    continue;
+                }
+                align(&mut data, 4);
+                let mut handlers = Vec::new();
+                push_uleb128(&mut handlers, code.tries.len() as u32);
+                for try_ in &code.tries {
+                    push_u32(&mut data, try_.start_addr);
+                    push_u16(&mut data, try_.insn_count);
+                    push_u16(&mut data, handlers.len() as u16);
+                    // sleb128 size, negative when there is a catch-all handler
+                    let size = try_.handlers.len() as i8;
+                    let size = if try_.catch_all_addr.is_some() {
+                        -size
+                    } else {
+                        size
+                    };
+                    handlers.push(size as u8 & 0x7f);
+                    for (type_idx, addr) in &try_.handlers {
+                        push_uleb128(&mut handlers, *type_idx);
+                        push_uleb128(&mut handlers, *addr);
+                    }
+                    if let Some(addr) = try_.catch_all_addr {
+                        push_uleb128(&mut handlers, addr);
+                    }
+                }
+                data.extend(handlers);
+            }
+        }
+        let nb_code_items = code_offs.iter().filter(|off| **off != 0).count();
+
+        let class_data_off = data_off + data.len();
+        let mut class_data_offs = Vec::new();
+        let mut code_offs = code_offs.into_iter();
+        for class in &self.classes {
+            class_data_offs.push(data_off + data.len());
+            push_uleb128(&mut data, 0);
+            push_uleb128(&mut data, class.instance_fields.len() as u32);
+            push_uleb128(&mut data, class.direct_methods.len() as u32);
+            push_uleb128(&mut data, class.virtual_methods.len() as u32);
+            let mut previous = 0;
+            for field_idx in &class.instance_fields {
+                push_uleb128(&mut data, field_idx - previous);
+                push_uleb128(&mut data, ACC_PUBLIC);
+                previous = *field_idx;
+            }
+            for methods in [&class.direct_methods, &class.virtual_methods] {
+                let mut previous = 0;
+                for method in methods {
+                    push_uleb128(&mut data, method.method_idx - previous);
+                    push_uleb128(&mut data, method.access_flags);
+                    push_uleb128(&mut data, code_offs.next().unwrap() as u32);
+                    previous = method.method_idx;
+                }
+            }
+        }
+
+        let string_data_off = data_off + data.len();
+        let mut string_data_offs = Vec::new();
+        for s in &self.strings {
+            string_data_offs.push(data_off + data.len());
+            push_uleb128(&mut data, s.encode_utf16().count() as u32);
+            data.extend_from_slice(s.as_bytes());
+            data.push(0);
+        }
+
+        align(&mut data, 4);
+        let map_off = data_off + data.len();
+        let mut map = vec![
+            (0x0000, 1, 0),
+            (0x0001, self.strings.len(), string_ids_off),
+            (0x0002, self.types.len(), type_ids_off),
+            (0x0003, self.protos.len(), proto_ids_off),
+            (0x0004, self.fields.len(), field_ids_off),
+            (0x0005, self.methods.len(), method_ids_off),
+            (0x0006, self.classes.len(), class_defs_off),
+            (0x1001, nb_type_lists, type_lists_off),
+            (0x2003, nb_debug_info_items, debug_info_items_off),
+            (0x2001, nb_code_items, code_items_off),
+            (0x2000, self.classes.len(), class_data_off),
+            (0x2002, self.strings.len(), string_data_off),
+            (0x1000, 1, map_off),
+        ];
+        map.retain(|(_, size, _)| *size != 0);
+        push_u32(&mut data, map.len() as u32);
+        for (typ, size, off) in map {
+            push_u16(&mut data, typ);
+            push_u16(&mut data, 0);
+            push_u32(&mut data, size as u32);
+            push_u32(&mut data, off as u32);
+        }
+
+        let file_size = data_off + data.len();
+        let mut out = Vec::with_capacity(file_size);
+        out.extend_from_slice(b"dex\n035\0");
+        push_u32(&mut out, self.checksum);
+        out.extend_from_slice(&[0; 20]); // signature (not checked)
+        push_u32(&mut out, file_size as u32);
+        push_u32(&mut out, 0x70);
+        push_u32(&mut out, 0x1234_5678);
+        push_u32(&mut out, 0);
+        push_u32(&mut out, 0);
+        push_u32(&mut out, map_off as u32);
+        for (size, off) in [
+            (self.strings.len(), string_ids_off),
+            (self.types.len(), type_ids_off),
+            (self.protos.len(), proto_ids_off),
+            (self.fields.len(), field_ids_off),
+            (self.methods.len(), method_ids_off),
+            (self.classes.len(), class_defs_off),
+            (data.len(), data_off),
+        ] {
+            push_u32(&mut out, size as u32);
+            push_u32(&mut out, if size == 0 { 0 } else { off as u32 });
+        }
+
+        for off in string_data_offs {
+            push_u32(&mut out, off as u32);
+        }
+        for t in &self.types {
+            push_u32(&mut out, *t);
+        }
+        for (proto, parameters_off) in self.protos.iter().zip(proto_parameters_offs) {
+            push_u32(&mut out, proto.shorty);
+            push_u32(&mut out, proto.return_type);
+            push_u32(&mut out, parameters_off as u32);
+        }
+        for (class, type_, name) in &self.fields {
+            push_u16(&mut out, *class as u16);
+            push_u16(&mut out, *type_ as u16);
+            push_u32(&mut out, *name);
+        }
+        for (class, proto, name) in &self.methods {
+            push_u16(&mut out, *class as u16);
+            push_u16(&mut out, *proto as u16);
+            push_u32(&mut out, *name);
+        }
+        for ((class, class_data_off), interfaces_off) in self
+            .classes
+            .iter()
+            .zip(class_data_offs)
+            .zip(interfaces_offs)
+        {
+            push_u32(&mut out, class.class_idx);
+            push_u32(&mut out, class.access_flags);
+            push_u32(&mut out, class.superclass_idx);
+            push_u32(&mut out, interfaces_off as u32);
+            push_u32(&mut out, class.source_file.unwrap_or(0xffff_ffff));
+            push_u32(&mut out, 0); // annotations_off
+            push_u32(&mut out, class_data_off as u32);
+            push_u32(&mut out, 0); // static_values_off
+        }
+        out.extend(data);
+        out
+    }
+
+    pub fn build_dex(self) -> Dex {
+        crate::parse(&self.build()).expect("valid test dex")
+    }
+}
+
+/// Pads `data` with zeros up to the next multiple of `n` bytes.
+pub fn align(data: &mut Vec<u8>, n: usize) {
+    data.resize(data.len().next_multiple_of(n), 0);
+}
+
+/// Appends a little-endian `u16`.
+pub fn push_u16(data: &mut Vec<u8>, v: u16) {
+    data.extend_from_slice(&v.to_le_bytes());
+}
+
+/// Appends a little-endian `u32`.
+pub fn push_u32(data: &mut Vec<u8>, v: u32) {
+    data.extend_from_slice(&v.to_le_bytes());
+}
+
+pub fn push_sleb128(data: &mut Vec<u8>, mut v: i32) {
+    loop {
+        let byte = (v & 0x7f) as u8;
+        v >>= 7;
+        if (v == 0 && byte & 0x40 == 0) || (v == -1 && byte & 0x40 != 0) {
+            data.push(byte);
+            return;
+        }
+        data.push(byte | 0x80);
+    }
+}
+
+pub fn push_uleb128(data: &mut Vec<u8>, mut v: u32) {
+    loop {
+        let byte = (v & 0x7f) as u8;
+        v >>= 7;
+        if v == 0 {
+            data.push(byte);
+            return;
+        }
+        data.push(byte | 0x80);
+    }
+}
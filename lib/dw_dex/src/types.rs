@@ -6,6 +6,7 @@ use crate::{Dex, DexCollection, DexIndex, Index, PrettyPrint};
 use serde::{Deserialize, Serialize};
 use std::convert::TryFrom;
 use std::fmt;
+use std::str::FromStr;
 
 /// The Dalvik type descriptor to be used for referencing it from other Dex data items.
 #[derive(Debug)]
@@ -122,6 +123,86 @@ impl PrettyPrint for ProtoIdItem {
     }
 }
 
+/// A method prototype, given by its return and parameters types, used to
+/// add prototypes to a [`Dex`] (see [`Dex::intern_method`]).
+///
+/// Prototypes are parsed from and displayed as JVM method descriptors, such
+/// as `(I[Ljava/lang/String;)V`.
+#[derive(Debug, Clone, PartialEq, Eq, PartialOrd, Ord)]
+pub struct ProtoDescr {
+    pub return_type: Type,
+    pub parameters: Vec<Type>,
+}
+
+impl TryFrom<&str> for ProtoDescr {
+    type Error = DexError;
+
+    fn try_from(s: &str) -> DexResult<Self> {
+        let conversion_error = || DexError::Conversion {
+            from: format!("&str ({s:?})"),
+            to: "ProtoDescr".to_string(),
+        };
+        let (parameters, return_type) = s
+            .strip_prefix('(')
+            .and_then(|s| s.split_once(')'))
+            .ok_or_else(conversion_error)?;
+
+        let mut parameters_types = Vec::new();
+        let mut rest = parameters;
+        while !rest.is_empty() {
+            let dims = rest.len() - rest.trim_start_matches('[').len();
+            let end = if rest[dims..].starts_with('L') {
+                dims + rest[dims..].find(';').ok_or_else(conversion_error)? + 1
+            } else {
+                dims + 1
+            };
+            let typ = rest.get(..end).ok_or_else(conversion_error)?;
+            match Type::try_from(typ) {
+                Ok(Type::Void) | Err(_) => return Err(conversion_error()),
+                Ok(typ) => parameters_types.push(typ),
+            }
+            rest = &rest[end..];
+        }
+
+        Ok(Self {
+            return_type: Type::try_from(return_type).map_err(|_| conversion_error())?,
+            parameters: parameters_types,
+        })
+    }
+}
+
+impl FromStr for ProtoDescr {
+    type Err = DexError;
+
+    fn from_str(s: &str) -> DexResult<Self> {
+        Self::try_from(s)
+    }
+}
+
+impl fmt::Display for ProtoDescr {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "(")?;
+        for typ in &self.parameters {
+            write!(f, "{typ}")?;
+        }
+        write!(f, "){}", self.return_type)
+    }
+}
+
+impl ProtoDescr {
+    /// Returns the [`Shorty`] descriptor string of the prototype.
+    #[must_use]
+    pub fn shorty(&self) -> String {
+        std::iter::once(&self.return_type)
+            .chain(&self.parameters)
+            .map(|typ| match typ {
+                Type::Array(..) | Type::Class(_) => "L".to_string(),
+                typ => typ.to_string(),
+            })
+            .collect()
+    }
+}
+
 #[derive(Debug)]
 pub(crate) struct TypeList {
     pub(crate) index: Index<TypeList>,
@@ -373,3 +454,31 @@ impl TryFrom<&str> for Shorty {
         }
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn proto_descriptors() {
+        let proto: ProtoDescr = "(I[Ljava/lang/String;)V".parse().unwrap();
+        assert_eq!(proto.return_type, Type::Void);
+        assert_eq!(
+            proto.parameters,
+            [
+                Type::Int,
+                Type::Array(1, Box::new(Type::Class("java/lang/String".to_string())))
+            ]
+        );
+        assert_eq!(proto.shorty(), "VIL");
+        assert_eq!(proto.to_string(), "(I[Ljava/lang/String;)V");
+
+        for descriptor in ["()V", "([[JLa/B;Z)La/C;", "(DF)[I"] {
+            let proto = ProtoDescr::try_from(descriptor).unwrap();
+            assert_eq!(proto.to_string(), descriptor);
+        }
+        for invalid in ["", "I)V", "(I", "(V)V", "(La/B)V", "(I)", "(I)X", "(Q)V"] {
+            assert!(invalid.parse::<ProtoDescr>().is_err(), "{invalid}");
+        }
+    }
+}
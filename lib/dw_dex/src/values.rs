@@ -1,14 +1,76 @@
-use crate::annotations::EncodedAnnotation;
+//! Dalvik bytecode encoded values, as found in static initial values and
+//! annotation elements.
+
+use crate::annotations::{Annotation, EncodedAnnotation};
 use crate::code::MethodHandleItem;
 use crate::errors::{DexError, DexResult};
 use crate::fields::FieldIdItem;
 use crate::methods::MethodIdItem;
 use crate::strings::StringIdItem;
-use crate::types::{ProtoIdItem, TypeIdItem};
+use crate::types::{ProtoIdItem, Type, TypeIdItem};
 use crate::{Dex, DexCollection, DexIndex, Index, PrettyPrint};
 use dw_utils::leb::Uleb128;
 use std::fmt;
 
+/// A decoded encoded value, whose references to the dex tables have been
+/// resolved. Arrays and annotations are decoded recursively.
+#[derive(Debug, Clone)]
+pub enum Value<'a> {
+    Byte(i8),
+    Short(i16),
+    Char(u16),
+    Int(i32),
+    Long(i64),
+    Float(f32),
+    Double(f64),
+    MethodType(&'a ProtoIdItem),
+    MethodHandle(&'a MethodHandleItem),
+    String(String),
+    Type(Type),
+    Field(&'a FieldIdItem),
+    Method(&'a MethodIdItem),
+    /// Enumeration constant, given as the field holding it.
+    Enum(&'a FieldIdItem),
+    Array(Vec<Value<'a>>),
+    Annotation(Annotation<'a>),
+    Null,
+    Boolean(bool),
+}
+
+impl PrettyPrint for Value<'_> {
+    fn pp(&self, f: &mut fmt::Formatter, dex: &Dex) -> DexResult<()> {
+        match self {
+            Self::Byte(v) => write!(f, "{v}")?,
+            Self::Short(v) => write!(f, "{v}")?,
+            Self::Char(v) => write!(f, "{v}")?,
+            Self::Int(v) => write!(f, "{v}")?,
+            Self::Long(v) => write!(f, "{v}")?,
+            Self::Float(v) => write!(f, "{v}")?,
+            Self::Double(v) => write!(f, "{v}")?,
+            Self::MethodType(proto) => proto.pp(f, dex)?,
+            Self::MethodHandle(handle) => handle.pp(f, dex)?,
+            Self::String(s) => write!(f, "{s:?}")?,
+            Self::Type(typ) => write!(f, "{typ}")?,
+            Self::Field(field) | Self::Enum(field) => field.pp(f, dex)?,
+            Self::Method(method) => method.pp(f, dex)?,
+            Self::Array(values) => {
+                write!(f, "[")?;
+                for (i, value) in values.iter().enumerate() {
+                    value.pp(f, dex)?;
+                    if i != values.len() - 1 {
+                        write!(f, ", ")?;
+                    }
+                }
+                write!(f, "]")?;
+            }
+            Self::Annotation(ann) => ann.pp(f, dex)?,
+            Self::Null => write!(f, "null")?,
+            Self::Boolean(b) => write!(f, "{b}")?,
+        };
+        Ok(())
+    }
+}
+
 #[derive(Debug)]
 pub(crate) enum EncodedValue {
     Byte(i8),
@@ -62,6 +124,29 @@ impl PrettyPrint for EncodedValue {
 }
 
 impl EncodedValue {
+    pub(crate) fn decode<'a>(&self, dex: &'a Dex) -> DexResult<Value<'a>> {
+        Ok(match self {
+            Self::Byte(v) => Value::Byte(*v),
+            Self::Short(_, v) => Value::Short(*v),
+            Self::Char(_, v) => Value::Char(*v),
+            Self::Int(_, v) => Value::Int(*v),
+            Self::Long(_, v) => Value::Long(*v),
+            Self::Float(_, v) => Value::Float(*v),
+            Self::Double(_, v) => Value::Double(*v),
+            Self::MethodType(_, proto) => Value::MethodType(proto.get(dex)?),
+            Self::MethodHandle(_, handle) => Value::MethodHandle(handle.get(dex)?),
+            Self::String(_, s) => Value::String(s.get(dex)?.to_string(dex)?),
+            Self::Type(_, typ) => Value::Type(typ.get(dex)?.to_type(dex)?),
+            Self::Field(_, field) => Value::Field(field.get(dex)?),
+            Self::Method(_, method) => Value::Method(method.get(dex)?),
+            Self::Enum(_, field) => Value::Enum(field.get(dex)?),
+            Self::Array(arr) => Value::Array(arr.decode(dex)?),
+            Self::Annotation(ann) => Value::Annotation(ann.decode(dex, None)?),
+            Self::Null => Value::Null,
+            Self::Boolean(b) => Value::Boolean(*b),
+        })
+    }
+
     pub(crate) fn size(&self) -> usize {
         1 + // value_tag
         match self {
@@ -128,7 +213,7 @@ impl PrettyPrint for EncodedArray {
         write!(f, "[")?;
         for (i, value) in self.values.iter().enumerate() {
             value.pp(f, dex)?;
-            if i == self.values.len() - 1 {
+            if i != self.values.len() - 1 {
                 write!(f, ", ")?;
             }
         }
@@ -138,6 +223,10 @@ impl PrettyPrint for EncodedArray {
 }
 
 impl EncodedArray {
+    pub(crate) fn decode<'a>(&self, dex: &'a Dex) -> DexResult<Vec<Value<'a>>> {
+        self.values.iter().map(|value| value.decode(dex)).collect()
+    }
+
     pub(crate) fn size(&self) -> usize {
         let values_size: usize = self.values.iter().map(EncodedValue::size).sum();
         self.size.size() + values_size
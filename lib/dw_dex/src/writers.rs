@@ -1,7 +1,7 @@
 use crate::annotations::*;
 use crate::classes::*;
 use crate::code::*;
-use crate::errors::DexResult;
+use crate::errors::{DexError, DexResult};
 use crate::fields::*;
 use crate::instrs::*;
 use crate::map::*;
@@ -14,18 +14,172 @@ use dw_utils::leb::Uleb128;
 use dw_utils::writers::*;
 use nom::number::Endianness;
 use sha1::{Digest, Sha1};
+use std::collections::BTreeMap;
 use std::io::{Cursor, Result, Seek, Write};
 
 const NO_INDEX: u32 = 0xFFFF_FFFF;
 
+/// Options controlling how a [`Dex`] is serialized by [`write_dex`].
+#[derive(Debug, Clone, Copy)]
+pub struct WriteOptions {
+    /// Recomputes the signature and checksum of the header (enabled by
+    /// default, as Android rejects dex files whose checksum is stale).
+    pub recompute_checksums: bool,
+    /// Omits debug information (source line numbers, local variable names).
+    pub strip_debug_info: bool,
+    /// Omits annotations directories, along with the annotations sets and
+    /// items they reference.
+    pub strip_annotations: bool,
+}
+
+impl Default for WriteOptions {
+    fn default() -> Self {
+        Self {
+            recompute_checksums: true,
+            strip_debug_info: false,
+            strip_annotations: false,
+        }
+    }
+}
+
+impl WriteOptions {
+    const fn strips(&self, typ: MapItemType) -> bool {
+        match typ {
+            MapItemType::DebugInfoItem => self.strip_debug_info,
+            MapItemType::AnnotationSetRefList
+            | MapItemType::AnnotationSetItem
+            | MapItemType::AnnotationItem
+            | MapItemType::AnnotationsDirectoryItem => self.strip_annotations,
+            _ => false,
+        }
+    }
+}
+
+/// Offsets of the data items in the written dex, indexed by their offsets in
+/// the parsed one. Offsets of stripped items are mapped to 0, unknown offsets
+/// are kept as is.
+#[derive(Debug, Default, PartialEq, Eq)]
+pub(crate) struct Relocations(pub(crate) BTreeMap<usize, usize>);
+
+impl Relocations {
+    pub(crate) fn get(&self, offset: usize) -> u32 {
+        self.0.get(&offset).copied().unwrap_or(offset) as u32
+    }
+
+    fn get_uleb(&self, offset: Uleb128) -> Uleb128 {
+        // the original encoding size is kept unless the new offset needs
+        // more bytes, which moves the following items again.
+        Uleb128::new(self.get(offset.value() as usize), Some(offset.size()))
+    }
+}
+
 /// Dex writing function. Borrows a [`Dex`] structure and returns a buffer.
-pub fn write_dex(dex: &Dex, recompute_checksums: bool) -> DexResult<Vec<u8>> {
+///
+/// When some items are stripped or have grown (such as code items with new
+/// exception handlers), the data items following them are moved and every
+/// offset referencing them, as well as the map list and the
+/// header, are updated accordingly.
+///
+/// The file size written in the header is always the one of the buffer.
+/// Unless disabled in `options`, the SHA-1 signature (of the bytes following
+/// it) and the Adler-32 checksum (of the bytes following it, signature
+/// included) are then recomputed.
+///
+/// Compact dex files cannot be written and make this function return
+/// [`DexError::UnsupportedCompactDexWrite`].
+pub fn write_dex(dex: &Dex, options: &WriteOptions) -> DexResult<Vec<u8>> {
     log::trace!("writing dex...");
 
+    if dex.header_item.compact {
+        return Err(DexError::UnsupportedCompactDexWrite);
+    }
+
+    let (mut buffer, mut relocations) = write_items(dex, options, &Relocations::default())?;
+    let moved = relocations.0.iter().any(|(old, new)| old != new);
+    let mut cursor = if options.strip_debug_info || options.strip_annotations || moved {
+        // items positions are known after a first pass, the next one writes
+        // offsets accordingly. As relocated offsets may change the size of
+        // their uleb128 encodings, passes are repeated until positions are
+        // stable.
+        loop {
+            let (next_buffer, next_relocations) = write_items(dex, options, &relocations)?;
+            buffer = next_buffer;
+            if next_relocations == relocations {
+                break;
+            }
+            relocations = next_relocations;
+        }
+        let mut cursor = Cursor::new(buffer);
+        cursor.set_position(52);
+        let _ = le_u32(&mut cursor, relocations.get(dex.header_item.map_off))?;
+        cursor.set_position(104);
+        let data_size = (dex.header_item.data_size + cursor.get_ref().len())
+            .saturating_sub(dex.header_item.file_size);
+        let _ = le_u32(&mut cursor, data_size as u32)?;
+        cursor
+    } else {
+        Cursor::new(buffer)
+    };
+
+    let file_size = cursor.get_ref().len();
+    cursor.set_position(32);
+    let _ = le_u32(&mut cursor, file_size as u32)?;
+
+    if options.recompute_checksums {
+        let signature = {
+            let buffer = cursor.get_ref();
+            let mut hasher = Sha1::new();
+            hasher.update(&buffer[32..]);
+            hasher.finalize()
+        };
+        cursor.set_position(12);
+        for byte in &signature {
+            let _ = le_u8(&mut cursor, *byte)?;
+        }
+        let checksum = {
+            let buffer = cursor.get_ref();
+            adler32::adler32(&buffer[12..])?
+        };
+        cursor.set_position(8);
+        let _ = le_u32(&mut cursor, checksum)?;
+    }
+
+    Ok(cursor.into_inner())
+}
+
+/// Writes all the items of `dex` in map order, using `relocations` to write
+/// offsets of data items. Returns the written buffer along with the
+/// positions of the data items in it.
+pub(crate) fn write_items(
+    dex: &Dex,
+    options: &WriteOptions,
+    relocations: &Relocations,
+) -> DexResult<(Vec<u8>, Relocations)> {
     let buffer: Vec<u8> = Vec::new();
     let mut cursor = Cursor::new(buffer);
+    let mut positions = BTreeMap::new();
 
     for map_item in &dex.map_list.list {
+        if options.strips(map_item.typ) {
+            let offsets: Vec<usize> = match map_item.typ {
+                MapItemType::DebugInfoItem => dex.debug_info_items.keys().copied().collect(),
+                MapItemType::AnnotationSetRefList => {
+                    dex.annotation_set_ref_lists.keys().copied().collect()
+                }
+                MapItemType::AnnotationSetItem => {
+                    dex.annotation_set_items.keys().copied().collect()
+                }
+                MapItemType::AnnotationItem => dex.annotation_items.keys().copied().collect(),
+                MapItemType::AnnotationsDirectoryItem => {
+                    dex.annotations_directory_items.keys().copied().collect()
+                }
+                _ => unreachable!("{:?} items cannot be stripped", map_item.typ),
+            };
+            log::trace!("stripping {} {:?}", offsets.len(), map_item.typ);
+            positions.extend(offsets.into_iter().map(|off| (off, 0)));
+            continue;
+        }
+
         match map_item.typ {
             MapItemType::HeaderItem => {
                 log::trace!("writing header_item");
@@ -35,7 +189,7 @@ pub fn write_dex(dex: &Dex, recompute_checksums: bool) -> DexResult<Vec<u8>> {
             MapItemType::StringIdItem => {
                 for item in &dex.string_id_items {
                     log::trace!("writing string_id_item");
-                    let wr = string_id_item_writer(&mut cursor, item)?;
+                    let wr = string_id_item_writer(&mut cursor, item, relocations)?;
                     debug_assert!(item.size() == wr);
                 }
             }
@@ -49,7 +203,7 @@ pub fn write_dex(dex: &Dex, recompute_checksums: bool) -> DexResult<Vec<u8>> {
             MapItemType::ProtoIdItem => {
                 for item in &dex.proto_id_items {
                     log::trace!("writing proto_id_item");
-                    let wr = proto_id_item_writer(&mut cursor, item)?;
+                    let wr = proto_id_item_writer(&mut cursor, item, relocations)?;
                     debug_assert!(item.size() == wr);
                 }
             }
@@ -70,14 +224,14 @@ pub fn write_dex(dex: &Dex, recompute_checksums: bool) -> DexResult<Vec<u8>> {
             MapItemType::ClassDefItem => {
                 for item in &dex.class_def_items {
                     log::trace!("writing class_def_item");
-                    let wr = class_def_item_writer(&mut cursor, item)?;
+                    let wr = class_def_item_writer(&mut cursor, item, relocations)?;
                     debug_assert!(item.size() == wr);
                 }
             }
             MapItemType::CallSiteIdItem => {
                 for item in &dex.call_site_id_items {
                     log::trace!("writing call_site_id_item");
-                    let wr = call_site_id_item_writer(&mut cursor, item)?;
+                    let wr = call_site_id_item_writer(&mut cursor, item, relocations)?;
                     debug_assert!(item.size() == wr);
                 }
             }
@@ -91,21 +245,28 @@ pub fn write_dex(dex: &Dex, recompute_checksums: bool) -> DexResult<Vec<u8>> {
             MapItemType::MapList => {
                 let _ = align4(&mut cursor)?;
                 log::trace!("writing map_list");
-                let wr = map_list_writer(&mut cursor, &dex.map_list)?;
-                debug_assert!(dex.map_list.size() == wr);
+                let _ = positions.insert(dex.header_item.map_off, cursor.position() as usize);
+                let wr = map_list_writer(&mut cursor, &dex.map_list, options, relocations)?;
+                debug_assert!(
+                    dex.map_list.size() == wr
+                        || options.strip_debug_info
+                        || options.strip_annotations
+                );
             }
             MapItemType::TypeList => {
-                for item in dex.type_lists.values() {
+                for (off, item) in &dex.type_lists {
                     let _ = align4(&mut cursor)?;
+                    let _ = positions.insert(*off, cursor.position() as usize);
                     log::trace!("writing type_list");
                     let _ = type_list_writer(&mut cursor, item)?;
                 }
             }
             MapItemType::AnnotationSetRefList => {
                 let _ = align4(&mut cursor)?;
-                for item in dex.annotation_set_ref_lists.values() {
+                for (off, item) in &dex.annotation_set_ref_lists {
+                    let _ = positions.insert(*off, cursor.position() as usize);
                     log::trace!("writing annotation_set_ref_list");
-                    let wr = annotation_set_ref_list_writer(&mut cursor, item)?;
+                    let wr = annotation_set_ref_list_writer(&mut cursor, item, relocations)?;
                     debug_assert!(item.size() == wr);
                     // declared as 4-byte-aligned in documentation,
                     // but should already be aligned by construction.
@@ -114,9 +275,10 @@ pub fn write_dex(dex: &Dex, recompute_checksums: bool) -> DexResult<Vec<u8>> {
             }
             MapItemType::AnnotationSetItem => {
                 let _ = align4(&mut cursor)?;
-                for item in dex.annotation_set_items.values() {
+                for (off, item) in &dex.annotation_set_items {
+                    let _ = positions.insert(*off, cursor.position() as usize);
                     log::trace!("writing annotation_set_item");
-                    let wr = annotation_set_item_writer(&mut cursor, item)?;
+                    let wr = annotation_set_item_writer(&mut cursor, item, relocations)?;
                     debug_assert!(item.size() == wr);
                     // declared as 4-byte-aligned in documentation,
                     // but should already be aligned by construction.
@@ -124,42 +286,48 @@ pub fn write_dex(dex: &Dex, recompute_checksums: bool) -> DexResult<Vec<u8>> {
                 }
             }
             MapItemType::ClassDataItem => {
-                for item in dex.class_data_items.values() {
+                for (off, item) in &dex.class_data_items {
+                    let _ = positions.insert(*off, cursor.position() as usize);
                     log::trace!("writing class_data_item");
-                    let wr = class_data_item_writer(&mut cursor, item)?;
+                    let wr = class_data_item_writer(&mut cursor, item, relocations)?;
                     debug_assert!(item.size() == wr);
                 }
             }
             MapItemType::CodeItem => {
-                for item in dex.code_items.values() {
+                for (off, item) in &dex.code_items {
                     let _ = align4(&mut cursor)?;
+                    let _ = positions.insert(*off, cursor.position() as usize);
                     log::trace!("writing code_item");
-                    let _ = code_item_writer(&mut cursor, &item.read().unwrap())?;
+                    let _ = code_item_writer(&mut cursor, &item.read().unwrap(), relocations)?;
                 }
             }
             MapItemType::StringDataItem => {
-                for item in dex.string_data_items.values() {
+                for (off, item) in &dex.string_data_items {
+                    let _ = positions.insert(*off, cursor.position() as usize);
                     log::trace!("writing string_data_item");
                     let wr = string_data_item_writer(&mut cursor, item)?;
                     debug_assert!(item.size() == wr);
                 }
             }
             MapItemType::DebugInfoItem => {
-                for item in dex.debug_info_items.values() {
+                for (off, item) in &dex.debug_info_items {
+                    let _ = positions.insert(*off, cursor.position() as usize);
                     log::trace!("writing debug_info_item");
                     let wr = debug_info_item_writer(&mut cursor, item)?;
                     debug_assert!(item.size() == wr);
                 }
             }
             MapItemType::AnnotationItem => {
-                for item in dex.annotation_items.values() {
+                for (off, item) in &dex.annotation_items {
+                    let _ = positions.insert(*off, cursor.position() as usize);
                     log::trace!("writing annotation_item");
                     let wr = annotation_item_writer(&mut cursor, item)?;
                     debug_assert!(item.size() == wr);
                 }
             }
             MapItemType::EncodedArrayItem => {
-                for item in dex.encoded_array_items.values() {
+                for (off, item) in &dex.encoded_array_items {
+                    let _ = positions.insert(*off, cursor.position() as usize);
                     log::trace!("writing encoded_array_item");
                     let wr = encoded_array_item_writer(&mut cursor, item)?;
                     debug_assert!(item.size() == wr);
@@ -167,9 +335,10 @@ pub fn write_dex(dex: &Dex, recompute_checksums: bool) -> DexResult<Vec<u8>> {
             }
             MapItemType::AnnotationsDirectoryItem => {
                 let _ = align4(&mut cursor)?;
-                for item in dex.annotations_directory_items.values() {
+                for (off, item) in &dex.annotations_directory_items {
+                    let _ = positions.insert(*off, cursor.position() as usize);
                     log::trace!("writing annotations_directory_item");
-                    let wr = annotations_director_item_writer(&mut cursor, item)?;
+                    let wr = annotations_director_item_writer(&mut cursor, item, relocations)?;
                     debug_assert!(item.size() == wr);
                     // declared as 4-byte-aligned in documentation,
                     // but should already be aligned by construction.
@@ -177,7 +346,8 @@ pub fn write_dex(dex: &Dex, recompute_checksums: bool) -> DexResult<Vec<u8>> {
                 }
             }
             MapItemType::HiddenapiClassDataItem => {
-                for item in dex.hiddenapi_class_data_items.values() {
+                for (off, item) in &dex.hiddenapi_class_data_items {
+                    let _ = positions.insert(*off, cursor.position() as usize);
                     log::trace!("writing hidden_class_data_item");
                     let wr = hiddenapi_class_data_item_writer(&mut cursor, item)?;
                     debug_assert!(item.size() == wr);
@@ -186,26 +356,7 @@ pub fn write_dex(dex: &Dex, recompute_checksums: bool) -> DexResult<Vec<u8>> {
         }
     }
 
-    if recompute_checksums {
-        let signature = {
-            let buffer = cursor.get_ref();
-            let mut hasher = Sha1::new();
-            hasher.update(&buffer[32..]);
-            hasher.finalize()
-        };
-        cursor.set_position(12);
-        for byte in &signature {
-            let _ = le_u8(&mut cursor, *byte)?;
-        }
-        let checksum = {
-            let buffer = cursor.get_ref();
-            adler32::adler32(&buffer[12..])?
-        };
-        cursor.set_position(8);
-        let _ = le_u32(&mut cursor, checksum)?;
-    }
-
-    Ok(cursor.into_inner())
+    Ok((cursor.into_inner(), Relocations(positions)))
 }
 
 fn magic_writer<W: Write>(output: &mut W, v: u32) -> Result<usize> {
@@ -257,15 +408,23 @@ fn header_item_writer<W: Write>(output: &mut W, item: &HeaderItem) -> Result<usi
     Ok(siz)
 }
 
-fn string_id_item_writer<W: Write>(output: &mut W, item: &StringIdItem) -> Result<usize> {
-    le_u32(output, item.string_data_off.as_usize() as u32)
+fn string_id_item_writer<W: Write>(
+    output: &mut W,
+    item: &StringIdItem,
+    relocations: &Relocations,
+) -> Result<usize> {
+    le_u32(output, relocations.get(item.string_data_off.as_usize()))
 }
 
 fn type_id_item_writer<W: Write>(output: &mut W, item: &TypeIdItem) -> Result<usize> {
     le_u32(output, item.descriptor_idx.as_usize() as u32)
 }
 
-fn proto_id_item_writer<W: Write>(output: &mut W, item: &ProtoIdItem) -> Result<usize> {
+fn proto_id_item_writer<W: Write>(
+    output: &mut W,
+    item: &ProtoIdItem,
+    relocations: &Relocations,
+) -> Result<usize> {
     let mut siz = 0;
     siz += le_u32(output, item.shorty_idx.as_usize() as u32)?;
     siz += le_u32(output, item.return_type_idx.as_usize() as u32)?;
@@ -273,7 +432,7 @@ fn proto_id_item_writer<W: Write>(output: &mut W, item: &ProtoIdItem) -> Result<
         output,
         item.parameters_off
             .as_ref()
-            .map_or(0, |off| off.as_usize() as u32),
+            .map_or(0, |off| relocations.get(off.as_usize())),
     )?;
     Ok(siz)
 }
@@ -294,7 +453,11 @@ fn method_id_item_writer<W: Write>(output: &mut W, item: &MethodIdItem) -> Resul
     Ok(siz)
 }
 
-fn class_def_item_writer<W: Write>(output: &mut W, item: &ClassDefItem) -> Result<usize> {
+fn class_def_item_writer<W: Write>(
+    output: &mut W,
+    item: &ClassDefItem,
+    relocations: &Relocations,
+) -> Result<usize> {
     let mut siz = 0;
     siz += le_u32(output, item.class_idx.as_usize() as u32)?;
     siz += le_u32(output, item.access_flags.bits())?;
@@ -308,7 +471,7 @@ fn class_def_item_writer<W: Write>(output: &mut W, item: &ClassDefItem) -> Resul
         output,
         item.interfaces_off
             .as_ref()
-            .map_or(0, |off| off.as_usize() as u32),
+            .map_or(0, |off| relocations.get(off.as_usize())),
     )?;
     siz += le_u32(
         output,
@@ -320,25 +483,29 @@ fn class_def_item_writer<W: Write>(output: &mut W, item: &ClassDefItem) -> Resul
         output,
         item.annotations_off
             .as_ref()
-            .map_or(0, |off| off.as_usize() as u32),
+            .map_or(0, |off| relocations.get(off.as_usize())),
     )?;
     siz += le_u32(
         output,
         item.class_data_off
             .as_ref()
-            .map_or(0, |off| off.as_usize() as u32),
+            .map_or(0, |off| relocations.get(off.as_usize())),
     )?;
     siz += le_u32(
         output,
         item.static_values_off
             .as_ref()
-            .map_or(0, |off| off.as_usize() as u32),
+            .map_or(0, |off| relocations.get(off.as_usize())),
     )?;
     Ok(siz)
 }
 
-fn call_site_id_item_writer<W: Write>(output: &mut W, item: &CallSiteIdItem) -> Result<usize> {
-    le_u32(output, item.call_site_off.as_usize() as u32)
+fn call_site_id_item_writer<W: Write>(
+    output: &mut W,
+    item: &CallSiteIdItem,
+    relocations: &Relocations,
+) -> Result<usize> {
+    le_u32(output, relocations.get(item.call_site_off.as_usize()))
 }
 
 fn method_handle_item_writer<W: Write>(output: &mut W, item: &MethodHandleItem) -> Result<usize> {
@@ -361,16 +528,30 @@ fn method_handle_item_writer<W: Write>(output: &mut W, item: &MethodHandleItem)
     Ok(siz)
 }
 
-fn map_list_writer<W: Write>(output: &mut W, list: &MapList) -> Result<usize> {
+fn map_list_writer<W: Write>(
+    output: &mut W,
+    list: &MapList,
+    options: &WriteOptions,
+    relocations: &Relocations,
+) -> Result<usize> {
+    let items: Vec<_> = list
+        .list
+        .iter()
+        .filter(|item| !options.strips(item.typ))
+        .collect();
     let mut siz = 0;
-    siz += le_u32(output, list.list.len() as u32)?;
-    for item in &list.list {
-        siz += map_item_writer(output, item)?;
+    siz += le_u32(output, items.len() as u32)?;
+    for item in items {
+        siz += map_item_writer(output, item, relocations)?;
     }
     Ok(siz)
 }
 
-fn map_item_writer<W: Write>(output: &mut W, item: &MapItem) -> Result<usize> {
+fn map_item_writer<W: Write>(
+    output: &mut W,
+    item: &MapItem,
+    relocations: &Relocations,
+) -> Result<usize> {
     let v: u16 = match item.typ {
         MapItemType::HeaderItem => 0x0000,
         MapItemType::StringIdItem => 0x0001,
@@ -398,7 +579,7 @@ fn map_item_writer<W: Write>(output: &mut W, item: &MapItem) -> Result<usize> {
     siz += le_u16(output, v)?;
     siz += le_u16(output, 0)?;
     siz += le_u32(output, item.size as u32)?;
-    siz += le_u32(output, item.offset as u32)?;
+    siz += le_u32(output, relocations.get(item.offset))?;
     Ok(siz)
 }
 
@@ -418,11 +599,12 @@ fn type_item_writer<W: Write>(output: &mut W, item: &TypeItem) -> Result<usize>
 fn annotation_set_ref_list_writer<W: Write>(
     output: &mut W,
     list: &AnnotationSetRefList,
+    relocations: &Relocations,
 ) -> Result<usize> {
     let mut siz = 0;
     siz += le_u32(output, list.list.len() as u32)?;
     for item in &list.list {
-        siz += annotation_set_ref_item_writer(output, item)?;
+        siz += annotation_set_ref_item_writer(output, item, relocations)?;
     }
     Ok(siz)
 }
@@ -430,24 +612,37 @@ fn annotation_set_ref_list_writer<W: Write>(
 fn annotation_set_ref_item_writer<W: Write>(
     output: &mut W,
     item: &AnnotationSetRefItem,
+    relocations: &Relocations,
 ) -> Result<usize> {
-    le_u32(output, item.annotations_off.as_usize() as u32)
+    le_u32(output, relocations.get(item.annotations_off.as_usize()))
 }
 
-fn annotation_set_item_writer<W: Write>(output: &mut W, item: &AnnotationSetItem) -> Result<usize> {
+fn annotation_set_item_writer<W: Write>(
+    output: &mut W,
+    item: &AnnotationSetItem,
+    relocations: &Relocations,
+) -> Result<usize> {
     let mut siz = 0;
     siz += le_u32(output, item.entries.len() as u32)?;
     for entry in &item.entries {
-        siz += annotation_off_item_writer(output, entry)?;
+        siz += annotation_off_item_writer(output, entry, relocations)?;
     }
     Ok(siz)
 }
 
-fn annotation_off_item_writer<W: Write>(output: &mut W, item: &AnnotationOffItem) -> Result<usize> {
-    le_u32(output, item.annotation_off.as_usize() as u32)
+fn annotation_off_item_writer<W: Write>(
+    output: &mut W,
+    item: &AnnotationOffItem,
+    relocations: &Relocations,
+) -> Result<usize> {
+    le_u32(output, relocations.get(item.annotation_off.as_usize()))
 }
 
-fn class_data_item_writer<W: Write>(output: &mut W, item: &ClassDataItem) -> Result<usize> {
+fn class_data_item_writer<W: Write>(
+    output: &mut W,
+    item: &ClassDataItem,
+    relocations: &Relocations,
+) -> Result<usize> {
     let mut siz = 0;
     siz += uleb128(output, item.static_fields_size)?;
     siz += uleb128(output, item.instance_fields_size)?;
@@ -461,10 +656,10 @@ fn class_data_item_writer<W: Write>(output: &mut W, item: &ClassDataItem) -> Res
         siz += encoded_field_writer(output, field)?;
     }
     for method in &item.direct_methods {
-        siz += encoded_method_writer(output, method)?;
+        siz += encoded_method_writer(output, method, relocations)?;
     }
     for method in &item.virtual_methods {
-        siz += encoded_method_writer(output, method)?;
+        siz += encoded_method_writer(output, method, relocations)?;
     }
     Ok(siz)
 }
@@ -476,7 +671,11 @@ fn encoded_field_writer<W: Write>(output: &mut W, field: &EncodedField) -> Resul
     Ok(siz)
 }
 
-fn encoded_method_writer<W: Write>(output: &mut W, method: &EncodedMethod) -> Result<usize> {
+fn encoded_method_writer<W: Write>(
+    output: &mut W,
+    method: &EncodedMethod,
+    relocations: &Relocations,
+) -> Result<usize> {
     let mut siz = 0;
     siz += uleb128(output, method.method_idx_diff)?;
     siz += uleb128(output, method.access_flags_repr)?;
@@ -485,12 +684,18 @@ fn encoded_method_writer<W: Write>(output: &mut W, method: &EncodedMethod) -> Re
         method
             .code_off
             .as_ref()
-            .map_or(Uleb128::new(0, None), |off| off.as_uleb()),
+            .map_or(Uleb128::new(0, None), |off| {
+                relocations.get_uleb(off.as_uleb())
+            }),
     )?;
     Ok(siz)
 }
 
-fn code_item_writer<W: Write>(output: &mut W, item: &CodeItem) -> Result<usize> {
+fn code_item_writer<W: Write>(
+    output: &mut W,
+    item: &CodeItem,
+    relocations: &Relocations,
+) -> Result<usize> {
     let mut siz = 0;
     siz += le_u16(output, item.registers_size as u16)?;
     siz += le_u16(output, item.ins_size as u16)?;
@@ -500,7 +705,7 @@ fn code_item_writer<W: Write>(output: &mut W, item: &CodeItem) -> Result<usize>
         output,
         item.debug_info_off
             .as_ref()
-            .map_or(0, |off| off.as_usize() as u32),
+            .map_or(0, |off| relocations.get(off.as_usize())),
     )?;
     let insns_size = item.insns.iter().map(|i| i.size() as u32).sum();
     siz += le_u32(output, insns_size)?;
@@ -508,7 +713,7 @@ fn code_item_writer<W: Write>(output: &mut W, item: &CodeItem) -> Result<usize>
         siz += write_instr(output, instr.instr())?;
     }
     if insns_size % 2 == 1 && !item.tries.is_empty() {
-        siz += le_u32(output, 0x0000)?;
+        siz += le_u16(output, 0x0000)?;
     }
     for trie in &item.tries {
         siz += try_item_writer(output, trie)?;
@@ -682,6 +887,12 @@ fn annotation_element_writer<W: Write>(
     Ok(siz)
 }
 
+/// Returns the `value_type` byte of an encoded value of the given type,
+/// whose `value_arg` holds the size of the value minus one.
+const fn value_tag(typ: u8, siz: usize) -> u8 {
+    (((siz - 1) as u8) << 5) | typ
+}
+
 fn encoded_value_writer<W: Write>(output: &mut W, value: &EncodedValue) -> Result<usize> {
     let mut siz = 0;
     match value {
@@ -690,55 +901,55 @@ fn encoded_value_writer<W: Write>(output: &mut W, value: &EncodedValue) -> Resul
             siz += le_i8(output, *b)?;
         }
         EncodedValue::Short(s, val) => {
-            siz += le_u8(output, (1 << 5) + 0x02)?;
+            siz += le_u8(output, value_tag(0x02, *s))?;
             siz += le_i16_on(output, *val, *s)?;
         }
         EncodedValue::Char(s, val) => {
-            siz += le_u8(output, (1 << 5) + 0x03)?;
+            siz += le_u8(output, value_tag(0x03, *s))?;
             siz += le_u16_on(output, *val, *s)?;
         }
         EncodedValue::Int(s, val) => {
-            siz += le_u8(output, (3 << 5) + 0x04)?;
+            siz += le_u8(output, value_tag(0x04, *s))?;
             siz += le_i32_on(output, *val, *s)?;
         }
         EncodedValue::Long(s, val) => {
-            siz += le_u8(output, (7 << 5) + 0x06)?;
+            siz += le_u8(output, value_tag(0x06, *s))?;
             siz += le_i64_on(output, *val, *s)?;
         }
         EncodedValue::Float(s, val) => {
-            siz += le_u8(output, (3 << 5) + 0x10)?;
+            siz += le_u8(output, value_tag(0x10, *s))?;
             siz += le_f32_on(output, *val, *s)?;
         }
         EncodedValue::Double(s, val) => {
-            siz += le_u8(output, (7 << 5) + 0x11)?;
+            siz += le_u8(output, value_tag(0x11, *s))?;
             siz += le_f64_on(output, *val, *s)?;
         }
         EncodedValue::MethodType(s, idx) => {
-            siz += le_u8(output, (3 << 5) + 0x15)?;
+            siz += le_u8(output, value_tag(0x15, *s))?;
             siz += le_u32_on(output, idx.as_usize() as u32, *s)?;
         }
         EncodedValue::MethodHandle(s, idx) => {
-            siz += le_u8(output, (3 << 5) + 0x16)?;
+            siz += le_u8(output, value_tag(0x16, *s))?;
             siz += le_u32_on(output, idx.as_usize() as u32, *s)?;
         }
         EncodedValue::String(s, idx) => {
-            siz += le_u8(output, (3 << 5) + 0x17)?;
+            siz += le_u8(output, value_tag(0x17, *s))?;
             siz += le_u32_on(output, idx.as_usize() as u32, *s)?;
         }
         EncodedValue::Type(s, idx) => {
-            siz += le_u8(output, (3 << 5) + 0x18)?;
+            siz += le_u8(output, value_tag(0x18, *s))?;
             siz += le_u32_on(output, idx.as_usize() as u32, *s)?;
         }
         EncodedValue::Field(s, idx) => {
-            siz += le_u8(output, (3 << 5) + 0x19)?;
+            siz += le_u8(output, value_tag(0x19, *s))?;
             siz += le_u32_on(output, idx.as_usize() as u32, *s)?;
         }
         EncodedValue::Method(s, idx) => {
-            siz += le_u8(output, (3 << 5) + 0x1a)?;
+            siz += le_u8(output, value_tag(0x1a, *s))?;
             siz += le_u32_on(output, idx.as_usize() as u32, *s)?;
         }
         EncodedValue::Enum(s, idx) => {
-            siz += le_u8(output, (3 << 5) + 0x1b)?;
+            siz += le_u8(output, value_tag(0x1b, *s))?;
             siz += le_u32_on(output, idx.as_usize() as u32, *s)?;
         }
         EncodedValue::Array(vs) => {
@@ -779,20 +990,24 @@ fn encoded_array_writer<W: Write>(output: &mut W, array: &EncodedArray) -> Resul
 fn annotations_director_item_writer<W: Write>(
     output: &mut W,
     item: &AnnotationsDirectoryItem,
+    relocations: &Relocations,
 ) -> Result<usize> {
     let mut siz = 0;
-    siz += le_u32(output, item.class_annotations_off.as_usize() as u32)?;
+    siz += le_u32(
+        output,
+        relocations.get(item.class_annotations_off.as_usize()),
+    )?;
     siz += le_u32(output, item.field_annotations.len() as u32)?;
     siz += le_u32(output, item.method_annotations.len() as u32)?;
     siz += le_u32(output, item.parameter_annotations.len() as u32)?;
     for annot in &item.field_annotations {
-        siz += field_annotation_writer(output, annot)?;
+        siz += field_annotation_writer(output, annot, relocations)?;
     }
     for annot in &item.method_annotations {
-        siz += method_annotation_writer(output, annot)?;
+        siz += method_annotation_writer(output, annot, relocations)?;
     }
     for annot in &item.parameter_annotations {
-        siz += parameter_annotation_writer(output, annot)?;
+        siz += parameter_annotation_writer(output, annot, relocations)?;
     }
     Ok(siz)
 }
@@ -800,30 +1015,42 @@ fn annotations_director_item_writer<W: Write>(
 fn field_annotation_writer<W: Write>(
     output: &mut W,
     annotation: &FieldAnnotation,
+    relocations: &Relocations,
 ) -> Result<usize> {
     let mut siz = 0;
     siz += le_u32(output, annotation.field_idx.as_usize() as u32)?;
-    siz += le_u32(output, annotation.annotations_off.as_usize() as u32)?;
+    siz += le_u32(
+        output,
+        relocations.get(annotation.annotations_off.as_usize()),
+    )?;
     Ok(siz)
 }
 
 fn method_annotation_writer<W: Write>(
     output: &mut W,
     annotation: &MethodAnnotation,
+    relocations: &Relocations,
 ) -> Result<usize> {
     let mut siz = 0;
     siz += le_u32(output, annotation.method_idx.as_usize() as u32)?;
-    siz += le_u32(output, annotation.annotations_off.as_usize() as u32)?;
+    siz += le_u32(
+        output,
+        relocations.get(annotation.annotations_off.as_usize()),
+    )?;
     Ok(siz)
 }
 
 fn parameter_annotation_writer<W: Write>(
     output: &mut W,
     annotation: &ParameterAnnotation,
+    relocations: &Relocations,
 ) -> Result<usize> {
     let mut siz = 0;
     siz += le_u32(output, annotation.method_idx.as_usize() as u32)?;
-    siz += le_u32(output, annotation.annotations_off.as_usize() as u32)?;
+    siz += le_u32(
+        output,
+        relocations.get(annotation.annotations_off.as_usize()),
+    )?;
     Ok(siz)
 }
 
@@ -841,7 +1068,7 @@ fn hiddenapi_class_data_item_writer<W: Write>(
     Ok(siz)
 }
 
-fn write_instr<W: Write>(output: &mut W, instr: &Instr) -> Result<usize> {
+pub(crate) fn write_instr<W: Write>(output: &mut W, instr: &Instr) -> Result<usize> {
     let mut siz = 0;
     match instr {
         Instr::Nop => siz += le_u16(output, 0x0000)?,
@@ -2289,3 +2516,240 @@ fn write_51l<W: Write>(output: &mut W, a: u8, b: i64) -> Result<usize> {
     siz += le_i64(output, b)?;
     Ok(siz)
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::testing::{push_u16, push_u32};
+    use crate::Addr;
+
+    #[test]
+    fn encoded_values_with_short_sizes() {
+        // value_arg holds the number of bytes of the value minus one
+        for (value, expected) in [
+            (EncodedValue::Short(1, 0x12), vec![0x02, 0x12]),
+            (EncodedValue::Char(2, 0x1234), vec![0x23, 0x34, 0x12]),
+            (EncodedValue::Int(1, 5), vec![0x04, 0x05]),
+            (EncodedValue::Long(2, 0x1234), vec![0x26, 0x34, 0x12]),
+        ] {
+            let mut output = Vec::new();
+            let siz = encoded_value_writer(&mut output, &value).unwrap();
+            assert_eq!(output, expected, "{value:?}");
+            assert_eq!(siz, value.size());
+        }
+    }
+
+    /// Assembles a dex declaring `Test.run()`, whose code item has debug
+    /// information laid out before it, so that stripping it moves the code.
+    fn dex_with_debug_info() -> Vec<u8> {
+        // string ids, type ids, proto ids, method ids and class defs
+        let ids_size = 3 * 4 + 2 * 4 + 12 + 8 + 32;
+        let data_off = 0x70 + ids_size;
+
+        let mut data = Vec::new();
+        let debug_off = data_off;
+        data.extend_from_slice(&[1, 0, 0]); // line_start, parameters_size, DBG_END_SEQUENCE
+        data.resize(data.len().next_multiple_of(4), 0);
+        let code_off = data_off + data.len() as u32;
+        push_u16(&mut data, 1); // registers_size
+        push_u16(&mut data, 1); // ins_size
+        push_u16(&mut data, 0); // outs_size
+        push_u16(&mut data, 0); // tries_size
+        push_u32(&mut data, debug_off);
+        push_u32(&mut data, 1); // insns_size
+        push_u16(&mut data, 0x000e); // return-void
+        let class_data_off = data_off + data.len() as u32;
+        data.extend_from_slice(&[0, 0, 0, 1, 0, 1]);
+        data.extend_from_slice(&[(code_off as u8) | 0x80, (code_off >> 7) as u8]);
+        let mut string_offs = Vec::new();
+        for string in ["LTest;", "V", "run"] {
+            string_offs.push(data_off + data.len() as u32);
+            data.push(string.len() as u8);
+            data.extend_from_slice(string.as_bytes());
+            data.push(0);
+        }
+        data.resize(data.len().next_multiple_of(4), 0);
+        let map_off = data_off + data.len() as u32;
+        let map = [
+            (0x0000, 1, 0),
+            (0x0001, 3, 0x70),
+            (0x0002, 2, 0x7c),
+            (0x0003, 1, 0x84),
+            (0x0005, 1, 0x90),
+            (0x0006, 1, 0x98),
+            (0x2003, 1, debug_off),
+            (0x2001, 1, code_off),
+            (0x2000, 1, class_data_off),
+            (0x2002, 3, string_offs[0]),
+            (0x1000, 1, map_off),
+        ];
+        push_u32(&mut data, map.len() as u32);
+        for (typ, size, off) in map {
+            push_u16(&mut data, typ);
+            push_u16(&mut data, 0);
+            push_u32(&mut data, size);
+            push_u32(&mut data, off);
+        }
+
+        let mut dex = b"dex\n035\0".to_vec();
+        push_u32(&mut dex, 0); // checksum
+        dex.extend_from_slice(&[0; 20]); // signature
+        push_u32(&mut dex, data_off + data.len() as u32);
+        push_u32(&mut dex, 0x70);
+        push_u32(&mut dex, 0x1234_5678);
+        push_u32(&mut dex, 0); // link_size
+        push_u32(&mut dex, 0); // link_off
+        push_u32(&mut dex, map_off);
+        for (size, off) in [
+            (3, 0x70),
+            (2, 0x7c),
+            (1, 0x84),
+            (0, 0),
+            (1, 0x90),
+            (1, 0x98),
+        ] {
+            push_u32(&mut dex, size);
+            push_u32(&mut dex, off);
+        }
+        push_u32(&mut dex, data.len() as u32);
+        push_u32(&mut dex, data_off);
+
+        for off in string_offs {
+            push_u32(&mut dex, off);
+        }
+        push_u32(&mut dex, 0); // LTest;
+        push_u32(&mut dex, 1); // V
+        push_u32(&mut dex, 1); // shorty "V"
+        push_u32(&mut dex, 1); // returns V
+        push_u32(&mut dex, 0); // no parameters
+        push_u16(&mut dex, 0); // Test
+        push_u16(&mut dex, 0); // ()V
+        push_u32(&mut dex, 2); // run
+        for v in [0, 1, NO_INDEX, 0, NO_INDEX, 0, class_data_off, 0] {
+            push_u32(&mut dex, v);
+        }
+        dex.extend(data);
+        sign(&mut dex);
+        dex
+    }
+
+    /// Computes the signature and checksum of an assembled dex as specified
+    /// by the dex format.
+    fn sign(dex: &mut [u8]) {
+        let signature = Sha1::digest(&dex[32..]);
+        dex[12..32].copy_from_slice(&signature);
+        let checksum = adler32::adler32(&dex[12..]).unwrap();
+        dex[8..12].copy_from_slice(&checksum.to_le_bytes());
+    }
+
+    #[test]
+    fn write_unchanged() {
+        let input = dex_with_debug_info();
+        let dex = crate::parse(&input).unwrap();
+        let output = write_dex(&dex, &WriteOptions::default()).unwrap();
+        assert_eq!(output, input);
+    }
+
+    #[test]
+    fn write_recomputes_checksums() {
+        let signed = dex_with_debug_info();
+        let mut input = signed.clone();
+        input[8..32].fill(0);
+
+        let dex = crate::parse(&input).unwrap();
+        let options = WriteOptions {
+            recompute_checksums: false,
+            ..WriteOptions::default()
+        };
+        assert_eq!(write_dex(&dex, &options).unwrap(), input);
+        assert_eq!(write_dex(&dex, &WriteOptions::default()).unwrap(), signed);
+
+        let mut dex = crate::parse(&input).unwrap();
+        dex.recompute_header().unwrap();
+        assert_eq!(dex.header_item.checksum.to_le_bytes(), signed[8..12]);
+        assert_eq!(dex.header_item.signature, signed[12..32]);
+        assert_eq!(write_dex(&dex, &options).unwrap(), signed);
+    }
+
+    #[test]
+    fn strip_debug_info() {
+        let input = dex_with_debug_info();
+        let dex = crate::parse(&input).unwrap();
+        assert_eq!(dex.debug_info_items.len(), 1);
+
+        let options = WriteOptions {
+            strip_debug_info: true,
+            ..WriteOptions::default()
+        };
+        let output = write_dex(&dex, &options).unwrap();
+        assert!(output.len() < input.len());
+
+        let stripped = crate::parse(&output).unwrap();
+        assert!(stripped.debug_info_items.is_empty());
+        assert_eq!(stripped.header_item.file_size, output.len());
+        let class = stripped.iter_class_defs().next().unwrap();
+        let method = &class.data(&stripped).unwrap().unwrap().virtual_methods[0];
+        let code = method.code(&stripped).unwrap().unwrap().read().unwrap();
+        assert!(code.debug_info_off.is_none());
+        assert_eq!(code.insns.len(), 1);
+    }
+
+    #[test]
+    fn patch_instruction() {
+        let dex = crate::parse(&dex_with_debug_info()).unwrap();
+        let class = dex.iter_class_defs().next().unwrap();
+        let method = &class.data(&dex).unwrap().unwrap().virtual_methods[0];
+        {
+            let mut code = method.code(&dex).unwrap().unwrap().write().unwrap();
+            assert_eq!(code.instruction_bytes(Addr(0)).unwrap(), [0x000e]);
+            assert!(matches!(
+                code.patch_instruction(Addr(0), &Instr::Const16(0u8.into(), 0)),
+                Err(DexError::BadInstructionSize)
+            ));
+            assert!(matches!(
+                code.patch_instruction(Addr(1), &Instr::Nop),
+                Err(DexError::InstructionNotFound(_))
+            ));
+            code.patch_instruction(Addr(0), &Instr::Nop).unwrap();
+        }
+
+        let output = write_dex(&dex, &WriteOptions::default()).unwrap();
+        let patched = crate::parse(&output).unwrap();
+        let class = patched.iter_class_defs().next().unwrap();
+        let method = &class.data(&patched).unwrap().unwrap().virtual_methods[0];
+        let code = method.code(&patched).unwrap().unwrap().read().unwrap();
+        assert_eq!(code.instruction_bytes(Addr(0)).unwrap(), [0x0000]);
+        assert!(matches!(
+            code.instruction_at(Addr(0)).unwrap().instr(),
+            Instr::Nop
+        ));
+    }
+
+    #[test]
+    fn add_catch_all() {
+        let dex = crate::parse(&dex_with_debug_info()).unwrap();
+        let class = dex.iter_class_defs().next().unwrap();
+        let method = &class.data(&dex).unwrap().unwrap().virtual_methods[0];
+        method
+            .code(&dex)
+            .unwrap()
+            .unwrap()
+            .write()
+            .unwrap()
+            .add_catch_all(Addr(0), Addr(1), Addr(0))
+            .unwrap();
+
+        let output = write_dex(&dex, &WriteOptions::default()).unwrap();
+        let patched = crate::parse(&output).unwrap();
+        let class = patched.iter_class_defs().next().unwrap();
+        let method = &class.data(&patched).unwrap().unwrap().virtual_methods[0];
+        let code = method.code(&patched).unwrap().unwrap().read().unwrap();
+        let tries: Vec<_> = code.iter_tries().collect();
+        assert_eq!(tries.len(), 1);
+        assert_eq!(tries[0].start_addr(), Addr(0));
+        assert_eq!(tries[0].end_addr(), Addr(1));
+        let handlers = tries[0].handlers(&code).unwrap();
+        assert_eq!(handlers.iter_handlers().count(), 0);
+        assert_eq!(handlers.catch_all_addr(), Some(0));
+    }
+}
@@ -0,0 +1,41 @@
+//! Cleartext network traffic policy of an application.
+
+use dw_resources::nsc::DomainCleartextPolicy;
+
+/// The first target SDK version for which cleartext traffic is denied by
+/// default (Android 9).
+pub const CLEARTEXT_DENIED_SINCE_SDK: u32 = 28;
+
+/// Where the default cleartext traffic policy of an application comes from.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum CleartextSource {
+    /// The `android:usesCleartextTraffic` attribute of the manifest.
+    Manifest,
+    /// The `<base-config>` node of the network security config.
+    NetworkSecurityConfig,
+    /// The platform default for the target SDK version.
+    SdkDefault,
+}
+
+/// Whether an application is allowed to use cleartext network traffic,
+/// along with the domain specific exceptions.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct CleartextPolicy {
+    /// Whether cleartext traffic is permitted for domains without a
+    /// specific policy.
+    pub permitted: bool,
+    pub source: CleartextSource,
+    /// The target SDK version of the application.
+    pub target_sdk: u32,
+    /// The domains for which the network security config sets a policy.
+    pub domains: Vec<DomainCleartextPolicy>,
+}
+
+impl CleartextPolicy {
+    /// Returns whether cleartext traffic is permitted for at least one
+    /// domain.
+    #[must_use]
+    pub fn any_permitted(&self) -> bool {
+        self.permitted || self.domains.iter().any(|domain| domain.permitted)
+    }
+}
@@ -2,7 +2,7 @@
 
 use dw_dex::errors::DexError;
 use dw_resources::errors::ResourcesError;
-use std::io;
+use std::{fmt, io};
 use thiserror::Error;
 use zip::result::ZipError;
 
@@ -16,6 +16,10 @@ pub enum PackageError {
     #[error("IO error: {0}")]
     IO(#[from] io::Error),
 
+    /// Error that can be returned when formatting package parts.
+    #[error("formatting error: {0}")]
+    Fmt(#[from] fmt::Error),
+
     /// Error that can be returned when opening or saving a zip file.
     #[error("zip error: {0}")]
     Zip(#[from] ZipError),
@@ -23,6 +27,30 @@ pub enum PackageError {
     #[error("file has been modified: {0}")]
     FileHasBeenModified(String),
 
+    #[error("package name not found in manifest")]
+    PackageNameNotFound,
+
+    #[error("conflicting file in split APKs: {0}")]
+    SplitConflict(String),
+
+    #[error("invalid dex file name: {0}")]
+    InvalidDexName(String),
+
+    #[error("package is not signed")]
+    SignatureNotFound,
+
+    #[error("invalid APK signing block: {0}")]
+    InvalidSigningBlock(&'static str),
+
+    #[error("invalid vdex file: {0}")]
+    InvalidVdex(&'static str),
+
+    #[error("unsupported vdex version: {0}")]
+    UnsupportedVdexVersion(String),
+
+    #[error("invalid oat file: {0}")]
+    InvalidOat(&'static str),
+
     #[error(transparent)]
     Dex(#[from] DexError),
 
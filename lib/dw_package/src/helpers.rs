@@ -1,5 +1,12 @@
+use crate::errors::PackageResult;
+use crate::PinningType;
+use dw_dex::classes::ClassDefItem;
+use dw_dex::code::CodeItem;
+use dw_dex::{Dex, PrettyPrinter};
 use lazy_static::lazy_static;
 use regex::Regex;
+use sha2::{Digest, Sha256};
+use std::fmt::Write;
 
 pub(crate) fn is_dex(filename: &str) -> bool {
     lazy_static! {
@@ -9,6 +16,16 @@ pub(crate) fn is_dex(filename: &str) -> bool {
     RE.is_match(filename)
 }
 
+/// Dex files names that are loaded by the runtime, as multidex expects them:
+/// `classes.dex`, then `classes2.dex`, `classes3.dex`, and so on.
+pub(crate) fn is_multidex_name(filename: &str) -> bool {
+    lazy_static! {
+        static ref RE: Regex = Regex::new(r"^classes([2-9]|[1-9][0-9]+)?\.dex$")
+            .expect("failed to compile multidex filename regex");
+    }
+    RE.is_match(filename)
+}
+
 pub(crate) fn is_manifest(filename: &str) -> bool {
     filename == "AndroidManifest.xml" || filename == "manifest/AndroidManifest.xml"
 }
@@ -16,3 +33,126 @@ pub(crate) fn is_manifest(filename: &str) -> bool {
 pub(crate) fn is_resources(filename: &str) -> bool {
     filename == "resources.arsc" || filename == "res/resources.arsc"
 }
+
+/// JAR signature files, which are the ones of the v1 signature scheme.
+pub(crate) fn is_jar_signature(filename: &str) -> bool {
+    filename.starts_with("META-INF/") && filename.ends_with(".SF")
+}
+
+/// Alignment of uncompressed files data, shared libraries being aligned on
+/// 16 KiB pages as `zipalign -P 16` does.
+pub(crate) fn alignment(filename: &str) -> u16 {
+    if filename.ends_with(".so") {
+        16384
+    } else {
+        4
+    }
+}
+
+/// Files that may hold pinning material: raw resources and assets.
+pub(crate) fn is_pinning_candidate(filename: &str) -> bool {
+    filename.starts_with("assets/") || filename.starts_with("res/raw/")
+}
+
+/// Detects certificates and public keys, either PEM encoded or DER encoded,
+/// and configuration files holding certificate pins (such as `sha256/...`
+/// pins used by OkHttp and most pinning libraries).
+pub(crate) fn sniff_pinning(content: &[u8]) -> Option<PinningType> {
+    lazy_static! {
+        static ref PEM_CERT: regex::bytes::Regex =
+            regex::bytes::Regex::new(r"-----BEGIN (X509 |TRUSTED )?CERTIFICATE-----")
+                .expect("failed to compile PEM certificate regex");
+        static ref PEM_PUBKEY: regex::bytes::Regex =
+            regex::bytes::Regex::new(r"-----BEGIN (RSA )?PUBLIC KEY-----")
+                .expect("failed to compile PEM public key regex");
+        static ref PINS: regex::bytes::Regex =
+            regex::bytes::Regex::new(r"(sha256/[A-Za-z0-9+/]{43}=|sha1/[A-Za-z0-9+/]{27}=)")
+                .expect("failed to compile pins regex");
+    }
+    if PEM_CERT.is_match(content) {
+        return Some(PinningType::Certificate);
+    }
+    if PEM_PUBKEY.is_match(content) {
+        return Some(PinningType::PublicKey);
+    }
+    if let Some(typ) = sniff_der(content) {
+        return Some(typ);
+    }
+    if PINS.is_match(content) {
+        return Some(PinningType::Config);
+    }
+    None
+}
+
+// A DER certificate is a SEQUENCE spanning the whole file, starting with
+// the (large) tbsCertificate SEQUENCE, while a DER public key
+// (SubjectPublicKeyInfo) starts with the (small) AlgorithmIdentifier SEQUENCE.
+fn sniff_der(content: &[u8]) -> Option<PinningType> {
+    let (len, header) = match content {
+        [0x30, 0x81, l, ..] => (usize::from(*l), 3),
+        [0x30, 0x82, h, l, ..] => (usize::from(*h) << 8 | usize::from(*l), 4),
+        _ => return None,
+    };
+    if header + len != content.len() {
+        return None;
+    }
+    match content[header..] {
+        [0x30, 0x82, ..] => Some(PinningType::Certificate),
+        [0x30, l, ..] if l < 0x80 => Some(PinningType::PublicKey),
+        _ => None,
+    }
+}
+
+/// Hashes a class definition (flags, hierarchy, fields and methods) in a
+/// form that does not depend on the dex file it comes from. Returns the
+/// class name along with the hash.
+pub(crate) fn class_fingerprint(
+    class: &ClassDefItem,
+    dex: &Dex,
+) -> PackageResult<(String, [u8; 32])> {
+    let name = class.class_name(dex)?;
+    let mut text = String::new();
+    writeln!(text, "class {name} {:#x}", class.flags().bits())?;
+    if let Some(superclass) = class.superclass(dex)? {
+        writeln!(text, "super {superclass}")?;
+    }
+    for interface in class.interfaces(dex)? {
+        writeln!(text, "implements {interface}")?;
+    }
+    if let Some(data) = class.data(dex)? {
+        for field in data.iter_fields() {
+            writeln!(
+                text,
+                "field {} {:#x}",
+                PrettyPrinter(field.descriptor(dex)?, dex),
+                field.flags().bits()
+            )?;
+        }
+        for method in data.iter_methods() {
+            write!(
+                text,
+                "method {} {:#x}",
+                PrettyPrinter(method.descriptor(dex)?, dex),
+                method.flags().bits()
+            )?;
+            if let Some(code) = method.code(dex)? {
+                let body = method_body_hash(&code.read().unwrap(), dex)?;
+                write!(text, " {}", hex(&body))?;
+            }
+            text.push('\n');
+        }
+    }
+    Ok((name, Sha256::digest(text.as_bytes()).into()))
+}
+
+/// Hashes a method body in the normalized form of
+/// [`CodeItem::normalized_body`], which does not depend on register
+/// allocation nor on the dex file layout.
+pub(crate) fn method_body_hash(code: &CodeItem, dex: &Dex) -> PackageResult<[u8; 32]> {
+    let body = code.normalized_body(dex)?.join("\n");
+    Ok(Sha256::digest(body.as_bytes()).into())
+}
+
+fn hex(bytes: &[u8]) -> String {
+    bytes.iter().map(|b| format!("{b:02x}")).collect()
+}
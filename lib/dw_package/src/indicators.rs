@@ -0,0 +1,260 @@
+//! Network indicators (URLs, domains and IP addresses) referenced by an
+//! application.
+
+use crate::errors::PackageResult;
+use dw_dex::instrs::Instr;
+use dw_dex::{Dex, DexIndex, PrettyPrinter};
+use lazy_static::lazy_static;
+use regex::Regex;
+use serde::Serialize;
+use std::collections::{BTreeMap, BTreeSet};
+use std::net::IpAddr;
+
+/// Leading labels of Java and Android package names, which look like
+/// domains once split on dots (such as `android.intent.action.MAIN`).
+const PACKAGE_ROOTS: &[&str] = &[
+    "android", "androidx", "dalvik", "java", "javax", "kotlin", "kotlinx",
+];
+
+/// Trailing labels of file names, which look like top level domains (such
+/// as `config.json`).
+const FILE_EXTENSIONS: &[&str] = &[
+    "bin",
+    "class",
+    "css",
+    "dex",
+    "gif",
+    "gz",
+    "htm",
+    "html",
+    "jar",
+    "java",
+    "jpeg",
+    "jpg",
+    "js",
+    "json",
+    "kt",
+    "log",
+    "mp3",
+    "mp4",
+    "php",
+    "png",
+    "properties",
+    "so",
+    "svg",
+    "tmp",
+    "txt",
+    "webp",
+    "xml",
+    "zip",
+];
+
+/// The network indicators found in the strings of a package.
+///
+/// Each indicator is mapped to the methods (in `Lcom/Foo;->bar()V` form)
+/// that load a string holding it. Indicators only found in strings that are
+/// not loaded by code, such as resources, are mapped to an empty set.
+#[derive(Debug, Clone, Default, PartialEq, Eq, Serialize)]
+pub struct Indicators {
+    /// URLs of network schemes (`http`, `https`, `ws`, `wss` and `ftp`).
+    pub urls: BTreeMap<String, BTreeSet<String>>,
+    /// Domain names, either standalone or hosts of the URLs.
+    pub domains: BTreeMap<String, BTreeSet<String>>,
+    /// IPv4 and IPv6 literals.
+    pub ips: BTreeMap<IpAddr, BTreeSet<String>>,
+}
+
+impl Indicators {
+    /// Returns whether no indicator has been found.
+    #[must_use]
+    pub fn is_empty(&self) -> bool {
+        self.urls.is_empty() && self.domains.is_empty() && self.ips.is_empty()
+    }
+
+    /// Records the indicators found in the strings loaded by the methods of
+    /// a dex file.
+    pub(crate) fn scan_dex(&mut self, dex: &Dex) -> PackageResult<()> {
+        for class in dex.iter_class_defs() {
+            let Some(data) = class.data(dex)? else {
+                continue;
+            };
+            for method in data.iter_methods() {
+                let Some(code) = method.code(dex)? else {
+                    continue;
+                };
+                let name = PrettyPrinter(method.descriptor(dex)?, dex).to_string();
+                for instr in code.read().unwrap().iter_instructions() {
+                    if let Instr::ConstString(_, sid) | Instr::ConstStringJumbo(_, sid) =
+                        instr.instr()
+                    {
+                        self.scan(&sid.get(dex)?.to_string(dex)?, Some(&name));
+                    }
+                }
+            }
+        }
+        Ok(())
+    }
+
+    /// Records the indicators found in `string`, referenced by the given
+    /// method if any.
+    pub(crate) fn scan(&mut self, string: &str, method: Option<&str>) {
+        lazy_static! {
+            static ref URL: Regex = Regex::new(r#"(?i)\b(?:https?|wss?|ftp)://[^\s"'<>()\\]+"#)
+                .expect("failed to compile URL regex");
+            static ref DOMAIN: Regex =
+                Regex::new(r"\b(?:[a-z0-9](?:[a-z0-9-]{0,61}[a-z0-9])?\.)+[a-z]{2,63}\b")
+                    .expect("failed to compile domain regex");
+            static ref IPV4: Regex =
+                Regex::new(r"\b\d{1,3}(?:\.\d{1,3}){3}\b").expect("failed to compile IPv4 regex");
+            static ref IPV6: Regex = Regex::new(r"(?i)\b(?:[0-9a-f]{0,4}:){2,7}[0-9a-f]{0,4}\b")
+                .expect("failed to compile IPv6 regex");
+        }
+
+        fn record<K: Ord>(map: &mut BTreeMap<K, BTreeSet<String>>, key: K, method: Option<&str>) {
+            let methods = map.entry(key).or_default();
+            if let Some(method) = method {
+                methods.insert(method.to_string());
+            }
+        }
+
+        let mut urls = Vec::new();
+        for url in URL.find_iter(string) {
+            urls.push(url.range());
+            let url = url.as_str();
+            record(&mut self.urls, url.to_string(), method);
+            if let Some(host) = url_host(url) {
+                match host.parse::<IpAddr>() {
+                    Ok(ip) => record(&mut self.ips, ip, method),
+                    Err(_) => record(&mut self.domains, host.to_ascii_lowercase(), method),
+                }
+            }
+        }
+        for domain in DOMAIN.find_iter(string) {
+            // URLs paths may hold file names
+            if urls.iter().any(|url| url.contains(&domain.start())) {
+                continue;
+            }
+            // prefix of a dotted name with uppercase parts, such as a class
+            // name (`com.example.MainActivity`)
+            let dotted = string[domain.end()..]
+                .strip_prefix('.')
+                .is_some_and(|rest| rest.starts_with(|c: char| c.is_ascii_alphanumeric()));
+            if !dotted && is_domain(domain.as_str()) {
+                record(&mut self.domains, domain.as_str().to_string(), method);
+            }
+        }
+        // dotted version numbers look like IPv4 addresses, such as in
+        // `v4.12.0.1` or `okhttp/4.12.0.1`; other ones (`version 4.12.0.1`)
+        // are still reported
+        let ipv4 = IPV4
+            .find_iter(string)
+            .filter(|ip| !string[..ip.start()].ends_with(['v', 'V', '/']));
+        for ip in ipv4.chain(IPV6.find_iter(string)) {
+            if let Ok(ip) = ip.as_str().parse::<IpAddr>() {
+                if !ip.is_unspecified() {
+                    record(&mut self.ips, ip, method);
+                }
+            }
+        }
+    }
+}
+
+/// Returns the host of a URL, without user information nor port.
+fn url_host(url: &str) -> Option<&str> {
+    let (_, rest) = url.split_once("://")?;
+    let authority = rest.split(['/', '?', '#']).next()?;
+    let host = authority
+        .rsplit_once('@')
+        .map_or(authority, |(_, host)| host);
+    let host = match host.strip_prefix('[') {
+        Some(ipv6) => ipv6.split_once(']')?.0,
+        None => host.split(':').next()?,
+    };
+    (!host.is_empty()).then_some(host)
+}
+
+/// Filters out domain-like strings that are Java package names and file
+/// names.
+fn is_domain(domain: &str) -> bool {
+    let first = domain.split('.').next().unwrap_or_default();
+    let last = domain.rsplit('.').next().unwrap_or_default();
+    !PACKAGE_ROOTS.contains(&first) && !FILE_EXTENSIONS.contains(&last)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use dw_dex::testing::{DexBuilder, ACC_PUBLIC, ACC_STATIC};
+
+    #[test]
+    fn false_positives() {
+        let mut indicators = Indicators::default();
+        for string in [
+            "android.intent.action.VIEW",
+            "java.lang.String",
+            "res/raw/config.json",
+            "com.example.MainActivity",
+            "version 1.2",
+            "12:30:45",
+            "okhttp/4.12.0.1",
+            "v1.0.0.2",
+            "see example.org.",
+        ] {
+            indicators.scan(string, None);
+        }
+        assert_eq!(
+            indicators.domains.keys().collect::<Vec<_>>(),
+            ["example.org"]
+        );
+        assert!(indicators.urls.is_empty());
+        assert!(indicators.ips.is_empty());
+    }
+
+    #[test]
+    fn ip_literals() {
+        let mut indicators = Indicators::default();
+        indicators.scan("connect to 10.0.2.2 or http://[fe80::1]:8080/", Some("m"));
+        assert_eq!(
+            indicators
+                .ips
+                .keys()
+                .map(ToString::to_string)
+                .collect::<Vec<_>>(),
+            ["10.0.2.2", "fe80::1"]
+        );
+        assert_eq!(
+            indicators.urls.keys().collect::<Vec<_>>(),
+            ["http://[fe80::1]:8080/"]
+        );
+    }
+
+    #[test]
+    fn loaded_strings() {
+        let mut builder = DexBuilder::new();
+        let class = builder.class("LFoo;", "Ljava/lang/Object;");
+        let load = builder.method("LFoo;", "load", "V", &[]);
+        let url = builder.string("https://c2.example.com/gate.php") as u16;
+        builder.code(
+            class,
+            load,
+            ACC_PUBLIC | ACC_STATIC,
+            1,
+            0,
+            0,
+            &[
+                0x001a, url,    // 0: const-string v0, "https://..."
+                0x000e, // 2: return-void
+            ],
+        );
+        let dex = builder.build_dex();
+
+        let mut indicators = Indicators::default();
+        indicators.scan_dex(&dex).unwrap();
+        let methods = BTreeSet::from(["LFoo;->load()V".to_string()]);
+        assert_eq!(
+            indicators.urls.get("https://c2.example.com/gate.php"),
+            Some(&methods)
+        );
+        assert_eq!(indicators.domains.get("c2.example.com"), Some(&methods));
+    }
+}
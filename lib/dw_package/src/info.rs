@@ -0,0 +1,26 @@
+//! Structured summary of an application manifest.
+
+use serde::Serialize;
+
+/// The main information declared in the manifest of a package, as printed
+/// by `dw-packageinfo`.
+///
+/// Component names are fully qualified: names relative to the application
+/// package (such as `.MainActivity`) are resolved against it.
+#[derive(Debug, Clone, Default, PartialEq, Eq, Serialize)]
+pub struct PackageInfo {
+    /// The application package name, if a manifest has been found.
+    pub package: Option<String>,
+    /// The `android:versionCode` attribute of the manifest.
+    pub version_code: Option<u32>,
+    /// The `android:versionName` attribute of the manifest.
+    pub version_name: Option<String>,
+    /// The names of the `<uses-permission>` elements.
+    pub permissions: Vec<String>,
+    /// The names of the `<uses-feature>` elements.
+    pub features: Vec<String>,
+    pub activities: Vec<String>,
+    pub services: Vec<String>,
+    pub receivers: Vec<String>,
+    pub providers: Vec<String>,
+}
@@ -3,17 +3,33 @@
 
 mod helpers;
 
+pub mod cleartext;
 pub mod errors;
+pub mod indicators;
+pub mod info;
+pub mod packing;
+pub mod report;
+pub mod signature;
+mod vdex;
 
+use crate::cleartext::{CleartextPolicy, CleartextSource, CLEARTEXT_DENIED_SINCE_SDK};
 use crate::errors::{PackageError, PackageResult};
+use crate::indicators::Indicators;
+use crate::info::PackageInfo;
+use crate::packing::{Evidence, PackingReport, ScannedAsset};
+use crate::report::{Check, SecurityReport, Severity, EXPLICIT_EXPORT_REQUIRED_SINCE_SDK};
+use crate::signature::SignatureInfo;
 use base64::{engine::general_purpose as b64, Engine};
+use dw_resources::values::ResolvedValue;
 use dw_resources::{manifest, nsc, resources};
 use rayon::prelude::*;
+use sha2::{Digest, Sha256};
 use std::collections::{BTreeMap, BTreeSet};
 use std::fmt;
-use std::fs::File;
-use std::io::{Read, Write};
+use std::fs::{self, File};
+use std::io::{self, Read, Write};
 use std::path::{Path, PathBuf};
+use std::sync::{Mutex, OnceLock};
 use zip::write::FileOptions;
 use zip::{result::ZipError, CompressionMethod, ZipArchive, ZipWriter};
 
@@ -22,11 +38,13 @@ use zip::{result::ZipError, CompressionMethod, ZipArchive, ZipWriter};
 #[derive(Debug)]
 pub struct Package {
     pub(crate) name: String,
+    pub(crate) path: PathBuf,
     pub(crate) dexs_path: Vec<PathBuf>,
     pub(crate) manifest_path: Option<PathBuf>,
     pub(crate) nsc_path: Option<PathBuf>,
     pub(crate) resources_path: Option<PathBuf>,
     pub(crate) files: BTreeMap<PathBuf, FileItem>,
+    pub(crate) archive: Option<Mutex<ZipArchive<File>>>,
 }
 
 impl Package {
@@ -36,32 +54,468 @@ impl Package {
         Options::default().open(path)
     }
 
+    /// Open the given split APKs (such as the `base.apk` and
+    /// `split_config.*.apk` files of an app bundle installation) as a single
+    /// Android application.
+    ///
+    /// See [`Options::open_splits`] for the merging rules.
+    pub fn open_splits<P: AsRef<Path>>(paths: &[P]) -> PackageResult<Self> {
+        Options::default().open_splits(paths)
+    }
+
+    /// Opens the Dex files embedded in the given `vdex` file, as generated
+    /// by `dex2oat` on Android 9 and later devices, as an Android
+    /// application without manifest nor resources.
+    ///
+    /// Quickened instructions are restored to their original form. Dex files
+    /// are named `classes.dex`, `classes2.dex`, and so on, and a vdex file
+    /// without dex section (its Dex files being kept in the APK) gives a
+    /// package without Dex files.
+    pub fn open_vdex<P: AsRef<Path>>(path: P) -> PackageResult<Self> {
+        let dexs = vdex::vdex_dexs(&std::fs::read(&path)?)?;
+        Self::from_dexs(path, dexs)
+    }
+
+    /// Opens the Dex files embedded in the given `odex` (oat) file as an
+    /// Android application without manifest nor resources.
+    ///
+    /// Only oat files generated before Android 8 embed Dex files, the ones
+    /// of more recent versions have to be opened with [`Package::open_vdex`].
+    pub fn open_odex<P: AsRef<Path>>(path: P) -> PackageResult<Self> {
+        let dexs = vdex::oat_dexs(&std::fs::read(&path)?)?;
+        Self::from_dexs(path, dexs)
+    }
+
+    fn from_dexs<P: AsRef<Path>>(path: P, dexs: Vec<Vec<u8>>) -> PackageResult<Self> {
+        let mut package = Package {
+            name: path
+                .as_ref()
+                .file_name()
+                .unwrap()
+                .to_str()
+                .unwrap()
+                .to_string(),
+            path: path.as_ref().to_path_buf(),
+            dexs_path: Vec::new(),
+            manifest_path: None,
+            nsc_path: None,
+            resources_path: None,
+            files: BTreeMap::new(),
+            archive: None,
+        };
+        let parsed = dexs
+            .into_par_iter()
+            .map(|raw| -> PackageResult<FileItem> {
+                let dex = dw_dex::parse(&raw)?;
+                Ok(FileItem::new_dex(raw, CompressionMethod::Deflated, dex))
+            })
+            .collect::<PackageResult<Vec<FileItem>>>()?;
+        for item in parsed {
+            let name = package.next_dex_name();
+            package.dexs_path.push(name.clone());
+            package.files.insert(name, item);
+        }
+        Ok(package)
+    }
+
+    /// Returns the package name declared in the manifest of the given
+    /// Android application, without parsing its code or other assets.
+    ///
+    /// Only the manifest entry is read, unless the `package` attribute is a
+    /// resource reference: in that case resources are parsed to resolve it,
+    /// and the raw attribute value is returned if resolution fails.
+    pub fn package_name_only<P: AsRef<Path>>(path: P) -> PackageResult<String> {
+        let file = File::open(path)?;
+        let mut zip = ZipArchive::new(file)?;
+
+        let manifest_name = zip
+            .file_names()
+            .find(|name| helpers::is_manifest(name))
+            .map(ToString::to_string)
+            .ok_or(PackageError::Zip(ZipError::FileNotFound))?;
+        let mut buffer = Vec::new();
+        zip.by_name(&manifest_name)?.read_to_end(&mut buffer)?;
+        let manifest = manifest::parse(&buffer)?;
+
+        if let Some(name) = manifest.package()? {
+            return Ok(name);
+        }
+
+        let resources_name = zip
+            .file_names()
+            .find(|name| helpers::is_resources(name))
+            .map(ToString::to_string);
+        let resources = match resources_name {
+            Some(name) => {
+                let mut buffer = Vec::new();
+                zip.by_name(&name)?.read_to_end(&mut buffer)?;
+                Some(resources::parse(&buffer)?)
+            }
+            None => None,
+        };
+
+        manifest
+            .package_resolved(resources.as_ref())?
+            .ok_or(PackageError::PackageNameNotFound)
+    }
+
+    /// Returns the deep links declared by exported and enabled activities,
+    /// that is the URIs matched by their `VIEW` intent filters.
+    ///
+    /// As Android does, the `<data>` elements of a filter are merged: each
+    /// declared scheme is combined with each declared host, and each host
+    /// with each declared path. Paths are ignored when no host is declared.
+    pub fn deep_links(&self) -> PackageResult<Vec<DeepLink>> {
+        let Some(manifest) = self.manifest() else {
+            return Ok(Vec::new());
+        };
+        let package_name = manifest.package()?;
+
+        let mut links = Vec::new();
+        for component in manifest.components_with_filters(self.resources())? {
+            if component.kind != manifest::ComponentKind::Activity {
+                continue;
+            }
+            let attribute = |name| component.tag.attributes.get(name).cloned().flatten();
+            if attribute("exported") != Some(ResolvedValue::Bool(true))
+                || attribute("enabled") == Some(ResolvedValue::Bool(false))
+            {
+                continue;
+            }
+            let Some(mut activity) = component.tag.name() else {
+                continue;
+            };
+            if let Some(package_name) = &package_name {
+                activity = manifest::Manifest::qualify(package_name, &activity);
+            }
+
+            for filter in &component.intent_filters {
+                if !filter
+                    .actions
+                    .iter()
+                    .any(|a| a == "android.intent.action.VIEW")
+                {
+                    continue;
+                }
+                let schemes: BTreeSet<&String> = filter
+                    .data
+                    .iter()
+                    .filter_map(|d| d.scheme.as_ref())
+                    .collect();
+                let hosts: BTreeSet<&String> =
+                    filter.data.iter().filter_map(|d| d.host.as_ref()).collect();
+                let mut paths = BTreeSet::new();
+                for data in &filter.data {
+                    paths.extend(data.path.clone().map(DeepLinkPath::Literal));
+                    paths.extend(data.path_prefix.clone().map(DeepLinkPath::Prefix));
+                    paths.extend(data.path_pattern.clone().map(DeepLinkPath::Pattern));
+                }
+
+                for scheme in &schemes {
+                    let mut link = DeepLink {
+                        activity: activity.clone(),
+                        scheme: (*scheme).clone(),
+                        host: None,
+                        path: None,
+                    };
+                    if hosts.is_empty() {
+                        links.push(link);
+                        continue;
+                    }
+                    for host in &hosts {
+                        link.host = Some((*host).clone());
+                        if paths.is_empty() {
+                            links.push(link.clone());
+                        }
+                        for path in &paths {
+                            link.path = Some(path.clone());
+                            links.push(link.clone());
+                        }
+                    }
+                }
+            }
+        }
+        links.sort();
+        links.dedup();
+        Ok(links)
+    }
+
+    /// Returns the certificates, public keys and pinning configuration files
+    /// found in assets and raw resources. Files are recognized from their
+    /// content, whatever their extension.
+    #[must_use]
+    pub fn pinning_material(&self) -> Vec<PinningAsset> {
+        self.files
+            .iter()
+            .filter(|(path, _)| path.to_str().is_some_and(helpers::is_pinning_candidate))
+            .filter_map(|(path, file_item)| {
+                let raw = self.raw(path, file_item).ok()?;
+                helpers::sniff_pinning(raw).map(|typ| PinningAsset {
+                    path: path.clone(),
+                    typ,
+                })
+            })
+            .collect()
+    }
+
+    /// Runs the manifest-based detectors (debuggable and backup flags,
+    /// cleartext traffic policy, exported components) along with the
+    /// pinning material lookup, and gathers their findings in a report.
+    ///
+    /// Components exported only because they declare an intent filter are
+    /// reported with a higher severity, as this is often unintended (it is
+    /// rejected for applications targeting Android 12 or higher).
+    ///
+    /// Code-based detectors are not run here, the returned report is thus
+    /// marked as not code-analyzed. Manifest checks are skipped when the
+    /// package has no manifest.
+    pub fn security_report(&self) -> PackageResult<SecurityReport> {
+        let mut report = SecurityReport::default();
+
+        if let Some(manifest) = self.manifest() {
+            let resources = self.resources();
+            report.package = manifest.package_resolved(resources)?;
+
+            if manifest.debuggable(resources)? == Some(true) {
+                report.push(
+                    Check::Debuggable,
+                    Severity::High,
+                    "application is debuggable".to_string(),
+                );
+            }
+            match manifest.allow_backup(resources)? {
+                Some(false) => (),
+                Some(true) => report.push(
+                    Check::AllowBackup,
+                    Severity::Medium,
+                    "application data can be backed up".to_string(),
+                ),
+                None => report.push(
+                    Check::AllowBackup,
+                    Severity::Low,
+                    "application data can be backed up (allowBackup defaults to true)".to_string(),
+                ),
+            }
+            let cleartext = self.cleartext_policy()?;
+            if cleartext.permitted {
+                report.push(
+                    Check::CleartextTraffic,
+                    Severity::Medium,
+                    "application allows cleartext traffic".to_string(),
+                );
+            } else if cleartext.any_permitted() {
+                report.push(
+                    Check::CleartextTraffic,
+                    Severity::Low,
+                    "application allows cleartext traffic to some domains".to_string(),
+                );
+            }
+
+            let components = [
+                ("activity", manifest.activities(resources)?),
+                ("service", manifest.services(resources)?),
+                ("receiver", manifest.receivers(resources)?),
+                ("provider", manifest.providers(resources)?),
+            ];
+            for (kind, tags) in components {
+                for tag in tags {
+                    let attribute = |name| tag.attributes.get(name).cloned().flatten();
+                    if attribute("exported") != Some(ResolvedValue::Bool(true))
+                        || attribute("enabled") == Some(ResolvedValue::Bool(false))
+                    {
+                        continue;
+                    }
+                    let name = tag.name().unwrap_or_else(|| "<unnamed>".to_string());
+                    if tag.is_implicitly_exported()
+                        && cleartext.target_sdk < EXPLICIT_EXPORT_REQUIRED_SINCE_SDK
+                    {
+                        report.push(
+                            Check::ExportedComponent,
+                            Severity::Medium,
+                            format!("{kind} {name} is implicitly exported by an intent filter"),
+                        );
+                    } else {
+                        report.push(
+                            Check::ExportedComponent,
+                            Severity::Low,
+                            format!("{kind} {name} is exported"),
+                        );
+                    }
+                }
+            }
+        }
+
+        for asset in self.pinning_material() {
+            report.push(
+                Check::PinningMaterial,
+                Severity::Info,
+                format!("{:?} found in {}", asset.typ, asset.path.display()),
+            );
+        }
+
+        report.sort();
+        Ok(report)
+    }
+
+    /// Gathers the package name, version, permissions, features and
+    /// components declared in the manifest.
+    ///
+    /// Component names in the relative form (`.Main` or `Main`) are
+    /// expanded to fully-qualified names. An empty [`PackageInfo`] is
+    /// returned when the package has no manifest.
+    pub fn info(&self) -> PackageResult<PackageInfo> {
+        let Some(manifest) = self.manifest() else {
+            return Ok(PackageInfo::default());
+        };
+        let resources = self.resources();
+        let package = manifest.package_resolved(resources)?;
+
+        let names = |tags: Vec<manifest::ManifestTag>| -> Vec<String> {
+            tags.iter()
+                .filter_map(manifest::ManifestTag::name)
+                .collect()
+        };
+        let components = |tags: Vec<manifest::ManifestTag>| -> Vec<String> {
+            names(tags)
+                .into_iter()
+                .map(|name| match &package {
+                    Some(package) => manifest::Manifest::qualify(package, &name),
+                    None => name,
+                })
+                .collect()
+        };
+
+        Ok(PackageInfo {
+            version_code: manifest.version_code()?,
+            version_name: manifest.version_name()?,
+            permissions: names(manifest.uses_permissions(resources)?),
+            features: names(manifest.uses_features(resources)?),
+            activities: components(manifest.activities(resources)?),
+            services: components(manifest.services(resources)?),
+            receivers: components(manifest.receivers(resources)?),
+            providers: components(manifest.providers(resources)?),
+            package,
+        })
+    }
+
+    /// Determines whether the application permits cleartext network traffic.
+    ///
+    /// As Android does, the `android:usesCleartextTraffic` attribute of the
+    /// manifest is ignored when the application has a network security
+    /// config: the default policy is then given by its `<base-config>` node.
+    /// Otherwise, it is given by the manifest attribute. In both cases, the
+    /// target SDK version gives the policy when it is not set (cleartext
+    /// traffic is denied by default since Android 9). Domain specific
+    /// policies of the network security config are listed along.
+    pub fn cleartext_policy(&self) -> PackageResult<CleartextPolicy> {
+        let resources = self.resources();
+        let (manifest_flag, target_sdk) = match self.manifest() {
+            Some(manifest) => (
+                manifest.uses_cleartext_traffic(resources)?,
+                manifest.target_sdk_version(resources)?,
+            ),
+            None => (None, 1),
+        };
+        let (flag, domains) = match self.network_security_config() {
+            Some(nsc) => (
+                nsc.base_cleartext_traffic_permitted()?
+                    .map(|permitted| (permitted, CleartextSource::NetworkSecurityConfig)),
+                nsc.cleartext_domains()?,
+            ),
+            None => (
+                manifest_flag.map(|permitted| (permitted, CleartextSource::Manifest)),
+                Vec::new(),
+            ),
+        };
+
+        let (permitted, source) = flag.unwrap_or((
+            target_sdk < CLEARTEXT_DENIED_SINCE_SDK,
+            CleartextSource::SdkDefault,
+        ));
+        Ok(CleartextPolicy {
+            permitted,
+            source,
+            target_sdk,
+            domains,
+        })
+    }
+
+    /// Computes a SHA-256 fingerprint of the application code, which does
+    /// not depend on packaging (signature, resources, compression) nor on
+    /// the way classes are spread over dex files.
+    ///
+    /// Each class definition is hashed in a normalized form, where strings,
+    /// types, fields and methods referenced by code are resolved instead of
+    /// being kept as dex table indices. Class hashes are then combined in
+    /// class name order.
+    pub fn code_fingerprint(&self) -> PackageResult<[u8; 32]> {
+        let mut classes = Vec::new();
+        for dex in self.iter_dexs() {
+            for class in dex.iter_class_defs() {
+                classes.push(helpers::class_fingerprint(class, dex)?);
+            }
+        }
+        classes.sort();
+
+        let mut hasher = Sha256::new();
+        for (name, hash) in &classes {
+            hasher.update(name.as_bytes());
+            hasher.update([0]);
+            hasher.update(hash);
+        }
+        Ok(hasher.finalize().into())
+    }
+
+    /// Verifies the APK Signature Scheme v3 block of the package, or its v2
+    /// block if there is no v3 one, and returns the signers along with the
+    /// verification status.
+    ///
+    /// As signatures cover the zip layout, the package file is read again
+    /// from disk. JAR signatures are not verified: if the package only holds
+    /// a `META-INF/*.SF` signature, [`SignatureScheme::V1Only`] is returned
+    /// without any signer.
+    ///
+    /// [`SignatureScheme::V1Only`]: signature::SignatureScheme::V1Only
+    pub fn verify_signature(&self) -> PackageResult<SignatureInfo> {
+        let apk = std::fs::read(&self.path)?;
+        let jar_signed = self
+            .files
+            .keys()
+            .any(|path| path.to_str().is_some_and(helpers::is_jar_signature));
+        signature::verify(&apk, jar_signed)
+    }
+
     /// Returns an iterator over file names that are contained in the package.
     pub fn iter_filenames(&self) -> impl Iterator<Item = &Path> {
         self.files.keys().map(PathBuf::as_path)
     }
 
     /// Returns an iterator over file names and sizes that are contained in
-    /// the package.
-    pub fn iter_filenames_with_size(&self) -> impl Iterator<Item = (&Path, usize)> {
+    /// the package. Sizes are the uncompressed ones, which may exceed 4GB in
+    /// Zip64 archives.
+    pub fn iter_filenames_with_size(&self) -> impl Iterator<Item = (&Path, u64)> {
         self.files
             .iter()
-            .map(|(name, file_item)| (PathBuf::as_path(name), file_item.raw.len()))
+            .map(|(name, file_item)| (PathBuf::as_path(name), file_item.size))
     }
 
     /// Due to a limitation in the number of methods that can be stored in a Dex
     /// file, Android application can have their code split between several Dex files.
     /// This method returns an iterator over all Dex object that are contained in
     /// the package.
+    ///
+    /// On a lazily opened package, Dex files are parsed on first access, and
+    /// the ones that fail to parse are skipped.
     pub fn iter_dexs(&self) -> impl Iterator<Item = &dw_dex::Dex> {
         self.dexs_path
             .iter()
-            .map(move |path| match self.files.get(path) {
-                Some(FileItem {
-                    content: FileContent::Dex(d),
-                    ..
-                }) => d,
-                _ => unreachable!(),
+            .filter_map(move |path| match self.content(path) {
+                Ok(FileContent::Dex(d)) => Some(d),
+                Ok(_) => unreachable!(),
+                Err(err) => {
+                    log::error!("failed to parse {path:?}: {err}");
+                    None
+                }
             })
     }
 
@@ -74,6 +528,13 @@ impl Package {
         }
     }
 
+    /// Returns the name of the split APK the given asset comes from, if the
+    /// package has been opened from splits.
+    #[must_use]
+    pub fn file_split(&self, asset: &PathBuf) -> Option<&str> {
+        self.files.get(asset)?.split.as_deref()
+    }
+
     pub fn get(&self, asset: &PathBuf) -> PackageResult<&[u8]> {
         match self.files.get(asset) {
             None => Err(PackageError::Zip(ZipError::FileNotFound)),
@@ -82,7 +543,7 @@ impl Package {
                     let filename = asset.as_path().to_str().unwrap().to_string();
                     return Err(PackageError::FileHasBeenModified(filename));
                 }
-                Ok(&fileitem.raw)
+                self.raw(asset, fileitem)
             }
         }
     }
@@ -97,8 +558,9 @@ impl Package {
                     return Err(PackageError::FileHasBeenModified(filename));
                 }
 
+                let raw = self.raw(asset, fileitem)?;
                 let mut file = File::create(output)?;
-                file.write_all(&fileitem.raw)?;
+                file.write_all(raw)?;
                 Ok(())
             }
         }
@@ -113,7 +575,7 @@ impl Package {
                     let filename = asset.as_path().to_str().unwrap().to_string();
                     Err(PackageError::FileHasBeenModified(filename))
                 } else {
-                    Ok(b64::STANDARD.encode(&fileitem.raw))
+                    Ok(b64::STANDARD.encode(self.raw(asset, fileitem)?))
                 }
             }
         }
@@ -132,8 +594,9 @@ impl Package {
         match self.files.remove(&asset) {
             None => Err(PackageError::Zip(ZipError::FileNotFound)),
             Some(old) => {
-                self.files
-                    .insert(asset, FileItem::new_other(content, old.compression));
+                let mut fileitem = FileItem::new_other(content, old.compression);
+                fileitem.split = old.split;
+                self.files.insert(asset, fileitem);
                 Ok(())
             }
         }
@@ -162,6 +625,42 @@ impl Package {
         Ok(())
     }
 
+    /// Inserts a new Dex file in the package, which is serialized when the
+    /// package is saved.
+    ///
+    /// The file name must follow the multidex convention (`classes.dex`,
+    /// `classes2.dex`, ...), see [`next_dex_name`](Self::next_dex_name).
+    pub fn insert_dex(&mut self, name: PathBuf, dex: dw_dex::Dex) -> PackageResult<()> {
+        let filename = name.to_str().unwrap_or_default();
+        if !helpers::is_multidex_name(filename) {
+            return Err(PackageError::InvalidDexName(filename.to_string()));
+        }
+        if self.files.contains_key(&name) {
+            return Err(PackageError::Zip(ZipError::InvalidArchive(
+                "file already exists",
+            )));
+        }
+        let mut fileitem = FileItem::new_dex(Vec::new(), CompressionMethod::Deflated, dex);
+        fileitem.modify();
+        self.dexs_path.push(name.clone());
+        self.files.insert(name, fileitem);
+        Ok(())
+    }
+
+    /// Returns the first Dex file name that is not used in the package,
+    /// following the multidex convention.
+    #[must_use]
+    pub fn next_dex_name(&self) -> PathBuf {
+        let first = PathBuf::from("classes.dex");
+        if !self.files.contains_key(&first) {
+            return first;
+        }
+        (2..)
+            .map(|n| PathBuf::from(format!("classes{n}.dex")))
+            .find(|path| !self.files.contains_key(path))
+            .unwrap()
+    }
+
     /// Return a set that contains strings that are referenced in all Dex files
     /// that are contained in the package.
     pub fn dexs_strings(&self) -> PackageResult<BTreeSet<String>> {
@@ -176,28 +675,102 @@ impl Package {
         Ok(strings)
     }
 
+    /// Looks for network indicators (URLs, domains and IP addresses) in
+    /// the strings of the dex files and of the resources.
+    ///
+    /// Indicators found in strings loaded by `const-string` instructions
+    /// are mapped to the loading methods. Domain-like Java package names
+    /// (such as `android.intent.action.VIEW`) and file names are left out.
+    pub fn network_indicators(&self) -> PackageResult<Indicators> {
+        let mut indicators = Indicators::default();
+        for dex in self.iter_dexs() {
+            indicators.scan_dex(dex)?;
+        }
+        for string in self.dexs_strings()? {
+            indicators.scan(&string, None);
+        }
+        if let Some(resources) = self.resources() {
+            for string in resources.strings()? {
+                indicators.scan(&string, None);
+            }
+        }
+        Ok(indicators)
+    }
+
+    /// Looks for hints that the package is packed, that is that its actual
+    /// code is shipped encrypted in assets and loaded at runtime by a stub:
+    ///
+    /// - assets that are not dex files nor of a compressed format, and
+    ///   whose entropy exceeds [`packing::ENTROPY_THRESHOLD`];
+    /// - invocations of methods of the [`packing::LOADER_CLASSES`], or of
+    ///   the [`packing::LOADER_METHODS`];
+    /// - a primary dex file that is small compared to the assets.
+    ///
+    /// The confidence score of the report depends on the kinds of evidence
+    /// found.
+    pub fn packing_report(&self) -> PackageResult<PackingReport> {
+        let mut evidence = Vec::new();
+
+        let mut assets_size = 0;
+        for (path, fileitem) in &self.files {
+            let Some(name) = path.to_str().filter(|name| name.starts_with("assets/")) else {
+                continue;
+            };
+            let scanned = self.read_with(path, fileitem, |content| {
+                packing::scan_asset(name, fileitem.size, content)
+            })?;
+            let ScannedAsset::Other(entropy) = scanned else {
+                continue;
+            };
+            assets_size += fileitem.size;
+            if let Some(entropy) = entropy.filter(|e| *e >= packing::ENTROPY_THRESHOLD) {
+                evidence.push(Evidence::HighEntropyAsset {
+                    path: path.clone(),
+                    entropy,
+                });
+            }
+        }
+
+        for dex in self.iter_dexs() {
+            evidence.extend(packing::loader_calls(dex)?);
+        }
+
+        if let Some(primary) = self.files.get(Path::new("classes.dex")) {
+            if primary.size.saturating_mul(packing::ASSETS_TO_DEX_RATIO) < assets_size {
+                evidence.push(Evidence::SmallPrimaryDex {
+                    dex_size: primary.size,
+                    assets_size,
+                });
+            }
+        }
+
+        Ok(PackingReport::new(evidence))
+    }
+
     /// Return the Android Manifest object of the package.
     #[must_use]
     pub fn manifest(&self) -> Option<&manifest::Manifest> {
         let path = self.manifest_path.as_ref()?;
-        match self.files.get(path) {
-            Some(FileItem {
-                content: FileContent::Manifest(m),
-                ..
-            }) => Some(m),
-            _ => unreachable!(),
+        match self.content(path) {
+            Ok(FileContent::Manifest(m)) => Some(m),
+            Ok(_) => unreachable!(),
+            Err(err) => {
+                log::error!("failed to parse {path:?}: {err}");
+                None
+            }
         }
     }
 
     #[must_use]
     pub fn network_security_config(&self) -> Option<&nsc::NetworkSecurityConfig> {
         let path = self.nsc_path.as_ref()?;
-        match self.files.get(path) {
-            Some(FileItem {
-                content: FileContent::NetworkSecurityConfig(nsc),
-                ..
-            }) => Some(nsc),
-            _ => unreachable!(),
+        match self.content(path) {
+            Ok(FileContent::NetworkSecurityConfig(nsc)) => Some(nsc),
+            Ok(_) => unreachable!(),
+            Err(err) => {
+                log::error!("failed to parse {path:?}: {err}");
+                None
+            }
         }
     }
 
@@ -205,12 +778,13 @@ impl Package {
     #[must_use]
     pub fn resources(&self) -> Option<&resources::Resources> {
         let path = self.resources_path.as_ref()?;
-        match self.files.get(path) {
-            Some(FileItem {
-                content: FileContent::Resources(r),
-                ..
-            }) => Some(r),
-            _ => unreachable!(),
+        match self.content(path) {
+            Ok(FileContent::Resources(r)) => Some(r),
+            Ok(_) => unreachable!(),
+            Err(err) => {
+                log::error!("failed to parse {path:?}: {err}");
+                None
+            }
         }
     }
 
@@ -220,40 +794,161 @@ impl Package {
     /// this there will be no need to recompute all manifest tables when exporting
     /// the whole package.
     pub fn manifest_mut(&mut self) -> Option<&mut manifest::Manifest> {
+        self.manifest()?;
         let path = self.manifest_path.as_ref()?;
         match self.files.get_mut(path) {
             Some(FileItem {
-                content: FileContent::Manifest(m),
-                modified,
-                ..
+                content, modified, ..
             }) => {
                 *modified = true;
-                Some(m)
+                match content.get_mut() {
+                    Some(FileContent::Manifest(m)) => Some(m),
+                    _ => unreachable!(),
+                }
             }
             _ => unreachable!(),
         }
     }
 
     pub fn set_nsc_path(&mut self, path: PathBuf) -> PackageResult<()> {
+        let nsc = nsc::parse(self.get(&path)?)?;
         match self.files.get_mut(&path) {
-            None => return Err(PackageError::Zip(ZipError::FileNotFound)),
             Some(fileitem) => {
-                if fileitem.modified {
-                    let filename = path.as_path().to_str().unwrap().to_string();
-                    return Err(PackageError::FileHasBeenModified(filename));
-                }
-                fileitem.convert_to_nsc()?;
+                fileitem.content = OnceLock::from(FileContent::NetworkSecurityConfig(nsc));
             }
+            None => unreachable!(),
         }
         self.nsc_path = Some(path);
         Ok(())
     }
 
+    /// Returns the raw content of a file, which is read from the archive on
+    /// first access if the package has been lazily opened.
+    fn raw<'a>(&'a self, path: &Path, fileitem: &'a FileItem) -> PackageResult<&'a [u8]> {
+        if let Some(raw) = fileitem.raw.get() {
+            return Ok(raw);
+        }
+        let Some(archive) = &self.archive else {
+            unreachable!()
+        };
+        let mut buffer = Vec::new();
+        archive
+            .lock()
+            .unwrap()
+            .by_name(path.to_str().unwrap())?
+            .read_to_end(&mut buffer)?;
+        Ok(fileitem.raw.get_or_init(|| buffer))
+    }
+
+    /// Reads the raw content of a file with `read`. If the package has been
+    /// lazily opened and the file has not been accessed yet, the content is
+    /// streamed from the archive without being kept.
+    fn read_with<T>(
+        &self,
+        path: &Path,
+        fileitem: &FileItem,
+        read: impl FnOnce(&mut dyn Read) -> io::Result<T>,
+    ) -> PackageResult<T> {
+        if let Some(raw) = fileitem.raw.get() {
+            return Ok(read(&mut raw.as_slice())?);
+        }
+        let Some(archive) = &self.archive else {
+            unreachable!()
+        };
+        let mut archive = archive.lock().unwrap();
+        let mut file = archive.by_name(path.to_str().unwrap())?;
+        Ok(read(&mut file)?)
+    }
+
+    /// Returns the decoded content of a file, which is parsed on first
+    /// access if the package has been lazily opened.
+    fn content(&self, path: &Path) -> PackageResult<&FileContent> {
+        let fileitem = self
+            .files
+            .get(path)
+            .ok_or(PackageError::Zip(ZipError::FileNotFound))?;
+        if let Some(content) = fileitem.content.get() {
+            return Ok(content);
+        }
+        let raw = self.raw(path, fileitem)?;
+        let content = if self.dexs_path.iter().any(|p| p == path) {
+            FileContent::Dex(dw_dex::parse(raw)?)
+        } else if self.manifest_path.as_deref() == Some(path) {
+            FileContent::Manifest(manifest::parse(raw)?)
+        } else if self.resources_path.as_deref() == Some(path) {
+            FileContent::Resources(resources::parse(raw)?)
+        } else {
+            FileContent::Other
+        };
+        Ok(fileitem.content.get_or_init(|| content))
+    }
+
     pub fn save<P: AsRef<Path>>(&self, path: P, clean_signature: bool) -> PackageResult<()> {
+        self.write_zip(path, clean_signature, false, None)
+    }
+
+    /// Same as [`save`](Self::save), but uncompressed files are aligned as
+    /// `zipalign` does: their data starts on a 4 bytes boundary, or on a
+    /// 16 KiB page boundary for shared libraries so that they can be mapped
+    /// in memory.
+    pub fn save_aligned<P: AsRef<Path>>(
+        &self,
+        path: P,
+        clean_signature: bool,
+    ) -> PackageResult<()> {
+        self.write_zip(path, clean_signature, true, None)
+    }
+
+    /// Same as [`save`](Self::save), but unmodified files are copied from
+    /// the archive the package has been opened from as they are stored in
+    /// it, without being decompressed and compressed again (which also
+    /// preserves their CRC). Only modified and new files are encoded.
+    ///
+    /// The output path can be the one of the opened archive, which is
+    /// replaced once the new archive has been completely written.
+    pub fn save_incremental<P: AsRef<Path>>(
+        &self,
+        path: P,
+        clean_signature: bool,
+    ) -> PackageResult<()> {
+        let mut source = ZipArchive::new(File::open(&self.path)?)?;
+        let in_place = match (path.as_ref().canonicalize(), self.path.canonicalize()) {
+            (Ok(output), Ok(input)) => output == input,
+            _ => false,
+        };
+        if !in_place {
+            return self.write_zip(path, clean_signature, false, Some(&mut source));
+        }
+        let mut tmp_name = path.as_ref().as_os_str().to_owned();
+        tmp_name.push(".tmp");
+        let tmp_path = PathBuf::from(tmp_name);
+        self.write_zip(&tmp_path, clean_signature, false, Some(&mut source))?;
+        drop(source);
+        fs::rename(tmp_path, path)?;
+        Ok(())
+    }
+
+    /// Writes the package files in a new zip archive. Unmodified files that
+    /// come from the `source` archive are copied from it without being
+    /// decompressed.
+    fn write_zip<P: AsRef<Path>>(
+        &self,
+        path: P,
+        clean_signature: bool,
+        aligned: bool,
+        mut source: Option<&mut ZipArchive<File>>,
+    ) -> PackageResult<()> {
         log::trace!("preparing zip file {:?}...", path.as_ref());
         let file = File::create(path)?;
         let mut zip = ZipWriter::new(file);
 
+        let mut source_indices = BTreeMap::new();
+        if let Some(source) = source.as_deref_mut() {
+            for i in 0..source.len() {
+                source_indices.insert(source.by_index_raw(i)?.name().to_string(), i);
+            }
+        }
+
         for (path, fileitem) in &self.files {
             log::trace!("adding {:?} to zip", path);
             let path = path.to_str().unwrap().to_string();
@@ -263,13 +958,29 @@ impl Package {
                     "META-INF/CERT.RSA" | "META-INF/CERT.SF" | "META-INF/MANIFEST.MF"
                 );
             if !drop_it {
-                let options = FileOptions::default().compression_method(fileitem.compression);
-                zip.start_file(&path, options)?;
+                let copied = source
+                    .as_deref_mut()
+                    .filter(|_| fileitem.archived && !fileitem.modified)
+                    .zip(source_indices.get(&path));
+                if let Some((source, index)) = copied {
+                    log::trace!("copying {:?} from source archive", path);
+                    zip.raw_copy_file(source.by_index_raw(*index)?)?;
+                    continue;
+                }
+                let options = FileOptions::default()
+                    .compression_method(fileitem.compression)
+                    .large_file(fileitem.size > u64::from(u32::MAX));
+                if aligned && fileitem.compression == CompressionMethod::Stored {
+                    // padding goes in the extra field of the local file header
+                    zip.start_file_aligned(&path, options, helpers::alignment(&path))?;
+                } else {
+                    zip.start_file(&path, options)?;
+                }
                 if fileitem.keep_it {
                     if fileitem.modified {
-                        match &fileitem.content {
+                        match self.content(Path::new(&path))? {
                             FileContent::Dex(dex) => {
-                                let buf = dw_dex::write(dex, true)?;
+                                let buf = dw_dex::write(dex, &dw_dex::WriteOptions::default())?;
                                 zip.write_all(&buf)?;
                             }
                             FileContent::Manifest(manifest) => {
@@ -290,7 +1001,7 @@ impl Package {
                             }
                         }
                     } else {
-                        zip.write_all(&fileitem.raw)?;
+                        zip.write_all(self.raw(Path::new(&path), fileitem)?)?;
                     }
                 }
             }
@@ -312,9 +1023,47 @@ impl fmt::Display for Package {
     }
 }
 
+/// A deep link, i.e. an URI that can be used to start an activity.
+#[derive(Debug, Clone, PartialEq, Eq, PartialOrd, Ord)]
+pub struct DeepLink {
+    pub activity: String,
+    pub scheme: String,
+    pub host: Option<String>,
+    pub path: Option<DeepLinkPath>,
+}
+
+/// The path part of a [`DeepLink`], as declared in `<data>` elements.
+#[derive(Debug, Clone, PartialEq, Eq, PartialOrd, Ord)]
+pub enum DeepLinkPath {
+    /// A `path` attribute, matched literally.
+    Literal(String),
+    /// A `pathPrefix` attribute.
+    Prefix(String),
+    /// A `pathPattern` attribute, a simple glob.
+    Pattern(String),
+}
+
+/// A file holding certificate pinning material.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct PinningAsset {
+    pub path: PathBuf,
+    pub typ: PinningType,
+}
+
+/// The kinds of certificate pinning material.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum PinningType {
+    /// A PEM or DER encoded certificate.
+    Certificate,
+    /// A PEM or DER encoded public key.
+    PublicKey,
+    /// A configuration file declaring pins (hashes of public keys).
+    Config,
+}
+
 /// Options to select which kind of asset is actually parsed when opening
 /// an Android [package](Package).
-#[derive(Debug)]
+#[derive(Debug, Clone, Copy)]
 pub struct Options {
     parse_dex: bool,
     parse_manifest: bool,
@@ -402,15 +1151,17 @@ impl Options {
             .unwrap()
             .to_string();
 
-        let file = File::open(path)?;
+        let file = File::open(&path)?;
         let mut zip = ZipArchive::new(file)?;
         let mut package = Package {
             name: package_name,
+            path: path.as_ref().to_path_buf(),
             dexs_path: Vec::new(),
             manifest_path: None,
             nsc_path: None,
             resources_path: None,
             files: BTreeMap::new(),
+            archive: None,
         };
 
         let mut tasks = Vec::new();
@@ -478,43 +1229,169 @@ impl Options {
                 package.files.insert(filename, item);
             }
         });
+        for fileitem in package.files.values_mut() {
+            fileitem.archived = true;
+        }
+
+        Ok(package)
+    }
+
+    /// Opens the given split APKs as a single Android application.
+    ///
+    /// The base split is the one named `base.apk`, or the first one. The
+    /// manifest, resources table and `META-INF` files are taken from it,
+    /// and the ones of other splits are ignored. Dex files of other splits
+    /// are renamed as additional `classesN.dex` files if their names are
+    /// already taken. Other files shared by several splits must be
+    /// identical, or a [`PackageError::SplitConflict`] is returned.
+    pub fn open_splits<P: AsRef<Path>>(self, paths: &[P]) -> PackageResult<Package> {
+        let base_index = paths
+            .iter()
+            .position(|path| path.as_ref().file_name() == Some("base.apk".as_ref()))
+            .unwrap_or(0);
+        let base_path = paths
+            .get(base_index)
+            .ok_or(PackageError::Zip(ZipError::FileNotFound))?;
+        let mut package = self.open(base_path)?;
+        for fileitem in package.files.values_mut() {
+            fileitem.split = Some(package.name.clone());
+        }
+
+        let split_options = Self {
+            parse_manifest: false,
+            parse_resources: false,
+            ..self
+        };
+        for (index, path) in paths.iter().enumerate() {
+            if index == base_index {
+                continue;
+            }
+            let split = split_options.open(path)?;
+            for (path, mut fileitem) in split.files {
+                let filename = path.to_str().unwrap();
+                if helpers::is_manifest(filename)
+                    || helpers::is_resources(filename)
+                    || filename.starts_with("META-INF/")
+                {
+                    continue;
+                }
+                if let Some(existing) = package.files.get(&path) {
+                    if existing.raw.get() == fileitem.raw.get() {
+                        continue;
+                    }
+                    if !helpers::is_dex(filename) {
+                        return Err(PackageError::SplitConflict(filename.to_string()));
+                    }
+                }
+                fileitem.split = Some(split.name.clone());
+                // not in the base archive
+                fileitem.archived = false;
+                let is_parsed_dex = split.dexs_path.contains(&path);
+                let path = if package.files.contains_key(&path) {
+                    (2..)
+                        .map(|n| PathBuf::from(format!("classes{n}.dex")))
+                        .find(|path| !package.files.contains_key(path))
+                        .unwrap()
+                } else {
+                    path
+                };
+                if is_parsed_dex {
+                    package.dexs_path.push(path.clone());
+                }
+                package.files.insert(path, fileitem);
+            }
+        }
+
+        Ok(package)
+    }
+
+    /// Opens the given filename as an Android application without reading
+    /// nor decompressing file contents, files are only listed from the zip
+    /// central directory.
+    ///
+    /// The archive is kept open, and files are read (then parsed, for Dex
+    /// files, manifest and resources) on first access. This avoids loading
+    /// a whole package in memory when only some of its files are needed.
+    pub fn open_lazy<P: AsRef<Path>>(self, path: P) -> PackageResult<Package> {
+        let package_name = path
+            .as_ref()
+            .file_name()
+            .unwrap()
+            .to_str()
+            .unwrap()
+            .to_string();
+
+        let file = File::open(&path)?;
+        let mut zip = ZipArchive::new(file)?;
+        let mut package = Package {
+            name: package_name,
+            path: path.as_ref().to_path_buf(),
+            dexs_path: Vec::new(),
+            manifest_path: None,
+            nsc_path: None,
+            resources_path: None,
+            files: BTreeMap::new(),
+            archive: None,
+        };
+
+        for i in 0..zip.len() {
+            let file = zip.by_index_raw(i)?;
+            let pathbuf = PathBuf::from(file.name());
+            if self.parse_dex && helpers::is_dex(file.name()) {
+                package.dexs_path.push(pathbuf.clone());
+            } else if self.parse_manifest && helpers::is_manifest(file.name()) {
+                package.manifest_path = Some(pathbuf.clone());
+            } else if self.parse_resources && helpers::is_resources(file.name()) {
+                package.resources_path = Some(pathbuf.clone());
+            }
+            package
+                .files
+                .insert(pathbuf, FileItem::new_lazy(file.size(), file.compression()));
+        }
 
+        package.archive = Some(Mutex::new(zip));
         Ok(package)
     }
 }
 
 #[derive(Debug)]
 struct FileItem {
-    raw: Vec<u8>,
+    raw: OnceLock<Vec<u8>>,
+    size: u64,
     compression: CompressionMethod,
     modified: bool,
     keep_it: bool,
-    content: FileContent,
+    content: OnceLock<FileContent>,
+    split: Option<String>,
+    /// Whether the raw content is the one of the entry with the same name
+    /// in the archive the package has been opened from.
+    archived: bool,
 }
 
 impl FileItem {
-    fn new_dex(raw: Vec<u8>, compression: CompressionMethod, dex: dw_dex::Dex) -> Self {
+    fn new(raw: Vec<u8>, compression: CompressionMethod, content: FileContent) -> Self {
         Self {
-            raw,
+            size: raw.len() as u64,
+            raw: OnceLock::from(raw),
             compression,
             modified: false,
             keep_it: true,
-            content: FileContent::Dex(dex),
+            content: OnceLock::from(content),
+            split: None,
+            archived: false,
         }
     }
 
+    fn new_dex(raw: Vec<u8>, compression: CompressionMethod, dex: dw_dex::Dex) -> Self {
+        Self::new(raw, compression, FileContent::Dex(dex))
+    }
+
     fn new_manifest(
         raw: Vec<u8>,
         compression: CompressionMethod,
         manifest: manifest::Manifest,
     ) -> Self {
-        Self {
-            raw,
-            compression,
-            modified: false,
-            keep_it: true,
-            content: FileContent::Manifest(manifest),
-        }
+        Self::new(raw, compression, FileContent::Manifest(manifest))
     }
 
     fn new_resources(
@@ -522,31 +1399,27 @@ impl FileItem {
         compression: CompressionMethod,
         resources: resources::Resources,
     ) -> Self {
-        Self {
-            raw,
-            compression,
-            modified: false,
-            keep_it: true,
-            content: FileContent::Resources(resources),
-        }
+        Self::new(raw, compression, FileContent::Resources(resources))
     }
 
     fn new_other(raw: Vec<u8>, compression: CompressionMethod) -> Self {
+        Self::new(raw, compression, FileContent::Other)
+    }
+
+    /// A file whose raw and decoded contents are loaded on first access.
+    fn new_lazy(size: u64, compression: CompressionMethod) -> Self {
         Self {
-            raw,
+            raw: OnceLock::new(),
+            size,
             compression,
             modified: false,
             keep_it: true,
-            content: FileContent::Other,
+            content: OnceLock::new(),
+            split: None,
+            archived: true,
         }
     }
 
-    fn convert_to_nsc(&mut self) -> PackageResult<()> {
-        let nsc = nsc::parse(&self.raw)?;
-        self.content = FileContent::NetworkSecurityConfig(nsc);
-        Ok(())
-    }
-
     fn modify(&mut self) {
         self.modified = true;
     }
@@ -560,3 +1433,1000 @@ pub(crate) enum FileContent {
     Resources(resources::Resources),
     Other,
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use dw_dex::testing::{DexBuilder, ACC_PUBLIC, ACC_STATIC};
+    use dw_resources::testing::{xml, Node};
+
+    /// Assembles a binary XML manifest made of a single `<manifest>` node
+    /// holding a `package` attribute.
+    fn manifest_xml(strings: &[&str], value_type: u8, data: u32) -> Vec<u8> {
+        xml(
+            strings,
+            &[Node::Start(0, &[(1, value_type, data)]), Node::End(0)],
+        )
+    }
+
+    /// Writes a zip file with the given files.
+    fn write_zip(name: &str, files: &[(&str, &[u8])]) -> PathBuf {
+        let path = std::env::temp_dir().join(format!("{}-{name}.apk", std::process::id()));
+        let mut zip = ZipWriter::new(File::create(&path).unwrap());
+        for (filename, content) in files {
+            zip.start_file(*filename, FileOptions::default()).unwrap();
+            zip.write_all(content).unwrap();
+        }
+        zip.finish().unwrap();
+        path
+    }
+
+    /// Writes a zip file with the given manifest and an invalid dex file,
+    /// which would make a full package opening fail.
+    fn write_package(name: &str, manifest: &[u8]) -> PathBuf {
+        write_zip(
+            name,
+            &[
+                ("classes.dex", b"not a dex file"),
+                ("AndroidManifest.xml", manifest),
+            ],
+        )
+    }
+
+    #[test]
+    fn package_name_only_string() {
+        let xml = manifest_xml(&["manifest", "package", "com.example.app"], 0x03, 2);
+        let path = write_package("string", &xml);
+        assert!(Package::open(&path).is_err());
+        let name = Package::package_name_only(&path);
+        std::fs::remove_file(&path).unwrap();
+        assert_eq!(name.unwrap(), "com.example.app");
+    }
+
+    #[test]
+    fn package_name_only_unresolved_reference() {
+        let xml = manifest_xml(&["manifest", "package"], 0x01, 0x7f01_0000);
+        let path = write_package("reference", &xml);
+        let name = Package::package_name_only(&path);
+        std::fs::remove_file(&path).unwrap();
+        assert_eq!(name.unwrap(), "@0x7f010000");
+    }
+
+    #[test]
+    fn open_lazy_reads_requested_files_only() {
+        let xml = manifest_xml(&["manifest", "package", "com.example.app"], 0x03, 2);
+        let path = write_zip(
+            "lazy",
+            &[
+                ("classes.dex", b"not a dex file"),
+                ("AndroidManifest.xml", &xml),
+                ("res/raw/readme.txt", b"nothing to see here"),
+            ],
+        );
+        let package = Options::manifest_only().open_lazy(&path).unwrap();
+        assert_eq!(package.iter_filenames().count(), 3);
+        assert!(package.files.values().all(|item| item.raw.get().is_none()));
+
+        let name = package.manifest().unwrap().package().unwrap();
+        let loaded: Vec<PathBuf> = package
+            .files
+            .iter()
+            .filter(|(_, item)| item.raw.get().is_some())
+            .map(|(path, _)| path.clone())
+            .collect();
+        drop(package);
+        std::fs::remove_file(&path).unwrap();
+        assert_eq!(name.as_deref(), Some("com.example.app"));
+        assert_eq!(loaded, vec![PathBuf::from("AndroidManifest.xml")]);
+    }
+
+    /// Writes a zip file whose entries and central directory are all located
+    /// through Zip64 records: a stored "big.bin" entry of `big` zeros is
+    /// followed by an "after.txt" entry. The file is then lazily opened,
+    /// and the sizes of its files and the content of "after.txt" are
+    /// returned.
+    fn open_lazy_zip64(big: u64) -> (Vec<(PathBuf, u64)>, Vec<u8>) {
+        use std::io::{Seek, SeekFrom};
+
+        // a stored "big.bin" entry of zeros (left as a hole in a sparse file)
+        // followed by an "after.txt" entry, both of them and the central
+        // directory being located through Zip64 records whatever their sizes
+        const CONTENT: &[u8] = b"after the boundary";
+        const CRC: u32 = 0x5afa_344b;
+        let zip64_sizes =
+            |size: u64| [&[1, 0, 16, 0][..], &size.to_le_bytes(), &size.to_le_bytes()].concat();
+        let local_header = |name: &str, crc: u32, size: u32, extra: &[u8]| {
+            let mut header = Vec::new();
+            header.extend_from_slice(&0x0403_4b50u32.to_le_bytes());
+            for v in [45u16, 0, 0, 0, 0x21] {
+                header.extend_from_slice(&v.to_le_bytes());
+            }
+            for v in [crc, size, size] {
+                header.extend_from_slice(&v.to_le_bytes());
+            }
+            header.extend_from_slice(&(name.len() as u16).to_le_bytes());
+            header.extend_from_slice(&(extra.len() as u16).to_le_bytes());
+            header.extend_from_slice(name.as_bytes());
+            header.extend_from_slice(extra);
+            header
+        };
+        let central_header = |name: &str, crc: u32, size: u32, offset: u32, extra: &[u8]| {
+            let mut header = Vec::new();
+            header.extend_from_slice(&0x0201_4b50u32.to_le_bytes());
+            for v in [45u16, 45, 0, 0, 0, 0x21] {
+                header.extend_from_slice(&v.to_le_bytes());
+            }
+            for v in [crc, size, size] {
+                header.extend_from_slice(&v.to_le_bytes());
+            }
+            for v in [name.len() as u16, extra.len() as u16, 0, 0, 0] {
+                header.extend_from_slice(&v.to_le_bytes());
+            }
+            header.extend_from_slice(&0u32.to_le_bytes());
+            header.extend_from_slice(&offset.to_le_bytes());
+            header.extend_from_slice(name.as_bytes());
+            header.extend_from_slice(extra);
+            header
+        };
+
+        let big_header = local_header("big.bin", 0, u32::MAX, &zip64_sizes(big));
+        let after_offset = big_header.len() as u64 + big;
+        let mut after = local_header("after.txt", CRC, CONTENT.len() as u32, &[]);
+        after.extend_from_slice(CONTENT);
+
+        let directory_offset = after_offset + after.len() as u64;
+        let mut directory = central_header("big.bin", 0, u32::MAX, 0, &zip64_sizes(big));
+        directory.extend(central_header(
+            "after.txt",
+            CRC,
+            CONTENT.len() as u32,
+            u32::MAX,
+            &[&[1, 0, 8, 0][..], &after_offset.to_le_bytes()].concat(),
+        ));
+        let mut footer = Vec::new();
+        // Zip64 end of central directory record
+        footer.extend_from_slice(&0x0606_4b50u32.to_le_bytes());
+        footer.extend_from_slice(&44u64.to_le_bytes());
+        footer.extend_from_slice(&[45, 0, 45, 0, 0, 0, 0, 0, 0, 0, 0, 0]);
+        for v in [2, 2, directory.len() as u64, directory_offset] {
+            footer.extend_from_slice(&v.to_le_bytes());
+        }
+        // Zip64 end of central directory locator
+        footer.extend_from_slice(&0x0706_4b50u32.to_le_bytes());
+        footer.extend_from_slice(&0u32.to_le_bytes());
+        footer.extend_from_slice(&(directory_offset + directory.len() as u64).to_le_bytes());
+        footer.extend_from_slice(&1u32.to_le_bytes());
+        // end of central directory record
+        footer.extend_from_slice(&0x0605_4b50u32.to_le_bytes());
+        for v in [0u16, 0, 2, 2] {
+            footer.extend_from_slice(&v.to_le_bytes());
+        }
+        footer.extend_from_slice(&(directory.len() as u32).to_le_bytes());
+        footer.extend_from_slice(&u32::MAX.to_le_bytes());
+        footer.extend_from_slice(&0u16.to_le_bytes());
+
+        let path = std::env::temp_dir().join(format!("{}-zip64-{big}.apk", std::process::id()));
+        let mut file = File::create(&path).unwrap();
+        file.write_all(&big_header).unwrap();
+        file.seek(SeekFrom::Start(after_offset)).unwrap();
+        file.write_all(&after).unwrap();
+        file.write_all(&directory).unwrap();
+        file.write_all(&footer).unwrap();
+        drop(file);
+
+        let package = Options::default().open_lazy(&path).unwrap();
+        let sizes: Vec<(PathBuf, u64)> = package
+            .iter_filenames_with_size()
+            .map(|(name, size)| (name.to_path_buf(), size))
+            .collect();
+        let content = package.get(&PathBuf::from("after.txt")).map(<[u8]>::to_vec);
+        drop(package);
+        std::fs::remove_file(&path).unwrap();
+        (sizes, content.unwrap())
+    }
+
+    #[test]
+    fn open_lazy_zip64_records() {
+        let (sizes, content) = open_lazy_zip64(16);
+        assert_eq!(
+            sizes,
+            vec![
+                (PathBuf::from("after.txt"), 18),
+                (PathBuf::from("big.bin"), 16),
+            ]
+        );
+        assert_eq!(content, b"after the boundary");
+    }
+
+    #[test]
+    #[ignore = "writes a 4GB sparse file, which some file systems allocate"]
+    fn open_lazy_zip64_entries() {
+        // the local header of "after.txt" lies past 4GB
+        const BIG: u64 = 0x1_0000_0010;
+        let (sizes, content) = open_lazy_zip64(BIG);
+        assert_eq!(
+            sizes,
+            vec![
+                (PathBuf::from("after.txt"), 18),
+                (PathBuf::from("big.bin"), BIG),
+            ]
+        );
+        assert_eq!(content, b"after the boundary");
+    }
+
+    #[test]
+    fn save_large_file_entries() {
+        let path = write_zip("large", &[("big.bin", b"pretends to be big")]);
+        let mut package = Options::dex_only().open(&path).unwrap();
+        std::fs::remove_file(&path).unwrap();
+        // only the recorded size decides whether the entry is written as a
+        // Zip64 one
+        package.files.get_mut(Path::new("big.bin")).unwrap().size = u64::from(u32::MAX) + 1;
+
+        let output = std::env::temp_dir().join(format!("{}-large.apk", std::process::id()));
+        package.save(&output, false).unwrap();
+        let raw = std::fs::read(&output).unwrap();
+        let mut zip = ZipArchive::new(File::open(&output).unwrap()).unwrap();
+        let mut content = Vec::new();
+        zip.by_name("big.bin")
+            .unwrap()
+            .read_to_end(&mut content)
+            .unwrap();
+        std::fs::remove_file(&output).unwrap();
+        // the local header extra field starts with a Zip64 one
+        let name_len = usize::from(u16::from_le_bytes([raw[26], raw[27]]));
+        assert_eq!(raw[30 + name_len..32 + name_len], [1, 0]);
+        assert_eq!(content, b"pretends to be big");
+    }
+
+    #[test]
+    fn save_aligned_stored_files() {
+        let path = write_zip("unaligned", &[("AndroidManifest.xml", b"not a manifest")]);
+        let mut package = Options::dex_only().open(&path).unwrap();
+        std::fs::remove_file(&path).unwrap();
+        package
+            .insert_file(PathBuf::from("a.txt"), b"x".to_vec())
+            .unwrap();
+        package
+            .insert_file(PathBuf::from("assets/b.bin"), b"yy".to_vec())
+            .unwrap();
+        package
+            .insert_file(PathBuf::from("lib/arm64-v8a/libc.so"), b"zzz".to_vec())
+            .unwrap();
+
+        let output = std::env::temp_dir().join(format!("{}-aligned.apk", std::process::id()));
+        package.save_aligned(&output, false).unwrap();
+        let mut zip = ZipArchive::new(File::open(&output).unwrap()).unwrap();
+        let mut stored = Vec::new();
+        for i in 0..zip.len() {
+            let file = zip.by_index(i).unwrap();
+            if file.compression() == CompressionMethod::Stored {
+                let alignment = u64::from(helpers::alignment(file.name()));
+                stored.push((file.name().to_string(), file.data_start() % alignment));
+            }
+        }
+        std::fs::remove_file(&output).unwrap();
+        assert_eq!(
+            stored,
+            vec![
+                ("a.txt".to_string(), 0),
+                ("assets/b.bin".to_string(), 0),
+                ("lib/arm64-v8a/libc.so".to_string(), 0),
+            ]
+        );
+    }
+
+    #[test]
+    fn open_splits_merges_dexs() {
+        let manifest = manifest_xml(&["manifest", "package", "com.example.app"], 0x03, 2);
+        let base = write_zip(
+            "split-base",
+            &[
+                ("AndroidManifest.xml", &manifest),
+                ("classes.dex", &class_dex("LFoo;", 1)),
+                ("res/raw/readme.txt", b"shared"),
+            ],
+        );
+        let config = write_zip(
+            "split-config",
+            &[
+                ("AndroidManifest.xml", b"not a manifest"),
+                ("classes.dex", &class_dex("LBar;", 1)),
+                ("lib/arm64-v8a/libfoo.so", b"native code"),
+                ("res/raw/readme.txt", b"shared"),
+            ],
+        );
+        let conflicting = write_zip("split-conflict", &[("res/raw/readme.txt", b"other")]);
+        let package = Package::open_splits(&[&base, &config]);
+        let conflict = Package::open_splits(&[&base, &conflicting]);
+        for path in [base, config, conflicting] {
+            std::fs::remove_file(path).unwrap();
+        }
+
+        let package = package.unwrap();
+        let classes: Vec<String> = package
+            .iter_dexs()
+            .flat_map(|dex| {
+                dex.iter_class_defs()
+                    .map(|class| class.class_name(dex).unwrap())
+            })
+            .collect();
+        assert_eq!(classes, ["Foo", "Bar"]);
+        assert_eq!(
+            package.manifest().unwrap().package().unwrap().as_deref(),
+            Some("com.example.app")
+        );
+        let split_name = format!("{}-split-config.apk", std::process::id());
+        assert_eq!(
+            package.file_split(&PathBuf::from("classes2.dex")),
+            Some(split_name.as_str())
+        );
+        assert_eq!(
+            package.file_split(&PathBuf::from("lib/arm64-v8a/libfoo.so")),
+            Some(split_name.as_str())
+        );
+        assert!(
+            matches!(conflict, Err(PackageError::SplitConflict(name)) if name == "res/raw/readme.txt")
+        );
+    }
+
+    #[test]
+    fn insert_dex_saved() {
+        let manifest = manifest_xml(&["manifest", "package", "com.example.app"], 0x03, 2);
+        let path = write_zip(
+            "insert-dex",
+            &[
+                ("AndroidManifest.xml", &manifest),
+                ("classes.dex", &class_dex("LFoo;", 1)),
+            ],
+        );
+        let mut package = Options::default()
+            .dont_parse_resources()
+            .open(&path)
+            .unwrap();
+        let dex = || dw_dex::parse(&class_dex("LBar;", 1)).unwrap();
+        assert!(matches!(
+            package.insert_dex(PathBuf::from("extra.dex"), dex()),
+            Err(PackageError::InvalidDexName(name)) if name == "extra.dex"
+        ));
+        assert_eq!(package.next_dex_name(), PathBuf::from("classes2.dex"));
+        package.insert_dex(package.next_dex_name(), dex()).unwrap();
+        assert_eq!(package.next_dex_name(), PathBuf::from("classes3.dex"));
+
+        let saved =
+            std::env::temp_dir().join(format!("{}-insert-dex-saved.apk", std::process::id()));
+        package.save(&saved, false).unwrap();
+        let package = Options::default().dont_parse_resources().open(&saved);
+        std::fs::remove_file(&path).unwrap();
+        std::fs::remove_file(&saved).unwrap();
+
+        let package = package.unwrap();
+        let classes: Vec<String> = package
+            .iter_dexs()
+            .flat_map(|dex| {
+                dex.iter_class_defs()
+                    .map(|class| class.class_name(dex).unwrap())
+            })
+            .collect();
+        assert_eq!(classes, ["Foo", "Bar"]);
+    }
+
+    #[test]
+    fn save_incremental_copies_unmodified_files() {
+        let manifest = manifest_xml(&["manifest", "package", "com.example.app"], 0x03, 2);
+        // the dex is compressed with a level that re-encoding would not use
+        let path = std::env::temp_dir().join(format!("{}-incremental.apk", std::process::id()));
+        let mut zip = ZipWriter::new(File::create(&path).unwrap());
+        zip.start_file("AndroidManifest.xml", FileOptions::default())
+            .unwrap();
+        zip.write_all(&manifest).unwrap();
+        let options = FileOptions::default().compression_level(Some(1));
+        zip.start_file("classes.dex", options).unwrap();
+        zip.write_all(&class_dex("LFoo;", 1)).unwrap();
+        zip.finish().unwrap();
+        // compressed bytes and CRC of the dex entry
+        let stored = |path: &Path| {
+            let mut zip = ZipArchive::new(File::open(path).unwrap()).unwrap();
+            let index = (0..zip.len())
+                .find(|i| zip.by_index_raw(*i).unwrap().name() == "classes.dex")
+                .unwrap();
+            let mut file = zip.by_index_raw(index).unwrap();
+            let mut buffer = Vec::new();
+            file.read_to_end(&mut buffer).unwrap();
+            (buffer, file.crc32())
+        };
+        let original = stored(&path);
+
+        let mut package = Options::default()
+            .dont_parse_resources()
+            .open(&path)
+            .unwrap();
+        package
+            .manifest_mut()
+            .unwrap()
+            .set_package("com.example.other")
+            .unwrap();
+        let recompressed =
+            std::env::temp_dir().join(format!("{}-recompressed.apk", std::process::id()));
+        package.save(&recompressed, false).unwrap();
+        assert_ne!(stored(&recompressed).0, original.0);
+        std::fs::remove_file(&recompressed).unwrap();
+        // saved in place
+        package.save_incremental(&path, false).unwrap();
+        let saved = stored(&path);
+        let package = Options::default().dont_parse_resources().open(&path);
+        std::fs::remove_file(&path).unwrap();
+
+        assert_eq!(saved, original);
+        let package = package.unwrap();
+        assert_eq!(
+            package.manifest().unwrap().package().unwrap().as_deref(),
+            Some("com.example.other")
+        );
+        assert_eq!(package.iter_dexs().count(), 1);
+    }
+
+    #[test]
+    fn deep_links_merged_data() {
+        let strings = [
+            "manifest",
+            "package",
+            "com.example.app",
+            "application",
+            "activity",
+            "name",
+            "Main",
+            "intent-filter",
+            "action",
+            "android.intent.action.VIEW",
+            "data",
+            "scheme",
+            "https",
+            "host",
+            "example.com",
+        ];
+        let string = |name, value| (name, 0x03, value);
+        let xml = xml(
+            &strings,
+            &[
+                Node::Start(0, &[string(1, 2)]),
+                Node::Start(3, &[]),
+                Node::Start(4, &[string(5, 6)]),
+                Node::Start(7, &[]),
+                Node::Start(8, &[string(5, 9)]),
+                Node::End(8),
+                Node::Start(10, &[string(11, 12)]),
+                Node::End(10),
+                Node::Start(10, &[string(13, 14)]),
+                Node::End(10),
+                Node::End(7),
+                Node::End(4),
+                Node::End(3),
+                Node::End(0),
+            ],
+        );
+        let path = write_package("deeplinks", &xml);
+        let package = Options::manifest_only().open(&path);
+        std::fs::remove_file(&path).unwrap();
+
+        let links = package.unwrap().deep_links().unwrap();
+        assert_eq!(
+            links,
+            vec![DeepLink {
+                activity: "com.example.app.Main".to_string(),
+                scheme: "https".to_string(),
+                host: Some("example.com".to_string()),
+                path: None,
+            }]
+        );
+    }
+
+    #[test]
+    fn pinning_material_pem_certificate() {
+        let pem = b"-----BEGIN CERTIFICATE-----\nMIIB\n-----END CERTIFICATE-----\n";
+        let path = write_zip(
+            "pinning",
+            &[
+                ("res/raw/server.txt", pem),
+                ("res/raw/readme.txt", b"nothing to see here"),
+                ("assets/server.pem.bak", b"not a certificate"),
+            ],
+        );
+        let package = Options::manifest_only().open(&path);
+        std::fs::remove_file(&path).unwrap();
+
+        assert_eq!(
+            package.unwrap().pinning_material(),
+            vec![PinningAsset {
+                path: PathBuf::from("res/raw/server.txt"),
+                typ: PinningType::Certificate,
+            }]
+        );
+    }
+
+    #[test]
+    fn security_report_debuggable() {
+        let strings = [
+            "manifest",
+            "package",
+            "com.example.app",
+            "application",
+            "debuggable",
+            "allowBackup",
+            "uses-sdk",
+            "targetSdkVersion",
+        ];
+        let xml = xml(
+            &strings,
+            &[
+                Node::Start(0, &[(1, 0x03, 2)]),
+                Node::Start(6, &[(7, 0x10, 30)]),
+                Node::End(6),
+                Node::Start(3, &[(4, 0x12, 0xffff_ffff), (5, 0x12, 0)]),
+                Node::End(3),
+                Node::End(0),
+            ],
+        );
+        let path = write_package("debuggable", &xml);
+        let package = Options::manifest_only().open(&path);
+        std::fs::remove_file(&path).unwrap();
+
+        let report = package.unwrap().security_report().unwrap();
+        assert_eq!(report.package.as_deref(), Some("com.example.app"));
+        assert!(!report.code_analyzed);
+        assert_eq!(
+            report.findings,
+            vec![report::Finding {
+                check: Check::Debuggable,
+                severity: Severity::High,
+                message: "application is debuggable".to_string(),
+            }]
+        );
+    }
+
+    #[test]
+    fn security_report_implicit_export() {
+        let strings = [
+            "manifest",
+            "uses-sdk",
+            "targetSdkVersion",
+            "application",
+            "allowBackup",
+            "activity",
+            "name",
+            ".Main",
+            "intent-filter",
+            "service",
+            ".Sync",
+            "exported",
+        ];
+        let report = |target_sdk| {
+            let xml = xml(
+                &strings,
+                &[
+                    Node::Start(0, &[]),
+                    Node::Start(1, &[(2, 0x10, target_sdk)]),
+                    Node::End(1),
+                    Node::Start(3, &[(4, 0x12, 0)]),
+                    Node::Start(5, &[(6, 0x03, 7)]),
+                    Node::Start(8, &[]),
+                    Node::End(8),
+                    Node::End(5),
+                    Node::Start(9, &[(6, 0x03, 10), (11, 0x12, 0xffff_ffff)]),
+                    Node::Start(8, &[]),
+                    Node::End(8),
+                    Node::End(9),
+                    Node::End(3),
+                    Node::End(0),
+                ],
+            );
+            let path = write_package(&format!("implicit-export-{target_sdk}"), &xml);
+            let package = Options::manifest_only().open(&path);
+            std::fs::remove_file(&path).unwrap();
+            package.unwrap().security_report().unwrap().findings
+        };
+        let exported = |severity, message: &str| report::Finding {
+            check: Check::ExportedComponent,
+            severity,
+            message: message.to_string(),
+        };
+
+        let findings = report(30);
+        assert_eq!(findings.len(), 2);
+        assert!(findings.contains(&exported(
+            Severity::Medium,
+            "activity .Main is implicitly exported by an intent filter"
+        )));
+        assert!(findings.contains(&exported(Severity::Low, "service .Sync is exported")));
+
+        let findings = report(31);
+        assert!(findings.contains(&exported(Severity::Low, "activity .Main is exported")));
+    }
+
+    #[test]
+    fn info_json_permissions() {
+        let strings = [
+            "manifest",
+            "package",
+            "com.example.app",
+            "uses-permission",
+            "name",
+            "android.permission.INTERNET",
+            "android.permission.CAMERA",
+            "application",
+            "activity",
+            ".Main",
+            "service",
+            "Sync",
+        ];
+        let string = |name, value| (name, 0x03, value);
+        let xml = xml(
+            &strings,
+            &[
+                Node::Start(0, &[string(1, 2)]),
+                Node::Start(3, &[string(4, 5)]),
+                Node::End(3),
+                Node::Start(3, &[string(4, 6)]),
+                Node::End(3),
+                Node::Start(7, &[]),
+                Node::Start(8, &[string(4, 9)]),
+                Node::End(8),
+                Node::Start(10, &[string(4, 11)]),
+                Node::End(10),
+                Node::End(7),
+                Node::End(0),
+            ],
+        );
+        let path = write_package("info", &xml);
+        let package = Options::manifest_only().open(&path);
+        std::fs::remove_file(&path).unwrap();
+
+        let info = package.unwrap().info().unwrap();
+        assert_eq!(info.activities, ["com.example.app.Main"]);
+        assert_eq!(info.services, ["com.example.app.Sync"]);
+        let json = serde_json::to_value(&info).unwrap();
+        assert_eq!(json["package"], "com.example.app");
+        assert_eq!(
+            json["permissions"],
+            serde_json::json!(["android.permission.INTERNET", "android.permission.CAMERA"])
+        );
+    }
+
+    /// Assembles a dex file declaring a single class without members.
+    /// Parsing does not check the header checksum, which is set to the given
+    /// value to mimic different builds.
+    fn class_dex(class: &str, checksum: u32) -> Vec<u8> {
+        strings_dex(class, &[], checksum)
+    }
+
+    /// Assembles a dex file declaring a single class without members, along
+    /// with additional strings that are not referenced by the class.
+    fn strings_dex(class: &str, strings: &[&str], checksum: u32) -> Vec<u8> {
+        let mut builder = DexBuilder::new();
+        builder.class(class, "Ljava/lang/Object;");
+        for string in strings {
+            builder.string(string);
+        }
+        builder.checksum(checksum);
+        builder.build()
+    }
+
+    #[test]
+    fn network_indicators() {
+        let dex = strings_dex(
+            "LFoo;",
+            &[
+                "https://c2.example.com/gate.php",
+                "android.intent.action.VIEW",
+            ],
+            1,
+        );
+        let path = write_zip("indicators", &[("classes.dex", &dex)]);
+        let package = Options::default().dont_parse_resources().open(&path);
+        std::fs::remove_file(&path).unwrap();
+
+        let indicators = package.unwrap().network_indicators().unwrap();
+        assert_eq!(
+            indicators.urls.keys().collect::<Vec<_>>(),
+            ["https://c2.example.com/gate.php"]
+        );
+        assert_eq!(
+            indicators.domains.keys().collect::<Vec<_>>(),
+            ["c2.example.com"]
+        );
+        assert!(indicators.ips.is_empty());
+    }
+
+    /// Assembles a dex file declaring a `LStub;` class, whose static `run`
+    /// method calls the `DexClassLoader` constructor and `DexFile.loadDex`.
+    fn loader_dex() -> Vec<u8> {
+        let mut builder = DexBuilder::new();
+        let class = builder.class("LStub;", "Ljava/lang/Object;");
+        let run = builder.method("LStub;", "run", "V", &[]);
+        let loader = builder.type_("Ldalvik/system/DexClassLoader;") as u16;
+        let init = builder.method(
+            "Ldalvik/system/DexClassLoader;",
+            "<init>",
+            "V",
+            &[
+                "Ljava/lang/String;",
+                "Ljava/lang/String;",
+                "Ljava/lang/String;",
+                "Ljava/lang/ClassLoader;",
+            ],
+        ) as u16;
+        let load_dex = builder.method(
+            "Ldalvik/system/DexFile;",
+            "loadDex",
+            "Ldalvik/system/DexFile;",
+            &["Ljava/lang/String;", "Ljava/lang/String;", "I"],
+        ) as u16;
+        // new-instance v0, Ldalvik/system/DexClassLoader;
+        // invoke-direct {v0, v1, v2, v3, v4}, Ldalvik/system/DexClassLoader;-><init>(...)V
+        // invoke-static {v1, v2, v3}, Ldalvik/system/DexFile;->loadDex(...)
+        // return-void
+        builder.code(
+            class,
+            run,
+            ACC_PUBLIC | ACC_STATIC,
+            5,
+            0,
+            5,
+            &[
+                0x0022, loader, 0x5470, init, 0x3210, 0x3071, load_dex, 0x0321, 0x000e,
+            ],
+        );
+        builder.build()
+    }
+
+    #[test]
+    fn packing_report() {
+        // xorshift output, close to 8 bits of entropy per byte
+        let mut state = 0x2545_f491_u32;
+        let payload: Vec<u8> = (0..0x10000)
+            .map(|_| {
+                state ^= state << 13;
+                state ^= state >> 17;
+                state ^= state << 5;
+                state as u8
+            })
+            .collect();
+        let plugin = [b"PK\x03\x04", &payload[..]].concat();
+        let logo = vec![0u8; 0x8000];
+        let dex = loader_dex();
+        let path = write_zip(
+            "packing",
+            &[
+                ("classes.dex", &dex),
+                ("assets/payload.jar", &payload),
+                ("assets/plugin.jar", &plugin),
+                ("assets/logo.png", &logo),
+            ],
+        );
+        let package = Options::default().dont_parse_resources().open(&path);
+        let lazy = Options::default().dont_parse_resources().open_lazy(&path);
+        std::fs::remove_file(&path).unwrap();
+
+        let report = package.unwrap().packing_report().unwrap();
+        assert!(report.is_packed());
+        assert!((report.confidence - 1.0).abs() < 1e-9);
+        assert!(matches!(
+            &report.evidence[0],
+            Evidence::HighEntropyAsset { path, entropy }
+                if path == Path::new("assets/payload.jar") && *entropy > 7.9
+        ));
+        assert_eq!(
+            report.evidence[1..],
+            [
+                Evidence::DexLoader {
+                    loader: "Ldalvik/system/DexClassLoader;".to_string(),
+                    method: "LStub;->run()V".to_string(),
+                },
+                Evidence::DexLoader {
+                    loader: "Ldalvik/system/DexFile;->loadDex".to_string(),
+                    method: "LStub;->run()V".to_string(),
+                },
+                Evidence::SmallPrimaryDex {
+                    dex_size: dex.len() as u64,
+                    assets_size: 0x28004,
+                },
+            ]
+        );
+
+        // assets are not kept in memory once scanned
+        let lazy = lazy.unwrap();
+        assert_eq!(lazy.packing_report().unwrap(), report);
+        assert!(lazy.files[Path::new("assets/payload.jar")]
+            .raw
+            .get()
+            .is_none());
+    }
+
+    #[test]
+    fn open_vdex_enumerates_classes() {
+        let dexs = [class_dex("LFoo;", 1), class_dex("LBar;", 2)];
+        let dex_size: usize = dexs.iter().map(|dex| dex.len() + 4).sum();
+
+        let mut vdex_021 = b"vdex021\x00002\0".to_vec();
+        // dex files, verifier deps, boot classpath and class loader sizes
+        for v in [2, 0, 0, 0] {
+            vdex_021.extend_from_slice(&u32::to_le_bytes(v));
+        }
+        // checksums, then dex, shared data and quickening info sizes
+        for v in [1, 2, dex_size as u32, 0, 0] {
+            vdex_021.extend_from_slice(&u32::to_le_bytes(v));
+        }
+        for dex in &dexs {
+            // quickening table offset
+            vdex_021.extend_from_slice(&0u32.to_le_bytes());
+            vdex_021.extend_from_slice(dex);
+        }
+
+        let mut vdex_027 = b"vdex027\0".to_vec();
+        // one section: dex files at offset 24
+        for v in [1, 1, 24, dexs[1].len() as u32] {
+            vdex_027.extend_from_slice(&u32::to_le_bytes(v));
+        }
+        vdex_027.extend_from_slice(&dexs[1]);
+
+        let classes = |vdex: &[u8]| -> PackageResult<Vec<String>> {
+            let path = std::env::temp_dir().join(format!("{}-base.vdex", std::process::id()));
+            std::fs::write(&path, vdex).unwrap();
+            let package = Package::open_vdex(&path);
+            std::fs::remove_file(&path).unwrap();
+            Ok(package?
+                .iter_dexs()
+                .flat_map(|dex| {
+                    dex.iter_class_defs()
+                        .map(|class| class.class_name(dex).unwrap())
+                })
+                .collect())
+        };
+        assert_eq!(classes(&vdex_021).unwrap(), ["Foo", "Bar"]);
+        assert_eq!(classes(&vdex_027).unwrap(), ["Bar"]);
+        assert!(matches!(
+            classes(b"vdex006\0"),
+            Err(PackageError::UnsupportedVdexVersion(version)) if version == "006"
+        ));
+    }
+
+    #[test]
+    fn code_fingerprint_ignores_packaging() {
+        let manifest = manifest_xml(&["manifest", "package", "com.example.app"], 0x03, 2);
+        let fingerprint = |name: &str, files: &[(&str, &[u8])]| {
+            let path = write_zip(name, files);
+            let package = Options::default().dont_parse_resources().open(&path);
+            std::fs::remove_file(&path).unwrap();
+            package.unwrap().code_fingerprint().unwrap()
+        };
+
+        let original = fingerprint(
+            "fingerprint-original",
+            &[
+                ("AndroidManifest.xml", &manifest),
+                ("classes.dex", &class_dex("LFoo;", 1)),
+                ("META-INF/CERT.RSA", b"first signature"),
+            ],
+        );
+        let resigned = fingerprint(
+            "fingerprint-resigned",
+            &[
+                ("META-INF/OTHER.RSA", b"second signature"),
+                ("res/raw/readme.txt", b"new resource"),
+                ("classes.dex", &class_dex("LFoo;", 2)),
+                ("AndroidManifest.xml", &manifest),
+            ],
+        );
+        let modified = fingerprint(
+            "fingerprint-modified",
+            &[
+                ("AndroidManifest.xml", &manifest),
+                ("classes.dex", &class_dex("LBar;", 1)),
+            ],
+        );
+        assert_eq!(original, resigned);
+        assert_ne!(original, modified);
+    }
+
+    /// Opens a package targeting `target_sdk`, with an optional
+    /// `usesCleartextTraffic` flag and network security config, and
+    /// returns its cleartext traffic policy.
+    fn cleartext_policy(
+        name: &str,
+        target_sdk: u32,
+        uses_cleartext_traffic: Option<bool>,
+        nsc: Option<nsc::NetworkSecurityConfigBuilder>,
+    ) -> CleartextPolicy {
+        let strings = [
+            "manifest",
+            "uses-sdk",
+            "targetSdkVersion",
+            "application",
+            "usesCleartextTraffic",
+        ];
+        let flag = uses_cleartext_traffic.map(|b| (4, 0x12, u32::from(b) * 0xffff_ffff));
+        let xml = xml(
+            &strings,
+            &[
+                Node::Start(0, &[]),
+                Node::Start(1, &[(2, 0x10, target_sdk)]),
+                Node::End(1),
+                Node::Start(3, flag.as_slice()),
+                Node::End(3),
+                Node::End(0),
+            ],
+        );
+        let nsc = nsc.map(|nsc| nsc::write(&nsc.build().unwrap()).unwrap());
+        let mut files: Vec<(&str, &[u8])> = vec![("AndroidManifest.xml", &xml)];
+        if let Some(nsc) = &nsc {
+            files.push(("res/xml/network_security_config.xml", nsc));
+        }
+        let path = write_zip(name, &files);
+        let package = Options::manifest_only().open(&path);
+        std::fs::remove_file(&path).unwrap();
+
+        let mut package = package.unwrap();
+        if nsc.is_some() {
+            package
+                .set_nsc_path(PathBuf::from("res/xml/network_security_config.xml"))
+                .unwrap();
+        }
+        package.cleartext_policy().unwrap()
+    }
+
+    #[test]
+    fn cleartext_policy_manifest_flag() {
+        let policy = cleartext_policy("cleartext-manifest", 23, Some(false), None);
+        assert!(!policy.permitted);
+        assert_eq!(policy.source, CleartextSource::Manifest);
+        assert!(!policy.any_permitted());
+
+        // the manifest flag is ignored when a network security config exists
+        let nsc = nsc::NetworkSecurityConfigBuilder::new().with_cleartext_traffic_permitted(true);
+        let policy = cleartext_policy("cleartext-manifest-nsc", 23, Some(false), Some(nsc));
+        assert!(policy.permitted);
+        assert_eq!(policy.source, CleartextSource::NetworkSecurityConfig);
+
+        let nsc = nsc::NetworkSecurityConfigBuilder::new();
+        let policy = cleartext_policy("cleartext-manifest-sdk", 23, Some(false), Some(nsc));
+        assert!(policy.permitted);
+        assert_eq!(policy.source, CleartextSource::SdkDefault);
+    }
+
+    #[test]
+    fn cleartext_policy_nsc_override() {
+        let nsc = nsc::NetworkSecurityConfigBuilder::new()
+            .with_cleartext_traffic_permitted(true)
+            .with_domain_config(
+                nsc::DomainConfig::new("example.com", true)
+                    .with_domain("example.org", false)
+                    .with_cleartext_traffic_permitted(false),
+            )
+            .with_domain_config(nsc::DomainConfig::new("example.net", false));
+        let policy = cleartext_policy("cleartext-nsc", 30, None, Some(nsc));
+        assert!(policy.permitted);
+        assert_eq!(policy.source, CleartextSource::NetworkSecurityConfig);
+        assert_eq!(policy.target_sdk, 30);
+        let denied = |domain: &str, include_subdomains| nsc::DomainCleartextPolicy {
+            domain: domain.to_string(),
+            include_subdomains,
+            permitted: false,
+        };
+        assert_eq!(
+            policy.domains,
+            vec![denied("example.com", true), denied("example.org", false)]
+        );
+    }
+
+    #[test]
+    fn cleartext_policy_sdk_default() {
+        let policy = cleartext_policy("cleartext-sdk27", 27, None, None);
+        assert!(policy.permitted);
+        assert_eq!(policy.source, CleartextSource::SdkDefault);
+        assert!(policy.domains.is_empty());
+
+        let policy = cleartext_policy("cleartext-sdk28", 28, None, None);
+        assert!(!policy.permitted);
+        assert_eq!(policy.source, CleartextSource::SdkDefault);
+    }
+}
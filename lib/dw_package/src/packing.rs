@@ -0,0 +1,272 @@
+//! Heuristics spotting packed applications, whose actual code is shipped
+//! encrypted in assets and loaded at runtime by a small stub.
+
+use crate::errors::PackageResult;
+use dw_dex::instrs::Instr;
+use dw_dex::{Dex, DexIndex, PrettyPrinter};
+use serde::Serialize;
+use std::collections::BTreeSet;
+use std::io::{self, Read};
+use std::path::PathBuf;
+
+/// Classes loading dex files that are not part of the package.
+pub const LOADER_CLASSES: &[&str] = &[
+    "Ldalvik/system/BaseDexClassLoader;",
+    "Ldalvik/system/DexClassLoader;",
+    "Ldalvik/system/InMemoryDexClassLoader;",
+    "Ldalvik/system/PathClassLoader;",
+];
+
+/// Methods, given as (class, name), loading dex files that are not part of
+/// the package, in classes that otherwise do not.
+pub const LOADER_METHODS: &[(&str, &str)] = &[("Ldalvik/system/DexFile;", "loadDex")];
+
+/// Trailing labels of file names whose formats are compressed, and thus
+/// have a high entropy without being encrypted. Zip archives (such as `jar`
+/// and `apk` files) are rather recognized by their magic, since packers
+/// often give such names to encrypted payloads.
+const COMPRESSED_EXTENSIONS: &[&str] = &[
+    "7z", "br", "gz", "jpeg", "jpg", "mp3", "mp4", "ogg", "png", "webm", "webp", "woff2", "xz",
+];
+
+const DEX_MAGIC: &[u8] = b"dex\n";
+
+const ZIP_MAGIC: &[u8] = b"PK\x03\x04";
+
+/// Entropy, in bits per byte, above which an asset is considered
+/// encrypted.
+pub const ENTROPY_THRESHOLD: f64 = 7.5;
+
+/// Assets smaller than this size (in bytes) are not checked for entropy,
+/// as their estimate is not reliable.
+const ENTROPY_MIN_SIZE: u64 = 4096;
+
+/// The primary dex file is suspiciously small when the assets are more than
+/// this many times larger.
+pub(crate) const ASSETS_TO_DEX_RATIO: u64 = 4;
+
+/// A hint that a package is packed.
+#[derive(Debug, Clone, PartialEq, Serialize)]
+#[serde(tag = "kind", rename_all = "kebab-case")]
+pub enum Evidence {
+    /// An asset that is not a dex file has a very high entropy.
+    HighEntropyAsset { path: PathBuf, entropy: f64 },
+    /// A method (in `Lcom/Foo;->bar()V` form) invokes a method of one of
+    /// the [`LOADER_CLASSES`], or one of the [`LOADER_METHODS`] (then given
+    /// as `loader` in `Lcom/Foo;->bar` form).
+    DexLoader { loader: String, method: String },
+    /// The primary dex file is small compared to the assets.
+    SmallPrimaryDex { dex_size: u64, assets_size: u64 },
+}
+
+impl Evidence {
+    /// The contribution of each kind of evidence to the confidence score,
+    /// counted once whatever the number of pieces of this kind.
+    const fn weight(&self) -> f64 {
+        match self {
+            Self::HighEntropyAsset { .. } | Self::DexLoader { .. } => 0.4,
+            Self::SmallPrimaryDex { .. } => 0.2,
+        }
+    }
+
+    const fn kind(&self) -> u8 {
+        match self {
+            Self::HighEntropyAsset { .. } => 0,
+            Self::DexLoader { .. } => 1,
+            Self::SmallPrimaryDex { .. } => 2,
+        }
+    }
+}
+
+/// The result of the packing heuristics run on a package.
+#[derive(Debug, Clone, Default, PartialEq, Serialize)]
+pub struct PackingReport {
+    /// The confidence that the package is packed, from 0 (no evidence) to 1
+    /// (all kinds of evidence).
+    pub confidence: f64,
+    pub evidence: Vec<Evidence>,
+}
+
+impl PackingReport {
+    pub(crate) fn new(evidence: Vec<Evidence>) -> Self {
+        let mut kinds = BTreeSet::new();
+        let confidence = evidence
+            .iter()
+            .filter(|evidence| kinds.insert(evidence.kind()))
+            .map(Evidence::weight)
+            .sum::<f64>()
+            .min(1.0);
+        Self {
+            confidence,
+            evidence,
+        }
+    }
+
+    /// Returns whether the package is likely packed, that is whether at
+    /// least two kinds of evidence have been found.
+    #[must_use]
+    pub fn is_packed(&self) -> bool {
+        self.confidence >= 0.6
+    }
+}
+
+/// What an asset turns out to be once its first bytes are read.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub(crate) enum ScannedAsset {
+    /// A dex file, which is not counted as an asset.
+    Dex,
+    /// Any other file, with its entropy if it has been checked.
+    Other(Option<f64>),
+}
+
+/// Reads an asset, given its name, size and content, to tell whether it is
+/// a dex file and to check its entropy.
+///
+/// Only the first bytes of small assets, of compressed formats or of zip
+/// archives are read. The content of other ones is read by chunks, and is
+/// not kept.
+pub(crate) fn scan_asset(
+    filename: &str,
+    size: u64,
+    mut content: impl Read,
+) -> io::Result<ScannedAsset> {
+    let mut magic = Vec::with_capacity(4);
+    content.by_ref().take(4).read_to_end(&mut magic)?;
+    if magic == DEX_MAGIC {
+        return Ok(ScannedAsset::Dex);
+    }
+    let extension = filename
+        .rsplit_once('.')
+        .map(|(_, ext)| ext.to_ascii_lowercase());
+    if size < ENTROPY_MIN_SIZE
+        || extension.is_some_and(|ext| COMPRESSED_EXTENSIONS.contains(&ext.as_str()))
+        || magic == ZIP_MAGIC
+    {
+        return Ok(ScannedAsset::Other(None));
+    }
+    let entropy = entropy(magic.as_slice().chain(content))?;
+    Ok(ScannedAsset::Other(Some(entropy)))
+}
+
+/// Computes the Shannon entropy of `content`, in bits per byte.
+pub(crate) fn entropy(mut content: impl Read) -> io::Result<f64> {
+    let mut counts = [0u64; 256];
+    let mut buffer = [0; 0x4000];
+    loop {
+        let read = match content.read(&mut buffer) {
+            Ok(0) => break,
+            Ok(read) => read,
+            Err(err) if err.kind() == io::ErrorKind::Interrupted => continue,
+            Err(err) => return Err(err),
+        };
+        for byte in &buffer[..read] {
+            counts[usize::from(*byte)] += 1;
+        }
+    }
+    let len = counts.iter().sum::<u64>() as f64;
+    Ok(counts
+        .iter()
+        .filter(|count| **count != 0)
+        .map(|count| {
+            let p = *count as f64 / len;
+            -p * p.log2()
+        })
+        .sum())
+}
+
+/// Lists the methods of a dex file that invoke methods of the
+/// [`LOADER_CLASSES`], or the [`LOADER_METHODS`].
+pub(crate) fn loader_calls(dex: &Dex) -> PackageResult<Vec<Evidence>> {
+    let mut calls = BTreeSet::new();
+    for class in dex.iter_class_defs() {
+        let Some(data) = class.data(dex)? else {
+            continue;
+        };
+        for method in data.iter_methods() {
+            let Some(code) = method.code(dex)? else {
+                continue;
+            };
+            for instr in code.read().unwrap().iter_instructions() {
+                let (Instr::InvokeVirtual(_, mid)
+                | Instr::InvokeSuper(_, mid)
+                | Instr::InvokeDirect(_, mid)
+                | Instr::InvokeStatic(_, mid)
+                | Instr::InvokeInterface(_, mid)
+                | Instr::InvokeVirtualRange(_, mid)
+                | Instr::InvokeSuperRange(_, mid)
+                | Instr::InvokeDirectRange(_, mid)
+                | Instr::InvokeStaticRange(_, mid)
+                | Instr::InvokeInterfaceRange(_, mid)) = instr.instr()
+                else {
+                    continue;
+                };
+                let mid = mid.get(dex)?;
+                let class = mid.definer(dex)?.to_string();
+                let loader = if LOADER_CLASSES.contains(&class.as_str()) {
+                    class
+                } else {
+                    let name = mid.name(dex)?;
+                    if !LOADER_METHODS.contains(&(class.as_str(), name.as_str())) {
+                        continue;
+                    }
+                    format!("{class}->{name}")
+                };
+                let name = PrettyPrinter(method.descriptor(dex)?, dex).to_string();
+                calls.insert((loader, name));
+            }
+        }
+    }
+    Ok(calls
+        .into_iter()
+        .map(|(loader, method)| Evidence::DexLoader { loader, method })
+        .collect())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn entropy_bounds() {
+        assert_eq!(entropy(&[0x42; 1024][..]).unwrap(), 0.0);
+        let all: Vec<u8> = (0..=255).cycle().take(0x10000).collect();
+        assert!((entropy(all.as_slice()).unwrap() - 8.0).abs() < 1e-9);
+    }
+
+    #[test]
+    fn scanned_assets() {
+        let all: Vec<u8> = (0..=255).cycle().take(4096).collect();
+        let scan =
+            |name: &str, content: &[u8]| scan_asset(name, content.len() as u64, content).unwrap();
+        assert_eq!(
+            scan("assets/data.bin", &all[..1024]),
+            ScannedAsset::Other(None)
+        );
+        assert_eq!(scan("assets/logo.PNG", &all), ScannedAsset::Other(None));
+        assert_eq!(
+            scan("assets/data.bin", &all),
+            ScannedAsset::Other(Some(8.0))
+        );
+        assert_eq!(
+            scan("assets/data.jar", &all),
+            ScannedAsset::Other(Some(8.0))
+        );
+
+        let archive = [ZIP_MAGIC, &all].concat();
+        assert_eq!(scan("assets/data.jar", &archive), ScannedAsset::Other(None));
+        let dex = [DEX_MAGIC, &all].concat();
+        assert_eq!(scan("assets/data.jar", &dex), ScannedAsset::Dex);
+    }
+
+    #[test]
+    fn confidence_counts_kinds_once() {
+        let loader = |method: &str| Evidence::DexLoader {
+            loader: LOADER_CLASSES[0].to_string(),
+            method: method.to_string(),
+        };
+        let report = PackingReport::new(vec![loader("LA;->a()V"), loader("LB;->b()V")]);
+        assert!((report.confidence - 0.4).abs() < 1e-9);
+        assert!(!report.is_packed());
+        assert_eq!(PackingReport::new(Vec::new()).confidence, 0.0);
+    }
+}
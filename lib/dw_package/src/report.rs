@@ -0,0 +1,74 @@
+//! Security report gathering the findings of the various detectors.
+
+use serde::Serialize;
+
+/// The first target SDK version for which components with intent filters
+/// must declare whether they are exported (Android 12).
+pub const EXPLICIT_EXPORT_REQUIRED_SINCE_SDK: u32 = 31;
+
+/// The severity of a [`Finding`], from the least to the most critical.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Serialize)]
+#[serde(rename_all = "lowercase")]
+pub enum Severity {
+    Info,
+    Low,
+    Medium,
+    High,
+}
+
+/// The detectors that can contribute to a [`SecurityReport`].
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Serialize)]
+#[serde(rename_all = "kebab-case")]
+pub enum Check {
+    /// The application is debuggable.
+    Debuggable,
+    /// The application data can be backed up.
+    AllowBackup,
+    /// The application allows cleartext network traffic.
+    CleartextTraffic,
+    /// A component can be started by other applications.
+    ExportedComponent,
+    /// Certificate pinning material is embedded in the package.
+    PinningMaterial,
+    /// A `WebView` is insecurely configured (requires code analysis).
+    WebView,
+}
+
+/// A single issue reported by a detector.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize)]
+pub struct Finding {
+    pub check: Check,
+    pub severity: Severity,
+    pub message: String,
+}
+
+/// The consolidated result of all the detectors run on a package.
+#[derive(Debug, Clone, Default, PartialEq, Eq, Serialize)]
+pub struct SecurityReport {
+    /// The application package name, if a manifest has been found.
+    pub package: Option<String>,
+    /// Whether the code-based detectors have been run.
+    pub code_analyzed: bool,
+    pub findings: Vec<Finding>,
+}
+
+impl SecurityReport {
+    /// Adds a finding to the report.
+    pub fn push(&mut self, check: Check, severity: Severity, message: String) {
+        self.findings.push(Finding {
+            check,
+            severity,
+            message,
+        });
+    }
+
+    /// Sorts findings by decreasing severity, then by detector.
+    pub fn sort(&mut self) {
+        self.findings.sort_by(|a, b| {
+            b.severity
+                .cmp(&a.severity)
+                .then(a.check.cmp(&b.check))
+                .then(a.message.cmp(&b.message))
+        });
+    }
+}
@@ -0,0 +1,572 @@
+//! APK Signature Scheme v2 and v3 verification.
+//!
+//! Both schemes store their signatures in the APK Signing Block, which is
+//! located right before the zip central directory. Signers sign a digest of
+//! the whole file except the signing block itself.
+
+use crate::errors::{PackageError, PackageResult};
+use p256::ecdsa;
+use p256::ecdsa::signature::{hazmat::PrehashVerifier, Verifier};
+use p256::pkcs8::DecodePublicKey;
+use rayon::prelude::*;
+use rsa::{pkcs1v15, pss, RsaPublicKey};
+use sha2::{Digest, Sha256, Sha512};
+use x509_cert::der::{Decode, Encode};
+use x509_cert::Certificate;
+use zip::result::ZipError;
+
+const EOCD_MAGIC: u32 = 0x0605_4b50;
+const EOCD_SIZE: usize = 22;
+const SIGNING_BLOCK_MAGIC: &[u8; 16] = b"APK Sig Block 42";
+const V2_BLOCK_ID: u32 = 0x7109_871a;
+const V3_BLOCK_ID: u32 = 0xf053_68c0;
+const CHUNK_SIZE: usize = 1024 * 1024;
+
+/// The most recent signature scheme found in a package.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum SignatureScheme {
+    /// Only a JAR signature (`META-INF/*.SF`) is present, it is not verified.
+    V1Only,
+    /// APK Signature Scheme v2.
+    V2,
+    /// APK Signature Scheme v3.
+    V3,
+}
+
+/// The outcome of a package signature verification.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct SignatureInfo {
+    pub scheme: SignatureScheme,
+    pub signers: Vec<Signer>,
+    /// Set when there is at least one signer and all of them verified.
+    pub verified: bool,
+}
+
+/// A signer of an APK Signature Scheme v2 or v3 block.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Signer {
+    /// DER encoded X.509 certificates, the first one holding the signer key.
+    pub certificates: Vec<Vec<u8>>,
+    /// Minimum and maximum platform versions the signer applies to (v3 only).
+    pub sdk_range: Option<(u32, u32)>,
+    /// The reason why the signer failed to verify, if it did.
+    pub error: Option<String>,
+}
+
+/// Verifies the signing block of the given raw package, preferring the v3
+/// scheme over the v2 one when both are present.
+pub(crate) fn verify(apk: &[u8], jar_signed: bool) -> PackageResult<SignatureInfo> {
+    let eocd_offset = find_eocd(apk)?;
+    let cd_offset = Reader(&apk[eocd_offset + 16..]).u32()? as usize;
+    if cd_offset > eocd_offset {
+        return Err(PackageError::Zip(ZipError::InvalidArchive(
+            "invalid central directory offset",
+        )));
+    }
+
+    let scheme_block = match signing_block(apk, cd_offset)? {
+        Some((block_offset, block)) => {
+            find_scheme_block(block)?.map(|(scheme, block)| (scheme, block_offset, block))
+        }
+        None => None,
+    };
+    let Some((scheme, block_offset, block)) = scheme_block else {
+        if jar_signed {
+            return Ok(SignatureInfo {
+                scheme: SignatureScheme::V1Only,
+                signers: Vec::new(),
+                verified: false,
+            });
+        }
+        return Err(PackageError::SignatureNotFound);
+    };
+
+    // the signed EOCD points to the signing block instead of the central directory
+    let mut eocd = apk[eocd_offset..].to_vec();
+    eocd[16..20].copy_from_slice(&(block_offset as u32).to_le_bytes());
+    let sections = [
+        &apk[..block_offset],
+        &apk[cd_offset..eocd_offset],
+        eocd.as_slice(),
+    ];
+
+    let mut signers = Vec::new();
+    for signer in Reader(block).sequence()? {
+        signers.push(parse_signer(signer, scheme, &sections)?);
+    }
+    let verified = !signers.is_empty() && signers.iter().all(|s| s.error.is_none());
+    Ok(SignatureInfo {
+        scheme,
+        signers,
+        verified,
+    })
+}
+
+fn find_eocd(apk: &[u8]) -> PackageResult<usize> {
+    let max_comment =
+        apk.len()
+            .checked_sub(EOCD_SIZE)
+            .ok_or(PackageError::Zip(ZipError::InvalidArchive(
+                "could not find end of central directory",
+            )))?;
+    for comment_len in 0..=max_comment.min(usize::from(u16::MAX)) {
+        let offset = apk.len() - EOCD_SIZE - comment_len;
+        let mut reader = Reader(&apk[offset..]);
+        if reader.u32()? == EOCD_MAGIC
+            && usize::from(u16::from_le_bytes([apk[offset + 20], apk[offset + 21]])) == comment_len
+        {
+            return Ok(offset);
+        }
+    }
+    Err(PackageError::Zip(ZipError::InvalidArchive(
+        "could not find end of central directory",
+    )))
+}
+
+/// Returns the offset of the APK Signing Block along with its id-value
+/// pairs, if the package has such a block.
+fn signing_block(apk: &[u8], cd_offset: usize) -> PackageResult<Option<(usize, &[u8])>> {
+    let magic = cd_offset
+        .checked_sub(16)
+        .and_then(|start| apk.get(start..cd_offset));
+    if cd_offset < 32 || magic != Some(SIGNING_BLOCK_MAGIC) {
+        return Ok(None);
+    }
+    let invalid_size = PackageError::InvalidSigningBlock("invalid block size");
+    let size = Reader(&apk[cd_offset - 24..]).u64()?;
+    // the size counts the pairs, the second size field and the magic
+    if size < 24 {
+        return Err(invalid_size);
+    }
+    let offset = usize::try_from(size)
+        .ok()
+        .and_then(|size| size.checked_add(8))
+        .and_then(|size| cd_offset.checked_sub(size))
+        .ok_or(invalid_size)?;
+    let first_size = apk
+        .get(offset..)
+        .ok_or(PackageError::InvalidSigningBlock("invalid block offset"))?;
+    if Reader(first_size).u64()? != size {
+        return Err(PackageError::InvalidSigningBlock("mismatching block sizes"));
+    }
+    let pairs = apk
+        .get(offset + 8..cd_offset - 24)
+        .ok_or(PackageError::InvalidSigningBlock("invalid block offset"))?;
+    Ok(Some((offset, pairs)))
+}
+
+fn find_scheme_block(pairs: &[u8]) -> PackageResult<Option<(SignatureScheme, &[u8])>> {
+    let mut reader = Reader(pairs);
+    let mut v2 = None;
+    let mut v3 = None;
+    while !reader.is_empty() {
+        let len = usize::try_from(reader.u64()?)
+            .map_err(|_| PackageError::InvalidSigningBlock("invalid pair size"))?;
+        let mut pair = Reader(reader.bytes(len)?);
+        match pair.u32()? {
+            V2_BLOCK_ID => v2 = Some(pair.0),
+            V3_BLOCK_ID => v3 = Some(pair.0),
+            _ => (),
+        }
+    }
+    Ok(v3
+        .map(|block| (SignatureScheme::V3, block))
+        .or(v2.map(|block| (SignatureScheme::V2, block))))
+}
+
+fn parse_signer(
+    signer: &[u8],
+    scheme: SignatureScheme,
+    sections: &[&[u8]],
+) -> PackageResult<Signer> {
+    let mut reader = Reader(signer);
+    let signed_data = reader.prefixed()?;
+    let sdk_range = match scheme {
+        SignatureScheme::V3 => Some((reader.u32()?, reader.u32()?)),
+        _ => None,
+    };
+    let signatures = reader.sequence()?;
+    let public_key = reader.prefixed()?;
+
+    let mut data = Reader(signed_data);
+    let digests = data.sequence()?;
+    let certificates = data.sequence()?;
+
+    let signatures = algorithm_values(&signatures)?;
+    let digests = algorithm_values(&digests)?;
+
+    let error = verify_signer(
+        signed_data,
+        &signatures,
+        &digests,
+        certificates.first().copied(),
+        public_key,
+        sections,
+    )
+    .err();
+    Ok(Signer {
+        certificates: certificates.into_iter().map(<[u8]>::to_vec).collect(),
+        sdk_range,
+        error,
+    })
+}
+
+/// Splits signatures or digests into their algorithm id and value.
+fn algorithm_values<'a>(items: &[&'a [u8]]) -> PackageResult<Vec<(u32, &'a [u8])>> {
+    items
+        .iter()
+        .map(|item| {
+            let mut reader = Reader(item);
+            Ok((reader.u32()?, reader.prefixed()?))
+        })
+        .collect()
+}
+
+fn verify_signer(
+    signed_data: &[u8],
+    signatures: &[(u32, &[u8])],
+    digests: &[(u32, &[u8])],
+    certificate: Option<&[u8]>,
+    public_key: &[u8],
+    sections: &[&[u8]],
+) -> Result<(), String> {
+    let (algorithm, signature, content_digest) = signatures
+        .iter()
+        .filter_map(|(algorithm, signature)| {
+            ContentDigest::of(*algorithm).map(|digest| (*algorithm, *signature, digest))
+        })
+        .max_by_key(|(_, _, digest)| *digest)
+        .ok_or("no supported signature algorithm")?;
+    verify_signed_data(algorithm, public_key, signed_data, signature)?;
+
+    let mut signed_algorithms: Vec<u32> = digests.iter().map(|(algorithm, _)| *algorithm).collect();
+    let mut signature_algorithms: Vec<u32> =
+        signatures.iter().map(|(algorithm, _)| *algorithm).collect();
+    signed_algorithms.sort_unstable();
+    signature_algorithms.sort_unstable();
+    if signed_algorithms != signature_algorithms {
+        return Err("signature and digest algorithms mismatch".to_string());
+    }
+
+    let certificate = Certificate::from_der(certificate.ok_or("no certificate")?)
+        .map_err(|e| format!("invalid certificate: {e}"))?;
+    let certificate_key = certificate
+        .tbs_certificate
+        .subject_public_key_info
+        .to_der()
+        .map_err(|e| format!("invalid certificate: {e}"))?;
+    if certificate_key != public_key {
+        return Err("certificate does not match public key".to_string());
+    }
+
+    let expected = digests
+        .iter()
+        .find(|(digest_algorithm, _)| *digest_algorithm == algorithm)
+        .map(|(_, digest)| *digest)
+        .ok_or("missing content digest")?;
+    if content_digest.compute(sections) != expected {
+        return Err("content digest mismatch".to_string());
+    }
+    Ok(())
+}
+
+fn verify_signed_data(
+    algorithm: u32,
+    public_key: &[u8],
+    data: &[u8],
+    signature: &[u8],
+) -> Result<(), String> {
+    let rsa_key = || RsaPublicKey::from_public_key_der(public_key).map_err(|e| e.to_string());
+    let ec_key = || ecdsa::VerifyingKey::from_public_key_der(public_key).map_err(|e| e.to_string());
+    let result = match algorithm {
+        0x0101 => pss::VerifyingKey::<Sha256>::new(rsa_key()?).verify(
+            data,
+            &pss::Signature::try_from(signature).map_err(|e| e.to_string())?,
+        ),
+        0x0102 => pss::VerifyingKey::<Sha512>::new(rsa_key()?).verify(
+            data,
+            &pss::Signature::try_from(signature).map_err(|e| e.to_string())?,
+        ),
+        0x0103 => pkcs1v15::VerifyingKey::<Sha256>::new(rsa_key()?).verify(
+            data,
+            &pkcs1v15::Signature::try_from(signature).map_err(|e| e.to_string())?,
+        ),
+        0x0104 => pkcs1v15::VerifyingKey::<Sha512>::new(rsa_key()?).verify(
+            data,
+            &pkcs1v15::Signature::try_from(signature).map_err(|e| e.to_string())?,
+        ),
+        0x0201 => ec_key()?.verify(
+            data,
+            &ecdsa::Signature::from_der(signature).map_err(|e| e.to_string())?,
+        ),
+        0x0202 => ec_key()?.verify_prehash(
+            &Sha512::digest(data),
+            &ecdsa::Signature::from_der(signature).map_err(|e| e.to_string())?,
+        ),
+        _ => unreachable!(),
+    };
+    result.map_err(|e| format!("invalid signature: {e}"))
+}
+
+/// Content digests of the supported signature algorithms, from the weakest
+/// to the strongest.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord)]
+enum ContentDigest {
+    Sha256,
+    Sha512,
+}
+
+impl ContentDigest {
+    const fn of(algorithm: u32) -> Option<Self> {
+        match algorithm {
+            0x0101 | 0x0103 | 0x0201 => Some(Self::Sha256),
+            0x0102 | 0x0104 | 0x0202 => Some(Self::Sha512),
+            _ => None,
+        }
+    }
+
+    fn compute(self, sections: &[&[u8]]) -> Vec<u8> {
+        match self {
+            Self::Sha256 => chunked_digest::<Sha256>(sections),
+            Self::Sha512 => chunked_digest::<Sha512>(sections),
+        }
+    }
+}
+
+/// Sections are split into 1 MiB chunks, which are hashed independently
+/// before hashing the concatenation of their digests.
+fn chunked_digest<D: Digest>(sections: &[&[u8]]) -> Vec<u8> {
+    let chunks: Vec<&[u8]> = sections
+        .iter()
+        .flat_map(|section| section.chunks(CHUNK_SIZE))
+        .collect();
+    let chunk_digests: Vec<Vec<u8>> = chunks
+        .par_iter()
+        .map(|chunk| {
+            let mut hasher = D::new();
+            hasher.update([0xa5]);
+            hasher.update((chunk.len() as u32).to_le_bytes());
+            hasher.update(chunk);
+            hasher.finalize().to_vec()
+        })
+        .collect();
+
+    let mut hasher = D::new();
+    hasher.update([0x5a]);
+    hasher.update((chunk_digests.len() as u32).to_le_bytes());
+    for digest in chunk_digests {
+        hasher.update(digest);
+    }
+    hasher.finalize().to_vec()
+}
+
+/// A cursor over the little-endian, length-prefixed structures of the
+/// signing block.
+struct Reader<'a>(&'a [u8]);
+
+impl<'a> Reader<'a> {
+    const fn is_empty(&self) -> bool {
+        self.0.is_empty()
+    }
+
+    fn bytes(&mut self, len: usize) -> PackageResult<&'a [u8]> {
+        if len > self.0.len() {
+            return Err(PackageError::InvalidSigningBlock("truncated structure"));
+        }
+        let (bytes, rest) = self.0.split_at(len);
+        self.0 = rest;
+        Ok(bytes)
+    }
+
+    fn u32(&mut self) -> PackageResult<u32> {
+        Ok(u32::from_le_bytes(self.bytes(4)?.try_into().unwrap()))
+    }
+
+    fn u64(&mut self) -> PackageResult<u64> {
+        Ok(u64::from_le_bytes(self.bytes(8)?.try_into().unwrap()))
+    }
+
+    fn prefixed(&mut self) -> PackageResult<&'a [u8]> {
+        let len = self.u32()? as usize;
+        self.bytes(len)
+    }
+
+    fn sequence(&mut self) -> PackageResult<Vec<&'a [u8]>> {
+        let mut items = Reader(self.prefixed()?);
+        let mut sequence = Vec::new();
+        while !items.is_empty() {
+            sequence.push(items.prefixed()?);
+        }
+        Ok(sequence)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::Options;
+    use p256::ecdsa::{signature::Signer as _, SigningKey};
+    use p256::pkcs8::EncodePublicKey;
+    use std::io::{Cursor, Write};
+    use zip::{write::FileOptions, ZipWriter};
+
+    fn prefixed(data: &[u8]) -> Vec<u8> {
+        let mut buffer = (data.len() as u32).to_le_bytes().to_vec();
+        buffer.extend_from_slice(data);
+        buffer
+    }
+
+    fn der(tag: u8, content: &[u8]) -> Vec<u8> {
+        let mut buffer = vec![tag];
+        match content.len() {
+            len if len < 0x80 => buffer.push(len as u8),
+            len if len < 0x100 => buffer.extend([0x81, len as u8]),
+            len => buffer.extend([0x82, (len >> 8) as u8, len as u8]),
+        }
+        buffer.extend_from_slice(content);
+        buffer
+    }
+
+    /// Assembles a minimal certificate for the given public key, with empty
+    /// names and signature, which are not checked by verification.
+    fn certificate(public_key: &[u8]) -> Vec<u8> {
+        let ecdsa_with_sha256 = der(
+            0x30,
+            &der(0x06, &[0x2a, 0x86, 0x48, 0xce, 0x3d, 0x04, 0x03, 0x02]),
+        );
+        let time = der(0x17, b"250101000000Z");
+        let tbs = der(
+            0x30,
+            &[
+                der(0xa0, &der(0x02, &[2])),
+                der(0x02, &[1]),
+                ecdsa_with_sha256.clone(),
+                der(0x30, &[]),
+                der(0x30, &[time.clone(), time].concat()),
+                der(0x30, &[]),
+                public_key.to_vec(),
+            ]
+            .concat(),
+        );
+        der(0x30, &[tbs, ecdsa_with_sha256, der(0x03, &[0])].concat())
+    }
+
+    /// Writes a zip file with the given files and inserts an APK Signature
+    /// Scheme v2 block signed with an ECDSA P-256 key.
+    fn signed_apk(files: &[(&str, &[u8])]) -> Vec<u8> {
+        let mut zip = ZipWriter::new(Cursor::new(Vec::new()));
+        for (filename, content) in files {
+            zip.start_file(*filename, FileOptions::default()).unwrap();
+            zip.write_all(content).unwrap();
+        }
+        let apk = zip.finish().unwrap().into_inner();
+        let eocd_offset = find_eocd(&apk).unwrap();
+        let cd_offset = Reader(&apk[eocd_offset + 16..]).u32().unwrap() as usize;
+
+        // the block is inserted at the central directory offset, so the
+        // signed sections are those of the unsigned file
+        let digest = chunked_digest::<Sha256>(&[
+            &apk[..cd_offset],
+            &apk[cd_offset..eocd_offset],
+            &apk[eocd_offset..],
+        ]);
+        let key = SigningKey::from_bytes(&[0x42; 32].into()).unwrap();
+        let public_key = key.verifying_key().to_public_key_der().unwrap();
+        let public_key = public_key.as_bytes();
+
+        let digest = [0x0201u32.to_le_bytes().to_vec(), prefixed(&digest)].concat();
+        let signed_data = [
+            prefixed(&prefixed(&digest)),
+            prefixed(&prefixed(&certificate(public_key))),
+            prefixed(&[]),
+        ]
+        .concat();
+        let signature: ecdsa::Signature = key.sign(&signed_data);
+        let signature = [
+            0x0201u32.to_le_bytes().to_vec(),
+            prefixed(signature.to_der().as_bytes()),
+        ]
+        .concat();
+        let signer = [
+            prefixed(&signed_data),
+            prefixed(&prefixed(&signature)),
+            prefixed(public_key),
+        ]
+        .concat();
+        let pair = [
+            V2_BLOCK_ID.to_le_bytes().to_vec(),
+            prefixed(&prefixed(&signer)),
+        ]
+        .concat();
+
+        let size = (8 + pair.len() + 8 + 16) as u64;
+        let block = [
+            size.to_le_bytes().to_vec(),
+            (pair.len() as u64).to_le_bytes().to_vec(),
+            pair,
+            size.to_le_bytes().to_vec(),
+            SIGNING_BLOCK_MAGIC.to_vec(),
+        ]
+        .concat();
+
+        let mut signed = [&apk[..cd_offset], &block, &apk[cd_offset..]].concat();
+        let new_cd_offset = (cd_offset + block.len()) as u32;
+        let new_eocd_offset = eocd_offset + block.len();
+        signed[new_eocd_offset + 16..new_eocd_offset + 20]
+            .copy_from_slice(&new_cd_offset.to_le_bytes());
+        signed
+    }
+
+    fn verify_file(name: &str, apk: &[u8]) -> PackageResult<SignatureInfo> {
+        let path = std::env::temp_dir().join(format!("{}-{name}.apk", std::process::id()));
+        std::fs::write(&path, apk).unwrap();
+        let info = Options::manifest_only()
+            .open(&path)
+            .and_then(|package| package.verify_signature());
+        std::fs::remove_file(&path).unwrap();
+        info
+    }
+
+    #[test]
+    fn v2_signature_valid() {
+        let apk = signed_apk(&[("res/raw/readme.txt", b"nothing to see here")]);
+        let info = verify_file("signature-valid", &apk).unwrap();
+        assert_eq!(info.scheme, SignatureScheme::V2);
+        assert_eq!(info.signers.len(), 1);
+        assert_eq!(info.signers[0].error, None);
+        assert_eq!(info.signers[0].certificates.len(), 1);
+        assert!(info.verified);
+    }
+
+    #[test]
+    fn signing_block_bounds() {
+        // the block sizes cannot even hold the magic
+        let apk = [
+            &[0; 8][..],
+            &16u64.to_le_bytes(),
+            &16u64.to_le_bytes(),
+            SIGNING_BLOCK_MAGIC,
+        ]
+        .concat();
+        assert!(matches!(
+            signing_block(&apk, apk.len()),
+            Err(PackageError::InvalidSigningBlock("invalid block size"))
+        ));
+        assert!(matches!(signing_block(&apk, apk.len() + 8), Ok(None)));
+    }
+
+    #[test]
+    fn v2_signature_tampered() {
+        let mut apk = signed_apk(&[("res/raw/readme.txt", b"nothing to see here")]);
+        // the modification time of the first local file header, which is
+        // not checked when extracting files
+        apk[10] ^= 0xff;
+        let info = verify_file("signature-tampered", &apk).unwrap();
+        assert_eq!(info.scheme, SignatureScheme::V2);
+        assert_eq!(
+            info.signers[0].error.as_deref(),
+            Some("content digest mismatch")
+        );
+        assert!(!info.verified);
+    }
+}
@@ -0,0 +1,728 @@
+//! Extraction of the Dex files embedded in the `vdex` and `odex` (oat) files
+//! that `dex2oat` generates on devices.
+//!
+//! Vdex files of Android 9 to 11 (versions `019` and `021`) store Dex files
+//! that may have been quickened, that is, where some instructions have been
+//! replaced by faster runtime-specific ones. The quickening information kept
+//! in the vdex file is used to restore the original instructions. Vdex files
+//! of Android 12 and later (version `027`) are not quickened anymore.
+//!
+//! Embedded Dex files may be compact Dex files, whose data section can be
+//! shared by all the Dex files of a vdex file: they are extracted along with
+//! the data they reference.
+
+use crate::errors::{PackageError, PackageResult};
+use std::collections::BTreeSet;
+
+const VDEX_MAGIC: &[u8] = b"vdex";
+const OAT_MAGIC: &[u8] = b"oat\n";
+const ELF_MAGIC: &[u8] = b"\x7fELF";
+const DEX_MAGIC: &[u8] = b"dex\n";
+const CDEX_MAGIC: &[u8] = b"cdex";
+
+/// Version of the dex section of `019` and `021` vdex files that embed Dex
+/// files,
+/// `000` being used when Dex files are kept in the APK.
+const DEX_SECTION_VERSION: &[u8] = b"002\0";
+/// Kind of the section holding Dex files in `027` vdex files.
+const DEX_FILE_SECTION: u32 = 1;
+const DEX_HEADER_SIZE: usize = 0x70;
+const CDEX_HEADER_SIZE: usize = 0x88;
+/// Flag of compact code items whose instructions count is extended in the
+/// preheader.
+const CDEX_PREHEADER_INSNS_SIZE: u16 = 0x10;
+const ENDIAN_CONSTANT: u32 = 0x1234_5678;
+/// Quickening information value of a `nop` that was not a `check-cast`.
+const NO_INDEX: u16 = 0xffff;
+
+/// Returns the Dex files embedded in the given vdex file, unquickened.
+///
+/// Vdex files whose Dex files are kept in the APK have no dex section, and
+/// give no Dex file.
+pub(crate) fn vdex_dexs(vdex: &[u8]) -> PackageResult<Vec<Vec<u8>>> {
+    if !vdex.starts_with(VDEX_MAGIC) {
+        return Err(PackageError::InvalidVdex("bad magic"));
+    }
+    let version = vdex
+        .get(4..8)
+        .ok_or(PackageError::InvalidVdex("truncated header"))?;
+    match version {
+        b"019\0" => vdex_dex_section_dexs(vdex, true),
+        b"021\0" => vdex_dex_section_dexs(vdex, false),
+        b"027\0" => vdex_027_dexs(vdex),
+        _ => Err(PackageError::UnsupportedVdexVersion(
+            String::from_utf8_lossy(version)
+                .trim_end_matches('\0')
+                .to_string(),
+        )),
+    }
+}
+
+/// Android 9 to 11 vdex layout: a header, the checksums of the Dex files,
+/// then a dex section made of a header, the Dex files each preceded by the
+/// offset of its quickening table, the data shared by compact Dex files and
+/// the quickening information.
+///
+/// The header of `019` vdex files lacks the sizes of the boot classpath
+/// checksums and of the class loader context, and their verifier
+/// dependencies lie between the shared data and the quickening information.
+fn vdex_dex_section_dexs(vdex: &[u8], version_019: bool) -> PackageResult<Vec<Vec<u8>>> {
+    let truncated = || PackageError::InvalidVdex("truncated file");
+    if vdex.get(8..12) != Some(DEX_SECTION_VERSION) {
+        return Ok(Vec::new());
+    }
+    let number_of_dex_files = u32_at(vdex, 12).ok_or_else(truncated)? as usize;
+    let verifier_deps_size = u32_at(vdex, 16).ok_or_else(truncated)? as usize;
+    let (header_size, deps_before_quickening) = if version_019 {
+        (20, verifier_deps_size)
+    } else {
+        (28, 0)
+    };
+    let section_header = number_of_dex_files
+        .checked_mul(4)
+        .and_then(|size| size.checked_add(header_size))
+        .ok_or_else(truncated)?;
+    let [dex_size, shared_data_size, quickening_info_size] =
+        [0, 4, 8].map(|i| u32_at(vdex, section_header + i).map(|size| size as usize));
+    let dex_begin = section_header + 12;
+    let dex_end = dex_begin
+        .checked_add(dex_size.ok_or_else(truncated)?)
+        .ok_or_else(truncated)?;
+    let quickening_begin = dex_end
+        .checked_add(shared_data_size.ok_or_else(truncated)?)
+        .and_then(|end| end.checked_add(deps_before_quickening))
+        .ok_or_else(truncated)?;
+    let quickening_info = quickening_begin
+        .checked_add(quickening_info_size.ok_or_else(truncated)?)
+        .and_then(|quickening_end| vdex.get(quickening_begin..quickening_end))
+        .ok_or_else(truncated)?;
+
+    let mut dexs = Vec::new();
+    let mut cursor = dex_begin;
+    while cursor < dex_end {
+        let table_offset = u32_at(vdex, cursor).ok_or_else(truncated)?;
+        let (dex, file_size) =
+            embedded_dex(vdex, cursor + 4).ok_or(PackageError::InvalidVdex("invalid dex file"))?;
+        let mut dex = dex.to_vec();
+        cursor = (cursor + 4 + file_size).next_multiple_of(4);
+        if !quickening_info.is_empty() {
+            unquicken(&mut dex, quickening_info, table_offset as usize)?;
+        }
+        dexs.push(dex);
+    }
+    if dexs.len() != number_of_dex_files {
+        return Err(PackageError::InvalidVdex("mismatching dex files count"));
+    }
+    Ok(dexs)
+}
+
+/// Android 12 and later vdex layout: a header followed by a table of
+/// sections, one of them holding the Dex files.
+fn vdex_027_dexs(vdex: &[u8]) -> PackageResult<Vec<Vec<u8>>> {
+    let truncated = || PackageError::InvalidVdex("truncated file");
+    let number_of_sections = u32_at(vdex, 8).ok_or_else(truncated)? as usize;
+    for i in 0..number_of_sections {
+        let header = 12 + 12 * i;
+        if u32_at(vdex, header).ok_or_else(truncated)? != DEX_FILE_SECTION {
+            continue;
+        }
+        let offset = u32_at(vdex, header + 4).ok_or_else(truncated)? as usize;
+        let size = u32_at(vdex, header + 8).ok_or_else(truncated)? as usize;
+        let mut dexs = Vec::new();
+        let mut cursor = offset;
+        while cursor < offset + size {
+            let (dex, file_size) =
+                embedded_dex(vdex, cursor).ok_or(PackageError::InvalidVdex("invalid dex file"))?;
+            cursor = (cursor + file_size).next_multiple_of(4);
+            dexs.push(dex.to_vec());
+        }
+        return Ok(dexs);
+    }
+    Ok(Vec::new())
+}
+
+/// Returns the Dex files embedded in the given oat file, either an ELF file
+/// (such as an `odex` file) or raw oat data.
+///
+/// The layout of the oat header depends on its version, so only the number
+/// of Dex files is read from it, and the Dex files are then located by
+/// their headers in the oat data. Since Android 8, Dex files are stored in
+/// the companion vdex file, which must be opened instead.
+pub(crate) fn oat_dexs(file: &[u8]) -> PackageResult<Vec<Vec<u8>>> {
+    let oat = if file.starts_with(ELF_MAGIC) {
+        elf_section(file, ".rodata").ok_or(PackageError::InvalidOat("oat data not found"))?
+    } else {
+        file
+    };
+    if !oat.starts_with(OAT_MAGIC) {
+        return Err(PackageError::InvalidOat("bad magic"));
+    }
+    let dex_file_count =
+        u32_at(oat, 20).ok_or(PackageError::InvalidOat("truncated header"))? as usize;
+
+    let mut dexs = Vec::new();
+    let mut cursor = 8;
+    while dexs.len() < dex_file_count && cursor < oat.len() {
+        match embedded_dex(oat, cursor) {
+            Some((dex, file_size)) => {
+                cursor = (cursor + file_size).next_multiple_of(4);
+                dexs.push(dex.to_vec());
+            }
+            None => cursor += 4,
+        }
+    }
+    match dexs.len() {
+        n if n == dex_file_count => Ok(dexs),
+        0 => Err(PackageError::InvalidOat(
+            "no embedded dex file, they may be stored in the vdex file",
+        )),
+        _ => Err(PackageError::InvalidOat("missing embedded dex files")),
+    }
+}
+
+/// Returns the Dex file starting at the given offset along with its size,
+/// if there is a valid Dex header there.
+///
+/// The data section of a compact Dex file may lie beyond the file itself,
+/// in the data shared by several Dex files: the returned content then
+/// extends up to the end of the data section.
+fn embedded_dex(data: &[u8], offset: usize) -> Option<(&[u8], usize)> {
+    let header = data.get(offset..offset.checked_add(DEX_HEADER_SIZE)?)?;
+    let compact = header.starts_with(CDEX_MAGIC);
+    let header_size = if compact {
+        CDEX_HEADER_SIZE
+    } else {
+        DEX_HEADER_SIZE
+    };
+    if !(compact || header.starts_with(DEX_MAGIC))
+        || header[7] != 0
+        || u32_at(header, 0x24)? as usize != header_size
+        || u32_at(header, 0x28)? != ENDIAN_CONSTANT
+    {
+        return None;
+    }
+    let file_size = u32_at(header, 0x20)? as usize;
+    if file_size < header_size {
+        return None;
+    }
+    let size = if compact {
+        let data_size = u32_at(header, 0x68)? as usize;
+        let data_off = u32_at(header, 0x6c)? as usize;
+        file_size.max(data_off.checked_add(data_size)?)
+    } else {
+        file_size
+    };
+    let dex = data.get(offset..offset.checked_add(size)?)?;
+    Some((dex, file_size))
+}
+
+/// Returns the content of the section with the given name of a little
+/// endian ELF file.
+fn elf_section<'a>(elf: &'a [u8], name: &str) -> Option<&'a [u8]> {
+    // offsets of e_shoff and sh_offset, and width of addresses and offsets
+    let (shoff_at, sh_offset_at, width) = match elf.get(4)? {
+        1 => (0x20, 0x10, 4),
+        2 => (0x28, 0x18, 8),
+        _ => return None,
+    };
+    if *elf.get(5)? != 1 {
+        return None;
+    }
+    let read = |offset: usize, width: usize| -> Option<usize> {
+        let bytes = elf.get(offset..offset.checked_add(width)?)?;
+        Some(bytes.iter().rev().fold(0, |acc, b| acc << 8 | *b as usize))
+    };
+    let shoff = read(shoff_at, width)?;
+    let shentsize = read(shoff_at + width + 10, 2)?;
+    let shnum = read(shoff_at + width + 12, 2)?;
+    let shstrndx = read(shoff_at + width + 14, 2)?;
+    let section = |index: usize| -> Option<(usize, &'a [u8])> {
+        let header = shoff.checked_add(index * shentsize)?;
+        let offset = read(header + sh_offset_at, width)?;
+        let size = read(header + sh_offset_at + width, width)?;
+        Some((
+            read(header, 4)?,
+            elf.get(offset..offset.checked_add(size)?)?,
+        ))
+    };
+    let (_, names) = section(shstrndx)?;
+    (0..shnum)
+        .filter_map(section)
+        .find_map(|(name_offset, content)| {
+            let section_name = names.get(name_offset..)?.split(|b| *b == 0).next()?;
+            (section_name == name.as_bytes()).then_some(content)
+        })
+}
+
+/// Restores the original instructions of the methods of a quickened Dex
+/// file, the offset of its quickening table in the quickening information
+/// being stored right before it in the vdex file.
+fn unquicken(dex: &mut [u8], quickening_info: &[u8], table_offset: usize) -> PackageResult<()> {
+    let table = quickening_info
+        .get(table_offset..)
+        .ok_or(PackageError::InvalidVdex("invalid quickening table offset"))?;
+    let truncated_code_item = || PackageError::InvalidVdex("truncated code item");
+    let compact = dex.starts_with(CDEX_MAGIC);
+    // data offsets of compact Dex files are relative to their data section
+    let data_off = if compact {
+        u32_at(dex, 0x6c).unwrap_or_default() as usize
+    } else {
+        0
+    };
+    let class_defs_size = u32_at(dex, 0x60).unwrap_or_default() as usize;
+    let class_defs_off = u32_at(dex, 0x64).unwrap_or_default() as usize;
+
+    // code items can be shared by several methods
+    let mut unquickened = BTreeSet::new();
+    for class_def in 0..class_defs_size {
+        let class_data_off = u32_at(dex, class_defs_off + 32 * class_def + 24)
+            .ok_or(PackageError::InvalidVdex("truncated class definition"))?
+            as usize;
+        if class_data_off == 0 {
+            continue;
+        }
+        for (method_idx, code_off) in methods(dex, data_off + class_data_off)? {
+            if code_off == 0 || !unquickened.insert(code_off) {
+                continue;
+            }
+            let Some(offset) = quickening_offset(table, method_idx) else {
+                continue;
+            };
+            let info = quicken_info(quickening_info, offset)?;
+            let code_off = data_off + code_off;
+            let (insns_off, insns_size) = if compact {
+                compact_insns(dex, code_off)
+            } else {
+                u32_at(dex, code_off + 12).map(|size| (code_off + 16, size as usize))
+            }
+            .ok_or_else(truncated_code_item)?;
+            let insns = insns_size
+                .checked_mul(2)
+                .and_then(|size| insns_off.checked_add(size))
+                .and_then(|insns_end| dex.get_mut(insns_off..insns_end))
+                .ok_or_else(truncated_code_item)?;
+            let mut units: Vec<u16> = insns
+                .chunks_exact(2)
+                .map(|unit| u16::from_le_bytes([unit[0], unit[1]]))
+                .collect();
+            decompile(&mut units, &info)?;
+            for (bytes, unit) in insns.chunks_exact_mut(2).zip(units) {
+                bytes.copy_from_slice(&unit.to_le_bytes());
+            }
+        }
+    }
+    Ok(())
+}
+
+/// Returns the offset and size (in code units) of the instructions of a
+/// compact code item, whose instructions count is packed with flags and
+/// possibly extended in the preheader preceding the code item.
+fn compact_insns(dex: &[u8], code_off: usize) -> Option<(usize, usize)> {
+    let insns_count_and_flags = u16_at(dex, code_off.checked_add(2)?)?;
+    let mut insns_size = usize::from(insns_count_and_flags >> 5);
+    if insns_count_and_flags & CDEX_PREHEADER_INSNS_SIZE != 0 {
+        insns_size += usize::from(u16_at(dex, code_off.checked_sub(2)?)?);
+        insns_size += usize::from(u16_at(dex, code_off.checked_sub(4)?)?) << 16;
+    }
+    Some((code_off + 4, insns_size))
+}
+
+/// Returns the method indices and code items offsets of the methods
+/// declared in the given class data item.
+fn methods(dex: &[u8], class_data_off: usize) -> PackageResult<Vec<(u32, usize)>> {
+    let mut methods = Vec::new();
+    let mut cursor = class_data_off;
+    let mut uleb =
+        || uleb128(dex, &mut cursor).ok_or(PackageError::InvalidVdex("truncated class data"));
+    let [static_fields, instance_fields, direct_methods, virtual_methods] =
+        [(); 4].map(|()| uleb());
+    for _ in 0..static_fields? + instance_fields? {
+        uleb()?;
+        uleb()?;
+    }
+    for count in [direct_methods?, virtual_methods?] {
+        let mut method_idx: u32 = 0;
+        for _ in 0..count {
+            method_idx = method_idx
+                .checked_add(uleb()?)
+                .ok_or(PackageError::InvalidVdex("invalid method index"))?;
+            uleb()?;
+            methods.push((method_idx, uleb()? as usize));
+        }
+    }
+    Ok(methods)
+}
+
+/// Returns the offset of the quickening information of the given method in
+/// a compact offset table, where offsets are grouped by blocks of 16
+/// methods, each block being a bitmask of the methods that have one followed
+/// by the delta encoded offsets.
+fn quickening_offset(table: &[u8], method_idx: u32) -> Option<usize> {
+    let minimum_offset = u32_at(table, 0)?;
+    let table_offset = u32_at(table, 4)? as usize;
+    let data = table.get(8..)?;
+    let block = u32_at(
+        data,
+        table_offset.checked_add(4 * (method_idx as usize / 16))?,
+    )? as usize;
+    let bit = method_idx % 16;
+    let mask = u16::from_be_bytes([*data.get(block)?, *data.get(block.checked_add(1)?)?]);
+    if mask & (1 << bit) == 0 {
+        return None;
+    }
+    let mut cursor = block + 2;
+    let mut offset = minimum_offset;
+    for _ in 0..(mask & (u16::MAX >> (15 - bit))).count_ones() {
+        offset = offset.checked_add(uleb128(data, &mut cursor)?)?;
+    }
+    Some(offset as usize)
+}
+
+/// Returns the quickening information at the given offset (which is shifted
+/// by one, zero meaning no information): a count followed by as many 16
+/// bits indices, consumed in order by the quickened instructions.
+fn quicken_info(quickening_info: &[u8], offset: usize) -> PackageResult<Vec<u16>> {
+    let invalid = || PackageError::InvalidVdex("invalid quickening info");
+    let mut cursor = offset.checked_sub(1).ok_or_else(invalid)?;
+    let count = uleb128(quickening_info, &mut cursor).ok_or_else(invalid)? as usize;
+    count
+        .checked_mul(2)
+        .and_then(|size| quickening_info.get(cursor..cursor.checked_add(size)?))
+        .map(|data| {
+            data.chunks_exact(2)
+                .map(|index| u16::from_le_bytes([index[0], index[1]]))
+                .collect()
+        })
+        .ok_or_else(invalid)
+}
+
+/// Restores the original instructions of a quickened method body.
+fn decompile(insns: &mut [u16], info: &[u16]) -> PackageResult<()> {
+    let mut indices = info.iter().copied();
+    let mut next_index = || {
+        indices
+            .next()
+            .ok_or(PackageError::InvalidVdex("missing quickening info"))
+    };
+    let mut pc = 0;
+    while pc < insns.len() {
+        let opcode = insns[pc] & 0xff;
+        match opcode {
+            // return-void-no-barrier
+            0x73 => insns[pc] = insns[pc] & 0xff00 | 0x0e,
+            // nop, the first half of an elided check-cast
+            0x00 if !info.is_empty() => {
+                let register = next_index()?;
+                if register != NO_INDEX {
+                    let type_idx = next_index()?;
+                    rewrite(insns, pc, [register << 8 | 0x1f, type_idx])?;
+                }
+            }
+            0xe3..=0xf2 => {
+                let original = match opcode {
+                    0xe3 => 0x52, // iget
+                    0xe4 => 0x53, // iget-wide
+                    0xe5 => 0x54, // iget-object
+                    0xe6 => 0x59, // iput
+                    0xe7 => 0x5a, // iput-wide
+                    0xe8 => 0x5b, // iput-object
+                    0xe9 => 0x6e, // invoke-virtual
+                    0xea => 0x74, // invoke-virtual/range
+                    0xeb => 0x5c, // iput-boolean
+                    0xec => 0x5d, // iput-byte
+                    0xed => 0x5e, // iput-char
+                    0xee => 0x5f, // iput-short
+                    0xef => 0x55, // iget-boolean
+                    0xf0 => 0x56, // iget-byte
+                    0xf1 => 0x57, // iget-char
+                    _ => 0x58,    // iget-short
+                };
+                rewrite(insns, pc, [insns[pc] & 0xff00 | original, next_index()?])?;
+            }
+            _ => (),
+        }
+        pc += instruction_width(insns, pc)
+            .ok_or(PackageError::InvalidVdex("truncated instruction"))?;
+    }
+    if pc != insns.len() || indices.next().is_some() {
+        return Err(PackageError::InvalidVdex("mismatching quickening info"));
+    }
+    Ok(())
+}
+
+fn rewrite(insns: &mut [u16], pc: usize, units: [u16; 2]) -> PackageResult<()> {
+    insns
+        .get_mut(pc..pc + 2)
+        .ok_or(PackageError::InvalidVdex("truncated instruction"))?
+        .copy_from_slice(&units);
+    Ok(())
+}
+
+/// Returns the number of code units of the instruction at the given
+/// address, including payload pseudo-instructions.
+fn instruction_width(insns: &[u16], pc: usize) -> Option<usize> {
+    let unit = |i: usize| insns.get(pc + i).map(|unit| *unit as usize);
+    let width = match insns[pc] {
+        // packed-switch-payload
+        0x0100 => unit(1)? * 2 + 4,
+        // sparse-switch-payload
+        0x0200 => unit(1)? * 4 + 2,
+        // fill-array-data-payload
+        0x0300 => (unit(1)? * (unit(2)? | unit(3)? << 16)).div_ceil(2) + 4,
+        insn => match insn & 0xff {
+            0x18 => 5,
+            0xfa | 0xfb => 4,
+            0x03 | 0x06 | 0x09 | 0x14 | 0x17 | 0x1b | 0x24..=0x26 | 0x2a..=0x2c => 3,
+            0x6e..=0x72 | 0x74..=0x78 | 0xe9 | 0xea | 0xfc | 0xfd => 3,
+            0x02 | 0x05 | 0x08 | 0x13 | 0x15 | 0x16 | 0x19 | 0x1a | 0x1c | 0x1f | 0x20 => 2,
+            0x22 | 0x23 | 0x29 | 0x2d..=0x3d | 0x44..=0x6d | 0x90..=0xaf => 2,
+            0xd0..=0xe8 | 0xeb..=0xf2 | 0xfe | 0xff => 2,
+            _ => 1,
+        },
+    };
+    Some(width)
+}
+
+fn u16_at(data: &[u8], offset: usize) -> Option<u16> {
+    let bytes = data.get(offset..offset.checked_add(2)?)?;
+    Some(u16::from_le_bytes(bytes.try_into().unwrap()))
+}
+
+fn u32_at(data: &[u8], offset: usize) -> Option<u32> {
+    let bytes = data.get(offset..offset.checked_add(4)?)?;
+    Some(u32::from_le_bytes(bytes.try_into().unwrap()))
+}
+
+fn uleb128(data: &[u8], cursor: &mut usize) -> Option<u32> {
+    let mut value = 0;
+    for shift in (0..35).step_by(7) {
+        let byte = *data.get(*cursor)?;
+        *cursor += 1;
+        value |= u32::from(byte & 0x7f) << shift;
+        if byte & 0x80 == 0 {
+            return Some(value);
+        }
+    }
+    None
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use dw_dex::testing::{push_u16, push_u32, push_uleb128, DexBuilder, ACC_PUBLIC};
+
+    /// A Dex header followed by padding, which is enough to be located.
+    fn dex_header(file_size: u32) -> Vec<u8> {
+        let mut dex = b"dex\n035\0".to_vec();
+        dex.resize(0x20, 0);
+        for v in [file_size, DEX_HEADER_SIZE as u32, ENDIAN_CONSTANT] {
+            dex.extend_from_slice(&v.to_le_bytes());
+        }
+        dex.resize(file_size as usize, 0xaa);
+        dex
+    }
+
+    /// Wraps a Dex file into a vdex file of the given version, along with
+    /// the quickening information of one of its methods.
+    fn quickened_vdex(version: &[u8], dex: &[u8], method_idx: u32, info: &[u16]) -> Vec<u8> {
+        let mut quickening = Vec::new();
+        push_uleb128(&mut quickening, info.len() as u32);
+        for index in info {
+            push_u16(&mut quickening, *index);
+        }
+        // offset table: the minimum offset (shifted by one), the offset of
+        // the blocks table, then a single block of 16 methods
+        let table_offset = quickening.len() as u32;
+        push_u32(&mut quickening, 1);
+        push_u32(&mut quickening, 3);
+        quickening.extend_from_slice(&(1u16 << method_idx).to_be_bytes());
+        quickening.push(0);
+        push_u32(&mut quickening, 0);
+
+        let verifier_deps = [0xde; 8];
+        let mut vdex = b"vdex".to_vec();
+        vdex.extend_from_slice(version);
+        vdex.extend_from_slice(DEX_SECTION_VERSION);
+        push_u32(&mut vdex, 1);
+        push_u32(&mut vdex, verifier_deps.len() as u32);
+        if version == b"021\0" {
+            // boot classpath checksums and class loader context sizes
+            push_u32(&mut vdex, 0);
+            push_u32(&mut vdex, 0);
+        }
+        push_u32(&mut vdex, 0); // checksum
+        let dex_size = (4 + dex.len()).next_multiple_of(4);
+        for size in [dex_size, 0, quickening.len()] {
+            push_u32(&mut vdex, size as u32);
+        }
+        push_u32(&mut vdex, table_offset);
+        vdex.extend_from_slice(dex);
+        vdex.resize(vdex.len().next_multiple_of(4), 0);
+        if version == b"019\0" {
+            vdex.extend_from_slice(&verifier_deps);
+        }
+        vdex.extend(quickening);
+        if version == b"021\0" {
+            vdex.extend_from_slice(&verifier_deps);
+        }
+        vdex
+    }
+
+    #[test]
+    fn unquicken_vdex() {
+        let mut builder = DexBuilder::new();
+        let class = builder.class("LFoo;", "Ljava/lang/Object;");
+        let field = builder.field("LFoo;", "value", "I");
+        let method = builder.method("LFoo;", "get", "I", &[]);
+        // iget-quick v0, v1, [obj+8], then return v0
+        builder.code(
+            class,
+            method,
+            ACC_PUBLIC,
+            2,
+            1,
+            0,
+            &[0x10e3, 0x0008, 0x000f],
+        );
+        let dex = builder.build();
+
+        for version in [b"019\0", b"021\0"] {
+            let vdex = quickened_vdex(version, &dex, method, &[field as u16]);
+            let dexs = vdex_dexs(&vdex).unwrap();
+            assert_eq!(dexs.len(), 1);
+            // iget v0, v1, field, then return v0
+            let iget = [0x52, 0x10, field as u8, 0, 0x0f, 0];
+            assert!(dexs[0].windows(iget.len()).any(|insns| insns == iget));
+            assert!(dw_dex::parse(&dexs[0]).is_ok());
+        }
+
+        // the quickening information does not match the instructions
+        let vdex = quickened_vdex(b"021\0", &dex, method, &[]);
+        assert!(matches!(
+            vdex_dexs(&vdex),
+            Err(PackageError::InvalidVdex(_))
+        ));
+    }
+
+    #[test]
+    fn embedded_compact_dex_with_shared_data() {
+        let mut cdex = b"cdex001\0".to_vec();
+        cdex.resize(0x20, 0);
+        for v in [
+            CDEX_HEADER_SIZE as u32,
+            CDEX_HEADER_SIZE as u32,
+            ENDIAN_CONSTANT,
+        ] {
+            push_u32(&mut cdex, v);
+        }
+        cdex.resize(0x68, 0);
+        // data section, in the data shared with other Dex files
+        push_u32(&mut cdex, 0x10);
+        push_u32(&mut cdex, 0x90);
+        cdex.resize(CDEX_HEADER_SIZE, 0);
+        cdex.resize(0xa0, 0xbb);
+
+        let (dex, file_size) = embedded_dex(&cdex, 0).unwrap();
+        assert_eq!((dex.len(), file_size), (0xa0, CDEX_HEADER_SIZE));
+        assert!(embedded_dex(&cdex[..0x9f], 0).is_none());
+    }
+
+    #[test]
+    fn decompile_quickened_method() {
+        let mut insns = [
+            0x10e3, 0x0008, // iget-quick v0, v1, [obj+8]
+            0x10e9, 0x0003, 0x0001, // invoke-virtual-quick {v1}, [vtable@3]
+            0x0000, 0x0000, // elided check-cast v1
+            0x0000, // nop
+            0x0073, // return-void-no-barrier
+        ];
+        decompile(&mut insns, &[5, 7, 1, 2, NO_INDEX]).unwrap();
+        assert_eq!(
+            insns,
+            [
+                0x1052, 0x0005, // iget v0, v1, field@5
+                0x106e, 0x0007, 0x0001, // invoke-virtual {v1}, method@7
+                0x011f, 0x0002, // check-cast v1, type@2
+                0x0000, // nop
+                0x000e, // return-void
+            ]
+        );
+
+        let mut insns = [0x10e3, 0x0008, 0x0073];
+        assert!(matches!(
+            decompile(&mut insns, &[5, 6]),
+            Err(PackageError::InvalidVdex(_))
+        ));
+    }
+
+    #[test]
+    fn compact_offset_table() {
+        let mut table = Vec::new();
+        // minimum offset, then offset of the blocks table
+        table.extend_from_slice(&10u32.to_le_bytes());
+        table.extend_from_slice(&6u32.to_le_bytes());
+        // methods 0 to 15: method 3 at minimum offset
+        table.extend_from_slice(&[0x00, 0x08, 0]);
+        // methods 16 to 31: method 17 at minimum offset + 20
+        table.extend_from_slice(&[0x00, 0x02, 20]);
+        table.extend_from_slice(&0u32.to_le_bytes());
+        table.extend_from_slice(&3u32.to_le_bytes());
+
+        assert_eq!(quickening_offset(&table, 3), Some(10));
+        assert_eq!(quickening_offset(&table, 17), Some(30));
+        assert_eq!(quickening_offset(&table, 4), None);
+        assert_eq!(quickening_offset(&table, 40), None);
+    }
+
+    #[test]
+    fn vdex_unknown_version() {
+        assert!(matches!(
+            vdex_dexs(b"vdex018\0\0\0\0\0"),
+            Err(PackageError::UnsupportedVdexVersion(version)) if version == "018"
+        ));
+    }
+
+    #[test]
+    fn oat_dexs_from_elf() {
+        let mut rodata = b"oat\n088\0".to_vec();
+        rodata.resize(20, 0);
+        rodata.extend_from_slice(&2u32.to_le_bytes());
+        rodata.resize(0x40, 0);
+        rodata.extend(dex_header(0x72));
+        rodata.resize(0xb4, 0);
+        rodata.extend(dex_header(0x80));
+
+        let mut elf = ELF_MAGIC.to_vec();
+        elf.extend_from_slice(&[2, 1, 1]);
+        elf.resize(64, 0);
+        let names = b"\0.shstrtab\0.rodata\0";
+        let names_offset = elf.len();
+        elf.extend_from_slice(names);
+        let rodata_offset = elf.len();
+        elf.extend_from_slice(&rodata);
+        let shoff = elf.len();
+        elf.resize(shoff + 64, 0);
+        for (name, offset, size) in [
+            (1, names_offset, names.len()),
+            (11, rodata_offset, rodata.len()),
+        ] {
+            elf.extend_from_slice(&u32::to_le_bytes(name));
+            elf.resize(elf.len() + 20, 0);
+            elf.extend_from_slice(&(offset as u64).to_le_bytes());
+            elf.extend_from_slice(&(size as u64).to_le_bytes());
+            elf.resize(elf.len() + 24, 0);
+        }
+        elf[0x28..0x30].copy_from_slice(&(shoff as u64).to_le_bytes());
+        elf[0x3a..0x40].copy_from_slice(&[64, 0, 3, 0, 1, 0]);
+
+        let dexs = oat_dexs(&elf).unwrap();
+        assert_eq!(dexs.iter().map(Vec::len).collect::<Vec<_>>(), [0x72, 0x80]);
+
+        rodata[20] = 0;
+        rodata[21] = 1;
+        assert!(matches!(
+            oat_dexs(&rodata),
+            Err(PackageError::InvalidOat(_))
+        ));
+    }
+}
@@ -41,6 +41,9 @@ pub enum ResourcesError {
     #[error("too complex resource: {0}")]
     TooComplexResource(String),
 
+    #[error("reference cycle while resolving resource: {0}")]
+    ReferenceCycle(String),
+
     #[error("xml query failed: {0}")]
     XmlQuery(String),
 
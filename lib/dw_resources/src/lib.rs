@@ -13,11 +13,14 @@ pub mod errors;
 pub mod manifest;
 pub mod nsc;
 pub mod resources;
+#[cfg(any(test, feature = "testing"))]
+pub mod testing;
 pub mod values;
 
 use crate::errors::ResourcesResult;
 use crate::parsers::parse_xml;
-use crate::strings::StringPool;
+use crate::strings::{StringPool, StringPoolIndex};
+use crate::values::Value;
 use crate::xml::{XmlEvent, XmlResourceMap};
 use std::collections::BTreeMap;
 use std::fmt;
@@ -38,40 +41,64 @@ impl fmt::Display for Xml {
             f,
             "<?xml version=\"1.0\" encoding=\"utf-8\" standalone=\"no\"?>"
         )?;
+        // namespaces in scope, along with the prefixes they shadow
         let mut namespaces_stack = Vec::new();
         let mut namespaces_map = BTreeMap::new();
+        // namespaces to be declared on the next element
+        let mut namespaces_pending = Vec::new();
+        let mut elements_stack = Vec::new();
+        let prefix = |map: &BTreeMap<_, _>, uri| -> Result<String, fmt::Error> {
+            let prefix = map.get(uri).ok_or(fmt::Error)?;
+            Ok(self
+                .string_pool
+                .get(*prefix)
+                .map_err(|_| fmt::Error)?
+                .to_string())
+        };
+        let same_element =
+            |a: &xml::XmlElement, b: &xml::XmlElement| a.ns == b.ns && a.name == b.name;
+
         let mut i_event = 0;
         while i_event < self.xml_body.len() {
             match &self.xml_body[i_event] {
                 XmlEvent::StartNamespace(ns) => {
-                    namespaces_stack.push(<&xml::XmlNamespace>::clone(&ns));
-                    namespaces_map.insert(ns.uri, ns.prefix);
+                    let shadowed = namespaces_map.insert(ns.uri, ns.prefix);
+                    namespaces_stack.push((<&xml::XmlNamespace>::clone(&ns), shadowed));
+                    namespaces_pending.push(<&xml::XmlNamespace>::clone(&ns));
                 }
-                XmlEvent::EndNamespace(_ns) => {
-                    // TODO remove from namespaces (check if it's the top of the stack)
-                    // (stack AND map)
+                XmlEvent::EndNamespace(ns) => {
+                    let (top, shadowed) = namespaces_stack.pop().ok_or(fmt::Error)?;
+                    if top.uri != ns.uri || top.prefix != ns.prefix {
+                        return Err(fmt::Error);
+                    }
+                    match shadowed {
+                        Some(prefix) => namespaces_map.insert(ns.uri, prefix),
+                        None => namespaces_map.remove(&ns.uri),
+                    };
                 }
                 XmlEvent::StartElement(elt, attrs) => {
                     write!(f, "<")?;
                     if let Some(uri) = &elt.ns {
-                        let prefix = namespaces_map.get(uri).unwrap();
+                        write!(f, "{}:", prefix(&namespaces_map, uri)?)?;
+                    }
+                    write!(f, "{}", elt.name(self).map_err(|_| fmt::Error)?)?;
+
+                    for ns in namespaces_pending.drain(..) {
                         write!(
                             f,
-                            "{}:",
-                            self.string_pool.get(*prefix).map_err(|_| fmt::Error)?
+                            " xmlns:{}={:?}",
+                            self.string_pool.get(ns.prefix).map_err(|_| fmt::Error)?,
+                            self.string_pool
+                                .get(ns.uri)
+                                .map_err(|_| fmt::Error)?
+                                .to_string()
                         )?;
                     }
-                    write!(f, "{}", elt.name(self).map_err(|_| fmt::Error)?)?;
 
                     for attr in &attrs.attrs {
                         write!(f, " ")?;
                         if let Some(uri) = &attr.ns {
-                            let prefix = namespaces_map.get(uri).unwrap();
-                            write!(
-                                f,
-                                "{}:",
-                                self.string_pool.get(*prefix).map_err(|_| fmt::Error)?
-                            )?;
+                            write!(f, "{}:", prefix(&namespaces_map, uri)?)?;
                         }
                         write!(f, "{}=", attr.name(self).map_err(|_| fmt::Error)?)?;
                         attr.typed_value
@@ -79,28 +106,32 @@ impl fmt::Display for Xml {
                             .map_err(|_| fmt::Error)?;
                     }
 
-                    if i_event < self.xml_body.len() - 1 {
-                        if let XmlEvent::EndElement(_) = self.xml_body[i_event + 1] {
-                            // TODO check that EndElement match the current StartElement
+                    match self.xml_body.get(i_event + 1) {
+                        Some(XmlEvent::EndElement(end)) => {
+                            if !same_element(elt, end) {
+                                return Err(fmt::Error);
+                            }
                             write!(f, "/>")?;
                             i_event += 1;
-                        } else {
+                        }
+                        Some(_) => {
                             write!(f, ">")?;
+                            elements_stack.push(elt);
                         }
-                    } else {
-                        // TODO error! last event cannot be a StartElement
+                        // last event cannot be a StartElement
+                        None => return Err(fmt::Error),
                     }
                 }
                 XmlEvent::EndElement(elt) => {
+                    let start = elements_stack.pop().ok_or(fmt::Error)?;
+                    if !same_element(start, elt) {
+                        return Err(fmt::Error);
+                    }
+                    write!(f, "</")?;
                     if let Some(uri) = &elt.ns {
-                        let prefix = namespaces_map.get(uri).unwrap();
-                        write!(
-                            f,
-                            "{}:",
-                            self.string_pool.get(*prefix).map_err(|_| fmt::Error)?
-                        )?;
+                        write!(f, "{}:", prefix(&namespaces_map, uri)?)?;
                     }
-                    write!(f, "</{}>", elt.name(self).map_err(|_| fmt::Error)?)?;
+                    write!(f, "{}>", elt.name(self).map_err(|_| fmt::Error)?)?;
                 }
                 XmlEvent::Cdata(data) => {
                     write!(f, "{}", self.string_pool.get(data.data).unwrap())?;
@@ -122,9 +153,169 @@ impl Default for Xml {
     }
 }
 
+impl Xml {
+    /// Returns the string pool index of the attribute name bound to
+    /// `resource_id` in the resource map, allocating it if needed.
+    ///
+    /// Names of attributes with a resource id come first in the string pool,
+    /// in the resource map order: allocating a new one shifts all the other
+    /// strings, and every string reference of the document is updated.
+    pub(crate) fn resource_attribute_name(
+        &mut self,
+        name: &str,
+        resource_id: u32,
+    ) -> StringPoolIndex {
+        let resource_ids = &mut self
+            .xml_resource_map
+            .get_or_insert_with(|| XmlResourceMap {
+                resource_ids: Vec::new(),
+            })
+            .resource_ids;
+        if let Some(i) = resource_ids.iter().position(|id| *id == resource_id) {
+            return StringPoolIndex::new(i);
+        }
+        let position = resource_ids.len();
+        resource_ids.push(resource_id);
+        let index = self.string_pool.insert(position, name);
+        self.shift_strings(position);
+        index
+    }
+
+    /// Increments every string reference greater or equal to `from`.
+    fn shift_strings(&mut self, from: usize) {
+        let shift = |index: &mut StringPoolIndex| {
+            if index.index() >= from {
+                *index = StringPoolIndex::new(index.index() + 1);
+            }
+        };
+        let shift_raw = |raw: &mut u32| {
+            if *raw != 0xffff_ffff && *raw as usize >= from {
+                *raw += 1;
+            }
+        };
+        let shift_value = |value: &mut Value| {
+            if let Value::String(index) = value {
+                shift(index);
+            }
+        };
+        for event in &mut self.xml_body {
+            match event {
+                XmlEvent::StartNamespace(ns) | XmlEvent::EndNamespace(ns) => {
+                    shift_raw(&mut ns.metadata.comment);
+                    shift(&mut ns.prefix);
+                    shift(&mut ns.uri);
+                }
+                XmlEvent::StartElement(elt, attrs) => {
+                    shift_raw(&mut elt.metadata.comment);
+                    elt.ns.iter_mut().for_each(shift);
+                    shift(&mut elt.name);
+                    for attr in &mut attrs.attrs {
+                        attr.ns.iter_mut().for_each(shift);
+                        shift(&mut attr.name);
+                        shift_raw(&mut attr.raw_value);
+                        shift_value(&mut attr.typed_value);
+                    }
+                }
+                XmlEvent::EndElement(elt) => {
+                    shift_raw(&mut elt.metadata.comment);
+                    elt.ns.iter_mut().for_each(shift);
+                    shift(&mut elt.name);
+                }
+                XmlEvent::Cdata(cdata) => {
+                    shift_raw(&mut cdata.metadata.comment);
+                    shift(&mut cdata.data);
+                    shift_value(&mut cdata.value);
+                }
+            }
+        }
+    }
+}
+
 pub fn open_xml<P: AsRef<Path>>(path: P) -> ResourcesResult<Xml> {
     let mut file = File::open(path)?;
     let mut contents = Vec::new();
     file.read_to_end(&mut contents)?;
     parse_xml(&contents)
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::strings::StringPoolIndex;
+    use crate::values::Value;
+    use crate::xml::{XmlAttribute, XmlElement, XmlElementAttrs, XmlMetadata, XmlNamespace};
+    use std::fmt::Write;
+
+    const METADATA: XmlMetadata = XmlMetadata {
+        line_number: 1,
+        comment: 0xffff_ffff,
+    };
+
+    fn namespace(prefix: StringPoolIndex, uri: StringPoolIndex) -> XmlNamespace {
+        XmlNamespace {
+            metadata: METADATA,
+            prefix,
+            uri,
+        }
+    }
+
+    fn element(ns: StringPoolIndex, name: StringPoolIndex) -> XmlElement {
+        XmlElement {
+            metadata: METADATA,
+            ns: Some(ns),
+            name,
+        }
+    }
+
+    fn attributes(attrs: Vec<XmlAttribute>) -> XmlElementAttrs {
+        XmlElementAttrs {
+            id_index: 0,
+            class_index: 0,
+            style_index: 0,
+            attrs,
+        }
+    }
+
+    #[test]
+    fn display_nested_namespaces() {
+        let mut xml = Xml::default();
+        let mut string = |s: &str| xml.string_pool.get_or_push(s.to_string()).unwrap().0;
+        let [prefix, outer, inner, root, child, other, attr, value] = [
+            "a", "urn:one", "urn:two", "root", "child", "other", "attr", "v",
+        ]
+        .map(&mut string);
+
+        xml.xml_body = vec![
+            XmlEvent::StartNamespace(namespace(prefix, outer)),
+            XmlEvent::StartElement(element(outer, root), attributes(Vec::new())),
+            XmlEvent::StartNamespace(namespace(prefix, inner)),
+            XmlEvent::StartElement(
+                element(inner, child),
+                attributes(vec![XmlAttribute {
+                    ns: Some(inner),
+                    name: attr,
+                    raw_value: 0,
+                    typed_value: Value::String(value),
+                }]),
+            ),
+            XmlEvent::EndElement(element(inner, child)),
+            XmlEvent::EndNamespace(namespace(prefix, inner)),
+            XmlEvent::StartElement(element(outer, other), attributes(Vec::new())),
+            XmlEvent::EndElement(element(outer, other)),
+            XmlEvent::EndElement(element(outer, root)),
+            XmlEvent::EndNamespace(namespace(prefix, outer)),
+        ];
+        assert_eq!(
+            xml.to_string(),
+            "<?xml version=\"1.0\" encoding=\"utf-8\" standalone=\"no\"?>\
+             <a:root xmlns:a=\"urn:one\">\
+             <a:child xmlns:a=\"urn:two\" a:attr=\"v\"/>\
+             <a:other/>\
+             </a:root>"
+        );
+
+        // closing the outer namespace first breaks scopes nesting
+        xml.xml_body.swap(5, 9);
+        assert!(write!(String::new(), "{xml}").is_err());
+    }
+}
@@ -6,6 +6,7 @@
 use crate::errors::{ResourcesError, ResourcesResult};
 use crate::parsers::parse_xml;
 use crate::resources::Resources;
+use crate::strings::StringPoolIndex;
 use crate::utils::{extract_single_bool_attribute, extract_single_string_attribute};
 use crate::values::{ResolvedValue, Value};
 use crate::writers::write_xml;
@@ -62,6 +63,111 @@ impl Manifest {
         }
     }
 
+    /// Returns the package name found in the manifest, resolving it against
+    /// `resources` when the attribute is a reference. If the reference cannot
+    /// be resolved, the raw attribute value is returned instead.
+    pub fn package_resolved(
+        &self,
+        resources: Option<&Resources>,
+    ) -> ResourcesResult<Option<String>> {
+        let attrs = xpath::Context::new(&self.xml)
+            .select(xpath::Select::Root(
+                &Regex::new("^manifest$").expect("regex"),
+            ))?
+            .select(xpath::Select::Attr("package"))?
+            .attributes()?;
+        let [attribute] = attrs[..] else {
+            return Ok(None);
+        };
+        match attribute
+            .typed_value
+            .resolve(&self.xml.string_pool, resources)
+        {
+            Ok(ResolvedValue::String(s)) => return Ok(Some(s)),
+            Ok(_)
+            | Err(
+                ResourcesError::CannotResolveWithoutResources(_)
+                | ResourcesError::ResNotFound(_)
+                | ResourcesError::TooComplexResource(_)
+                | ResourcesError::ReferenceCycle(_),
+            ) => (),
+            Err(e) => return Err(e),
+        }
+        if attribute.raw_value != 0xffff_ffff {
+            let val = self
+                .xml
+                .string_pool
+                .get(StringPoolIndex::new(attribute.raw_value as usize))?
+                .string()?;
+            return Ok(Some(val));
+        }
+        match attribute.typed_value {
+            Value::Reference(r) => Ok(Some(format!("@0x{r:0>8x}"))),
+            _ => Ok(None),
+        }
+    }
+
+    /// Returns the fully-qualified form of a component (or application)
+    /// class `name`, which may be written relatively to `package` (`.Main`
+    /// or `Main`).
+    #[must_use]
+    pub fn qualify(package: &str, name: &str) -> String {
+        if name.starts_with('.') {
+            format!("{package}{name}")
+        } else if !name.contains('.') {
+            format!("{package}.{name}")
+        } else {
+            name.to_string()
+        }
+    }
+
+    /// Renames the application package to `new_package`.
+    ///
+    /// Component (and application) class names written in the relative
+    /// form (`.Main` or `Main`) are expanded to fully-qualified names
+    /// referencing the old package, so that they keep resolving to the same
+    /// classes once the package has been changed.
+    pub fn set_package(&mut self, new_package: &str) -> ResourcesResult<()> {
+        let old_package = self
+            .package()?
+            .ok_or_else(|| ResourcesError::Structure("/manifest@package not found".to_string()))?;
+
+        let expand = |name: &str| {
+            let qualified = Self::qualify(&old_package, name);
+            (qualified != name).then_some(qualified)
+        };
+        let _ = xpath::ContextMut::new(&mut self.xml)
+            .select(xpath::Select::Root(
+                &Regex::new("^manifest$").expect("regex"),
+            ))?
+            .select(xpath::Select::Root(
+                &Regex::new("^application$").expect("regex"),
+            ))?
+            .select(xpath::Select::Attr("name"))?
+            .map_string_attributes(expand)?;
+        let _ = xpath::ContextMut::new(&mut self.xml)
+            .select(xpath::Select::Root(
+                &Regex::new("^manifest$").expect("regex"),
+            ))?
+            .select(xpath::Select::Root(
+                &Regex::new("^application$").expect("regex"),
+            ))?
+            .select(xpath::Select::Root(
+                &Regex::new("^(activity|activity-alias|service|receiver|provider)$")
+                    .expect("regex"),
+            ))?
+            .select(xpath::Select::Attr("name"))?
+            .map_string_attributes(expand)?;
+
+        let _ = xpath::ContextMut::new(&mut self.xml)
+            .select(xpath::Select::Root(
+                &Regex::new("^manifest$").expect("regex"),
+            ))?
+            .select(xpath::Select::Attr("package"))?
+            .map_string_attributes(|_| Some(new_package.to_string()))?;
+        Ok(())
+    }
+
     /// Returns the compileSdkVersion name found in the manifest.
     /// Equivalent of xpath `/manifest@compilesdkversion` selection.
     pub fn compile_sdk_version(&self) -> ResourcesResult<Option<u32>> {
@@ -222,6 +328,24 @@ impl Manifest {
         }
     }
 
+    /// Returns the name of the `Application` subclass declared in the manifest, if any.
+    /// This is the equivalent of xpath `/manifest/application@name` selection.
+    pub fn application_name(
+        &self,
+        resources: Option<&Resources>,
+    ) -> ResourcesResult<Option<String>> {
+        let attrs = xpath::Context::new(&self.xml)
+            .select(xpath::Select::Root(
+                &Regex::new("^manifest$").expect("regex"),
+            ))?
+            .select(xpath::Select::Root(
+                &Regex::new("^application$").expect("regex"),
+            ))?
+            .select(xpath::Select::Attr("name"))?
+            .attributes()?;
+        extract_single_string_attribute(&attrs, &self.xml, resources)
+    }
+
     pub fn network_security_config(
         &self,
         resources: Option<&Resources>,
@@ -280,6 +404,19 @@ impl Manifest {
         )
     }
 
+    /// Returns the `targetSdkVersion` attribute of the `<uses-sdk>` node,
+    /// which defaults to `minSdkVersion` (itself defaulting to 1).
+    pub fn target_sdk_version(&self, resources: Option<&Resources>) -> ResourcesResult<u32> {
+        if let Some(mut sdk) = self.uses_sdk(resources)?.into_iter().next() {
+            for name in ["targetSdkVersion", "minSdkVersion"] {
+                if let Some(ResolvedValue::Int(version)) = sdk.attributes.remove(name).flatten() {
+                    return Ok(version);
+                }
+            }
+        }
+        Ok(1)
+    }
+
     /// Returns a vec of permissions names declared in the manifest.
     /// This is the equivalent of xpath `/manifest/uses-permission@name` selection,
     /// and also captures `/manifest/uses-permissions-use-sdk23@name` selection.
@@ -287,7 +424,10 @@ impl Manifest {
         &self,
         resources: Option<&Resources>,
     ) -> ResourcesResult<Vec<ManifestTag>> {
-        let default_attributes = HashMap::from([("name".to_string(), None)]);
+        let default_attributes = HashMap::from([
+            ("name".to_string(), None),
+            ("maxSdkVersion".to_string(), None),
+        ]);
         self.manifest_tags(
             false,
             &Regex::new("^uses-permission").expect("regex"),
@@ -296,23 +436,52 @@ impl Manifest {
         )
     }
 
-    /// Adds permission nodes by name.
-    pub fn add_uses_permission(&mut self, permission: &str) -> ResourcesResult<()> {
+    /// Adds a permission node by name, with an optional `android:maxSdkVersion`
+    /// attribute. Returns `false` if the permission is already declared.
+    pub fn add_uses_permission(
+        &mut self,
+        permission: &str,
+        max_sdk: Option<u32>,
+    ) -> ResourcesResult<bool> {
+        if self
+            .uses_permissions(None)?
+            .iter()
+            .any(|tag| tag.name().as_deref() == Some(permission))
+        {
+            return Ok(false);
+        }
+
+        // attribute names are allocated first, since they shift the other strings
+        let mut names = vec!["name"];
+        if max_sdk.is_some() {
+            names.push("maxSdkVersion");
+        }
+        for name in names {
+            let resource_id = android_attribute_id(name).expect("android attribute");
+            self.xml.resource_attribute_name(name, resource_id);
+        }
         let (permission_index, _) = self.xml.string_pool.get_or_push(permission.to_string())?;
 
         let query = xpath::ContextMut::new(&mut self.xml).select(xpath::Select::Root(
             &Regex::new("^manifest$").expect("regex"),
         ))?;
 
-        let attrs = vec![(
+        let mut attrs = vec![(
             Some("android".to_string()),
             "name".to_string(),
             Value::String(permission_index),
         )];
+        if let Some(max_sdk) = max_sdk {
+            attrs.push((
+                Some("android".to_string()),
+                "maxSdkVersion".to_string(),
+                Value::IntDec(max_sdk),
+            ));
+        }
 
         let _ = query.add_self_contained_nodes("uses-permission".to_string(), attrs)?;
 
-        Ok(())
+        Ok(true)
     }
 
     /// Removes permission nodes by name. In xpath terms, removes the
@@ -349,6 +518,91 @@ impl Manifest {
         self.remove_tags_by_name(false, &Regex::new("^uses-feature").expect("regex"), feature)
     }
 
+    /// Returns the shared libraries declared in the manifest.
+    /// This is the equivalent of xpath `/manifest/application/uses-library` selection.
+    pub fn uses_libraries(
+        &self,
+        resources: Option<&Resources>,
+    ) -> ResourcesResult<Vec<UsesLibrary>> {
+        self.libraries(&Regex::new("^uses-library$").expect("regex"), resources)
+    }
+
+    /// Returns the vendor native libraries declared in the manifest.
+    /// This is the equivalent of xpath `/manifest/application/uses-native-library`
+    /// selection.
+    pub fn uses_native_libraries(
+        &self,
+        resources: Option<&Resources>,
+    ) -> ResourcesResult<Vec<UsesLibrary>> {
+        self.libraries(
+            &Regex::new("^uses-native-library$").expect("regex"),
+            resources,
+        )
+    }
+
+    /// Adds a shared library node.
+    pub fn add_uses_library(&mut self, library: &str, required: bool) -> ResourcesResult<()> {
+        let (library_index, _) = self.xml.string_pool.get_or_push(library.to_string())?;
+
+        let query = xpath::ContextMut::new(&mut self.xml)
+            .select(xpath::Select::Root(
+                &Regex::new("^manifest$").expect("regex"),
+            ))?
+            .select(xpath::Select::Root(
+                &Regex::new("^application$").expect("regex"),
+            ))?;
+        if query.has_empty_selection() {
+            return Err(ResourcesError::Structure(
+                "/manifest/application not found".to_string(),
+            ));
+        }
+
+        let attrs = vec![
+            (
+                Some("android".to_string()),
+                "name".to_string(),
+                Value::String(library_index),
+            ),
+            (
+                Some("android".to_string()),
+                "required".to_string(),
+                Value::IntBoolean(required),
+            ),
+        ];
+
+        let _ = query.add_self_contained_nodes("uses-library".to_string(), attrs)?;
+
+        Ok(())
+    }
+
+    /// Removes shared library nodes by name. In xpath terms, removes the
+    /// `/manifest/application/uses-library[@name=library]` nodes.
+    pub fn remove_uses_library(&mut self, library: &str) -> ResourcesResult<bool> {
+        self.remove_tags_by_name(true, &Regex::new("^uses-library$").expect("regex"), library)
+    }
+
+    fn libraries(
+        &self,
+        tag: &Regex,
+        resources: Option<&Resources>,
+    ) -> ResourcesResult<Vec<UsesLibrary>> {
+        let default_attributes = HashMap::from([
+            ("name".to_string(), None),
+            ("required".to_string(), Some(ResolvedValue::Bool(true))),
+        ]);
+        let tags = self.manifest_tags(true, tag, default_attributes, resources)?;
+        Ok(tags
+            .into_iter()
+            .filter_map(|tag| {
+                let required = !matches!(
+                    tag.attributes.get("required"),
+                    Some(Some(ResolvedValue::Bool(false)))
+                );
+                tag.name().map(|name| UsesLibrary { name, required })
+            })
+            .collect())
+    }
+
     /// Returns a vec of activities names declared in the manifest.
     /// This is the equivalent of xpath `/manifest/application/activity@name` selection,
     /// and also captures `/manifest/application/activity-alias@name` selection.
@@ -379,7 +633,7 @@ impl Manifest {
     /// Removes activity nodes by name. In xpath terms, removes the
     /// `/manifest/activity[@name=activity]` nodes.
     pub fn remove_activity(&mut self, activity: &str) -> ResourcesResult<bool> {
-        self.remove_tags_by_name(true, &Regex::new("^activity").expect("regex"), activity)
+        self.remove_components_by_name(&Regex::new("^activity").expect("regex"), activity)
     }
 
     /// Returns a vec of services names declared in the manifest.
@@ -411,7 +665,7 @@ impl Manifest {
     /// Removes service nodes by name. In xpath terms, removes the
     /// `/manifest/service[@name=service]` nodes.
     pub fn remove_service(&mut self, service: &str) -> ResourcesResult<bool> {
-        self.remove_tags_by_name(true, &Regex::new("^service").expect("regex"), service)
+        self.remove_components_by_name(&Regex::new("^service").expect("regex"), service)
     }
 
     /// Returns a vec of receivers names declared in the manifest.
@@ -443,7 +697,7 @@ impl Manifest {
     /// Removes receiver nodes by name. In xpath terms, removes the
     /// `/manifest/receiver[@name=receiver]` nodes.
     pub fn remove_receiver(&mut self, receiver: &str) -> ResourcesResult<bool> {
-        self.remove_tags_by_name(true, &Regex::new("^receiver").expect("regex"), receiver)
+        self.remove_components_by_name(&Regex::new("^receiver").expect("regex"), receiver)
     }
 
     /// Returns a vec of providers names declared in the manifest.
@@ -493,7 +747,206 @@ impl Manifest {
     /// Removes receiver nodes by name. In xpath terms, removes the
     /// `/manifest/provider[@name=provider]` nodes.
     pub fn remove_provider(&mut self, provider: &str) -> ResourcesResult<bool> {
-        self.remove_tags_by_name(true, &Regex::new("^provider").expect("regex"), provider)
+        self.remove_components_by_name(&Regex::new("^provider").expect("regex"), provider)
+    }
+
+    /// Sets the attribute `attr` (in the `android` namespace) of the
+    /// component of the given kind named `name`, inserting the attribute if
+    /// the component does not declare it yet. Returns whether a matching
+    /// component has been found.
+    ///
+    /// Only the attributes bearing a resource id known by DroidWorks can be
+    /// inserted, since the Android framework looks them up by id.
+    pub fn set_component_attribute(
+        &mut self,
+        kind: ComponentKind,
+        name: &str,
+        attr: &str,
+        value: ManifestValue,
+    ) -> ResourcesResult<bool> {
+        fn component<'a>(
+            xml: &'a mut Xml,
+            kind: ComponentKind,
+            package: &str,
+            name: &str,
+        ) -> ResourcesResult<xpath::ContextMut<'a>> {
+            xpath::ContextMut::new(xml)
+                .select(xpath::Select::Root(
+                    &Regex::new("^manifest$").expect("regex"),
+                ))?
+                .select(xpath::Select::Root(
+                    &Regex::new("^application$").expect("regex"),
+                ))?
+                .select(xpath::Select::Root(&Regex::new(kind.tag()).expect("regex")))?
+                .filter(xpath::Predicate::Component(package, name))
+        }
+
+        let package = self.package()?.unwrap_or_default();
+        let query = component(&mut self.xml, kind, &package, name)?;
+        if query.has_empty_selection() {
+            return Ok(false);
+        }
+        let attr_query = query.select(xpath::Select::Attr(attr))?;
+        if attr_query.has_empty_selection() {
+            let resource_id = android_attribute_id(attr).ok_or_else(|| {
+                ResourcesError::Structure(format!("unknown resource id for attribute '{attr}'"))
+            })?;
+            let attr_name = self.xml.resource_attribute_name(attr, resource_id);
+            let value = value.into_value(&mut self.xml)?;
+            component(&mut self.xml, kind, &package, name)?
+                .insert_attribute_raw(attr_name, value)?;
+        } else {
+            let value = value.into_value(&mut self.xml)?;
+            component(&mut self.xml, kind, &package, name)?
+                .select(xpath::Select::Attr(attr))?
+                .edit_attribute(value)?;
+        }
+        Ok(true)
+    }
+
+    /// Returns all the application components (activities, activity
+    /// aliases, services, receivers and providers) along with their intent
+    /// filters. Components attributes are the same as the ones returned by
+    /// the simple accessors ([`Manifest::activities`], etc.).
+    pub fn components_with_filters(
+        &self,
+        resources: Option<&Resources>,
+    ) -> ResourcesResult<Vec<Component>> {
+        let kinds = [
+            (
+                ComponentKind::Activity,
+                "^activity",
+                self.activities(resources)?,
+            ),
+            (
+                ComponentKind::Service,
+                "^service",
+                self.services(resources)?,
+            ),
+            (
+                ComponentKind::Receiver,
+                "^receiver",
+                self.receivers(resources)?,
+            ),
+            (
+                ComponentKind::Provider,
+                "^provider",
+                self.providers(resources)?,
+            ),
+        ];
+
+        let mut components = Vec::new();
+        for (kind, tag, tags) in kinds {
+            let filters = self.intent_filters(&Regex::new(tag).expect("regex"), resources)?;
+            components.extend(tags.into_iter().zip(filters).map(|(tag, intent_filters)| {
+                Component {
+                    kind,
+                    tag,
+                    intent_filters,
+                }
+            }));
+        }
+        Ok(components)
+    }
+
+    /// Returns the intent filters of each `/manifest/application/tag` node,
+    /// in document order.
+    fn intent_filters(
+        &self,
+        tag: &Regex,
+        resources: Option<&Resources>,
+    ) -> ResourcesResult<Vec<Vec<IntentFilter>>> {
+        let components = xpath::Context::new(&self.xml)
+            .select(xpath::Select::Root(
+                &Regex::new("^manifest$").expect("regex"),
+            ))?
+            .select(xpath::Select::Root(
+                &Regex::new("^application$").expect("regex"),
+            ))?
+            .select(xpath::Select::Root(tag))?;
+
+        components
+            .split()
+            .into_iter()
+            .map(|component| {
+                component
+                    .select(xpath::Select::Root(
+                        &Regex::new("^intent-filter$").expect("regex"),
+                    ))?
+                    .split()
+                    .into_iter()
+                    .map(|filter| self.intent_filter(filter, resources))
+                    .collect()
+            })
+            .collect()
+    }
+
+    fn intent_filter(
+        &self,
+        filter: xpath::Context,
+        resources: Option<&Resources>,
+    ) -> ResourcesResult<IntentFilter> {
+        let mut intent_filter = IntentFilter {
+            priority: self
+                .node_attribute(&filter, "priority", resources)?
+                .and_then(|value| match value {
+                    ResolvedValue::Int(i) => Some(i as i32),
+                    _ => None,
+                }),
+            ..IntentFilter::default()
+        };
+
+        for child in filter
+            .select(xpath::Select::Root(
+                &Regex::new("^(action|category|data)$").expect("regex"),
+            ))?
+            .split()
+        {
+            let (elt, _) = child.clone().nodes()?[0];
+            let string_attribute = |name| -> ResourcesResult<Option<String>> {
+                Ok(self
+                    .node_attribute(&child, name, resources)?
+                    .and_then(|value| value.as_str().map(ToString::to_string)))
+            };
+            match elt.name(&self.xml)?.string()?.as_str() {
+                "action" => intent_filter.actions.extend(string_attribute("name")?),
+                "category" => intent_filter.categories.extend(string_attribute("name")?),
+                _ => intent_filter.data.push(IntentFilterData {
+                    scheme: string_attribute("scheme")?,
+                    host: string_attribute("host")?,
+                    port: string_attribute("port")?,
+                    path: string_attribute("path")?,
+                    path_prefix: string_attribute("pathPrefix")?,
+                    path_pattern: string_attribute("pathPattern")?,
+                    mime_type: string_attribute("mimeType")?,
+                }),
+            }
+        }
+        Ok(intent_filter)
+    }
+
+    /// Resolves the attribute `name` of the single node selected by `node`.
+    /// Values that are too complex to be resolved are ignored.
+    fn node_attribute(
+        &self,
+        node: &xpath::Context,
+        name: &str,
+        resources: Option<&Resources>,
+    ) -> ResourcesResult<Option<ResolvedValue>> {
+        let attrs = node
+            .clone()
+            .select(xpath::Select::Attr(name))?
+            .attributes()?;
+        match attrs.first() {
+            None => Ok(None),
+            Some(attr) => match attr.typed_value.resolve(&self.xml.string_pool, resources) {
+                Ok(value) => Ok(Some(value)),
+                Err(ResourcesError::TooComplexResource(_) | ResourcesError::ReferenceCycle(_)) => {
+                    Ok(None)
+                }
+                Err(other_err) => Err(other_err),
+            },
+        }
     }
 
     fn remove_tags_by_name(
@@ -501,6 +954,22 @@ impl Manifest {
         in_application: bool,
         tag: &Regex,
         name: &str,
+    ) -> ResourcesResult<bool> {
+        self.remove_tags(in_application, tag, xpath::Predicate::Attr("name", name))
+    }
+
+    /// Same as [`Manifest::remove_tags_by_name`] for application components,
+    /// whose names are compared once qualified.
+    fn remove_components_by_name(&mut self, tag: &Regex, name: &str) -> ResourcesResult<bool> {
+        let package = self.package()?.unwrap_or_default();
+        self.remove_tags(true, tag, xpath::Predicate::Component(&package, name))
+    }
+
+    fn remove_tags(
+        &mut self,
+        in_application: bool,
+        tag: &Regex,
+        predicate: xpath::Predicate,
     ) -> ResourcesResult<bool> {
         let mut query = xpath::ContextMut::new(&mut self.xml).select(xpath::Select::Root(
             &Regex::new("^manifest$").expect("regex"),
@@ -512,9 +981,7 @@ impl Manifest {
             ))?;
         }
 
-        query = query
-            .select(xpath::Select::Root(tag))?
-            .filter(xpath::Predicate::Attr("name", name))?;
+        query = query.select(xpath::Select::Root(tag))?.filter(predicate)?;
 
         query.remove_nodes()
     }
@@ -537,29 +1004,32 @@ impl Manifest {
         }
 
         tags = tags.select(xpath::Select::Root(tag))?;
-        let query = tags.clone();
+        let queries = tags.clone().split();
         let attrs = tags.nodes()?;
 
         attrs
             .into_iter()
-            .map(|(_, attrs)| {
+            .zip(queries)
+            .map(|((_, attrs), query)| {
                 let mut attributes = default_attributes.clone();
+                let mut declares_exported = false;
 
                 for attr in &attrs.attrs {
                     let attr_name = self.xml.string_pool.get(attr.name)?.string()?;
+                    declares_exported |= attr_name == "exported";
                     if attributes.contains_key(attr_name.as_str()) {
                         match attr.typed_value.resolve(&self.xml.string_pool, resources) {
                             Ok(value) => attributes.insert(attr_name, Some(value)),
-                            Err(ResourcesError::TooComplexResource(_)) => {
-                                attributes.insert(attr_name, None)
-                            }
+                            Err(
+                                ResourcesError::TooComplexResource(_)
+                                | ResourcesError::ReferenceCycle(_),
+                            ) => attributes.insert(attr_name, None),
                             Err(other_err) => return Err(other_err),
                         };
                     }
                 }
 
                 let has_intent_filter = !query
-                    .clone()
                     .select(xpath::Select::Root(
                         &Regex::new("^intent-filter$").expect("regex"),
                     ))?
@@ -569,23 +1039,444 @@ impl Manifest {
                 Ok(ManifestTag {
                     attributes,
                     has_intent_filter,
+                    implicitly_exported: has_intent_filter && !declares_exported,
                 })
             })
             .collect()
     }
 }
 
+/// The kinds of application components.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, serde::Serialize)]
+pub enum ComponentKind {
+    Activity,
+    Service,
+    Receiver,
+    Provider,
+}
+
+impl ComponentKind {
+    /// The regex matching the manifest tags of this kind of component
+    /// (activity aliases are activities).
+    const fn tag(self) -> &'static str {
+        match self {
+            Self::Activity => "^activity",
+            Self::Service => "^service",
+            Self::Receiver => "^receiver",
+            Self::Provider => "^provider",
+        }
+    }
+}
+
+/// A value to be set on a manifest attribute.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum ManifestValue {
+    Bool(bool),
+    Int(u32),
+    String(String),
+    /// A resource reference, such as `@string/app_name`.
+    Reference(u32),
+}
+
+impl ManifestValue {
+    fn into_value(self, xml: &mut Xml) -> ResourcesResult<Value> {
+        Ok(match self {
+            Self::Bool(b) => Value::IntBoolean(b),
+            Self::Int(i) => Value::IntDec(i),
+            Self::String(s) => Value::String(xml.string_pool.get_or_push(s)?.0),
+            Self::Reference(r) => Value::Reference(r),
+        })
+    }
+}
+
+/// Returns the resource id of the `android` namespace attribute `name`, for
+/// the attributes that can be set on components or permissions.
+fn android_attribute_id(name: &str) -> Option<u32> {
+    let id = match name {
+        "name" => 0x0101_0003,
+        "permission" => 0x0101_0006,
+        "readPermission" => 0x0101_0007,
+        "writePermission" => 0x0101_0008,
+        "enabled" => 0x0101_000e,
+        "exported" => 0x0101_0010,
+        "process" => 0x0101_0011,
+        "authorities" => 0x0101_0018,
+        "grantUriPermissions" => 0x0101_001b,
+        "maxSdkVersion" => 0x0101_0271,
+        "directBootAware" => 0x0101_0505,
+        _ => return None,
+    };
+    Some(id)
+}
+
+/// An application component along with its intent filters.
+#[derive(Debug, serde::Serialize)]
+pub struct Component {
+    pub kind: ComponentKind,
+    pub tag: ManifestTag,
+    pub intent_filters: Vec<IntentFilter>,
+}
+
+/// An `<intent-filter>` node.
+#[derive(Debug, Default, Clone, PartialEq, Eq, serde::Serialize)]
+pub struct IntentFilter {
+    pub actions: Vec<String>,
+    pub categories: Vec<String>,
+    pub data: Vec<IntentFilterData>,
+    pub priority: Option<i32>,
+}
+
+/// A `<data>` node of an intent filter.
+#[derive(Debug, Default, Clone, PartialEq, Eq, serde::Serialize)]
+pub struct IntentFilterData {
+    pub scheme: Option<String>,
+    pub host: Option<String>,
+    pub port: Option<String>,
+    pub path: Option<String>,
+    pub path_prefix: Option<String>,
+    pub path_pattern: Option<String>,
+    pub mime_type: Option<String>,
+}
+
+/// A `<uses-library>` or `<uses-native-library>` node.
+#[derive(Debug, Clone, PartialEq, Eq, serde::Serialize)]
+pub struct UsesLibrary {
+    pub name: String,
+    pub required: bool,
+}
+
 #[derive(Debug, serde::Serialize)]
 pub struct ManifestTag {
     pub attributes: HashMap<String, Option<ResolvedValue>>,
     #[serde(skip)]
     has_intent_filter: bool,
+    #[serde(skip)]
+    implicitly_exported: bool,
 }
 
 impl ManifestTag {
+    /// Returns whether the tag is a component exported because of an intent
+    /// filter, without declaring the `android:exported` attribute (which is
+    /// rejected for applications targeting Android 12 or higher).
+    #[must_use]
+    pub const fn is_implicitly_exported(&self) -> bool {
+        self.implicitly_exported
+    }
+
     #[must_use]
     pub fn name(&self) -> Option<String> {
         let value = self.attributes.get("name")?.clone()?;
         value.as_str().map(std::string::ToString::to_string)
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::testing::{xml, Node};
+    use crate::xml::XmlEvent;
+
+    #[test]
+    fn deep_link_intent_filter() {
+        let strings = [
+            "manifest",
+            "application",
+            "activity",
+            "name",
+            ".Main",
+            "intent-filter",
+            "priority",
+            "action",
+            "android.intent.action.VIEW",
+            "category",
+            "android.intent.category.BROWSABLE",
+            "data",
+            "scheme",
+            "https",
+            "host",
+            "example.com",
+            "pathPrefix",
+            "/open",
+        ];
+        let input = xml(
+            &strings,
+            &[
+                Node::Start(0, &[]),
+                Node::Start(1, &[]),
+                Node::Start(2, &[(3, 0x03, 4)]),
+                Node::Start(5, &[(6, 0x10, 10)]),
+                Node::Start(7, &[(3, 0x03, 8)]),
+                Node::End(7),
+                Node::Start(9, &[(3, 0x03, 10)]),
+                Node::End(9),
+                Node::Start(11, &[(12, 0x03, 13), (14, 0x03, 15), (16, 0x03, 17)]),
+                Node::End(11),
+                Node::End(5),
+                Node::End(2),
+                Node::End(1),
+                Node::End(0),
+            ],
+        );
+        let manifest = parse(&input).unwrap();
+
+        let components = manifest.components_with_filters(None).unwrap();
+        assert_eq!(components.len(), 1);
+        assert_eq!(components[0].kind, ComponentKind::Activity);
+        assert_eq!(components[0].tag.name().as_deref(), Some(".Main"));
+        assert_eq!(
+            components[0].intent_filters,
+            vec![IntentFilter {
+                actions: vec!["android.intent.action.VIEW".to_string()],
+                categories: vec!["android.intent.category.BROWSABLE".to_string()],
+                data: vec![IntentFilterData {
+                    scheme: Some("https".to_string()),
+                    host: Some("example.com".to_string()),
+                    path_prefix: Some("/open".to_string()),
+                    ..IntentFilterData::default()
+                }],
+                priority: Some(10),
+            }]
+        );
+    }
+
+    #[test]
+    fn set_package_expands_relative_names() {
+        let strings = [
+            "manifest",
+            "package",
+            "com.old",
+            "application",
+            "name",
+            ".App",
+            "activity",
+            ".Main",
+            "service",
+            "Sync",
+            "receiver",
+            "com.other.Receiver",
+        ];
+        let input = xml(
+            &strings,
+            &[
+                Node::Start(0, &[(1, 0x03, 2)]),
+                Node::Start(3, &[(4, 0x03, 5)]),
+                Node::Start(6, &[(4, 0x03, 7)]),
+                Node::End(6),
+                Node::Start(8, &[(4, 0x03, 9)]),
+                Node::End(8),
+                Node::Start(10, &[(4, 0x03, 11)]),
+                Node::End(10),
+                Node::End(3),
+                Node::End(0),
+            ],
+        );
+        let mut manifest = parse(&input).unwrap();
+        manifest.set_package("com.new").unwrap();
+
+        let manifest = parse(&write(&manifest).unwrap()).unwrap();
+        assert_eq!(manifest.package().unwrap().as_deref(), Some("com.new"));
+        let names = |tags: Vec<ManifestTag>| tags.iter().map(ManifestTag::name).collect::<Vec<_>>();
+        assert_eq!(
+            names(manifest.activities(None).unwrap()),
+            vec![Some("com.old.Main".to_string())]
+        );
+        assert_eq!(
+            names(manifest.services(None).unwrap()),
+            vec![Some("com.old.Sync".to_string())]
+        );
+        assert_eq!(
+            names(manifest.receivers(None).unwrap()),
+            vec![Some("com.other.Receiver".to_string())]
+        );
+        let application = xpath::Context::new(&manifest.xml)
+            .select(xpath::Select::Root(
+                &Regex::new("^manifest$").expect("regex"),
+            ))
+            .unwrap()
+            .select(xpath::Select::Root(
+                &Regex::new("^application$").expect("regex"),
+            ))
+            .unwrap();
+        assert_eq!(
+            manifest.node_attribute(&application, "name", None).unwrap(),
+            Some(ResolvedValue::String("com.old.App".to_string()))
+        );
+    }
+
+    #[test]
+    fn add_and_remove_uses_library() {
+        let strings = ["manifest", "application"];
+        let input = xml(
+            &strings,
+            &[
+                Node::Start(0, &[]),
+                Node::Start(1, &[]),
+                Node::End(1),
+                Node::End(0),
+            ],
+        );
+        let mut manifest = parse(&input).unwrap();
+        manifest
+            .add_uses_library("org.apache.http.legacy", false)
+            .unwrap();
+
+        let mut manifest = parse(&write(&manifest).unwrap()).unwrap();
+        assert_eq!(
+            manifest.uses_libraries(None).unwrap(),
+            vec![UsesLibrary {
+                name: "org.apache.http.legacy".to_string(),
+                required: false,
+            }]
+        );
+        assert!(manifest.uses_native_libraries(None).unwrap().is_empty());
+
+        assert!(manifest
+            .remove_uses_library("org.apache.http.legacy")
+            .unwrap());
+        assert!(manifest.uses_libraries(None).unwrap().is_empty());
+    }
+
+    #[test]
+    fn add_uses_permission_with_max_sdk() {
+        let strings = [
+            "android",
+            "http://schemas.android.com/apk/res/android",
+            "manifest",
+        ];
+        let input = xml(
+            &strings,
+            &[
+                Node::StartNamespace(0, 1),
+                Node::Start(2, &[]),
+                Node::End(2),
+                Node::EndNamespace(0, 1),
+            ],
+        );
+        let mut manifest = parse(&input).unwrap();
+        assert!(manifest
+            .add_uses_permission("android.permission.INTERNET", Some(30))
+            .unwrap());
+        assert!(!manifest
+            .add_uses_permission("android.permission.INTERNET", None)
+            .unwrap());
+
+        let manifest = parse(&write(&manifest).unwrap()).unwrap();
+        let permissions = manifest.uses_permissions(None).unwrap();
+        assert_eq!(permissions.len(), 1);
+        assert_eq!(
+            permissions[0].name().as_deref(),
+            Some("android.permission.INTERNET")
+        );
+        assert_eq!(
+            permissions[0].attributes.get("maxSdkVersion"),
+            Some(&Some(ResolvedValue::Int(30)))
+        );
+        assert_eq!(
+            manifest.xml.xml_resource_map.as_ref().unwrap().resource_ids,
+            vec![0x0101_0003, 0x0101_0271]
+        );
+        assert!(manifest
+            .xml
+            .to_string()
+            .contains("<uses-permission android:name=\"android.permission.INTERNET\" android:maxSdkVersion=\"30\"/>"));
+    }
+
+    #[test]
+    fn set_component_exported() {
+        let strings = [
+            "android",
+            "http://schemas.android.com/apk/res/android",
+            "manifest",
+            "application",
+            "activity",
+            "name",
+            ".Main",
+            "intent-filter",
+            "package",
+            "com.example.app",
+        ];
+        let input = xml(
+            &strings,
+            &[
+                Node::StartNamespace(0, 1),
+                Node::Start(2, &[(8, 0x03, 9)]),
+                Node::Start(3, &[]),
+                Node::Start(4, &[(5, 0x03, 6)]),
+                Node::Start(7, &[]),
+                Node::End(7),
+                Node::End(4),
+                Node::End(3),
+                Node::End(2),
+                Node::EndNamespace(0, 1),
+            ],
+        );
+        let mut manifest = parse(&input).unwrap();
+        let exported = |manifest: &Manifest| {
+            manifest.activities(None).unwrap()[0]
+                .attributes
+                .get("exported")
+                .cloned()
+                .flatten()
+        };
+        assert_eq!(exported(&manifest), Some(ResolvedValue::Bool(true)));
+        // pretend the name is the style attribute, to check that its index
+        // follows the insertion
+        for event in &mut manifest.xml.xml_body {
+            if let XmlEvent::StartElement(_, attrs) = event {
+                if !attrs.attrs.is_empty() {
+                    attrs.style_index = 1;
+                }
+            }
+        }
+
+        assert!(!manifest
+            .set_component_attribute(
+                ComponentKind::Activity,
+                ".Other",
+                "exported",
+                ManifestValue::Bool(false)
+            )
+            .unwrap());
+        assert!(manifest
+            .set_component_attribute(
+                ComponentKind::Activity,
+                "com.example.app.Main",
+                "exported",
+                ManifestValue::Bool(false)
+            )
+            .unwrap());
+        assert!(manifest
+            .xml
+            .to_string()
+            .contains("<activity android:exported=\"false\" name=\".Main\">"));
+        assert!(manifest.xml.xml_body.iter().any(|event| matches!(
+            event,
+            XmlEvent::StartElement(_, attrs) if attrs.attrs.len() == 2 && attrs.style_index == 2
+        )));
+
+        let mut manifest = parse(&write(&manifest).unwrap()).unwrap();
+        assert_eq!(exported(&manifest), Some(ResolvedValue::Bool(false)));
+        assert_eq!(
+            manifest.activities(None).unwrap()[0].name().as_deref(),
+            Some(".Main")
+        );
+        assert_eq!(
+            manifest.xml.xml_resource_map.as_ref().unwrap().resource_ids,
+            vec![0x0101_0010]
+        );
+
+        // the attribute now exists and is edited in place
+        assert!(manifest
+            .set_component_attribute(
+                ComponentKind::Activity,
+                ".Main",
+                "exported",
+                ManifestValue::Bool(true)
+            )
+            .unwrap());
+        assert_eq!(exported(&manifest), Some(ResolvedValue::Bool(true)));
+
+        assert!(manifest.remove_activity("Main").unwrap());
+        assert!(manifest.activities(None).unwrap().is_empty());
+    }
+}
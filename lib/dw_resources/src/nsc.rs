@@ -1,7 +1,15 @@
 use crate::errors::{ResourcesError, ResourcesResult};
 use crate::parsers::parse_xml;
+use crate::utils::{extract_single_bool_attribute, extract_single_string_attribute};
+use crate::values::Value;
 use crate::writers::write_xml;
+use crate::xml::{
+    XmlAttribute, XmlCdata, XmlElement, XmlElementAttrs, XmlEvent, XmlMetadata, XmlResourceMap,
+};
+use crate::xpath;
 use crate::Xml;
+use chrono::NaiveDate;
+use regex::Regex;
 use std::fmt;
 
 #[derive(Debug)]
@@ -24,6 +32,205 @@ impl fmt::Display for NetworkSecurityConfig {
     }
 }
 
+impl NetworkSecurityConfig {
+    /// Returns the `cleartextTrafficPermitted` attribute of the
+    /// `<base-config>` node, if any.
+    pub fn base_cleartext_traffic_permitted(&self) -> ResourcesResult<Option<bool>> {
+        let attrs = xpath::Context::new(&self.xml)
+            .select(xpath::Select::Root(
+                &Regex::new("^network-security-config$").expect("regex"),
+            ))?
+            .select(xpath::Select::Root(
+                &Regex::new("^base-config$").expect("regex"),
+            ))?
+            .select(xpath::Select::Attr("cleartextTrafficPermitted"))?
+            .attributes()?;
+        extract_single_bool_attribute(&attrs, &self.xml, None)
+    }
+
+    /// Returns the domains for which a `<domain-config>` node sets the
+    /// cleartext traffic policy. Nested domain configurations inherit the
+    /// policy of their parent when they do not set it.
+    pub fn cleartext_domains(&self) -> ResourcesResult<Vec<DomainCleartextPolicy>> {
+        let configs = xpath::Context::new(&self.xml)
+            .select(xpath::Select::Root(
+                &Regex::new("^network-security-config$").expect("regex"),
+            ))?
+            .select(xpath::Select::Root(
+                &Regex::new("^domain-config$").expect("regex"),
+            ))?;
+        let mut policies = Vec::new();
+        self.collect_cleartext_domains(configs, None, &mut policies)?;
+        Ok(policies)
+    }
+
+    fn collect_cleartext_domains(
+        &self,
+        configs: xpath::Context,
+        inherited: Option<bool>,
+        policies: &mut Vec<DomainCleartextPolicy>,
+    ) -> ResourcesResult<()> {
+        let domain_regex = Regex::new("^domain$").expect("regex");
+        let domain_config_regex = Regex::new("^domain-config$").expect("regex");
+        for config in configs.split() {
+            let attrs = config
+                .clone()
+                .select(xpath::Select::Attr("cleartextTrafficPermitted"))?
+                .attributes()?;
+            let permitted = extract_single_bool_attribute(&attrs, &self.xml, None)?.or(inherited);
+
+            if let Some(permitted) = permitted {
+                for domain in config
+                    .clone()
+                    .select(xpath::Select::Root(&domain_regex))?
+                    .split()
+                {
+                    let attrs = domain
+                        .clone()
+                        .select(xpath::Select::Attr("includeSubdomains"))?
+                        .attributes()?;
+                    let include_subdomains =
+                        extract_single_bool_attribute(&attrs, &self.xml, None)?.unwrap_or(false);
+                    policies.push(DomainCleartextPolicy {
+                        domain: domain.texts()?.concat().trim().to_string(),
+                        include_subdomains,
+                        permitted,
+                    });
+                }
+            }
+
+            let nested = config.select(xpath::Select::Root(&domain_config_regex))?;
+            self.collect_cleartext_domains(nested, permitted, policies)?;
+        }
+        Ok(())
+    }
+
+    /// Reviews the domain configurations, reporting the pin sets that
+    /// expired before `now` or that contain pins with another digest
+    /// algorithm than SHA-256, and the domains for which cleartext traffic
+    /// is permitted. Nested domain configurations inherit the pin set of
+    /// their parent when they do not set one.
+    ///
+    /// The base configuration is not reviewed, its cleartext traffic policy
+    /// is given by [`Self::base_cleartext_traffic_permitted`].
+    pub fn audit(&self, now: NaiveDate) -> ResourcesResult<Vec<NscFinding>> {
+        let configs = xpath::Context::new(&self.xml)
+            .select(xpath::Select::Root(
+                &Regex::new("^network-security-config$").expect("regex"),
+            ))?
+            .select(xpath::Select::Root(
+                &Regex::new("^domain-config$").expect("regex"),
+            ))?;
+        let mut findings = Vec::new();
+        self.collect_pin_findings(configs, None, now, &mut findings)?;
+        findings.extend(
+            self.cleartext_domains()?
+                .into_iter()
+                .filter(|policy| policy.permitted)
+                .map(|policy| NscFinding::CleartextPermitted {
+                    domain: policy.domain,
+                }),
+        );
+        Ok(findings)
+    }
+
+    fn collect_pin_findings(
+        &self,
+        configs: xpath::Context,
+        inherited: Option<&(Option<NaiveDate>, Vec<String>)>,
+        now: NaiveDate,
+        findings: &mut Vec<NscFinding>,
+    ) -> ResourcesResult<()> {
+        let domain_regex = Regex::new("^domain$").expect("regex");
+        let domain_config_regex = Regex::new("^domain-config$").expect("regex");
+        let pin_set_regex = Regex::new("^pin-set$").expect("regex");
+        let pin_regex = Regex::new("^pin$").expect("regex");
+        for config in configs.split() {
+            let pin_set = config.clone().select(xpath::Select::Root(&pin_set_regex))?;
+            // expiration date and digest algorithms of the pins
+            let own_pin_set = if pin_set.clone().nodes()?.is_empty() {
+                None
+            } else {
+                let attrs = pin_set
+                    .clone()
+                    .select(xpath::Select::Attr("expiration"))?
+                    .attributes()?;
+                let expiration = extract_single_string_attribute(&attrs, &self.xml, None)?
+                    .and_then(|date| match NaiveDate::parse_from_str(&date, "%Y-%m-%d") {
+                        Ok(date) => Some(date),
+                        Err(_) => {
+                            log::warn!("invalid pin set expiration date '{date}'");
+                            None
+                        }
+                    });
+                let mut algs = Vec::new();
+                for attr in pin_set
+                    .select(xpath::Select::Root(&pin_regex))?
+                    .select(xpath::Select::Attr("digest"))?
+                    .attributes()?
+                {
+                    if let Some(alg) = extract_single_string_attribute(&[attr], &self.xml, None)? {
+                        if !algs.contains(&alg) {
+                            algs.push(alg);
+                        }
+                    }
+                }
+                Some((expiration, algs))
+            };
+            let pin_set = own_pin_set.as_ref().or(inherited);
+
+            if let Some((expiration, algs)) = pin_set {
+                for domain in config
+                    .clone()
+                    .select(xpath::Select::Root(&domain_regex))?
+                    .texts()?
+                {
+                    let domain = domain.trim().to_string();
+                    if let Some(expiration) = expiration.filter(|date| *date < now) {
+                        findings.push(NscFinding::ExpiredPinSet {
+                            domain: domain.clone(),
+                            expiration,
+                        });
+                    }
+                    for alg in algs.iter().filter(|alg| *alg != "SHA-256") {
+                        findings.push(NscFinding::WeakDigest {
+                            domain: domain.clone(),
+                            alg: alg.clone(),
+                        });
+                    }
+                }
+            }
+
+            let nested = config.select(xpath::Select::Root(&domain_config_regex))?;
+            self.collect_pin_findings(nested, pin_set, now, findings)?;
+        }
+        Ok(())
+    }
+}
+
+/// A weakness found by [`NetworkSecurityConfig::audit`].
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum NscFinding {
+    /// The pins of the domain are not enforced anymore since `expiration`.
+    ExpiredPinSet {
+        domain: String,
+        expiration: NaiveDate,
+    },
+    /// A pin of the domain has a digest computed with `alg` instead of
+    /// SHA-256.
+    WeakDigest { domain: String, alg: String },
+    /// Cleartext traffic is permitted for the domain.
+    CleartextPermitted { domain: String },
+}
+
+/// The cleartext traffic policy set for a domain by a `<domain-config>` node.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct DomainCleartextPolicy {
+    pub domain: String,
+    pub include_subdomains: bool,
+    pub permitted: bool,
+}
+
 pub fn system_store_without_clear_traffic() -> ResourcesResult<NetworkSecurityConfig> {
     let input = include_bytes!("../data/nsc_system_store_without_clear_traffic.axml");
     parse(input)
@@ -62,3 +269,431 @@ pub fn network_security_config(name: &str) -> ResourcesResult<NetworkSecurityCon
         _ => Err(ResourcesError::ResNotFound(name.to_string())),
     }
 }
+
+/// The source of the certificates of a `<trust-anchors>` node.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum CertificatesSource {
+    /// The pre-installed system CA certificates.
+    System,
+    /// The CA certificates added by the user.
+    User,
+    /// The certificates stored in the raw resource of the given id.
+    Raw(u32),
+}
+
+/// A `<certificates>` node of a `<trust-anchors>` set.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct TrustAnchor {
+    pub src: CertificatesSource,
+    pub override_pins: bool,
+}
+
+impl TrustAnchor {
+    #[must_use]
+    pub const fn new(src: CertificatesSource) -> Self {
+        Self {
+            src,
+            override_pins: false,
+        }
+    }
+
+    #[must_use]
+    pub const fn with_override_pins(mut self, override_pins: bool) -> Self {
+        self.override_pins = override_pins;
+        self
+    }
+}
+
+/// A `<pin-set>` node, made of the base64 encoded SHA-256 digests of the
+/// pinned public keys.
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct PinSet {
+    pub expiration: Option<String>,
+    pub pins: Vec<String>,
+}
+
+impl PinSet {
+    #[must_use]
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Sets the pin set expiration date, in the `yyyy-MM-dd` format.
+    #[must_use]
+    pub fn with_expiration(mut self, date: &str) -> Self {
+        self.expiration = Some(date.to_string());
+        self
+    }
+
+    #[must_use]
+    pub fn with_pin(mut self, sha256: &str) -> Self {
+        self.pins.push(sha256.to_string());
+        self
+    }
+}
+
+/// A `<domain-config>` node.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct DomainConfig {
+    /// Domain names along with their `includeSubdomains` attribute.
+    pub domains: Vec<(String, bool)>,
+    pub cleartext_traffic_permitted: Option<bool>,
+    pub pin_set: Option<PinSet>,
+    pub trust_anchors: Vec<TrustAnchor>,
+}
+
+impl DomainConfig {
+    #[must_use]
+    pub fn new(domain: &str, include_subdomains: bool) -> Self {
+        Self {
+            domains: vec![(domain.to_string(), include_subdomains)],
+            cleartext_traffic_permitted: None,
+            pin_set: None,
+            trust_anchors: Vec::new(),
+        }
+    }
+
+    #[must_use]
+    pub fn with_domain(mut self, domain: &str, include_subdomains: bool) -> Self {
+        self.domains.push((domain.to_string(), include_subdomains));
+        self
+    }
+
+    #[must_use]
+    pub const fn with_cleartext_traffic_permitted(mut self, permitted: bool) -> Self {
+        self.cleartext_traffic_permitted = Some(permitted);
+        self
+    }
+
+    #[must_use]
+    pub fn with_pin_set(mut self, pin_set: PinSet) -> Self {
+        self.pin_set = Some(pin_set);
+        self
+    }
+
+    #[must_use]
+    pub fn with_trust_anchor(mut self, anchor: TrustAnchor) -> Self {
+        self.trust_anchors.push(anchor);
+        self
+    }
+}
+
+/// Assembles a [`NetworkSecurityConfig`] from its base configuration and
+/// its domain specific configurations.
+///
+/// The `<base-config>` node is emitted only if the cleartext traffic policy
+/// or some trust anchors have been set.
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct NetworkSecurityConfigBuilder {
+    cleartext_traffic_permitted: Option<bool>,
+    trust_anchors: Vec<TrustAnchor>,
+    domain_configs: Vec<DomainConfig>,
+}
+
+/// Attribute values of the built nodes.
+enum Attr {
+    String(String),
+    Bool(bool),
+    Reference(u32),
+}
+
+/// An element of the built document, along with its attributes, text
+/// content and children elements.
+struct Node {
+    name: &'static str,
+    attrs: Vec<(&'static str, Attr)>,
+    text: Option<String>,
+    children: Vec<Node>,
+}
+
+impl Node {
+    const fn new(name: &'static str) -> Self {
+        Self {
+            name,
+            attrs: Vec::new(),
+            text: None,
+            children: Vec::new(),
+        }
+    }
+
+    fn trust_anchors(anchors: &[TrustAnchor]) -> Self {
+        let mut node = Self::new("trust-anchors");
+        for anchor in anchors {
+            let mut certificates = Self::new("certificates");
+            let src = match anchor.src {
+                CertificatesSource::System => Attr::String("system".to_string()),
+                CertificatesSource::User => Attr::String("user".to_string()),
+                CertificatesSource::Raw(id) => Attr::Reference(id),
+            };
+            certificates.attrs.push(("src", src));
+            if anchor.override_pins {
+                certificates.attrs.push(("overridePins", Attr::Bool(true)));
+            }
+            node.children.push(certificates);
+        }
+        node
+    }
+
+    fn emit(&self, xml: &mut Xml, line: &mut u32) -> ResourcesResult<()> {
+        let metadata = |line: u32| XmlMetadata {
+            line_number: line,
+            comment: 0xffff_ffff,
+        };
+        let mut attrs = Vec::with_capacity(self.attrs.len());
+        for (name, value) in &self.attrs {
+            let name = xml.string_pool.get_or_push((*name).to_string())?.0;
+            let typed_value = match value {
+                Attr::String(s) => Value::String(xml.string_pool.get_or_push(s.clone())?.0),
+                Attr::Bool(b) => Value::IntBoolean(*b),
+                Attr::Reference(id) => Value::Reference(*id),
+            };
+            attrs.push(XmlAttribute {
+                ns: None,
+                name,
+                raw_value: typed_value.raw_value(),
+                typed_value,
+            });
+        }
+        let name = xml.string_pool.get_or_push(self.name.to_string())?.0;
+        let element = XmlElement {
+            metadata: metadata(*line),
+            ns: None,
+            name,
+        };
+        xml.xml_body.push(XmlEvent::StartElement(
+            element.clone(),
+            XmlElementAttrs {
+                id_index: 0,
+                class_index: 0,
+                style_index: 0,
+                attrs,
+            },
+        ));
+        if let Some(text) = &self.text {
+            let data = xml.string_pool.get_or_push(text.clone())?.0;
+            xml.xml_body.push(XmlEvent::Cdata(XmlCdata {
+                metadata: metadata(*line),
+                data,
+                value: Value::Null,
+            }));
+        }
+        for child in &self.children {
+            *line += 1;
+            child.emit(xml, line)?;
+        }
+        if !self.children.is_empty() {
+            *line += 1;
+        }
+        xml.xml_body.push(XmlEvent::EndElement(XmlElement {
+            metadata: metadata(*line),
+            ..element
+        }));
+        Ok(())
+    }
+}
+
+impl NetworkSecurityConfigBuilder {
+    #[must_use]
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Sets the `cleartextTrafficPermitted` attribute of the base configuration.
+    #[must_use]
+    pub const fn with_cleartext_traffic_permitted(mut self, permitted: bool) -> Self {
+        self.cleartext_traffic_permitted = Some(permitted);
+        self
+    }
+
+    /// Adds a trust anchor to the base configuration.
+    #[must_use]
+    pub fn with_trust_anchor(mut self, anchor: TrustAnchor) -> Self {
+        self.trust_anchors.push(anchor);
+        self
+    }
+
+    #[must_use]
+    pub fn with_domain_config(mut self, domain_config: DomainConfig) -> Self {
+        self.domain_configs.push(domain_config);
+        self
+    }
+
+    /// Assembles the network security config document. As the nodes of such
+    /// a document are not namespaced, the emitted resource map is empty.
+    pub fn build(&self) -> ResourcesResult<NetworkSecurityConfig> {
+        let mut root = Node::new("network-security-config");
+
+        if self.cleartext_traffic_permitted.is_some() || !self.trust_anchors.is_empty() {
+            let mut base = Node::new("base-config");
+            if let Some(permitted) = self.cleartext_traffic_permitted {
+                base.attrs
+                    .push(("cleartextTrafficPermitted", Attr::Bool(permitted)));
+            }
+            if !self.trust_anchors.is_empty() {
+                base.children.push(Node::trust_anchors(&self.trust_anchors));
+            }
+            root.children.push(base);
+        }
+
+        for domain_config in &self.domain_configs {
+            if domain_config.domains.is_empty() {
+                return Err(ResourcesError::Structure(
+                    "domain-config without domain".to_string(),
+                ));
+            }
+            let mut node = Node::new("domain-config");
+            if let Some(permitted) = domain_config.cleartext_traffic_permitted {
+                node.attrs
+                    .push(("cleartextTrafficPermitted", Attr::Bool(permitted)));
+            }
+            for (name, include_subdomains) in &domain_config.domains {
+                let mut domain = Node::new("domain");
+                domain
+                    .attrs
+                    .push(("includeSubdomains", Attr::Bool(*include_subdomains)));
+                domain.text = Some(name.clone());
+                node.children.push(domain);
+            }
+            if let Some(pin_set) = &domain_config.pin_set {
+                let mut pins = Node::new("pin-set");
+                if let Some(expiration) = &pin_set.expiration {
+                    pins.attrs
+                        .push(("expiration", Attr::String(expiration.clone())));
+                }
+                for digest in &pin_set.pins {
+                    let mut pin = Node::new("pin");
+                    pin.attrs
+                        .push(("digest", Attr::String("SHA-256".to_string())));
+                    pin.text = Some(digest.clone());
+                    pins.children.push(pin);
+                }
+                node.children.push(pins);
+            }
+            if !domain_config.trust_anchors.is_empty() {
+                node.children
+                    .push(Node::trust_anchors(&domain_config.trust_anchors));
+            }
+            root.children.push(node);
+        }
+
+        let mut xml = Xml::default();
+        xml.string_pool.utf8 = true;
+        xml.xml_resource_map = Some(XmlResourceMap {
+            resource_ids: Vec::new(),
+        });
+        // line 1 holds the xml declaration
+        root.emit(&mut xml, &mut 2)?;
+        Ok(NetworkSecurityConfig { xml })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn builder_round_trip() {
+        let nsc = NetworkSecurityConfigBuilder::new()
+            .with_trust_anchor(TrustAnchor::new(CertificatesSource::System))
+            .with_domain_config(
+                DomainConfig::new("example.com", true)
+                    .with_cleartext_traffic_permitted(false)
+                    .with_pin_set(
+                        PinSet::new()
+                            .with_expiration("2030-01-01")
+                            .with_pin("7HIpactkIAq2Y49orFOOQKurWxmmSFZhBCoQYcRhJ3Y="),
+                    ),
+            )
+            .build()
+            .unwrap();
+
+        let bytes = write(&nsc).unwrap();
+        let parsed = parse(&bytes).unwrap();
+        assert_eq!(write(&parsed).unwrap(), bytes);
+        assert_eq!(
+            parsed.to_string(),
+            "<?xml version=\"1.0\" encoding=\"utf-8\" standalone=\"no\"?>\
+             <network-security-config>\
+             <base-config>\
+             <trust-anchors><certificates src=\"system\"/></trust-anchors>\
+             </base-config>\
+             <domain-config cleartextTrafficPermitted=\"false\">\
+             <domain includeSubdomains=\"true\">example.com</domain>\
+             <pin-set expiration=\"2030-01-01\">\
+             <pin digest=\"SHA-256\">7HIpactkIAq2Y49orFOOQKurWxmmSFZhBCoQYcRhJ3Y=</pin>\
+             </pin-set>\
+             </domain-config>\
+             </network-security-config>"
+        );
+
+        let empty_domain = DomainConfig {
+            domains: Vec::new(),
+            ..DomainConfig::new("example.com", false)
+        };
+        assert!(NetworkSecurityConfigBuilder::new()
+            .with_domain_config(empty_domain)
+            .build()
+            .is_err());
+    }
+
+    #[test]
+    fn audit_pin_sets() {
+        let pin = "7HIpactkIAq2Y49orFOOQKurWxmmSFZhBCoQYcRhJ3Y=";
+        let mut nsc = NetworkSecurityConfigBuilder::new()
+            .with_domain_config(
+                DomainConfig::new("expired.example.com", true)
+                    .with_pin_set(PinSet::new().with_expiration("2020-01-01")),
+            )
+            .with_domain_config(
+                DomainConfig::new("weak.example.com", false)
+                    .with_cleartext_traffic_permitted(true)
+                    .with_pin_set(PinSet::new().with_expiration("2030-01-01").with_pin(pin)),
+            )
+            .build()
+            .unwrap();
+        let now = NaiveDate::from_ymd_opt(2026, 10, 16).unwrap();
+        assert_eq!(
+            nsc.audit(now).unwrap(),
+            [
+                NscFinding::ExpiredPinSet {
+                    domain: "expired.example.com".to_string(),
+                    expiration: NaiveDate::from_ymd_opt(2020, 1, 1).unwrap(),
+                },
+                NscFinding::CleartextPermitted {
+                    domain: "weak.example.com".to_string(),
+                },
+            ]
+        );
+
+        // the builder only emits SHA-256 pins
+        let edited = xpath::ContextMut::new(&mut nsc.xml)
+            .select(xpath::Select::Root(
+                &Regex::new("^network-security-config$").unwrap(),
+            ))
+            .unwrap()
+            .select(xpath::Select::Root(&Regex::new("^domain-config$").unwrap()))
+            .unwrap()
+            .select(xpath::Select::Root(&Regex::new("^pin-set$").unwrap()))
+            .unwrap()
+            .select(xpath::Select::Root(&Regex::new("^pin$").unwrap()))
+            .unwrap()
+            .select(xpath::Select::Attr("digest"))
+            .unwrap()
+            .map_string_attributes(|_| Some("SHA-1".to_string()))
+            .unwrap();
+        assert_eq!(edited, 1);
+        let nsc = parse(&write(&nsc).unwrap()).unwrap();
+        assert_eq!(
+            nsc.audit(now).unwrap()[1..],
+            [
+                NscFinding::WeakDigest {
+                    domain: "weak.example.com".to_string(),
+                    alg: "SHA-1".to_string(),
+                },
+                NscFinding::CleartextPermitted {
+                    domain: "weak.example.com".to_string(),
+                },
+            ]
+        );
+    }
+}
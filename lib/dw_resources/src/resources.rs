@@ -1,13 +1,28 @@
 //! The Android resources data structures and accessors.
 
-use crate::errors::ResourcesResult;
+use crate::errors::{ResourcesError, ResourcesResult};
 use crate::parsers::parse_resources;
 use crate::strings::StringPool;
-use crate::tables::{Config, TablePackage, TablePackagePool, TableTypeEntry};
+use crate::tables::{
+    TableEntry, TablePackage, TablePackagePool, TableTypeEntry, TableTypeEntryContent,
+    TableTypeEntryPoolIndex,
+};
+use crate::values::Value;
+use crate::writers::write_resources;
 use std::collections::{BTreeMap, BTreeSet};
 use std::fmt;
 use std::sync::Arc;
 
+pub use crate::tables::Config;
+
+/// The package id of application resources, as opposed to framework
+/// resources which live in package `0x01`.
+const APP_PACKAGE_ID: u8 = 0x7f;
+
+/// The maximum number of references followed when resolving a resource,
+/// above which references are considered to loop.
+const MAX_REFERENCE_DEPTH: usize = 32;
+
 #[derive(Debug)]
 pub(crate) struct ResourcesTable {
     pub(crate) string_pool: StringPool,
@@ -21,8 +36,8 @@ pub fn parse(input: &[u8]) -> ResourcesResult<Resources> {
     parse_resources(input)
 }
 
-pub fn write(_resources: &Resources) -> ResourcesResult<Vec<u8>> {
-    todo!("write_resource(&resources.res)")
+pub fn write(resources: &Resources) -> ResourcesResult<Vec<u8>> {
+    write_resources(resources)
 }
 
 impl Resources {
@@ -40,6 +55,322 @@ impl Resources {
             })
     }
 
+    /// Returns the strings of the global string pool, which holds the
+    /// string values of all the resources.
+    ///
+    /// # Errors
+    ///
+    /// This function returns an error if a string is not properly encoded.
+    pub fn strings(&self) -> ResourcesResult<Vec<String>> {
+        self.0
+            .string_pool
+            .strings
+            .iter()
+            .map(|string| string.string())
+            .collect()
+    }
+
+    /// Returns the symbolic name (`type/entry`) of the resource with the
+    /// given id. Names of resources that do not belong to the application
+    /// package are prefixed by their package name, as in `android:string/ok`.
+    #[must_use]
+    pub fn name_of(&self, id: u32) -> Option<String> {
+        let package = self.package_lookup(id)?;
+        let type_id = ((id >> 16) & 0xff) as u8;
+        let entry_id = (id & 0xffff) as u16;
+
+        let typ = package
+            .type_strings
+            .as_ref()?
+            .strings
+            .get(usize::from(type_id).checked_sub(1)?)?
+            .string()
+            .ok()?;
+        let key = package
+            .type_pool
+            .resolve(type_id)?
+            .iter()
+            .find_map(|typ| typ.entry_pool.resolve(entry_id))?
+            .entry
+            .key;
+        let key = package
+            .key_strings
+            .as_ref()?
+            .strings
+            .get(key as usize)?
+            .string()
+            .ok()?;
+
+        if package.id == APP_PACKAGE_ID {
+            Some(format!("{typ}/{key}"))
+        } else {
+            Some(format!("{}:{typ}/{key}", package.name))
+        }
+    }
+
+    /// Returns the id of the resource with the given symbolic name, in the
+    /// form returned by [`Resources::name_of`]. A leading `@` is accepted.
+    #[must_use]
+    pub fn id_of(&self, symbolic: &str) -> Option<u32> {
+        let symbolic = symbolic.strip_prefix('@').unwrap_or(symbolic);
+        let (package, name) = match symbolic.split_once(':') {
+            Some((package_name, name)) => (
+                self.0
+                    .package_pool
+                    .packages()
+                    .iter()
+                    .find(|package| package.name == package_name)
+                    .map(Arc::clone)?,
+                name,
+            ),
+            None => (self.0.package_pool.resolve(APP_PACKAGE_ID)?, symbolic),
+        };
+        let (typ, key) = name.split_once('/')?;
+
+        let type_index = package
+            .type_strings
+            .as_ref()?
+            .strings
+            .iter()
+            .position(|s| s.string().is_ok_and(|s| s == typ))?;
+        let type_id = u8::try_from(type_index + 1).ok()?;
+        let key_index = package
+            .key_strings
+            .as_ref()?
+            .strings
+            .iter()
+            .position(|s| s.string().is_ok_and(|s| s == key))?;
+
+        package
+            .type_pool
+            .resolve(type_id)?
+            .iter()
+            .flat_map(|typ| typ.entry_pool.entries())
+            .find(|(_, entry)| entry.entry.key as usize == key_index)
+            .map(|(entry_id, _)| {
+                (u32::from(package.id) << 24) | (u32::from(type_id) << 16) | u32::from(*entry_id)
+            })
+    }
+
+    /// Resolves the resource with the given id to a concrete value, following
+    /// references to other resources.
+    ///
+    /// When a configuration is given, the entry selected by
+    /// [`best_match`](Self::best_match) is used. Otherwise, the entry of the default configuration is
+    /// selected, or any entry of the resource if all of them hold the same
+    /// value.
+    /// Attributes are returned as is, as they depend on the current theme.
+    pub fn resolve(&self, id: u32, config: Option<&Config>) -> ResourcesResult<Value> {
+        let mut reference = id;
+        for _ in 0..MAX_REFERENCE_DEPTH {
+            let entries = self.lookup(reference).ok_or_else(|| {
+                ResourcesError::CannotResolveWithoutResources(format!("resource @{reference:#x}"))
+            })?;
+            let entry = match config {
+                Some(config) => Self::select(&entries, config),
+                None if entries.len() > 1 => match entries.get(&Config::default()) {
+                    Some(entry) => Some(entry),
+                    None => {
+                        let entry = self.common_entry(&entries)?;
+                        if entry.is_none() {
+                            return Err(ResourcesError::TooComplexResource(format!(
+                                "resource @{reference:#x} has several configurations but no default one",
+                            )));
+                        }
+                        entry
+                    }
+                },
+                None => entries.values().next(),
+            }
+            .ok_or_else(|| ResourcesError::ResNotFound(format!("resource @{reference:#x}")))?;
+
+            match entry.content {
+                TableTypeEntryContent::EntryValue(Value::Reference(next)) => reference = next,
+                TableTypeEntryContent::EntryValue(value) => return Ok(value),
+                TableTypeEntryContent::EntryMap(_) => {
+                    return Err(ResourcesError::TooComplexResource(format!(
+                        "resource @{reference:#x} is an EntryMap",
+                    )));
+                }
+            }
+        }
+        Err(ResourcesError::ReferenceCycle(format!("resource @{id:#x}")))
+    }
+
+    /// Iterates over the values of the resource with the given id, along with
+    /// the configuration each of them is defined for. Complex resources
+    /// (styles, arrays, etc.) are skipped.
+    pub fn iter_configs(&self, id: u32) -> impl Iterator<Item = (Config, Value)> {
+        self.lookup(id)
+            .into_iter()
+            .flatten()
+            .filter_map(|(config, entry)| match entry.content {
+                TableTypeEntryContent::EntryValue(value) => Some((config, value)),
+                TableTypeEntryContent::EntryMap(_) => None,
+            })
+    }
+
+    /// Returns the value of the resource with the given id that Android would
+    /// select for the `requested` configuration, without following
+    /// references. Configurations contradicting the requested one are
+    /// discarded, then the best remaining one is selected following
+    /// Android's qualifiers precedence (see [`Config::is_better_than`]).
+    #[must_use]
+    pub fn best_match(&self, id: u32, requested: &Config) -> Option<Value> {
+        let entries = self.lookup(id)?;
+        match Self::select(&entries, requested)?.content {
+            TableTypeEntryContent::EntryValue(value) => Some(value),
+            TableTypeEntryContent::EntryMap(_) => None,
+        }
+    }
+
+    /// Returns an entry of `entries` if all of them hold the same value,
+    /// strings being compared by content.
+    fn common_entry<'a>(
+        &self,
+        entries: &'a BTreeMap<Config, Arc<TableTypeEntry>>,
+    ) -> ResourcesResult<Option<&'a Arc<TableTypeEntry>>> {
+        let mut values = entries.values();
+        let Some(first) = values.next() else {
+            return Ok(None);
+        };
+        for entry in values {
+            let same = match (&first.content, &entry.content) {
+                (
+                    TableTypeEntryContent::EntryValue(Value::String(a)),
+                    TableTypeEntryContent::EntryValue(Value::String(b)),
+                ) => {
+                    a == b
+                        || self.0.string_pool.get(*a)?.string()?
+                            == self.0.string_pool.get(*b)?.string()?
+                }
+                (TableTypeEntryContent::EntryValue(a), TableTypeEntryContent::EntryValue(b)) => {
+                    a == b
+                }
+                _ => false,
+            };
+            if !same {
+                return Ok(None);
+            }
+        }
+        Ok(Some(first))
+    }
+
+    fn select<'a>(
+        entries: &'a BTreeMap<Config, Arc<TableTypeEntry>>,
+        requested: &Config,
+    ) -> Option<&'a Arc<TableTypeEntry>> {
+        entries
+            .iter()
+            .filter(|(config, _)| config.matches(requested))
+            .reduce(|best, candidate| {
+                if candidate.0.is_better_than(best.0, requested) {
+                    candidate
+                } else {
+                    best
+                }
+            })
+            .map(|(_, entry)| entry)
+    }
+
+    /// Sets the value of the string resource `name` of the given package for
+    /// the given configuration, and returns the id of the resource. A new
+    /// resource is allocated after the existing ones if the package has no
+    /// string resource with that name.
+    ///
+    /// # Errors
+    ///
+    /// This function returns an error if the package does not exist or if
+    /// it has no room left for a new resource.
+    pub fn set_string(
+        &mut self,
+        package_id: u8,
+        name: &str,
+        config: &Config,
+        value: &str,
+    ) -> ResourcesResult<u32> {
+        let (value, _) = self.0.string_pool.get_or_push(value.to_string())?;
+        let package = self.0.package_pool.resolve_mut(package_id)?;
+
+        let type_id = match package.type_id("string") {
+            Some(type_id) => type_id,
+            None => {
+                let (index, _) = package
+                    .type_strings
+                    .get_or_insert_with(StringPool::new)
+                    .get_or_push("string".to_string())?;
+                u8::try_from(index.index() + 1).map_err(|_| {
+                    ResourcesError::Internal("no type id left for strings".to_string())
+                })?
+            }
+        };
+        let (key, _) = package
+            .key_strings
+            .get_or_insert_with(StringPool::new)
+            .get_or_push(name.to_string())?;
+        let key = key.index() as u32;
+
+        let existing = package
+            .type_pool
+            .resolve(type_id)
+            .unwrap_or_default()
+            .iter()
+            .flat_map(|typ| typ.entry_pool.entries().values())
+            .find(|entry| entry.entry.key == key)
+            .map(|entry| (entry.self_ref, entry.entry.flags));
+        let (self_ref, flags) = match existing {
+            Some(existing) => existing,
+            None => {
+                let entry_id =
+                    u16::try_from(package.config_masks(type_id).len()).map_err(|_| {
+                        ResourcesError::Internal("no entry id left for strings".to_string())
+                    })?;
+                (TableTypeEntryPoolIndex::new(entry_id), 0)
+            }
+        };
+        let entry_id = self_ref.index();
+
+        package
+            .type_pool
+            .get_or_push(type_id, config)?
+            .entry_pool
+            .insert(TableTypeEntry {
+                self_ref,
+                entry: TableEntry { flags, key },
+                content: TableTypeEntryContent::EntryValue(Value::String(value)),
+            });
+        package.update_config_mask(type_id, entry_id);
+
+        Ok((u32::from(package_id) << 24) | (u32::from(type_id) << 16) | u32::from(entry_id))
+    }
+
+    /// Removes the resource with the given id from all configurations, and
+    /// returns whether it existed. The entry is left empty so that the ids
+    /// of the following resources are unchanged.
+    ///
+    /// # Errors
+    ///
+    /// This function returns an error if the package of the resource does
+    /// not exist.
+    pub fn remove_entry(&mut self, id: u32) -> ResourcesResult<bool> {
+        let package_id = (id >> 24) as u8;
+        let type_id = ((id >> 16) & 0xff) as u8;
+        let entry_id = (id & 0xffff) as u16;
+        let package = self.0.package_pool.resolve_mut(package_id)?;
+
+        // the type spec is completed before removing the entry, so that it
+        // still covers the following entries of the type
+        let _spec = package.type_spec_mut(type_id);
+        let mut removed = false;
+        for typ in package.type_pool.resolve_mut(type_id)? {
+            removed |= typ.entry_pool.remove(entry_id);
+        }
+        if removed {
+            package.update_config_mask(type_id, entry_id);
+        }
+        Ok(removed)
+    }
+
     pub(crate) fn package_lookup(&self, reference: u32) -> Option<Arc<TablePackage>> {
         let package_id = (reference >> 24) as u8;
         self.0.package_pool.resolve(package_id)
@@ -74,3 +405,385 @@ impl fmt::Display for Resources {
         Ok(())
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::strings::StringPoolIndex;
+    use crate::tables::{
+        TableEntry, TablePackagePoolIndex, TableType, TableTypeEntryContent, TableTypeEntryPool,
+        TableTypeEntryPoolIndex, TableTypePool, TableTypePoolIndex,
+    };
+    use crate::values::Value;
+
+    fn package(
+        index: usize,
+        id: u8,
+        name: &str,
+        types: &[&str],
+        entries: &[(u8, u16, &str, Value)],
+    ) -> Arc<TablePackage> {
+        let entries: Vec<_> = entries
+            .iter()
+            .map(|(type_id, entry_id, key, value)| {
+                (*type_id, Config::default(), *entry_id, *key, *value)
+            })
+            .collect();
+        package_with_configs(index, id, name, types, &entries)
+    }
+
+    fn package_with_configs(
+        index: usize,
+        id: u8,
+        name: &str,
+        types: &[&str],
+        entries: &[(u8, Config, u16, &str, Value)],
+    ) -> Arc<TablePackage> {
+        let mut type_strings = StringPool::new();
+        for typ in types {
+            type_strings.get_or_push(typ.to_string()).unwrap();
+        }
+        let mut key_strings = StringPool::new();
+        let mut table_types: Vec<Arc<TableType>> = Vec::new();
+        for (type_id, config, entry_id, key, value) in entries {
+            let (key, _) = key_strings.get_or_push(key.to_string()).unwrap();
+            let entry = Arc::new(TableTypeEntry {
+                self_ref: TableTypeEntryPoolIndex::new(*entry_id),
+                entry: TableEntry {
+                    flags: 0,
+                    key: key.index() as u32,
+                },
+                content: TableTypeEntryContent::EntryValue(*value),
+            });
+            let mut type_entries = table_types
+                .iter()
+                .position(|typ| typ.id == *type_id && typ.config == *config)
+                .map(|i| table_types.remove(i).entry_pool.entries().clone())
+                .unwrap_or_default();
+            type_entries.insert(*entry_id, entry);
+            table_types.push(Arc::new(TableType {
+                self_ref: TableTypePoolIndex::new(table_types.len()),
+                id: *type_id,
+                config: config.clone(),
+                entry_pool: TableTypeEntryPool::new(type_entries),
+            }));
+        }
+        Arc::new(TablePackage {
+            self_ref: TablePackagePoolIndex::new(index),
+            id,
+            name: name.to_string(),
+            last_public_type: 0,
+            last_public_key: 0,
+            type_strings: Some(type_strings),
+            key_strings: Some(key_strings),
+            string_pools: Vec::new(),
+            table_type_specs: Vec::new(),
+            type_pool: TableTypePool::new(table_types),
+            table_libraries: Vec::new(),
+            table_overlayables: Vec::new(),
+            table_overlayable_policies: Vec::new(),
+            table_staged_aliases: Vec::new(),
+        })
+    }
+
+    fn resources() -> Resources {
+        let mut string_pool = StringPool::new();
+        let (app_name, _) = string_pool.get_or_push("My App".to_string()).unwrap();
+        let framework = package(
+            0,
+            0x01,
+            "android",
+            &["string"],
+            &[(1, 0, "ok", Value::Null)],
+        );
+        let app = package(
+            1,
+            0x7f,
+            "com.example.app",
+            &["attr", "string"],
+            &[
+                (2, 0, "app_name", Value::String(app_name)),
+                (2, 1, "title", Value::Reference(0x7f02_0000)),
+                (2, 2, "loop", Value::Reference(0x7f02_0002)),
+                (1, 0, "color", Value::Null),
+            ],
+        );
+        Resources(ResourcesTable {
+            string_pool,
+            package_pool: TablePackagePool::new(vec![framework, app]).unwrap(),
+        })
+    }
+
+    #[test]
+    fn symbolic_names() {
+        let resources = resources();
+
+        assert_eq!(
+            resources.name_of(0x7f02_0001).as_deref(),
+            Some("string/title")
+        );
+        assert_eq!(
+            resources.name_of(0x7f01_0000).as_deref(),
+            Some("attr/color")
+        );
+        assert_eq!(
+            resources.name_of(0x0101_0000).as_deref(),
+            Some("android:string/ok")
+        );
+        assert_eq!(resources.name_of(0x7f02_0003), None);
+        assert_eq!(resources.name_of(0x0201_0000), None);
+
+        assert_eq!(resources.id_of("@string/app_name"), Some(0x7f02_0000));
+        assert_eq!(resources.id_of("android:string/ok"), Some(0x0101_0000));
+        assert_eq!(resources.id_of("string/ok"), None);
+
+        for id in [0x7f02_0000, 0x7f02_0001, 0x7f01_0000, 0x0101_0000] {
+            let name = resources.name_of(id).unwrap();
+            assert_eq!(resources.id_of(&name), Some(id));
+        }
+    }
+
+    #[test]
+    fn resolve_references() {
+        let resources = resources();
+        let literal = |value| match value {
+            Value::String(index) => resources.0.string_pool.get(index).unwrap().to_string(),
+            other => panic!("unexpected value {other:?}"),
+        };
+
+        let app_name = resources.id_of("@string/app_name").unwrap();
+        assert_eq!(
+            literal(resources.resolve(app_name, None).unwrap()),
+            "My App"
+        );
+        assert_eq!(
+            literal(
+                resources
+                    .resolve(0x7f02_0001, Some(&Config::default()))
+                    .unwrap()
+            ),
+            "My App"
+        );
+        assert!(matches!(
+            resources.resolve(0x7f02_0002, None),
+            Err(ResourcesError::ReferenceCycle(_))
+        ));
+        assert!(matches!(
+            resources.resolve(0x7f02_0003, None),
+            Err(ResourcesError::ResNotFound(_))
+        ));
+    }
+
+    #[test]
+    fn resolve_without_default_config() {
+        let mut string_pool = StringPool::new();
+        let (index, utf) = string_pool.get_or_push("Hello".to_string()).unwrap();
+        let hello = Value::String(index);
+        // pools may hold duplicated strings
+        string_pool.strings.push(utf);
+        let hello_again = Value::String(StringPoolIndex::new(string_pool.strings.len() - 1));
+        let bonjour = Value::String(string_pool.get_or_push("Bonjour".to_string()).unwrap().0);
+        let (en, fr) = (
+            Config::default().with_locale("en", None),
+            Config::default().with_locale("fr", None),
+        );
+        let app = package_with_configs(
+            0,
+            0x7f,
+            "com.example.app",
+            &["string"],
+            &[
+                (1, en.clone(), 0, "same", hello),
+                (1, en, 1, "translated", hello),
+                (1, fr.clone(), 0, "same", hello_again),
+                (1, fr, 1, "translated", bonjour),
+            ],
+        );
+        let resources = Resources(ResourcesTable {
+            string_pool,
+            package_pool: TablePackagePool::new(vec![app]).unwrap(),
+        });
+
+        let Value::String(index) = resources.resolve(0x7f01_0000, None).unwrap() else {
+            panic!("unexpected value");
+        };
+        assert_eq!(
+            resources.0.string_pool.get(index).unwrap().to_string(),
+            "Hello"
+        );
+        assert!(matches!(
+            resources.resolve(0x7f01_0001, None),
+            Err(ResourcesError::TooComplexResource(_))
+        ));
+    }
+
+    #[test]
+    fn best_match_configs() {
+        let mut string_pool = StringPool::new();
+        let mut string = |s: &str| Value::String(string_pool.get_or_push(s.to_string()).unwrap().0);
+        let (default, french, hdpi) = (string("Hello"), string("Bonjour"), string("Hello HD"));
+        let app = package_with_configs(
+            0,
+            0x7f,
+            "com.example.app",
+            &["string"],
+            &[
+                (1, Config::default(), 0, "hello", default),
+                (
+                    1,
+                    Config::default().with_locale("fr", None),
+                    0,
+                    "hello",
+                    french,
+                ),
+                (1, Config::default().with_density(240), 0, "hello", hdpi),
+            ],
+        );
+        let resources = Resources(ResourcesTable {
+            string_pool,
+            package_pool: TablePackagePool::new(vec![app]).unwrap(),
+        });
+
+        let configs: Vec<_> = resources.iter_configs(0x7f01_0000).collect();
+        assert_eq!(configs.len(), 3);
+        assert!(configs.iter().any(|(config, _)| config.is_default()));
+        assert!(configs
+            .iter()
+            .any(|(config, _)| config.locale_language().as_deref() == Some("fr")));
+        assert!(configs
+            .iter()
+            .any(|(config, _)| config.density() == Some(240)));
+
+        let mdpi = Config::default().with_density(160);
+        let fr_mdpi = mdpi.clone().with_locale("fr", None);
+        let de_mdpi = mdpi.clone().with_locale("de", None);
+        let de_xhdpi = Config::default().with_locale("de", None).with_density(320);
+        let best_match = |id, config| {
+            resources.best_match(id, config).map(|value| match value {
+                Value::String(index) => resources.0.string_pool.get(index).unwrap().to_string(),
+                other => panic!("unexpected value {other:?}"),
+            })
+        };
+        assert_eq!(
+            best_match(0x7f01_0000, &fr_mdpi).as_deref(),
+            Some("Bonjour")
+        );
+        assert_eq!(best_match(0x7f01_0000, &de_mdpi).as_deref(), Some("Hello"));
+        assert_eq!(
+            best_match(0x7f01_0000, &de_xhdpi).as_deref(),
+            Some("Hello HD")
+        );
+        assert_eq!(best_match(0x7f01_0001, &fr_mdpi), None);
+    }
+
+    #[test]
+    fn best_match_screen_configs() {
+        let mut string_pool = StringPool::new();
+        let mut string = |s: &str| Value::String(string_pool.get_or_push(s.to_string()).unwrap().0);
+        let wvga = Config::default().with_screen_size(480, 800);
+        let hd = Config::default().with_screen_size(720, 1280);
+        let sw600 = Config {
+            screen_config_smallest_width_dp: Some(600),
+            ..Config::default()
+        };
+        // large screen layout size, any layout direction
+        let large = Config {
+            screen_config_layout: Some(0x03),
+            ..Config::default()
+        };
+        let app = package_with_configs(
+            0,
+            0x7f,
+            "com.example.app",
+            &["string"],
+            &[
+                (1, Config::default(), 0, "screen", string("any")),
+                (1, wvga.clone(), 0, "screen", string("wvga")),
+                (1, hd.clone(), 0, "screen", string("hd")),
+                (1, sw600, 0, "screen", string("sw600")),
+                (1, large, 0, "screen", string("large")),
+            ],
+        );
+        let resources = Resources(ResourcesTable {
+            string_pool,
+            package_pool: TablePackagePool::new(vec![app]).unwrap(),
+        });
+        let best_match = |config: &Config| {
+            resources
+                .best_match(0x7f01_0000, config)
+                .map(|value| match value {
+                    Value::String(index) => resources.0.string_pool.get(index).unwrap().to_string(),
+                    other => panic!("unexpected value {other:?}"),
+                })
+        };
+
+        let small = Config::default().with_screen_size(320, 480);
+        assert_eq!(best_match(&small).as_deref(), Some("any"));
+        let medium = Config::default().with_screen_size(600, 1000);
+        assert_eq!(best_match(&medium).as_deref(), Some("wvga"));
+        let phone = Config::default().with_screen_size(720, 1280);
+        assert_eq!(best_match(&phone).as_deref(), Some("hd"));
+        assert!(hd.is_better_than(&wvga, &phone));
+        assert!(!wvga.is_better_than(&hd, &phone));
+
+        let tablet = Config {
+            screen_config_smallest_width_dp: Some(720),
+            ..Config::default().with_screen_size(1200, 1920)
+        };
+        assert_eq!(best_match(&tablet).as_deref(), Some("sw600"));
+        // left-to-right layout direction, extra large screen layout size
+        let xlarge = Config {
+            screen_config_layout: Some(0x44),
+            ..Config::default().with_screen_size(1200, 1920)
+        };
+        assert_eq!(best_match(&xlarge).as_deref(), Some("large"));
+    }
+
+    #[test]
+    fn add_and_remove_entries() {
+        let mut resources = resources();
+        let french = Config::default().with_locale("fr", None);
+        let greeting = resources
+            .set_string(0x7f, "greeting", &Config::default(), "Hello")
+            .unwrap();
+        assert_eq!(greeting, 0x7f02_0003);
+        assert_eq!(
+            resources
+                .set_string(0x7f, "greeting", &french, "Bonjour")
+                .unwrap(),
+            greeting
+        );
+        assert!(resources.set_string(0x02, "greeting", &french, "").is_err());
+
+        let literal = |resources: &Resources, value| match value {
+            Value::String(index) => resources.0.string_pool.get(index).unwrap().to_string(),
+            other => panic!("unexpected value {other:?}"),
+        };
+        let resources = parse(&write(&resources).unwrap()).unwrap();
+        assert_eq!(resources.id_of("string/greeting"), Some(greeting));
+        assert_eq!(
+            literal(&resources, resources.resolve(greeting, None).unwrap()),
+            "Hello"
+        );
+        assert_eq!(
+            literal(&resources, resources.best_match(greeting, &french).unwrap()),
+            "Bonjour"
+        );
+        assert_eq!(
+            literal(&resources, resources.resolve(0x7f02_0001, None).unwrap()),
+            "My App"
+        );
+
+        let mut resources = resources;
+        assert!(resources.remove_entry(0x7f02_0001).unwrap());
+        assert!(!resources.remove_entry(0x7f02_0001).unwrap());
+        let resources = parse(&write(&resources).unwrap()).unwrap();
+        assert_eq!(resources.name_of(0x7f02_0001), None);
+        assert_eq!(resources.id_of("string/loop"), Some(0x7f02_0002));
+        assert_eq!(resources.id_of("string/greeting"), Some(greeting));
+        assert_eq!(
+            literal(&resources, resources.best_match(greeting, &french).unwrap()),
+            "Bonjour"
+        );
+    }
+}
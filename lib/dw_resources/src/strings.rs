@@ -101,6 +101,29 @@ impl StringPool {
             Ok((index, utf_string))
         }
     }
+
+    /// Inserts a new string at `position`, without looking for an existing
+    /// equal string. Following strings (and their styles) are shifted by
+    /// one: references to them must be updated by the caller.
+    pub(crate) fn insert(&mut self, position: usize, string: &str) -> StringPoolIndex {
+        self.strings.insert(
+            position,
+            Arc::new(UtfString::new(string, StringPoolIndex(position), self.utf8)),
+        );
+        for (i, utf) in self.strings.iter_mut().enumerate().skip(position + 1) {
+            *utf = Arc::new(utf.with_self_ref(StringPoolIndex(i)));
+        }
+        if position < self.styles.len() {
+            self.styles.insert(position, Style { spans: Vec::new() });
+        }
+        for span in self.styles.iter_mut().flat_map(|style| &mut style.spans) {
+            if span.name as usize >= position {
+                span.name += 1;
+            }
+        }
+        self.sorted = false;
+        StringPoolIndex(position)
+    }
 }
 
 #[derive(Debug, PartialEq, Eq)]
@@ -117,6 +140,35 @@ pub enum UtfString {
 }
 
 impl UtfString {
+    fn new(string: &str, self_ref: StringPoolIndex, utf8: bool) -> Self {
+        if utf8 {
+            Self::Utf8 {
+                self_ref,
+                raw: string.as_bytes().to_vec(),
+                size: string.chars().count(),
+            }
+        } else {
+            Self::Utf16 {
+                self_ref,
+                raw: string.encode_utf16().collect(),
+            }
+        }
+    }
+
+    fn with_self_ref(&self, self_ref: StringPoolIndex) -> Self {
+        match self {
+            Self::Utf8 { raw, size, .. } => Self::Utf8 {
+                self_ref,
+                raw: raw.clone(),
+                size: *size,
+            },
+            Self::Utf16 { raw, .. } => Self::Utf16 {
+                self_ref,
+                raw: raw.clone(),
+            },
+        }
+    }
+
     pub fn string(&self) -> ResourcesResult<String> {
         match self {
             Self::Utf8 { raw, .. } => {
@@ -55,6 +55,15 @@ impl TablePackagePool {
             .ok_or_else(|| ResourcesError::ResNotFound("TablePackage by raw index".to_string()))
             .map(Arc::clone)
     }
+
+    pub(crate) fn resolve_mut(&mut self, package_id: u8) -> ResourcesResult<&mut TablePackage> {
+        let idx = self
+            .index
+            .get(&package_id)
+            .ok_or_else(|| ResourcesError::ResNotFound(format!("package {package_id:#x}")))?;
+        Arc::get_mut(&mut self.packages[*idx])
+            .ok_or_else(|| ResourcesError::Internal("shared TablePackage".to_string()))
+    }
 }
 
 #[derive(Debug, Clone, Copy)]
@@ -112,6 +121,52 @@ impl TableTypePool {
             .ok_or_else(|| ResourcesError::ResNotFound("TableType by raw index".to_string()))
             .map(Arc::clone)
     }
+
+    /// Returns the types with the given id, for all their configurations.
+    pub(crate) fn resolve_mut(
+        &mut self,
+        type_id: u8,
+    ) -> ResourcesResult<impl Iterator<Item = &mut TableType>> {
+        let indexes = self.index.get(&type_id).cloned().unwrap_or_default();
+        self.types
+            .iter_mut()
+            .enumerate()
+            .filter(|(idx, _)| indexes.contains(idx))
+            .map(|(_, typ)| {
+                Arc::get_mut(typ)
+                    .ok_or_else(|| ResourcesError::Internal("shared TableType".to_string()))
+            })
+            .collect::<ResourcesResult<Vec<_>>>()
+            .map(Vec::into_iter)
+    }
+
+    /// Returns the type with the given id and configuration, which is added
+    /// (without any entry) if it does not exist.
+    pub(crate) fn get_or_push(
+        &mut self,
+        type_id: u8,
+        config: &Config,
+    ) -> ResourcesResult<&mut TableType> {
+        let existing = self.index.get(&type_id).and_then(|indexes| {
+            indexes
+                .iter()
+                .copied()
+                .find(|idx| self.types[*idx].config == *config)
+        });
+        let idx = existing.unwrap_or_else(|| {
+            let idx = self.types.len();
+            self.types.push(Arc::new(TableType {
+                self_ref: TableTypePoolIndex::new(idx),
+                id: type_id,
+                config: config.clone(),
+                entry_pool: TableTypeEntryPool::new(BTreeMap::new()),
+            }));
+            let _opt = self.index.entry(type_id).or_default().insert(idx);
+            idx
+        });
+        Arc::get_mut(&mut self.types[idx])
+            .ok_or_else(|| ResourcesError::Internal("shared TableType".to_string()))
+    }
 }
 
 #[derive(Debug)]
@@ -133,6 +188,83 @@ pub(crate) struct TablePackage {
 }
 
 impl TablePackage {
+    /// Returns the id of the type with the given name, such as `string`.
+    pub(crate) fn type_id(&self, name: &str) -> Option<u8> {
+        let index = self
+            .type_strings
+            .as_ref()?
+            .strings
+            .iter()
+            .position(|s| s.string().is_ok_and(|s| s == name))?;
+        u8::try_from(index + 1).ok()
+    }
+
+    /// Returns the configuration masks of the entries of a type: the ones of
+    /// its type spec, completed with masks computed from the configurations
+    /// of the entries that the type spec does not cover.
+    pub(crate) fn config_masks(&self, type_id: u8) -> Vec<u32> {
+        let mut masks = self
+            .table_type_specs
+            .iter()
+            .find(|spec| spec.id == type_id)
+            .map(|spec| spec.config_mask.clone())
+            .unwrap_or_default();
+        let types = self.type_pool.resolve(type_id).unwrap_or_default();
+        let entry_count = types
+            .iter()
+            .filter_map(|typ| typ.entry_pool.entries().keys().next_back())
+            .map(|entry_id| usize::from(*entry_id) + 1)
+            .max()
+            .unwrap_or(0);
+        for entry_id in masks.len()..entry_count {
+            masks.push(Self::entry_config_mask(&types, entry_id as u16));
+        }
+        masks
+    }
+
+    /// Returns the type spec of a type, which is added if the package has
+    /// none, and extended to cover all the entries of the type.
+    pub(crate) fn type_spec_mut(&mut self, type_id: u8) -> &mut TableTypeSpec {
+        let config_mask = self.config_masks(type_id);
+        let idx = match self
+            .table_type_specs
+            .iter()
+            .position(|spec| spec.id == type_id)
+        {
+            Some(idx) => idx,
+            None => {
+                self.table_type_specs.push(TableTypeSpec {
+                    id: type_id,
+                    config_mask: Vec::new(),
+                });
+                self.table_type_specs.len() - 1
+            }
+        };
+        let spec = &mut self.table_type_specs[idx];
+        spec.config_mask = config_mask;
+        spec
+    }
+
+    /// Recomputes the configuration mask of an entry after its values have
+    /// been changed, keeping its public flag.
+    pub(crate) fn update_config_mask(&mut self, type_id: u8, entry_id: u16) {
+        let types = self.type_pool.resolve(type_id).unwrap_or_default();
+        let mask = Self::entry_config_mask(&types, entry_id);
+        let spec = self.type_spec_mut(type_id);
+        let entry_id = usize::from(entry_id);
+        if spec.config_mask.len() <= entry_id {
+            spec.config_mask.resize(entry_id + 1, 0);
+        }
+        spec.config_mask[entry_id] = (spec.config_mask[entry_id] & SPEC_PUBLIC) | mask;
+    }
+
+    fn entry_config_mask(types: &[Arc<TableType>], entry_id: u16) -> u32 {
+        types
+            .iter()
+            .filter(|typ| typ.entry_pool.entries().contains_key(&entry_id))
+            .fold(0, |mask, typ| mask | typ.config.diff_flags())
+    }
+
     pub fn pretty_print(
         &self,
         f: &mut fmt::Formatter,
@@ -146,6 +278,9 @@ impl TablePackage {
     }
 }
 
+/// Flag of the type spec configuration masks marking public resources.
+pub(crate) const SPEC_PUBLIC: u32 = 0x4000_0000;
+
 #[derive(Debug)]
 pub(crate) struct TableTypeSpec {
     pub(crate) id: u8,
@@ -197,6 +332,10 @@ impl TableTypeEntryPoolIndex {
     pub(crate) const fn new(idx: u16) -> Self {
         Self(idx)
     }
+
+    pub(crate) const fn index(self) -> u16 {
+        self.0
+    }
 }
 
 #[derive(Debug)]
@@ -223,6 +362,14 @@ impl TableTypeEntryPool {
             .ok_or_else(|| ResourcesError::ResNotFound("TableTypeEntry".to_string()))
             .map(Arc::clone)
     }
+
+    pub(crate) fn insert(&mut self, entry: TableTypeEntry) {
+        let _opt = self.entries.insert(entry.self_ref.0, Arc::new(entry));
+    }
+
+    pub(crate) fn remove(&mut self, entry_id: u16) -> bool {
+        self.entries.remove(&entry_id).is_some()
+    }
 }
 
 #[derive(Debug)]
@@ -453,12 +600,130 @@ pub struct Config {
     pub(crate) screen_config_2_pad2: Option<u16>,
 }
 
+/// The density assumed for configurations that do not define one.
+const DENSITY_MEDIUM: u16 = 160;
+/// The density of resources that can be scaled to any density.
+const DENSITY_ANY: u16 = 0xfffe;
+
 impl Config {
     pub fn is_default(&self) -> bool {
         self == &Self::default()
     }
 
-    fn locale_language(&self) -> Option<String> {
+    /// Returns the `CONFIG_*` flags of the qualifiers that differ from the
+    /// default configuration, as used in type specs configuration masks.
+    pub(crate) fn diff_flags(&self) -> u32 {
+        const CONFIG_MCC: u32 = 0x0001;
+        const CONFIG_MNC: u32 = 0x0002;
+        const CONFIG_LOCALE: u32 = 0x0004;
+        const CONFIG_TOUCHSCREEN: u32 = 0x0008;
+        const CONFIG_KEYBOARD: u32 = 0x0010;
+        const CONFIG_KEYBOARD_HIDDEN: u32 = 0x0020;
+        const CONFIG_NAVIGATION: u32 = 0x0040;
+        const CONFIG_ORIENTATION: u32 = 0x0080;
+        const CONFIG_DENSITY: u32 = 0x0100;
+        const CONFIG_SCREEN_SIZE: u32 = 0x0200;
+        const CONFIG_VERSION: u32 = 0x0400;
+        const CONFIG_SCREEN_LAYOUT: u32 = 0x0800;
+        const CONFIG_UI_MODE: u32 = 0x1000;
+        const CONFIG_SMALLEST_SCREEN_SIZE: u32 = 0x2000;
+        const CONFIG_LAYOUTDIR: u32 = 0x4000;
+        const CONFIG_SCREEN_ROUND: u32 = 0x8000;
+        const CONFIG_COLOR_MODE: u32 = 0x0001_0000;
+        const MASK_LAYOUTDIR: u8 = 0xc0;
+
+        let flag = |set: bool, flag: u32| if set { flag } else { 0 };
+        let layout = self.screen_config_layout.unwrap_or(0);
+        flag(self.imsi_mcc.is_some(), CONFIG_MCC)
+            | flag(self.imsi_mnc.is_some(), CONFIG_MNC)
+            | flag(
+                self.locale_language.is_some()
+                    || self.locale_country.is_some()
+                    || self.locale_script.is_some()
+                    || self.locale_variant.is_some(),
+                CONFIG_LOCALE,
+            )
+            | flag(self.screen_type_touchscreen.is_some(), CONFIG_TOUCHSCREEN)
+            | flag(self.input_keyboard.is_some(), CONFIG_KEYBOARD)
+            | flag(self.input_flags.is_some(), CONFIG_KEYBOARD_HIDDEN)
+            | flag(self.input_navigation.is_some(), CONFIG_NAVIGATION)
+            | flag(self.screen_type_orientation.is_some(), CONFIG_ORIENTATION)
+            | flag(self.screen_type_density.is_some(), CONFIG_DENSITY)
+            | flag(
+                self.screen_size_width.is_some()
+                    || self.screen_size_height.is_some()
+                    || self.screen_size_dp_width.is_some()
+                    || self.screen_size_dp_height.is_some(),
+                CONFIG_SCREEN_SIZE,
+            )
+            | flag(
+                self.version_sdk.is_some() || self.version_minor.is_some(),
+                CONFIG_VERSION,
+            )
+            | flag(layout & !MASK_LAYOUTDIR != 0, CONFIG_SCREEN_LAYOUT)
+            | flag(layout & MASK_LAYOUTDIR != 0, CONFIG_LAYOUTDIR)
+            | flag(self.screen_config_ui_mode.is_some(), CONFIG_UI_MODE)
+            | flag(
+                self.screen_config_smallest_width_dp.is_some(),
+                CONFIG_SMALLEST_SCREEN_SIZE,
+            )
+            | flag(self.screen_config_2_layout.is_some(), CONFIG_SCREEN_ROUND)
+            | flag(self.screen_config_color_mode.is_some(), CONFIG_COLOR_MODE)
+    }
+
+    /// Sets the locale qualifier from a two-letter language code and an
+    /// optional two-letter region code, as in `fr` and `CA` for `fr-rCA`.
+    #[must_use]
+    pub fn with_locale(mut self, language: &str, country: Option<&str>) -> Self {
+        fn code(code: &str) -> Option<Vec<u8>> {
+            (code.len() == 2).then(|| code.as_bytes().to_vec())
+        }
+
+        self.locale_language = code(language);
+        self.locale_country = country.and_then(code);
+        self
+    }
+
+    /// Sets the screen density qualifier, in dots per inch (160 for `mdpi`,
+    /// 240 for `hdpi`, etc.).
+    #[must_use]
+    pub fn with_density(mut self, density: u16) -> Self {
+        self.screen_type_density = Some(density).filter(|d| *d != 0);
+        self
+    }
+
+    /// Sets the screen size qualifier, in pixels.
+    #[must_use]
+    pub fn with_screen_size(mut self, width: u16, height: u16) -> Self {
+        self.screen_size_width = Some(width).filter(|w| *w != 0);
+        self.screen_size_height = Some(height).filter(|h| *h != 0);
+        self
+    }
+
+    /// Sets the platform version qualifier, as an API level.
+    #[must_use]
+    pub fn with_sdk_version(mut self, version: u16) -> Self {
+        self.version_sdk = Some(version).filter(|v| *v != 0);
+        self
+    }
+
+    #[must_use]
+    pub const fn density(&self) -> Option<u16> {
+        self.screen_type_density
+    }
+
+    #[must_use]
+    pub const fn screen_size(&self) -> (Option<u16>, Option<u16>) {
+        (self.screen_size_width, self.screen_size_height)
+    }
+
+    #[must_use]
+    pub const fn sdk_version(&self) -> Option<u16> {
+        self.version_sdk
+    }
+
+    #[must_use]
+    pub fn locale_language(&self) -> Option<String> {
         self.locale_language.as_ref().map(|l| {
             let mut s = String::with_capacity(2);
             s.push(l[0] as char);
@@ -467,7 +732,8 @@ impl Config {
         })
     }
 
-    fn locale_country(&self) -> Option<String> {
+    #[must_use]
+    pub fn locale_country(&self) -> Option<String> {
         self.locale_country.as_ref().map(|c| {
             let mut s = String::with_capacity(2);
             s.push(c[0] as char);
@@ -476,6 +742,17 @@ impl Config {
         })
     }
 
+    const MASK_LAYOUTDIR: u8 = 0xc0;
+    const MASK_SCREENSIZE: u8 = 0x0f;
+    const MASK_SCREENLONG: u8 = 0x30;
+
+    /// Returns the field of the screen layout selected by `mask`, if set.
+    fn screen_layout(&self, mask: u8) -> Option<u8> {
+        self.screen_config_layout
+            .map(|layout| layout & mask)
+            .filter(|field| *field != 0)
+    }
+
     fn importance_score_of_locale(&self) -> i8 {
         let v0 = self.locale_variant.as_ref().map_or(0, |l| l[0]);
         let s0 = self.locale_script.as_ref().map_or(0, |l| l[0]);
@@ -489,6 +766,289 @@ impl Config {
         (if v0 == 0 { 0 } else { 4 }) + (if s0 == 0 { 0 } else { 2 })
     }
 
+    /// Returns whether resources of this configuration can be used with the
+    /// `requested` one: each qualifier must be unset or equal to the
+    /// requested one, except for the screen sizes, the screen layout size and
+    /// the platform version which must not be above the requested ones, and
+    /// for the density as the closest one is selected among matching
+    /// configurations.
+    pub fn matches(&self, requested: &Self) -> bool {
+        fn compatible<T: PartialEq>(own: &Option<T>, requested: &Option<T>) -> bool {
+            own.is_none() || own == requested
+        }
+
+        fn at_most<T: PartialOrd>(own: &Option<T>, requested: &Option<T>) -> bool {
+            match (own, requested) {
+                (Some(own), Some(requested)) => own <= requested,
+                (Some(_), None) => false,
+                (None, _) => true,
+            }
+        }
+
+        compatible(&self.imsi_mcc, &requested.imsi_mcc)
+            && compatible(&self.imsi_mnc, &requested.imsi_mnc)
+            && compatible(&self.locale_language, &requested.locale_language)
+            && compatible(&self.locale_country, &requested.locale_country)
+            && compatible(&self.locale_script, &requested.locale_script)
+            && compatible(&self.locale_variant, &requested.locale_variant)
+            && compatible(
+                &self.screen_type_orientation,
+                &requested.screen_type_orientation,
+            )
+            && compatible(
+                &self.screen_type_touchscreen,
+                &requested.screen_type_touchscreen,
+            )
+            && compatible(&self.input_keyboard, &requested.input_keyboard)
+            && compatible(&self.input_navigation, &requested.input_navigation)
+            && compatible(&self.input_flags, &requested.input_flags)
+            && at_most(&self.screen_size_width, &requested.screen_size_width)
+            && at_most(&self.screen_size_height, &requested.screen_size_height)
+            && compatible(
+                &self.screen_layout(Self::MASK_LAYOUTDIR),
+                &requested.screen_layout(Self::MASK_LAYOUTDIR),
+            )
+            && at_most(
+                &self.screen_layout(Self::MASK_SCREENSIZE),
+                &requested.screen_layout(Self::MASK_SCREENSIZE),
+            )
+            && compatible(
+                &self.screen_layout(Self::MASK_SCREENLONG),
+                &requested.screen_layout(Self::MASK_SCREENLONG),
+            )
+            && compatible(
+                &self.screen_config_ui_mode,
+                &requested.screen_config_ui_mode,
+            )
+            && at_most(
+                &self.screen_config_smallest_width_dp,
+                &requested.screen_config_smallest_width_dp,
+            )
+            && at_most(&self.screen_size_dp_width, &requested.screen_size_dp_width)
+            && at_most(
+                &self.screen_size_dp_height,
+                &requested.screen_size_dp_height,
+            )
+            && compatible(
+                &self.screen_config_2_layout,
+                &requested.screen_config_2_layout,
+            )
+            && compatible(
+                &self.screen_config_color_mode,
+                &requested.screen_config_color_mode,
+            )
+            && at_most(&self.version_sdk, &requested.version_sdk)
+    }
+
+    /// Returns whether this configuration is a better match than `other` for
+    /// the `requested` one, both being assumed to [match](Self::matches) it.
+    ///
+    /// Qualifiers are compared following Android's precedence order: the
+    /// first qualifier that is requested and on which both configurations
+    /// differ decides. Among two set values, the larger one is preferred
+    /// (bigger screen, higher platform version...), except for the density
+    /// where the closest one is preferred, scaling down being favored over
+    /// scaling up.
+    pub fn is_better_than(&self, other: &Self, requested: &Self) -> bool {
+        fn defined<T: PartialOrd>(
+            own: &Option<T>,
+            other: &Option<T>,
+            requested: &Option<T>,
+        ) -> Option<bool> {
+            // an unset value is lower than any set one
+            (requested.is_some() && own != other).then(|| own > other)
+        }
+
+        let decided = defined(&self.imsi_mcc, &other.imsi_mcc, &requested.imsi_mcc)
+            .or_else(|| defined(&self.imsi_mnc, &other.imsi_mnc, &requested.imsi_mnc))
+            .or_else(|| {
+                defined(
+                    &self.locale_language,
+                    &other.locale_language,
+                    &requested.locale_language,
+                )
+            })
+            .or_else(|| {
+                defined(
+                    &self.locale_country,
+                    &other.locale_country,
+                    &requested.locale_country,
+                )
+            })
+            .or_else(|| {
+                defined(
+                    &self.locale_script,
+                    &other.locale_script,
+                    &requested.locale_script,
+                )
+            })
+            .or_else(|| {
+                defined(
+                    &self.locale_variant,
+                    &other.locale_variant,
+                    &requested.locale_variant,
+                )
+            })
+            .or_else(|| {
+                defined(
+                    &self.screen_layout(Self::MASK_LAYOUTDIR),
+                    &other.screen_layout(Self::MASK_LAYOUTDIR),
+                    &requested.screen_layout(Self::MASK_LAYOUTDIR),
+                )
+            })
+            .or_else(|| {
+                defined(
+                    &self.screen_config_smallest_width_dp,
+                    &other.screen_config_smallest_width_dp,
+                    &requested.screen_config_smallest_width_dp,
+                )
+            })
+            .or_else(|| {
+                defined(
+                    &self.screen_size_dp_width,
+                    &other.screen_size_dp_width,
+                    &requested.screen_size_dp_width,
+                )
+            })
+            .or_else(|| {
+                defined(
+                    &self.screen_size_dp_height,
+                    &other.screen_size_dp_height,
+                    &requested.screen_size_dp_height,
+                )
+            })
+            .or_else(|| {
+                defined(
+                    &self.screen_layout(Self::MASK_SCREENSIZE),
+                    &other.screen_layout(Self::MASK_SCREENSIZE),
+                    &requested.screen_layout(Self::MASK_SCREENSIZE),
+                )
+            })
+            .or_else(|| {
+                defined(
+                    &self.screen_layout(Self::MASK_SCREENLONG),
+                    &other.screen_layout(Self::MASK_SCREENLONG),
+                    &requested.screen_layout(Self::MASK_SCREENLONG),
+                )
+            })
+            .or_else(|| {
+                defined(
+                    &self.screen_config_2_layout,
+                    &other.screen_config_2_layout,
+                    &requested.screen_config_2_layout,
+                )
+            })
+            .or_else(|| {
+                defined(
+                    &self.screen_config_color_mode,
+                    &other.screen_config_color_mode,
+                    &requested.screen_config_color_mode,
+                )
+            })
+            .or_else(|| {
+                defined(
+                    &self.screen_type_orientation,
+                    &other.screen_type_orientation,
+                    &requested.screen_type_orientation,
+                )
+            })
+            .or_else(|| {
+                defined(
+                    &self.screen_config_ui_mode,
+                    &other.screen_config_ui_mode,
+                    &requested.screen_config_ui_mode,
+                )
+            })
+            .or_else(|| {
+                (self.screen_type_density != other.screen_type_density)
+                    .then(|| self.is_density_better_than(other, requested))
+            })
+            .or_else(|| {
+                defined(
+                    &self.screen_type_touchscreen,
+                    &other.screen_type_touchscreen,
+                    &requested.screen_type_touchscreen,
+                )
+            })
+            .or_else(|| {
+                defined(
+                    &self.input_flags,
+                    &other.input_flags,
+                    &requested.input_flags,
+                )
+            })
+            .or_else(|| {
+                defined(
+                    &self.input_keyboard,
+                    &other.input_keyboard,
+                    &requested.input_keyboard,
+                )
+            })
+            .or_else(|| {
+                defined(
+                    &self.input_navigation,
+                    &other.input_navigation,
+                    &requested.input_navigation,
+                )
+            })
+            .or_else(|| {
+                defined(
+                    &self.screen_size_width,
+                    &other.screen_size_width,
+                    &requested.screen_size_width,
+                )
+            })
+            .or_else(|| {
+                defined(
+                    &self.screen_size_height,
+                    &other.screen_size_height,
+                    &requested.screen_size_height,
+                )
+            })
+            .or_else(|| {
+                (requested.version_sdk.is_some() && self.version_sdk != other.version_sdk)
+                    .then(|| self.version_sdk > other.version_sdk)
+            })
+            .or_else(|| {
+                (requested.version_minor.is_some() && self.version_minor != other.version_minor)
+                    .then(|| self.version_minor > other.version_minor)
+            });
+
+        decided.unwrap_or(false)
+    }
+
+    /// See `isBetterThan` in ResourceTypes.cpp: unset densities are
+    /// considered as medium ones.
+    fn is_density_better_than(&self, other: &Self, requested: &Self) -> bool {
+        let density = |config: &Self| config.screen_type_density.unwrap_or(DENSITY_MEDIUM);
+        let (own, other) = (density(self), density(other));
+        if own == DENSITY_ANY {
+            return true;
+        }
+        if other == DENSITY_ANY {
+            return false;
+        }
+        let requested = match density(requested) {
+            DENSITY_ANY => DENSITY_MEDIUM,
+            requested => requested,
+        };
+
+        let (low, high) = (i64::from(own.min(other)), i64::from(own.max(other)));
+        let requested = i64::from(requested);
+        let bigger = own > other;
+        if requested >= high {
+            // both need scaling up, the highest density is the closest
+            bigger
+        } else if low >= requested {
+            // both need scaling down, the lowest density is the closest
+            !bigger
+        } else {
+            // scaling down is preferred, unless the higher density is much
+            // further than the lower one
+            ((2 * low - requested) * high > requested * requested) != bigger
+        }
+    }
+
     pub fn is_more_specific_than(&self, other: &Self) -> bool {
         if (self.imsi_mcc.is_some() || other.imsi_mcc.is_some()) && self.imsi_mcc != other.imsi_mcc
         {
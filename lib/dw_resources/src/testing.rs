@@ -0,0 +1,97 @@
+//! Helpers to assemble binary XML documents for unit tests, available to
+//! other crates with the `testing` feature.
+
+/// An event of a binary XML document, strings being referenced by their
+/// index in the string pool.
+pub enum Node<'a> {
+    /// Element name and attributes (name, value type, data).
+    Start(u32, &'a [(u32, u8, u32)]),
+    End(u32),
+    /// Namespace prefix and uri.
+    StartNamespace(u32, u32),
+    EndNamespace(u32, u32),
+}
+
+/// Assembles a binary XML document from a string pool and a flat list of
+/// element events. String attributes are expected to have their data
+/// pointing in the string pool.
+pub fn xml(strings: &[&str], nodes: &[Node]) -> Vec<u8> {
+    let mut pool = Vec::new();
+    let mut offsets = Vec::new();
+    for s in strings {
+        offsets.push(pool.len() as u32);
+        pool.push(s.len() as u8);
+        pool.push(s.len() as u8);
+        pool.extend_from_slice(s.as_bytes());
+        pool.push(0);
+    }
+    pool.resize(pool.len().next_multiple_of(4), 0);
+
+    let mut body = Vec::new();
+    let strings_start = 0x1c + 4 * strings.len() as u32;
+    body.extend_from_slice(&1u16.to_le_bytes());
+    body.extend_from_slice(&0x1cu16.to_le_bytes());
+    body.extend_from_slice(&(strings_start + pool.len() as u32).to_le_bytes());
+    for v in [strings.len() as u32, 0, 0x100, strings_start, 0] {
+        body.extend_from_slice(&v.to_le_bytes());
+    }
+    for off in offsets {
+        body.extend_from_slice(&off.to_le_bytes());
+    }
+    body.extend(pool);
+
+    for node in nodes {
+        match node {
+            Node::Start(name, attrs) => {
+                body.extend_from_slice(&0x0102u16.to_le_bytes());
+                body.extend_from_slice(&0x10u16.to_le_bytes());
+                body.extend_from_slice(&(0x24 + 0x14 * attrs.len() as u32).to_le_bytes());
+                for v in [1, 0xffff_ffff, 0xffff_ffff, *name] {
+                    body.extend_from_slice(&u32::to_le_bytes(v));
+                }
+                for v in [0x14u16, 0x14, attrs.len() as u16, 0, 0, 0] {
+                    body.extend_from_slice(&v.to_le_bytes());
+                }
+                for (name, value_type, data) in *attrs {
+                    let raw_value = if *value_type == 0x03 {
+                        *data
+                    } else {
+                        0xffff_ffff
+                    };
+                    for v in [0xffff_ffff, *name, raw_value] {
+                        body.extend_from_slice(&u32::to_le_bytes(v));
+                    }
+                    body.extend_from_slice(&[8, 0, 0, *value_type]);
+                    body.extend_from_slice(&data.to_le_bytes());
+                }
+            }
+            Node::End(name) => {
+                body.extend_from_slice(&0x0103u16.to_le_bytes());
+                body.extend_from_slice(&0x10u16.to_le_bytes());
+                body.extend_from_slice(&0x18u32.to_le_bytes());
+                for v in [1, 0xffff_ffff, 0xffff_ffff, *name] {
+                    body.extend_from_slice(&u32::to_le_bytes(v));
+                }
+            }
+            Node::StartNamespace(prefix, uri) | Node::EndNamespace(prefix, uri) => {
+                let typ: u16 = match node {
+                    Node::StartNamespace(..) => 0x0100,
+                    _ => 0x0101,
+                };
+                body.extend_from_slice(&typ.to_le_bytes());
+                body.extend_from_slice(&0x10u16.to_le_bytes());
+                body.extend_from_slice(&0x18u32.to_le_bytes());
+                for v in [1, 0xffff_ffff, *prefix, *uri] {
+                    body.extend_from_slice(&u32::to_le_bytes(v));
+                }
+            }
+        }
+    }
+
+    let mut xml = Vec::new();
+    xml.extend_from_slice(&3u16.to_le_bytes());
+    xml.extend_from_slice(&8u16.to_le_bytes());
+    xml.extend_from_slice(&(8 + body.len() as u32).to_le_bytes());
+    xml.extend(body);
+    xml
+}
@@ -1,11 +1,10 @@
 use crate::errors::{ResourcesError, ResourcesResult};
 use crate::resources::Resources;
 use crate::strings::{StringPool, StringPoolIndex};
-use crate::tables::TableTypeEntryContent;
 use crate::Xml;
 use std::fmt;
 
-#[derive(Debug, Clone, Copy)]
+#[derive(Debug, Clone, Copy, PartialEq)]
 pub enum Value {
     Null,
     Reference(u32),
@@ -99,52 +98,14 @@ impl Value {
         match self {
             Self::Null => Ok(ResolvedValue::Null),
             Self::Reference(r) => {
-                if oresources.is_none() {
+                let Some(resources) = oresources else {
                     return Err(ResourcesError::ResNotFound(format!(
                         "no resources provided to resolve ]{r:#x}",
                     )));
-                }
-
-                let resources = oresources.unwrap();
-
-                let ocmap = resources.lookup(*r);
-                if ocmap.is_none() {
-                    return Err(ResourcesError::CannotResolveWithoutResources(format!(
-                        "resource @{r:#x}",
-                    )));
-                }
-                let cmap = ocmap.unwrap();
-                if cmap.is_empty() {
-                    return Err(ResourcesError::ResNotFound(format!("resource @{r:#x}")));
-                }
-
-                let mut prev = None;
-                for (_, entry) in cmap {
-                    let cur = match entry.content {
-                        TableTypeEntryContent::EntryValue(v) => {
-                            let string_pool = &resources.0.string_pool;
-                            v.resolve(string_pool, oresources)?
-                        }
-                        TableTypeEntryContent::EntryMap(_) => {
-                            return Err(ResourcesError::TooComplexResource(format!(
-                                "resource @{r:#x} is an EntryMap",
-                            )));
-                        }
-                    };
-
-                    match prev {
-                        None => prev = Some(cur),
-                        Some(ref prev) => {
-                            if *prev != cur {
-                                return Err(ResourcesError::TooComplexResource(format!("resource @{r:#x} has several configurations with different values")));
-                            }
-                        }
-                    }
-                }
-                match prev {
-                    None => Err(ResourcesError::ResNotFound(format!("resource @{r:#x}"))),
-                    Some(rv) => Ok(rv),
-                }
+                };
+                resources
+                    .resolve(*r, None)?
+                    .resolve(&resources.0.string_pool, oresources)
             }
             Self::Attribute(a) => Ok(ResolvedValue::Attribute(*a)),
             Self::String(s) => Ok(ResolvedValue::String(string_pool.get(*s)?.string()?)),
@@ -1,7 +1,11 @@
 use crate::chunk::{ChunkHeader, ChunkType};
 use crate::errors::ResourcesResult;
-use crate::resources::Resources;
+use crate::resources::{Resources, ResourcesTable};
 use crate::strings::{StringPool, UtfString};
+use crate::tables::{
+    Config, TableLibrary, TableOverlayable, TableOverlayablePolicy, TablePackage, TableStagedAlias,
+    TableType, TableTypeEntry, TableTypeEntryContent,
+};
 use crate::values::Value;
 use crate::xml::{
     XmlCdata, XmlElement, XmlElementAttrs, XmlEvent, XmlMetadata, XmlNamespace, XmlResourceMap,
@@ -10,9 +14,13 @@ use crate::Xml;
 use dw_utils::writers::{bytes, le_u16, le_u32, le_u8, tag};
 use std::io::{Cursor, Result, Write};
 
-#[allow(dead_code)]
-pub fn write_resources(_resources: &Resources) -> ResourcesResult<Vec<u8>> {
-    todo!("write_resources");
+pub fn write_resources(resources: &Resources) -> ResourcesResult<Vec<u8>> {
+    let buffer: Vec<u8> = Vec::new();
+    let mut cursor = Cursor::new(buffer);
+
+    let _ = resources_writer(&mut cursor, &resources.0)?;
+
+    Ok(cursor.into_inner())
 }
 
 pub fn write_xml(xml: &Xml) -> ResourcesResult<Vec<u8>> {
@@ -49,6 +57,345 @@ fn xml_writer<W: Write>(output: &mut W, xml: &Xml) -> Result<usize> {
     Ok(siz)
 }
 
+fn resources_writer<W: Write>(output: &mut W, table: &ResourcesTable) -> Result<usize> {
+    const HEADER_SIZE: usize = 12;
+    let mut table_cursor = Cursor::new(Vec::new());
+
+    let mut siz = HEADER_SIZE;
+    siz += string_pool_writer(&mut table_cursor, &table.string_pool)?;
+    for package in table.package_pool.packages() {
+        siz += table_package_writer(&mut table_cursor, package)?;
+    }
+
+    let _ = chunk_header_writer(
+        output,
+        &ChunkHeader {
+            typ: ChunkType::Table,
+            header_size: HEADER_SIZE,
+            chunk_size: siz,
+        },
+    )?;
+    let _ = le_u32(output, table.package_pool.packages().len() as u32)?;
+    let _ = bytes(output, &table_cursor.into_inner())?;
+    Ok(siz)
+}
+
+fn table_package_writer<W: Write>(output: &mut W, package: &TablePackage) -> Result<usize> {
+    const HEADER_SIZE: usize = 0x120;
+    let empty_pool = StringPool::new();
+    let mut package_cursor = Cursor::new(Vec::new());
+
+    let mut siz = HEADER_SIZE;
+    let type_strings_offset = siz;
+    siz += string_pool_writer(
+        &mut package_cursor,
+        package.type_strings.as_ref().unwrap_or(&empty_pool),
+    )?;
+    let key_strings_offset = siz;
+    siz += string_pool_writer(
+        &mut package_cursor,
+        package.key_strings.as_ref().unwrap_or(&empty_pool),
+    )?;
+    for string_pool in &package.string_pools {
+        siz += string_pool_writer(&mut package_cursor, string_pool)?;
+    }
+
+    // type specs (including missing ones) are written before the types
+    // sharing their id
+    let mut type_ids: Vec<u8> = package
+        .table_type_specs
+        .iter()
+        .map(|spec| spec.id)
+        .chain(package.type_pool.types().iter().map(|typ| typ.id))
+        .collect();
+    type_ids.sort_unstable();
+    type_ids.dedup();
+    for type_id in type_ids {
+        let config_mask = package.config_masks(type_id);
+        siz += table_type_spec_writer(&mut package_cursor, type_id, &config_mask)?;
+        for typ in package.type_pool.resolve(type_id).unwrap_or_default() {
+            siz += table_type_writer(&mut package_cursor, &typ, config_mask.len())?;
+        }
+    }
+
+    for library in &package.table_libraries {
+        siz += table_library_writer(&mut package_cursor, library)?;
+    }
+    // overlayable policies are not attached to their overlayable when
+    // parsed, they are all nested in the last overlayable
+    if let Some((last, others)) = package.table_overlayables.split_last() {
+        for overlayable in others {
+            siz += table_overlayable_writer(&mut package_cursor, overlayable, &[])?;
+        }
+        siz += table_overlayable_writer(
+            &mut package_cursor,
+            last,
+            &package.table_overlayable_policies,
+        )?;
+    }
+    for staged_alias in &package.table_staged_aliases {
+        siz += table_staged_alias_writer(&mut package_cursor, staged_alias)?;
+    }
+
+    let _ = chunk_header_writer(
+        output,
+        &ChunkHeader {
+            typ: ChunkType::TablePackage,
+            header_size: HEADER_SIZE,
+            chunk_size: siz,
+        },
+    )?;
+    let _ = le_u32(output, u32::from(package.id))?;
+    let _ = utf16_name_writer(output, &package.name, 128)?;
+    let _ = le_u32(output, type_strings_offset as u32)?;
+    let _ = le_u32(output, package.last_public_type)?;
+    let _ = le_u32(output, key_strings_offset as u32)?;
+    let _ = le_u32(output, package.last_public_key)?;
+    let _ = le_u32(output, 0)?; // type id offset
+    let _ = bytes(output, &package_cursor.into_inner())?;
+    Ok(siz)
+}
+
+/// Writes a fixed-size, zero-padded UTF-16 name, truncated if needed to
+/// keep a terminating null character.
+fn utf16_name_writer<W: Write>(output: &mut W, name: &str, len: usize) -> Result<usize> {
+    let mut siz = 0;
+    let mut chars: Vec<u16> = name.encode_utf16().take(len - 1).collect();
+    chars.resize(len, 0);
+    for c in chars {
+        siz += le_u16(output, c)?;
+    }
+    Ok(siz)
+}
+
+fn table_type_spec_writer<W: Write>(
+    output: &mut W,
+    type_id: u8,
+    config_mask: &[u32],
+) -> Result<usize> {
+    const HEADER_SIZE: usize = 0x10;
+
+    let siz = HEADER_SIZE + 4 * config_mask.len();
+    let _ = chunk_header_writer(
+        output,
+        &ChunkHeader {
+            typ: ChunkType::TableTypeSpec,
+            header_size: HEADER_SIZE,
+            chunk_size: siz,
+        },
+    )?;
+    let _ = le_u8(output, type_id)?;
+    let _ = tag(output, "\x00")?;
+    let _ = tag(output, "\x00\x00")?;
+    let _ = le_u32(output, config_mask.len() as u32)?;
+    for mask in config_mask {
+        let _ = le_u32(output, *mask)?;
+    }
+    Ok(siz)
+}
+
+fn table_type_writer<W: Write>(
+    output: &mut W,
+    typ: &TableType,
+    entry_count: usize,
+) -> Result<usize> {
+    const CONFIG_SIZE: usize = 64;
+    const HEADER_SIZE: usize = 0x14 + CONFIG_SIZE;
+    let mut entries_cursor = Cursor::new(Vec::new());
+
+    let mut entries_siz = 0;
+    let mut entry_offsets = Vec::with_capacity(entry_count);
+    for entry_id in 0..entry_count {
+        match typ.entry_pool.entries().get(&(entry_id as u16)) {
+            None => entry_offsets.push(0xFFFF_FFFF),
+            Some(entry) => {
+                entry_offsets.push(entries_siz as u32);
+                entries_siz += table_type_entry_writer(&mut entries_cursor, entry)?;
+            }
+        }
+    }
+
+    let entries_start = HEADER_SIZE + 4 * entry_count;
+    let siz = entries_start + entries_siz;
+    let _ = chunk_header_writer(
+        output,
+        &ChunkHeader {
+            typ: ChunkType::TableType,
+            header_size: HEADER_SIZE,
+            chunk_size: siz,
+        },
+    )?;
+    let _ = le_u8(output, typ.id)?;
+    let _ = tag(output, "\x00")?;
+    let _ = tag(output, "\x00\x00")?;
+    let _ = le_u32(output, entry_count as u32)?;
+    let _ = le_u32(output, entries_start as u32)?;
+    let _ = config_writer(output, &typ.config, CONFIG_SIZE)?;
+    for offset in entry_offsets {
+        let _ = le_u32(output, offset)?;
+    }
+    let _ = bytes(output, &entries_cursor.into_inner())?;
+    Ok(siz)
+}
+
+fn config_writer<W: Write>(output: &mut W, config: &Config, config_size: usize) -> Result<usize> {
+    fn code<W: Write>(output: &mut W, code: Option<&Vec<u8>>, len: usize) -> Result<usize> {
+        let mut code = code.cloned().unwrap_or_default();
+        code.resize(len, 0);
+        bytes(output, &code)
+    }
+
+    let mut siz = 0;
+    siz += le_u32(output, config_size as u32)?;
+    siz += le_u16(output, config.imsi_mcc.unwrap_or(0))?;
+    siz += le_u16(output, config.imsi_mnc.unwrap_or(0))?;
+    siz += code(output, config.locale_language.as_ref(), 2)?;
+    siz += code(output, config.locale_country.as_ref(), 2)?;
+    siz += le_u8(output, config.screen_type_orientation.unwrap_or(0))?;
+    siz += le_u8(output, config.screen_type_touchscreen.unwrap_or(0))?;
+    siz += le_u16(output, config.screen_type_density.unwrap_or(0))?;
+    siz += le_u8(output, config.input_keyboard.unwrap_or(0))?;
+    siz += le_u8(output, config.input_navigation.unwrap_or(0))?;
+    siz += le_u8(output, config.input_flags.unwrap_or(0))?;
+    siz += le_u8(output, config.input_pad0.unwrap_or(0))?;
+    siz += le_u16(output, config.screen_size_width.unwrap_or(0))?;
+    siz += le_u16(output, config.screen_size_height.unwrap_or(0))?;
+    siz += le_u16(output, config.version_sdk.unwrap_or(0))?;
+    siz += le_u16(output, config.version_minor.unwrap_or(0))?;
+    siz += le_u8(output, config.screen_config_layout.unwrap_or(0))?;
+    siz += le_u8(output, config.screen_config_ui_mode.unwrap_or(0))?;
+    siz += le_u16(output, config.screen_config_smallest_width_dp.unwrap_or(0))?;
+    siz += le_u16(output, config.screen_size_dp_width.unwrap_or(0))?;
+    siz += le_u16(output, config.screen_size_dp_height.unwrap_or(0))?;
+    siz += code(output, config.locale_script.as_ref(), 4)?;
+    siz += code(output, config.locale_variant.as_ref(), 8)?;
+    siz += le_u8(output, config.screen_config_2_layout.unwrap_or(0))?;
+    siz += le_u8(output, config.screen_config_color_mode.unwrap_or(0))?;
+    siz += le_u16(output, config.screen_config_2_pad2.unwrap_or(0))?;
+    // qualifiers that are not parsed
+    while siz < config_size {
+        siz += tag(output, "\x00")?;
+    }
+    Ok(siz)
+}
+
+fn table_type_entry_writer<W: Write>(output: &mut W, entry: &TableTypeEntry) -> Result<usize> {
+    let mut siz = 0;
+    match &entry.content {
+        TableTypeEntryContent::EntryValue(value) => {
+            siz += le_u16(output, 8)?;
+            siz += le_u16(output, entry.entry.flags)?;
+            siz += le_u32(output, entry.entry.key)?;
+            siz += value_writer(output, *value)?;
+        }
+        TableTypeEntryContent::EntryMap(map) => {
+            siz += le_u16(output, 16)?;
+            siz += le_u16(output, entry.entry.flags)?;
+            siz += le_u32(output, entry.entry.key)?;
+            siz += le_u32(output, map.parent)?;
+            siz += le_u32(output, map.table_maps.len() as u32)?;
+            for table_map in &map.table_maps {
+                siz += le_u32(output, table_map.name)?;
+                siz += value_writer(output, table_map.value)?;
+            }
+        }
+    }
+    Ok(siz)
+}
+
+fn table_library_writer<W: Write>(output: &mut W, library: &TableLibrary) -> Result<usize> {
+    const HEADER_SIZE: usize = 12;
+
+    let siz = HEADER_SIZE + library.libraries.len() * (4 + 2 * 128);
+    let _ = chunk_header_writer(
+        output,
+        &ChunkHeader {
+            typ: ChunkType::TableLibrary,
+            header_size: HEADER_SIZE,
+            chunk_size: siz,
+        },
+    )?;
+    let _ = le_u32(output, library.libraries.len() as u32)?;
+    for entry in &library.libraries {
+        let _ = le_u32(output, entry.id)?;
+        let _ = utf16_name_writer(output, &entry.name, 128)?;
+    }
+    Ok(siz)
+}
+
+fn table_overlayable_writer<W: Write>(
+    output: &mut W,
+    overlayable: &TableOverlayable,
+    policies: &[TableOverlayablePolicy],
+) -> Result<usize> {
+    const HEADER_SIZE: usize = 8 + 2 * 256 + 2 * 256;
+    let mut policies_cursor = Cursor::new(Vec::new());
+
+    let mut siz = HEADER_SIZE;
+    for policy in policies {
+        siz += table_overlayable_policy_writer(&mut policies_cursor, policy)?;
+    }
+
+    let _ = chunk_header_writer(
+        output,
+        &ChunkHeader {
+            typ: ChunkType::TableOverlayable,
+            header_size: HEADER_SIZE,
+            chunk_size: siz,
+        },
+    )?;
+    let _ = utf16_name_writer(output, &overlayable.name, 256)?;
+    let _ = utf16_name_writer(output, &overlayable.actor, 256)?;
+    let _ = bytes(output, &policies_cursor.into_inner())?;
+    Ok(siz)
+}
+
+fn table_overlayable_policy_writer<W: Write>(
+    output: &mut W,
+    policy: &TableOverlayablePolicy,
+) -> Result<usize> {
+    const HEADER_SIZE: usize = 16;
+
+    let siz = HEADER_SIZE + 4 * policy.entries.len();
+    let _ = chunk_header_writer(
+        output,
+        &ChunkHeader {
+            typ: ChunkType::TableOverlayablePolicy,
+            header_size: HEADER_SIZE,
+            chunk_size: siz,
+        },
+    )?;
+    let _ = le_u32(output, policy.flags)?;
+    let _ = le_u32(output, policy.entries.len() as u32)?;
+    for entry in &policy.entries {
+        let _ = le_u32(output, *entry)?;
+    }
+    Ok(siz)
+}
+
+fn table_staged_alias_writer<W: Write>(
+    output: &mut W,
+    staged_alias: &TableStagedAlias,
+) -> Result<usize> {
+    const HEADER_SIZE: usize = 12;
+
+    let siz = HEADER_SIZE + 8 * staged_alias.entries.len();
+    let _ = chunk_header_writer(
+        output,
+        &ChunkHeader {
+            typ: ChunkType::TableStagedAlias,
+            header_size: HEADER_SIZE,
+            chunk_size: siz,
+        },
+    )?;
+    let _ = le_u32(output, staged_alias.entries.len() as u32)?;
+    for entry in &staged_alias.entries {
+        let _ = le_u32(output, entry.stage_id)?;
+        let _ = le_u32(output, entry.finalized_id)?;
+    }
+    Ok(siz)
+}
+
 fn chunk_header_writer<W: Write>(output: &mut W, chunk_header: &ChunkHeader) -> Result<usize> {
     let mut siz = 0;
     siz += le_u16(output, chunk_header.typ.into())?;
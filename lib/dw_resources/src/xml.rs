@@ -63,6 +63,40 @@ pub struct XmlElementAttrs {
     pub(crate) attrs: Vec<XmlAttribute>,
 }
 
+impl XmlElementAttrs {
+    /// Inserts `attr` at `position`, keeping the 1-based `id`, `class` and
+    /// `style` attribute indices pointing to the same attributes.
+    pub(crate) fn insert(&mut self, position: usize, attr: XmlAttribute) {
+        self.attrs.insert(position, attr);
+        for index in [
+            &mut self.id_index,
+            &mut self.class_index,
+            &mut self.style_index,
+        ] {
+            if usize::from(*index) > position {
+                *index += 1;
+            }
+        }
+    }
+
+    /// Removes the attribute at `position`, updating the `id`, `class` and
+    /// `style` attribute indices (which are reset if they pointed to it).
+    pub(crate) fn remove(&mut self, position: usize) {
+        self.attrs.remove(position);
+        for index in [
+            &mut self.id_index,
+            &mut self.class_index,
+            &mut self.style_index,
+        ] {
+            if usize::from(*index) == position + 1 {
+                *index = 0;
+            } else if usize::from(*index) > position + 1 {
+                *index -= 1;
+            }
+        }
+    }
+}
+
 #[derive(Clone, Debug)]
 pub(crate) struct XmlAttribute {
     pub(crate) ns: Option<StringPoolIndex>,
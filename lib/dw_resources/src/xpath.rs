@@ -1,4 +1,5 @@
 use crate::errors::{ResourcesError, ResourcesResult};
+use crate::manifest::Manifest;
 use crate::strings::StringPoolIndex;
 use crate::values::Value;
 use crate::xml::{XmlAttribute, XmlElement, XmlElementAttrs, XmlEvent, XmlMetadata};
@@ -89,33 +90,26 @@ impl<'a> Context<'a> {
         let Context { xml, selection } = self;
         let mut new_selection = Vec::new();
 
-        match predicate {
-            Predicate::Attr(attr_name, attr_str) => {
-                for (selected, namespaces) in selection {
-                    let event_id = selected.node()?;
-                    let (_, attrs) = xml.xml_body.get(event_id).unwrap().start_element()?;
-                    let mut pred_satisfied = false;
-                    for attr in &attrs.attrs {
-                        if xml.string_pool.get(attr.name)?.string()? == attr_name {
-                            match &attr.typed_value {
-                                Value::String(str_ref) => {
-                                    if xml.string_pool.get(*str_ref)?.string()? == attr_str {
-                                        pred_satisfied = true;
-                                    }
-                                }
-                                _ => {
-                                    return Err(ResourcesError::XmlQuery(
-                                        "not a string".to_string(),
-                                    ))
-                                }
+        let attr_name = predicate.attr_name();
+        for (selected, namespaces) in selection {
+            let event_id = selected.node()?;
+            let (_, attrs) = xml.xml_body.get(event_id).unwrap().start_element()?;
+            let mut pred_satisfied = false;
+            for attr in &attrs.attrs {
+                if xml.string_pool.get(attr.name)?.string()? == attr_name {
+                    match &attr.typed_value {
+                        Value::String(str_ref) => {
+                            if predicate.matches(&xml.string_pool.get(*str_ref)?.string()?) {
+                                pred_satisfied = true;
                             }
                         }
-                    }
-                    if pred_satisfied {
-                        new_selection.push((selected, namespaces));
+                        _ => return Err(ResourcesError::XmlQuery("not a string".to_string())),
                     }
                 }
             }
+            if pred_satisfied {
+                new_selection.push((selected, namespaces));
+            }
         }
         Ok(Self {
             xml,
@@ -123,6 +117,19 @@ impl<'a> Context<'a> {
         })
     }
 
+    /// Splits the selection into as many contexts as selected items, so
+    /// that further selections can be made relatively to each item.
+    pub(crate) fn split(self) -> Vec<Self> {
+        let Context { xml, selection } = self;
+        selection
+            .into_iter()
+            .map(|selected| Context {
+                xml,
+                selection: vec![selected],
+            })
+            .collect()
+    }
+
     pub(crate) fn nodes(self) -> ResourcesResult<Vec<(&'a XmlElement, &'a XmlElementAttrs)>> {
         let Context { xml, selection } = self;
         let mut nodes = Vec::new();
@@ -134,6 +141,31 @@ impl<'a> Context<'a> {
         Ok(nodes)
     }
 
+    /// Returns the text content of each selected node, made of its
+    /// character data children (nested elements text is ignored).
+    pub(crate) fn texts(self) -> ResourcesResult<Vec<String>> {
+        let Context { xml, selection } = self;
+        let mut texts = Vec::new();
+        for (selected, _namespaces) in selection {
+            let mut text = String::new();
+            let mut level = 1;
+            let mut i = selected.node()? + 1;
+            while level > 0 && i < xml.xml_body.len() {
+                match &xml.xml_body[i] {
+                    XmlEvent::StartElement(_, _) => level += 1,
+                    XmlEvent::EndElement(_) => level -= 1,
+                    XmlEvent::Cdata(cdata) if level == 1 => {
+                        text.push_str(&xml.string_pool.get(cdata.data)?.string()?);
+                    }
+                    _ => (),
+                }
+                i += 1;
+            }
+            texts.push(text);
+        }
+        Ok(texts)
+    }
+
     pub(crate) fn attributes(self) -> ResourcesResult<Vec<&'a XmlAttribute>> {
         let Context { xml, selection } = self;
         let mut attributes = Vec::new();
@@ -306,10 +338,11 @@ impl<'a> ContextMut<'a> {
             .map(|(selected, _namespaces)| selected.attr())
             .collect::<ResourcesResult<BTreeSet<(usize, usize)>>>()?;
         let mut removed = false;
-        for (node, attr) in nodes_attrs {
+        // in reverse order, so that removals do not shift the next positions
+        for (node, attr) in nodes_attrs.into_iter().rev() {
             match xml.xml_body.get_mut(node).unwrap() {
                 XmlEvent::StartElement(_, attrs) => {
-                    attrs.attrs.remove(attr);
+                    attrs.remove(attr);
                     removed = true;
                 }
                 _ => {
@@ -323,11 +356,21 @@ impl<'a> ContextMut<'a> {
     }
 
     pub(crate) fn insert_attribute(self, name: String, value: Value) -> ResourcesResult<()> {
+        let (name, _) = self.xml.string_pool.get_or_push(name)?;
+        self.insert_attribute_raw(name, value)
+    }
+
+    /// Same as [`ContextMut::insert_attribute`], with an attribute name
+    /// already allocated in the string pool.
+    pub(crate) fn insert_attribute_raw(
+        self,
+        name: StringPoolIndex,
+        value: Value,
+    ) -> ResourcesResult<()> {
         let ContextMut { xml, selection } = self;
         for (selected, namespaces) in selection {
             let event_id = selected.node()?;
             if let Some(XmlEvent::StartElement(_elt, attrs)) = xml.xml_body.get_mut(event_id) {
-                let (string_pool_index, _) = xml.string_pool.get_or_push(name.clone())?;
                 // TODO 1/ for now, we push 'android' namespace. must be patched when supporting
                 // other attributes in the manifest
                 let mut ns = None;
@@ -337,13 +380,31 @@ impl<'a> ContextMut<'a> {
                         break;
                     }
                 }
-                let ns = ns.unwrap();
-                attrs.attrs.push(XmlAttribute {
-                    ns: Some(ns),
-                    name: string_pool_index,
-                    raw_value: value.raw_value(),
-                    typed_value: value,
-                });
+                let ns = ns.ok_or_else(|| {
+                    ResourcesError::XmlQuery("'android' namespace not found".to_string())
+                })?;
+                // attributes are sorted by resource id, those without one
+                // coming last
+                let resource_id = |name: StringPoolIndex| {
+                    xml.xml_resource_map
+                        .as_ref()
+                        .and_then(|map| map.resource_ids.get(name.index()).copied())
+                        .unwrap_or(u32::MAX)
+                };
+                let position = attrs
+                    .attrs
+                    .iter()
+                    .position(|attr| resource_id(attr.name) > resource_id(name))
+                    .unwrap_or(attrs.attrs.len());
+                attrs.insert(
+                    position,
+                    XmlAttribute {
+                        ns: Some(ns),
+                        name,
+                        raw_value: value.raw_value(),
+                        typed_value: value,
+                    },
+                );
             } else {
                 return Err(ResourcesError::XmlQuery(
                     "'start element' event was expected".to_string(),
@@ -358,13 +419,40 @@ impl<'a> ContextMut<'a> {
         for (selected, _namespaces) in selection {
             let (event_id, attr_id) = selected.attr()?;
             if let Some(XmlEvent::StartElement(_elt, attrs)) = xml.xml_body.get_mut(event_id) {
-                match new_value {
-                    Value::IntBoolean(b) => {
-                        // for intbool, no need to edit raw_value field,
-                        // which should stay equal to 0xffffffff
-                        attrs.attrs[attr_id].typed_value = Value::IntBoolean(b);
+                let attr = &mut attrs.attrs[attr_id];
+                attr.raw_value = new_value.raw_value();
+                attr.typed_value = new_value;
+            } else {
+                return Err(ResourcesError::XmlQuery(
+                    "'start element' event was expected".to_string(),
+                ));
+            }
+        }
+        Ok(())
+    }
+
+    /// Rewrites the selected string attributes with the result of `f`, which
+    /// receives the current value and returns the new one (or `None` to keep
+    /// the attribute untouched). Non-string attributes are left as is.
+    /// Returns the number of edited attributes.
+    pub(crate) fn map_string_attributes<F>(self, f: F) -> ResourcesResult<usize>
+    where
+        F: Fn(&str) -> Option<String>,
+    {
+        let ContextMut { xml, selection } = self;
+        let mut edited = 0;
+        for (selected, _namespaces) in selection {
+            let (event_id, attr_id) = selected.attr()?;
+            if let Some(XmlEvent::StartElement(_elt, attrs)) = xml.xml_body.get_mut(event_id) {
+                let attr = &mut attrs.attrs[attr_id];
+                if let Value::String(s) = attr.typed_value {
+                    let old_value = xml.string_pool.get(s)?.string()?;
+                    if let Some(new_value) = f(&old_value) {
+                        let (new_index, _) = xml.string_pool.get_or_push(new_value)?;
+                        attr.raw_value = new_index.index() as u32;
+                        attr.typed_value = Value::String(new_index);
+                        edited += 1;
                     }
-                    _ => panic!("unsupported edit value type"),
                 }
             } else {
                 return Err(ResourcesError::XmlQuery(
@@ -372,7 +460,7 @@ impl<'a> ContextMut<'a> {
                 ));
             }
         }
-        Ok(())
+        Ok(edited)
     }
 }
 
@@ -384,7 +472,30 @@ pub(crate) enum Select<'a> {
 
 #[derive(Clone, Copy)]
 pub(crate) enum Predicate<'a> {
-    Attr(&'a str, &'a str), // '[@attr=str]'
+    Attr(&'a str, &'a str),      // '[@attr=str]'
+    Component(&'a str, &'a str), // '[@name=component]', relative to a package
+}
+
+impl Predicate<'_> {
+    /// Returns the name of the tested attribute.
+    const fn attr_name(&self) -> &str {
+        match self {
+            Self::Attr(attr_name, _) => attr_name,
+            Self::Component(_, _) => "name",
+        }
+    }
+
+    /// Returns whether the value of the tested attribute satisfies the
+    /// predicate. Component names are compared once qualified, so that
+    /// `.Main` matches `com.example.Main` in the `com.example` package.
+    fn matches(&self, value: &str) -> bool {
+        match self {
+            Self::Attr(_, attr_str) => value == *attr_str,
+            Self::Component(package, component) => {
+                Manifest::qualify(package, value) == Manifest::qualify(package, component)
+            }
+        }
+    }
 }
 
 #[derive(Clone)]
@@ -70,8 +70,10 @@ pub fn le_f64<W: Write>(output: &mut W, f: f64) -> Result<usize> {
 pub fn le_i16_on<W: Write>(output: &mut W, v: i16, s: usize) -> Result<usize> {
     let vbytes = v.to_le_bytes();
     let sz = output.write(&vbytes[0..s])?;
+    // skipped bytes are recovered by sign extension
+    let fill = if v < 0 { 0xff } else { 0 };
     for b in vbytes.iter().skip(s) {
-        assert_eq!(*b, 0);
+        assert_eq!(*b, fill);
     }
     Ok(sz)
 }
@@ -87,8 +89,9 @@ pub fn le_u16_on<W: Write>(output: &mut W, v: u16, s: usize) -> Result<usize> {
 pub fn le_i32_on<W: Write>(output: &mut W, v: i32, s: usize) -> Result<usize> {
     let vbytes = v.to_le_bytes();
     let sz = output.write(&vbytes[0..s])?;
+    let fill = if v < 0 { 0xff } else { 0 };
     for b in vbytes.iter().skip(s) {
-        assert_eq!(*b, 0);
+        assert_eq!(*b, fill);
     }
     Ok(sz)
 }
@@ -105,8 +108,9 @@ pub fn le_u32_on<W: Write>(output: &mut W, v: u32, s: usize) -> Result<usize> {
 pub fn le_i64_on<W: Write>(output: &mut W, v: i64, s: usize) -> Result<usize> {
     let vbytes = v.to_le_bytes();
     let sz = output.write(&vbytes[0..s])?;
+    let fill = if v < 0 { 0xff } else { 0 };
     for b in vbytes.iter().skip(s) {
-        assert_eq!(*b, 0);
+        assert_eq!(*b, fill);
     }
     Ok(sz)
 }
@@ -122,8 +126,10 @@ pub fn le_u64_on<W: Write>(output: &mut W, v: u64, s: usize) -> Result<usize> {
 
 pub fn le_f32_on<W: Write>(output: &mut W, f: f32, s: usize) -> Result<usize> {
     let fbytes = f.to_le_bytes();
-    let sz = output.write(&fbytes[0..s])?;
-    for b in fbytes.iter().skip(s) {
+    // floating point values are right zero-extended
+    let skip = fbytes.len() - s;
+    let sz = output.write(&fbytes[skip..])?;
+    for b in fbytes.iter().take(skip) {
         assert_eq!(*b, 0);
     }
     Ok(sz)
@@ -131,8 +137,9 @@ pub fn le_f32_on<W: Write>(output: &mut W, f: f32, s: usize) -> Result<usize> {
 
 pub fn le_f64_on<W: Write>(output: &mut W, f: f64, s: usize) -> Result<usize> {
     let fbytes = f.to_le_bytes();
-    let sz = output.write(&fbytes[0..s])?;
-    for b in fbytes.iter().skip(s) {
+    let skip = fbytes.len() - s;
+    let sz = output.write(&fbytes[skip..])?;
+    for b in fbytes.iter().take(skip) {
         assert_eq!(*b, 0);
     }
     Ok(sz)
@@ -260,6 +267,24 @@ mod tests {
         assert_eq!(buf, vec![0x88, 0x77, 0x66, 0x55, 0x44, 0x33, 0x22, 0x11]);
     }
 
+    #[test]
+    fn le_i32_on_writer() {
+        let mut buf = Vec::new();
+        assert!(le_i32_on(&mut buf, -2i32, 1).is_ok());
+        assert_eq!(buf, vec![0xfe]);
+
+        buf.clear();
+        assert!(le_i32_on(&mut buf, 0x1234i32, 2).is_ok());
+        assert_eq!(buf, vec![0x34, 0x12]);
+    }
+
+    #[test]
+    fn le_f32_on_writer() {
+        let mut buf = Vec::new();
+        assert!(le_f32_on(&mut buf, 1.5f32, 2).is_ok());
+        assert_eq!(buf, vec![0xc0, 0x3f]);
+    }
+
     #[test]
     fn uleb128_writer() {
         let mut buf = Vec::new();
@@ -2,8 +2,8 @@ use clap::ArgMatches;
 use clap_complete::{generate, Shell};
 use droidworks::prelude::*;
 use droidworks::{
-    cli, dw_aresources, dw_callgraph, dw_dexdissect, dw_disas, dw_hierarchy, dw_manifest, dw_nsc,
-    dw_packageinfo, dw_permissions, dw_stats, dw_strip, dw_typecheck,
+    cli, dw_aresources, dw_audit, dw_callgraph, dw_dexdissect, dw_disas, dw_hierarchy, dw_manifest,
+    dw_nsc, dw_packageinfo, dw_permissions, dw_stats, dw_strip, dw_typecheck,
 };
 use std::io;
 
@@ -12,6 +12,7 @@ fn main() -> DwResult<()> {
 
     match &args.subcommand() {
         Some(("aresources", cmd_args)) => dw_aresources::run(cmd_args),
+        Some(("audit", cmd_args)) => dw_audit::run(cmd_args),
         Some(("callgraph", cmd_args)) => dw_callgraph::run(cmd_args),
         Some(("dexdissect", cmd_args)) => dw_dexdissect::run(cmd_args),
         Some(("disas", cmd_args)) => dw_disas::run(cmd_args),
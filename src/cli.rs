@@ -82,6 +82,7 @@ pub fn droidworks() -> Command {
         .author(AUTHORS)
         .about(DESCRIPTION)
         .subcommand(aresources())
+        .subcommand(audit())
         .subcommand(callgraph())
         .subcommand(dexdissect())
         .subcommand(disas())
@@ -121,6 +122,28 @@ pub fn aresources() -> Command {
         .arg(arg_input())
 }
 
+#[must_use]
+pub fn audit() -> Command {
+    Command::new("audit")
+        .bin_name("dw-audit")
+        .version(VERSION)
+        .author(AUTHORS)
+        .about("Runs security detectors and prints a JSON report")
+        .arg(arg_debug())
+        .arg(arg_verbose())
+        .arg(arg_ecslog())
+        .arg(arg_input())
+        .arg(arg_system())
+        .arg(arg_output("Output JSON file (default to stdout)"))
+        .arg(
+            Arg::new("manifest-only")
+                .short('m')
+                .long("manifest-only")
+                .action(ArgAction::SetTrue)
+                .help("Run only manifest-based checks, without analyzing code"),
+        )
+}
+
 #[must_use]
 pub fn callgraph() -> Command {
     Command::new("callgraph")
@@ -133,9 +156,31 @@ pub fn callgraph() -> Command {
         .arg(arg_ecslog())
         .arg(arg_input())
         .arg(arg_system())
-        .arg(arg_output("Output dot file"))
+        .arg(arg_output("Output graph file"))
+        .arg(
+            Arg::new("format")
+                .short('f')
+                .long("format")
+                .action(ArgAction::Set)
+                .value_parser(["dot", "graphml"])
+                .default_value("dot")
+                .help("Output graph format"),
+        )
         .arg(arg_filter_class())
         .arg(arg_filter_method())
+        .arg(
+            Arg::new("zombies-only")
+                .long("zombies-only")
+                .action(ArgAction::SetTrue)
+                .conflicts_with("reachable-only")
+                .help("Keep only zombie methods"),
+        )
+        .arg(
+            Arg::new("reachable-only")
+                .long("reachable-only")
+                .action(ArgAction::SetTrue)
+                .help("Keep only reachable (non-zombie) methods"),
+        )
 }
 
 #[must_use]
@@ -154,7 +199,14 @@ pub fn dexdissect() -> Command {
                 .short('t')
                 .long("table")
                 .action(ArgAction::Set)
-                .value_parser(["strings", "types", "protos", "fields", "methods"])
+                .value_parser([
+                    "strings",
+                    "types",
+                    "protos",
+                    "fields",
+                    "methods",
+                    "static-values",
+                ])
                 .required(true),
         )
 }
@@ -170,9 +222,18 @@ pub fn disas() -> Command {
         .arg(arg_verbose())
         .arg(arg_ecslog())
         .arg(arg_input())
-        .arg(arg_output("Dot output directory"))
+        .arg(arg_output("Dot (or smali) output directory"))
         .arg(arg_filter_class())
         .arg(arg_filter_method())
+        .arg(
+            Arg::new("format")
+                .short('f')
+                .long("format")
+                .action(ArgAction::Set)
+                .value_parser(["text", "smali"])
+                .default_value("text")
+                .help("Disassembly format, smali files are written in the output directory"),
+        )
 }
 
 #[must_use]
@@ -187,7 +248,7 @@ pub fn hierarchy() -> Command {
         .arg(arg_ecslog())
         .arg(arg_input())
         .arg(arg_system())
-        .arg(arg_output("Output dot file"))
+        .arg(arg_output("Output graph file"))
         .arg(arg_filter_class())
         .arg(arg_filter_method())
 }
@@ -229,6 +290,12 @@ pub fn packageinfo() -> Command {
         .arg(arg_verbose())
         .arg(arg_ecslog())
         .arg(arg_input())
+        .arg(
+            Arg::new("json")
+                .long("json")
+                .action(ArgAction::SetTrue)
+                .help("Print information as JSON on stdout"),
+        )
 }
 
 #[must_use]
@@ -277,6 +344,20 @@ pub fn permissions() -> Command {
                         .long("drop")
                         .action(ArgAction::Append)
                         .help("Permissions to drop"),
+                )
+                .arg(
+                    Arg::new("add")
+                        .short('a')
+                        .long("add")
+                        .action(ArgAction::Append)
+                        .help("Permissions to add"),
+                )
+                .arg(
+                    Arg::new("max-sdk")
+                        .long("max-sdk")
+                        .value_parser(value_parser!(u32))
+                        .requires("add")
+                        .help("maxSdkVersion of the added permissions"),
                 ),
         )
 }
@@ -314,6 +395,27 @@ pub fn stats() -> Command {
                 .conflicts_with("missing")
                 .help("Consider only stubs classes"),
         )
+        .arg(
+            Arg::new("class-metrics")
+                .long("class-metrics")
+                .action(ArgAction::SetTrue)
+                .conflicts_with_all(["count", "missing", "stubs"])
+                .help("Print per-class metrics as CSV"),
+        )
+        .arg(
+            Arg::new("complexity")
+                .long("complexity")
+                .action(ArgAction::SetTrue)
+                .conflicts_with_all(["count", "missing", "stubs", "class-metrics"])
+                .help("Print per-method complexity metrics as CSV"),
+        )
+        .arg(
+            Arg::new("refs")
+                .long("refs")
+                .action(ArgAction::SetTrue)
+                .conflicts_with_all(["count", "missing", "stubs", "class-metrics", "complexity"])
+                .help("Print per-dex references counts as CSV"),
+        )
 }
 
 #[must_use]
@@ -329,6 +431,12 @@ pub fn strip() -> Command {
         .arg(arg_input())
         .arg(arg_output("Output app or dex file"))
         .arg(arg_system())
+        .arg(
+            Arg::new("prune")
+                .long("prune")
+                .action(ArgAction::SetTrue)
+                .help("Stub out methods unreachable from the manifest entry points"),
+        )
 }
 
 #[must_use]
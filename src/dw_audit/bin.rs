@@ -0,0 +1,7 @@
+use droidworks::prelude::DwResult;
+use droidworks::{cli, dw_audit};
+
+fn main() -> DwResult<()> {
+    let args = cli::audit().get_matches();
+    dw_audit::run(&args)
+}
@@ -0,0 +1,60 @@
+use crate::analysis::audit;
+use crate::owndex::OwnDex;
+use crate::prelude::*;
+use clap::ArgMatches;
+use std::fs::File;
+use std::io;
+
+pub fn run(args: &ArgMatches) -> DwResult<()> {
+    init_logger(args);
+
+    let input_fname = args
+        .get_one::<String>("input")
+        .ok_or_else(|| DwError::BadArguments("--input needed".to_string()))?;
+
+    let report = if args.get_flag("manifest-only") {
+        log::info!("manifest-only input, code-based checks are skipped");
+        let package = PackageOptions::manifest_only().open(input_fname)?;
+        audit::security_report(&package, None)?
+    } else {
+        let package = Package::open(input_fname)?;
+        let mut repo = Repo::new();
+        let sys = args
+            .get_one::<String>("system")
+            .map(OwnDex::open)
+            .transpose()?;
+        if let Some(sys) = &sys {
+            for dex in sys.borrow_dexs() {
+                repo.register_dex(dex, true)?;
+            }
+        }
+        let mut has_code = false;
+        for dex in package.iter_dexs() {
+            repo.register_dex(dex, false)?;
+            has_code = true;
+        }
+        repo.close_hierarchy();
+
+        if has_code {
+            audit::security_report(&package, Some(&repo))?
+        } else {
+            log::warn!("no dex found in package, code-based checks are skipped");
+            audit::security_report(&package, None)?
+        }
+    };
+
+    log::info!("{} finding(s)", report.findings.len());
+    match args.get_one::<String>("output") {
+        Some(json_filename) => {
+            let file = File::create(json_filename)?;
+            serde_json::to_writer_pretty(file, &report).map_err(io::Error::from)?;
+            log::info!("report written in {:?}", json_filename);
+        }
+        None => {
+            serde_json::to_writer_pretty(io::stdout(), &report).map_err(io::Error::from)?;
+            println!();
+        }
+    }
+
+    Ok(())
+}
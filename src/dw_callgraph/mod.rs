@@ -47,15 +47,31 @@ pub fn run(args: &ArgMatches) -> DwResult<()> {
                     || method_pattern.as_ref().unwrap().is_match(meth.name()))
         })
     };
+    let cg = if args.get_flag("zombies-only") {
+        log::debug!("keeping only zombie methods");
+        cg.retain(|meth| meth.is_zombie())
+    } else if args.get_flag("reachable-only") {
+        log::debug!("keeping only reachable methods");
+        cg.retain(|meth| meth.is_reachable())
+    } else {
+        cg
+    };
 
     log::info!("callgraph contains {} methods with:", cg.nb_methods());
     log::info!("    - {} system methods", cg.nb_system_methods());
     log::info!("    - {} zombie methods", cg.nb_zombie_methods());
 
-    if let Some(dot_filename) = &args.get_one::<String>("output") {
-        let mut file = File::create(dot_filename)?;
-        file.write_all(cg.to_dot().as_bytes())?;
-        log::info!("dot output written in {:?}", dot_filename);
+    if let Some(filename) = &args.get_one::<String>("output") {
+        let format = args
+            .get_one::<String>("format")
+            .map_or("dot", String::as_str);
+        let output = match format {
+            "graphml" => cg.to_graphml(),
+            _ => cg.to_dot(),
+        };
+        let mut file = File::create(filename)?;
+        file.write_all(output.as_bytes())?;
+        log::info!("{} output written in {:?}", format, filename);
     }
 
     Ok(())
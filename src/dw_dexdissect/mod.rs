@@ -41,6 +41,21 @@ pub fn run(args: &ArgMatches) -> DwResult<()> {
                     println!("Method[{:6}] = {}", i, PrettyPrinter(method, dex));
                 }
             }
+            "static-values" => {
+                for class in dex.iter_class_defs() {
+                    let Some(data) = class.data(dex)? else {
+                        continue;
+                    };
+                    let values = class.static_values(dex)?;
+                    for (field, value) in data.iter_static_fields().zip(&values) {
+                        println!(
+                            "StaticValue[{}] = {}",
+                            PrettyPrinter(field.descriptor(dex)?, dex),
+                            PrettyPrinter(value, dex)
+                        );
+                    }
+                }
+            }
             _ => {
                 return Err(DwError::BadArguments(
                     "subcommand 'dex-dissect' need --table arg".to_string(),
@@ -1,4 +1,4 @@
-use crate::dex::PrettyPrinter;
+use crate::dex::{smali, PrettyPrinter};
 use crate::owndex::OwnDex;
 use crate::prelude::*;
 use clap::ArgMatches;
@@ -10,14 +10,10 @@ use std::path::Path;
 pub fn run(args: &ArgMatches) -> DwResult<()> {
     init_logger(args);
 
-    let mut repo = Repo::new();
     let input_fname = args
         .get_one::<String>("input")
         .ok_or_else(|| DwError::BadArguments("--input needed".to_string()))?;
     let input = OwnDex::open(input_fname)?;
-    for dex in input.borrow_dexs() {
-        repo.register_dex(dex, false)?;
-    }
 
     let class_pattern = args
         .get_one::<String>("filter-class")
@@ -27,6 +23,23 @@ pub fn run(args: &ArgMatches) -> DwResult<()> {
         .get_one::<String>("filter-method")
         .map(|r| Regex::new(r))
         .transpose()?;
+
+    if args.get_one::<String>("format").map(String::as_str) == Some("smali") {
+        let smali_dir = args
+            .get_one::<String>("output")
+            .ok_or_else(|| DwError::BadArguments("--output needed for smali".to_string()))?;
+        return write_smali_files(
+            smali_dir,
+            &input,
+            class_pattern.as_ref(),
+            method_pattern.as_ref(),
+        );
+    }
+
+    let mut repo = Repo::new();
+    for dex in input.borrow_dexs() {
+        repo.register_dex(dex, false)?;
+    }
     let classes: Box<dyn Iterator<Item = &Class>> = if let Some(r) = &class_pattern {
         Box::new(repo.find_classes(r))
     } else {
@@ -93,3 +106,44 @@ fn write_cfg_file<P: AsRef<Path>>(
 
     Ok(())
 }
+
+/// Writes one smali file per class, in a directory hierarchy following the
+/// packages (base_dir/package/path/ClassName.smali). With a method filter,
+/// only the classes defining a matching method are written, but they are
+/// written whole so that they can be reassembled.
+fn write_smali_files<P: AsRef<Path>>(
+    base_dir: P,
+    input: &OwnDex,
+    class_pattern: Option<&Regex>,
+    method_pattern: Option<&Regex>,
+) -> DwResult<()> {
+    for dex in input.borrow_dexs() {
+        for class in dex.iter_class_defs() {
+            let class_name = class.class_name(dex)?;
+            if class_pattern.is_some_and(|r| !r.is_match(&class_name)) {
+                continue;
+            }
+            if let Some(r) = method_pattern {
+                let mut matching = false;
+                if let Some(data) = class.data(dex)? {
+                    for method in data.iter_methods() {
+                        matching |= r.is_match(&method.descriptor(dex)?.name(dex)?);
+                    }
+                }
+                if !matching {
+                    continue;
+                }
+            }
+            println!("[*] {class_name}");
+
+            let path = base_dir.as_ref().join(format!("{class_name}.smali"));
+            if let Some(dir) = path.parent() {
+                create_dir_all(dir)?;
+            }
+            let mut file = File::create(path)?;
+            file.write_all(smali::class_to_smali(class, dex)?.as_bytes())?;
+        }
+    }
+
+    Ok(())
+}
@@ -1,5 +1,6 @@
 use crate::prelude::*;
 use clap::ArgMatches;
+use std::io;
 
 pub fn run(args: &ArgMatches) -> DwResult<()> {
     init_logger(args);
@@ -8,6 +9,13 @@ pub fn run(args: &ArgMatches) -> DwResult<()> {
         .get_one::<String>("input")
         .ok_or_else(|| DwError::BadArguments("--input needed".to_string()))?;
     let package = Package::open(filename)?;
+
+    if args.get_flag("json") {
+        serde_json::to_writer_pretty(io::stdout(), &package.info()?).map_err(io::Error::from)?;
+        println!();
+        return Ok(());
+    }
+
     println!("{package}");
 
     let manifest = package.manifest().expect("Android manifest");
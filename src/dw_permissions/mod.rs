@@ -124,6 +124,19 @@ fn modify_permissions(sub_args: &ArgMatches) -> DwResult<()> {
         }
     }
 
+    let max_sdk = sub_args.get_one::<u32>("max-sdk").copied();
+    let to_add = sub_args
+        .get_many::<String>("add")
+        .map_or_else(Vec::new, Iterator::collect);
+    for perm in to_add {
+        log::debug!("adding permission '{}'...", perm);
+        if manifest.add_uses_permission(perm, max_sdk)? {
+            log::debug!("permission '{}' added.", perm);
+        } else {
+            log::debug!("permission '{}' already declared.", perm);
+        }
+    }
+
     package.save(out_filename, clear_signatures)?;
     log::info!("file '{}' written", out_filename);
 
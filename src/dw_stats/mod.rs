@@ -1,3 +1,4 @@
+use crate::analysis::stats;
 use crate::owndex::OwnDex;
 use crate::prelude::*;
 use clap::ArgMatches;
@@ -15,6 +16,49 @@ pub fn run(args: &ArgMatches) -> DwResult<()> {
     }
     repo.close_hierarchy();
 
+    if args.get_flag("class-metrics") {
+        println!("class,methods,fields,code_size,synthetic");
+        for class in repo.iter_classes().filter(|c| !c.is_system()) {
+            let metrics = stats::class_metrics(class, &repo);
+            println!(
+                "{},{},{},{},{}",
+                class.name(),
+                metrics.methods,
+                metrics.fields,
+                metrics.code_size,
+                metrics.synthetic
+            );
+        }
+        return Ok(());
+    }
+
+    if args.get_flag("complexity") {
+        let report = stats::complexity_report(&repo);
+        println!("method,cyclomatic,basic_blocks,instructions,loop_depth");
+        for (method, complexity) in &report.methods {
+            println!(
+                "{},{},{},{},{}",
+                method,
+                complexity.cyclomatic,
+                complexity.basic_blocks,
+                complexity.instructions,
+                complexity.loop_depth
+            );
+        }
+        return Ok(());
+    }
+
+    if args.get_flag("refs") {
+        println!("dex,strings,types,fields,methods,near_limit");
+        for (i, (counts, near_limit)) in repo.multidex_report().into_iter().enumerate() {
+            println!(
+                "{},{},{},{},{},{}",
+                i, counts.strings, counts.types, counts.fields, counts.methods, near_limit
+            );
+        }
+        return Ok(());
+    }
+
     if args.get_flag("stubs") {
         let mut n = 0;
         for class in repo.iter_classes() {
@@ -32,6 +32,19 @@ pub fn run(args: &ArgMatches) -> DwResult<()> {
 
     cg.patch_unknown_refs(&repo)?;
 
+    if args.get_flag("prune") {
+        let manifest = input.package().and_then(Package::manifest).ok_or_else(|| {
+            DwError::BadArguments("--prune needs an application with a manifest".to_string())
+        })?;
+        log::info!("looking for methods unreachable from entry points...");
+        cg.prune_unreachable(&repo, &repo.entry_points(manifest)?);
+        log_info_callgraph_stats(&cg);
+
+        let removable: Vec<_> = cg.removable_methods().collect();
+        let stubbed = repo.stub_methods(&removable);
+        log::info!("{} unreachable methods stubbed", stubbed);
+    }
+
     if let Some(output_fname) = args.get_one::<String>("output") {
         input.modify_dexs();
         input.save(output_fname)?;
@@ -44,4 +57,5 @@ fn log_info_callgraph_stats(cg: &callgraph::CallGraph) {
     log::info!("callgraph contains {} methods with:", cg.nb_methods());
     log::info!("    - {} system methods", cg.nb_system_methods());
     log::info!("    - {} zombie methods", cg.nb_zombie_methods());
+    log::info!("    - {} removable methods", cg.nb_removable_methods());
 }
@@ -1,4 +1,3 @@
-use crate::analysis;
 use crate::owndex::OwnDex;
 use crate::prelude::*;
 use clap::ArgMatches;
@@ -54,22 +53,16 @@ pub fn run(args: &ArgMatches) -> DwResult<()> {
         (_, None) | (_, Some(_)) => log::warn!("unknown input API level"),
     }
 
-    let typecheck = if *args.get_one::<bool>("backward").unwrap_or(&false) {
+    let backward = *args.get_one::<bool>("backward").unwrap_or(&false);
+    if backward {
         log::info!("backward typecheck");
-        analysis::backward_typecheck
     } else {
         log::info!("forward typecheck");
-        analysis::forward_typecheck
-    };
-
-    for (class, method) in repo
-        .iter_classes_methods()
-        .filter(|(class, method)| !class.is_system() && method.code().is_some())
-    {
-        log::info!("typecheck {}", method.descriptor());
+    }
 
-        //let cfg = controlflow::Cfg::build(method, class, method.dex())?;
-        match typecheck(method, class, &repo) {
+    for (uid, res) in repo.par_typecheck(backward) {
+        log::info!("typecheck {}", repo[uid].descriptor());
+        match res {
             Ok(res) => {
                 log::debug!(
                     "{:#?}",
@@ -79,19 +72,6 @@ pub fn run(args: &ArgMatches) -> DwResult<()> {
             }
             Err(err) => {
                 log::error!("{}", err);
-                /*
-                    info!("    ------------------------------");
-                    for block in cfg.iter_ordered_blocks() {
-                        for instr in block.instructions() {
-                            info!(
-                                "    {:04}: {}",
-                                instr.addr(),
-                                PrettyPrinter(instr.instr(), method.dex())
-                            );
-                        }
-                        info!("    ------------------------------");
-                }
-                    */
                 nb_fails += 1;
                 last_res = Err(err.into());
             }
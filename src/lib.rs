@@ -61,6 +61,7 @@ mod errors;
 
 pub mod cli;
 pub mod dw_aresources;
+pub mod dw_audit;
 pub mod dw_callgraph;
 pub mod dw_dexdissect;
 pub mod dw_disas;
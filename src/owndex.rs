@@ -31,8 +31,9 @@ impl OwnDex {
     pub fn save<P: AsRef<Path>>(self, path: P) -> DwResult<()> {
         match self {
             Self::Dex(dex) => {
-                let buf = dw_dex::write(&dex, true).map_err(DwError::from)?;
-                let mut file = File::create("foo.txt")?;
+                let buf =
+                    dw_dex::write(&dex, &dw_dex::WriteOptions::default()).map_err(DwError::from)?;
+                let mut file = File::create(path)?;
                 file.write_all(&buf)?;
                 Ok(())
             }